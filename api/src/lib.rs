@@ -38,7 +38,7 @@ use serde::de::DeserializeOwned;
 use serde::ser::Serialize;
 use serde_derive::{Deserialize, Serialize};
 use stegos_crypto::pbc;
-pub use stegos_network::NodeInfo;
+pub use stegos_network::{NodeInfo, PeerScoreInfo};
 pub use stegos_node::{ChainNotification, NodeRequest, NodeResponse, StatusNotification};
 pub use stegos_wallet::api::*;
 pub use websocket::WebSocketError;
@@ -78,6 +78,7 @@ pub enum NetworkRequest {
         data: Vec<u8>,
     },
     ConnectedNodesRequest {},
+    PeerScores {},
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -94,6 +95,7 @@ pub enum NetworkResponse {
     PublishedBroadcast,
     ConnectedNodesRequested,
     ConnectedNodes { total: usize, nodes: Vec<NodeInfo> },
+    PeerScores { peers: Vec<PeerScoreInfo> },
     Error { error: String },
 }
 
@@ -108,6 +110,9 @@ pub enum NetworkNotification {
     },
     BroadcastMessage {
         topic: String,
+        /// Network key of the publisher, if the message was signed.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        from: Option<pbc::PublicKey>,
         data: Vec<u8>,
     },
 }
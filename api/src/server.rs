@@ -32,7 +32,9 @@ use futures::{task, Async, AsyncSink, Future, Poll, Sink, Stream};
 use log::*;
 use std::collections::HashMap;
 use std::net::SocketAddr;
-use stegos_network::{Network, NetworkResponse as NetworkServiceResponse, UnicastMessage};
+use stegos_network::{
+    BroadcastMessage, Network, NetworkResponse as NetworkServiceResponse, UnicastMessage,
+};
 use stegos_node::{ChainNotification, Node, NodeResponse, StatusNotification};
 use stegos_wallet::api::{WalletControlResponse, WalletNotification, WalletResponse};
 use stegos_wallet::Wallet;
@@ -69,7 +71,7 @@ struct WebSocketHandler {
     /// Network unicast subscribtions.
     network_unicast: HashMap<String, mpsc::UnboundedReceiver<UnicastMessage>>,
     /// Network broadcast subscribtions.
-    network_broadcast: HashMap<String, mpsc::UnboundedReceiver<Vec<u8>>>,
+    network_broadcast: HashMap<String, mpsc::UnboundedReceiver<BroadcastMessage>>,
     /// Responses from the network subsystem
     network_responses: Vec<(RequestId, oneshot::Receiver<NetworkServiceResponse>)>,
     /// Wallet API.
@@ -114,7 +116,7 @@ impl WebSocketHandler {
         let rx = network.subscribe_unicast(CONSOLE_TOPIC).unwrap();
         network_unicast.insert(CONSOLE_TOPIC.to_string(), rx);
         let mut network_broadcast = HashMap::new();
-        let rx = network.subscribe(CONSOLE_TOPIC).unwrap();
+        let rx = network.subscribe_with_origin(CONSOLE_TOPIC).unwrap();
         network_broadcast.insert(CONSOLE_TOPIC.to_string(), rx);
         let network_responses = Vec::new();
         let wallet_notifications = if let Some(wallet) = &wallet {
@@ -175,7 +177,7 @@ impl WebSocketHandler {
             }
             NetworkRequest::SubscribeBroadcast { topic } => {
                 if !self.network_broadcast.contains_key(&topic) {
-                    let rx = self.network.subscribe(&topic)?;
+                    let rx = self.network.subscribe_with_origin(&topic)?;
                     self.network_broadcast.insert(topic, rx);
                     task::current().notify();
                 }
@@ -209,6 +211,10 @@ impl WebSocketHandler {
                 let rx = self.network.list_connected_nodes()?;
                 Ok(NetworkResult::Async(rx))
             }
+            NetworkRequest::PeerScores {} => {
+                let rx = self.network.peer_scores()?;
+                Ok(NetworkResult::Async(rx))
+            }
         }
     }
 }
@@ -371,9 +377,10 @@ impl Future for WebSocketHandler {
         for (topic, rx) in self.network_broadcast.iter_mut() {
             loop {
                 match rx.poll().unwrap() {
-                    Async::Ready(Some(data)) => {
+                    Async::Ready(Some(BroadcastMessage { from, data })) => {
                         let msg = NetworkNotification::BroadcastMessage {
                             topic: topic.clone(),
+                            from,
                             data,
                         };
                         let msg = Response {
@@ -402,6 +409,12 @@ impl Future for WebSocketHandler {
                             }),
                             id,
                         },
+                        NetworkServiceResponse::PeerScores { peers } => Response {
+                            kind: ResponseKind::NetworkResponse(NetworkResponse::PeerScores {
+                                peers,
+                            }),
+                            id,
+                        },
                     };
                     try_send!(self, resp);
                     continue;
@@ -25,6 +25,7 @@ use crate::api::StatusInfo;
 use crate::awards::{Awards, ValidatorAwardState};
 use crate::block::*;
 use crate::config::*;
+use crate::delegation::Delegations;
 use crate::election::mix;
 use crate::election::ElectionInfo;
 use crate::election::{self, ElectionResult};
@@ -35,7 +36,9 @@ use crate::metrics;
 use crate::mvcc::MultiVersionedMap;
 use crate::output::*;
 use crate::timestamp::Timestamp;
-use crate::transaction::{CoinbaseTransaction, ServiceAwardTransaction, Transaction};
+use crate::transaction::{
+    CoinbaseTransaction, DelegationRewardTransaction, ServiceAwardTransaction, Transaction,
+};
 use crate::view_changes::ViewChangeProof;
 use crate::BlockReader;
 use bit_vec::BitVec;
@@ -90,6 +93,32 @@ pub struct EpochInfo {
     pub awards: AwardsInfo,
 }
 
+/// Performance of validator in some epoch.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ValidatorStats {
+    pub epoch: u64,
+    /// Number of blocks proposed by validator.
+    pub blocks_proposed: u32,
+    /// Number of view changes caused by missed micro blocks.
+    pub view_changes: u32,
+    /// Number of missed signatures for macro blocks.
+    pub missed_macro_signatures: u32,
+    /// Amount of service awards won.
+    pub awards: i64,
+}
+
+impl ValidatorStats {
+    pub fn new(epoch: u64) -> Self {
+        ValidatorStats {
+            epoch,
+            blocks_proposed: 0,
+            view_changes: 0,
+            missed_macro_signatures: 0,
+            awards: 0,
+        }
+    }
+}
+
 /// Retrospective information for some epoch.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct LightEpochInfo {
@@ -189,6 +218,7 @@ type BalanceMap = MultiVersionedMap<(), Balance, LSN>;
 
 type ElectionResultList = MultiVersionedMap<(), ElectionResult, LSN>;
 type ValidatorsActivity = MultiVersionedMap<pbc::PublicKey, ValidatorAwardState, LSN>;
+type ValidatorsStats = MultiVersionedMap<pbc::PublicKey, ValidatorStats, LSN>;
 
 #[derive(Eq, PartialEq, Debug, Clone)]
 pub struct OutputRecovery {
@@ -206,6 +236,7 @@ const ESCROW: &'static str = "escrow";
 
 const SERVICE_AWARD: &'static str = "service_award";
 const EPOCH_INFOS: &'static str = "epoch_infos";
+const VALIDATOR_STATS: &'static str = "validator_stats";
 const META: &'static str = "META";
 
 const COLON_FAMILIES: &[&'static str] = &[
@@ -214,6 +245,7 @@ const COLON_FAMILIES: &[&'static str] = &[
     ESCROW,
     SERVICE_AWARD,
     EPOCH_INFOS,
+    VALIDATOR_STATS,
     META,
 ];
 
@@ -281,6 +313,10 @@ pub struct Blockchain {
     //
     awards: Awards,
     epoch_activity: ValidatorsActivity,
+    epoch_stats: ValidatorsStats,
+    /// Performance of validators at the end of the epoch.
+    /// Micro blocks are reverted before applying the macro block, so save it until the macro block.
+    full_epoch_stats: Option<BTreeMap<pbc::PublicKey, ValidatorStats>>,
 
     // Block ache
     cache: VecDeque<Block>,
@@ -338,6 +374,8 @@ impl Blockchain {
         //
         let awards = Awards::new(cfg.awards_difficulty);
         let epoch_activity = MultiVersionedMap::new();
+        let epoch_stats = MultiVersionedMap::new();
+        let full_epoch_stats = None;
         // Block cache.
         let cache = VecDeque::with_capacity(cfg.stake_epochs as usize + 1);
 
@@ -362,6 +400,8 @@ impl Blockchain {
             last_block_hash,
             awards,
             epoch_activity,
+            epoch_stats,
+            full_epoch_stats,
             cache,
         };
 
@@ -908,6 +948,58 @@ impl Blockchain {
             .transpose()?)
     }
 
+    fn validator_stats_key(validator_pkey: &pbc::PublicKey, epoch: u64) -> Vec<u8> {
+        let mut key = validator_pkey.to_bytes().to_vec();
+        let mut epoch_bytes = [0u8; 8];
+        BigEndian::write_u64(&mut epoch_bytes, epoch);
+        key.extend_from_slice(&epoch_bytes);
+        key
+    }
+
+    /// Returns performance of validator for past epochs in range [from_epoch, to_epoch].
+    pub fn validator_stats(
+        &self,
+        validator_pkey: &pbc::PublicKey,
+        from_epoch: u64,
+        to_epoch: u64,
+    ) -> Result<Vec<ValidatorStats>, BlockchainError> {
+        let cf_validator_stats = self.database.cf_handle(VALIDATOR_STATS).unwrap();
+        let prefix = validator_pkey.to_bytes();
+        let key = Self::validator_stats_key(validator_pkey, from_epoch);
+        let mode = rocksdb::IteratorMode::From(&key, rocksdb::Direction::Forward);
+        let mut stats = Vec::new();
+        for (key, value) in self.database.iterator_cf(cf_validator_stats, mode)? {
+            if !key.starts_with(&prefix[..]) {
+                break;
+            }
+            let value: ValidatorStats = ProtoConvert::from_buffer(&value)?;
+            if value.epoch > to_epoch {
+                break;
+            }
+            stats.push(value);
+        }
+        Ok(stats)
+    }
+
+    /// Returns performance of validators in the current epoch.
+    pub fn epoch_stats(&self) -> &BTreeMap<pbc::PublicKey, ValidatorStats> {
+        self.epoch_stats.inner()
+    }
+
+    /// Update performance of validator in the current epoch.
+    fn update_epoch_stats<F>(&mut self, lsn: LSN, validator_pkey: pbc::PublicKey, f: F)
+    where
+        F: FnOnce(&mut ValidatorStats),
+    {
+        let mut stats = self
+            .epoch_stats
+            .get(&validator_pkey)
+            .cloned()
+            .unwrap_or_else(|| ValidatorStats::new(self.epoch));
+        f(&mut stats);
+        self.epoch_stats.insert(lsn, validator_pkey, stats);
+    }
+
     /// Returns current state of election result.
     /// Note:
     /// Election result changes on epoch start, and on slashing.
@@ -941,6 +1033,16 @@ impl Blockchain {
         &self.election_result().facilitator
     }
 
+    /// Returns the current epoch facilitator candidates, in the order of preference.
+    pub fn facilitators(&self) -> Vec<pbc::PublicKey> {
+        let election_result = self.election_result();
+        election::select_facilitators(
+            &election_result.random.rand,
+            &election_result.validators,
+            election::FACILITATOR_CANDIDATES,
+        )
+    }
+
     /// Returns the validator network key, by validator_id.
     #[inline]
     pub fn validator_key_by_id(&self, id: usize) -> Option<pbc::PublicKey> {
@@ -1034,6 +1136,20 @@ impl Blockchain {
         self.escrow.validate_stakes(inputs, outputs, self.epoch)
     }
 
+    /// A shortcut for self.escrow.validate_key_rotation().
+    #[inline]
+    pub fn validate_key_rotation<'a, OutputIter>(
+        &self,
+        inputs: OutputIter,
+        outputs: OutputIter,
+    ) -> Result<(), BlockchainError>
+    where
+        OutputIter: Iterator<Item = &'a Output>,
+    {
+        self.escrow
+            .validate_key_rotation(inputs, outputs, self.epoch)
+    }
+
     ///
     /// Iterate over stakes of specified validator.
     ///
@@ -1056,6 +1172,132 @@ impl Blockchain {
         self.escrow.account_by_network_key(validator_pkey)
     }
 
+    ///
+    /// Return an account key of a validator of the current epoch.
+    /// Unlike account_by_network_key(), this also works for validators
+    /// which have rotated their network key at the end of the epoch.
+    ///
+    pub fn validator_account(&self, validator_pkey: &pbc::PublicKey) -> Option<scc::PublicKey> {
+        if let Some(account_pkey) = self.escrow.account_by_network_key(validator_pkey) {
+            return Some(account_pkey);
+        }
+        if self.epoch == 0 {
+            return None;
+        }
+        let epoch_info = self.epoch_info(self.epoch - 1).ok()??;
+        epoch_info
+            .validators
+            .into_iter()
+            .find(|v| v.network_pkey == *validator_pkey)
+            .map(|v| v.account_pkey)
+    }
+
+    ///
+    /// Return commission declared by validator, in basis points.
+    ///
+    #[inline]
+    pub fn validator_commission(&self, validator_pkey: &pbc::PublicKey) -> u32 {
+        self.escrow.validator_commission(validator_pkey)
+    }
+
+    ///
+    /// Return the next epoch, which validator skips for maintenance.
+    ///
+    pub fn scheduled_maintenance(&self, validator_pkey: &pbc::PublicKey) -> Option<u64> {
+        let maintenance_epoch = self.escrow.validator_maintenance_epoch(validator_pkey);
+        if maintenance_epoch > self.epoch {
+            Some(maintenance_epoch)
+        } else {
+            None
+        }
+    }
+
+    ///
+    /// Return active stakes of validator and its delegators.
+    ///
+    #[inline]
+    pub fn delegations(&self, validator_pkey: &pbc::PublicKey) -> Option<Delegations> {
+        self.escrow.delegations(validator_pkey, self.epoch)
+    }
+
+    ///
+    /// Return a part of block reward, which is paid to leader by coinbase.
+    /// The rest belongs to delegators and is paid by the next macro block.
+    ///
+    pub fn coinbase_reward(&self, leader: &pbc::PublicKey) -> i64 {
+        let block_reward = self.cfg.block_reward;
+        match self.delegations(leader) {
+            Some(delegations) => block_reward - delegations.delegators_share(block_reward),
+            None => block_reward,
+        }
+    }
+
+    ///
+    /// Split service award between the winner and delegators of its validator.
+    ///
+    pub fn service_award_payouts(
+        &self,
+        winner_pkey: &scc::PublicKey,
+        reward: i64,
+    ) -> Vec<(scc::PublicKey, i64)> {
+        let delegations = self
+            .validators()
+            .iter()
+            .filter_map(|(validator_pkey, _)| self.delegations(validator_pkey))
+            .find(|delegations| delegations.owner == *winner_pkey);
+        match delegations {
+            Some(delegations) => delegations.split(reward),
+            None => vec![(*winner_pkey, reward)],
+        }
+    }
+
+    ///
+    /// Return rewards withheld from delegators during the current epoch,
+    /// including the share of the macro block with specified leader.
+    /// Rewards withheld by slashed validators are paid to the leader.
+    ///
+    pub fn delegation_payouts(&self, leader: &pbc::PublicKey) -> Vec<(scc::PublicKey, i64)> {
+        let mut withheld: BTreeMap<pbc::PublicKey, i64> = BTreeMap::new();
+        let count = self.cfg.micro_blocks_in_epoch as usize;
+        for (offset, block) in self.blocks_starting(self.epoch, 0).take(count).enumerate() {
+            let block = if let Block::MicroBlock(block) = block {
+                block
+            } else {
+                panic!(
+                    "Expected micro block: epoch={}, offset={}",
+                    self.epoch, offset
+                );
+            };
+            if let Some(Transaction::CoinbaseTransaction(tx)) = block.transactions.get(0) {
+                *withheld.entry(block.header.pkey).or_insert(0) +=
+                    self.cfg.block_reward - tx.block_reward;
+            }
+        }
+        *withheld.entry(*leader).or_insert(0) +=
+            self.cfg.block_reward - self.coinbase_reward(leader);
+
+        let mut payouts: Vec<(scc::PublicKey, i64)> = Vec::new();
+        let mut orphaned: i64 = 0;
+        for (validator_pkey, amount) in withheld {
+            if amount == 0 {
+                continue;
+            }
+            match self.delegations(&validator_pkey) {
+                Some(delegations) => payouts.extend(delegations.split_delegated(amount)),
+                // Stakes of validator and its delegators have been confiscated during the epoch.
+                None => orphaned += amount,
+            }
+        }
+        if orphaned > 0 {
+            // Slashed validators are excluded from the election, so the leader has stakes.
+            let account_pkey = self
+                .validator_account(leader)
+                .expect("leader has account key");
+            payouts.push((account_pkey, orphaned));
+        }
+        payouts
+    }
+
     /// Return information about escrow.
     #[inline]
     pub fn escrow_info(&self) -> EscrowInfo {
@@ -1136,8 +1378,7 @@ impl Blockchain {
 
         let validators_activity = epoch_activity.iter().map(|(k, v)| {
             (
-                self.escrow
-                    .account_by_network_key(k)
+                self.validator_account(k)
                     .expect("validator has account key"),
                 *v,
             )
@@ -1162,7 +1403,7 @@ impl Blockchain {
             // Set failed if no activity was set.
             let activity = activity_map.get(id).unwrap_or(false);
             let validator_account =
-                if let Some(validator_account) = self.escrow.account_by_network_key(validator) {
+                if let Some(validator_account) = self.validator_account(validator) {
                     validator_account
                 } else {
                     continue;
@@ -1334,7 +1575,7 @@ impl Blockchain {
         // Coinbase.
         //
         {
-            let block_reward = self.cfg.block_reward;
+            let block_reward = self.coinbase_reward(&network_pkey);
             let data = PaymentPayloadData::Comment("Block reward".to_string());
             let (output, gamma, _rvalue) =
                 PaymentOutput::with_payload(None, &beneficiary_pkey, block_reward, data.clone())
@@ -1343,7 +1584,7 @@ impl Blockchain {
             info!(
                 "Created reward UTXO: hash={}, amount={}, data={:?}",
                 Hash::digest(&output),
-                self.cfg.block_reward,
+                block_reward,
                 data
             );
 
@@ -1365,14 +1606,29 @@ impl Blockchain {
         //
         let (activity_map, winner) = self.awards_from_active_epoch(&random);
         if let Some((k, reward)) = winner {
-            let output = PublicPaymentOutput::new(&k, reward);
-            let tx = ServiceAwardTransaction {
-                winner_reward: vec![output.into()],
-            };
+            let winner_reward = self
+                .service_award_payouts(&k, reward)
+                .into_iter()
+                .map(|(recipient, amount)| PublicPaymentOutput::new(&recipient, amount).into())
+                .collect();
+            let tx = ServiceAwardTransaction { winner_reward };
             full_reward += reward;
             transactions.push(tx.into());
         }
 
+        //
+        // Delegation rewards.
+        //
+        let rewards: Vec<Output> = self
+            .delegation_payouts(&network_pkey)
+            .into_iter()
+            .map(|(recipient, amount)| PublicPaymentOutput::new(&recipient, amount).into())
+            .collect();
+        if !rewards.is_empty() {
+            let tx = DelegationRewardTransaction { rewards };
+            transactions.push(tx.into());
+        }
+
         let extra_transactions = transactions.clone();
 
         // Collect transactions from epoch.
@@ -1548,6 +1804,7 @@ impl Blockchain {
             block_hash,
             &inputs,
             &outputs,
+            &HashMap::new(),
             block.header.gamma,
             block.header.block_reward,
         );
@@ -1571,6 +1828,38 @@ impl Blockchain {
             );
         }
 
+        //
+        // Collect validators performance (skip genesis).
+        //
+        let mut epoch_stats = self
+            .full_epoch_stats
+            .take()
+            .unwrap_or_else(|| self.epoch_stats.inner().clone());
+        if epoch > 0 {
+            let validators = self.validators_at_epoch_start();
+            for (validator_id, (validator_pkey, _slots)) in validators.iter().enumerate() {
+                let stats = epoch_stats
+                    .entry(*validator_pkey)
+                    .or_insert_with(|| ValidatorStats::new(epoch));
+                if !block.multisigmap.get(validator_id).unwrap_or(false) {
+                    stats.missed_macro_signatures += 1;
+                }
+            }
+            epoch_stats
+                .entry(block.header.pkey)
+                .or_insert_with(|| ValidatorStats::new(epoch))
+                .blocks_proposed += 1;
+            if let Some((winner_pk, amount)) = winner {
+                // Multiple validators can have single wallet, count the award once.
+                let winner = validators.iter().find(|(validator_pkey, _)| {
+                    self.validator_account(validator_pkey) == Some(winner_pk)
+                });
+                if let Some((validator_pkey, _)) = winner {
+                    epoch_stats.get_mut(validator_pkey).unwrap().awards += amount;
+                }
+            }
+        }
+
         //
         // Update metadata.
         //
@@ -1622,7 +1911,9 @@ impl Blockchain {
         let cf_output_by_hash = self.database.cf_handle(OUTPUT_BY_HASH).unwrap();
         let cf_escrow = self.database.cf_handle(ESCROW).unwrap();
         let cf_epoch_infos = self.database.cf_handle(EPOCH_INFOS).unwrap();
+        let cf_validator_stats = self.database.cf_handle(VALIDATOR_STATS).unwrap();
         let cf_meta = self.database.cf_handle(META).unwrap();
+        let is_recovery = batch.is_none();
         let mut batch = batch.unwrap_or_default();
         //
         // Finalize storage.
@@ -1694,7 +1985,19 @@ impl Blockchain {
             &Self::block_key(LSN(epoch, MACRO_BLOCK_OFFSET)),
             &data,
         )?;
+        // Micro blocks of past epochs are not available during recovery, keep saved stats.
+        if !is_recovery {
+            for (validator_pkey, stats) in epoch_stats {
+                let data = stats.into_buffer()?;
+                batch.put_cf(
+                    cf_validator_stats,
+                    &Self::validator_stats_key(&validator_pkey, epoch),
+                    &data,
+                )?;
+            }
+        }
         self.epoch_activity.reset();
+        self.epoch_stats.reset();
         self.database.write(batch)?;
 
         let mut outputs: HashMap<Hash, Output> =
@@ -1766,12 +2069,15 @@ impl Blockchain {
     ///
     /// Common part of register_macro_block()/register_micro_block().
     ///
+    /// * `rotations` - stakes re-bound by key rotation, with the old key of validator.
+    ///
     fn register_inputs_and_outputs(
         &mut self,
         lsn: LSN,
         block_hash: Hash,
         inputs: &HashMap<Hash, Output>,
         outputs: &HashMap<Hash, (Output, OutputKey)>,
+        rotations: &HashMap<Hash, pbc::PublicKey>,
         gamma: Fr,
         block_reward: i64,
     ) {
@@ -1791,6 +2097,52 @@ impl Blockchain {
         let mut burned = Pt::identity();
         let mut created = Pt::identity();
 
+        //
+        // Schedule commissions and locks of new stakes before re-staked inputs are pruned.
+        // Own stakes re-staked only to re-schedule maintenance keep the existing lock.
+        //
+        let mut locks: HashMap<pbc::PublicKey, u64> = HashMap::new();
+        for (input_hash, input) in inputs.iter() {
+            if let Output::StakeOutput(o) = input {
+                if let Some((validator_pkey, active_until_epoch)) =
+                    self.escrow.own_stake_lock(o.validator, input_hash.clone())
+                {
+                    let lock = locks.entry(validator_pkey).or_insert(0);
+                    *lock = std::cmp::max(*lock, active_until_epoch);
+                }
+            }
+        }
+        let mut stakes: HashMap<Hash, (Commission, u64)> = HashMap::new();
+        for (output_hash, (output, _output_key)) in outputs.iter() {
+            if let Output::StakeOutput(o) = output {
+                let validator_pkey = rotations.get(output_hash).unwrap_or(&o.validator);
+                let lock = self.epoch + self.cfg.stake_epochs;
+                let stake = if o.delegated {
+                    (Commission::new(o.commission, self.epoch), lock)
+                } else {
+                    let commission = self.escrow.declare_commission(
+                        validator_pkey,
+                        o.commission,
+                        self.epoch,
+                        self.cfg.stake_epochs,
+                    );
+                    // Past maintenance is the same as none.
+                    let scheduled = self.scheduled_maintenance(validator_pkey).unwrap_or(0);
+                    let maintenance_epoch = if o.maintenance_epoch > self.epoch {
+                        o.maintenance_epoch
+                    } else {
+                        0
+                    };
+                    let lock = match locks.get(validator_pkey) {
+                        Some(lock) if maintenance_epoch != scheduled => *lock,
+                        _ => lock,
+                    };
+                    (commission, lock)
+                };
+                stakes.insert(output_hash.clone(), stake);
+            }
+        }
+
         //
         // Process inputs.
         //
@@ -1849,14 +2201,24 @@ impl Blockchain {
                 Output::PaymentOutput(_o) => {}
                 Output::PublicPaymentOutput(_o) => {}
                 Output::StakeOutput(o) => {
+                    // Rotated stakes stay bound to the old key until the end of epoch.
+                    let (validator_pkey, rotated_to) = match rotations.get(output_hash) {
+                        Some(old_validator_pkey) => (*old_validator_pkey, Some(o.validator)),
+                        None => (o.validator, None),
+                    };
+                    let (commission, active_until_epoch) = stakes[output_hash];
                     self.escrow.stake(
                         lsn,
-                        o.validator,
+                        validator_pkey,
                         o.recipient,
                         output_hash.clone(),
                         self.epoch,
-                        self.cfg.stake_epochs,
+                        active_until_epoch,
                         o.amount,
+                        commission,
+                        o.delegated,
+                        o.maintenance_epoch,
+                        rotated_to,
                     );
                     assert_eq!(self.escrow.current_lsn(), lsn);
                 }
@@ -1925,6 +2287,7 @@ impl Blockchain {
         let mut outputs: HashMap<Hash, (Output, OutputKey)> = HashMap::new();
         let mut gamma = Fr::zero();
         let mut block_reward: i64 = 0;
+        let mut rotations: HashMap<Hash, pbc::PublicKey> = HashMap::new();
         let mut txs: HashMap<Hash, Transaction> = HashMap::new();
         // Regular transactions.
         for (tx_id, tx) in block.transactions.into_iter().enumerate() {
@@ -1961,6 +2324,16 @@ impl Blockchain {
                     gamma += tx.gamma;
                 }
                 Transaction::RestakeTransaction(_tx) => {}
+                Transaction::ValidatorKeyRotationTransaction(tx) => {
+                    // The new key takes effect from the next epoch.
+                    let old_validator_pkey = match inputs.get(&tx.txins[0]) {
+                        Some(Output::StakeOutput(o)) => o.validator,
+                        _ => panic!("Invalid key rotation input: tx={}", tx_hash),
+                    };
+                    for output in &tx.txouts {
+                        rotations.insert(Hash::digest(output), old_validator_pkey);
+                    }
+                }
                 Transaction::SlashingTransaction(tx) => {
                     info!(
                         "Found slashing transaction, removing validator, from list: cheater={}",
@@ -1985,6 +2358,9 @@ impl Blockchain {
                 Transaction::ServiceAwardTransaction(_tx) => {
                     panic!("Found a ServiceAward transaction inside a MicroBlock")
                 }
+                Transaction::DelegationRewardTransaction(_tx) => {
+                    panic!("Found a DelegationReward transaction inside a MicroBlock")
+                }
             }
 
             assert!(txs.insert(tx_hash, tx).is_none());
@@ -2004,6 +2380,7 @@ impl Blockchain {
                     offset: self.offset(),
                 },
             );
+            self.update_epoch_stats(lsn, leader, |stats| stats.view_changes += 1);
         }
 
         // set current leader to active, if it was unknown.
@@ -2014,11 +2391,20 @@ impl Blockchain {
             self.epoch_activity
                 .insert(lsn, leader, ValidatorAwardState::Active);
         }
+        self.update_epoch_stats(lsn, leader, |stats| stats.blocks_proposed += 1);
 
         //
         // Register block.
         //
-        self.register_inputs_and_outputs(lsn, block_hash, &inputs, &outputs, gamma, block_reward);
+        self.register_inputs_and_outputs(
+            lsn,
+            block_hash,
+            &inputs,
+            &outputs,
+            &rotations,
+            gamma,
+            block_reward,
+        );
 
         //
         // Update metadata.
@@ -2031,6 +2417,9 @@ impl Blockchain {
         election_result.random = block.header.random;
         self.election_result.insert(lsn, (), election_result);
         self.view_change_proof = None;
+        if self.is_epoch_full() {
+            self.full_epoch_stats = Some(self.epoch_stats.inner().clone());
+        }
 
         //
         // Update metrics.
@@ -2111,11 +2500,13 @@ impl Blockchain {
         self.balance.rollback_to_lsn(lsn);
         self.escrow.rollback_to_lsn(lsn);
         self.epoch_activity.rollback_to_lsn(lsn);
+        self.epoch_stats.rollback_to_lsn(lsn);
 
         self.election_result.rollback_to_lsn(lsn);
         assert_eq!(self.block_by_hash.current_lsn(), lsn);
         assert_eq!(self.election_result.current_lsn(), lsn);
         assert!(self.epoch_activity.current_lsn() <= lsn);
+        assert!(self.epoch_stats.current_lsn() <= lsn);
         assert!(self.output_by_hash.current_lsn() <= lsn);
         assert!(self.balance.current_lsn() <= lsn);
         assert!(self.escrow.current_lsn() <= lsn);
@@ -2146,9 +2537,9 @@ impl Blockchain {
                 );
             }
             match tx {
-                Transaction::PaymentTransaction(_) | Transaction::RestakeTransaction(_) => {
-                    removed.push(tx)
-                }
+                Transaction::PaymentTransaction(_)
+                | Transaction::RestakeTransaction(_)
+                | Transaction::ValidatorKeyRotationTransaction(_) => removed.push(tx),
                 _ => continue,
             }
         }
@@ -2253,6 +2644,7 @@ pub mod tests {
 
     use crate::test;
     use crate::timestamp::Timestamp;
+    use crate::transaction::{PaymentTransaction, ValidatorKeyRotationTransaction};
     use rand::Rng;
     use simple_logger;
     use std::collections::BTreeMap;
@@ -2409,6 +2801,8 @@ pub mod tests {
             }
             unspent.sort();
 
+            let validators_len = chain.validators_at_epoch_start().len();
+
             // Remove all micro blocks.
             while chain.offset() > 0 {
                 chain.pop_micro_block().expect("Should be ok");
@@ -2423,6 +2817,24 @@ pub mod tests {
             assert_eq!(hash, chain.last_block_hash());
             assert_eq!(epoch + 1, chain.epoch());
             assert_eq!(0, chain.offset());
+
+            // Check validators performance.
+            let stats: Vec<ValidatorStats> = keychains
+                .iter()
+                .flat_map(|k| {
+                    chain
+                        .validator_stats(&k.network_pkey, epoch, epoch)
+                        .unwrap()
+                })
+                .collect();
+            assert_eq!(stats.len(), validators_len);
+            let blocks_proposed: u32 = stats.iter().map(|s| s.blocks_proposed).sum();
+            assert_eq!(blocks_proposed, chain.cfg().micro_blocks_in_epoch + 1);
+            for s in &stats {
+                assert_eq!(s.epoch, epoch);
+                assert_eq!(s.view_changes, 0);
+                assert_eq!(s.missed_macro_signatures, 0);
+            }
         }
 
         //
@@ -2891,6 +3303,500 @@ pub mod tests {
             chain.last_macro_block_timestamp()
         );
     }
+
+    /// Stakes of a key rotated in a micro block.
+    fn rotate_leader_key(
+        chain: &Blockchain,
+        keychains: &[test::KeyChain],
+    ) -> (usize, pbc::PublicKey, ValidatorKeyRotationTransaction) {
+        let leader_pkey = chain.leader();
+        let leader = keychains
+            .iter()
+            .position(|k| k.network_pkey == leader_pkey)
+            .unwrap();
+        let stake_hashes: Vec<Hash> = chain
+            .iter_validator_stakes(&leader_pkey)
+            .map(|(hash, _, _, _)| *hash)
+            .collect();
+        let stakes: Vec<Output> = stake_hashes
+            .iter()
+            .map(|hash| chain.output_by_hash(hash).unwrap().unwrap())
+            .collect();
+        let (new_network_skey, new_network_pkey) = pbc::make_random_keys();
+        let tx = ValidatorKeyRotationTransaction::new(
+            &keychains[leader].account_skey,
+            &keychains[leader].network_skey,
+            &new_network_skey,
+            &new_network_pkey,
+            &stakes,
+        )
+        .unwrap();
+        (leader, new_network_pkey, tx)
+    }
+
+    #[test]
+    fn key_rotation() {
+        simple_logger::init_with_level(log::Level::Debug).unwrap_or_default();
+        let mut cfg: ChainConfig = Default::default();
+        cfg.micro_blocks_in_epoch = 2;
+        let mut timestamp = Timestamp::now();
+        let (keychains, genesis) = test::fake_genesis(
+            cfg.min_stake_amount,
+            10 * cfg.min_stake_amount,
+            cfg.max_slot_count,
+            3,
+            timestamp,
+            None,
+        );
+        let chain_dir = TempDir::new("test").unwrap();
+        let mut chain = Blockchain::new(
+            cfg.clone(),
+            chain_dir.path(),
+            ConsistencyCheck::Full,
+            genesis,
+            timestamp,
+        )
+        .expect("Failed to create blockchain");
+
+        let (leader, new_network_pkey, tx) = rotate_leader_key(&chain, &keychains);
+        let old_network_pkey = keychains[leader].network_pkey;
+        let account_pkey = keychains[leader].account_pkey;
+        timestamp += Duration::from_millis(1);
+        let block = test::create_micro_block_with_transactions(
+            &chain,
+            &keychains,
+            timestamp,
+            vec![tx.into()],
+        );
+        chain
+            .push_micro_block(block, timestamp)
+            .expect("no I/O errors");
+
+        // The old key stays a validator until the end of epoch.
+        assert!(chain.is_validator(&old_network_pkey));
+        assert_eq!(
+            chain.account_by_network_key(&old_network_pkey),
+            Some(account_pkey)
+        );
+        assert_eq!(chain.account_by_network_key(&new_network_pkey), None);
+
+        while !chain.is_epoch_full() {
+            timestamp += Duration::from_millis(1);
+            let block = test::create_micro_block_with_coinbase(&chain, &keychains, timestamp);
+            chain
+                .push_micro_block(block, timestamp)
+                .expect("no I/O errors");
+        }
+        timestamp += Duration::from_millis(1);
+        let (block, _extra_transactions) =
+            test::create_fake_macro_block(&chain, &keychains, timestamp);
+        while chain.offset() > 0 {
+            chain.pop_micro_block().expect("Should be ok");
+        }
+        chain
+            .push_macro_block(block, timestamp)
+            .expect("Invalid block");
+
+        // The new key is elected for the next epoch.
+        assert!(!chain.is_validator(&old_network_pkey));
+        assert!(chain.is_validator(&new_network_pkey));
+        assert_eq!(chain.account_by_network_key(&old_network_pkey), None);
+        assert_eq!(
+            chain.account_by_network_key(&new_network_pkey),
+            Some(account_pkey)
+        );
+    }
+
+    #[test]
+    fn key_rotation_after_equivocation() {
+        use crate::slashing::{confiscate_tx, SlashingProof};
+
+        simple_logger::init_with_level(log::Level::Debug).unwrap_or_default();
+        let mut cfg: ChainConfig = Default::default();
+        cfg.micro_blocks_in_epoch = 5;
+        let mut timestamp = Timestamp::now();
+        let (keychains, genesis) = test::fake_genesis(
+            cfg.min_stake_amount,
+            10 * cfg.min_stake_amount,
+            cfg.max_slot_count,
+            3,
+            timestamp,
+            None,
+        );
+        let chain_dir = TempDir::new("test").unwrap();
+        let mut chain = Blockchain::new(
+            cfg.clone(),
+            chain_dir.path(),
+            ConsistencyCheck::Full,
+            genesis,
+            timestamp,
+        )
+        .expect("Failed to create blockchain");
+
+        // The leader signs two blocks and rotates its key in one of them.
+        let (leader, new_network_pkey, tx) = rotate_leader_key(&chain, &keychains);
+        let cheater = keychains[leader].network_pkey;
+        let rotated: Vec<Hash> = tx.txouts.iter().map(Hash::digest).collect();
+        timestamp += Duration::from_millis(1);
+        let block1 = test::create_micro_block_with_transactions(
+            &chain,
+            &keychains,
+            timestamp,
+            vec![tx.into()],
+        );
+        let block2 = test::create_micro_block_with_coinbase(
+            &chain,
+            &keychains,
+            timestamp + Duration::from_millis(1),
+        );
+        let proof = SlashingProof::new_unchecked(block1.clone(), block2);
+        chain
+            .push_micro_block(block1, timestamp)
+            .expect("no I/O errors");
+
+        // The rotated stakes are still slashable.
+        let leader_pkey = chain.leader();
+        let slashing_tx = confiscate_tx(&chain, &leader_pkey, proof).expect("slashable");
+        let mut txins = slashing_tx.txins.clone();
+        txins.sort();
+        let mut expected = rotated.clone();
+        expected.sort();
+        assert_eq!(txins, expected);
+
+        timestamp += Duration::from_millis(2);
+        let block = test::create_micro_block_with_transactions(
+            &chain,
+            &keychains,
+            timestamp,
+            vec![slashing_tx.into()],
+        );
+        chain
+            .push_micro_block(block, timestamp)
+            .expect("no I/O errors");
+        for output_hash in &rotated {
+            assert!(!chain.contains_output(output_hash));
+        }
+        assert!(!chain.is_validator(&cheater));
+        assert_eq!(chain.iter_validator_stakes(&cheater).count(), 0);
+        assert_eq!(chain.iter_validator_stakes(&new_network_pkey).count(), 0);
+        assert!(chain
+            .escrow
+            .get_stakers_majority(chain.epoch() + 1, cfg.min_stake_amount)
+            .iter()
+            .all(|(validator_pkey, _)| *validator_pkey != new_network_pkey));
+    }
+
+    fn delegation_rewards(transactions: &[Transaction]) -> Vec<Output> {
+        transactions
+            .iter()
+            .find_map(|tx| match tx {
+                Transaction::DelegationRewardTransaction(tx) => Some(tx.rewards.clone()),
+                _ => None,
+            })
+            .expect("delegation rewards")
+    }
+
+    #[test]
+    fn delegation_rewards() {
+        simple_logger::init_with_level(log::Level::Debug).unwrap_or_default();
+        let mut cfg: ChainConfig = Default::default();
+        cfg.micro_blocks_in_epoch = 10;
+        let mut timestamp = Timestamp::now();
+        let (keychains, genesis) = test::fake_genesis(
+            cfg.min_stake_amount,
+            10 * cfg.min_stake_amount,
+            cfg.max_slot_count,
+            3,
+            timestamp,
+            None,
+        );
+        let chain_dir = TempDir::new("test").unwrap();
+        let mut chain = Blockchain::new(
+            cfg.clone(),
+            chain_dir.path(),
+            ConsistencyCheck::Full,
+            genesis,
+            timestamp,
+        )
+        .expect("Failed to create blockchain");
+
+        // The first account delegates to other validators.
+        let delegator = &keychains[0];
+        let delegated: Vec<pbc::PublicKey> =
+            keychains[1..].iter().map(|k| k.network_pkey).collect();
+        let (tx, stakes) = test::create_delegation_transaction(
+            &chain,
+            delegator,
+            &delegated,
+            cfg.min_stake_amount,
+        );
+        timestamp += Duration::from_millis(1);
+        let block = test::create_micro_block_with_transactions(
+            &chain,
+            &keychains,
+            timestamp,
+            vec![tx.into()],
+        );
+        chain
+            .push_micro_block(block, timestamp)
+            .expect("no I/O errors");
+
+        // Validators share block rewards with the delegator.
+        assert_eq!(
+            chain.coinbase_reward(&delegator.network_pkey),
+            cfg.block_reward
+        );
+        for validator_pkey in &delegated {
+            let delegations = chain.delegations(validator_pkey).unwrap();
+            assert_eq!(
+                delegations.delegators.get(&delegator.account_pkey),
+                Some(&cfg.min_stake_amount)
+            );
+            assert_eq!(chain.coinbase_reward(validator_pkey), cfg.block_reward / 2);
+        }
+
+        // The delegator can't leave in the middle of epoch.
+        let (output, outputs_gamma) =
+            PaymentOutput::new(&delegator.account_pkey, cfg.min_stake_amount).unwrap();
+        let inputs = vec![stakes[0].clone()];
+        let outputs: Vec<Output> = vec![output.into()];
+        let tx = PaymentTransaction::new(
+            &delegator.account_skey,
+            &inputs,
+            &outputs,
+            &outputs_gamma,
+            0,
+        )
+        .unwrap();
+        tx.validate(&inputs).unwrap();
+        match chain.validate_stakes(inputs.iter(), outputs.iter()) {
+            Err(BlockchainError::StakeIsLocked(..)) => {}
+            e => panic!("{:?}", e),
+        }
+
+        // Coinbase is reduced by the delegator's share.
+        let mut withheld: i64 = 0;
+        while !chain.is_epoch_full() {
+            let block_reward = chain.coinbase_reward(&chain.leader());
+            if block_reward != cfg.block_reward {
+                timestamp += Duration::from_millis(1);
+                let block = test::create_micro_block_with_reward(
+                    &chain,
+                    &keychains,
+                    timestamp,
+                    cfg.block_reward,
+                    Vec::new(),
+                );
+                match chain.validate_micro_block(&block, timestamp, true) {
+                    Err(BlockchainError::BlockError(BlockError::InvalidMicroBlockReward(..))) => {}
+                    e => panic!("{:?}", e),
+                }
+            }
+            withheld += cfg.block_reward - block_reward;
+            timestamp += Duration::from_millis(1);
+            let block = test::create_micro_block_with_coinbase(&chain, &keychains, timestamp);
+            chain
+                .push_micro_block(block, timestamp)
+                .expect("no I/O errors");
+        }
+        assert!(withheld > 0);
+
+        // The macro block pays the withheld rewards.
+        timestamp += Duration::from_millis(1);
+        let (block, extra_transactions) =
+            test::create_fake_macro_block(&chain, &keychains, timestamp);
+        withheld += cfg.block_reward - chain.coinbase_reward(&block.header.pkey);
+        let rewards = delegation_rewards(&extra_transactions);
+        let mut paid: i64 = 0;
+        for output in &rewards {
+            match output {
+                Output::PublicPaymentOutput(o) => {
+                    assert_eq!(o.recipient, delegator.account_pkey);
+                    paid += o.amount;
+                }
+                o => panic!("{:?}", o),
+            }
+        }
+        assert_eq!(paid, withheld);
+
+        let block_hash = Hash::digest(&block);
+        let view_change = chain.view_change();
+        chain
+            .validate_proposed_macro_block(
+                view_change,
+                &block_hash,
+                &block.header,
+                &extra_transactions,
+            )
+            .expect("block is valid");
+
+        // Missing payouts.
+        let missing: Vec<Transaction> = extra_transactions
+            .iter()
+            .filter(|tx| match tx {
+                Transaction::DelegationRewardTransaction(_) => false,
+                _ => true,
+            })
+            .cloned()
+            .collect();
+        match chain.validate_proposed_macro_block(view_change, &block_hash, &block.header, &missing)
+        {
+            Err(BlockchainError::BlockError(BlockError::NoDelegationRewardTx(..))) => {}
+            Err(e) => panic!("{}", e),
+            Ok(_) => panic!("missing delegation rewards are accepted"),
+        }
+
+        // Wrong payouts.
+        let wrong: Vec<Transaction> = extra_transactions
+            .iter()
+            .map(|tx| match tx {
+                Transaction::DelegationRewardTransaction(tx) => {
+                    let mut tx = tx.clone();
+                    tx.rewards[0] =
+                        PublicPaymentOutput::new(&keychains[1].account_pkey, paid).into();
+                    tx.into()
+                }
+                tx => tx.clone(),
+            })
+            .collect();
+        match chain.validate_proposed_macro_block(view_change, &block_hash, &block.header, &wrong) {
+            Err(BlockchainError::BlockError(BlockError::InvalidDelegationRewards(..))) => {}
+            Err(e) => panic!("{}", e),
+            Ok(_) => panic!("wrong delegation rewards are accepted"),
+        }
+
+        while chain.offset() > 0 {
+            chain.pop_micro_block().expect("Should be ok");
+        }
+        chain
+            .push_macro_block(block, timestamp)
+            .expect("Invalid block");
+        for output in &rewards {
+            assert!(chain.contains_output(&Hash::digest(output)));
+        }
+    }
+
+    #[test]
+    fn delegation_rewards_after_slashing() {
+        use crate::slashing::{confiscate_tx, SlashingProof};
+
+        simple_logger::init_with_level(log::Level::Debug).unwrap_or_default();
+        let mut cfg: ChainConfig = Default::default();
+        cfg.micro_blocks_in_epoch = 10;
+        let mut timestamp = Timestamp::now();
+        let (keychains, genesis) = test::fake_genesis(
+            cfg.min_stake_amount,
+            10 * cfg.min_stake_amount,
+            cfg.max_slot_count,
+            3,
+            timestamp,
+            None,
+        );
+        let chain_dir = TempDir::new("test").unwrap();
+        let mut chain = Blockchain::new(
+            cfg.clone(),
+            chain_dir.path(),
+            ConsistencyCheck::Full,
+            genesis,
+            timestamp,
+        )
+        .expect("Failed to create blockchain");
+
+        let delegated: Vec<pbc::PublicKey> =
+            keychains[1..].iter().map(|k| k.network_pkey).collect();
+        let (tx, _stakes) = test::create_delegation_transaction(
+            &chain,
+            &keychains[0],
+            &delegated,
+            cfg.min_stake_amount,
+        );
+        timestamp += Duration::from_millis(1);
+        let block = test::create_micro_block_with_transactions(
+            &chain,
+            &keychains,
+            timestamp,
+            vec![tx.into()],
+        );
+        chain
+            .push_micro_block(block, timestamp)
+            .expect("no I/O errors");
+        while !delegated.contains(&chain.leader()) {
+            assert!(!chain.is_epoch_full());
+            timestamp += Duration::from_millis(1);
+            let block = test::create_micro_block_with_coinbase(&chain, &keychains, timestamp);
+            chain
+                .push_micro_block(block, timestamp)
+                .expect("no I/O errors");
+        }
+
+        // A validator with delegators withholds a reward and signs two blocks.
+        let cheater = chain.leader();
+        let withheld = cfg.block_reward - chain.coinbase_reward(&cheater);
+        assert!(withheld > 0);
+        timestamp += Duration::from_millis(1);
+        let block1 = test::create_micro_block_with_coinbase(&chain, &keychains, timestamp);
+        let block2 = test::create_micro_block_with_coinbase(
+            &chain,
+            &keychains,
+            timestamp + Duration::from_millis(1),
+        );
+        let proof = SlashingProof::new_unchecked(block1.clone(), block2);
+        chain
+            .push_micro_block(block1, timestamp)
+            .expect("no I/O errors");
+
+        // Delegated stakes are confiscated together with the validator's one.
+        let leader_pkey = chain.leader();
+        let slashing_tx = confiscate_tx(&chain, &leader_pkey, proof).expect("slashable");
+        assert_eq!(slashing_tx.txins.len(), 2);
+        timestamp += Duration::from_millis(2);
+        let block = test::create_micro_block_with_transactions(
+            &chain,
+            &keychains,
+            timestamp,
+            vec![slashing_tx.into()],
+        );
+        chain
+            .push_micro_block(block, timestamp)
+            .expect("no I/O errors");
+        assert_eq!(chain.delegations(&cheater), None);
+
+        while !chain.is_epoch_full() {
+            timestamp += Duration::from_millis(1);
+            let block = test::create_micro_block_with_coinbase(&chain, &keychains, timestamp);
+            chain
+                .push_micro_block(block, timestamp)
+                .expect("no I/O errors");
+        }
+
+        // The withheld reward of the cheater goes to the leader.
+        timestamp += Duration::from_millis(1);
+        let (block, extra_transactions) =
+            test::create_fake_macro_block(&chain, &keychains, timestamp);
+        let leader_account = chain.validator_account(&block.header.pkey).unwrap();
+        match delegation_rewards(&extra_transactions).last() {
+            Some(Output::PublicPaymentOutput(o)) => {
+                assert_eq!(o.recipient, leader_account);
+                assert_eq!(o.amount, withheld);
+            }
+            o => panic!("{:?}", o),
+        }
+        let block_hash = Hash::digest(&block);
+        chain
+            .validate_proposed_macro_block(
+                chain.view_change(),
+                &block_hash,
+                &block.header,
+                &extra_transactions,
+            )
+            .expect("block is valid");
+        while chain.offset() > 0 {
+            chain.pop_micro_block().expect("Should be ok");
+        }
+        chain
+            .push_macro_block(block, timestamp)
+            .expect("Invalid block");
+    }
 }
 
 impl BlockReader for Blockchain {
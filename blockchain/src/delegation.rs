@@ -0,0 +1,173 @@
+//! Delegated staking.
+
+//
+// MIT License
+//
+// Copyright (c) 2018 Stegos AG
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use crate::output::MAX_COMMISSION;
+use std::collections::BTreeMap;
+use stegos_crypto::scc::PublicKey;
+
+/// Active stakes bound to a validator key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Delegations {
+    /// Account of validator.
+    pub owner: PublicKey,
+    /// Commission of validator in basis points.
+    pub commission: u32,
+    /// Active stake of validator itself.
+    pub own_stake: i64,
+    /// Active stakes of delegators, grouped by account.
+    pub delegators: BTreeMap<PublicKey, i64>,
+}
+
+impl Delegations {
+    ///
+    /// Split reward between validator and delegators proportionally to active stakes.
+    /// Validator takes commission from delegators' part and the rounding remainder.
+    ///
+    /// Returns non-zero payouts, validator goes first.
+    ///
+    pub fn split(&self, reward: i64) -> Vec<(PublicKey, i64)> {
+        assert!(reward >= 0);
+        let total: i128 = self.own_stake as i128
+            + self
+                .delegators
+                .values()
+                .map(|amount| *amount as i128)
+                .sum::<i128>();
+
+        let mut owner_reward = reward;
+        let mut payouts: Vec<(PublicKey, i64)> = Vec::with_capacity(self.delegators.len() + 1);
+        if total > 0 {
+            for (account_pkey, amount) in &self.delegators {
+                let gross = reward as i128 * *amount as i128 / total;
+                let commission = gross * self.commission as i128 / MAX_COMMISSION as i128;
+                let net = (gross - commission) as i64;
+                if net > 0 {
+                    owner_reward -= net;
+                    payouts.push((*account_pkey, net));
+                }
+            }
+        }
+        assert!(owner_reward >= 0);
+        if owner_reward > 0 {
+            payouts.insert(0, (self.owner, owner_reward));
+        }
+        payouts
+    }
+
+    ///
+    /// Returns part of reward which belongs to delegators.
+    ///
+    pub fn delegators_share(&self, reward: i64) -> i64 {
+        self.split(reward)
+            .into_iter()
+            .filter(|(account_pkey, _)| *account_pkey != self.owner)
+            .map(|(_, amount)| amount)
+            .sum()
+    }
+
+    ///
+    /// Distribute reward withheld from delegators, proportionally to their stakes.
+    /// Commission was already taken, validator gets only the rounding remainder
+    /// or the whole amount if there are no delegators anymore.
+    ///
+    pub fn split_delegated(&self, reward: i64) -> Vec<(PublicKey, i64)> {
+        let delegated = Delegations {
+            owner: self.owner,
+            commission: 0,
+            own_stake: 0,
+            delegators: self.delegators.clone(),
+        };
+        delegated.split(reward)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use stegos_crypto::scc;
+
+    fn delegations(commission: u32, own_stake: i64, stakes: &[i64]) -> Delegations {
+        Delegations {
+            owner: scc::make_random_keys().1,
+            commission,
+            own_stake,
+            delegators: stakes
+                .iter()
+                .map(|amount| (scc::make_random_keys().1, *amount))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn no_delegators() {
+        let d = delegations(1000, 100, &[]);
+        assert_eq!(d.split(24), vec![(d.owner, 24)]);
+        assert_eq!(d.delegators_share(24), 0);
+    }
+
+    #[test]
+    fn proportional() {
+        // 50% of stake is delegated, no commission.
+        let d = delegations(0, 100, &[50, 50]);
+        let payouts = d.split(1000);
+        assert_eq!(payouts.len(), 3);
+        assert_eq!(payouts[0], (d.owner, 500));
+        assert!(payouts[1..].iter().all(|(_, amount)| *amount == 250));
+        assert_eq!(d.delegators_share(1000), 500);
+    }
+
+    #[test]
+    fn commission() {
+        // 10% commission on a fully delegated validator.
+        let d = delegations(1000, 0, &[100]);
+        let payouts = d.split(1000);
+        assert_eq!(payouts[0], (d.owner, 100));
+        assert_eq!(payouts[1].1, 900);
+
+        // 100% commission leaves nothing to delegators.
+        let d = delegations(MAX_COMMISSION, 10, &[100]);
+        assert_eq!(d.split(1000), vec![(d.owner, 1000)]);
+    }
+
+    #[test]
+    fn withheld() {
+        let d = delegations(5000, 1000, &[10, 30]);
+        let payouts = d.split_delegated(100);
+        assert_eq!(payouts.len(), 2);
+        assert_eq!(payouts[0].1 + payouts[1].1, 100);
+
+        let d = delegations(5000, 1000, &[]);
+        assert_eq!(d.split_delegated(100), vec![(d.owner, 100)]);
+    }
+
+    #[test]
+    fn remainder() {
+        let d = delegations(0, 1, &[1, 1]);
+        let payouts = d.split(10);
+        let total: i64 = payouts.iter().map(|(_, amount)| amount).sum();
+        assert_eq!(total, 10);
+        assert_eq!(payouts[0], (d.owner, 4));
+    }
+}
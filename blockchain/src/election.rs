@@ -165,6 +165,9 @@ pub fn select_validators_slots(
     }
 }
 
+/// The number of facilitator candidates per epoch.
+pub const FACILITATOR_CANDIDATES: usize = 3;
+
 pub fn select_facilitator(random: &Hash, validators: &StakersGroup) -> pbc::PublicKey {
     // generate special random for facilitator.
     let mut hasher = Hasher::new();
@@ -176,6 +179,36 @@ pub fn select_facilitator(random: &Hash, validators: &StakersGroup) -> pbc::Publ
     validators[facilitator_id].0.clone()
 }
 
+/// Returns up to `count` distinct facilitator candidates in the order of preference.
+/// The first candidate is the one returned by `select_facilitator`,
+/// others are used by wallets when the previous candidate doesn't respond.
+pub fn select_facilitators(
+    random: &Hash,
+    validators: &StakersGroup,
+    count: usize,
+) -> Vec<pbc::PublicKey> {
+    let mut hasher = Hasher::new();
+    random.hash(&mut hasher);
+    "facilitator".hash(&mut hasher);
+    let seed = hasher.result();
+    let mut rest = validators.clone();
+    let mut candidates = Vec::with_capacity(count);
+    for i in 0..count {
+        // The first draw must match select_facilitator().
+        let rand = if i == 0 {
+            shrink_hash(seed)
+        } else {
+            generate_u64(seed, i as u32)
+        };
+        let index = match select_winner(rest.iter().map(|(_k, slots)| slots), rand) {
+            Some(index) => index,
+            None => break,
+        };
+        candidates.push(rest.remove(index).0);
+    }
+    candidates
+}
+
 /// Mix seed hash with round value to produce new hash.
 pub fn mix(random: Hash, round: u32) -> Hash {
     let mut hasher = Hasher::new();
@@ -202,7 +235,7 @@ fn generate_u64(seed: Hash, index: u32) -> i64 {
 
 #[cfg(test)]
 mod test {
-    use super::{select_validators_slots, select_winner};
+    use super::{select_facilitator, select_facilitators, select_validators_slots, select_winner};
     use std::collections::{HashMap, HashSet};
 
     use stegos_crypto::hash::Hash;
@@ -299,6 +332,24 @@ mod test {
         assert_eq!(leaders[&1], leaders[&0] * 2);
     }
 
+    #[test]
+    fn test_facilitators() {
+        let validators: Vec<(pbc::PublicKey, i64)> = (1..5)
+            .map(|slots| (pbc::make_random_keys().1, slots))
+            .collect();
+        let random = Hash::digest("random");
+        let candidates = select_facilitators(&random, &validators, 3);
+        assert_eq!(candidates.len(), 3);
+        assert_eq!(candidates[0], select_facilitator(&random, &validators));
+        let unique: HashSet<_> = candidates.iter().collect();
+        assert_eq!(unique.len(), candidates.len());
+        assert_eq!(candidates, select_facilitators(&random, &validators, 3));
+
+        // Limited by the number of validators.
+        let candidates = select_facilitators(&random, &validators, 10);
+        assert_eq!(candidates.len(), validators.len());
+    }
+
     /// Check if group size actually depends on limit.
     #[test]
     fn test_group_size() {
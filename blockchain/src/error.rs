@@ -67,6 +67,33 @@ pub enum BlockchainError {
         _0, _1, _2
     )]
     StakeIsLocked(pbc::PublicKey, i64, i64),
+    #[fail(
+        display = "Validator has no own stakes to delegate to: validator={}, utxo={}",
+        _0, _1
+    )]
+    NoDelegationTarget(pbc::PublicKey, Hash),
+    #[fail(
+        display = "Validator can't delegate to itself: validator={}, utxo={}",
+        _0, _1
+    )]
+    SelfDelegation(pbc::PublicKey, Hash),
+    #[fail(
+        display = "Key rotation must include all stakes of validator: validator={}",
+        _0
+    )]
+    IncompleteKeyRotation(pbc::PublicKey),
+    #[fail(display = "Validator key already has stakes: validator={}", _0)]
+    ValidatorKeyInUse(pbc::PublicKey),
+    #[fail(
+        display = "Validator key rotation is pending until the end of epoch: validator={}",
+        _0
+    )]
+    PendingKeyRotation(pbc::PublicKey),
+    #[fail(
+        display = "Validator key can't be rotated while delegators have active stakes: validator={}",
+        _0
+    )]
+    KeyRotationWithDelegations(pbc::PublicKey),
     #[fail(display = "Storage I/O error={}", _0)]
     StorageError(StorageError),
     #[fail(display = "Transaction error={}", _0)]
@@ -143,6 +170,9 @@ pub enum TransactionError {
     #[fail(display = "TXIN amount .ne. TXOUT amount: tx={}", _0)]
     ImbalancedRestaking(Hash),
 
+    #[fail(display = "Validator key is not changed: tx={}, utxo={}", _0, _1)]
+    SameValidatorKey(Hash, Hash),
+
     #[fail(display = "Slashing error ={}", _0)]
     SlashingError(SlashingError),
 }
@@ -213,10 +243,10 @@ pub enum BlockError {
     )]
     NoServiceAwardTx(Hash),
     #[fail(
-        display = "Found that service award with more than one winner: block={}, winner_count={}",
-        _0, _1
+        display = "Found service award with invalid number of payouts: block={}, got={}, expected={}",
+        _0, _1, _2
     )]
-    AwardMoreThanOneWinner(Hash, usize),
+    AwardInvalidPayoutsLen(Hash, usize, usize),
     #[fail(
         display = "Found that service award produce different winner: block={}, \
                    actual_winner={}, award_winner={}",
@@ -225,6 +255,10 @@ pub enum BlockError {
     AwardDifferentWinner(Hash, PublicKey, PublicKey),
     #[fail(display = "Found service award tx with different output: block={}", _0)]
     AwardDifferentOutputType(Hash),
+    #[fail(display = "Delegators have rewards, but no tx found: block={}", _0)]
+    NoDelegationRewardTx(Hash),
+    #[fail(display = "Invalid delegation rewards: block={}", _0)]
+    InvalidDelegationRewards(Hash),
     #[fail(
         display = "Found that service award produce different reward: block={}, \
                    actual_reward={}, award_reward={}",
@@ -23,13 +23,15 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
+use crate::delegation::Delegations;
 use crate::error::BlockchainError;
 use crate::mvcc::MultiVersionedMap;
 use crate::output::Output;
 use log::*;
 use serde_derive::{Deserialize, Serialize};
+use std::cmp::Reverse;
 use std::collections::BTreeMap;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use stegos_crypto::hash::Hash;
 use stegos_crypto::pbc;
 use stegos_crypto::scc;
@@ -45,6 +47,51 @@ pub(crate) struct EscrowValue {
     pub(crate) account_pkey: scc::PublicKey,
     pub(crate) active_until_epoch: u64,
     pub(crate) amount: i64,
+    pub(crate) commission: Commission,
+    pub(crate) delegated: bool,
+    pub(crate) maintenance_epoch: u64,
+    /// The new key of validator, if the stake has been rotated during the current epoch.
+    /// The stake stays bound to the old key (and slashable) until the end of epoch.
+    pub(crate) rotated_to: Option<pbc::PublicKey>,
+}
+
+/// Commission declared by validator, in basis points.
+/// An increase applies only after delegations locked under the previous commission expire.
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub(crate) struct Commission {
+    /// Declared commission.
+    pub(crate) value: u32,
+    /// Epoch of the declaration.
+    pub(crate) declared_epoch: u64,
+    /// Commission applied until active_from_epoch.
+    pub(crate) previous_value: u32,
+    /// Epoch since which the declared commission applies.
+    pub(crate) active_from_epoch: u64,
+}
+
+impl Commission {
+    ///
+    /// Create a commission, which applies immediately.
+    ///
+    pub(crate) fn new(value: u32, epoch: u64) -> Self {
+        Commission {
+            value,
+            declared_epoch: epoch,
+            previous_value: value,
+            active_from_epoch: epoch,
+        }
+    }
+
+    ///
+    /// Return commission applied in the epoch.
+    ///
+    pub(crate) fn at(&self, epoch: u64) -> u32 {
+        if epoch >= self.active_from_epoch {
+            self.value
+        } else {
+            self.previous_value
+        }
+    }
 }
 
 use crate::LSN;
@@ -78,6 +125,20 @@ pub struct StakeInfo {
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub is_active: Option<bool>,
     pub amount: i64,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub commission: u32,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub delegated: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub maintenance_epoch: Option<u64>,
+}
+
+fn is_zero(value: &u32) -> bool {
+    *value == 0
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
 }
 
 impl Escrow {
@@ -98,10 +159,13 @@ impl Escrow {
         account_pkey: scc::PublicKey,
         output_hash: Hash,
         epoch: u64,
-        stakes_epoch: u64,
+        active_until_epoch: u64,
         amount: i64,
+        commission: Commission,
+        delegated: bool,
+        maintenance_epoch: u64,
+        rotated_to: Option<pbc::PublicKey>,
     ) {
-        let active_until_epoch = epoch + stakes_epoch;
         let key = EscrowKey {
             validator_pkey,
             output_hash,
@@ -110,6 +174,10 @@ impl Escrow {
             account_pkey,
             active_until_epoch,
             amount,
+            commission,
+            delegated,
+            maintenance_epoch,
+            rotated_to,
         };
 
         if let Some(v) = self.escrow.insert(lsn, key, value) {
@@ -136,10 +204,8 @@ impl Escrow {
         output_hash: Hash,
         epoch: u64,
     ) {
-        let key = EscrowKey {
-            validator_pkey,
-            output_hash,
-        };
+        let key = self.escrow_key(validator_pkey, output_hash);
+        let validator_pkey = key.validator_pkey;
         let val = self.escrow.remove(lsn, &key).expect("stake exists");
 
         let (active_balance, expired_balance) = self.validator_balance(&validator_pkey, epoch);
@@ -150,12 +216,65 @@ impl Escrow {
     }
 
     ///
-    /// Iterate over stakes of specified validator.
+    /// Find an escrow record of the stake.
+    /// Rotated stakes are bound to the old key of validator until the end of epoch.
     ///
-    pub fn iter_validator_stakes(
+    fn escrow_key(&self, validator_pkey: pbc::PublicKey, output_hash: Hash) -> EscrowKey {
+        let key = EscrowKey {
+            validator_pkey,
+            output_hash,
+        };
+        if self.escrow.get(&key).is_some() {
+            return key;
+        }
+        self.escrow
+            .iter()
+            .find(|(k, v)| k.output_hash == output_hash && v.rotated_to == Some(validator_pkey))
+            .map(|(k, _v)| k.clone())
+            .unwrap_or(key)
+    }
+
+    ///
+    /// Return the validator and the lock of an own stake.
+    ///
+    pub(crate) fn own_stake_lock(
+        &self,
+        validator_pkey: pbc::PublicKey,
+        output_hash: Hash,
+    ) -> Option<(pbc::PublicKey, u64)> {
+        let key = self.escrow_key(validator_pkey, output_hash);
+        self.escrow
+            .get(&key)
+            .filter(|value| !value.delegated)
+            .map(|value| (key.validator_pkey, value.active_until_epoch))
+    }
+
+    ///
+    /// Returns true if the key has stakes or pending rotation of stakes.
+    ///
+    fn is_key_in_use(&self, validator_pkey: &pbc::PublicKey) -> bool {
+        self.iter_stakes(validator_pkey).next().is_some()
+            || self
+                .escrow
+                .iter()
+                .any(|(_k, v)| v.rotated_to.as_ref() == Some(validator_pkey))
+    }
+
+    ///
+    /// Returns true if own stakes of the validator are being rotated to a new key.
+    ///
+    fn is_rotation_pending(&self, validator_pkey: &pbc::PublicKey) -> bool {
+        self.iter_own_stakes(validator_pkey)
+            .any(|(_key, value)| value.rotated_to.is_some())
+    }
+
+    ///
+    /// Iterate over escrow records of specified validator.
+    ///
+    fn iter_stakes(
         &self,
         validator_pkey: &pbc::PublicKey,
-    ) -> impl Iterator<Item = (&Hash, i64, &scc::PublicKey, u64)> {
+    ) -> impl Iterator<Item = (&EscrowKey, &EscrowValue)> {
         let (hash_min, hash_max) = Hash::bounds();
         let key_min = EscrowKey {
             validator_pkey: validator_pkey.clone(),
@@ -166,7 +285,28 @@ impl Escrow {
             output_hash: hash_max,
         };
 
-        self.escrow.range(&key_min..=&key_max).map(|(key, value)| {
+        self.escrow.range(&key_min..=&key_max)
+    }
+
+    ///
+    /// Iterate over own (non-delegated) stakes of specified validator.
+    ///
+    fn iter_own_stakes(
+        &self,
+        validator_pkey: &pbc::PublicKey,
+    ) -> impl Iterator<Item = (&EscrowKey, &EscrowValue)> {
+        self.iter_stakes(validator_pkey)
+            .filter(|(_key, value)| !value.delegated)
+    }
+
+    ///
+    /// Iterate over stakes of specified validator.
+    ///
+    pub fn iter_validator_stakes(
+        &self,
+        validator_pkey: &pbc::PublicKey,
+    ) -> impl Iterator<Item = (&Hash, i64, &scc::PublicKey, u64)> {
+        self.iter_stakes(validator_pkey).map(|(key, value)| {
             (
                 &key.output_hash,
                 value.amount,
@@ -194,16 +334,139 @@ impl Escrow {
         )
     }
 
+    ///
+    /// Get staked value of account for validator.
+    ///
+    /// Returns (active_balance, expired_balance) stake.
+    ///
+    fn account_balance(
+        &self,
+        validator_pkey: &pbc::PublicKey,
+        account_pkey: &scc::PublicKey,
+        epoch: u64,
+    ) -> (i64, i64) {
+        self.iter_validator_stakes(validator_pkey)
+            .filter(|(_, _, stake_account_pkey, _)| *stake_account_pkey == account_pkey)
+            .fold(
+                (0i64, 0i64),
+                |(active_balance, expired_balance), (_, amount, _, active_until_epoch)| {
+                    if active_until_epoch >= epoch {
+                        (active_balance + amount, expired_balance)
+                    } else {
+                        (active_balance, expired_balance + amount)
+                    }
+                },
+            )
+    }
+
     ///
     /// Return an account key by network key.
+    /// Delegators are never returned.
     ///
     pub fn account_by_network_key(
         &self,
         validator_pkey: &pbc::PublicKey,
     ) -> Option<scc::PublicKey> {
-        self.iter_validator_stakes(&validator_pkey)
+        self.iter_own_stakes(&validator_pkey)
             .next()
-            .map(|(_hash, _amount, account_pkey, _active_until_epoch)| account_pkey.clone())
+            .map(|(_key, value)| value.account_pkey.clone())
+    }
+
+    ///
+    /// Return the most recent commission declaration of validator.
+    /// Lower commission wins if declarations were made in the same epoch.
+    ///
+    fn latest_commission(&self, validator_pkey: &pbc::PublicKey) -> Option<Commission> {
+        self.iter_own_stakes(&validator_pkey)
+            .map(|(_key, value)| value.commission)
+            .max_by_key(|c| (c.declared_epoch, Reverse(c.value)))
+    }
+
+    ///
+    /// Return commission declared by validator, in basis points.
+    ///
+    pub fn validator_commission(&self, validator_pkey: &pbc::PublicKey) -> u32 {
+        self.latest_commission(validator_pkey)
+            .map(|c| c.value)
+            .unwrap_or(0)
+    }
+
+    ///
+    /// Return commission applied to rewards of delegators in the epoch, in basis points.
+    ///
+    pub fn validator_commission_at(&self, validator_pkey: &pbc::PublicKey, epoch: u64) -> u32 {
+        self.latest_commission(validator_pkey)
+            .map(|c| c.at(epoch))
+            .unwrap_or(0)
+    }
+
+    ///
+    /// Schedule commission of a new own stake of validator.
+    /// Re-declaring the same commission keeps the current schedule.
+    /// An increase applies from `epoch + stakes_epoch`, when all delegations made
+    /// under the current commission have expired. A decrease applies immediately.
+    ///
+    pub(crate) fn declare_commission(
+        &self,
+        validator_pkey: &pbc::PublicKey,
+        value: u32,
+        epoch: u64,
+        stakes_epoch: u64,
+    ) -> Commission {
+        let latest = match self.latest_commission(validator_pkey) {
+            Some(latest) if latest.value == value => return latest,
+            Some(latest) => latest,
+            // Nobody could delegate before the first declaration.
+            None => return Commission::new(value, epoch),
+        };
+        let current = latest.at(epoch);
+        if value <= current {
+            return Commission::new(value, epoch);
+        }
+        Commission {
+            value,
+            declared_epoch: epoch,
+            previous_value: current,
+            active_from_epoch: epoch + stakes_epoch,
+        }
+    }
+
+    ///
+    /// Return epoch, which validator skips for maintenance, or zero.
+    ///
+    pub fn validator_maintenance_epoch(&self, validator_pkey: &pbc::PublicKey) -> u64 {
+        self.iter_own_stakes(&validator_pkey)
+            .map(|(_key, value)| value.maintenance_epoch)
+            .max()
+            .unwrap_or(0)
+    }
+
+    ///
+    /// Return active stakes of validator and its delegators.
+    ///
+    pub fn delegations(&self, validator_pkey: &pbc::PublicKey, epoch: u64) -> Option<Delegations> {
+        let owner = self.account_by_network_key(validator_pkey)?;
+        let mut delegations = Delegations {
+            owner,
+            commission: self.validator_commission_at(validator_pkey, epoch),
+            own_stake: 0,
+            delegators: BTreeMap::new(),
+        };
+        for (_key, value) in self.iter_stakes(validator_pkey) {
+            if value.active_until_epoch < epoch {
+                // Skip expired stakes.
+                continue;
+            }
+            if value.delegated {
+                *delegations
+                    .delegators
+                    .entry(value.account_pkey)
+                    .or_insert(0) += value.amount;
+            } else {
+                delegations.own_stake += value.amount;
+            }
+        }
+        Some(delegations)
     }
 
     ///
@@ -224,7 +487,10 @@ impl Escrow {
 
     ///
     /// Get all staked values of all validators.
+    /// Delegated stakes are counted in favor of their validator.
     /// Filter out stakers with stake lower than min_stake_amount.
+    /// Filter out validators, which have scheduled maintenance for this epoch.
+    /// Stakes rotated during the current epoch are counted in favor of the new key.
     ///
     pub fn get_stakers_majority(
         &self,
@@ -232,24 +498,48 @@ impl Escrow {
         min_stake_amount: i64,
     ) -> Vec<(pbc::PublicKey, i64)> {
         let mut stakes: BTreeMap<pbc::PublicKey, i64> = BTreeMap::new();
+        let mut owners: HashSet<pbc::PublicKey> = HashSet::new();
+        let mut paused: HashSet<pbc::PublicKey> = HashSet::new();
         for (k, v) in self.escrow.iter() {
+            // Rotated stakes are elected under the new key.
+            let validator_pkey = v.rotated_to.unwrap_or(k.validator_pkey);
+            if !v.delegated {
+                owners.insert(validator_pkey);
+                if v.maintenance_epoch != 0 && v.maintenance_epoch == epoch {
+                    paused.insert(validator_pkey);
+                }
+            }
             if v.active_until_epoch < epoch {
                 // Skip expired stakes.
                 continue;
             }
-            let entry = stakes.entry(k.validator_pkey).or_insert(0);
+            let entry = stakes.entry(validator_pkey).or_insert(0);
             *entry += v.amount;
         }
 
-        // filter out validators with low stake.
-        stakes
+        // filter out validators with low stake and keys without validator.
+        let stakers: Vec<(pbc::PublicKey, i64)> = stakes
             .into_iter()
+            .filter(|(validator_pkey, _)| owners.contains(validator_pkey))
             .filter(|(_, amount)| *amount >= min_stake_amount)
+            .collect();
+
+        // The network can't stop, ignore maintenance if all validators have scheduled it.
+        if stakers
+            .iter()
+            .all(|(validator_pkey, _)| paused.contains(validator_pkey))
+        {
+            return stakers;
+        }
+        stakers
+            .into_iter()
+            .filter(|(validator_pkey, _)| !paused.contains(validator_pkey))
             .collect()
     }
 
     /// Validate that staker didn't try to spent locked stake.
     /// Validate that staker has only one key.
+    /// Validate that delegators stake to an existing validator.
     /// # Arguments
     ///
     /// * - `inputs` - UTXOs referred by self.txins, in the same order as in self.txins.
@@ -263,14 +553,16 @@ impl Escrow {
     where
         OutputIter: Iterator<Item = &'a Output>,
     {
-        let mut staking_balance: HashMap<pbc::PublicKey, i64> = HashMap::new();
+        let mut staking_balance: HashMap<(pbc::PublicKey, scc::PublicKey), i64> = HashMap::new();
         for input in inputs {
             match input {
                 Output::PaymentOutput(_o) => {}
                 Output::PublicPaymentOutput(_o) => {}
                 Output::StakeOutput(o) => {
                     // Update staking balance.
-                    let stake = staking_balance.entry(o.validator).or_insert(0);
+                    let stake = staking_balance
+                        .entry((o.validator, o.recipient))
+                        .or_insert(0);
                     *stake -= o.amount;
                 }
             }
@@ -280,7 +572,30 @@ impl Escrow {
                 Output::PaymentOutput(_o) => {}
                 Output::PublicPaymentOutput(_o) => {}
                 Output::StakeOutput(o) => {
-                    if let Some(account_pkey) = self.account_by_network_key(&o.validator) {
+                    let owner = self.account_by_network_key(&o.validator);
+                    if o.delegated {
+                        if self.is_rotation_pending(&o.validator) {
+                            // The key will be abandoned at the end of epoch.
+                            return Err(BlockchainError::PendingKeyRotation(o.validator));
+                        }
+                        match owner {
+                            None => {
+                                let utxo_hash = Hash::digest(output);
+                                return Err(BlockchainError::NoDelegationTarget(
+                                    o.validator,
+                                    utxo_hash,
+                                ));
+                            }
+                            Some(account_pkey) if account_pkey == o.recipient => {
+                                let utxo_hash = Hash::digest(output);
+                                return Err(BlockchainError::SelfDelegation(
+                                    o.validator,
+                                    utxo_hash,
+                                ));
+                            }
+                            Some(_) => {}
+                        }
+                    } else if let Some(account_pkey) = owner {
                         if account_pkey != o.recipient {
                             let utxo_hash = Hash::digest(output);
                             return Err(BlockchainError::StakeOutputWithDifferentAccountKey(
@@ -292,14 +607,17 @@ impl Escrow {
                         }
                     }
                     // Update staking balance.
-                    let stake = staking_balance.entry(o.validator).or_insert(0);
+                    let stake = staking_balance
+                        .entry((o.validator, o.recipient))
+                        .or_insert(0);
                     *stake += o.amount;
                 }
             };
         }
 
-        for (validator_pkey, balance) in &staking_balance {
-            let (active_balance, expired_balance) = self.validator_balance(validator_pkey, epoch);
+        for ((validator_pkey, account_pkey), balance) in &staking_balance {
+            let (active_balance, expired_balance) =
+                self.account_balance(validator_pkey, account_pkey, epoch);
             let expected_balance = active_balance + expired_balance + balance;
             if expected_balance < active_balance {
                 return Err(BlockchainError::StakeIsLocked(
@@ -313,6 +631,64 @@ impl Escrow {
         Ok(())
     }
 
+    /// Validate that all own stakes of a validator are re-bound to a new key.
+    /// Validate that the stakes haven't been rotated during this epoch yet.
+    /// Validate that the validator has no active delegations, which would stay bound to the old key.
+    /// Validate that the new key has no stakes yet.
+    /// # Arguments
+    ///
+    /// * - `inputs` - UTXOs referred by self.txins, in the same order as in self.txins.
+    ///
+    pub fn validate_key_rotation<'a, OutputIter>(
+        &self,
+        inputs: OutputIter,
+        outputs: OutputIter,
+        epoch: u64,
+    ) -> Result<(), BlockchainError>
+    where
+        OutputIter: Iterator<Item = &'a Output>,
+    {
+        let mut rotated: HashMap<pbc::PublicKey, HashSet<Hash>> = HashMap::new();
+        for input in inputs {
+            if let Output::StakeOutput(o) = input {
+                let output_hash = Hash::digest(input);
+                let key = EscrowKey {
+                    validator_pkey: o.validator,
+                    output_hash,
+                };
+                if self.escrow.get(&key).is_none() {
+                    // The stake is still bound to the previous key.
+                    return Err(BlockchainError::PendingKeyRotation(o.validator));
+                }
+                rotated.entry(o.validator).or_default().insert(output_hash);
+            }
+        }
+        for (validator_pkey, rotated) in &rotated {
+            let all_stakes = self
+                .iter_own_stakes(validator_pkey)
+                .all(|(key, _value)| rotated.contains(&key.output_hash));
+            if !all_stakes {
+                return Err(BlockchainError::IncompleteKeyRotation(*validator_pkey));
+            }
+            let has_delegations = self
+                .iter_stakes(validator_pkey)
+                .any(|(_key, value)| value.delegated && value.active_until_epoch >= epoch);
+            if has_delegations {
+                return Err(BlockchainError::KeyRotationWithDelegations(*validator_pkey));
+            }
+        }
+
+        for output in outputs {
+            if let Output::StakeOutput(o) = output {
+                if self.is_key_in_use(&o.validator) {
+                    return Err(BlockchainError::ValidatorKeyInUse(o.validator));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     /// Returns an object that represent printable part of the state.
     pub fn info(&self, epoch: u64) -> EscrowInfo {
         let mut validators: HashMap<pbc::PublicKey, ValidatorInfo> = HashMap::new();
@@ -332,6 +708,13 @@ impl Escrow {
                 active_until_epoch: v.active_until_epoch.into(),
                 is_active: is_active.into(),
                 amount: v.amount,
+                commission: v.commission.value,
+                delegated: v.delegated,
+                maintenance_epoch: if v.maintenance_epoch != 0 {
+                    Some(v.maintenance_epoch)
+                } else {
+                    None
+                },
             };
             (*entry).stakes.push(stake);
             if is_active {
@@ -360,3 +743,427 @@ impl Escrow {
         self.escrow.rollback_to_lsn(to_lsn);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::output::StakeOutput;
+
+    #[test]
+    fn delegation() {
+        let (validator_skey, validator_pkey) = pbc::make_random_keys();
+        let (_owner_skey, owner_pkey) = scc::make_random_keys();
+        let (_delegator_skey, delegator_pkey) = scc::make_random_keys();
+        let epoch: u64 = 1;
+        let stakes_epoch: u64 = 2;
+        let none: [Output; 0] = [];
+        let mut escrow = Escrow::new();
+
+        // Validator doesn't exist yet.
+        let delegated: Output = StakeOutput::new_delegated(&delegator_pkey, &validator_pkey, 50)
+            .unwrap()
+            .into();
+        match escrow.validate_stakes(none.iter(), [delegated.clone()].iter(), epoch) {
+            Err(BlockchainError::NoDelegationTarget(..)) => {}
+            e => panic!("{:?}", e),
+        }
+
+        let own: Output = StakeOutput::new_with_commission(
+            &owner_pkey,
+            &validator_skey,
+            &validator_pkey,
+            100,
+            1000,
+        )
+        .unwrap()
+        .into();
+        escrow.stake(
+            LSN(epoch, 0),
+            validator_pkey,
+            owner_pkey,
+            Hash::digest(&own),
+            epoch,
+            epoch + stakes_epoch,
+            100,
+            Commission::new(1000, epoch),
+            false,
+            0,
+            None,
+        );
+        escrow
+            .validate_stakes(none.iter(), [delegated.clone()].iter(), epoch)
+            .unwrap();
+
+        // Validator can't delegate to itself.
+        let self_delegated: Output = StakeOutput::new_delegated(&owner_pkey, &validator_pkey, 50)
+            .unwrap()
+            .into();
+        match escrow.validate_stakes(none.iter(), [self_delegated].iter(), epoch) {
+            Err(BlockchainError::SelfDelegation(..)) => {}
+            e => panic!("{:?}", e),
+        }
+
+        escrow.stake(
+            LSN(epoch, 1),
+            validator_pkey,
+            delegator_pkey,
+            Hash::digest(&delegated),
+            epoch,
+            epoch + stakes_epoch,
+            50,
+            Commission::new(0, epoch),
+            true,
+            0,
+            None,
+        );
+        assert_eq!(
+            escrow.account_by_network_key(&validator_pkey),
+            Some(owner_pkey)
+        );
+        let delegations = escrow.delegations(&validator_pkey, epoch).unwrap();
+        assert_eq!(delegations.owner, owner_pkey);
+        assert_eq!(delegations.commission, 1000);
+        assert_eq!(delegations.own_stake, 100);
+        assert_eq!(delegations.delegators.get(&delegator_pkey), Some(&50));
+        assert_eq!(
+            escrow.get_stakers_majority(epoch, 0),
+            vec![(validator_pkey, 150)]
+        );
+
+        // Delegations would stay bound to the old key.
+        let (new_skey, new_pkey) = pbc::make_random_keys();
+        let rotated: Output =
+            StakeOutput::new_with_commission(&owner_pkey, &new_skey, &new_pkey, 100, 1000)
+                .unwrap()
+                .into();
+        match escrow.validate_key_rotation([own.clone()].iter(), [rotated.clone()].iter(), epoch) {
+            Err(BlockchainError::KeyRotationWithDelegations(..)) => {}
+            e => panic!("{:?}", e),
+        }
+        escrow
+            .validate_key_rotation([own].iter(), [rotated].iter(), epoch + stakes_epoch + 1)
+            .unwrap();
+
+        // Active stakes are locked.
+        match escrow.validate_stakes([delegated.clone()].iter(), none.iter(), epoch) {
+            Err(BlockchainError::StakeIsLocked(..)) => {}
+            e => panic!("{:?}", e),
+        }
+        escrow
+            .validate_stakes([delegated].iter(), none.iter(), epoch + stakes_epoch + 1)
+            .unwrap();
+    }
+
+    #[test]
+    fn commission_increase() {
+        let (validator_skey, validator_pkey) = pbc::make_random_keys();
+        let (_owner_skey, owner_pkey) = scc::make_random_keys();
+        let (_delegator_skey, delegator_pkey) = scc::make_random_keys();
+        let stakes_epoch: u64 = 5;
+        let mut escrow = Escrow::new();
+
+        // The first declaration applies immediately.
+        let own = StakeOutput::new_with_commission(
+            &owner_pkey,
+            &validator_skey,
+            &validator_pkey,
+            100,
+            1000,
+        )
+        .unwrap();
+        let commission = escrow.declare_commission(&validator_pkey, 1000, 1, stakes_epoch);
+        assert_eq!(commission, Commission::new(1000, 1));
+        let own_hash = Hash::digest(&own);
+        escrow.stake(
+            LSN(1, 0),
+            validator_pkey,
+            owner_pkey,
+            own_hash,
+            1,
+            1 + stakes_epoch,
+            100,
+            commission,
+            false,
+            0,
+            None,
+        );
+
+        // Delegator is locked until epoch 2 + stakes_epoch.
+        let delegated = StakeOutput::new_delegated(&delegator_pkey, &validator_pkey, 50).unwrap();
+        escrow.stake(
+            LSN(2, 0),
+            validator_pkey,
+            delegator_pkey,
+            Hash::digest(&delegated),
+            2,
+            2 + stakes_epoch,
+            50,
+            Commission::new(0, 2),
+            true,
+            0,
+            None,
+        );
+
+        // Validator raises commission in the middle of the lock.
+        let epoch: u64 = 3;
+        let raised = StakeOutput::new_with_commission(
+            &owner_pkey,
+            &validator_skey,
+            &validator_pkey,
+            100,
+            3000,
+        )
+        .unwrap();
+        let commission = escrow.declare_commission(&validator_pkey, 3000, epoch, stakes_epoch);
+        escrow.unstake(LSN(epoch, 0), validator_pkey, own_hash, epoch);
+        let raised_hash = Hash::digest(&raised);
+        escrow.stake(
+            LSN(epoch, 0),
+            validator_pkey,
+            owner_pkey,
+            raised_hash,
+            epoch,
+            epoch + stakes_epoch,
+            100,
+            commission,
+            false,
+            0,
+            None,
+        );
+        assert_eq!(escrow.validator_commission(&validator_pkey), 3000);
+        for epoch in epoch..epoch + stakes_epoch {
+            let delegations = escrow.delegations(&validator_pkey, epoch).unwrap();
+            assert_eq!(delegations.commission, 1000);
+        }
+        let delegations = escrow
+            .delegations(&validator_pkey, epoch + stakes_epoch)
+            .unwrap();
+        assert_eq!(delegations.commission, 3000);
+        assert!(delegations.delegators.is_empty());
+
+        // Re-staking with the same commission keeps the schedule.
+        assert_eq!(
+            escrow.declare_commission(&validator_pkey, 3000, epoch + 1, stakes_epoch),
+            commission
+        );
+
+        // A decrease applies immediately.
+        let epoch = epoch + 1;
+        let lowered = escrow.declare_commission(&validator_pkey, 500, epoch, stakes_epoch);
+        assert_eq!(lowered, Commission::new(500, epoch));
+        let lowered_output = StakeOutput::new_with_commission(
+            &owner_pkey,
+            &validator_skey,
+            &validator_pkey,
+            100,
+            500,
+        )
+        .unwrap();
+        escrow.unstake(LSN(epoch, 0), validator_pkey, raised_hash, epoch);
+        escrow.stake(
+            LSN(epoch, 0),
+            validator_pkey,
+            owner_pkey,
+            Hash::digest(&lowered_output),
+            epoch,
+            epoch + stakes_epoch,
+            100,
+            lowered,
+            false,
+            0,
+            None,
+        );
+        assert_eq!(escrow.validator_commission(&validator_pkey), 500);
+        let delegations = escrow.delegations(&validator_pkey, epoch).unwrap();
+        assert_eq!(delegations.commission, 500);
+    }
+
+    #[test]
+    fn maintenance() {
+        let (validator1_skey, validator1_pkey) = pbc::make_random_keys();
+        let (validator2_skey, validator2_pkey) = pbc::make_random_keys();
+        let (_account_skey, account_pkey) = scc::make_random_keys();
+        let epoch: u64 = 1;
+        let stakes_epoch: u64 = 5;
+        let mut escrow = Escrow::new();
+
+        let output1 = StakeOutput::new_with_maintenance(
+            &account_pkey,
+            &validator1_skey,
+            &validator1_pkey,
+            100,
+            0,
+            epoch + 1,
+        )
+        .unwrap();
+        let output2 =
+            StakeOutput::new(&account_pkey, &validator2_skey, &validator2_pkey, 100).unwrap();
+        for (offset, output) in [output1, output2].iter().enumerate() {
+            escrow.stake(
+                LSN(epoch, offset as u32),
+                output.validator,
+                output.recipient,
+                Hash::digest(output),
+                epoch,
+                epoch + stakes_epoch,
+                output.amount,
+                Commission::new(output.commission, epoch),
+                output.delegated,
+                output.maintenance_epoch,
+                None,
+            );
+        }
+        assert_eq!(
+            escrow.validator_maintenance_epoch(&validator1_pkey),
+            epoch + 1
+        );
+        assert_eq!(escrow.validator_maintenance_epoch(&validator2_pkey), 0);
+
+        // Validator skips only the scheduled epoch.
+        assert_eq!(
+            escrow.get_stakers_majority(epoch + 1, 0),
+            vec![(validator2_pkey, 100)]
+        );
+        assert_eq!(escrow.get_stakers_majority(epoch + 2, 0).len(), 2);
+
+        // The network can't stop when all validators have scheduled maintenance.
+        let output3 = StakeOutput::new_with_maintenance(
+            &account_pkey,
+            &validator2_skey,
+            &validator2_pkey,
+            100,
+            0,
+            epoch + 1,
+        )
+        .unwrap();
+        escrow.stake(
+            LSN(epoch, 2),
+            validator2_pkey,
+            account_pkey,
+            Hash::digest(&output3),
+            epoch,
+            epoch + stakes_epoch,
+            100,
+            Commission::new(0, epoch),
+            false,
+            epoch + 1,
+            None,
+        );
+        assert_eq!(escrow.get_stakers_majority(epoch + 1, 0).len(), 2);
+    }
+
+    #[test]
+    fn key_rotation() {
+        let (old_skey, old_pkey) = pbc::make_random_keys();
+        let (new_skey, new_pkey) = pbc::make_random_keys();
+        let (other_skey, other_pkey) = pbc::make_random_keys();
+        let (_account_skey, account_pkey) = scc::make_random_keys();
+        let epoch: u64 = 1;
+        let stakes_epoch: u64 = 5;
+        let mut escrow = Escrow::new();
+
+        let old: Output = StakeOutput::new(&account_pkey, &old_skey, &old_pkey, 100)
+            .unwrap()
+            .into();
+        let other: Output = StakeOutput::new(&account_pkey, &other_skey, &other_pkey, 100)
+            .unwrap()
+            .into();
+        for (offset, output) in [old.clone(), other.clone()].iter().enumerate() {
+            let validator_pkey = match output {
+                Output::StakeOutput(o) => o.validator,
+                _ => unreachable!(),
+            };
+            escrow.stake(
+                LSN(epoch, offset as u32),
+                validator_pkey,
+                account_pkey,
+                Hash::digest(output),
+                epoch,
+                epoch + stakes_epoch,
+                100,
+                Commission::new(0, epoch),
+                false,
+                0,
+                None,
+            );
+        }
+
+        // Rotate the key in a micro block.
+        let new: Output = StakeOutput::new(&account_pkey, &new_skey, &new_pkey, 100)
+            .unwrap()
+            .into();
+        escrow
+            .validate_key_rotation([old.clone()].iter(), [new.clone()].iter(), epoch)
+            .unwrap();
+        let new_hash = Hash::digest(&new);
+        escrow.unstake(LSN(epoch, 2), old_pkey, Hash::digest(&old), epoch);
+        escrow.stake(
+            LSN(epoch, 2),
+            old_pkey,
+            account_pkey,
+            new_hash,
+            epoch,
+            epoch + stakes_epoch,
+            100,
+            Commission::new(0, epoch),
+            false,
+            0,
+            Some(new_pkey),
+        );
+
+        // The old key is still slashable until the end of epoch.
+        let stakes: Vec<Hash> = escrow
+            .iter_validator_stakes(&old_pkey)
+            .map(|(hash, _, _, _)| *hash)
+            .collect();
+        assert_eq!(stakes, vec![new_hash]);
+        assert_eq!(escrow.account_by_network_key(&old_pkey), Some(account_pkey));
+        assert_eq!(escrow.account_by_network_key(&new_pkey), None);
+
+        // The new key is elected for the next epoch.
+        let mut expected = vec![(new_pkey, 100), (other_pkey, 100)];
+        expected.sort();
+        assert_eq!(escrow.get_stakers_majority(epoch + 1, 0), expected);
+
+        // The key can be rotated once per epoch.
+        let (newer_skey, newer_pkey) = pbc::make_random_keys();
+        let newer: Output = StakeOutput::new(&account_pkey, &newer_skey, &newer_pkey, 100)
+            .unwrap()
+            .into();
+        match escrow.validate_key_rotation([new.clone()].iter(), [newer].iter(), epoch) {
+            Err(BlockchainError::PendingKeyRotation(..)) => {}
+            e => panic!("{:?}", e),
+        }
+        // Nobody can delegate to the key being abandoned.
+        let (_delegator_skey, delegator_pkey) = scc::make_random_keys();
+        let delegated: Output = StakeOutput::new_delegated(&delegator_pkey, &old_pkey, 50)
+            .unwrap()
+            .into();
+        let none: [Output; 0] = [];
+        match escrow.validate_stakes(none.iter(), [delegated].iter(), epoch) {
+            Err(BlockchainError::PendingKeyRotation(..)) => {}
+            e => panic!("{:?}", e),
+        }
+        // Another validator can't take the pending key.
+        let taken: Output = StakeOutput::new(&account_pkey, &new_skey, &new_pkey, 100)
+            .unwrap()
+            .into();
+        match escrow.validate_key_rotation([other].iter(), [taken].iter(), epoch) {
+            Err(BlockchainError::ValidatorKeyInUse(..)) => {}
+            e => panic!("{:?}", e),
+        }
+
+        // Slashing confiscates the rotated stake.
+        escrow.unstake(LSN(epoch, 3), new_pkey, new_hash, epoch);
+        assert_eq!(escrow.iter_validator_stakes(&old_pkey).count(), 0);
+        assert_eq!(
+            escrow.get_stakers_majority(epoch + 1, 0),
+            vec![(other_pkey, 100)]
+        );
+
+        // Micro blocks are reverted before the macro block.
+        escrow.rollback_to_lsn(LSN(epoch, 1));
+        assert_eq!(escrow.account_by_network_key(&old_pkey), Some(account_pkey));
+        assert_eq!(escrow.iter_validator_stakes(&new_pkey).count(), 0);
+    }
+}
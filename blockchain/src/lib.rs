@@ -28,6 +28,7 @@ mod awards;
 mod block;
 mod blockchain;
 mod config;
+mod delegation;
 pub mod election;
 mod error;
 mod escrow;
@@ -48,6 +49,7 @@ pub use crate::awards::ValidatorAwardState;
 pub use crate::block::*;
 pub use crate::blockchain::*;
 pub use crate::config::*;
+pub use crate::delegation::*;
 pub use crate::election::{mix, ElectionInfo, ElectionResult};
 pub use crate::error::*;
 pub use crate::escrow::*;
@@ -47,6 +47,9 @@ const PAYMENT_PAYLOAD_LEN: usize = 1024;
 pub const PAYMENT_DATA_LEN: usize =
     PAYMENT_PAYLOAD_LEN - PAYMENT_PAYLOAD_CANARY_LEN - 32 - 32 - 8 - 64;
 
+/// Maximal validator commission, in basis points (100%).
+pub const MAX_COMMISSION: u32 = 10_000;
+
 /// UTXO errors.
 #[derive(Debug, Fail)]
 pub enum OutputError {
@@ -69,6 +72,13 @@ pub enum OutputError {
     InvalidAmount(Hash, i64),
     #[fail(display = "Invalid signature on validator pkey: utxo={}", _0)]
     InvalidStakeSignature(Hash),
+    #[fail(display = "Invalid commission: utxo={}, commission={}", _0, _1)]
+    InvalidCommission(Hash, u32),
+    #[fail(
+        display = "Invalid maintenance epoch: utxo={}, maintenance_epoch={}",
+        _0, _1
+    )]
+    InvalidMaintenanceEpoch(Hash, u64),
     #[fail(
         display = "Input is locked: hash={}, tx_time={}, last_macro_block_time={}",
         _0, _1, _2
@@ -141,7 +151,20 @@ pub struct StakeOutput {
     pub serno: i64,
 
     /// BLS signature of recipient, validator and payload.
+    /// Zero for delegated stakes.
     pub signature: pbc::Signature,
+
+    /// Commission of validator in basis points, declared on own stakes.
+    #[serde(default)]
+    pub commission: u32,
+
+    /// Stake of a third-party delegator, not signed by validator.
+    #[serde(default)]
+    pub delegated: bool,
+
+    /// Epoch, which validator skips for maintenance. Zero if not scheduled.
+    #[serde(default)]
+    pub maintenance_epoch: u64,
 }
 
 /// Blockchain UTXO.
@@ -673,6 +696,39 @@ impl StakeOutput {
     ) -> Result<Self, Error> {
         assert!(amount > 0);
 
+        Self::new_with_commission(recipient_pkey, validator_skey, validator_pkey, amount, 0)
+    }
+
+    /// Create a new StakeOutput, which declares validator commission.
+    pub fn new_with_commission(
+        recipient_pkey: &PublicKey,
+        validator_skey: &pbc::SecretKey,
+        validator_pkey: &pbc::PublicKey,
+        amount: i64,
+        commission: u32,
+    ) -> Result<Self, Error> {
+        Self::new_with_maintenance(
+            recipient_pkey,
+            validator_skey,
+            validator_pkey,
+            amount,
+            commission,
+            0,
+        )
+    }
+
+    /// Create a new StakeOutput, which also schedules validator maintenance.
+    pub fn new_with_maintenance(
+        recipient_pkey: &PublicKey,
+        validator_skey: &pbc::SecretKey,
+        validator_pkey: &pbc::PublicKey,
+        amount: i64,
+        commission: u32,
+        maintenance_epoch: u64,
+    ) -> Result<Self, Error> {
+        assert!(amount > 0);
+        assert!(commission <= MAX_COMMISSION);
+
         let serno = random::<i64>();
 
         let mut output = StakeOutput {
@@ -681,6 +737,9 @@ impl StakeOutput {
             amount,
             serno,
             signature: pbc::Signature::zero(),
+            commission,
+            delegated: false,
+            maintenance_epoch,
         };
 
         // Form BLS signature on the Stake UTXO
@@ -690,6 +749,30 @@ impl StakeOutput {
         Ok(output)
     }
 
+    /// Create a new StakeOutput, which delegates money to a third-party validator.
+    pub fn new_delegated(
+        recipient_pkey: &PublicKey,
+        validator_pkey: &pbc::PublicKey,
+        amount: i64,
+    ) -> Result<Self, Error> {
+        assert!(amount > 0);
+
+        let serno = random::<i64>();
+
+        let output = StakeOutput {
+            recipient: recipient_pkey.clone(),
+            validator: validator_pkey.clone(),
+            amount,
+            serno,
+            signature: pbc::Signature::zero(),
+            commission: 0,
+            delegated: true,
+            maintenance_epoch: 0,
+        };
+
+        Ok(output)
+    }
+
     /// Validates UTXO structure and keying.
     pub fn validate(&self) -> Result<(), BlockchainError> {
         let output_hash = Hash::digest(self);
@@ -697,6 +780,27 @@ impl StakeOutput {
             return Err(OutputError::InvalidAmount(output_hash, self.amount).into());
         }
 
+        if self.delegated {
+            // Delegators can't declare commission.
+            if self.commission != 0 {
+                return Err(OutputError::InvalidCommission(output_hash, self.commission).into());
+            }
+            // Only validator can schedule maintenance.
+            if self.maintenance_epoch != 0 {
+                return Err(OutputError::InvalidMaintenanceEpoch(
+                    output_hash,
+                    self.maintenance_epoch,
+                )
+                .into());
+            }
+            // Delegated stakes are not signed by validator.
+            return Ok(());
+        }
+
+        if self.commission > MAX_COMMISSION {
+            return Err(OutputError::InvalidCommission(output_hash, self.commission).into());
+        }
+
         // Validate BLS signature of validator_pkey
         if let Err(_e) = pbc::check_hash(&output_hash, &self.signature, &self.validator) {
             return Err(OutputError::InvalidStakeSignature(output_hash).into());
@@ -735,6 +839,16 @@ impl Output {
         Ok(Output::StakeOutput(output))
     }
 
+    /// Create a new delegated stake.
+    pub fn new_delegated_stake(
+        recipient_pkey: &PublicKey,
+        validator_pkey: &pbc::PublicKey,
+        amount: i64,
+    ) -> Result<Self, Error> {
+        let output = StakeOutput::new_delegated(recipient_pkey, validator_pkey, amount)?;
+        Ok(Output::StakeOutput(output))
+    }
+
     /// Validates UTXO structure and keying.
     pub fn validate(&self) -> Result<(), BlockchainError> {
         match self {
@@ -816,6 +930,18 @@ impl Hashable for StakeOutput {
         self.validator.hash(state);
         self.amount.hash(state);
         self.serno.hash(state);
+        // Keep hashes of plain stakes unchanged.
+        if self.commission != 0 {
+            "Commission".hash(state);
+            self.commission.hash(state);
+        }
+        if self.delegated {
+            "Delegated".hash(state);
+        }
+        if self.maintenance_epoch != 0 {
+            "Maintenance".hash(state);
+            self.maintenance_epoch.hash(state);
+        }
     }
 }
 
@@ -152,6 +152,9 @@ impl ProtoConvert for EscrowValue {
         msg.set_account_pkey(self.account_pkey.into_proto());
         msg.set_active_until_epoch(self.active_until_epoch);
         msg.set_amount(self.amount);
+        msg.set_commission(self.commission);
+        msg.set_delegated(self.delegated);
+        msg.set_maintenance_epoch(self.maintenance_epoch);
         msg
     }
 
@@ -159,11 +162,17 @@ impl ProtoConvert for EscrowValue {
         let account_pkey = ProtoConvert::from_proto(proto.get_account_pkey())?;
         let active_until_epoch = proto.get_active_until_epoch();
         let amount = proto.get_amount();
+        let commission = proto.get_commission();
+        let delegated = proto.get_delegated();
+        let maintenance_epoch = proto.get_maintenance_epoch();
 
         Ok(Self {
             account_pkey,
             active_until_epoch,
             amount,
+            commission,
+            delegated,
+            maintenance_epoch,
         })
     }
 }
@@ -403,6 +412,30 @@ impl ProtoConvert for EpochInfo {
     }
 }
 
+impl ProtoConvert for ValidatorStats {
+    type Proto = blockchain::ValidatorStats;
+
+    fn into_proto(&self) -> Self::Proto {
+        let mut msg = Self::Proto::new();
+        msg.set_epoch(self.epoch);
+        msg.set_blocks_proposed(self.blocks_proposed);
+        msg.set_view_changes(self.view_changes);
+        msg.set_missed_macro_signatures(self.missed_macro_signatures);
+        msg.set_awards(self.awards);
+        msg
+    }
+
+    fn from_proto(proto: &Self::Proto) -> Result<Self, Error> {
+        Ok(Self {
+            epoch: proto.get_epoch(),
+            blocks_proposed: proto.get_blocks_proposed(),
+            view_changes: proto.get_view_changes(),
+            missed_macro_signatures: proto.get_missed_macro_signatures(),
+            awards: proto.get_awards(),
+        })
+    }
+}
+
 impl ProtoConvert for LightEpochInfo {
     type Proto = blockchain::LightEpochInfo;
 
@@ -567,6 +600,9 @@ impl ProtoConvert for StakeOutput {
         proto.set_amount(self.amount);
         proto.set_serno(self.serno);
         proto.set_signature(self.signature.into_proto());
+        proto.set_commission(self.commission);
+        proto.set_delegated(self.delegated);
+        proto.set_maintenance_epoch(self.maintenance_epoch);
         proto
     }
 
@@ -576,12 +612,18 @@ impl ProtoConvert for StakeOutput {
         let amount = proto.get_amount();
         let serno = proto.get_serno();
         let signature = pbc::Signature::from_proto(proto.get_signature())?;
+        let commission = proto.get_commission();
+        let delegated = proto.get_delegated();
+        let maintenance_epoch = proto.get_maintenance_epoch();
         Ok(StakeOutput {
             recipient,
             validator,
             amount,
             serno,
             signature,
+            commission,
+            delegated,
+            maintenance_epoch,
         })
     }
 }
@@ -770,6 +812,43 @@ impl ProtoConvert for RestakeTransaction {
     }
 }
 
+impl ProtoConvert for ValidatorKeyRotationTransaction {
+    type Proto = blockchain::ValidatorKeyRotationTransaction;
+    fn into_proto(&self) -> Self::Proto {
+        let mut proto = blockchain::ValidatorKeyRotationTransaction::new();
+
+        for txin in &self.txins {
+            proto.txins.push(txin.into_proto());
+        }
+        for txout in &self.txouts {
+            proto.txouts.push(txout.into_proto());
+        }
+        proto.set_network_sig(self.network_sig.into_proto());
+        proto.set_account_sig(self.account_sig.into_proto());
+        proto
+    }
+
+    fn from_proto(proto: &Self::Proto) -> Result<Self, Error> {
+        let mut txins = Vec::<Hash>::with_capacity(proto.txins.len());
+        for txin in proto.txins.iter() {
+            txins.push(Hash::from_proto(txin)?);
+        }
+        let mut txouts = Vec::<Output>::with_capacity(proto.txouts.len());
+        for txout in proto.txouts.iter() {
+            txouts.push(Output::from_proto(txout)?);
+        }
+        let network_sig = pbc::Signature::from_proto(proto.get_network_sig())?;
+        let account_sig = SchnorrSig::from_proto(proto.get_account_sig())?;
+
+        Ok(ValidatorKeyRotationTransaction {
+            txins,
+            txouts,
+            network_sig,
+            account_sig,
+        })
+    }
+}
+
 impl ProtoConvert for ServiceAwardTransaction {
     type Proto = blockchain::ServiceAwardTransaction;
     fn into_proto(&self) -> Self::Proto {
@@ -789,6 +868,25 @@ impl ProtoConvert for ServiceAwardTransaction {
     }
 }
 
+impl ProtoConvert for DelegationRewardTransaction {
+    type Proto = blockchain::DelegationRewardTransaction;
+    fn into_proto(&self) -> Self::Proto {
+        let mut proto = blockchain::DelegationRewardTransaction::new();
+        for txout in &self.rewards {
+            proto.rewards.push(txout.into_proto());
+        }
+        proto
+    }
+
+    fn from_proto(proto: &Self::Proto) -> Result<Self, Error> {
+        let mut rewards = Vec::<Output>::with_capacity(proto.rewards.len());
+        for txout in proto.rewards.iter() {
+            rewards.push(Output::from_proto(txout)?);
+        }
+        Ok(DelegationRewardTransaction { rewards })
+    }
+}
+
 impl ProtoConvert for SlashingTransaction {
     type Proto = blockchain::SlashingTransaction;
     fn into_proto(&self) -> Self::Proto {
@@ -841,6 +939,12 @@ impl ProtoConvert for Transaction {
             Transaction::ServiceAwardTransaction(service_reward_transaction) => {
                 proto.set_service_reward_transaction(service_reward_transaction.into_proto())
             }
+            Transaction::ValidatorKeyRotationTransaction(rotation_transaction) => {
+                proto.set_validator_key_rotation_transaction(rotation_transaction.into_proto())
+            }
+            Transaction::DelegationRewardTransaction(delegation_reward_transaction) => {
+                proto.set_delegation_reward_transaction(delegation_reward_transaction.into_proto())
+            }
         }
         proto
     }
@@ -878,6 +982,22 @@ impl ProtoConvert for Transaction {
                     ServiceAwardTransaction::from_proto(service_reward_transaction)?;
                 Transaction::ServiceAwardTransaction(service_reward_transaction)
             }
+            Some(
+                blockchain::Transaction_oneof_transaction::validator_key_rotation_transaction(
+                    ref rotation_transaction,
+                ),
+            ) => {
+                let rotation_transaction =
+                    ValidatorKeyRotationTransaction::from_proto(rotation_transaction)?;
+                Transaction::ValidatorKeyRotationTransaction(rotation_transaction)
+            }
+            Some(blockchain::Transaction_oneof_transaction::delegation_reward_transaction(
+                ref delegation_reward_transaction,
+            )) => {
+                let delegation_reward_transaction =
+                    DelegationRewardTransaction::from_proto(delegation_reward_transaction)?;
+                Transaction::DelegationRewardTransaction(delegation_reward_transaction)
+            }
             None => {
                 return Err(ProtoError::MissingField(
                     "transaction".to_string(),
@@ -1394,6 +1514,31 @@ mod tests {
         roundtrip(&output);
         roundtrip(&output.canary());
 
+        let output: Output =
+            StakeOutput::new_with_commission(&pkey1, &network_skey1, &network_pkey1, amount, 500)
+                .expect("keys are valid")
+                .into();
+        roundtrip(&output);
+        output.validate().expect("valid commission");
+
+        let output: Output = StakeOutput::new_with_maintenance(
+            &pkey1,
+            &network_skey1,
+            &network_pkey1,
+            amount,
+            500,
+            42,
+        )
+        .expect("keys are valid")
+        .into();
+        roundtrip(&output);
+        output.validate().expect("valid maintenance epoch");
+
+        let output =
+            Output::new_delegated_stake(&pkey1, &network_pkey1, amount).expect("keys are valid");
+        roundtrip(&output);
+        output.validate().expect("delegated stake is not signed");
+
         let output = Output::new_stake(&pkey1, &network_skey1, &network_pkey1, amount)
             .expect("keys are valid");
         roundtrip(&output);
@@ -1463,6 +1608,17 @@ mod tests {
         roundtrip(&output);
     }
 
+    #[test]
+    fn delegation_reward_transaction() {
+        let (_skey, pkey) = scc::make_random_keys();
+        let tx = DelegationRewardTransaction {
+            rewards: vec![PublicPaymentOutput::new(&pkey, 100).into()],
+        };
+        roundtrip(&tx);
+        let tx: Transaction = tx.into();
+        roundtrip(&tx);
+    }
+
     #[test]
     fn payment_transaction() {
         let tx = mktransaction();
@@ -1475,6 +1631,34 @@ mod tests {
         PaymentTransaction::from_buffer(&buf).expect_err("error");
     }
 
+    #[test]
+    fn validator_key_rotation_transaction() {
+        let (account_skey, account_pkey) = scc::make_random_keys();
+        let (old_skey, old_pkey) = pbc::make_random_keys();
+        let (new_skey, new_pkey) = pbc::make_random_keys();
+
+        let inputs: Vec<Output> = (0..2)
+            .map(|_| Output::new_stake(&account_pkey, &old_skey, &old_pkey, 100).unwrap())
+            .collect();
+        let tx = ValidatorKeyRotationTransaction::new(
+            &account_skey,
+            &old_skey,
+            &new_skey,
+            &new_pkey,
+            &inputs,
+        )
+        .expect("keys are valid");
+        tx.validate(&inputs).unwrap();
+        let tx2 = roundtrip(&tx);
+        tx2.validate(&inputs).unwrap();
+        let mut tx3 = tx2.clone();
+        tx3.account_sig = scc::SchnorrSig::new();
+        tx3.validate(&inputs)
+            .expect_err("invalid account signature");
+        let tx: Transaction = tx.into();
+        roundtrip(&tx);
+    }
+
     #[test]
     fn micro_blocks() {
         init_test_network_prefix();
@@ -1639,6 +1823,9 @@ mod tests {
             account_pkey: scc::PublicKey::from(scc::Pt::random()),
             active_until_epoch: 324,
             amount: 55,
+            commission: 500,
+            delegated: true,
+            maintenance_epoch: 42,
         };
         roundtrip_eq(&value);
     }
@@ -1720,4 +1907,16 @@ mod tests {
         };
         roundtrip_eq(&epoch_info);
     }
+
+    #[test]
+    fn roundtrip_validator_stats() {
+        let stats = ValidatorStats {
+            epoch: 42,
+            blocks_proposed: 17,
+            view_changes: 3,
+            missed_macro_signatures: 1,
+            awards: 1_000_000,
+        };
+        roundtrip_eq(&stats);
+    }
 }
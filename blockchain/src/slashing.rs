@@ -96,6 +96,10 @@ impl SlashingProof {
     }
 }
 
+///
+/// Create a transaction, which confiscates all active stakes bound to the cheater's key,
+/// including stakes delegated by third parties, and splits them between the other validators.
+///
 pub fn confiscate_tx(
     chain: &Blockchain,
     our_key: &pbc::PublicKey, // our key, used to add change to payment utxo.
@@ -137,7 +141,7 @@ pub fn confiscate_tx(
     let mut outputs = Vec::new();
     for validator in &validators {
         let key = chain
-            .account_by_network_key(validator)
+            .validator_account(validator)
             .expect("validator has account key");
         let mut output = PublicPaymentOutput::new(&key, piece);
         if validator == our_key {
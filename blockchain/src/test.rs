@@ -315,7 +315,7 @@ pub fn create_fake_micro_block(
     let seed = mix(last_random, view_change);
     let random = pbc::make_VRF(&leader.network_skey, &seed);
     let solution = chain.vdf_solver()();
-    let block_reward = chain.cfg().block_reward;
+    let block_reward = chain.coinbase_reward(&leader.network_pkey);
     let block_fee: i64 = 0;
     let mut transactions: Vec<Transaction> = Vec::new();
 
@@ -458,6 +458,27 @@ pub fn create_micro_block_with_coinbase(
     chain: &Blockchain,
     keychains: &[KeyChain],
     timestamp: Timestamp,
+) -> MicroBlock {
+    create_micro_block_with_transactions(chain, keychains, timestamp, Vec::new())
+}
+
+pub fn create_micro_block_with_transactions(
+    chain: &Blockchain,
+    keychains: &[KeyChain],
+    timestamp: Timestamp,
+    transactions: Vec<Transaction>,
+) -> MicroBlock {
+    let key = chain.select_leader(chain.view_change());
+    let block_reward = chain.coinbase_reward(&key);
+    create_micro_block_with_reward(chain, keychains, timestamp, block_reward, transactions)
+}
+
+pub fn create_micro_block_with_reward(
+    chain: &Blockchain,
+    keychains: &[KeyChain],
+    timestamp: Timestamp,
+    block_reward: i64,
+    transactions: Vec<Transaction>,
 ) -> MicroBlock {
     let previous = chain.last_block_hash().clone();
     let epoch = chain.epoch();
@@ -473,7 +494,6 @@ pub fn create_micro_block_with_coinbase(
     let mut gamma = scc::Fr::zero();
 
     let block_fee = 0;
-    let block_reward = chain.cfg().block_reward;
     // Create outputs for fee and rewards.
     for (amount, comment) in vec![(block_fee, "fee"), (block_reward, "reward")] {
         if amount <= 0 {
@@ -502,7 +522,8 @@ pub fn create_micro_block_with_coinbase(
         gamma,
         txouts,
     };
-    let txs = vec![coinbase.into()];
+    let mut txs = vec![coinbase.into()];
+    txs.extend(transactions);
     let mut block = MicroBlock::new(
         previous,
         epoch,
@@ -519,6 +540,65 @@ pub fn create_micro_block_with_coinbase(
     block
 }
 
+/// Create a transaction, which delegates `amount` of money to each of validators.
+/// Returns the transaction and the delegated stakes.
+pub fn create_delegation_transaction(
+    chain: &Blockchain,
+    delegator: &KeyChain,
+    validators: &[pbc::PublicKey],
+    amount: i64,
+) -> (PaymentTransaction, Vec<Output>) {
+    let accounts_recovery = recover_account(
+        chain,
+        &delegator.account_skey,
+        &delegator.account_pkey,
+        0,
+        HashMap::new(),
+    )
+    .unwrap();
+    let unspent = accounts_recovery
+        .commited
+        .into_iter()
+        .chain(accounts_recovery.prepared);
+    let mut inputs: Vec<Output> = Vec::new();
+    let mut balance: i64 = 0;
+    for (_, OutputRecovery { output, .. }) in unspent {
+        if let Output::PaymentOutput(ref o) = output {
+            let payload = o
+                .decrypt_payload(&delegator.account_pkey, &delegator.account_skey)
+                .unwrap();
+            balance += payload.amount;
+            inputs.push(output);
+        }
+    }
+
+    let mut outputs: Vec<Output> = Vec::new();
+    let mut stakes: Vec<Output> = Vec::new();
+    for validator_pkey in validators {
+        let output: Output =
+            StakeOutput::new_delegated(&delegator.account_pkey, validator_pkey, amount)
+                .expect("keys are valid")
+                .into();
+        balance -= amount;
+        stakes.push(output.clone());
+        outputs.push(output);
+    }
+    assert!(balance > 0);
+    let (change, outputs_gamma) =
+        PaymentOutput::new(&delegator.account_pkey, balance).expect("keys are valid");
+    outputs.push(change.into());
+    let tx = PaymentTransaction::new(
+        &delegator.account_skey,
+        &inputs,
+        &outputs,
+        &outputs_gamma,
+        0,
+    )
+    .expect("Invalid keys");
+    tx.validate(&inputs).expect("Invalid transaction");
+    (tx, stakes)
+}
+
 #[test]
 fn roundtrip_bitvec() {
     use bit_vec::BitVec;
@@ -51,6 +51,26 @@ impl Hashable for ServiceAwardTransaction {
     }
 }
 
+//--------------------------------------------------------------------------------------------------
+// Delegation Reward Transaction.
+//--------------------------------------------------------------------------------------------------
+
+/// Transaction that pays block rewards withheld from delegators during epoch.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DelegationRewardTransaction {
+    pub rewards: Vec<Output>,
+}
+
+impl Hashable for DelegationRewardTransaction {
+    fn hash(&self, state: &mut Hasher) {
+        "DelegationReward".hash(state);
+        (self.rewards.len() as u64).hash(state);
+        for reward in &self.rewards {
+            reward.hash(state)
+        }
+    }
+}
+
 //--------------------------------------------------------------------------------------------------
 // Slashing Transaction.
 //--------------------------------------------------------------------------------------------------
@@ -431,6 +451,9 @@ impl RestakeTransaction {
                 Output::PaymentOutput(_) | Output::PublicPaymentOutput(_) => {
                     return Err(TransactionError::InvalidRestakingInput(htx, h).into());
                 }
+                Output::StakeOutput(o) if o.delegated => {
+                    return Err(TransactionError::InvalidRestakingInput(htx, h).into());
+                }
                 Output::StakeOutput(o) => {
                     inp_amt += o.amount;
                     if *pkey != o.validator {
@@ -529,6 +552,124 @@ impl RestakeTransaction {
     }
 }
 
+//--------------------------------------------------------------------------------------------------
+// Validator Key Rotation Transaction.
+//--------------------------------------------------------------------------------------------------
+
+/// Transaction which re-binds all stakes of a validator to a new network key.
+/// Stakes stay locked in the escrow, and the new key takes part
+/// in the election starting from the next epoch.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ValidatorKeyRotationTransaction {
+    /// Stakes bound to the old network key.
+    pub txins: Vec<Hash>,
+    /// The same stakes bound to the new network key.
+    pub txouts: Vec<Output>,
+    /// Signature by the old network key.
+    pub network_sig: pbc::Signature,
+    /// Signature by the account key.
+    pub account_sig: SchnorrSig,
+}
+
+impl Hashable for ValidatorKeyRotationTransaction {
+    fn hash(&self, state: &mut Hasher) {
+        "ValidatorKeyRotation".hash(state);
+
+        // Sign txins.
+        let txins_count: u64 = self.txins.len() as u64;
+        txins_count.hash(state);
+        for txin_hash in &self.txins {
+            txin_hash.hash(state);
+        }
+
+        // Sign txouts.
+        let txouts_count: u64 = self.txouts.len() as u64;
+        txouts_count.hash(state);
+        for txout in &self.txouts {
+            txout.hash(state);
+        }
+    }
+}
+
+impl ValidatorKeyRotationTransaction {
+    /// Create a new transaction.
+    ///
+    /// # Arguments
+    ///
+    /// * `account_skey` - Account's secret key
+    /// * `old_network_skey` - Validator's current secret key
+    /// * `new_network_skey` - Validator's new secret key
+    /// * `new_network_pkey` - Validator's new public key
+    /// * `inputs` - StakeUTXOs bound to the current key
+    ///
+    pub fn new(
+        account_skey: &SecretKey,
+        old_network_skey: &pbc::SecretKey,
+        new_network_skey: &pbc::SecretKey,
+        new_network_pkey: &pbc::PublicKey,
+        inputs: &[Output],
+    ) -> Result<Self, Error> {
+        let mut txins: Vec<Hash> = Vec::with_capacity(inputs.len());
+        let mut txouts: Vec<Output> = Vec::with_capacity(inputs.len());
+        let mut validator = None;
+        let mut owner = None;
+        let htx = Hash::digest("");
+        for txin in inputs {
+            let h = Hash::digest(&txin);
+            let o = match txin {
+                Output::PaymentOutput(_) | Output::PublicPaymentOutput(_) => {
+                    return Err(TransactionError::InvalidRestakingInput(htx, h).into());
+                }
+                Output::StakeOutput(o) => o,
+            };
+            match validator {
+                None => validator = Some(o.validator),
+                Some(v) if v != o.validator => {
+                    return Err(TransactionError::RestakingValidatorKeyMismatch(htx, h).into());
+                }
+                Some(_) => {}
+            }
+            match owner {
+                None => owner = Some(o.recipient),
+                Some(r) if r != o.recipient => {
+                    return Err(TransactionError::MixedRestakingOwners(htx, h).into());
+                }
+                Some(_) => {}
+            }
+            if o.validator == *new_network_pkey {
+                return Err(TransactionError::SameValidatorKey(htx, h).into());
+            }
+            txins.push(h);
+            if o.delegated {
+                return Err(TransactionError::InvalidRestakingInput(htx, h).into());
+            }
+            let txout = StakeOutput::new_with_maintenance(
+                &o.recipient,
+                new_network_skey,
+                new_network_pkey,
+                o.amount,
+                o.commission,
+                o.maintenance_epoch,
+            )?;
+            txouts.push(txout.into());
+        }
+        if txins.is_empty() {
+            return Err(TransactionError::NoRestakingTxins(htx).into());
+        }
+
+        let mut tx = ValidatorKeyRotationTransaction {
+            txins,
+            txouts,
+            network_sig: pbc::Signature::new(),
+            account_sig: SchnorrSig::new(),
+        };
+        let h = Hash::digest(&tx);
+        tx.network_sig = pbc::sign_hash(&h, old_network_skey);
+        tx.account_sig = sign_hash(&h, account_skey);
+        Ok(tx)
+    }
+}
+
 //--------------------------------------------------------------------------------------------------
 // Transaction (enum).
 //--------------------------------------------------------------------------------------------------
@@ -543,6 +684,8 @@ pub enum Transaction {
     RestakeTransaction(RestakeTransaction),
     SlashingTransaction(SlashingTransaction),
     ServiceAwardTransaction(ServiceAwardTransaction),
+    ValidatorKeyRotationTransaction(ValidatorKeyRotationTransaction),
+    DelegationRewardTransaction(DelegationRewardTransaction),
 }
 
 impl Transaction {
@@ -554,6 +697,8 @@ impl Transaction {
             Transaction::RestakeTransaction(_tx) => Fr::zero(),
             Transaction::SlashingTransaction(_tx) => Fr::zero(),
             Transaction::ServiceAwardTransaction(_tx) => Fr::zero(),
+            Transaction::ValidatorKeyRotationTransaction(_tx) => Fr::zero(),
+            Transaction::DelegationRewardTransaction(_tx) => Fr::zero(),
         }
     }
 
@@ -565,6 +710,8 @@ impl Transaction {
             Transaction::RestakeTransaction(_tx) => 0,
             Transaction::SlashingTransaction(_tx) => 0,
             Transaction::ServiceAwardTransaction(_tx) => 0,
+            Transaction::ValidatorKeyRotationTransaction(_tx) => 0,
+            Transaction::DelegationRewardTransaction(_tx) => 0,
         }
     }
 
@@ -576,6 +723,8 @@ impl Transaction {
             Transaction::RestakeTransaction(tx) => &tx.txins,
             Transaction::SlashingTransaction(tx) => &tx.txins,
             Transaction::ServiceAwardTransaction(_tx) => &[],
+            Transaction::ValidatorKeyRotationTransaction(tx) => &tx.txins,
+            Transaction::DelegationRewardTransaction(_tx) => &[],
         }
     }
 
@@ -587,6 +736,8 @@ impl Transaction {
             Transaction::RestakeTransaction(tx) => &tx.txouts,
             Transaction::SlashingTransaction(tx) => &tx.txouts,
             Transaction::ServiceAwardTransaction(tx) => &tx.winner_reward,
+            Transaction::ValidatorKeyRotationTransaction(tx) => &tx.txouts,
+            Transaction::DelegationRewardTransaction(tx) => &tx.rewards,
         }
     }
 
@@ -597,6 +748,8 @@ impl Transaction {
             Transaction::RestakeTransaction(_) => "RestakeTransaction",
             Transaction::SlashingTransaction(_) => "SlashingTransaction",
             Transaction::ServiceAwardTransaction(_) => "ServiceAwardTransaction",
+            Transaction::ValidatorKeyRotationTransaction(_) => "ValidatorKeyRotationTransaction",
+            Transaction::DelegationRewardTransaction(_) => "DelegationRewardTransaction",
         }
     }
 
@@ -608,6 +761,11 @@ impl Transaction {
             Transaction::RestakeTransaction(tx) => tx.sig.hash(state),
             Transaction::SlashingTransaction(_tx) => (),
             Transaction::ServiceAwardTransaction(_tx) => (),
+            Transaction::ValidatorKeyRotationTransaction(tx) => {
+                tx.network_sig.hash(state);
+                tx.account_sig.hash(state);
+            }
+            Transaction::DelegationRewardTransaction(_tx) => (),
         }
     }
 
@@ -627,6 +785,8 @@ impl Hashable for Transaction {
             Transaction::RestakeTransaction(tx) => tx.hash(state),
             Transaction::SlashingTransaction(tx) => tx.hash(state),
             Transaction::ServiceAwardTransaction(tx) => tx.hash(state),
+            Transaction::ValidatorKeyRotationTransaction(tx) => tx.hash(state),
+            Transaction::DelegationRewardTransaction(tx) => tx.hash(state),
         }
     }
 }
@@ -661,6 +821,18 @@ impl From<ServiceAwardTransaction> for Transaction {
     }
 }
 
+impl From<ValidatorKeyRotationTransaction> for Transaction {
+    fn from(tx: ValidatorKeyRotationTransaction) -> Self {
+        Transaction::ValidatorKeyRotationTransaction(tx)
+    }
+}
+
+impl From<DelegationRewardTransaction> for Transaction {
+    fn from(tx: DelegationRewardTransaction) -> Self {
+        Transaction::DelegationRewardTransaction(tx)
+    }
+}
+
 //--------------------------------------------------------------------------------------------------
 // Transaction Status (enum).
 //--------------------------------------------------------------------------------------------------
@@ -31,6 +31,7 @@ use crate::slashing::confiscate_tx;
 use crate::timestamp::Timestamp;
 use crate::transaction::{
     CoinbaseTransaction, PaymentTransaction, RestakeTransaction, SlashingTransaction, Transaction,
+    ValidatorKeyRotationTransaction,
 };
 use crate::Merkle;
 use log::*;
@@ -243,6 +244,10 @@ impl RestakeTransaction {
                 Output::PaymentOutput(_) | Output::PublicPaymentOutput(_) => {
                     return Err(TransactionError::InvalidRestakingInput(tx_hash, *txin_hash).into());
                 }
+                // Delegated stakes can be moved only by delegators.
+                Output::StakeOutput(o) if o.delegated => {
+                    return Err(TransactionError::InvalidRestakingInput(tx_hash, *txin_hash).into());
+                }
                 Output::StakeOutput(o) => {
                     match eff_vkey {
                         None => {
@@ -287,6 +292,11 @@ impl RestakeTransaction {
                         TransactionError::InvalidRestakingOutput(tx_hash, txout_hash).into(),
                     );
                 }
+                Output::StakeOutput(o) if o.delegated => {
+                    return Err(
+                        TransactionError::InvalidRestakingOutput(tx_hash, txout_hash).into(),
+                    );
+                }
                 Output::StakeOutput(o) => {
                     match out_pkey {
                         None => {
@@ -322,6 +332,116 @@ impl RestakeTransaction {
     }
 }
 
+impl ValidatorKeyRotationTransaction {
+    /// Validate the balance and signatures of transaction.
+    ///
+    /// # Arguments
+    ///
+    /// * - `inputs` - UTXOs referred by self.txins, in the same order as in self.txins.
+    ///
+    pub fn validate(&self, inputs: &[Output]) -> Result<(), BlockchainError> {
+        //
+        // Validation checklist:
+        //
+        // - At least one input is present.
+        // - Inputs are unique StakeUTXOs of the same validator and account.
+        // - Outputs are unique StakeUTXOs of the same account and a new validator.
+        // - Amounts are preserved.
+        // - Signatures of the old validator key and the account key are valid.
+        //
+
+        let tx_hash = Hash::digest(&self);
+
+        assert_eq!(self.txins.len(), inputs.len());
+
+        // Check that transaction has inputs.
+        if self.txins.is_empty() {
+            return Err(TransactionError::NoInputs(tx_hash).into());
+        }
+
+        let mut old_validator: Option<pbc::PublicKey> = None;
+        let mut account: Option<scc::PublicKey> = None;
+        let mut txin_sum: i64 = 0;
+        let mut txins_set: HashSet<Hash> = HashSet::new();
+        for (txin_hash, txin) in self.txins.iter().zip(inputs) {
+            assert_eq!(Hash::digest(txin), *txin_hash);
+            if !txins_set.insert(*txin_hash) {
+                return Err(TransactionError::DuplicateInput(tx_hash, *txin_hash).into());
+            }
+            let o = match txin {
+                Output::PaymentOutput(_) | Output::PublicPaymentOutput(_) => {
+                    return Err(TransactionError::InvalidRestakingInput(tx_hash, *txin_hash).into());
+                }
+                // Delegators stay with the old key.
+                Output::StakeOutput(o) if o.delegated => {
+                    return Err(TransactionError::InvalidRestakingInput(tx_hash, *txin_hash).into());
+                }
+                Output::StakeOutput(o) => o,
+            };
+            if *old_validator.get_or_insert(o.validator) != o.validator {
+                return Err(
+                    TransactionError::RestakingValidatorKeyMismatch(tx_hash, *txin_hash).into(),
+                );
+            }
+            if *account.get_or_insert(o.recipient) != o.recipient {
+                return Err(TransactionError::MixedRestakingOwners(tx_hash, *txin_hash).into());
+            }
+            txin_sum += o.amount;
+        }
+        drop(txins_set);
+        let old_validator = old_validator.unwrap();
+        let account = account.unwrap();
+
+        let mut new_validator: Option<pbc::PublicKey> = None;
+        let mut txout_sum: i64 = 0;
+        let mut txouts_set: HashSet<Hash> = HashSet::new();
+        for txout in &self.txouts {
+            let txout_hash = Hash::digest(txout);
+            if !txouts_set.insert(txout_hash) {
+                return Err(TransactionError::DuplicateOutput(tx_hash, txout_hash).into());
+            }
+            txout.validate()?;
+            let o = match txout {
+                Output::PaymentOutput(_) | Output::PublicPaymentOutput(_) => {
+                    return Err(
+                        TransactionError::InvalidRestakingOutput(tx_hash, txout_hash).into(),
+                    );
+                }
+                Output::StakeOutput(o) if o.delegated => {
+                    return Err(
+                        TransactionError::InvalidRestakingOutput(tx_hash, txout_hash).into(),
+                    );
+                }
+                Output::StakeOutput(o) => o,
+            };
+            if o.validator == old_validator {
+                return Err(TransactionError::SameValidatorKey(tx_hash, txout_hash).into());
+            }
+            if *new_validator.get_or_insert(o.validator) != o.validator {
+                return Err(TransactionError::MixedTxoutValidators(tx_hash, txout_hash).into());
+            }
+            if o.recipient != account {
+                return Err(TransactionError::MixedRestakingOwners(tx_hash, txout_hash).into());
+            }
+            txout_sum += o.amount;
+        }
+        drop(txouts_set);
+
+        if txin_sum != txout_sum {
+            return Err(TransactionError::ImbalancedRestaking(tx_hash).into());
+        }
+
+        // Check signatures.
+        pbc::check_hash(&tx_hash, &self.network_sig, &old_validator)
+            .map_err(|_e| TransactionError::InvalidSignature(tx_hash))?;
+        scc::validate_sig(&tx_hash, &self.account_sig, &account)
+            .map_err(|_e| TransactionError::InvalidSignature(tx_hash))?;
+
+        // Transaction is valid.
+        Ok(())
+    }
+}
+
 impl SlashingTransaction {
     pub fn validate(
         &self,
@@ -734,12 +854,13 @@ impl Blockchain {
         // Coinbase.
         if let Some(Transaction::CoinbaseTransaction(tx)) = transactions.get(0) {
             tx.validate()?;
-            if tx.block_reward != self.cfg().block_reward {
+            let block_reward = self.coinbase_reward(&header.pkey);
+            if tx.block_reward != block_reward {
                 return Err(BlockError::InvalidMacroBlockReward(
                     epoch,
                     block_hash.clone(),
                     tx.block_reward,
-                    self.cfg().block_reward,
+                    block_reward,
                 )
                 .into());
             }
@@ -765,40 +886,65 @@ impl Blockchain {
             tx_len += 1;
             full_reward += reward;
             if let Some(Transaction::ServiceAwardTransaction(tx)) = transactions.get(1) {
-                if tx.winner_reward.len() != 1 {
-                    return Err(BlockError::AwardMoreThanOneWinner(
+                let payouts = self.service_award_payouts(&k, reward);
+                if tx.winner_reward.len() != payouts.len() {
+                    return Err(BlockError::AwardInvalidPayoutsLen(
                         block_hash.clone(),
                         tx.winner_reward.len(),
+                        payouts.len(),
                     )
                     .into());
                 }
-                let ref output = tx.winner_reward[0];
-
-                if let Output::PublicPaymentOutput(out) = output {
-                    if out.recipient != k {
-                        return Err(BlockError::AwardDifferentWinner(
-                            block_hash.clone(),
-                            out.recipient,
-                            k,
-                        )
-                        .into());
-                    }
-                    if out.amount != reward {
-                        return Err(BlockError::AwardDifferentReward(
-                            block_hash.clone(),
-                            out.amount,
-                            reward,
-                        )
-                        .into());
+
+                for (output, (recipient, amount)) in tx.winner_reward.iter().zip(payouts) {
+                    if let Output::PublicPaymentOutput(out) = output {
+                        if out.recipient != recipient {
+                            return Err(BlockError::AwardDifferentWinner(
+                                block_hash.clone(),
+                                out.recipient,
+                                recipient,
+                            )
+                            .into());
+                        }
+                        if out.amount != amount {
+                            return Err(BlockError::AwardDifferentReward(
+                                block_hash.clone(),
+                                out.amount,
+                                amount,
+                            )
+                            .into());
+                        }
+                    } else {
+                        return Err(BlockError::AwardDifferentOutputType(block_hash.clone()).into());
                     }
-                } else {
-                    return Err(BlockError::AwardDifferentOutputType(block_hash.clone()).into());
                 }
             } else {
                 return Err(BlockError::NoServiceAwardTx(block_hash.clone()).into());
             }
         }
 
+        // Delegation rewards.
+        let payouts = self.delegation_payouts(&header.pkey);
+        if !payouts.is_empty() {
+            let tx = match transactions.get(tx_len) {
+                Some(Transaction::DelegationRewardTransaction(tx)) => tx,
+                _ => return Err(BlockError::NoDelegationRewardTx(block_hash.clone()).into()),
+            };
+            tx_len += 1;
+            let valid = tx.rewards.len() == payouts.len()
+                && tx.rewards.iter().zip(payouts).all(
+                    |(output, (recipient, amount))| match output {
+                        Output::PublicPaymentOutput(out) => {
+                            out.recipient == recipient && out.amount == amount
+                        }
+                        _ => false,
+                    },
+                );
+            if !valid {
+                return Err(BlockError::InvalidDelegationRewards(block_hash.clone()).into());
+            }
+        }
+
         if transactions.len() > tx_len {
             return Err(BlockError::InvalidBlockBalance(epoch, block_hash.clone()).into());
         }
@@ -893,6 +1039,10 @@ impl Blockchain {
         match tx {
             // Staking balance of cheater was already validated in tx.validate()
             Transaction::SlashingTransaction(_) => {}
+            // Locked stakes are moved to the new key as is.
+            Transaction::ValidatorKeyRotationTransaction(_) => {
+                self.validate_key_rotation(inputs.iter(), tx.txouts().iter())?
+            }
             _ => self.validate_stakes(inputs.iter(), tx.txouts().iter())?,
         }
 
@@ -903,8 +1053,10 @@ impl Blockchain {
             }
             Transaction::PaymentTransaction(tx) => tx.validate(&inputs)?,
             Transaction::RestakeTransaction(tx) => tx.validate(&inputs)?,
+            Transaction::ValidatorKeyRotationTransaction(tx) => tx.validate(&inputs)?,
             Transaction::SlashingTransaction(tx) => tx.validate(self, leader)?,
-            Transaction::ServiceAwardTransaction(_) => {
+            Transaction::ServiceAwardTransaction(_)
+            | Transaction::DelegationRewardTransaction(_) => {
                 return Err(TransactionError::UnexpectedTxType.into())
             }
         }
@@ -1025,13 +1177,14 @@ impl Blockchain {
         }
         // Check block reward.
         if let Some(Transaction::CoinbaseTransaction(tx)) = block.transactions.get(0) {
-            if tx.block_reward != self.cfg().block_reward {
+            let block_reward = self.coinbase_reward(&leader);
+            if tx.block_reward != block_reward {
                 return Err(BlockError::InvalidMicroBlockReward(
                     epoch,
                     offset,
                     block_hash,
                     tx.block_reward,
-                    self.cfg().block_reward,
+                    block_reward,
                 )
                 .into());
             }
@@ -194,6 +194,18 @@ impl ViewChangeCollector {
         Ok(None)
     }
 
+    /// Validators whose view change votes have been collected so far.
+    pub fn voted_validators(&self) -> Vec<ValidatorId> {
+        let mut validators: Vec<ValidatorId> = self.actual_view_changes.keys().cloned().collect();
+        validators.sort();
+        validators
+    }
+
+    /// Number of slots collected by view change votes.
+    pub fn collected_slots(&self) -> i64 {
+        self.collected_slots
+    }
+
     /// Handle block timeout, starting mooving to the next view change.
     pub fn handle_timeout(&self, chain_info: ChainInfo) -> ViewChangeMessage {
         // on timeout, create view change message.
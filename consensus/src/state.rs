@@ -40,13 +40,19 @@ struct LockedRound {
     block_proposal: MacroBlockProposal,
 }
 
-#[derive(Debug, Eq, Copy, PartialEq, Clone)]
+#[derive(Debug, Eq, PartialEq, Clone)]
 pub struct ConsensusInfo {
     pub epoch: u64,
     pub round: u32,
     pub state: ConsensusState,
-    pub prevotes_len: usize,
-    pub precommits_len: usize,
+    /// Leader of the current round.
+    pub leader: pbc::PublicKey,
+    /// Validators which have sent a prevote in the current round.
+    pub prevotes: Vec<pbc::PublicKey>,
+    /// Validators which have sent a precommit in the current round.
+    pub precommits: Vec<pbc::PublicKey>,
+    /// True if some block is locked from the previous rounds.
+    pub locked: bool,
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Ord, PartialOrd)]
@@ -63,7 +69,7 @@ pub enum ConsensusState {
 
 impl ConsensusState {
     /// Enum to string.
-    fn name(&self) -> &'static str {
+    pub fn name(&self) -> &'static str {
         match *self {
             ConsensusState::Propose => "Propose",
             ConsensusState::Prevote => "Prevote",
@@ -192,8 +198,10 @@ impl Consensus {
             epoch: self.epoch,
             round: self.round,
             state: self.state,
-            prevotes_len: self.prevotes.len(),
-            precommits_len: self.precommits.len(),
+            leader: self.leader(),
+            prevotes: self.prevotes.keys().cloned().collect(),
+            precommits: self.precommits.keys().cloned().collect(),
+            locked: self.locked_round.is_some(),
         }
     }
 
@@ -0,0 +1,77 @@
+//
+// Copyright (c) 2018 Stegos AG
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! DiceMix decoding of arbitrary matrices must fail gracefully.
+
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use std::collections::HashMap;
+use stegos_crypto::dicemix::*;
+use stegos_crypto::hash::Hash;
+use stegos_crypto::pbc;
+use stegos_crypto::scc::Fr;
+
+fuzz_target!(|data: &[u8]| {
+    if data.len() < 3 {
+        return;
+    }
+    let nparts = 1 + data[0] as usize % 4;
+    let nsheets = 1 + data[1] as usize % 2;
+    let nchunks = 1 + data[2] as usize % 4;
+    let mut cells = data[3..].chunks(32).map(|chunk| {
+        let mut bytes = [0u8; 32];
+        bytes[..chunk.len()].copy_from_slice(chunk);
+        bytes[31] &= 0x0f;
+        Fr::try_from_bytes(&bytes).unwrap_or(Fr::zero())
+    });
+
+    let mut participants = Vec::new();
+    let mut matrices = HashMap::new();
+    for ix in 0..nparts {
+        let (_sk, pk) = pbc::make_deterministic_keys(format!("User_{}", ix).as_bytes());
+        let p = ParticipantID::from_pk(pk);
+        let matrix: DcMatrix = (0..nsheets)
+            .map(|_| {
+                (0..nchunks)
+                    .map(|_| {
+                        (0..nparts)
+                            .map(|_| cells.next().unwrap_or(Fr::zero()))
+                            .collect()
+                    })
+                    .collect()
+            })
+            .collect();
+        participants.push(p);
+        matrices.insert(p, matrix);
+    }
+
+    let p_excl = Vec::new();
+    let k_excl: HashMap<ParticipantID, HashMap<ParticipantID, Hash>> = HashMap::new();
+    let _ = dc_decode(
+        &participants,
+        &matrices,
+        &participants[0],
+        nsheets,
+        nchunks,
+        &p_excl,
+        &k_excl,
+    );
+});
@@ -0,0 +1,108 @@
+//
+// Copyright (c) 2018 Stegos AG
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! DiceMix encode/decode round-trip for arbitrary session shapes and messages.
+
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use std::collections::HashMap;
+use stegos_crypto::dicemix::*;
+use stegos_crypto::hash::Hash;
+use stegos_crypto::{pbc, scc};
+
+fuzz_target!(|data: &[u8]| {
+    if data.len() < 3 {
+        return;
+    }
+    let nparts = 2 + data[0] as usize % 5;
+    let nsheets = 1 + data[1] as usize % 3;
+    let msg_len = 2 + data[2] as usize % 127;
+    let payload = &data[3..];
+
+    let mut participants = Vec::new();
+    let mut sess_pkeys = HashMap::new();
+    let mut sess_skeys = HashMap::new();
+    for ix in 0..nparts {
+        let (_sk, pk) = pbc::make_deterministic_keys(format!("User_{}", ix).as_bytes());
+        let p = ParticipantID::from_pk(pk);
+        let seed = format!("User_{}_Session_Key", ix);
+        let (sk, pk) = scc::make_deterministic_keys(seed.as_bytes());
+        sess_pkeys.insert(p, pk);
+        sess_skeys.insert(p, sk);
+        participants.push(p);
+    }
+    let sess = Hash::digest(&(payload.len() as u64));
+
+    // Messages are taken from the payload and tagged to be distinct.
+    let mut msgs = HashMap::new();
+    let mut expected = Vec::new();
+    for (ix, p) in participants.iter().enumerate() {
+        let nmsgs = payload
+            .get(ix)
+            .map(|b| *b as usize % (nsheets + 1))
+            .unwrap_or(nsheets);
+        let mut my_msgs = Vec::new();
+        for ix_m in 0..nmsgs {
+            let mut msg: Vec<u8> = payload
+                .iter()
+                .cycle()
+                .skip(ix * nsheets + ix_m)
+                .take(msg_len)
+                .cloned()
+                .collect();
+            msg.resize(msg_len, 0);
+            msg[0] = (ix * nsheets + ix_m + 1) as u8;
+            msg[msg_len - 1] |= 0x80;
+            expected.push(msg.clone());
+            my_msgs.push(msg);
+        }
+        msgs.insert(*p, my_msgs);
+    }
+
+    let max_chunks = split_message(&vec![0u8; msg_len], None).len();
+    let mut matrices = HashMap::new();
+    for p in &participants {
+        let cloaks = dc_keys(&participants, &sess_pkeys, p, &sess_skeys[p], &sess);
+        let matrix = dc_encode_matrix(nsheets, max_chunks, &msgs[p], &participants, p, &cloaks);
+        assert!(dc_matrix_has_dims(&matrix, nsheets, max_chunks, nparts));
+        matrices.insert(*p, matrix);
+    }
+
+    let p_excl = Vec::new();
+    let k_excl: HashMap<ParticipantID, HashMap<ParticipantID, Hash>> =
+        participants.iter().map(|p| (*p, HashMap::new())).collect();
+    let mut decoded = dc_decode(
+        &participants,
+        &matrices,
+        &participants[0],
+        nsheets,
+        max_chunks,
+        &p_excl,
+        &k_excl,
+    )
+    .expect("DiceMix solution");
+    for msg in decoded.iter_mut() {
+        msg.truncate(msg_len);
+    }
+    decoded.sort();
+    expected.sort();
+    assert_eq!(decoded, expected);
+});
@@ -303,6 +303,38 @@ pub fn dc_encode_sheet(
         .collect()
 }
 
+pub fn dc_encode_matrix(
+    nsheets: usize,
+    max_chunks: usize,
+    msgs: &[Vec<u8>],
+    participants: &Vec<ParticipantID>,
+    my_id: &ParticipantID,
+    share_cloaks: &HashMap<ParticipantID, Hash>,
+) -> DcMatrix {
+    // Encode one message per sheet. The number of sheets is agreed
+    // by all participants of the session and can be larger than the number
+    // of our messages, so fill the remaining sheets with null messages,
+    // which contain only the cloaking factors.
+    //
+    // Sheets are numbered from zero, as expected by dc_open() and dc_reconstruct().
+    assert!(msgs.len() <= nsheets, "Too many messages for the matrix");
+    let null_msg = Vec::<u8>::new();
+    (0..nsheets)
+        .map(|sheet| {
+            let msg = msgs.get(sheet).unwrap_or(&null_msg);
+            dc_encode_sheet(sheet, max_chunks, msg, participants, my_id, share_cloaks)
+        })
+        .collect()
+}
+
+pub fn dc_matrix_has_dims(matrix: &DcMatrix, nsheets: usize, nrows: usize, ncols: usize) -> bool {
+    // Check dimensions of a matrix received from another participant.
+    matrix.len() == nsheets
+        && matrix
+            .iter()
+            .all(|sheet| sheet.len() == nrows && sheet.iter().all(|row| row.len() == ncols))
+}
+
 fn dc_slot_pad(
     participants: &Vec<ParticipantID>,
     my_id: &ParticipantID,
@@ -803,6 +835,8 @@ pub fn dc_scalar_open(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
     use std::dbg;
 
     #[test]
@@ -1467,6 +1501,70 @@ mod tests {
         tst_end_to_end(nparts, nutxo, utxo_len);
     }
 
+    #[test]
+    fn tst_encode_matrix() {
+        // Participants share different numbers of messages
+        // in a matrix with more sheets than any of them needs.
+        let nsheets = 8;
+        let msg_len = 100;
+        let nmsgs = [1, 3, 7];
+        let nparts = nmsgs.len();
+        let mut participants = Vec::<ParticipantID>::new();
+        let mut sess_pkeys = HashMap::new();
+        let mut sess_skeys = HashMap::new();
+        for ix in 0..nparts {
+            let seed = format!("User_{}", ix).into_bytes();
+            let (_sk, pk) = pbc::make_deterministic_keys(&seed);
+            let p = ParticipantID::from_pk(pk);
+            let seed = format!("User_{}_Session_Key", ix).into_bytes();
+            let (sk, pk) = scc::make_deterministic_keys(&seed);
+            sess_pkeys.insert(p, pk);
+            sess_skeys.insert(p, sk);
+            participants.push(p);
+        }
+        let sess = Hash::from_str("Session 1");
+        let max_chunks = split_message(&vec![0u8; msg_len], None).len();
+
+        let mut matrices: HashMap<ParticipantID, DcMatrix> = HashMap::new();
+        let mut expected = Vec::<Vec<u8>>::new();
+        for (ix, p) in participants.iter().enumerate() {
+            let msgs: Vec<Vec<u8>> = (0..nmsgs[ix])
+                .map(|ix_m| vec![(1 + ix * nsheets + ix_m) as u8; msg_len])
+                .collect();
+            expected.extend(msgs.iter().cloned());
+            let cloaks = dc_keys(&participants, &sess_pkeys, p, &sess_skeys[p], &sess);
+            let matrix = dc_encode_matrix(nsheets, max_chunks, &msgs, &participants, p, &cloaks);
+            assert!(dc_matrix_has_dims(&matrix, nsheets, max_chunks, nparts));
+            assert!(!dc_matrix_has_dims(
+                &matrix,
+                nsheets - 1,
+                max_chunks,
+                nparts
+            ));
+            matrices.insert(*p, matrix);
+        }
+
+        let p_excl = Vec::<ParticipantID>::new();
+        let k_excl: HashMap<ParticipantID, HashMap<ParticipantID, Hash>> =
+            participants.iter().map(|p| (*p, HashMap::new())).collect();
+        let mut msgs = dc_decode(
+            &participants,
+            &matrices,
+            &participants[0],
+            nsheets,
+            max_chunks,
+            &p_excl,
+            &k_excl,
+        )
+        .expect("Ok");
+        for msg in msgs.iter_mut() {
+            msg.truncate(msg_len);
+        }
+        msgs.sort();
+        expected.sort();
+        assert_eq!(msgs, expected);
+    }
+
     #[test]
     fn tst_10_5() {
         tst_end_to_end(10, 5, 1350);
@@ -1500,4 +1598,268 @@ mod tests {
             /* */
         }
     }
+
+    // ------------------------------------------------------------------
+    // Property tests.
+    //
+    // Each case is a random session generated from its own seed,
+    // which is included in assertion messages, so that a failed case can be reproduced.
+
+    const PROPERTY_CASES: u8 = 16;
+
+    struct RandomSession {
+        participants: Vec<ParticipantID>,
+        cloaks: HashMap<ParticipantID, HashMap<ParticipantID, Hash>>,
+        msgs: HashMap<ParticipantID, Vec<Vec<u8>>>,
+        nsheets: usize,
+        max_chunks: usize,
+        msg_len: usize,
+    }
+
+    impl RandomSession {
+        fn new(seed: u8) -> RandomSession {
+            let mut rng = StdRng::from_seed([seed; 32]);
+            let nparts = rng.gen_range(2, 7);
+            let nsheets = rng.gen_range(1, 4);
+            let msg_len = rng.gen_range(1, 200);
+            let mut participants = Vec::<ParticipantID>::new();
+            let mut sess_pkeys = HashMap::new();
+            let mut sess_skeys = HashMap::new();
+            for _ in 0..nparts {
+                let (_sk, pk) = pbc::make_deterministic_keys(&rng.gen::<[u8; 32]>());
+                let p = ParticipantID::new(pk, rng.gen::<[u8; 32]>());
+                let (sk, pk) = scc::make_deterministic_keys(&rng.gen::<[u8; 32]>());
+                sess_pkeys.insert(p, pk);
+                sess_skeys.insert(p, sk);
+                participants.push(p);
+            }
+            let sess = Hash::digest(&rng.gen::<u64>());
+            let cloaks = participants
+                .iter()
+                .map(|p| {
+                    let cloaks = dc_keys(&participants, &sess_pkeys, p, &sess_skeys[p], &sess);
+                    (*p, cloaks)
+                })
+                .collect();
+
+            // Every participant shares up to nsheets distinct non-null messages.
+            let mut seen = HashSet::new();
+            let mut msgs = HashMap::new();
+            for p in &participants {
+                let nmsgs = rng.gen_range(0, nsheets + 1);
+                let mut my_msgs = Vec::new();
+                while my_msgs.len() < nmsgs {
+                    let mut msg: Vec<u8> = (0..msg_len).map(|_| rng.gen()).collect();
+                    msg[msg_len - 1] |= 1;
+                    if seen.insert(msg.clone()) {
+                        my_msgs.push(msg);
+                    }
+                }
+                msgs.insert(*p, my_msgs);
+            }
+            let max_chunks = split_message(&vec![0u8; msg_len], None).len();
+            RandomSession {
+                participants,
+                cloaks,
+                msgs,
+                nsheets,
+                max_chunks,
+                msg_len,
+            }
+        }
+
+        fn encode(&self, p: &ParticipantID) -> DcMatrix {
+            dc_encode_matrix(
+                self.nsheets,
+                self.max_chunks,
+                &self.msgs[p],
+                &self.participants,
+                p,
+                &self.cloaks[p],
+            )
+        }
+
+        /// Decodes matrices of `participants`, the rest are excluded.
+        fn decode(
+            &self,
+            participants: &Vec<ParticipantID>,
+            matrices: &HashMap<ParticipantID, DcMatrix>,
+        ) -> Result<Vec<Vec<u8>>, CryptoError> {
+            let p_excl: Vec<ParticipantID> = self
+                .participants
+                .iter()
+                .filter(|p| !participants.contains(p))
+                .cloned()
+                .collect();
+            let k_excl = self.excluded_cloaks(participants, &p_excl);
+            let mut msgs = dc_decode(
+                participants,
+                matrices,
+                &participants[0],
+                self.nsheets,
+                self.max_chunks,
+                &p_excl,
+                &k_excl,
+            )?;
+            for msg in msgs.iter_mut() {
+                msg.truncate(self.msg_len);
+            }
+            msgs.sort();
+            Ok(msgs)
+        }
+
+        /// Cloaking factors shared with excluded participants, by each remaining participant.
+        fn excluded_cloaks(
+            &self,
+            participants: &Vec<ParticipantID>,
+            p_excl: &Vec<ParticipantID>,
+        ) -> HashMap<ParticipantID, HashMap<ParticipantID, Hash>> {
+            participants
+                .iter()
+                .map(|p| {
+                    let cloaks = p_excl.iter().map(|e| (*e, self.cloaks[p][e])).collect();
+                    (*p, cloaks)
+                })
+                .collect()
+        }
+
+        fn expected(&self, participants: &Vec<ParticipantID>) -> Vec<Vec<u8>> {
+            let mut msgs: Vec<Vec<u8>> = participants
+                .iter()
+                .flat_map(|p| self.msgs[p].iter().cloned())
+                .collect();
+            msgs.sort();
+            msgs
+        }
+    }
+
+    #[test]
+    fn prop_dc_roundtrip() {
+        for seed in 0..PROPERTY_CASES {
+            let session = RandomSession::new(seed);
+            let matrices: HashMap<ParticipantID, DcMatrix> = session
+                .participants
+                .iter()
+                .map(|p| (*p, session.encode(p)))
+                .collect();
+            for matrix in matrices.values() {
+                assert!(
+                    dc_matrix_has_dims(
+                        matrix,
+                        session.nsheets,
+                        session.max_chunks,
+                        session.participants.len()
+                    ),
+                    "seed={}",
+                    seed
+                );
+            }
+            let msgs = session
+                .decode(&session.participants, &matrices)
+                .unwrap_or_else(|e| panic!("seed={}: {}", seed, e));
+            assert_eq!(
+                msgs,
+                session.expected(&session.participants),
+                "seed={}",
+                seed
+            );
+        }
+    }
+
+    #[test]
+    fn prop_dc_dropout() {
+        for seed in 0..PROPERTY_CASES {
+            let session = RandomSession::new(seed);
+            if session.participants.len() < 3 {
+                continue;
+            }
+            // One participant has shared keys, but not a matrix.
+            let mut rng = StdRng::from_seed([!seed; 32]);
+            let dropout = session.participants[rng.gen_range(0, session.participants.len())];
+            let participants: Vec<ParticipantID> = session
+                .participants
+                .iter()
+                .filter(|p| **p != dropout)
+                .cloned()
+                .collect();
+            let matrices: HashMap<ParticipantID, DcMatrix> = participants
+                .iter()
+                .map(|p| (*p, session.encode(p)))
+                .collect();
+            let msgs = session
+                .decode(&participants, &matrices)
+                .unwrap_or_else(|e| panic!("seed={}: {}", seed, e));
+            assert_eq!(msgs, session.expected(&participants), "seed={}", seed);
+        }
+    }
+
+    #[test]
+    fn prop_dc_tampered_matrix() {
+        for seed in 0..PROPERTY_CASES {
+            let session = RandomSession::new(seed);
+            let mut matrices: HashMap<ParticipantID, DcMatrix> = session
+                .participants
+                .iter()
+                .map(|p| (*p, session.encode(p)))
+                .collect();
+            // A cheater changes a single cell of its matrix.
+            let mut rng = StdRng::from_seed([!seed; 32]);
+            let cheater = session.participants[rng.gen_range(0, session.participants.len())];
+            let matrix = matrices.get_mut(&cheater).unwrap();
+            let sheet = rng.gen_range(0, matrix.len());
+            let row = rng.gen_range(0, matrix[sheet].len());
+            let col = rng.gen_range(0, matrix[sheet][row].len());
+            matrix[sheet][row][col] += Fr::one();
+            match session.decode(&session.participants, &matrices) {
+                Ok(msgs) => assert_ne!(
+                    msgs,
+                    session.expected(&session.participants),
+                    "seed={}",
+                    seed
+                ),
+                Err(CryptoError::DiceMixNoSolution) => {}
+                Err(e) => panic!("Unexpected error: seed={}: {}", seed, e),
+            }
+        }
+    }
+
+    #[test]
+    fn prop_dc_scalar() {
+        for seed in 0..PROPERTY_CASES {
+            let session = RandomSession::new(seed);
+            let mut rng = StdRng::from_seed([!seed; 32]);
+            let values: HashMap<ParticipantID, Fr> = session
+                .participants
+                .iter()
+                .map(|p| (*p, Fr::from(rng.gen::<u32>() as i64)))
+                .collect();
+            let cloaked: HashMap<ParticipantID, Fr> = values
+                .iter()
+                .map(|(p, x)| {
+                    let cloaked =
+                        dc_encode_scalar(*x, &session.participants, p, &session.cloaks[p]);
+                    (*p, cloaked)
+                })
+                .collect();
+            let sum = values.values().fold(Fr::zero(), |sum, x| sum + *x);
+            let p_excl = Vec::new();
+            let k_excl = HashMap::new();
+            let opened = dc_scalar_open(&session.participants, &cloaked, &p_excl, &k_excl);
+            assert_eq!(opened, sum, "seed={}", seed);
+
+            // Without a participant, its cloaks are provided by others.
+            if session.participants.len() < 3 {
+                continue;
+            }
+            let dropout = session.participants[0];
+            let participants: Vec<ParticipantID> =
+                session.participants[1..].iter().cloned().collect();
+            let mut cloaked = cloaked;
+            cloaked.remove(&dropout);
+            let p_excl = vec![dropout];
+            let k_excl = session.excluded_cloaks(&participants, &p_excl);
+            let opened = dc_scalar_open(&participants, &cloaked, &p_excl, &k_excl);
+            assert_eq!(opened, sum - values[&dropout], "seed={}", seed);
+        }
+    }
 }
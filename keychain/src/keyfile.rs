@@ -34,6 +34,10 @@ use stegos_serialization::traits::ProtoConvert;
 const ACCOUNT_ENCRYPTED_SKEY_TAG: &'static str = "STEGOS-CURVE25519 ENCRYPTED SECRET KEY";
 /// PEM tag for network secret key.
 const NETWORK_SKEY_TAG: &'static str = "STEGOS-PBC SECRET KEY";
+/// Network secret key to switch to after validator key rotation.
+pub const NEXT_NETWORK_SKEY_FILE: &'static str = "network.next.skey";
+/// Network public key to switch to after validator key rotation.
+pub const NEXT_NETWORK_PKEY_FILE: &'static str = "network.next.pkey";
 
 fn read(path: &Path) -> Result<Vec<u8>, KeyError> {
     match fs::read(path) {
@@ -239,6 +243,16 @@ pub fn load_network_keys(
     }
 }
 
+/// Load or create the network key pair for validator key rotation.
+pub fn load_next_network_keys(
+    keys_dir: &Path,
+) -> Result<(pbc::SecretKey, pbc::PublicKey), KeyError> {
+    load_network_keys(
+        &keys_dir.join(NEXT_NETWORK_SKEY_FILE),
+        &keys_dir.join(NEXT_NETWORK_PKEY_FILE),
+    )
+}
+
 #[cfg(test)]
 mod test {
 
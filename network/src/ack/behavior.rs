@@ -0,0 +1,323 @@
+//
+// MIT License
+//
+// Copyright (c) 2018-2019 Stegos AG
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use futures::prelude::*;
+use futures::sync::oneshot;
+use libp2p_core::{ConnectedPoint, Multiaddr, PeerId};
+use libp2p_swarm::{
+    protocols_handler::{DummyProtocolsHandler, ProtocolsHandler},
+    NetworkBehaviour, NetworkBehaviourAction, PollParameters,
+};
+use log::*;
+use lru_time_cache::LruCache;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::marker::PhantomData;
+use std::time::{Duration, Instant};
+use stegos_crypto::pbc;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_timer::Delay;
+use void::Void;
+
+use super::metrics;
+
+/// Reserved protocol_id for acknowledgements.
+pub const ACK_PROTOCOL_ID: &'static str = "stegos/unicast-ack";
+
+// How often to check for expired messages
+const CHECK_INTERVAL: Duration = Duration::from_millis(500);
+// How long to remember received ack_ids to drop duplicates
+const RECEIVED_EXPIRE_TIME: Duration = Duration::from_secs(5 * 60);
+const RECEIVED_CAPACITY: usize = 100_000;
+
+/// Outcome of an acknowledged unicast.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryStatus {
+    /// The recipient has confirmed the message.
+    Delivered,
+    /// No confirmation after all retries.
+    Timeout,
+}
+
+struct PendingMessage {
+    to: pbc::PublicKey,
+    protocol_id: String,
+    data: Vec<u8>,
+    attempt: u32,
+    sent_at: Instant,
+    deadline: Instant,
+    tx: oneshot::Sender<DeliveryStatus>,
+}
+
+/// Network behaviour which tracks unicast messages waiting for acknowledgement.
+/// Doesn't open any substreams, just asks to resend messages which were not confirmed in time.
+pub struct UnicastAcks<TSubstream> {
+    /// How long to wait for an acknowledgement before resending.
+    timeout: Duration,
+    /// How many times to resend a message.
+    max_retries: u32,
+    /// Messages waiting for acknowledgement, by ack_id.
+    pending: HashMap<Vec<u8>, PendingMessage>,
+    /// Recently received (from, ack_id) pairs.
+    received: LruCache<(pbc::PublicKey, Vec<u8>), ()>,
+    /// Events that need to be yielded to the outside when polling.
+    events: VecDeque<NetworkBehaviourAction<Void, AckEvent>>,
+    /// Expiration timer.
+    check_delay: Delay,
+    /// Marker to pin the generics.
+    marker: PhantomData<TSubstream>,
+}
+
+impl<TSubstream> UnicastAcks<TSubstream> {
+    pub fn new(timeout: Duration, max_retries: u32) -> Self {
+        UnicastAcks {
+            timeout,
+            max_retries,
+            pending: HashMap::new(),
+            received: LruCache::with_expiry_duration_and_capacity(
+                RECEIVED_EXPIRE_TIME,
+                RECEIVED_CAPACITY,
+            ),
+            events: VecDeque::new(),
+            check_delay: Delay::new(Instant::now() + CHECK_INTERVAL),
+            marker: PhantomData,
+        }
+    }
+
+    /// Registers a sent message and returns its ack_id.
+    pub fn track(
+        &mut self,
+        to: pbc::PublicKey,
+        protocol_id: String,
+        data: Vec<u8>,
+        tx: oneshot::Sender<DeliveryStatus>,
+    ) -> Vec<u8> {
+        let ack_id = rand::random::<[u8; 16]>().to_vec();
+        let now = Instant::now();
+        self.pending.insert(
+            ack_id.clone(),
+            PendingMessage {
+                to,
+                protocol_id,
+                data,
+                attempt: 0,
+                sent_at: now,
+                deadline: now + self.timeout,
+                tx,
+            },
+        );
+        metrics::SENT.inc();
+        metrics::PENDING.set(self.pending.len() as i64);
+        ack_id
+    }
+
+    /// Resolves the message confirmed by its recipient.
+    pub fn acknowledge(&mut self, from: &pbc::PublicKey, ack_id: &[u8]) {
+        match self.pending.get(ack_id) {
+            Some(msg) if msg.to == *from => {}
+            Some(_) => {
+                debug!(target: "stegos_network::ack", "ack from a wrong node: from={}", from);
+                return;
+            }
+            None => {
+                trace!(target: "stegos_network::ack", "ack for unknown message: from={}", from);
+                return;
+            }
+        }
+        let msg = self.pending.remove(ack_id).unwrap();
+        debug!(target: "stegos_network::ack", "unicast acknowledged: to={}, protocol_id={}, attempt={}", msg.to, msg.protocol_id, msg.attempt);
+        metrics::ACKNOWLEDGED.inc();
+        metrics::LATENCY.observe(msg.sent_at.elapsed().as_secs_f64());
+        metrics::PENDING.set(self.pending.len() as i64);
+        let _ = msg.tx.send(DeliveryStatus::Delivered);
+    }
+
+    /// Returns true if this message was already received and must not be passed to consumers again.
+    pub fn is_duplicate(&mut self, from: &pbc::PublicKey, ack_id: &[u8]) -> bool {
+        let key = (*from, ack_id.to_vec());
+        if self.received.contains_key(&key) {
+            return true;
+        }
+        self.received.insert(key, ());
+        false
+    }
+
+    /// Resends or fails messages which were not acknowledged in time.
+    fn on_check_timer(&mut self) {
+        let now = Instant::now();
+        let expired: Vec<Vec<u8>> = self
+            .pending
+            .iter()
+            .filter(|(_, msg)| msg.deadline <= now)
+            .map(|(ack_id, _)| ack_id.clone())
+            .collect();
+        for ack_id in expired {
+            let msg = self.pending.get_mut(&ack_id).unwrap();
+            if msg.attempt < self.max_retries {
+                msg.attempt += 1;
+                msg.deadline = now + self.timeout;
+                debug!(target: "stegos_network::ack", "resending unicast: to={}, protocol_id={}, attempt={}", msg.to, msg.protocol_id, msg.attempt);
+                metrics::RETRIES.inc();
+                self.events
+                    .push_back(NetworkBehaviourAction::GenerateEvent(AckEvent::Resend {
+                        to: msg.to,
+                        protocol_id: msg.protocol_id.clone(),
+                        data: msg.data.clone(),
+                        ack_id,
+                        attempt: msg.attempt,
+                    }));
+            } else {
+                let msg = self.pending.remove(&ack_id).unwrap();
+                warn!(target: "stegos_network::ack", "unicast not acknowledged: to={}, protocol_id={}", msg.to, msg.protocol_id);
+                metrics::TIMEOUTS.inc();
+                let _ = msg.tx.send(DeliveryStatus::Timeout);
+            }
+        }
+        metrics::PENDING.set(self.pending.len() as i64);
+    }
+}
+
+impl<TSubstream> NetworkBehaviour for UnicastAcks<TSubstream>
+where
+    TSubstream: AsyncRead + AsyncWrite,
+{
+    type ProtocolsHandler = DummyProtocolsHandler<TSubstream>;
+    type OutEvent = AckEvent;
+
+    fn new_handler(&mut self) -> Self::ProtocolsHandler {
+        DummyProtocolsHandler::default()
+    }
+
+    fn addresses_of_peer(&mut self, _: &PeerId) -> Vec<Multiaddr> {
+        Vec::new()
+    }
+
+    fn inject_connected(&mut self, _: PeerId, _: ConnectedPoint) {}
+
+    fn inject_disconnected(&mut self, _: &PeerId, _: ConnectedPoint) {}
+
+    fn inject_node_event(&mut self, _: PeerId, event: Void) {
+        void::unreachable(event)
+    }
+
+    fn poll(
+        &mut self,
+        _: &mut impl PollParameters,
+    ) -> Async<
+        NetworkBehaviourAction<
+            <Self::ProtocolsHandler as ProtocolsHandler>::InEvent,
+            Self::OutEvent,
+        >,
+    > {
+        loop {
+            match self.check_delay.poll() {
+                Ok(Async::Ready(_)) => {
+                    self.on_check_timer();
+                    self.check_delay.reset(Instant::now() + CHECK_INTERVAL);
+                }
+                Ok(Async::NotReady) => break,
+                Err(e) => {
+                    debug!(target: "stegos_network::ack", "check timer error: error={}", e);
+                    break;
+                }
+            }
+        }
+
+        if let Some(event) = self.events.pop_front() {
+            return Async::Ready(event);
+        }
+
+        Async::NotReady
+    }
+}
+
+/// Event that can happen on the acknowledgement behaviour.
+#[derive(Debug)]
+pub enum AckEvent {
+    /// Send the message again, via other routes.
+    Resend {
+        to: pbc::PublicKey,
+        protocol_id: String,
+        data: Vec<u8>,
+        ack_id: Vec<u8>,
+        attempt: u32,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn acknowledge() {
+        let (_, to) = pbc::make_random_keys();
+        let (_, other) = pbc::make_random_keys();
+        let mut acks = UnicastAcks::<()>::new(Duration::from_secs(0), 1);
+        let (tx, mut rx) = oneshot::channel();
+        let ack_id = acks.track(to, "test".to_string(), vec![1, 2, 3], tx);
+
+        // Timeout, resend.
+        acks.on_check_timer();
+        match acks.events.pop_front() {
+            Some(NetworkBehaviourAction::GenerateEvent(AckEvent::Resend {
+                to: resend_to,
+                ack_id: resend_id,
+                attempt,
+                ..
+            })) => {
+                assert_eq!(resend_to, to);
+                assert_eq!(resend_id, ack_id);
+                assert_eq!(attempt, 1);
+            }
+            e => panic!("unexpected event: {:?}", e),
+        }
+
+        // Ack from a wrong node is ignored.
+        acks.acknowledge(&other, &ack_id);
+        assert_eq!(rx.poll(), Ok(Async::NotReady));
+        acks.acknowledge(&to, &ack_id);
+        assert_eq!(rx.poll(), Ok(Async::Ready(DeliveryStatus::Delivered)));
+        assert!(acks.pending.is_empty());
+    }
+
+    #[test]
+    fn timeout() {
+        let (_, to) = pbc::make_random_keys();
+        let mut acks = UnicastAcks::<()>::new(Duration::from_secs(0), 0);
+        let (tx, mut rx) = oneshot::channel();
+        acks.track(to, "test".to_string(), vec![], tx);
+        acks.on_check_timer();
+        assert!(acks.events.is_empty());
+        assert_eq!(rx.poll(), Ok(Async::Ready(DeliveryStatus::Timeout)));
+    }
+
+    #[test]
+    fn duplicates() {
+        let (_, from) = pbc::make_random_keys();
+        let mut acks = UnicastAcks::<()>::new(Duration::from_secs(5), 3);
+        assert!(!acks.is_duplicate(&from, &[1]));
+        assert!(acks.is_duplicate(&from, &[1]));
+        assert!(!acks.is_duplicate(&from, &[2]));
+    }
+}
@@ -0,0 +1,59 @@
+//
+// MIT License
+//
+// Copyright (c) 2018-2019 Stegos AG
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use lazy_static::lazy_static;
+use prometheus::*;
+
+lazy_static! {
+    pub static ref SENT: IntCounter = register_int_counter!(
+        "stegos_unicast_ack_sent",
+        "Count of unicast messages sent with acknowledgement"
+    )
+    .unwrap();
+    pub static ref ACKNOWLEDGED: IntCounter = register_int_counter!(
+        "stegos_unicast_ack_acknowledged",
+        "Count of acknowledged unicast messages"
+    )
+    .unwrap();
+    pub static ref RETRIES: IntCounter = register_int_counter!(
+        "stegos_unicast_ack_retries",
+        "Count of unicast messages resent after timeout"
+    )
+    .unwrap();
+    pub static ref TIMEOUTS: IntCounter = register_int_counter!(
+        "stegos_unicast_ack_timeouts",
+        "Count of unicast messages never acknowledged"
+    )
+    .unwrap();
+    pub static ref PENDING: IntGauge = register_int_gauge!(
+        "stegos_unicast_ack_pending",
+        "Count of unicast messages waiting for acknowledgement"
+    )
+    .unwrap();
+    pub static ref LATENCY: Histogram = register_histogram!(
+        "stegos_unicast_ack_latency",
+        "Time between sending a unicast message and receiving its acknowledgement (secs)",
+        vec![0.01, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0]
+    )
+    .unwrap();
+}
@@ -0,0 +1,278 @@
+//
+// MIT License
+//
+// Copyright (c) 2018-2019 Stegos AG
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Capture of sent and received messages for offline replay.
+
+mod proto;
+
+use self::proto::capture_proto;
+use crate::config::CaptureConfig;
+use failure::Error;
+use log::*;
+use protobuf::{CodedInputStream, Message};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use stegos_crypto::pbc;
+
+/// Direction of a captured message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+/// Kind of a captured message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageKind {
+    Unicast,
+    Broadcast,
+}
+
+/// A captured message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaptureRecord {
+    pub timestamp: SystemTime,
+    pub direction: Direction,
+    pub kind: MessageKind,
+    /// Topic or protocol_id.
+    pub topic: String,
+    /// Recipient of sent unicast or origin of received messages, if known.
+    pub peer: Option<pbc::PublicKey>,
+    pub data: Vec<u8>,
+}
+
+impl CaptureRecord {
+    fn into_proto(self) -> capture_proto::Record {
+        let timestamp = self
+            .timestamp
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let mut proto = capture_proto::Record::new();
+        proto.set_timestamp(timestamp.as_secs() * 1000 + timestamp.subsec_millis() as u64);
+        proto.set_outgoing(self.direction == Direction::Sent);
+        proto.set_unicast(self.kind == MessageKind::Unicast);
+        proto.set_topic(self.topic);
+        if let Some(peer) = self.peer {
+            proto.set_peer(peer.to_bytes().to_vec());
+        }
+        proto.set_data(self.data);
+        proto
+    }
+
+    fn from_proto(mut proto: capture_proto::Record) -> Result<Self, Error> {
+        let timestamp = UNIX_EPOCH + Duration::from_millis(proto.get_timestamp());
+        let direction = if proto.get_outgoing() {
+            Direction::Sent
+        } else {
+            Direction::Received
+        };
+        let kind = if proto.get_unicast() {
+            MessageKind::Unicast
+        } else {
+            MessageKind::Broadcast
+        };
+        let peer = if proto.get_peer().is_empty() {
+            None
+        } else {
+            Some(pbc::PublicKey::try_from_bytes(proto.get_peer())?)
+        };
+        Ok(CaptureRecord {
+            timestamp,
+            direction,
+            kind,
+            topic: proto.take_topic(),
+            peer,
+            data: proto.take_data(),
+        })
+    }
+}
+
+/// Writes captured messages to a rotating file in a background thread.
+pub struct Capture {
+    tx: mpsc::Sender<CaptureRecord>,
+}
+
+impl Capture {
+    /// Starts capture, if it is enabled in the config.
+    pub fn new(config: &CaptureConfig) -> Option<Capture> {
+        if config.file.is_empty() {
+            return None;
+        }
+        let (tx, rx) = mpsc::channel();
+        let config = config.clone();
+        info!(target: "stegos_network::capture", "Capturing messages: file={}", config.file);
+        thread::Builder::new()
+            .name("capture".to_string())
+            .spawn(move || {
+                if let Err(e) = write_capture(&config, rx) {
+                    error!(target: "stegos_network::capture", "Failed to write capture: file={}, error={}", config.file, e);
+                }
+            })
+            .expect("failed to start capture thread");
+        Some(Capture { tx })
+    }
+
+    pub fn record(
+        &self,
+        direction: Direction,
+        kind: MessageKind,
+        topic: &str,
+        peer: Option<pbc::PublicKey>,
+        data: &[u8],
+    ) {
+        let record = CaptureRecord {
+            timestamp: SystemTime::now(),
+            direction,
+            kind,
+            topic: topic.to_string(),
+            peer,
+            data: data.to_vec(),
+        };
+        // The thread has already logged the error.
+        self.tx.send(record).ok();
+    }
+}
+
+fn write_capture(config: &CaptureConfig, rx: mpsc::Receiver<CaptureRecord>) -> Result<(), Error> {
+    let path = PathBuf::from(&config.file);
+    let (mut file, mut size) = open_capture(&path)?;
+    while let Ok(record) = rx.recv() {
+        let mut pending = vec![record];
+        pending.extend(rx.try_iter());
+        for record in pending {
+            let proto = record.into_proto();
+            let len = proto.compute_size() as u64 + 5;
+            if size > 0 && size + len > config.max_file_size {
+                file.flush()?;
+                drop(file);
+                rotate_capture(&path, config.max_files)?;
+                let (new_file, new_size) = open_capture(&path)?;
+                file = new_file;
+                size = new_size;
+            }
+            proto.write_length_delimited_to_writer(&mut file)?;
+            size += len;
+        }
+        file.flush()?;
+    }
+    Ok(())
+}
+
+fn open_capture(path: &Path) -> Result<(BufWriter<File>, u64), Error> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let size = file.metadata()?.len();
+    Ok((BufWriter::new(file), size))
+}
+
+fn rotated_path(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", n));
+    PathBuf::from(name)
+}
+
+/// Shifts capture -> capture.1 -> capture.2 ..., removing the oldest file.
+fn rotate_capture(path: &Path, max_files: usize) -> Result<(), Error> {
+    if max_files <= 1 {
+        fs::remove_file(path)?;
+        return Ok(());
+    }
+    let oldest = rotated_path(path, max_files - 1);
+    if oldest.exists() {
+        fs::remove_file(&oldest)?;
+    }
+    for n in (1..max_files - 1).rev() {
+        let from = rotated_path(path, n);
+        if from.exists() {
+            fs::rename(&from, rotated_path(path, n + 1))?;
+        }
+    }
+    fs::rename(path, rotated_path(path, 1))?;
+    Ok(())
+}
+
+/// Reads all messages from a capture file.
+pub fn read_capture(path: &Path) -> Result<Vec<CaptureRecord>, Error> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut input = CodedInputStream::from_buffered_reader(&mut reader);
+    let mut records = Vec::new();
+    while !input.eof()? {
+        let proto: capture_proto::Record = input.read_message()?;
+        records.push(CaptureRecord::from_proto(proto)?);
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(n: u8) -> CaptureRecord {
+        let (_skey, pkey) = pbc::make_random_keys();
+        CaptureRecord {
+            timestamp: UNIX_EPOCH + Duration::from_millis(1_000 + n as u64),
+            direction: Direction::Received,
+            kind: MessageKind::Unicast,
+            topic: "snowball".to_string(),
+            peer: Some(pkey),
+            data: vec![n; 100],
+        }
+    }
+
+    #[test]
+    fn write_read_rotate() {
+        let path =
+            std::env::temp_dir().join(format!("stegos_capture_{}.bin", rand::random::<u64>()));
+        let config = CaptureConfig {
+            file: path.to_string_lossy().to_string(),
+            max_file_size: 400,
+            max_files: 3,
+        };
+        let (tx, rx) = mpsc::channel();
+        let records: Vec<CaptureRecord> = (0..5).map(record).collect();
+        for record in records.iter() {
+            tx.send(record.clone()).unwrap();
+        }
+        drop(tx);
+        write_capture(&config, rx).unwrap();
+
+        // Two records per file, the oldest file is removed.
+        assert_eq!(read_capture(&path).unwrap(), records[4..].to_vec());
+        assert_eq!(
+            read_capture(&rotated_path(&path, 1)).unwrap(),
+            records[2..4].to_vec()
+        );
+        assert_eq!(
+            read_capture(&rotated_path(&path, 2)).unwrap(),
+            records[0..2].to_vec()
+        );
+        assert!(!rotated_path(&path, 3).exists());
+
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(rotated_path(&path, 1)).unwrap();
+        fs::remove_file(rotated_path(&path, 2)).unwrap();
+    }
+}
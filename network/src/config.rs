@@ -22,6 +22,7 @@
 // SOFTWARE.
 
 use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 /// Network configuration.
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -29,14 +30,26 @@ use serde_derive::{Deserialize, Serialize};
 pub struct NetworkConfig {
     /// DNS server ton use to resolve seed nodes
     pub dns_servers: Vec<String>,
-    /// Local Node endpoint.
+    /// Local Node endpoint ("host:port" or multiaddr).
     pub endpoint: String,
-    /// Advertised Node endpoint.
+    /// Advertised Node endpoint ("host:port" or multiaddr).
     pub advertised_endpoint: String,
     /// DNS name of pool of seed nodes
     pub seed_pool: String,
-    /// List of nodes to connect to on startup.
+    /// List of nodes to connect to on startup ("host:port" or multiaddr).
     pub seed_nodes: Vec<String>,
+    /// File to keep known peers across restarts (empty to keep peers in memory only).
+    pub peers_file: String,
+    /// List of nodes to always keep connected to (reconnected with backoff).
+    pub persistent_peers: Vec<String>,
+    /// PeerIds or network keys of peers allowed to connect to us (empty to allow everybody).
+    /// Peers listed by network key are admitted once they sign it in the handshake.
+    /// If not empty, we also don't dial other peers.
+    pub allowed_peers: Vec<String>,
+    /// Don't advertise our address or relays to peers.
+    /// Sentries still advertise the validator: they return its PeerId and network key in DHT
+    /// lookups, and its address if they've learned it elsewhere (e.g. from `persistent_peers`).
+    pub hidden: bool,
     /// Minimum active connections (try to keep at least so many established connections)
     pub min_connections: usize,
     /// Maximum active connections (Don't try to open more than max_connections connections)
@@ -47,6 +60,168 @@ pub struct NetworkConfig {
     pub hanshake_puzzle_difficulty: u64,
     /// Network readiness threshold (number of handshake-enabled established connections)
     pub readiness_threshold: usize,
+    /// Protocol used for broadcast messages.
+    pub pubsub: PubsubProtocol,
+    /// Gossipsub parameters (used only if pubsub = "gossipsub").
+    pub gossipsub: GossipsubConfig,
+    /// Peer reputation parameters.
+    pub peer_scoring: PeerScoringConfig,
+    /// Outgoing bandwidth limits.
+    pub bandwidth: BandwidthConfig,
+    /// Time to wait for acknowledgement of a unicast message before resending (secs)
+    pub unicast_ack_timeout: u64,
+    /// How many times to resend an unacknowledged unicast message
+    pub unicast_max_retries: u32,
+    /// Sign our broadcast messages with the network key.
+    pub sign_broadcasts: bool,
+    /// Topics on which unsigned broadcast messages are dropped.
+    /// Consensus and view change messages must be attributable to their publisher by default.
+    pub signed_topics: Vec<String>,
+    /// NAT traversal.
+    pub nat: NatConfig,
+    /// Transport and handshake protocols.
+    pub transport: TransportConfig,
+    /// Capture of sent and received messages.
+    pub capture: CaptureConfig,
+}
+
+/// Broadcast protocol.
+/// All nodes of the network should use the same protocol.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PubsubProtocol {
+    /// Forward every message to every peer.
+    Floodsub,
+    /// Forward messages to a bounded mesh of peers and gossip about the rest.
+    Gossipsub,
+}
+
+/// Gossipsub configuration.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct GossipsubConfig {
+    /// Target number of peers in the mesh of each topic.
+    pub mesh_n: usize,
+    /// Add peers to the mesh if it has less peers than this.
+    pub mesh_n_low: usize,
+    /// Remove peers from the mesh if it has more peers than this.
+    pub mesh_n_high: usize,
+    /// Number of peers outside of the mesh to send IHAVE gossip to.
+    pub gossip_lazy: usize,
+    /// Mesh maintenance interval (secs)
+    pub heartbeat_interval: u64,
+    /// Time to keep fanout peers of a topic after the last publish (secs)
+    pub fanout_ttl: u64,
+    /// Number of heartbeats to keep messages for IWANT requests.
+    pub history_length: usize,
+    /// Number of heartbeats to advertise messages in IHAVE gossip.
+    pub history_gossip: usize,
+}
+
+/// Peer reputation configuration.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct PeerScoringConfig {
+    /// Time for a peer score to decay by half (secs)
+    pub decay_half_life: u64,
+    /// Ignore broadcast messages from peers with score below this value.
+    pub graylist_threshold: f64,
+    /// Ban peers with score below this value.
+    pub ban_threshold: f64,
+    /// Ban duration (secs)
+    pub ban_duration: u64,
+    /// Maximal rate of incoming broadcast messages per peer (messages per sec)
+    pub max_message_rate: f64,
+    /// File to keep bans across restarts (empty to keep bans in memory only).
+    pub bans_file: String,
+}
+
+/// Outgoing bandwidth configuration.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct BandwidthConfig {
+    /// Topics sent before all other broadcast messages and never delayed by limits.
+    pub priority_topics: Vec<String>,
+    /// Outgoing broadcast rate per peer (bytes per sec, 0 - unlimited).
+    pub peer_rate: u64,
+    /// Outgoing broadcast burst per peer (bytes).
+    pub peer_burst: u64,
+    /// Outgoing rate limits per topic, for all peers together.
+    pub topics: HashMap<String, TopicLimit>,
+    /// Maximal number of queued outgoing broadcast messages.
+    pub max_queue_len: usize,
+    /// Drop queued outgoing broadcast messages after this time (secs).
+    pub max_queue_delay: u64,
+    /// Outgoing replication rate per peer (bytes per sec, 0 - unlimited).
+    pub replication_rate: u64,
+}
+
+/// Transport configuration.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct TransportConfig {
+    /// Transport to listen on.
+    pub profile: TransportProfile,
+    /// DER-encoded certificate to listen on secure WebSocket (empty for plain WebSocket).
+    pub tls_cert_file: String,
+    /// DER-encoded private key of the certificate.
+    pub tls_key_file: String,
+    /// Handshake protocols to accept, noise is preferred if both are enabled.
+    pub security: Vec<SecurityProtocol>,
+}
+
+/// Transport profile.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TransportProfile {
+    /// Listen on TCP, dial both TCP and WebSocket addresses.
+    Tcp,
+    /// Listen and dial only WebSocket addresses (for proxies and browsers).
+    Websocket,
+}
+
+/// Encryption handshake.
+/// Peers negotiate a protocol supported by both of them.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SecurityProtocol {
+    Secio,
+    Noise,
+}
+
+/// Traffic capture configuration.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct CaptureConfig {
+    /// File to write sent and received messages to (empty to disable capture).
+    pub file: String,
+    /// Rotate the file when it gets bigger than this (bytes).
+    pub max_file_size: u64,
+    /// Number of files to keep, including the current one.
+    pub max_files: usize,
+}
+
+/// NAT traversal configuration.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct NatConfig {
+    /// Map the listening port on the gateway using NAT-PMP or UPnP (if advertised_endpoint is empty).
+    pub port_mapping: bool,
+    /// Gateway address for NAT-PMP (empty to use the default route).
+    pub gateway: String,
+    /// Lifetime of port mappings (secs), they are renewed at half of it.
+    pub mapping_lifetime: u32,
+    /// Relay unicast messages to peers which are not reachable from outside.
+    pub relay: bool,
+}
+
+/// Token-bucket limit.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TopicLimit {
+    /// Bytes per sec.
+    pub rate: u64,
+    /// Bytes.
+    pub burst: u64,
 }
 
 /// Default values for network configuration.
@@ -56,6 +231,10 @@ impl Default for NetworkConfig {
             dns_servers: vec![],
             seed_pool: "".to_string(),
             seed_nodes: vec![],
+            peers_file: "".to_string(),
+            persistent_peers: vec![],
+            allowed_peers: vec![],
+            hidden: false,
             advertised_endpoint: "".to_string(),
             endpoint: "".to_string(),
             min_connections: 8,
@@ -63,6 +242,105 @@ impl Default for NetworkConfig {
             monitoring_interval: 60,
             hanshake_puzzle_difficulty: 100,
             readiness_threshold: 2,
+            pubsub: PubsubProtocol::Floodsub,
+            gossipsub: GossipsubConfig::default(),
+            peer_scoring: PeerScoringConfig::default(),
+            bandwidth: BandwidthConfig::default(),
+            unicast_ack_timeout: 5,
+            unicast_max_retries: 3,
+            sign_broadcasts: true,
+            signed_topics: vec![
+                "consensus".to_string(),
+                "view_changes".to_string(),
+                "view_changes_proofs".to_string(),
+            ],
+            nat: NatConfig::default(),
+            transport: TransportConfig::default(),
+            capture: CaptureConfig::default(),
+        }
+    }
+}
+
+/// Default values for gossipsub configuration.
+impl Default for GossipsubConfig {
+    fn default() -> GossipsubConfig {
+        GossipsubConfig {
+            mesh_n: 6,
+            mesh_n_low: 4,
+            mesh_n_high: 12,
+            gossip_lazy: 6,
+            heartbeat_interval: 1,
+            fanout_ttl: 60,
+            history_length: 5,
+            history_gossip: 3,
+        }
+    }
+}
+
+/// Default values for peer reputation configuration.
+impl Default for PeerScoringConfig {
+    fn default() -> PeerScoringConfig {
+        PeerScoringConfig {
+            decay_half_life: 10 * 60,
+            graylist_threshold: -50.0,
+            ban_threshold: -100.0,
+            ban_duration: 60 * 60,
+            max_message_rate: 200.0,
+            bans_file: "".to_string(),
+        }
+    }
+}
+
+/// Default values for bandwidth configuration.
+impl Default for BandwidthConfig {
+    fn default() -> BandwidthConfig {
+        BandwidthConfig {
+            priority_topics: vec![
+                "consensus".to_string(),
+                "view_changes".to_string(),
+                "view_changes_proofs".to_string(),
+            ],
+            peer_rate: 0,
+            peer_burst: 1024 * 1024,
+            topics: HashMap::new(),
+            max_queue_len: 10_000,
+            max_queue_delay: 30,
+            replication_rate: 0,
+        }
+    }
+}
+
+/// Default values for NAT traversal configuration.
+impl Default for NatConfig {
+    fn default() -> NatConfig {
+        NatConfig {
+            port_mapping: false,
+            gateway: "".to_string(),
+            mapping_lifetime: 3600,
+            relay: false,
+        }
+    }
+}
+
+/// Default values for transport configuration.
+impl Default for TransportConfig {
+    fn default() -> TransportConfig {
+        TransportConfig {
+            profile: TransportProfile::Tcp,
+            tls_cert_file: "".to_string(),
+            tls_key_file: "".to_string(),
+            security: vec![SecurityProtocol::Secio],
+        }
+    }
+}
+
+/// Default values for traffic capture configuration.
+impl Default for CaptureConfig {
+    fn default() -> CaptureConfig {
+        CaptureConfig {
+            file: "".to_string(),
+            max_file_size: 64 * 1024 * 1024,
+            max_files: 5,
         }
     }
 }
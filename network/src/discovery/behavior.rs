@@ -60,6 +60,8 @@ pub struct Discovery<TSubstream> {
     kademlia: Kademlia<TSubstream>,
     /// Known nodes
     known_nodes: LruBimap<pbc::PublicKey, PeerId>,
+    /// Relays of nodes behind NAT
+    relays: LruCache<pbc::PublicKey, Vec<PeerId>>,
     /// Outbound events
     out_events: VecDeque<DiscoveryOutEvent>,
     /// Set of currently connected peers
@@ -81,11 +83,15 @@ impl<TSubstream> Discovery<TSubstream>
 where
     TSubstream: AsyncRead + AsyncWrite,
 {
-    pub fn new(local_node_id: pbc::PublicKey) -> Self {
+    pub fn new(local_node_id: pbc::PublicKey, hidden: bool) -> Self {
+        let mut kademlia = Kademlia::without_init(local_node_id.clone());
+        // Hidden nodes don't advertise themselves in Kademlia responses.
+        kademlia.set_hidden(&local_node_id, hidden);
         Discovery {
             my_id: local_node_id.clone(),
-            kademlia: Kademlia::without_init(local_node_id),
+            kademlia,
             known_nodes: LruBimap::<pbc::PublicKey, PeerId>::with_expiry_duration(NODES_TTL),
+            relays: LruCache::with_expiry_duration(NODES_TTL),
             out_events: VecDeque::new(),
             connected_peers: HashSet::new(),
             next_query: Delay::new(Instant::now() + Duration::from_secs(30)),
@@ -125,7 +131,50 @@ where
         self.known_nodes.insert(node_id, peer_id);
     }
 
+    /// Excludes the node which asked not to be advertised from Kademlia responses.
+    pub fn set_hidden(&mut self, node_id: &pbc::PublicKey, hidden: bool) {
+        self.kademlia.set_hidden(node_id, hidden);
+    }
+
+    /// Sets relays of the node behind NAT (empty if the node is reachable directly).
+    pub fn set_relays(&mut self, node_id: &pbc::PublicKey, relays: Vec<PeerId>) {
+        if relays.is_empty() {
+            self.relays.remove(node_id);
+        } else {
+            self.relays.insert(node_id.clone(), relays);
+        }
+    }
+
+    /// Returns node_id of the peer if it is behind NAT and reachable only through relays.
+    pub fn relayed_node(&mut self, peer_id: &PeerId) -> Option<pbc::PublicKey> {
+        let node_id = self.known_nodes.get_by_value(peer_id)?.clone();
+        if self.relays.contains_key(&node_id) {
+            Some(node_id)
+        } else {
+            None
+        }
+    }
+
     pub fn deliver_unicast(&mut self, to: &pbc::PublicKey, payload: Vec<u8>) {
+        let message = self.new_unicast(to, payload);
+        self.route(to, message);
+    }
+
+    /// Resends the payload directly, via relays and via closer nodes, since the previous route has failed.
+    pub fn redeliver_unicast(&mut self, to: &pbc::PublicKey, payload: Vec<u8>) {
+        let message = self.new_unicast(to, payload);
+        if let Some(peer_id) = self.known_nodes.get_by_key(to) {
+            debug!(target: "stegos_network::delivery", "retrying to node's peer_id: node_id={}, peer_id={}, seq_no={}", to, peer_id, u8v_to_hexstr(&message.seq_no));
+            self.out_events.push_back(DiscoveryOutEvent::Route {
+                next_hop: peer_id.clone(),
+                message: message.clone(),
+            });
+        }
+        self.route_via_relays(to, &message);
+        self.route_via_closer(to, message);
+    }
+
+    fn new_unicast(&mut self, to: &pbc::PublicKey, payload: Vec<u8>) -> Unicast {
         let mut message = Unicast {
             to: to.clone(),
             payload,
@@ -144,19 +193,21 @@ where
         }
         self.received.insert(message.digest(), ());
         super::metrics::LRU_CACHE_SIZE.set(self.received.len() as i64);
-
-        self.route(to, message);
+        message
     }
 
     pub fn route(&mut self, to: &pbc::PublicKey, message: Unicast) {
         // Check if we already know node's peer_id
-        if let Some(peer_id) = self.known_nodes.get_by_key(to) {
-            debug!(target: "stegos_network::delivery", "found node's peer_id: node_id={}, peer_id={}, seq_no={}", to, peer_id, u8v_to_hexstr(&message.seq_no));
-            self.out_events.push_back(DiscoveryOutEvent::Route {
-                next_hop: peer_id.clone(),
-                message,
-            });
-            return;
+        if let Some(peer_id) = self.known_nodes.get_by_key(to).cloned() {
+            // Nodes behind NAT can't be dialed, they are reachable only through relays
+            if self.connected_peers.contains(&peer_id) || !self.relays.contains_key(to) {
+                debug!(target: "stegos_network::delivery", "found node's peer_id: node_id={}, peer_id={}, seq_no={}", to, peer_id, u8v_to_hexstr(&message.seq_no));
+                self.out_events.push_back(DiscoveryOutEvent::Route {
+                    next_hop: peer_id,
+                    message,
+                });
+                return;
+            }
         }
 
         // If destination node is present in out DHT, send payload directly
@@ -173,6 +224,34 @@ where
             }
         }
 
+        // Relays might have gone, fall back to closer nodes unless one of them is connected
+        if self.route_via_relays(to, &message) {
+            return;
+        }
+
+        self.route_via_closer(to, message);
+    }
+
+    /// Sends the message to relays of the node behind NAT.
+    /// Returns false if the node has no relays or none of them is connected.
+    fn route_via_relays(&mut self, to: &pbc::PublicKey, message: &Unicast) -> bool {
+        let relays = match self.relays.get(to) {
+            Some(relays) => relays.clone(),
+            None => return false,
+        };
+        let mut connected = false;
+        for relay in relays {
+            debug!(target: "stegos_network::delivery", "node is behind NAT, delivering via relay: node_id={}, relay={}, seq_no={}", to, relay, u8v_to_hexstr(&message.seq_no));
+            connected |= self.connected_peers.contains(&relay);
+            self.out_events.push_back(DiscoveryOutEvent::Route {
+                next_hop: relay,
+                message: message.clone(),
+            });
+        }
+        connected
+    }
+
+    fn route_via_closer(&mut self, to: &pbc::PublicKey, message: Unicast) {
         debug!(target: "stegos_network::delivery", "finding route to node: node_id={}, seq_no={}", to, u8v_to_hexstr(&message.seq_no));
         // Collect DELIVERY_REPLICATION closest nodes with both peer_id and IPs known
         let closer_peers_temp: Vec<pbc::PublicKey> =
@@ -32,20 +32,23 @@ use lru_time_cache::LruCache;
 use rand::{seq::SliceRandom, thread_rng};
 use std::cmp::max;
 use std::error;
-use std::time::{Duration, SystemTime};
+use std::str::FromStr;
+use std::time::{Duration, Instant, SystemTime};
 use std::{
-    collections::{HashSet, VecDeque},
+    collections::{HashMap, HashSet, VecDeque},
     marker::PhantomData,
     thread,
 };
+use stegos_crypto::pbc;
 use stegos_crypto::vdf::VDF;
 use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_timer::Delay;
 
 use super::handler::{GatekeeperHandler, GatekeeperSendEvent};
-use super::protocol::{GatekeeperMessage, VDFProof};
+use super::protocol::{GatekeeperMessage, KeyProof, VDFProof};
 use crate::config::NetworkConfig;
-use crate::utils::{socket_to_multi_addr, ExpiringQueue, PeerIdKey};
-use std::net::SocketAddr;
+use crate::utils::{parse_endpoint, ExpiringQueue, PeerIdKey};
+use failure::{format_err, Error};
 
 // Dialout timeout
 const DIAL_TIMEOUT: Duration = Duration::from_secs(60);
@@ -55,6 +58,12 @@ const HASH_CASH_TIMEOUT: Duration = Duration::from_secs(5 * 60);
 const HASH_CASH_PROOF_TTL: Duration = Duration::from_secs(365 * 24 * 60 * 60);
 // How long to wait for next event
 const HANDSHAKE_STEP_TIMEOUT: Duration = Duration::from_secs(30);
+// Initial delay before reconnecting to a persistent peer
+const MIN_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+// Maximal delay before reconnecting to a persistent peer
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(5 * 60);
+// How often to check persistent peers for reconnection
+const RECONNECT_CHECK_INTERVAL: Duration = Duration::from_secs(1);
 
 /// Network behavior to handle initial nodes handshake
 pub struct Gatekeeper<TSubstream> {
@@ -92,13 +101,42 @@ pub struct Gatekeeper<TSubstream> {
     hanshake_puzzle_difficulty: u64,
     /// Netwrok readyness threshold
     readiness_threshold: usize,
+    /// Addresses we are always keeping connected to
+    persistent_peers: HashMap<Multiaddr, Reconnect>,
+    /// Delay to the next check of persistent peers
+    reconnect_delay: Delay,
+    /// Peers allowed to connect to us (empty - everybody is allowed)
+    allowed_peers: HashSet<PeerId>,
+    /// Network keys of peers allowed to connect to us
+    allowed_keys: HashSet<pbc::PublicKey>,
+    /// Our PeerId
+    local_peer_id: PeerId,
+    /// Our network public key
+    network_pkey: pbc::PublicKey,
+    /// Our network secret key
+    network_skey: pbc::SecretKey,
     /// Marker to pin the generics.
     marker: PhantomData<TSubstream>,
 }
 
+/// Reconnection state of a persistent peer.
+struct Reconnect {
+    /// True if connection is established
+    connected: bool,
+    /// Current delay between attempts
+    delay: Duration,
+    /// Time of the next attempt
+    next_attempt: Option<Instant>,
+}
+
 impl<TSubstream> Gatekeeper<TSubstream> {
     /// Creates a NetworkBehaviour for Gatekeeper.
-    pub fn new(config: &NetworkConfig) -> Self {
+    pub fn new(
+        config: &NetworkConfig,
+        local_peer_id: PeerId,
+        network_pkey: pbc::PublicKey,
+        network_skey: pbc::SecretKey,
+    ) -> Result<Self, Error> {
         let mut desired_addesses: HashSet<Multiaddr> = HashSet::new();
         let mut events: VecDeque<NetworkBehaviourAction<GatekeeperSendEvent, GatekeeperOutEvent>> =
             VecDeque::new();
@@ -109,8 +147,8 @@ impl<TSubstream> Gatekeeper<TSubstream> {
         addrs.shuffle(&mut rng);
 
         for addr in addrs.iter() {
-            let addr = addr.parse::<SocketAddr>().expect("Invalid seed_node");
-            let addr = socket_to_multi_addr(&addr);
+            let addr = parse_endpoint(addr, &config.transport)
+                .map_err(|e| format_err!("Invalid seed_nodes '{}': {}", addr, e))?;
             debug!(target: "stegos_network::gatekeeper", "dialing peer with address {}", addr);
             events.push_back(NetworkBehaviourAction::DialAddress {
                 address: addr.clone(),
@@ -118,10 +156,44 @@ impl<TSubstream> Gatekeeper<TSubstream> {
             desired_addesses.insert(addr);
         }
 
+        let mut persistent_peers = HashMap::new();
+        for addr in config.persistent_peers.iter() {
+            let addr = parse_endpoint(addr, &config.transport)
+                .map_err(|e| format_err!("Invalid persistent_peers '{}': {}", addr, e))?;
+            debug!(target: "stegos_network::gatekeeper", "dialing persistent peer with address {}", addr);
+            events.push_back(NetworkBehaviourAction::DialAddress {
+                address: addr.clone(),
+            });
+            desired_addesses.insert(addr.clone());
+            persistent_peers.insert(
+                addr,
+                Reconnect {
+                    connected: false,
+                    delay: MIN_RECONNECT_DELAY,
+                    next_attempt: None,
+                },
+            );
+        }
+
+        let mut allowed_peers = HashSet::new();
+        let mut allowed_keys = HashSet::new();
+        for peer in config.allowed_peers.iter() {
+            if let Ok(peer_id) = PeerId::from_str(peer) {
+                allowed_peers.insert(peer_id);
+            } else if let Ok(node_id) = pbc::PublicKey::try_from_hex(peer) {
+                allowed_keys.insert(node_id);
+            } else {
+                return Err(format_err!(
+                    "Invalid allowed_peers '{}': expected PeerId or network key",
+                    peer
+                ));
+            }
+        }
+
         let (solution_sink, solution_stream) = unbounded::<Solution>();
         let solver_threads = max(num_cpus::get() - 2, 1);
         debug!(target: "stegos_network::gatekeeper", "number of VDF solver threads: {}", solver_threads);
-        Gatekeeper {
+        Ok(Gatekeeper {
             events,
             connected_peers: HashSet::new(),
             desired_peers: HashSet::new(),
@@ -144,8 +216,15 @@ impl<TSubstream> Gatekeeper<TSubstream> {
             challenges_queue: VecDeque::new(),
             hanshake_puzzle_difficulty: config.hanshake_puzzle_difficulty,
             readiness_threshold: config.readiness_threshold,
+            persistent_peers,
+            reconnect_delay: Delay::new(Instant::now() + RECONNECT_CHECK_INTERVAL),
+            allowed_peers,
+            allowed_keys,
+            local_peer_id,
+            network_pkey,
+            network_skey,
             marker: PhantomData,
-        }
+        })
     }
 
     pub fn is_network_ready(&self) -> bool {
@@ -168,6 +247,65 @@ impl<TSubstream> Gatekeeper<TSubstream> {
         self.protocol_updates.push_back(event);
     }
 
+    /// Returns true if we are allowed to talk to the peer.
+    /// Peers with allowed network keys are only known after they've proven their keys.
+    pub fn is_allowed(&self, peer_id: &PeerId) -> bool {
+        (self.allowed_peers.is_empty() && self.allowed_keys.is_empty())
+            || self.allowed_peers.contains(peer_id)
+    }
+
+    /// Switches to new network keys.
+    pub fn change_network_keys(
+        &mut self,
+        network_pkey: pbc::PublicKey,
+        network_skey: pbc::SecretKey,
+    ) {
+        self.network_pkey = network_pkey;
+        self.network_skey = network_skey;
+    }
+
+    /// Signs our network key for the peer.
+    fn key_proof(&self, peer_id: &PeerId) -> KeyProof {
+        KeyProof::sign(
+            &self.local_peer_id,
+            peer_id,
+            &self.network_pkey,
+            &self.network_skey,
+        )
+    }
+
+    /// Admits the peer if it's allowed by PeerId or has proven an allowed network key.
+    fn check_key_proof(&mut self, peer_id: &PeerId, key_proof: Option<KeyProof>) -> bool {
+        if self.is_allowed(peer_id) {
+            return true;
+        }
+        let key_proof = match key_proof {
+            Some(key_proof) => key_proof,
+            None => return false,
+        };
+        if !self.allowed_keys.contains(&key_proof.node_id) {
+            return false;
+        }
+        if !key_proof.verify(peer_id, &self.local_peer_id) {
+            debug!(target: "stegos_network::gatekeeper", "invalid network key proof: node_id={}, peer_id={}", key_proof.node_id, peer_id);
+            return false;
+        }
+        debug!(target: "stegos_network::gatekeeper", "allowed peer proved its network key: node_id={}, peer_id={}", key_proof.node_id, peer_id);
+        self.allowed_peers.insert(peer_id.clone());
+        true
+    }
+
+    fn schedule_reconnect(&mut self, address: &Multiaddr) {
+        if let Some(reconnect) = self.persistent_peers.get_mut(address) {
+            reconnect.connected = false;
+            if reconnect.next_attempt.is_none() {
+                debug!(target: "stegos_network::gatekeeper", "scheduling reconnect to persistent peer: addr={}, delay={:?}", address, reconnect.delay);
+                reconnect.next_attempt = Some(Instant::now() + reconnect.delay);
+                reconnect.delay = std::cmp::min(reconnect.delay * 2, MAX_RECONNECT_DELAY);
+            }
+        }
+    }
+
     fn send_new_challenge(&mut self, peer_id: PeerId) {
         let challenge = generate_challenge(&peer_id);
         self.our_challenges.insert(
@@ -188,7 +326,27 @@ impl<TSubstream> Gatekeeper<TSubstream> {
         })
     }
 
-    fn handle_unlock_request(&mut self, peer_id: PeerId, proof: Option<VDFProof>) {
+    fn handle_unlock_request(
+        &mut self,
+        peer_id: PeerId,
+        proof: Option<VDFProof>,
+        key_proof: Option<KeyProof>,
+    ) {
+        if !self.check_key_proof(&peer_id, key_proof) {
+            debug!(target: "stegos_network::gatekeeper", "unlock request from peer not in allowed list, rejecting: peer_id={}", peer_id);
+            self.events.push_back(NetworkBehaviourAction::SendEvent {
+                peer_id: peer_id.clone(),
+                event: GatekeeperSendEvent::Send(GatekeeperMessage::PermitReply {
+                    connection_allowed: false,
+                    key_proof: None,
+                }),
+            });
+            self.events.push_back(NetworkBehaviourAction::GenerateEvent(
+                GatekeeperOutEvent::Rejected { peer_id },
+            ));
+            return;
+        }
+
         if self.unlocked_peers.contains_key(&peer_id.clone().into()) {
             debug!(target: "stegos_network::gatekeeper", "unlock request from already unlocked peer: peer_id={}", peer_id);
             self.pending_in_peers
@@ -268,10 +426,12 @@ impl<TSubstream> Gatekeeper<TSubstream> {
                     difficulty: p.0.difficulty,
                     proof: p.1.clone().expect("Checked for Some earlier"),
                 };
+                let key_proof = Some(self.key_proof(&peer_id));
                 self.events.push_back(NetworkBehaviourAction::SendEvent {
                     peer_id: peer_id.clone(),
                     event: GatekeeperSendEvent::Send(GatekeeperMessage::UnlockRequest {
                         proof: Some(proof),
+                        key_proof,
                     }),
                 });
                 self.pending_out_peers
@@ -308,6 +468,11 @@ where
         self.connected_peers.insert(id.clone());
         // FIXME: use LRU cache for dialing addresses/peers
         if let ConnectedPoint::Dialer { address } = cp {
+            if let Some(reconnect) = self.persistent_peers.get_mut(&address) {
+                reconnect.connected = true;
+                reconnect.delay = MIN_RECONNECT_DELAY;
+                reconnect.next_attempt = None;
+            }
             if self.desired_addesses.contains(&address) {
                 self.desired_peers.insert(id.clone());
             }
@@ -339,6 +504,10 @@ where
             },
         ));
         if let ConnectedPoint::Dialer { address } = cp {
+            if self.persistent_peers.contains_key(&address) {
+                self.schedule_reconnect(&address);
+                return;
+            }
             if self.desired_peers.contains(id) || self.desired_addesses.contains(&address) {
                 debug!(target: "stegos_network::gatekeeper", "re-connecting to peer/addr: peer_id={}, addr={}", id, address);
                 self.events
@@ -373,6 +542,7 @@ where
         };
 
         debug!(target: "stegos_network::gatekeeper", "failure reaching address: peer_id={}, addr={}, error={}", peer_info, addr, error);
+        self.schedule_reconnect(addr);
     }
 
     /// Indicates to the behaviour that we tried to dial all the addresses known for a node, but
@@ -385,14 +555,27 @@ where
         // Process received Gatekeeper message (passed from Handler as Custom(message))
         debug!(target: "stegos_network::gatekeeper", "Received a message: {:?}", event);
         match event {
-            GatekeeperMessage::UnlockRequest { proof } => {
-                self.handle_unlock_request(propagation_source, proof)
+            GatekeeperMessage::UnlockRequest { proof, key_proof } => {
+                self.handle_unlock_request(propagation_source, proof, key_proof)
             }
             GatekeeperMessage::ChallengeReply {
                 challenge,
                 difficulty,
             } => self.handle_challenge_reply(propagation_source, challenge, difficulty),
-            GatekeeperMessage::PermitReply { connection_allowed } => {
+            GatekeeperMessage::PermitReply {
+                connection_allowed,
+                key_proof,
+            } => {
+                if connection_allowed && !self.check_key_proof(&propagation_source, key_proof) {
+                    debug!(target: "stegos_network::gatekeeper", "permit reply from peer not in allowed list, rejecting: peer_id={}", propagation_source);
+                    self.pending_out_peers.remove(&propagation_source);
+                    self.events.push_back(NetworkBehaviourAction::GenerateEvent(
+                        GatekeeperOutEvent::Rejected {
+                            peer_id: propagation_source,
+                        },
+                    ));
+                    return;
+                }
                 if connection_allowed {
                     debug!(target: "stegos_network::gatekeeper", "succesfully negotiated VDF handshake: peer_id={}", propagation_source);
                     self.unlocked_peers
@@ -496,10 +679,12 @@ where
                     debug!(target: "stegos_network::gatekeeper", "listener enabled, sending unlock request: peer_id={}, with_proof={}", peer_id, proof.is_some());
                     self.pending_out_peers
                         .insert(peer_id.clone().into(), DialerPeerState::UnlockRequestSent);
+                    let key_proof = Some(self.key_proof(&peer_id));
                     self.events.push_back(NetworkBehaviourAction::SendEvent {
                         peer_id,
                         event: GatekeeperSendEvent::Send(GatekeeperMessage::UnlockRequest {
                             proof,
+                            key_proof,
                         }),
                     })
                 }
@@ -507,10 +692,12 @@ where
                     if self.pending_in_peers.contains_key(&peer_id) {
                         debug!(target: "stegos_network::gatekeeper", "dialer enabled, sending permit reply: peer_id={}", peer_id);
                         self.pending_in_peers.remove(&peer_id);
+                        let key_proof = Some(self.key_proof(&peer_id));
                         self.events.push_back(NetworkBehaviourAction::SendEvent {
                             peer_id,
                             event: GatekeeperSendEvent::Send(GatekeeperMessage::PermitReply {
                                 connection_allowed: true,
+                                key_proof,
                             }),
                         });
                     } else {
@@ -530,11 +717,13 @@ where
                             proof: proof.clone(),
                         };
                         if self.connected_peers.contains(&peer_id) {
+                            let key_proof = Some(self.key_proof(&peer_id));
                             self.events.push_back(NetworkBehaviourAction::SendEvent {
                                 peer_id,
                                 event: GatekeeperSendEvent::Send(
                                     GatekeeperMessage::UnlockRequest {
                                         proof: Some(vdf_proof),
+                                        key_proof,
                                     },
                                 ),
                             })
@@ -549,6 +738,37 @@ where
             }
         }
 
+        // Reconnect to persistent peers
+        loop {
+            match self.reconnect_delay.poll() {
+                Ok(Async::Ready(_)) => {
+                    self.reconnect_delay
+                        .reset(Instant::now() + RECONNECT_CHECK_INTERVAL);
+                    let now = Instant::now();
+                    for (address, reconnect) in self.persistent_peers.iter_mut() {
+                        match reconnect.next_attempt {
+                            Some(next_attempt) if next_attempt <= now => {}
+                            _ => continue,
+                        }
+                        if reconnect.connected {
+                            reconnect.next_attempt = None;
+                            continue;
+                        }
+                        debug!(target: "stegos_network::gatekeeper", "re-connecting to persistent peer: addr={}", address);
+                        reconnect.next_attempt = None;
+                        self.events.push_back(NetworkBehaviourAction::DialAddress {
+                            address: address.clone(),
+                        });
+                    }
+                }
+                Ok(Async::NotReady) => break,
+                Err(e) => {
+                    error!(target: "stegos_network::gatekeeper", "reconnect timer error: {}", e);
+                    break;
+                }
+            }
+        }
+
         // Expire outbound peer negotiations
         loop {
             match self.pending_out_peers.poll() {
@@ -621,6 +841,10 @@ pub enum GatekeeperOutEvent {
     Finished {
         peer_id: PeerId,
     },
+    /// Peer is not allowed to connect to us.
+    Rejected {
+        peer_id: PeerId,
+    },
     NetworkReady,
 }
 
@@ -671,3 +895,29 @@ impl StegosDisplay for ConnectedPoint {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allowed_peers() {
+        let config = NetworkConfig::default();
+        let gatekeeper = Gatekeeper::<()>::new(&config);
+        assert!(gatekeeper.is_allowed(&PeerId::random()));
+
+        let allowed_peer = PeerId::random();
+        let (_, allowed_key) = pbc::make_random_keys();
+        let mut config = NetworkConfig::default();
+        config.allowed_peers = vec![allowed_peer.to_base58(), allowed_key.to_hex()];
+        let mut gatekeeper = Gatekeeper::<()>::new(&config);
+        assert!(gatekeeper.is_allowed(&allowed_peer));
+        let peer_id = PeerId::random();
+        assert!(!gatekeeper.is_allowed(&peer_id));
+        let (_, other_key) = pbc::make_random_keys();
+        gatekeeper.discovered_node(&other_key, &peer_id);
+        assert!(!gatekeeper.is_allowed(&peer_id));
+        gatekeeper.discovered_node(&allowed_key, &peer_id);
+        assert!(gatekeeper.is_allowed(&peer_id));
+    }
+}
@@ -25,9 +25,11 @@ use crate::metrics;
 
 use bytes::{BufMut, BytesMut};
 use futures::future;
-use libp2p_core::{upgrade::Negotiated, InboundUpgrade, OutboundUpgrade, UpgradeInfo};
+use libp2p_core::{upgrade::Negotiated, InboundUpgrade, OutboundUpgrade, PeerId, UpgradeInfo};
 use protobuf::Message as ProtobufMessage;
 use std::{io, iter};
+use stegos_crypto::hash::{Hash, Hashable, Hasher};
+use stegos_crypto::pbc;
 use tokio::codec::{Decoder, Encoder, Framed};
 use tokio::io::{AsyncRead, AsyncWrite};
 use unsigned_varint::codec;
@@ -107,7 +109,7 @@ impl Encoder for GatekeeperCodec {
 
     fn encode(&mut self, item: Self::Item, dst: &mut BytesMut) -> Result<(), Self::Error> {
         let proto = match item {
-            GatekeeperMessage::UnlockRequest { proof } => {
+            GatekeeperMessage::UnlockRequest { proof, key_proof } => {
                 let mut msg_typ = gatekeeper_proto::UnlockRequest::new();
                 if let Some(proof) = proof {
                     let mut proof_proto = gatekeeper_proto::VDFProof::new();
@@ -116,6 +118,9 @@ impl Encoder for GatekeeperCodec {
                     proof_proto.set_vdf_proof(proof.proof);
                     msg_typ.set_proof(proof_proto);
                 }
+                if let Some(key_proof) = key_proof {
+                    msg_typ.set_key_proof(encode_key_proof(key_proof));
+                }
                 let mut proto_msg = gatekeeper_proto::Message::new();
                 proto_msg.set_unlock_request(msg_typ);
                 proto_msg
@@ -131,9 +136,15 @@ impl Encoder for GatekeeperCodec {
                 proto_msg.set_challenge_reply(msg_typ);
                 proto_msg
             }
-            GatekeeperMessage::PermitReply { connection_allowed } => {
+            GatekeeperMessage::PermitReply {
+                connection_allowed,
+                key_proof,
+            } => {
                 let mut msg_typ = gatekeeper_proto::PermitReply::new();
                 msg_typ.set_connection_allowed(connection_allowed);
+                if let Some(key_proof) = key_proof {
+                    msg_typ.set_key_proof(encode_key_proof(key_proof));
+                }
                 let mut proto_msg = gatekeeper_proto::Message::new();
                 proto_msg.set_permit_reply(msg_typ);
                 proto_msg
@@ -189,7 +200,12 @@ impl Decoder for GatekeeperCodec {
                 } else {
                     None
                 };
-                Ok(Some(GatekeeperMessage::UnlockRequest { proof }))
+                let key_proof = if unlock_request_msg.has_key_proof() {
+                    Some(decode_key_proof(unlock_request_msg.get_key_proof())?)
+                } else {
+                    None
+                };
+                Ok(Some(GatekeeperMessage::UnlockRequest { proof, key_proof }))
             }
             Some(Message_oneof_typ::challenge_reply(reply_msg)) => {
                 Ok(Some(GatekeeperMessage::ChallengeReply {
@@ -198,8 +214,14 @@ impl Decoder for GatekeeperCodec {
                 }))
             }
             Some(Message_oneof_typ::permit_reply(reply_msg)) => {
+                let key_proof = if reply_msg.has_key_proof() {
+                    Some(decode_key_proof(reply_msg.get_key_proof())?)
+                } else {
+                    None
+                };
                 Ok(Some(GatekeeperMessage::PermitReply {
                     connection_allowed: reply_msg.get_connection_allowed(),
+                    key_proof,
                 }))
             }
             None => {
@@ -212,6 +234,30 @@ impl Decoder for GatekeeperCodec {
     }
 }
 
+fn encode_key_proof(key_proof: KeyProof) -> gatekeeper_proto::KeyProof {
+    let mut key_proof_proto = gatekeeper_proto::KeyProof::new();
+    key_proof_proto.set_node_id(key_proof.node_id.to_bytes().to_vec());
+    key_proof_proto.set_signature(key_proof.signature.to_bytes().to_vec());
+    key_proof_proto
+}
+
+fn decode_key_proof(key_proof_msg: &gatekeeper_proto::KeyProof) -> Result<KeyProof, io::Error> {
+    let node_id = pbc::PublicKey::try_from_bytes(key_proof_msg.get_node_id()).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "bad protobuf encoding, failed to decode node_id",
+        )
+    })?;
+    let signature =
+        pbc::Signature::try_from_bytes(key_proof_msg.get_signature()).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "bad protobuf encoding, failed to decode signature",
+            )
+        })?;
+    Ok(KeyProof { node_id, signature })
+}
+
 /// Structs
 /// VDF solution proof
 #[derive(Debug, Clone, PartialEq)]
@@ -221,24 +267,76 @@ pub struct VDFProof {
     pub proof: Vec<u8>,
 }
 
+/// Proof that a peer owns its network key.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyProof {
+    pub node_id: pbc::PublicKey,
+    pub signature: pbc::Signature,
+}
+
+/// The signature covers both ends of the connection, so it can't be replayed to other peers.
+fn key_proof_hash(signer: &PeerId, recipient: &PeerId) -> Hash {
+    let mut hasher = Hasher::new();
+    "gatekeeper".hash(&mut hasher);
+    signer.as_bytes().hash(&mut hasher);
+    recipient.as_bytes().hash(&mut hasher);
+    hasher.result()
+}
+
+impl KeyProof {
+    /// Signs our network key for the connection from `signer` to `recipient`.
+    pub fn sign(
+        signer: &PeerId,
+        recipient: &PeerId,
+        network_pkey: &pbc::PublicKey,
+        network_skey: &pbc::SecretKey,
+    ) -> KeyProof {
+        let hash = key_proof_hash(signer, recipient);
+        KeyProof {
+            node_id: network_pkey.clone(),
+            signature: pbc::sign_hash(&hash, network_skey),
+        }
+    }
+
+    /// Checks that the network key was signed by `signer` for `recipient`.
+    pub fn verify(&self, signer: &PeerId, recipient: &PeerId) -> bool {
+        let hash = key_proof_hash(signer, recipient);
+        pbc::check_hash(&hash, &self.signature, &self.node_id).is_ok()
+    }
+}
+
 /// Message that we can send to a peer or received from a peer.
 #[derive(Debug, Clone, PartialEq)]
 pub enum GatekeeperMessage {
-    UnlockRequest { proof: Option<VDFProof> },
-    ChallengeReply { challenge: Vec<u8>, difficulty: u64 },
-    PermitReply { connection_allowed: bool },
+    UnlockRequest {
+        proof: Option<VDFProof>,
+        key_proof: Option<KeyProof>,
+    },
+    ChallengeReply {
+        challenge: Vec<u8>,
+        difficulty: u64,
+    },
+    PermitReply {
+        connection_allowed: bool,
+        key_proof: Option<KeyProof>,
+    },
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{GatekeeperCodec, GatekeeperMessage, VDFProof};
+    use super::{GatekeeperCodec, GatekeeperMessage, KeyProof, VDFProof};
     use futures::{future, Future, Sink, Stream};
+    use libp2p_core::PeerId;
+    use stegos_crypto::pbc;
     use tokio::codec::Framed;
     use tokio::net::{TcpListener, TcpStream};
 
     #[test]
     fn correct_transfer() {
-        let unlock_request_null = GatekeeperMessage::UnlockRequest { proof: None };
+        let unlock_request_null = GatekeeperMessage::UnlockRequest {
+            proof: None,
+            key_proof: None,
+        };
         test_one(unlock_request_null);
 
         let proof = VDFProof {
@@ -246,9 +344,20 @@ mod tests {
             difficulty: rand::random::<u64>(),
             proof: rand::random::<[u8; 20]>().to_vec(),
         };
-        let unlock_request_proof = GatekeeperMessage::UnlockRequest { proof: Some(proof) };
+        let unlock_request_proof = GatekeeperMessage::UnlockRequest {
+            proof: Some(proof),
+            key_proof: None,
+        };
         test_one(unlock_request_proof);
 
+        let (skey, pkey) = pbc::make_random_keys();
+        let key_proof = KeyProof::sign(&PeerId::random(), &PeerId::random(), &pkey, &skey);
+        let unlock_request_key_proof = GatekeeperMessage::UnlockRequest {
+            proof: None,
+            key_proof: Some(key_proof),
+        };
+        test_one(unlock_request_key_proof);
+
         let challenge_reply = GatekeeperMessage::ChallengeReply {
             challenge: random_vec(256),
             difficulty: 16,
@@ -257,8 +366,37 @@ mod tests {
 
         let permit_reply = GatekeeperMessage::PermitReply {
             connection_allowed: false,
+            key_proof: None,
         };
         test_one(permit_reply);
+
+        let key_proof = KeyProof::sign(&PeerId::random(), &PeerId::random(), &pkey, &skey);
+        let permit_reply_key_proof = GatekeeperMessage::PermitReply {
+            connection_allowed: true,
+            key_proof: Some(key_proof),
+        };
+        test_one(permit_reply_key_proof);
+    }
+
+    #[test]
+    fn key_proof() {
+        let (skey, pkey) = pbc::make_random_keys();
+        let signer = PeerId::random();
+        let recipient = PeerId::random();
+        let key_proof = KeyProof::sign(&signer, &recipient, &pkey, &skey);
+        assert!(key_proof.verify(&signer, &recipient));
+
+        // Replayed to another peer.
+        assert!(!key_proof.verify(&signer, &PeerId::random()));
+        // Replayed by another peer.
+        assert!(!key_proof.verify(&PeerId::random(), &recipient));
+        // Reflected back to the signer.
+        assert!(!key_proof.verify(&recipient, &signer));
+        // Claimed for another key.
+        let (_skey2, pkey2) = pbc::make_random_keys();
+        let mut forged = key_proof.clone();
+        forged.node_id = pkey2;
+        assert!(!forged.verify(&signer, &recipient));
     }
 
     fn test_one(msg: GatekeeperMessage) {
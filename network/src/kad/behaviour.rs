@@ -60,6 +60,9 @@ pub struct Kademlia<TSubstream> {
     /// Mapping PeerId -> pbc::PublicKey (we use Vec<u8> here, 'cause PeerId doesn't implement Ord)
     known_peers: LruCache<Vec<u8>, pbc::PublicKey>,
 
+    /// Nodes which asked not to be advertised, including ourselves if hidden.
+    hidden_nodes: FnvHashSet<pbc::PublicKey>,
+
     /// All the iterative queries we are currently performing, with their ID. The last parameter
     /// is the list of accumulated providers for `GET_PROVIDERS` queries.
     active_queries: FnvHashMap<QueryId, (QueryState, QueryPurpose, Vec<pbc::PublicKey>)>,
@@ -183,9 +186,31 @@ impl<TSubstream> Kademlia<TSubstream> {
     /// Change node's id (pbc::PublicKey)
     pub fn change_id(&mut self, new_id: pbc::PublicKey) {
         self.kbuckets = self.kbuckets.new_table(new_id.clone());
+        if self.hidden_nodes.remove(&self.my_id) {
+            self.hidden_nodes.insert(new_id.clone());
+        }
         self.my_id = new_id;
     }
 
+    /// Excludes the node from responses to other nodes, or includes it back.
+    pub fn set_hidden(&mut self, node_id: &pbc::PublicKey, hidden: bool) {
+        if hidden {
+            self.hidden_nodes.insert(node_id.clone());
+        } else {
+            self.hidden_nodes.remove(node_id);
+        }
+    }
+
+    /// Returns the closest nodes which can be advertised to other nodes.
+    fn find_closest_advertised(&mut self, key: &Multihash) -> Vec<pbc::PublicKey> {
+        let hidden_nodes = &self.hidden_nodes;
+        self.kbuckets
+            .find_closest_with_self(key)
+            .filter(|node_id| !hidden_nodes.contains(node_id))
+            .take(self.num_results)
+            .collect()
+    }
+
     #[inline]
     pub fn find_closest(&mut self, id: &pbc::PublicKey) -> VecIntoIter<pbc::PublicKey> {
         self.kbuckets.find_closest(id)
@@ -239,6 +264,7 @@ impl<TSubstream> Kademlia<TSubstream> {
                 Duration::from_secs(BUCKET_EXPIRATION_PERIOD),
             ),
             known_peers: LruCache::<Vec<u8>, pbc::PublicKey>::with_capacity(512 * (20 + 1)), // Total size of kBucketsTable
+            hidden_nodes: FnvHashSet::default(),
             queued_events: SmallVec::new(),
             queries_to_starts: SmallVec::new(),
             active_queries: Default::default(),
@@ -287,9 +313,8 @@ impl<TSubstream> Kademlia<TSubstream> {
         match query {
             QueryTarget::FindPeer(key) => {
                 let closer_peers = self
-                    .kbuckets
-                    .find_closest_with_self(&key)
-                    .take(self.num_results)
+                    .find_closest_advertised(&key)
+                    .into_iter()
                     .map(|node_id| build_kad_peer(node_id, parameters, &self.kbuckets))
                     .collect();
                 trace!(target: "stegos_network::kad", "sending FindNodeRes with: {:#?}", closer_peers);
@@ -300,17 +325,18 @@ impl<TSubstream> Kademlia<TSubstream> {
             }
             QueryTarget::GetProviders(key) => {
                 let closer_peers = self
-                    .kbuckets
-                    .find_closest_with_self(&key)
-                    .take(self.num_results)
+                    .find_closest_advertised(&key)
+                    .into_iter()
                     .map(|node_id| build_kad_peer(node_id, parameters, &self.kbuckets))
                     .collect();
 
+                let hidden_nodes = &self.hidden_nodes;
                 let provider_peers = self
                     .values_providers
                     .get(&key)
                     .into_iter()
                     .flat_map(|peers| peers)
+                    .filter(|node_id| !hidden_nodes.contains(*node_id))
                     .map(|node_id| build_kad_peer(node_id.clone(), parameters, &self.kbuckets))
                     .collect();
 
@@ -984,3 +1010,33 @@ fn build_kad_peer(
         connection_ty,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hidden_nodes() {
+        let (_, my_id) = pbc::make_random_keys();
+        let (_, public_id) = pbc::make_random_keys();
+        let (_, hidden_id) = pbc::make_random_keys();
+        let mut kademlia = Kademlia::<()>::without_init(my_id.clone());
+        kademlia.kbuckets.entry_mut(&public_id);
+        kademlia.kbuckets.entry_mut(&hidden_id);
+        let key = public_id.clone().into_multihash();
+
+        let closest = kademlia.find_closest_advertised(&key);
+        assert_eq!(closest.len(), 3);
+
+        kademlia.set_hidden(&hidden_id, true);
+        kademlia.set_hidden(&my_id, true);
+        let closest = kademlia.find_closest_advertised(&key);
+        assert_eq!(closest, vec![public_id.clone()]);
+
+        kademlia.set_hidden(&hidden_id, false);
+        let closest = kademlia.find_closest_advertised(&key);
+        assert_eq!(closest.len(), 2);
+        assert!(closest.contains(&hidden_id));
+        assert!(!closest.contains(&my_id));
+    }
+}
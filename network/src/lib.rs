@@ -24,6 +24,8 @@
 #![allow(dead_code)]
 #![deny(warnings)]
 
+mod ack;
+pub mod capture;
 mod config;
 mod delivery;
 mod discovery;
@@ -32,9 +34,11 @@ mod kad;
 mod libp2p_network;
 pub mod loopback;
 mod metrics;
+mod nat;
 mod ncp;
 mod pubsub;
 mod replication;
+mod reputation;
 mod utils;
 
 use failure::{Error, Fail};
@@ -42,6 +46,7 @@ use futures::sync::{mpsc, oneshot};
 use std::fmt;
 use stegos_crypto::pbc;
 
+pub use self::ack::DeliveryStatus;
 pub use self::config::*;
 pub use self::kad::KBucketsPeerId;
 pub use self::libp2p_network::Libp2pNetwork;
@@ -52,7 +57,8 @@ pub use self::libp2p_network::NETWORK_READY_TOKEN;
 pub use self::libp2p_network::NETWORK_STATUS_TOPIC;
 pub use self::ncp::NodeInfo;
 pub use self::replication::ReplicationEvent;
-pub use self::utils::IntoMultihash;
+pub use self::reputation::{PeerOffense, PeerScoreInfo};
+pub use self::utils::{parse_endpoint, IntoMultihash};
 
 pub type Network = Box<dyn NetworkProvider + Send>;
 
@@ -63,6 +69,12 @@ where
     /// Subscribe to topic, returns Stream<Vec<u8>> of messages incoming to topic
     fn subscribe(&self, topic: &str) -> Result<mpsc::UnboundedReceiver<Vec<u8>>, Error>;
 
+    /// Subscribe to topic, returns Stream<BroadcastMessage> with the verified origin of signed messages
+    fn subscribe_with_origin(
+        &self,
+        topic: &str,
+    ) -> Result<mpsc::UnboundedReceiver<BroadcastMessage>, Error>;
+
     /// Published message to topic
     fn publish(&self, topic: &str, data: Vec<u8>) -> Result<(), Error>;
 
@@ -75,6 +87,15 @@ where
     /// Send unicast message to peer identified by network public key
     fn send(&self, dest: pbc::PublicKey, protocol_id: &str, data: Vec<u8>) -> Result<(), Error>;
 
+    /// Send unicast message and wait for acknowledgement from the recipient.
+    /// Unacknowledged messages are resent via alternative routes up to network.unicast_max_retries times.
+    fn send_with_ack(
+        &self,
+        dest: pbc::PublicKey,
+        protocol_id: &str,
+        data: Vec<u8>,
+    ) -> Result<oneshot::Receiver<DeliveryStatus>, Error>;
+
     /// Connect to a replication upstream.
     fn replication_connect(&self, peer_id: PeerId) -> Result<(), Error>;
 
@@ -83,6 +104,17 @@ where
     /// Request list of connected nodes
     fn list_connected_nodes(&self) -> Result<oneshot::Receiver<NetworkResponse>, Error>;
 
+    /// Penalize the peer which has forwarded an invalid broadcast message to us.
+    /// The forwarding peer is never graylisted or banned for relayed messages.
+    fn report_message(&self, topic: &str, data: Vec<u8>, offense: PeerOffense)
+        -> Result<(), Error>;
+
+    /// Penalize the peer
+    fn report_peer(&self, peer_id: PeerId, offense: PeerOffense) -> Result<(), Error>;
+
+    /// Request scores of misbehaving and banned peers
+    fn peer_scores(&self) -> Result<oneshot::Receiver<NetworkResponse>, Error>;
+
     /// Helper for cloning boxed object
     fn box_clone(&self) -> Network;
 
@@ -94,6 +126,13 @@ where
     ) -> Result<(), Error>;
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BroadcastMessage {
+    /// Network key of the publisher, if the message was signed.
+    pub from: Option<pbc::PublicKey>,
+    pub data: Vec<u8>,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct UnicastMessage {
     pub from: pbc::PublicKey,
@@ -103,6 +142,7 @@ pub struct UnicastMessage {
 #[derive(Debug, Clone)]
 pub enum NetworkResponse {
     ConnectedNodes { nodes: Vec<NodeInfo> },
+    PeerScores { peers: Vec<PeerScoreInfo> },
 }
 
 impl Clone for Network {
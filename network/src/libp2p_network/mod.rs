@@ -27,18 +27,15 @@ use futures::sync::{mpsc, oneshot};
 use libp2p;
 pub use libp2p_core::multiaddr::Multiaddr;
 pub use libp2p_core::PeerId;
-use libp2p_core::{identity, identity::ed25519, transport::TransportError, Transport};
+use libp2p_core::{identity, identity::ed25519};
 use libp2p_core_derive::NetworkBehaviour;
-use libp2p_dns as dns;
-use libp2p_secio as secio;
 use libp2p_swarm::{NetworkBehaviourEventProcess, Swarm};
-use libp2p_tcp as tcp;
 use log::*;
+use lru_time_cache::LruCache;
 use protobuf::Message as ProtoMessage;
 use smallvec::SmallVec;
 use std::collections::{HashMap, HashSet};
-use std::error;
-use std::io;
+use std::convert::TryFrom;
 use std::net::{SocketAddr, SocketAddrV4};
 use std::time::Duration;
 use stegos_crypto::hash::{Hashable, Hasher};
@@ -46,18 +43,24 @@ use stegos_crypto::pbc;
 use stegos_crypto::utils::u8v_to_hexstr;
 use tokio::io::{AsyncRead, AsyncWrite};
 
-use crate::config::NetworkConfig;
+use crate::ack::{AckEvent, DeliveryStatus, UnicastAcks, ACK_PROTOCOL_ID};
+use crate::capture::{Capture, Direction, MessageKind};
+use crate::config::{NetworkConfig, PubsubProtocol};
 use crate::delivery::{Delivery, DeliveryEvent, DeliveryMessage};
 use crate::discovery::{Discovery, DiscoveryOutEvent};
 use crate::gatekeeper::{Gatekeeper, GatekeeperOutEvent, PeerEvent};
+use crate::nat::{Nat, NatEvent, DIAL_BACK_PROTOCOL_ID};
 use crate::ncp::{Ncp, NcpOutEvent};
-use crate::pubsub::{Floodsub, FloodsubEvent};
+use crate::pubsub::{Floodsub, FloodsubEvent, FloodsubMessage, Gossipsub, MessageOrigin};
 use crate::replication::{Replication, ReplicationEvent};
-use crate::{Network, NetworkProvider, NetworkResponse, UnicastMessage};
+use crate::reputation::{PeerOffense, Reputation, ReputationEvent};
+use crate::{BroadcastMessage, Network, NetworkProvider, NetworkResponse, UnicastMessage};
 
 mod proto;
+mod transport;
 use self::proto::unicast_proto;
-use crate::utils::socket_to_multi_addr;
+use self::transport::build_transport;
+use crate::utils::parse_endpoint;
 use std::str::FromStr;
 use trust_dns_resolver::config::{NameServerConfig, Protocol};
 
@@ -74,6 +77,13 @@ pub const NETWORK_READY_TOKEN: &'static [u8] = &[1, 0, 0, 0];
 
 const IBE_ID: &'static [u8] = &[105u8, 13, 185, 148, 68, 76, 69, 155];
 
+// How long to remember peers which forwarded broadcast messages to us
+const MESSAGE_SOURCES_TTL: Duration = Duration::from_secs(5 * 60);
+const MESSAGE_SOURCES_CAPACITY: usize = 100_000;
+
+// Don't ask the same node behind NAT to connect to us, or honor its requests, more often
+const DIAL_BACK_INTERVAL: Duration = Duration::from_secs(60);
+
 impl Libp2pNetwork {
     pub fn new(
         mut config: NetworkConfig,
@@ -110,6 +120,17 @@ impl NetworkProvider for Libp2pNetwork {
         Ok(rx)
     }
 
+    fn subscribe_with_origin(
+        &self,
+        topic: &str,
+    ) -> Result<mpsc::UnboundedReceiver<BroadcastMessage>, Error> {
+        let topic: String = topic.clone().into();
+        let (tx, rx) = mpsc::unbounded();
+        let msg = ControlMessage::SubscribeWithOrigin { topic, handler: tx };
+        self.control_tx.unbounded_send(msg)?;
+        Ok(rx)
+    }
+
     /// Published message to topic
     fn publish(&self, topic: &str, data: Vec<u8>) -> Result<(), Error> {
         let topic: String = topic.clone().into();
@@ -148,6 +169,25 @@ impl NetworkProvider for Libp2pNetwork {
         Ok(())
     }
 
+    // Send direct message to public key and wait for acknowledgement
+    fn send_with_ack(
+        &self,
+        to: pbc::PublicKey,
+        protocol_id: &str,
+        data: Vec<u8>,
+    ) -> Result<oneshot::Receiver<DeliveryStatus>, Error> {
+        let protocol_id: String = protocol_id.clone().into();
+        let (tx, rx) = oneshot::channel::<DeliveryStatus>();
+        let msg = ControlMessage::SendUnicastWithAck {
+            to,
+            protocol_id,
+            data,
+            tx,
+        };
+        self.control_tx.unbounded_send(msg)?;
+        Ok(rx)
+    }
+
     fn replication_connect(&self, peer_id: PeerId) -> Result<(), Error> {
         let msg = ControlMessage::EnableReplicationUpstream { peer_id };
         self.control_tx.unbounded_send(msg)?;
@@ -167,6 +207,33 @@ impl NetworkProvider for Libp2pNetwork {
         Ok(rx)
     }
 
+    fn report_message(
+        &self,
+        topic: &str,
+        data: Vec<u8>,
+        offense: PeerOffense,
+    ) -> Result<(), Error> {
+        let msg = ControlMessage::ReportMessage {
+            digest: FloodsubMessage::content_digest(topic, &data),
+            offense,
+        };
+        self.control_tx.unbounded_send(msg)?;
+        Ok(())
+    }
+
+    fn report_peer(&self, peer_id: PeerId, offense: PeerOffense) -> Result<(), Error> {
+        let msg = ControlMessage::ReportPeer { peer_id, offense };
+        self.control_tx.unbounded_send(msg)?;
+        Ok(())
+    }
+
+    fn peer_scores(&self) -> Result<oneshot::Receiver<NetworkResponse>, Error> {
+        let (tx, rx) = oneshot::channel::<NetworkResponse>();
+        self.control_tx
+            .unbounded_send(ControlMessage::PeerScoresRequest { tx })?;
+        Ok(rx)
+    }
+
     // Clone self as a box
     fn box_clone(&self) -> Network {
         Box::new((*self).clone())
@@ -202,19 +269,20 @@ fn new_service(
     let local_pub_key = local_key.public();
     let peer_id = local_pub_key.clone().into_peer_id();
 
-    // Set up a an encrypted DNS-enabled TCP Transport over the Mplex and Yamux protocols
-    let transport = build_tcp_ws_secio_yamux(local_key);
+    // Set up a an encrypted DNS-enabled TCP/WebSocket Transport over the Mplex protocol
+    let transport = build_transport(local_key, &config.transport)?;
 
     // Create a Swarm to manage peers and events
     let (behaviour, replication_rx) =
-        Libp2pBehaviour::new(config, network_skey, network_pkey, peer_id.clone());
+        Libp2pBehaviour::new(config, network_skey, network_pkey, peer_id.clone())?;
 
     let mut swarm = Swarm::new(transport, behaviour, peer_id.clone());
 
     if config.endpoint != "" {
-        let endpoint = SocketAddr::from_str(&config.endpoint).expect("Invalid endpoint");
-        let endpoint = socket_to_multi_addr(&endpoint);
-        Swarm::listen_on(&mut swarm, endpoint).unwrap();
+        let endpoint = parse_endpoint(&config.endpoint, &config.transport)
+            .map_err(|e| format_err!("Invalid endpoint '{}': {}", config.endpoint, e))?;
+        Swarm::listen_on(&mut swarm, endpoint)
+            .map_err(|e| format_err!("Failed to listen on '{}': {:?}", config.endpoint, e))?;
     }
 
     let (control_tx, mut control_rx) = mpsc::unbounded::<ControlMessage>();
@@ -252,23 +320,45 @@ fn new_service(
 #[derive(NetworkBehaviour)]
 pub struct Libp2pBehaviour<TSubstream: AsyncRead + AsyncWrite> {
     floodsub: Floodsub<TSubstream>,
+    gossipsub: Gossipsub<TSubstream>,
     ncp: Ncp<TSubstream>,
     gatekeeper: Gatekeeper<TSubstream>,
     delivery: Delivery<TSubstream>,
     discovery: Discovery<TSubstream>,
     replication: Replication<TSubstream>,
+    reputation: Reputation<TSubstream>,
+    acks: UnicastAcks<TSubstream>,
+    nat: Nat<TSubstream>,
     #[behaviour(ignore)]
     consumers: HashMap<String, SmallVec<[mpsc::UnboundedSender<Vec<u8>>; 3]>>,
     #[behaviour(ignore)]
+    origin_consumers: HashMap<String, SmallVec<[mpsc::UnboundedSender<BroadcastMessage>; 3]>>,
+    #[behaviour(ignore)]
     unicast_consumers: HashMap<String, SmallVec<[mpsc::UnboundedSender<UnicastMessage>; 3]>>,
     #[behaviour(ignore)]
     replication_tx: mpsc::UnboundedSender<ReplicationEvent>,
     #[behaviour(ignore)]
+    my_peer_id: PeerId,
+    #[behaviour(ignore)]
     my_pkey: pbc::PublicKey,
     #[behaviour(ignore)]
     my_skey: pbc::SecretKey,
     #[behaviour(ignore)]
     connected_peers: HashSet<PeerId>,
+    #[behaviour(ignore)]
+    pubsub: PubsubProtocol,
+    #[behaviour(ignore)]
+    message_sources: LruCache<u64, PeerId>,
+    #[behaviour(ignore)]
+    sign_broadcasts: bool,
+    #[behaviour(ignore)]
+    broadcast_seq_no: u64,
+    #[behaviour(ignore)]
+    capture: Option<Capture>,
+    #[behaviour(ignore)]
+    dial_back_requests: LruCache<pbc::PublicKey, ()>,
+    #[behaviour(ignore)]
+    dial_backs: LruCache<pbc::PublicKey, ()>,
 }
 
 impl<TSubstream> Libp2pBehaviour<TSubstream>
@@ -280,7 +370,7 @@ where
         network_skey: pbc::SecretKey,
         network_pkey: pbc::PublicKey,
         peer_id: PeerId,
-    ) -> (Self, mpsc::UnboundedReceiver<ReplicationEvent>) {
+    ) -> Result<(Self, mpsc::UnboundedReceiver<ReplicationEvent>), Error> {
         let relaying = if config.advertised_endpoint == "".to_string() {
             false
         } else {
@@ -289,21 +379,58 @@ where
 
         let (replication_tx, replication_rx) = mpsc::unbounded::<ReplicationEvent>();
         let behaviour = Libp2pBehaviour {
-            floodsub: Floodsub::new(peer_id.clone(), relaying),
-            ncp: Ncp::new(config, network_pkey.clone()),
-            gatekeeper: Gatekeeper::new(config),
+            floodsub: Floodsub::new(
+                peer_id.clone(),
+                relaying,
+                config.peer_scoring.max_message_rate,
+                &config.bandwidth,
+                &config.signed_topics,
+            ),
+            gossipsub: Gossipsub::new(
+                peer_id.clone(),
+                relaying,
+                config.peer_scoring.max_message_rate,
+                &config.bandwidth,
+                &config.signed_topics,
+                config.gossipsub.clone(),
+            )?,
+            ncp: Ncp::new(config, network_pkey.clone(), network_skey.clone())?,
+            gatekeeper: Gatekeeper::new(
+                config,
+                peer_id.clone(),
+                network_pkey.clone(),
+                network_skey.clone(),
+            )?,
             delivery: Delivery::new(),
-            discovery: Discovery::new(network_pkey.clone()),
-            replication: Replication::new(),
+            discovery: Discovery::new(network_pkey.clone(), config.hidden),
+            replication: Replication::new(config.bandwidth.replication_rate),
+            reputation: Reputation::new(config),
+            acks: UnicastAcks::new(
+                Duration::from_secs(config.unicast_ack_timeout),
+                config.unicast_max_retries,
+            ),
+            nat: Nat::new(config)?,
             replication_tx,
             consumers: HashMap::new(),
+            origin_consumers: HashMap::new(),
             unicast_consumers: HashMap::new(),
+            my_peer_id: peer_id.clone(),
             my_pkey: network_pkey.clone(),
             my_skey: network_skey.clone(),
             connected_peers: HashSet::new(),
+            pubsub: config.pubsub,
+            message_sources: LruCache::with_expiry_duration_and_capacity(
+                MESSAGE_SOURCES_TTL,
+                MESSAGE_SOURCES_CAPACITY,
+            ),
+            sign_broadcasts: config.sign_broadcasts,
+            broadcast_seq_no: rand::random(),
+            capture: Capture::new(&config.capture),
+            dial_back_requests: LruCache::with_expiry_duration(DIAL_BACK_INTERVAL),
+            dial_backs: LruCache::with_expiry_duration(DIAL_BACK_INTERVAL),
         };
         debug!(target: "stegos_network::delivery", "Network endpoints: node_id={}, peer_id={}", network_pkey, peer_id);
-        (behaviour, replication_rx)
+        Ok((behaviour, replication_rx))
     }
 
     fn process_event(&mut self, msg: ControlMessage) {
@@ -315,7 +442,10 @@ where
                         .entry(topic.clone())
                         .or_insert(SmallVec::new())
                         .push(handler);
-                    self.floodsub.subscribe(topic);
+                    match self.pubsub {
+                        PubsubProtocol::Floodsub => self.floodsub.subscribe(topic),
+                        PubsubProtocol::Gossipsub => self.gossipsub.subscribe(topic),
+                    };
                     return;
                 }
                 if self.gatekeeper.is_network_ready() {
@@ -329,18 +459,50 @@ where
                     .or_insert(SmallVec::new())
                     .push(handler);
             }
+            ControlMessage::SubscribeWithOrigin { topic, handler } => {
+                self.origin_consumers
+                    .entry(topic.clone())
+                    .or_insert(SmallVec::new())
+                    .push(handler);
+                match self.pubsub {
+                    PubsubProtocol::Floodsub => self.floodsub.subscribe(topic),
+                    PubsubProtocol::Gossipsub => self.gossipsub.subscribe(topic),
+                };
+            }
             ControlMessage::Publish { topic, data } => {
                 debug!(target: "stegos_network::pubsub",
                     "Sending broadcast message: topic={}, size={}",
                     topic,
                     data.len(),
                 );
-                self.floodsub.publish(topic, data)
+                let origin = if self.sign_broadcasts {
+                    self.broadcast_seq_no = self.broadcast_seq_no.wrapping_add(1);
+                    let seq_no = self.broadcast_seq_no.to_be_bytes().to_vec();
+                    Some(MessageOrigin::sign(
+                        &topic,
+                        seq_no,
+                        &data,
+                        &self.my_pkey,
+                        &self.my_skey,
+                    ))
+                } else {
+                    None
+                };
+                if let Some(ref capture) = self.capture {
+                    capture.record(Direction::Sent, MessageKind::Broadcast, &topic, None, &data);
+                }
+                match self.pubsub {
+                    PubsubProtocol::Floodsub => self.floodsub.publish(topic, data, origin),
+                    PubsubProtocol::Gossipsub => self.gossipsub.publish(topic, data, origin),
+                }
             }
             ControlMessage::ChangeNetworkKeys { new_pkey, new_skey } => {
                 debug!(target: "stegos_network::libp2p_network","changing network key: from={}, to={}", self.my_pkey, new_pkey);
-                self.ncp.change_network_key(new_pkey.clone());
+                self.ncp
+                    .change_network_key(new_pkey.clone(), new_skey.clone());
                 self.discovery.change_network_key(new_pkey.clone());
+                self.gatekeeper
+                    .change_network_keys(new_pkey.clone(), new_skey.clone());
                 self.my_pkey = new_pkey;
                 self.my_skey = new_skey;
             }
@@ -358,43 +520,21 @@ where
                 protocol_id,
                 data,
             } => {
-                debug!(target: "stegos_network::delivery",
-                    "Sending unicast message: to={}, from={}, protocol={}, size={}",
-                    to,
-                    self.my_pkey,
-                    protocol_id,
-                    data.len(),
-                );
-
+                self.send_unicast(to, protocol_id, data, Vec::new());
+            }
+            ControlMessage::SendUnicastWithAck {
+                to,
+                protocol_id,
+                data,
+                tx,
+            } => {
                 if to == self.my_pkey {
-                    let msg = UnicastMessage {
-                        from: to.clone(),
-                        data,
-                    };
-                    self.unicast_consumers
-                        .entry(protocol_id)
-                        .or_insert(SmallVec::new())
-                        .retain({
-                            move |c| {
-                                if let Err(e) = c.unbounded_send(msg.clone()) {
-                                    error!("Error sending data to consumer: {}", e);
-                                    false
-                                } else {
-                                    true
-                                }
-                            }
-                        })
-                } else {
-                    let payload = UnicastPayload {
-                        from: self.my_pkey.clone(),
-                        to: to.clone(),
-                        protocol_id,
-                        data,
-                    };
-                    let msg = encode_unicast(payload, &self.my_skey);
-                    // self.floodsub.publish(floodsub_topic, msg);
-                    self.discovery.deliver_unicast(&to, msg);
+                    self.send_unicast(to, protocol_id, data, Vec::new());
+                    tx.send(DeliveryStatus::Delivered).ok();
+                    return;
                 }
+                let ack_id = self.acks.track(to, protocol_id.clone(), data.clone(), tx);
+                self.send_unicast(to, protocol_id, data, ack_id);
             }
             ControlMessage::EnableReplicationUpstream { peer_id } => {
                 self.replication.connect(peer_id);
@@ -408,9 +548,174 @@ where
                     warn!(target: "stegos_network", "Failed send API response for connected nodes");
                 }
             }
+            ControlMessage::ReportMessage { digest, offense } => {
+                match self.message_sources.get(&digest).cloned() {
+                    // The source might have only relayed the message.
+                    Some(peer_id) => self.reputation.report_relayed(&peer_id, offense),
+                    None => {
+                        debug!(target: "stegos_network::reputation", "source of reported message is unknown: offense={:?}", offense);
+                    }
+                }
+            }
+            ControlMessage::ReportPeer { peer_id, offense } => {
+                self.reputation.report(&peer_id, offense);
+            }
+            ControlMessage::PeerScoresRequest { tx } => {
+                let peers = self.reputation.scores();
+                if let Err(_v) = tx.send(NetworkResponse::PeerScores { peers }) {
+                    warn!(target: "stegos_network", "Failed send API response for peer scores");
+                }
+            }
+        }
+    }
+
+    fn send_unicast(
+        &mut self,
+        to: pbc::PublicKey,
+        protocol_id: String,
+        data: Vec<u8>,
+        ack_id: Vec<u8>,
+    ) {
+        debug!(target: "stegos_network::delivery",
+            "Sending unicast message: to={}, from={}, protocol={}, size={}",
+            to,
+            self.my_pkey,
+            protocol_id,
+            data.len(),
+        );
+        if let Some(ref capture) = self.capture {
+            capture.record(
+                Direction::Sent,
+                MessageKind::Unicast,
+                &protocol_id,
+                Some(to),
+                &data,
+            );
+        }
+
+        if to == self.my_pkey {
+            let msg = UnicastMessage {
+                from: to.clone(),
+                data,
+            };
+            self.deliver_to_consumers(protocol_id, msg);
+        } else {
+            let payload = UnicastPayload {
+                from: self.my_pkey.clone(),
+                to: to.clone(),
+                protocol_id,
+                data,
+                ack_id,
+            };
+            let msg = encode_unicast(payload, &self.my_skey);
+            self.discovery.deliver_unicast(&to, msg);
         }
     }
 
+    fn deliver_to_consumers(&mut self, protocol_id: String, msg: UnicastMessage) {
+        if let Some(ref capture) = self.capture {
+            capture.record(
+                Direction::Received,
+                MessageKind::Unicast,
+                &protocol_id,
+                Some(msg.from),
+                &msg.data,
+            );
+        }
+        self.unicast_consumers
+            .entry(protocol_id)
+            .or_insert(SmallVec::new())
+            .retain({
+                move |c| {
+                    if let Err(e) = c.unbounded_send(msg.clone()) {
+                        error!(target:"stegos_network::delivery", "Error sending data to consumer: {}", e);
+                        false
+                    } else {
+                        true
+                    }
+                }
+            })
+    }
+
+    fn dial_peer(&mut self, peer_id: PeerId) {
+        if self.reputation.is_banned(&peer_id) {
+            debug!(target: "stegos_network::reputation", "not dialing banned peer: peer_id={}", peer_id);
+            return;
+        }
+        if !self.gatekeeper.is_allowed(&peer_id) {
+            debug!(target: "stegos_network::gatekeeper", "not dialing peer outside of allowed list: peer_id={}", peer_id);
+            return;
+        }
+        // Nodes behind NAT can't be dialed, ask them to connect to us through their relays.
+        // Replication runs over established connections, so it works in both directions then.
+        if let Some(node_id) = self.discovery.relayed_node(&peer_id) {
+            self.request_dial_back(node_id);
+            return;
+        }
+        self.gatekeeper.dial_peer(peer_id);
+    }
+
+    fn request_dial_back(&mut self, node_id: pbc::PublicKey) {
+        let address = match self.ncp.our_address() {
+            Some(address) => address.clone(),
+            None => {
+                debug!(target: "stegos_network::nat", "can't connect to node behind NAT, we are not reachable too: node_id={}", node_id);
+                return;
+            }
+        };
+        if self.dial_back_requests.contains_key(&node_id) {
+            return;
+        }
+        self.dial_back_requests.insert(node_id.clone(), ());
+        debug!(target: "stegos_network::nat", "asking node behind NAT to connect: node_id={}, address={}", node_id, address);
+        let address = address.with(libp2p_core::multiaddr::Protocol::P2p(
+            self.my_peer_id.clone().into(),
+        ));
+        self.send_unicast(
+            node_id,
+            DIAL_BACK_PROTOCOL_ID.to_string(),
+            address.to_vec(),
+            Vec::new(),
+        );
+    }
+
+    fn dial_back(&mut self, node_id: pbc::PublicKey, data: Vec<u8>) {
+        let mut address = match Multiaddr::try_from(data) {
+            Ok(address) => address,
+            Err(e) => {
+                debug!(target: "stegos_network::nat", "invalid dial back request: node_id={}, error={}", node_id, e);
+                return;
+            }
+        };
+        let peer_id = match address.pop() {
+            Some(libp2p_core::multiaddr::Protocol::P2p(hash)) => match PeerId::from_multihash(hash)
+            {
+                Ok(peer_id) => peer_id,
+                Err(_) => {
+                    debug!(target: "stegos_network::nat", "invalid peer_id in dial back request: node_id={}", node_id);
+                    return;
+                }
+            },
+            _ => {
+                debug!(target: "stegos_network::nat", "missing peer_id in dial back request: node_id={}", node_id);
+                return;
+            }
+        };
+        if self.connected_peers.contains(&peer_id) || self.dial_backs.contains_key(&node_id) {
+            return;
+        }
+        self.dial_backs.insert(node_id.clone(), ());
+        debug!(target: "stegos_network::nat", "connecting back: node_id={}, peer_id={}, address={}", node_id, peer_id, address);
+        self.discovery.add_node(node_id.clone(), peer_id.clone());
+        self.discovery.set_peer_id(&node_id, peer_id.clone());
+        self.discovery.add_not_connected_address(&node_id, address);
+        if self.reputation.is_banned(&peer_id) || !self.gatekeeper.is_allowed(&peer_id) {
+            debug!(target: "stegos_network::nat", "not connecting back to banned or not allowed peer: peer_id={}", peer_id);
+            return;
+        }
+        self.gatekeeper.dial_peer(peer_id);
+    }
+
     fn shutdown(&mut self, peer_id: &PeerId) {
         self.ncp.terminate(peer_id.clone());
     }
@@ -426,7 +731,10 @@ where
                 self.gatekeeper.dial_address(address);
             }
             NcpOutEvent::DialPeer { peer_id } => {
-                self.gatekeeper.dial_peer(peer_id);
+                self.dial_peer(peer_id);
+            }
+            NcpOutEvent::ObservedAddress { peer_id, address } => {
+                self.nat.observed(peer_id, &address);
             }
             NcpOutEvent::Connected { peer_id } => {
                 self.connected_peers.insert(peer_id);
@@ -438,9 +746,15 @@ where
                 node_id,
                 peer_id,
                 addresses,
+                relays,
+                hidden,
             } => {
-                debug!(target: "stegos_network::discovery", "discovered node: node_id={}, peer_id={}", node_id, peer_id);
+                debug!(target: "stegos_network::discovery", "discovered node: node_id={}, peer_id={}, hidden={}", node_id, peer_id, hidden);
                 self.discovery.add_node(node_id.clone(), peer_id.clone());
+                self.discovery.set_hidden(&node_id, hidden);
+                if let Some(relays) = relays {
+                    self.discovery.set_relays(&node_id, relays);
+                }
                 if addresses.len() > 0 {
                     self.discovery.set_peer_id(&node_id, peer_id.clone());
                     if self.connected_peers.contains(&peer_id) {
@@ -459,25 +773,71 @@ where
     }
 }
 
+impl<TSubstream> NetworkBehaviourEventProcess<NatEvent> for Libp2pBehaviour<TSubstream>
+where
+    TSubstream: AsyncRead + AsyncWrite,
+{
+    fn inject_event(&mut self, event: NatEvent) {
+        match event {
+            NatEvent::ExternalAddress { address } => {
+                self.ncp.set_external_address(address);
+            }
+        }
+    }
+}
+
 impl<TSubstream> NetworkBehaviourEventProcess<FloodsubEvent> for Libp2pBehaviour<TSubstream>
 where
     TSubstream: AsyncRead + AsyncWrite,
 {
-    // Called when `floodsub` produces an event.
+    // Called when `floodsub` or `gossipsub` produces an event.
     // Send received message to consumers.
     fn inject_event(&mut self, message: FloodsubEvent) {
         match message {
-            FloodsubEvent::Message(message) => {
+            FloodsubEvent::Message {
+                source,
+                from,
+                message,
+            } => {
                 // ignore messages with NETWORK_STATUS_TOPIC
                 if message.topic == NETWORK_STATUS_TOPIC {
                     return;
                 }
+                // Envelopes are verified by pubsub before forwarding.
+                self.message_sources.insert(
+                    FloodsubMessage::content_digest(&message.topic, &message.data),
+                    source,
+                );
 
                 debug!(target: "stegos_network::pubsub",
-                       "Received broadcast message: topic={}, size={}",
+                       "Received broadcast message: topic={}, size={}, from={:?}",
                        &message.topic,
                        message.data.len(),
+                       from,
                 );
+                if let Some(ref capture) = self.capture {
+                    capture.record(
+                        Direction::Received,
+                        MessageKind::Broadcast,
+                        &message.topic,
+                        from,
+                        &message.data,
+                    );
+                }
+                if let Some(consumers) = self.origin_consumers.get_mut(&message.topic) {
+                    let message = BroadcastMessage {
+                        from,
+                        data: message.data.clone(),
+                    };
+                    consumers.retain(move |c| {
+                        if let Err(e) = c.unbounded_send(message.clone()) {
+                            error!(target: "stegos_network::pubsub", "Error sending data to consumer: {}", e);
+                            false
+                        } else {
+                            true
+                        }
+                    })
+                }
                 let consumers = self
                     .consumers
                     .entry(message.topic)
@@ -496,6 +856,36 @@ where
             }
             FloodsubEvent::Subscribed { .. } => {}
             FloodsubEvent::Unsubscribed { .. } => {}
+            FloodsubEvent::InvalidSignature { peer_id, .. } => {
+                self.reputation
+                    .report(&peer_id, PeerOffense::InvalidSignature);
+            }
+            FloodsubEvent::RateLimitExceeded { peer_id, .. } => {
+                self.reputation
+                    .report(&peer_id, PeerOffense::RateLimitExceeded);
+            }
+        }
+    }
+}
+
+impl<TSubstream> NetworkBehaviourEventProcess<ReputationEvent> for Libp2pBehaviour<TSubstream>
+where
+    TSubstream: AsyncRead + AsyncWrite,
+{
+    fn inject_event(&mut self, event: ReputationEvent) {
+        match event {
+            ReputationEvent::Graylisted { peer_id } => {
+                self.floodsub.graylist(&peer_id);
+                self.gossipsub.graylist(&peer_id);
+            }
+            ReputationEvent::Ungraylisted { peer_id } => {
+                self.floodsub.ungraylist(&peer_id);
+                self.gossipsub.ungraylist(&peer_id);
+            }
+            ReputationEvent::Banned { peer_id } => {
+                debug!(target: "stegos_network::reputation", "disconnecting banned peer: peer_id={}", peer_id);
+                self.ncp.terminate(peer_id);
+            }
         }
     }
 }
@@ -508,15 +898,21 @@ where
         match event {
             GatekeeperOutEvent::PrepareListener { peer_id } => {
                 self.floodsub.enable_incoming(&peer_id);
+                self.gossipsub.enable_incoming(&peer_id);
                 self.gatekeeper
                     .notify(PeerEvent::EnabledListener { peer_id });
             }
             GatekeeperOutEvent::PrepareDialer { peer_id } => {
                 self.floodsub.enable_outgoing(&peer_id);
+                self.gossipsub.enable_outgoing(&peer_id);
                 self.gatekeeper.notify(PeerEvent::EnabledDialer { peer_id });
             }
             GatekeeperOutEvent::Finished { peer_id } => {
                 self.floodsub.enable_outgoing(&peer_id);
+                self.gossipsub.enable_outgoing(&peer_id);
+            }
+            GatekeeperOutEvent::Rejected { peer_id } => {
+                self.ncp.terminate(peer_id);
             }
             GatekeeperOutEvent::NetworkReady => {
                 debug!(target: "stegos_network::gatekeeper", "network is ready");
@@ -541,7 +937,7 @@ where
         match event {
             DiscoveryOutEvent::DialPeer { peer_id } => {
                 debug!(target: "stegos_network::kad", "connecting to closest peer: {}", peer_id);
-                self.gatekeeper.dial_peer(peer_id);
+                self.dial_peer(peer_id);
             }
             DiscoveryOutEvent::Route { next_hop, message } => {
                 debug!(target: "stegos_network::delivery", "delivering paylod: node_id={}, peer_id={}", message.to, next_hop);
@@ -591,23 +987,32 @@ where
                                         payload.protocol_id,
                                         payload.data.len()
                                     );
+                                    if payload.protocol_id == ACK_PROTOCOL_ID {
+                                        self.acks.acknowledge(&payload.from, &payload.data);
+                                        return;
+                                    }
+                                    if payload.protocol_id == DIAL_BACK_PROTOCOL_ID {
+                                        self.dial_back(payload.from, payload.data);
+                                        return;
+                                    }
+                                    if !payload.ack_id.is_empty() {
+                                        // Confirm every copy, previous ACK could be lost
+                                        self.send_unicast(
+                                            payload.from,
+                                            ACK_PROTOCOL_ID.to_string(),
+                                            payload.ack_id.clone(),
+                                            Vec::new(),
+                                        );
+                                        if self.acks.is_duplicate(&payload.from, &payload.ack_id) {
+                                            debug!(target: "stegos_network::delivery", "got resent unicast message: from={}", payload.from);
+                                            return;
+                                        }
+                                    }
                                     let msg = UnicastMessage {
                                         from: payload.from,
                                         data: payload.data,
                                     };
-                                    self.unicast_consumers
-                                        .entry(payload.protocol_id)
-                                        .or_insert(SmallVec::new())
-                                        .retain({
-                                            move |c| {
-                                                if let Err(e) = c.unbounded_send(msg.clone()) {
-                                                    error!(target:"stegos_network::delivery", "Error sending data to consumer: {}", e);
-                                                    false
-                                                } else {
-                                                    true
-                                                }
-                                            }
-                                        })
+                                    self.deliver_to_consumers(payload.protocol_id, msg);
                                 }
                             }
                             Err(e) => {
@@ -626,6 +1031,39 @@ where
     }
 }
 
+impl<TSubstream> NetworkBehaviourEventProcess<AckEvent> for Libp2pBehaviour<TSubstream>
+where
+    TSubstream: AsyncRead + AsyncWrite,
+{
+    fn inject_event(&mut self, event: AckEvent) {
+        match event {
+            AckEvent::Resend {
+                to,
+                protocol_id,
+                data,
+                ack_id,
+                attempt,
+            } => {
+                debug!(target: "stegos_network::delivery",
+                    "Resending unicast message: to={}, protocol={}, attempt={}",
+                    to,
+                    protocol_id,
+                    attempt,
+                );
+                let payload = UnicastPayload {
+                    from: self.my_pkey.clone(),
+                    to: to.clone(),
+                    protocol_id,
+                    data,
+                    ack_id,
+                };
+                let msg = encode_unicast(payload, &self.my_skey);
+                self.discovery.redeliver_unicast(&to, msg);
+            }
+        }
+    }
+}
+
 impl<TSubstream> NetworkBehaviourEventProcess<ReplicationEvent> for Libp2pBehaviour<TSubstream>
 where
     TSubstream: AsyncRead + AsyncWrite,
@@ -644,6 +1082,10 @@ pub enum ControlMessage {
         topic: String,
         handler: mpsc::UnboundedSender<Vec<u8>>,
     },
+    SubscribeWithOrigin {
+        topic: String,
+        handler: mpsc::UnboundedSender<BroadcastMessage>,
+    },
     Publish {
         topic: String,
         data: Vec<u8>,
@@ -653,6 +1095,12 @@ pub enum ControlMessage {
         protocol_id: String,
         data: Vec<u8>,
     },
+    SendUnicastWithAck {
+        to: pbc::PublicKey,
+        protocol_id: String,
+        data: Vec<u8>,
+        tx: oneshot::Sender<DeliveryStatus>,
+    },
     SubscribeUnicast {
         protocol_id: String,
         consumer: mpsc::UnboundedSender<UnicastMessage>,
@@ -670,6 +1118,17 @@ pub enum ControlMessage {
     ConnectedNodesRequest {
         tx: oneshot::Sender<NetworkResponse>,
     },
+    ReportMessage {
+        digest: u64,
+        offense: PeerOffense,
+    },
+    ReportPeer {
+        peer_id: PeerId,
+        offense: PeerOffense,
+    },
+    PeerScoresRequest {
+        tx: oneshot::Sender<NetworkResponse>,
+    },
 }
 
 #[derive(Clone, Debug)]
@@ -678,6 +1137,8 @@ pub struct UnicastPayload {
     to: pbc::PublicKey,
     protocol_id: String,
     data: Vec<u8>,
+    /// Non-empty if the sender waits for acknowledgement.
+    ack_id: Vec<u8>,
 }
 
 // Encode unicast message
@@ -694,6 +1155,10 @@ fn encode_unicast(payload: UnicastPayload, sign_key: &pbc::SecretKey) -> Vec<u8>
     payload.protocol_id.hash(&mut hasher);
     enc_packet.rval().hash(&mut hasher);
     enc_packet.cmsg().hash(&mut hasher);
+    // Keep signatures of messages without ACK compatible with older nodes
+    if !payload.ack_id.is_empty() {
+        payload.ack_id.hash(&mut hasher);
+    }
     let hash = hasher.result();
     let sig = pbc::sign_hash(&hash, sign_key);
 
@@ -703,6 +1168,7 @@ fn encode_unicast(payload: UnicastPayload, sign_key: &pbc::SecretKey) -> Vec<u8>
     msg.set_to(payload.to.to_bytes().to_vec());
     msg.set_protocol_id(payload.protocol_id.into_bytes().to_vec());
     msg.set_signature(sig.to_bytes().to_vec());
+    msg.set_ack_id(payload.ack_id);
 
     msg.write_to_bytes()
         .expect("protobuf encoding should never fail")
@@ -718,12 +1184,14 @@ fn decode_unicast(input: Vec<u8>) -> Result<(UnicastPayload, pbc::Signature, pbc
     let protocol_id = String::from_utf8(protocol_id_bytes.to_vec())?;
     let data = msg.take_data().to_vec();
     let rval = pbc::RVal::try_from_bytes(&msg.take_rval().to_vec())?;
+    let ack_id = msg.take_ack_id().to_vec();
 
     let payload = UnicastPayload {
         from,
         to,
         protocol_id,
         data,
+        ack_id,
     };
 
     Ok((payload, signature, rval))
@@ -743,6 +1211,9 @@ fn decrypt_message(
     payload.protocol_id.hash(&mut hasher);
     rval.hash(&mut hasher);
     payload.data.hash(&mut hasher);
+    if !payload.ack_id.is_empty() {
+        payload.ack_id.hash(&mut hasher);
+    }
     let hash = hasher.result();
 
     if let Err(_e) = pbc::check_hash(&hash, &signature, &payload.from) {
@@ -758,85 +1229,6 @@ fn decrypt_message(
     }
 }
 
-/// Builds an implementation of `Transport` that is suitable for usage with the `Swarm`.
-///
-/// The implementation supports TCP/IP, WebSockets over TCP/IP, secio as the encryption layer,
-/// and mplex or yamux as the multiplexing layer.
-///
-/// > **Note**: If you ever need to express the type of this `Transport`.
-pub fn build_tcp_ws_secio_yamux(
-    keypair: identity::Keypair,
-) -> impl Transport<
-    Output = (
-        PeerId,
-        impl libp2p_core::muxing::StreamMuxer<
-                OutboundSubstream = impl Send,
-                Substream = impl Send,
-                Error = impl Into<io::Error>,
-            > + Send
-            + Sync,
-    ),
-    Error = impl error::Error + Send,
-    Listener = impl Send,
-    Dial = impl Send,
-    ListenerUpgrade = impl Send,
-> + Clone {
-    let mut mplex_config = libp2p_mplex::MplexConfig::new();
-    mplex_config.max_buffer_len_behaviour(libp2p_mplex::MaxBufferBehaviour::Block);
-
-    CommonTransport::new()
-        .upgrade(libp2p_core::upgrade::Version::V1)
-        .authenticate(secio::SecioConfig::new(keypair))
-        .multiplex(mplex_config)
-        .map(|(peer, muxer), _| (peer, libp2p_core::muxing::StreamMuxerBox::new(muxer)))
-        .timeout(Duration::from_secs(20))
-}
-
-/// Implementation of `Transport` that supports the most common protocols.
-///
-/// The list currently is TCP/IP, DNS, and WebSockets. However this list could change in the
-/// future to get new transports.
-#[derive(Debug, Clone)]
-struct CommonTransport {
-    // The actual implementation of everything.
-    inner: CommonTransportInner,
-}
-
-type InnerImplementation = dns::DnsConfig<tcp::TcpConfig>;
-
-#[derive(Debug, Clone)]
-struct CommonTransportInner {
-    inner: InnerImplementation,
-}
-
-impl CommonTransport {
-    /// Initializes the `CommonTransport`.
-    pub fn new() -> CommonTransport {
-        let tcp = tcp::TcpConfig::new().nodelay(true);
-        let transport = dns::DnsConfig::new(tcp);
-
-        CommonTransport {
-            inner: CommonTransportInner { inner: transport },
-        }
-    }
-}
-
-impl Transport for CommonTransport {
-    type Output = <InnerImplementation as Transport>::Output;
-    type Error = <InnerImplementation as Transport>::Error;
-    type Listener = <InnerImplementation as Transport>::Listener;
-    type ListenerUpgrade = <InnerImplementation as Transport>::ListenerUpgrade;
-    type Dial = <InnerImplementation as Transport>::Dial;
-
-    fn listen_on(self, addr: Multiaddr) -> Result<Self::Listener, TransportError<Self::Error>> {
-        self.inner.inner.listen_on(addr)
-    }
-
-    fn dial(self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
-        self.inner.inner.dial(addr)
-    }
-}
-
 fn resolve_seed_nodes(seed_pool: &str, dns_servers: &[String]) -> Result<Vec<String>, Error> {
     use trust_dns_resolver::{
         config::{ResolverConfig, ResolverOpts},
@@ -912,6 +1304,7 @@ mod tests {
             to,
             protocol_id,
             data,
+            ack_id: vec![1, 2, 3],
         };
 
         let encoded = super::encode_unicast(payload.clone(), &from_skey);
@@ -923,6 +1316,7 @@ mod tests {
         assert_eq!(payload.to, payload_2.to);
         assert_eq!(payload.protocol_id, payload_2.protocol_id);
         assert_eq!(payload.data, payload_2.data);
+        assert_eq!(payload.ack_id, payload_2.ack_id);
         assert_ne!(payload.data, enc_data);
     }
 
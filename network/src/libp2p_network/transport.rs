@@ -0,0 +1,281 @@
+//
+// MIT License
+//
+// Copyright (c) 2018-2019 Stegos AG
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//!
+//! Transport: TCP or WebSocket, secio or noise, mplex.
+//!
+
+use failure::{bail, format_err, Error};
+use libp2p_core::either::EitherOutput;
+use libp2p_core::multiaddr::Protocol;
+use libp2p_core::muxing::StreamMuxerBox;
+use libp2p_core::transport::{boxed::Boxed, OrTransport, TransportError};
+use libp2p_core::upgrade::{
+    self, InboundUpgrade, MapInboundUpgrade, MapOutboundUpgrade, Negotiated, OutboundUpgrade,
+    SelectUpgrade, UpgradeInfo,
+};
+use libp2p_core::{identity, Multiaddr, PeerId, Transport};
+use libp2p_dns as dns;
+use libp2p_noise as noise;
+use libp2p_secio as secio;
+use libp2p_tcp as tcp;
+use libp2p_websocket as websocket;
+use std::fs;
+use std::io;
+use std::time::Duration;
+
+use crate::config::{SecurityProtocol, TransportConfig, TransportProfile};
+
+/// Transport used by the `Swarm`.
+pub type BoxedTransport = Boxed<(PeerId, StreamMuxerBox), io::Error>;
+
+/// Builds an implementation of `Transport` that is suitable for usage with the `Swarm`.
+///
+/// The implementation supports TCP/IP and WebSockets over TCP/IP (optionally TLS-wrapped),
+/// secio and/or noise as the encryption layer, and mplex as the multiplexing layer.
+/// The transport is chosen by the multiaddr, the encryption is negotiated per connection.
+pub fn build_transport(
+    keypair: identity::Keypair,
+    config: &TransportConfig,
+) -> Result<BoxedTransport, Error> {
+    if config.security.is_empty() {
+        bail!("network.transport.security is empty");
+    }
+    let secio = if config.security.contains(&SecurityProtocol::Secio) {
+        Some(secio::SecioConfig::new(keypair.clone()))
+    } else {
+        None
+    };
+    let noise = if config.security.contains(&SecurityProtocol::Noise) {
+        let keys = noise::Keypair::<noise::X25519>::new()
+            .into_authentic(&keypair)
+            .map_err(|e| format_err!("Failed to create noise keys: {:?}", e))?;
+        Some(noise::NoiseConfig::xx(keys).into_authenticated())
+    } else {
+        None
+    };
+    // Noise is preferred if both are enabled.
+    let security = SelectUpgrade::new(Toggle(noise), Toggle(secio));
+    let security = MapInboundUpgrade::new(
+        MapOutboundUpgrade::new(security, select_output),
+        select_output,
+    );
+
+    let mut mplex_config = libp2p_mplex::MplexConfig::new();
+    mplex_config.max_buffer_len_behaviour(libp2p_mplex::MaxBufferBehaviour::Block);
+
+    let transport = CommonTransport::new(config)?
+        .upgrade(upgrade::Version::V1)
+        .authenticate(security)
+        .multiplex(mplex_config)
+        .map(|(peer, muxer), _| (peer, StreamMuxerBox::new(muxer)))
+        .timeout(Duration::from_secs(20))
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+        .boxed();
+    Ok(transport)
+}
+
+fn is_websocket(addr: &Multiaddr) -> bool {
+    addr.iter().any(|p| match p {
+        Protocol::Ws | Protocol::Wss => true,
+        _ => false,
+    })
+}
+
+/// Moves PeerId out of the output of either security upgrade.
+fn select_output<A, B>(
+    output: EitherOutput<(PeerId, A), (PeerId, B)>,
+) -> (PeerId, EitherOutput<A, B>) {
+    match output {
+        EitherOutput::First((peer_id, a)) => (peer_id, EitherOutput::First(a)),
+        EitherOutput::Second((peer_id, b)) => (peer_id, EitherOutput::Second(b)),
+    }
+}
+
+/// Upgrade which can be disabled in the config.
+/// A disabled upgrade advertises no protocols, so it is never negotiated.
+#[derive(Debug, Clone)]
+struct Toggle<U>(Option<U>);
+
+impl<U: UpgradeInfo> UpgradeInfo for Toggle<U> {
+    type Info = U::Info;
+    type InfoIter = std::vec::IntoIter<U::Info>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        match self.0 {
+            Some(ref upgrade) => upgrade
+                .protocol_info()
+                .into_iter()
+                .collect::<Vec<_>>()
+                .into_iter(),
+            None => Vec::new().into_iter(),
+        }
+    }
+}
+
+impl<C, U: InboundUpgrade<C>> InboundUpgrade<C> for Toggle<U> {
+    type Output = U::Output;
+    type Error = U::Error;
+    type Future = U::Future;
+
+    fn upgrade_inbound(self, socket: Negotiated<C>, info: Self::Info) -> Self::Future {
+        self.0
+            .expect("disabled upgrade has no protocols to negotiate")
+            .upgrade_inbound(socket, info)
+    }
+}
+
+impl<C, U: OutboundUpgrade<C>> OutboundUpgrade<C> for Toggle<U> {
+    type Output = U::Output;
+    type Error = U::Error;
+    type Future = U::Future;
+
+    fn upgrade_outbound(self, socket: Negotiated<C>, info: Self::Info) -> Self::Future {
+        self.0
+            .expect("disabled upgrade has no protocols to negotiate")
+            .upgrade_outbound(socket, info)
+    }
+}
+
+/// Implementation of `Transport` that supports the most common protocols.
+///
+/// The list currently is TCP/IP, DNS, and WebSockets. However this list could change in the
+/// future to get new transports.
+#[derive(Debug, Clone)]
+struct CommonTransport {
+    // The actual implementation of everything.
+    inner: CommonTransportInner,
+    // Refuse TCP addresses (for proxies and browsers).
+    websocket_only: bool,
+}
+
+type InnerImplementation =
+    dns::DnsConfig<OrTransport<websocket::WsConfig<tcp::TcpConfig>, tcp::TcpConfig>>;
+
+#[derive(Debug, Clone)]
+struct CommonTransportInner {
+    inner: InnerImplementation,
+}
+
+impl CommonTransport {
+    /// Initializes the `CommonTransport`.
+    pub fn new(config: &TransportConfig) -> Result<CommonTransport, Error> {
+        let tcp = tcp::TcpConfig::new().nodelay(true);
+        let mut ws = websocket::WsConfig::new(tcp.clone());
+        if config.tls_cert_file != "" {
+            ws.set_tls_config(load_tls_config(config)?);
+        }
+        let transport = dns::DnsConfig::new(ws.or_transport(tcp));
+
+        Ok(CommonTransport {
+            inner: CommonTransportInner { inner: transport },
+            websocket_only: config.profile == TransportProfile::Websocket,
+        })
+    }
+}
+
+impl Transport for CommonTransport {
+    type Output = <InnerImplementation as Transport>::Output;
+    type Error = <InnerImplementation as Transport>::Error;
+    type Listener = <InnerImplementation as Transport>::Listener;
+    type ListenerUpgrade = <InnerImplementation as Transport>::ListenerUpgrade;
+    type Dial = <InnerImplementation as Transport>::Dial;
+
+    fn listen_on(self, addr: Multiaddr) -> Result<Self::Listener, TransportError<Self::Error>> {
+        if self.websocket_only && !is_websocket(&addr) {
+            return Err(TransportError::MultiaddrNotSupported(addr));
+        }
+        self.inner.inner.listen_on(addr)
+    }
+
+    fn dial(self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+        if self.websocket_only && !is_websocket(&addr) {
+            return Err(TransportError::MultiaddrNotSupported(addr));
+        }
+        self.inner.inner.dial(addr)
+    }
+}
+
+/// Loads DER-encoded certificate and private key.
+fn load_tls_config(config: &TransportConfig) -> Result<websocket::tls::Config, Error> {
+    let cert = fs::read(&config.tls_cert_file).map_err(|e| {
+        format_err!(
+            "Failed to read network.transport.tls_cert_file '{}': {}",
+            config.tls_cert_file,
+            e
+        )
+    })?;
+    let key = fs::read(&config.tls_key_file).map_err(|e| {
+        format_err!(
+            "Failed to read network.transport.tls_key_file '{}': {}",
+            config.tls_key_file,
+            e
+        )
+    })?;
+    let tls = websocket::tls::Config::new(
+        websocket::tls::PrivateKey::new(key),
+        vec![websocket::tls::Certificate::new(cert)],
+    )
+    .map_err(|e| format_err!("Invalid TLS certificate: {}", e))?;
+    Ok(tls)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{endpoint_to_multi_addr, parse_endpoint};
+    use std::net::SocketAddr;
+
+    #[test]
+    fn websocket_addresses() {
+        let endpoint: SocketAddr = "1.2.3.4:10203".parse().unwrap();
+        let mut config = TransportConfig::default();
+        let tcp = endpoint_to_multi_addr(&endpoint, &config);
+        assert_eq!(tcp, "/ip4/1.2.3.4/tcp/10203".parse().unwrap());
+        assert!(!is_websocket(&tcp));
+
+        config.profile = TransportProfile::Websocket;
+        let ws = endpoint_to_multi_addr(&endpoint, &config);
+        assert_eq!(ws, "/ip4/1.2.3.4/tcp/10203/ws".parse().unwrap());
+        assert!(is_websocket(&ws));
+
+        config.tls_cert_file = "cert.der".to_string();
+        let wss = endpoint_to_multi_addr(&endpoint, &config);
+        assert_eq!(wss, "/ip4/1.2.3.4/tcp/10203/wss".parse().unwrap());
+        assert!(is_websocket(&wss));
+    }
+
+    #[test]
+    fn endpoints() {
+        let config = TransportConfig::default();
+        let tcp = parse_endpoint("1.2.3.4:10203", &config).unwrap();
+        assert_eq!(tcp, "/ip4/1.2.3.4/tcp/10203".parse().unwrap());
+        // A multiaddr selects the transport regardless of the profile.
+        let ws = parse_endpoint("/ip4/1.2.3.4/tcp/10203/ws", &config).unwrap();
+        assert!(is_websocket(&ws));
+        let dns = parse_endpoint("/dns4/seed.example.com/tcp/10203", &config).unwrap();
+        assert!(!is_websocket(&dns));
+        assert!(parse_endpoint("seed.example.com:10203", &config).is_err());
+        assert!(parse_endpoint("/ip4/1.2.3.4/tcp/x", &config).is_err());
+    }
+}
@@ -21,8 +21,12 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 #![allow(dead_code)]
+use crate::capture::{CaptureRecord, Direction, MessageKind};
 use crate::replication::ReplicationEvent;
-use crate::{Network, NetworkProvider, NetworkResponse, UnicastMessage};
+use crate::{
+    BroadcastMessage, DeliveryStatus, Network, NetworkProvider, NetworkResponse, PeerOffense,
+    UnicastMessage,
+};
 use failure::{format_err, Error};
 use futures::sync::{mpsc, oneshot};
 use libp2p_core::identity::ed25519;
@@ -54,6 +58,22 @@ impl NetworkProvider for LoopbackNetwork {
         Ok(rx)
     }
 
+    fn subscribe_with_origin(
+        &self,
+        stopic: &str,
+    ) -> Result<mpsc::UnboundedReceiver<BroadcastMessage>, Error> {
+        let topic: String = stopic.to_string();
+        let (tx, rx) = mpsc::unbounded::<BroadcastMessage>();
+        self.state
+            .lock()
+            .unwrap()
+            .origin_consumers
+            .entry(topic)
+            .or_default()
+            .push(tx);
+        Ok(rx)
+    }
+
     fn subscribe_unicast(
         &self,
         stopic: &str,
@@ -80,6 +100,18 @@ impl NetworkProvider for LoopbackNetwork {
         Ok(())
     }
 
+    fn send_with_ack(
+        &self,
+        to: pbc::PublicKey,
+        protocol_id: &str,
+        data: Vec<u8>,
+    ) -> Result<oneshot::Receiver<DeliveryStatus>, Error> {
+        self.send(to, protocol_id, data)?;
+        let (tx, rx) = oneshot::channel();
+        tx.send(DeliveryStatus::Delivered).ok();
+        Ok(rx)
+    }
+
     fn publish(&self, topic: &str, data: Vec<u8>) -> Result<(), Error> {
         trace!("Received publish for topic = {}", topic);
         let topic: String = topic.to_string();
@@ -105,6 +137,28 @@ impl NetworkProvider for LoopbackNetwork {
         }
     }
 
+    fn report_message(
+        &self,
+        _topic: &str,
+        _data: Vec<u8>,
+        _offense: PeerOffense,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn report_peer(&self, _peer_id: PeerId, _offense: PeerOffense) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn peer_scores(&self) -> Result<oneshot::Receiver<NetworkResponse>, Error> {
+        let (tx, rx) = oneshot::channel::<NetworkResponse>();
+        if let Err(_v) = tx.send(NetworkResponse::PeerScores { peers: vec![] }) {
+            Err(format_err!("Failed to send reply to oneshot channel"))
+        } else {
+            Ok(rx)
+        }
+    }
+
     fn change_network_keys(
         &self,
         _new_pkey: pbc::PublicKey,
@@ -122,6 +176,7 @@ impl NetworkProvider for LoopbackNetwork {
 #[derive(Debug, Clone)]
 struct LoopbackState {
     consumers: HashMap<String, Vec<mpsc::UnboundedSender<Vec<u8>>>>,
+    origin_consumers: HashMap<String, Vec<mpsc::UnboundedSender<BroadcastMessage>>>,
     unicast_consumers: HashMap<String, Vec<mpsc::UnboundedSender<UnicastMessage>>>,
     queue: VecDeque<MessageFromNode>,
     replication_tx: mpsc::UnboundedSender<ReplicationEvent>,
@@ -144,11 +199,13 @@ impl Loopback {
         let local_pub_key = local_key.public();
         let peer_id = local_pub_key.clone().into_peer_id();
         let consumers = HashMap::new();
+        let origin_consumers = HashMap::new();
         let unicast_consumers = HashMap::new();
         let (replication_tx, replication_rx) = mpsc::unbounded::<ReplicationEvent>();
         let queue = VecDeque::new();
         let state = LoopbackState {
             consumers,
+            origin_consumers,
             unicast_consumers,
             replication_tx,
             queue,
@@ -302,13 +359,27 @@ impl Loopback {
     }
 
     pub fn receive_broadcast_raw(&mut self, topic: &str, data: Vec<u8>) {
-        let ref mut state = self.state.lock().unwrap();
-        let ref mut nodes = state
-            .consumers
-            .get_mut(topic)
-            .expect("Node didn't subscribe to broadcast");
+        self.receive_signed_broadcast_raw(None, topic, data)
+    }
 
-        nodes.retain(move |tx| tx.unbounded_send(data.clone()).is_ok());
+    pub fn receive_signed_broadcast_raw(
+        &mut self,
+        from: Option<pbc::PublicKey>,
+        topic: &str,
+        data: Vec<u8>,
+    ) {
+        let ref mut state = self.state.lock().unwrap();
+        assert!(
+            state.consumers.contains_key(topic) || state.origin_consumers.contains_key(topic),
+            "Node didn't subscribe to broadcast"
+        );
+        if let Some(nodes) = state.consumers.get_mut(topic) {
+            nodes.retain(|tx| tx.unbounded_send(data.clone()).is_ok());
+        }
+        if let Some(nodes) = state.origin_consumers.get_mut(topic) {
+            let message = BroadcastMessage { from, data };
+            nodes.retain(move |tx| tx.unbounded_send(message.clone()).is_ok());
+        }
     }
 
     pub fn receive_broadcast<M: ProtoConvert>(&mut self, topic: &str, msg: M) {
@@ -328,6 +399,42 @@ impl Loopback {
     pub fn receive_unicast<M: ProtoConvert>(&mut self, peer: pbc::PublicKey, topic: &str, msg: M) {
         self.receive_unicast_raw(peer, topic, msg.into_buffer().unwrap());
     }
+
+    /// Feeds a captured received message to subscribers.
+    /// Returns false if the message was sent by the node or nobody is subscribed to its topic.
+    pub fn replay(&mut self, record: &CaptureRecord) -> bool {
+        if record.direction != Direction::Received {
+            return false;
+        }
+        let subscribed = {
+            let state = self.state.lock().unwrap();
+            match record.kind {
+                MessageKind::Unicast => state.unicast_consumers.contains_key(&record.topic),
+                MessageKind::Broadcast => {
+                    state.consumers.contains_key(&record.topic)
+                        || state.origin_consumers.contains_key(&record.topic)
+                }
+            }
+        };
+        if !subscribed {
+            return false;
+        }
+        match (record.kind, record.peer) {
+            (MessageKind::Unicast, Some(peer)) => {
+                self.receive_unicast_raw(peer, &record.topic, record.data.clone());
+            }
+            (MessageKind::Unicast, None) => return false,
+            (MessageKind::Broadcast, from) => {
+                self.receive_signed_broadcast_raw(from, &record.topic, record.data.clone());
+            }
+        }
+        true
+    }
+
+    /// Takes the next message sent by the node.
+    pub fn pop_message(&mut self) -> Option<MessageFromNode> {
+        self.state.lock().unwrap().queue.pop_front()
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -0,0 +1,427 @@
+//
+// MIT License
+//
+// Copyright (c) 2018-2019 Stegos AG
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use failure::{format_err, Error};
+use futures::prelude::*;
+use futures::sync::mpsc;
+use libp2p_core::{multiaddr::Protocol, ConnectedPoint, Multiaddr, PeerId};
+use libp2p_swarm::{
+    protocols_handler::{DummyProtocolsHandler, ProtocolsHandler},
+    NetworkBehaviour, NetworkBehaviourAction, PollParameters,
+};
+use log::*;
+use std::cmp;
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::marker::PhantomData;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use void::Void;
+
+use super::metrics;
+use super::natpmp;
+use super::upnp;
+use crate::config::NetworkConfig;
+use crate::utils::{endpoint_to_multi_addr, parse_endpoint};
+
+// Trust our address as seen by peers only if so many of them agree
+const MIN_OBSERVATIONS: usize = 3;
+// Retry failed port mapping after this time
+const MAPPING_RETRY_INTERVAL: Duration = Duration::from_secs(5 * 60);
+// Don't renew mappings more often than this
+const MIN_RENEW_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Unicast protocol to ask a node behind NAT to connect to us, sent through its relays.
+/// The payload is our advertised multiaddr with the /p2p/ suffix.
+pub const DIAL_BACK_PROTOCOL_ID: &'static str = "stegos/nat-dial-back";
+
+/// Network behaviour which finds out how the node can be reached from outside.
+/// Port mapping runs on a separate thread, since NAT-PMP and UPnP clients are blocking.
+pub struct Nat<TSubstream> {
+    /// Listening address, to build the advertised multiaddr with the same transport.
+    endpoint: Multiaddr,
+    /// Results of port mapping.
+    mapping_rx: Option<mpsc::UnboundedReceiver<Option<SocketAddr>>>,
+    /// External address assigned by the gateway.
+    mapped: Option<SocketAddr>,
+    /// Our IP address as seen by connected peers.
+    observations: HashMap<PeerId, IpAddr>,
+    /// Address to advertise, if the node is reachable.
+    external: Option<Multiaddr>,
+    /// Events that need to be yielded to the outside when polling.
+    events: VecDeque<NetworkBehaviourAction<Void, NatEvent>>,
+    /// Marker to pin the generics.
+    marker: PhantomData<TSubstream>,
+}
+
+impl<TSubstream> Nat<TSubstream> {
+    /// Creates a `Nat`, starting port mapping if network.nat.port_mapping is enabled.
+    pub fn new(config: &NetworkConfig) -> Result<Self, Error> {
+        let endpoint = if config.endpoint != "" {
+            parse_endpoint(&config.endpoint, &config.transport)
+                .map_err(|e| format_err!("Invalid endpoint '{}': {}", config.endpoint, e))?
+        } else {
+            endpoint_to_multi_addr(&([0, 0, 0, 0], 0).into(), &config.transport)
+        };
+        let port = endpoint
+            .iter()
+            .find_map(|p| match p {
+                Protocol::Tcp(port) => Some(port),
+                _ => None,
+            })
+            .unwrap_or(0);
+        let mapping_rx = if config.nat.port_mapping && config.advertised_endpoint == "" && port != 0
+        {
+            let gateway = if config.nat.gateway != "" {
+                let gateway = Ipv4Addr::from_str(&config.nat.gateway).map_err(|e| {
+                    format_err!("Invalid nat.gateway '{}': {}", config.nat.gateway, e)
+                })?;
+                Some(gateway)
+            } else {
+                None
+            };
+            let lifetime = config.nat.mapping_lifetime;
+            let (tx, rx) = mpsc::unbounded();
+            thread::Builder::new()
+                .name("port-mapping".to_string())
+                .spawn(move || port_mapping(gateway, port, lifetime, tx))
+                .expect("failed to start port mapping thread");
+            Some(rx)
+        } else {
+            None
+        };
+        Ok(Nat {
+            endpoint,
+            mapping_rx,
+            mapped: None,
+            observations: HashMap::new(),
+            external: None,
+            events: VecDeque::new(),
+            marker: PhantomData,
+        })
+    }
+
+    /// Records our address as seen by the peer.
+    pub fn observed(&mut self, peer_id: PeerId, address: &Multiaddr) {
+        let ip = match address.iter().next() {
+            Some(Protocol::Ip4(ip)) => IpAddr::V4(ip),
+            Some(Protocol::Ip6(ip)) => IpAddr::V6(ip),
+            _ => return,
+        };
+        trace!(target: "stegos_network::nat", "observed address: peer_id={}, ip={}", peer_id, ip);
+        if self.observations.insert(peer_id, ip) != Some(ip) {
+            self.update();
+        }
+    }
+
+    /// Returns the address to advertise, if the node is reachable from outside.
+    pub fn external_address(&self) -> Option<&Multiaddr> {
+        self.external.as_ref()
+    }
+
+    fn mapped(&mut self, mapped: Option<SocketAddr>) {
+        if self.mapped != mapped {
+            self.mapped = mapped;
+            self.update();
+        }
+    }
+
+    /// Returns the IP address reported by most peers, if enough of them agree.
+    fn observed_ip(&self) -> Option<IpAddr> {
+        let mut counts: HashMap<IpAddr, usize> = HashMap::new();
+        for ip in self.observations.values() {
+            *counts.entry(*ip).or_insert(0) += 1;
+        }
+        counts
+            .into_iter()
+            .filter(|(_, count)| *count >= MIN_OBSERVATIONS)
+            .max_by_key(|(_, count)| *count)
+            .map(|(ip, _)| ip)
+    }
+
+    fn update(&mut self) {
+        let external = match self.mapped {
+            Some(mapped) if !is_global(&mapped.ip()) => {
+                debug!(target: "stegos_network::nat", "gateway has a private external address, probably behind another NAT: address={}", mapped);
+                None
+            }
+            Some(mapped) => match self.observed_ip() {
+                Some(ip) if ip != mapped.ip() => {
+                    debug!(target: "stegos_network::nat", "peers see another address than the gateway, probably behind another NAT: mapped={}, observed={}", mapped, ip);
+                    None
+                }
+                _ => Some(mapped_endpoint(&self.endpoint, &mapped)),
+            },
+            None => None,
+        };
+        if external == self.external {
+            return;
+        }
+        match external {
+            Some(ref address) => {
+                info!(target: "stegos_network::nat", "node is reachable from outside: address={}", address)
+            }
+            None => info!(target: "stegos_network::nat", "node is not reachable from outside"),
+        }
+        metrics::REACHABLE.set(external.is_some() as i64);
+        self.external = external.clone();
+        self.events.push_back(NetworkBehaviourAction::GenerateEvent(
+            NatEvent::ExternalAddress { address: external },
+        ));
+    }
+}
+
+impl<TSubstream> NetworkBehaviour for Nat<TSubstream>
+where
+    TSubstream: AsyncRead + AsyncWrite,
+{
+    type ProtocolsHandler = DummyProtocolsHandler<TSubstream>;
+    type OutEvent = NatEvent;
+
+    fn new_handler(&mut self) -> Self::ProtocolsHandler {
+        DummyProtocolsHandler::default()
+    }
+
+    fn addresses_of_peer(&mut self, _: &PeerId) -> Vec<Multiaddr> {
+        Vec::new()
+    }
+
+    fn inject_connected(&mut self, _: PeerId, _: ConnectedPoint) {}
+
+    fn inject_disconnected(&mut self, peer_id: &PeerId, _: ConnectedPoint) {
+        if self.observations.remove(peer_id).is_some() {
+            self.update();
+        }
+    }
+
+    fn inject_node_event(&mut self, _: PeerId, event: Void) {
+        void::unreachable(event)
+    }
+
+    fn poll(
+        &mut self,
+        _: &mut impl PollParameters,
+    ) -> Async<
+        NetworkBehaviourAction<
+            <Self::ProtocolsHandler as ProtocolsHandler>::InEvent,
+            Self::OutEvent,
+        >,
+    > {
+        loop {
+            let result = match self.mapping_rx {
+                Some(ref mut rx) => rx.poll(),
+                None => break,
+            };
+            match result {
+                Ok(Async::Ready(Some(mapped))) => self.mapped(mapped),
+                Ok(Async::Ready(None)) | Err(()) => {
+                    debug!(target: "stegos_network::nat", "port mapping thread has stopped");
+                    self.mapping_rx = None;
+                }
+                Ok(Async::NotReady) => break,
+            }
+        }
+
+        if let Some(event) = self.events.pop_front() {
+            return Async::Ready(event);
+        }
+
+        Async::NotReady
+    }
+}
+
+/// Event that can happen on the NAT behaviour.
+#[derive(Debug)]
+pub enum NatEvent {
+    /// The address to advertise has changed (None if the node is not reachable from outside).
+    ExternalAddress { address: Option<Multiaddr> },
+}
+
+/// Keeps the port mapped until the behaviour is dropped.
+fn port_mapping(
+    gateway: Option<Ipv4Addr>,
+    port: u16,
+    lifetime: u32,
+    tx: mpsc::UnboundedSender<Option<SocketAddr>>,
+) {
+    loop {
+        let (mapped, delay) = match map_port(gateway, port, lifetime) {
+            Ok((address, lifetime)) => {
+                debug!(target: "stegos_network::nat", "port mapped: address={}, lifetime={}", address, lifetime);
+                let renew = Duration::from_secs(lifetime as u64 / 2);
+                (Some(address), cmp::max(renew, MIN_RENEW_INTERVAL))
+            }
+            Err(e) => {
+                warn!(target: "stegos_network::nat", "failed to map port: port={}, error={}", port, e);
+                metrics::PORT_MAPPING_FAILURES.inc();
+                (None, MAPPING_RETRY_INTERVAL)
+            }
+        };
+        if tx.unbounded_send(mapped).is_err() {
+            return;
+        }
+        thread::sleep(delay);
+    }
+}
+
+/// Maps the port with NAT-PMP, falling back to UPnP.
+fn map_port(
+    gateway: Option<Ipv4Addr>,
+    port: u16,
+    lifetime: u32,
+) -> Result<(SocketAddr, u32), Error> {
+    if let Some(gateway) = gateway.or_else(default_gateway) {
+        let gateway = SocketAddr::new(gateway.into(), natpmp::NATPMP_PORT);
+        let result = natpmp::external_address(&gateway).and_then(|ip| {
+            let (port, lifetime) = natpmp::map_tcp(&gateway, port, port, lifetime)?;
+            Ok((SocketAddr::new(ip.into(), port), lifetime))
+        });
+        match result {
+            Ok(mapped) => return Ok(mapped),
+            Err(e) => {
+                debug!(target: "stegos_network::nat", "NAT-PMP failed, trying UPnP: error={}", e)
+            }
+        }
+    }
+    let gateway = upnp::Gateway::discover()?;
+    let local_ip = gateway.local_ip()?;
+    gateway.add_port_mapping(&SocketAddr::new(local_ip, port), lifetime)?;
+    let ip = gateway.external_address()?;
+    Ok((SocketAddr::new(ip.into(), port), lifetime))
+}
+
+/// Reads the default IPv4 gateway from /proc/net/route (Linux only).
+fn default_gateway() -> Option<Ipv4Addr> {
+    let routes = fs::read_to_string("/proc/net/route").ok()?;
+    for line in routes.lines().skip(1) {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() > 2 && fields[1] == "00000000" {
+            let gateway = u32::from_str_radix(fields[2], 16).ok()?;
+            return Some(Ipv4Addr::from(gateway.to_ne_bytes()));
+        }
+    }
+    None
+}
+
+/// Returns false for private, shared (carrier-grade NAT) and other non-routable addresses.
+fn is_global(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let octets = ip.octets();
+            let shared = octets[0] == 100 && (octets[1] & 0xc0) == 64;
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || shared)
+        }
+        IpAddr::V6(ip) => !(ip.is_loopback() || ip.is_unspecified()),
+    }
+}
+
+/// Replaces the IP address and the port of the listening address by the mapped ones.
+fn mapped_endpoint(endpoint: &Multiaddr, mapped: &SocketAddr) -> Multiaddr {
+    endpoint
+        .iter()
+        .map(|p| match p {
+            Protocol::Ip4(_) | Protocol::Ip6(_) => match mapped.ip() {
+                IpAddr::V4(ip) => Protocol::Ip4(ip),
+                IpAddr::V6(ip) => Protocol::Ip6(ip),
+            },
+            Protocol::Tcp(_) => Protocol::Tcp(mapped.port()),
+            p => p,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn observe(nat: &mut Nat<()>, count: usize, address: &str) {
+        let address: Multiaddr = address.parse().unwrap();
+        for _ in 0..count {
+            nat.observed(PeerId::random(), &address);
+        }
+    }
+
+    #[test]
+    fn external_address() {
+        let mut nat = Nat::<()>::new(&NetworkConfig::default()).unwrap();
+        assert!(nat.external_address().is_none());
+
+        // Mapped to a public address.
+        nat.mapped(Some("203.0.113.7:10203".parse().unwrap()));
+        let expected: Multiaddr = "/ip4/203.0.113.7/tcp/10203".parse().unwrap();
+        assert_eq!(nat.external_address(), Some(&expected));
+        assert_eq!(nat.events.len(), 1);
+
+        // Peers agree.
+        observe(&mut nat, MIN_OBSERVATIONS, "/ip4/203.0.113.7/tcp/40000");
+        assert_eq!(nat.external_address(), Some(&expected));
+
+        // Peers see another address - double NAT.
+        observe(
+            &mut nat,
+            MIN_OBSERVATIONS + 1,
+            "/ip4/198.51.100.1/tcp/40000",
+        );
+        assert!(nat.external_address().is_none());
+        assert_eq!(nat.events.len(), 2);
+    }
+
+    #[test]
+    fn private_mapping() {
+        let mut nat = Nat::<()>::new(&NetworkConfig::default()).unwrap();
+        nat.mapped(Some("100.64.10.1:10203".parse().unwrap()));
+        assert!(nat.external_address().is_none());
+        nat.mapped(Some("192.168.0.2:10203".parse().unwrap()));
+        assert!(nat.external_address().is_none());
+        assert!(nat.events.is_empty());
+    }
+
+    #[test]
+    fn websocket_endpoint() {
+        let mut config = NetworkConfig::default();
+        config.endpoint = "/ip4/0.0.0.0/tcp/10203/ws".to_string();
+        let mut nat = Nat::<()>::new(&config).unwrap();
+        nat.mapped(Some("203.0.113.7:10204".parse().unwrap()));
+        let expected: Multiaddr = "/ip4/203.0.113.7/tcp/10204/ws".parse().unwrap();
+        assert_eq!(nat.external_address(), Some(&expected));
+
+        config.endpoint = "0.0.0.0".to_string();
+        assert!(Nat::<()>::new(&config).is_err());
+    }
+
+    #[test]
+    fn invalid_gateway() {
+        let mut config = NetworkConfig::default();
+        config.endpoint = "0.0.0.0:10203".to_string();
+        config.nat.port_mapping = true;
+        config.nat.gateway = "gateway.local".to_string();
+        assert!(Nat::<()>::new(&config).is_err());
+    }
+}
@@ -0,0 +1,144 @@
+//
+// MIT License
+//
+// Copyright (c) 2018-2019 Stegos AG
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//!
+//! Minimal NAT-PMP client (RFC 6886).
+//!
+
+use failure::{bail, Error};
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::time::Duration;
+
+/// NAT-PMP port on the gateway.
+pub const NATPMP_PORT: u16 = 5351;
+
+// Initial retransmission timeout, doubled on every retry
+const INITIAL_TIMEOUT: Duration = Duration::from_millis(250);
+// Number of requests to send before giving up
+const MAX_ATTEMPTS: u32 = 4;
+
+const OPCODE_EXTERNAL_ADDRESS: u8 = 0;
+const OPCODE_MAP_TCP: u8 = 2;
+const RESPONSE_FLAG: u8 = 128;
+
+/// Requests the external IPv4 address of the gateway.
+pub fn external_address(gateway: &SocketAddr) -> Result<Ipv4Addr, Error> {
+    let response = request(gateway, &[0, OPCODE_EXTERNAL_ADDRESS], 12)?;
+    Ok(Ipv4Addr::new(
+        response[8],
+        response[9],
+        response[10],
+        response[11],
+    ))
+}
+
+/// Maps a TCP port, returns the mapped external port and the granted lifetime (secs).
+pub fn map_tcp(
+    gateway: &SocketAddr,
+    internal_port: u16,
+    external_port: u16,
+    lifetime: u32,
+) -> Result<(u16, u32), Error> {
+    let mut req = vec![0, OPCODE_MAP_TCP, 0, 0];
+    req.extend_from_slice(&internal_port.to_be_bytes());
+    req.extend_from_slice(&external_port.to_be_bytes());
+    req.extend_from_slice(&lifetime.to_be_bytes());
+    let response = request(gateway, &req, 16)?;
+    let mapped_port = u16::from_be_bytes([response[10], response[11]]);
+    let lifetime = u32::from_be_bytes([response[12], response[13], response[14], response[15]]);
+    Ok((mapped_port, lifetime))
+}
+
+fn request(gateway: &SocketAddr, req: &[u8], response_len: usize) -> Result<Vec<u8>, Error> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.connect(gateway)?;
+    let mut timeout = INITIAL_TIMEOUT;
+    let mut buf = [0u8; 16];
+    for _ in 0..MAX_ATTEMPTS {
+        socket.send(req)?;
+        socket.set_read_timeout(Some(timeout))?;
+        match socket.recv(&mut buf) {
+            Ok(len) => {
+                if len < response_len || buf[0] != 0 || buf[1] != req[1] | RESPONSE_FLAG {
+                    bail!("invalid NAT-PMP response from {}", gateway);
+                }
+                let result = u16::from_be_bytes([buf[2], buf[3]]);
+                if result != 0 {
+                    bail!(
+                        "NAT-PMP request failed: gateway={}, result={}",
+                        gateway,
+                        result
+                    );
+                }
+                return Ok(buf[..len].to_vec());
+            }
+            Err(_) => timeout *= 2,
+        }
+    }
+    bail!("no NAT-PMP response from {}", gateway)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    // Stand-in gateway which answers two requests.
+    fn gateway() -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap();
+        thread::spawn(move || {
+            let mut buf = [0u8; 16];
+            for _ in 0..2 {
+                let (len, from) = socket.recv_from(&mut buf).unwrap();
+                let mut response = vec![0, buf[1] | RESPONSE_FLAG, 0, 0, 0, 0, 0, 1];
+                if buf[1] == OPCODE_EXTERNAL_ADDRESS {
+                    assert_eq!(len, 2);
+                    response.extend_from_slice(&[203, 0, 113, 7]);
+                } else {
+                    assert_eq!(len, 12);
+                    // Internal port, external port + 1, requested lifetime.
+                    response.extend_from_slice(&buf[4..6]);
+                    let port = u16::from_be_bytes([buf[6], buf[7]]) + 1;
+                    response.extend_from_slice(&port.to_be_bytes());
+                    response.extend_from_slice(&buf[8..12]);
+                }
+                socket.send_to(&response, from).unwrap();
+            }
+        });
+        addr
+    }
+
+    #[test]
+    fn natpmp() {
+        let gateway = gateway();
+        assert_eq!(
+            external_address(&gateway).unwrap(),
+            Ipv4Addr::new(203, 0, 113, 7)
+        );
+        assert_eq!(
+            map_tcp(&gateway, 10203, 10203, 3600).unwrap(),
+            (10204, 3600)
+        );
+    }
+}
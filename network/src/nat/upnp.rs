@@ -0,0 +1,333 @@
+//
+// MIT License
+//
+// Copyright (c) 2018-2019 Stegos AG
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//!
+//! Minimal UPnP IGD client: SSDP discovery and WAN(IP|PPP)Connection port mapping.
+//!
+
+use failure::{bail, format_err, Error};
+use std::io::{Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+const SSDP_ADDRESS: &'static str = "239.255.255.250:1900";
+const SSDP_TIMEOUT: Duration = Duration::from_secs(3);
+const HTTP_TIMEOUT: Duration = Duration::from_secs(5);
+
+const SERVICE_TYPES: &[&'static str] = &[
+    "urn:schemas-upnp-org:service:WANIPConnection:1",
+    "urn:schemas-upnp-org:service:WANIPConnection:2",
+    "urn:schemas-upnp-org:service:WANPPPConnection:1",
+];
+
+/// Internet gateway device found on the local network.
+#[derive(Debug, Clone)]
+pub struct Gateway {
+    /// HTTP endpoint of the device.
+    addr: SocketAddr,
+    /// Path of the control URL of the connection service.
+    control_path: String,
+    /// Type of the connection service.
+    service_type: String,
+}
+
+impl Gateway {
+    /// Finds a gateway using SSDP multicast.
+    pub fn discover() -> Result<Gateway, Error> {
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        let request = format!(
+            "M-SEARCH * HTTP/1.1\r\nHOST: {}\r\nST: urn:schemas-upnp-org:device:InternetGatewayDevice:1\r\nMAN: \"ssdp:discover\"\r\nMX: 2\r\n\r\n",
+            SSDP_ADDRESS
+        );
+        socket.send_to(request.as_bytes(), SSDP_ADDRESS)?;
+        let deadline = Instant::now() + SSDP_TIMEOUT;
+        let mut buf = [0u8; 2048];
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                bail!("no UPnP gateway found");
+            }
+            socket.set_read_timeout(Some(deadline - now))?;
+            let len = match socket.recv_from(&mut buf) {
+                Ok((len, _)) => len,
+                Err(_) => bail!("no UPnP gateway found"),
+            };
+            let response = String::from_utf8_lossy(&buf[..len]);
+            if let Some(location) = header(&response, "location") {
+                if let Ok(gateway) = Gateway::from_location(location) {
+                    return Ok(gateway);
+                }
+            }
+        }
+    }
+
+    /// Reads the device description from `location` and finds the connection service.
+    pub fn from_location(location: &str) -> Result<Gateway, Error> {
+        let (addr, path) = parse_url(location)?;
+        let (status, description) = http(&addr, &format!("GET {} HTTP/1.0\r\n\r\n", path))?;
+        if status != 200 {
+            bail!("failed to get UPnP description: status={}", status);
+        }
+        for service in description.split("<service>").skip(1) {
+            let service_type = match element(service, "serviceType") {
+                Some(t) => t,
+                None => continue,
+            };
+            if !SERVICE_TYPES.contains(&service_type) {
+                continue;
+            }
+            let control_url =
+                element(service, "controlURL").ok_or_else(|| format_err!("no controlURL"))?;
+            let control_path = if control_url.starts_with("http://") {
+                parse_url(control_url)?.1
+            } else if control_url.starts_with('/') {
+                control_url.to_string()
+            } else {
+                format!("/{}", control_url)
+            };
+            return Ok(Gateway {
+                addr,
+                control_path,
+                service_type: service_type.to_string(),
+            });
+        }
+        bail!("no WAN connection service in UPnP description")
+    }
+
+    /// Requests the external IPv4 address of the gateway.
+    pub fn external_address(&self) -> Result<Ipv4Addr, Error> {
+        let response = self.soap("GetExternalIPAddress", "")?;
+        let ip = element(&response, "NewExternalIPAddress")
+            .ok_or_else(|| format_err!("no NewExternalIPAddress in UPnP response"))?;
+        Ok(Ipv4Addr::from_str(ip)?)
+    }
+
+    /// Maps the same external TCP port to `local_addr`.
+    pub fn add_port_mapping(&self, local_addr: &SocketAddr, lifetime: u32) -> Result<(), Error> {
+        let args = format!(
+            "<NewRemoteHost></NewRemoteHost>\
+             <NewExternalPort>{}</NewExternalPort>\
+             <NewProtocol>TCP</NewProtocol>\
+             <NewInternalPort>{}</NewInternalPort>\
+             <NewInternalClient>{}</NewInternalClient>\
+             <NewEnabled>1</NewEnabled>\
+             <NewPortMappingDescription>stegos</NewPortMappingDescription>\
+             <NewLeaseDuration>{}</NewLeaseDuration>",
+            local_addr.port(),
+            local_addr.port(),
+            local_addr.ip(),
+            lifetime
+        );
+        self.soap("AddPortMapping", &args)?;
+        Ok(())
+    }
+
+    /// Local address used to reach the gateway.
+    pub fn local_ip(&self) -> Result<std::net::IpAddr, Error> {
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        socket.connect(self.addr)?;
+        Ok(socket.local_addr()?.ip())
+    }
+
+    fn soap(&self, action: &str, args: &str) -> Result<String, Error> {
+        let body = format!(
+            "<?xml version=\"1.0\"?>\
+             <s:Envelope xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\" \
+             s:encodingStyle=\"http://schemas.xmlsoap.org/soap/encoding/\">\
+             <s:Body><u:{action} xmlns:u=\"{service}\">{args}</u:{action}></s:Body>\
+             </s:Envelope>",
+            action = action,
+            service = self.service_type,
+            args = args
+        );
+        let request = format!(
+            "POST {} HTTP/1.0\r\nHost: {}\r\nContent-Type: text/xml; charset=\"utf-8\"\r\nSOAPAction: \"{}#{}\"\r\nContent-Length: {}\r\n\r\n{}",
+            self.control_path,
+            self.addr,
+            self.service_type,
+            action,
+            body.len(),
+            body
+        );
+        let (status, response) = http(&self.addr, &request)?;
+        if status != 200 {
+            bail!("UPnP {} failed: status={}", action, status);
+        }
+        Ok(response)
+    }
+}
+
+/// Sends a request and returns the status code and the body of the response.
+fn http(addr: &SocketAddr, request: &str) -> Result<(u16, String), Error> {
+    let mut stream = TcpStream::connect_timeout(addr, HTTP_TIMEOUT)?;
+    stream.set_read_timeout(Some(HTTP_TIMEOUT))?;
+    stream.set_write_timeout(Some(HTTP_TIMEOUT))?;
+    stream.write_all(request.as_bytes())?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    let status = response
+        .split_whitespace()
+        .nth(1)
+        .and_then(|s| s.parse::<u16>().ok())
+        .ok_or_else(|| format_err!("invalid HTTP response from {}", addr))?;
+    let body = match response.find("\r\n\r\n") {
+        Some(pos) => response[pos + 4..].to_string(),
+        None => String::new(),
+    };
+    Ok((status, body))
+}
+
+/// Splits "http://host:port/path" into the socket address and the path.
+fn parse_url(url: &str) -> Result<(SocketAddr, String), Error> {
+    let rest = url
+        .trim()
+        .trim_start_matches("http://")
+        .trim_start_matches("HTTP://");
+    let (host, path) = match rest.find('/') {
+        Some(pos) => (&rest[..pos], &rest[pos..]),
+        None => (rest, "/"),
+    };
+    let host = if host.contains(':') {
+        host.to_string()
+    } else {
+        format!("{}:80", host)
+    };
+    let addr = host
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| format_err!("failed to resolve {}", host))?;
+    Ok((addr, path.to_string()))
+}
+
+/// Returns the value of an HTTP header (case-insensitive name).
+fn header<'a>(response: &'a str, name: &str) -> Option<&'a str> {
+    for line in response.lines() {
+        if let Some(pos) = line.find(':') {
+            if line[..pos].trim().eq_ignore_ascii_case(name) {
+                return Some(line[pos + 1..].trim());
+            }
+        }
+    }
+    None
+}
+
+/// Returns the text of the first element with the given name (ignoring namespace prefix).
+fn element<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    let mut rest = xml;
+    while let Some(pos) = rest.find('<') {
+        rest = &rest[pos + 1..];
+        let end = rest.find('>')?;
+        let tag = rest[..end].split_whitespace().next().unwrap_or("");
+        rest = &rest[end + 1..];
+        if tag.starts_with('/') {
+            continue;
+        }
+        if tag.rsplit(':').next() == Some(name) {
+            let close = rest.find("</")?;
+            return Some(rest[..close].trim());
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    const DESCRIPTION: &'static str =
+        "<?xml version=\"1.0\"?><root><device><serviceList>\
+         <service><serviceType>urn:schemas-upnp-org:service:Layer3Forwarding:1</serviceType>\
+         <controlURL>/ctl/L3F</controlURL></service>\
+         <service><serviceType>urn:schemas-upnp-org:service:WANIPConnection:1</serviceType>\
+         <controlURL>/ctl/IPConn</controlURL></service>\
+         </serviceList></device></root>";
+
+    // Stand-in gateway which answers `count` HTTP requests.
+    fn gateway(count: usize) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            for _ in 0..count {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut buf = [0u8; 4096];
+                let len = stream.read(&mut buf).unwrap();
+                let request = String::from_utf8_lossy(&buf[..len]).to_string();
+                let body = if request.starts_with("GET /rootDesc.xml") {
+                    DESCRIPTION.to_string()
+                } else if request.starts_with("POST /ctl/IPConn") {
+                    if request.contains("#GetExternalIPAddress") {
+                        "<s:Envelope><s:Body><u:GetExternalIPAddressResponse>\
+                         <NewExternalIPAddress>198.51.100.3</NewExternalIPAddress>\
+                         </u:GetExternalIPAddressResponse></s:Body></s:Envelope>"
+                            .to_string()
+                    } else {
+                        assert!(request.contains("#AddPortMapping"));
+                        assert!(request.contains("<NewExternalPort>10203</NewExternalPort>"));
+                        assert!(
+                            request.contains("<NewInternalClient>192.168.1.5</NewInternalClient>")
+                        );
+                        String::new()
+                    }
+                } else {
+                    let response = "HTTP/1.0 404 Not Found\r\n\r\n";
+                    stream.write_all(response.as_bytes()).unwrap();
+                    continue;
+                };
+                let response = format!("HTTP/1.0 200 OK\r\nContent-Type: text/xml\r\n\r\n{}", body);
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        addr
+    }
+
+    #[test]
+    fn upnp() {
+        let addr = gateway(3);
+        let gateway = Gateway::from_location(&format!("http://{}/rootDesc.xml", addr)).unwrap();
+        assert_eq!(gateway.control_path, "/ctl/IPConn");
+        assert_eq!(
+            gateway.external_address().unwrap(),
+            Ipv4Addr::new(198, 51, 100, 3)
+        );
+        let local: SocketAddr = "192.168.1.5:10203".parse().unwrap();
+        gateway.add_port_mapping(&local, 3600).unwrap();
+    }
+
+    #[test]
+    fn parsing() {
+        let response = "HTTP/1.1 200 OK\r\nCACHE-CONTROL: max-age=120\r\nLocation: http://192.168.1.1:5000/rootDesc.xml\r\n\r\n";
+        assert_eq!(
+            header(response, "location"),
+            Some("http://192.168.1.1:5000/rootDesc.xml")
+        );
+        let (addr, path) = parse_url("http://192.168.1.1:5000/rootDesc.xml").unwrap();
+        assert_eq!(addr, "192.168.1.1:5000".parse().unwrap());
+        assert_eq!(path, "/rootDesc.xml");
+        assert_eq!(element("<a><u:b> x </u:b></a>", "b"), Some("x"));
+        assert_eq!(element("<a></b><b>y</b></a>", "b"), Some("y"));
+        assert_eq!(element("<a></a>", "b"), None);
+    }
+}
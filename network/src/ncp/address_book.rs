@@ -0,0 +1,366 @@
+//
+// MIT License
+//
+// Copyright (c) 2018-2019 Stegos AG
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//!
+//! Persistent address book of known peers.
+//!
+//! File format: one peer per line,
+//! "PEER_ID NODE_ID LAST_SEEN SUCCESSES FAILURES ADDR[,ADDR...]".
+//!
+
+use libp2p_core::{Multiaddr, PeerId};
+use log::*;
+use std::collections::HashMap;
+use std::fs;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use stegos_crypto::pbc;
+
+// Forget peers which were not seen for 30 days.
+const MAX_PEER_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+// Forget peers which failed so many times more than succeeded.
+const MAX_EXCESS_FAILURES: u64 = 10;
+
+/// Saved information about a peer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AddressBookEntry {
+    /// Network key of the peer.
+    pub node_id: pbc::PublicKey,
+    /// Known addresses of the peer.
+    pub addresses: Vec<Multiaddr>,
+    /// Last time the peer was seen online (unix secs), 0 if never.
+    pub last_seen: u64,
+    /// Number of successful connections.
+    pub successes: u64,
+    /// Number of failed dial attempts.
+    pub failures: u64,
+}
+
+impl AddressBookEntry {
+    fn rank(&self) -> i64 {
+        self.successes as i64 - self.failures as i64
+    }
+
+    fn is_stale(&self, now: u64) -> bool {
+        (self.last_seen != 0 && self.last_seen + MAX_PEER_AGE.as_secs() < now)
+            || self.failures > self.successes + MAX_EXCESS_FAILURES
+    }
+}
+
+/// Known peers, kept across restarts.
+pub struct AddressBook {
+    /// File to save peers to (empty to keep peers in memory only).
+    path: String,
+    /// Maximal number of peers to keep.
+    capacity: usize,
+    /// Peers.
+    entries: HashMap<PeerId, AddressBookEntry>,
+    /// True if entries were changed since the last save.
+    dirty: bool,
+}
+
+impl AddressBook {
+    /// Loads saved peers from the file.
+    pub fn load(path: &str, capacity: usize) -> Self {
+        let mut book = AddressBook {
+            path: path.to_string(),
+            capacity,
+            entries: HashMap::new(),
+            dirty: false,
+        };
+        if path == "" {
+            return book;
+        }
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) => {
+                debug!(target: "stegos_network::ncp", "no saved peers: file={}, error={}", path, e);
+                return book;
+            }
+        };
+        for line in contents.lines() {
+            match parse_entry(line) {
+                Some((peer_id, entry)) => {
+                    book.entries.insert(peer_id, entry);
+                }
+                None => {
+                    warn!(target: "stegos_network::ncp", "invalid line in peers file: {}", line);
+                }
+            }
+        }
+        book.prune();
+        info!(target: "stegos_network::ncp", "loaded saved peers: file={}, peers={}", path, book.entries.len());
+        book
+    }
+
+    /// Writes peers to the file, if changed.
+    pub fn save(&mut self) {
+        if self.path == "" || !self.dirty {
+            return;
+        }
+        self.prune();
+        let mut contents = String::new();
+        for (peer_id, entry) in self.entries.iter() {
+            let addresses: Vec<String> = entry.addresses.iter().map(|a| a.to_string()).collect();
+            contents.push_str(&format!(
+                "{} {} {} {} {} {}\n",
+                peer_id.to_base58(),
+                entry.node_id.to_hex(),
+                entry.last_seen,
+                entry.successes,
+                entry.failures,
+                addresses.join(",")
+            ));
+        }
+        match fs::write(&self.path, contents) {
+            Ok(()) => self.dirty = false,
+            Err(e) => {
+                error!(target: "stegos_network::ncp", "failed to save peers: file={}, error={}", self.path, e);
+            }
+        }
+    }
+
+    /// Updates addresses of a discovered peer.
+    /// The peer isn't considered seen until we connect to it or it responds.
+    pub fn discovered(
+        &mut self,
+        peer_id: &PeerId,
+        node_id: &pbc::PublicKey,
+        addresses: &[Multiaddr],
+    ) {
+        if addresses.is_empty() {
+            return;
+        }
+        let entry = self
+            .entries
+            .entry(peer_id.clone())
+            .or_insert_with(|| AddressBookEntry {
+                node_id: *node_id,
+                addresses: Vec::new(),
+                last_seen: 0,
+                successes: 0,
+                failures: 0,
+            });
+        entry.node_id = *node_id;
+        entry.addresses = addresses.to_vec();
+        self.dirty = true;
+        if self.entries.len() > self.capacity {
+            self.evict();
+        }
+    }
+
+    /// Records a successful connection to the peer.
+    pub fn connected(&mut self, peer_id: &PeerId) {
+        if let Some(entry) = self.entries.get_mut(peer_id) {
+            entry.last_seen = unix_now();
+            entry.successes += 1;
+            self.dirty = true;
+        }
+    }
+
+    /// Marks the peer as seen online.
+    pub fn seen(&mut self, peer_id: &PeerId) {
+        if let Some(entry) = self.entries.get_mut(peer_id) {
+            entry.last_seen = unix_now();
+            if entry.successes == 0 {
+                entry.successes = 1;
+            }
+            self.dirty = true;
+        }
+    }
+
+    /// Records a failed dial attempt to the peer.
+    pub fn dial_failed(&mut self, peer_id: &PeerId) {
+        if let Some(entry) = self.entries.get_mut(peer_id) {
+            entry.failures += 1;
+            self.dirty = true;
+        }
+    }
+
+    /// Returns saved information about the peer.
+    pub fn get(&self, peer_id: &PeerId) -> Option<&AddressBookEntry> {
+        self.entries.get(peer_id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns up to `count` best peers to bootstrap from.
+    pub fn best_peers(&self, count: usize) -> Vec<(PeerId, AddressBookEntry)> {
+        let mut peers: Vec<(PeerId, AddressBookEntry)> = self
+            .entries
+            .iter()
+            .map(|(p, e)| (p.clone(), e.clone()))
+            .collect();
+        peers.sort_by(|(_, a), (_, b)| b.rank().cmp(&a.rank()).then(b.last_seen.cmp(&a.last_seen)));
+        peers.truncate(count);
+        peers
+    }
+
+    fn prune(&mut self) {
+        let now = unix_now();
+        let before = self.entries.len();
+        self.entries.retain(|_, e| !e.is_stale(now));
+        if self.entries.len() != before {
+            self.dirty = true;
+        }
+        while self.entries.len() > self.capacity {
+            self.evict();
+        }
+    }
+
+    // Removes the worst peer.
+    fn evict(&mut self) {
+        let worst = self
+            .entries
+            .iter()
+            .min_by(|(_, a), (_, b)| a.rank().cmp(&b.rank()).then(a.last_seen.cmp(&b.last_seen)))
+            .map(|(p, _)| p.clone());
+        if let Some(peer_id) = worst {
+            self.entries.remove(&peer_id);
+            self.dirty = true;
+        }
+    }
+}
+
+fn parse_entry(line: &str) -> Option<(PeerId, AddressBookEntry)> {
+    let parts: Vec<&str> = line.split_whitespace().collect();
+    if parts.len() != 6 {
+        return None;
+    }
+    let peer_id = PeerId::from_str(parts[0]).ok()?;
+    let node_id = pbc::PublicKey::try_from_hex(parts[1]).ok()?;
+    let last_seen = parts[2].parse::<u64>().ok()?;
+    let successes = parts[3].parse::<u64>().ok()?;
+    let failures = parts[4].parse::<u64>().ok()?;
+    let mut addresses = Vec::new();
+    for addr in parts[5].split(',') {
+        addresses.push(Multiaddr::from_str(addr).ok()?);
+    }
+    let entry = AddressBookEntry {
+        node_id,
+        addresses,
+        last_seen,
+        successes,
+        failures,
+    };
+    Some((peer_id, entry))
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn random_peer() -> (PeerId, pbc::PublicKey) {
+        let (_, node_id) = pbc::make_random_keys();
+        (PeerId::random(), node_id)
+    }
+
+    #[test]
+    fn save_and_load() {
+        let path = std::env::temp_dir().join(format!("stegos_peers_{}.txt", rand::random::<u64>()));
+        let path = path.to_string_lossy().to_string();
+        let addr: Multiaddr = "/ip4/10.0.0.1/tcp/10203".parse().unwrap();
+        let (peer1, node1) = random_peer();
+        let (peer2, node2) = random_peer();
+        {
+            let mut book = AddressBook::load(&path, 10);
+            assert_eq!(book.len(), 0);
+            book.discovered(&peer1, &node1, &[addr.clone()]);
+            book.discovered(&peer2, &node2, &[addr.clone()]);
+            book.connected(&peer1);
+            book.dial_failed(&peer2);
+            book.save();
+        }
+        let book = AddressBook::load(&path, 10);
+        assert_eq!(book.len(), 2);
+        let entry = book.get(&peer1).unwrap();
+        assert_eq!(entry.node_id, node1);
+        assert_eq!(entry.addresses, vec![addr]);
+        assert_eq!(entry.successes, 1);
+        assert_eq!(book.get(&peer2).unwrap().failures, 1);
+        assert_eq!(book.best_peers(1)[0].0, peer1);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn discovered_is_not_seen() {
+        let addr: Multiaddr = "/ip4/10.0.0.1/tcp/10203".parse().unwrap();
+        let (peer1, node1) = random_peer();
+        let (peer2, node2) = random_peer();
+        let (peer3, node3) = random_peer();
+        let mut book = AddressBook::load("", 3);
+        book.discovered(&peer1, &node1, &[addr.clone()]);
+        book.discovered(&peer2, &node2, &[addr.clone()]);
+        book.discovered(&peer3, &node3, &[addr.clone()]);
+        assert_eq!(book.get(&peer1).unwrap().last_seen, 0);
+        book.connected(&peer2);
+        book.seen(&peer3);
+        assert_ne!(book.get(&peer2).unwrap().last_seen, 0);
+        assert_ne!(book.get(&peer3).unwrap().last_seen, 0);
+
+        // Advertised peers don't age, but are evicted first.
+        book.prune();
+        assert_eq!(book.len(), 3);
+        let (peer4, node4) = random_peer();
+        book.discovered(&peer4, &node4, &[addr.clone()]);
+        assert_eq!(book.len(), 3);
+        assert!(book.get(&peer2).is_some());
+        assert!(book.get(&peer3).is_some());
+    }
+
+    #[test]
+    fn prune() {
+        let addr: Multiaddr = "/ip4/10.0.0.1/tcp/10203".parse().unwrap();
+
+        // Capacity.
+        let mut book = AddressBook::load("", 2);
+        let (peer1, node1) = random_peer();
+        let (peer2, node2) = random_peer();
+        let (peer3, node3) = random_peer();
+        book.discovered(&peer1, &node1, &[addr.clone()]);
+        book.connected(&peer1);
+        book.discovered(&peer2, &node2, &[addr.clone()]);
+        book.connected(&peer2);
+        book.discovered(&peer3, &node3, &[addr.clone()]);
+        assert_eq!(book.len(), 2);
+        assert!(book.get(&peer3).is_none());
+
+        // Failures.
+        for _ in 0..MAX_EXCESS_FAILURES + 2 {
+            book.dial_failed(&peer2);
+        }
+        book.prune();
+        assert_eq!(book.len(), 1);
+        assert!(book.get(&peer2).is_none());
+    }
+}
@@ -32,7 +32,7 @@ use rand::{thread_rng, Rng};
 use serde_derive::{Deserialize, Serialize};
 use smallvec::SmallVec;
 use std::{
-    collections::VecDeque,
+    collections::{HashMap, HashSet, VecDeque},
     marker::PhantomData,
     net::Ipv4Addr,
     time::{Duration, Instant},
@@ -42,11 +42,11 @@ use tokio::io::{AsyncRead, AsyncWrite};
 use tokio_timer::Delay;
 
 use crate::config::NetworkConfig;
+use crate::ncp::address_book::AddressBook;
 use crate::ncp::handler::NcpHandler;
 use crate::ncp::protocol::{GetPeersResponse, NcpMessage, PeerInfo};
-use crate::utils::{socket_to_multi_addr, ExpiringQueue};
-use std::net::SocketAddr;
-use std::str::FromStr;
+use crate::utils::{parse_endpoint, ExpiringQueue};
+use failure::{format_err, Error};
 
 // Size of table for "known" peers
 const KNOWN_PEERS_TABLE_SIZE: usize = 1024;
@@ -54,6 +54,9 @@ const KNOWN_PEERS_TABLE_SIZE: usize = 1024;
 // Treat connection as dead after 5 minutes inactivity
 const IDLE_TIMEOUT: Duration = Duration::from_secs(5 * 60);
 
+// Number of relays to keep connected to when behind NAT
+const MAX_RELAYS: usize = 3;
+
 // Localhost Multiaddr (to use in the filter)
 const LOCALHOST_MULTIADDR: Protocol = Protocol::Ip4(Ipv4Addr::new(127, 0, 0, 1));
 
@@ -62,8 +65,26 @@ const LOCALHOST_MULTIADDR: Protocol = Protocol::Ip4(Ipv4Addr::new(127, 0, 0, 1))
 pub struct Ncp<TSubstream> {
     /// Out network key
     node_id: pbc::PublicKey,
+    /// Our network secret key, used to sign our relays.
+    network_skey: pbc::SecretKey,
     /// Advertised Multiaddr.
     advertised_endpoint: Option<Multiaddr>,
+    /// Mapped address on the NAT gateway.
+    external_address: Option<Multiaddr>,
+    /// Don't advertise our addresses to peers.
+    hidden: bool,
+    /// Relay unicast messages to peers behind NAT.
+    relay: bool,
+    /// Addresses of connected peers as we see them.
+    remote_addrs: HashMap<PeerId, Multiaddr>,
+    /// Connected peers which relay messages for us.
+    relay_peers: HashSet<PeerId>,
+    /// Connected peers which asked not to be advertised.
+    hidden_peers: HashSet<PeerId>,
+    /// Known peers which relay messages for nodes behind NAT.
+    known_relays: LruCache<Vec<u8>, ()>,
+    /// Relays of known peers behind NAT, as signed by the peers themselves.
+    peer_relays: LruCache<Vec<u8>, (Vec<PeerId>, pbc::Signature)>,
     /// Queue of internal events
     events: VecDeque<NcpEvent>,
     /// Events that need to be yielded to the outside when polling.
@@ -72,6 +93,8 @@ pub struct Ncp<TSubstream> {
     connected_peers: ExpiringQueue<PeerId, Instant>,
     /// Known peers
     known_peers: LruCache<Vec<u8>, (pbc::PublicKey, SmallVec<[Multiaddr; 16]>)>,
+    /// Known peers, kept across restarts.
+    address_book: AddressBook,
     /// Maximum connections allowd
     max_connections: usize,
     /// Minimum connections to keep
@@ -99,17 +122,27 @@ pub struct NodeInfo {
 
 impl<TSubstream> Ncp<TSubstream> {
     /// Creates a NetworkBehaviour for NCP.
-    pub fn new(config: &NetworkConfig, network_pkey: pbc::PublicKey) -> Self {
-        let mut seed_nodes: Vec<Multiaddr> = config
-            .seed_nodes
-            .iter()
-            .map(|a| socket_to_multi_addr(&SocketAddr::from_str(a).expect("Invalid seed_nodes")))
-            .collect();
+    pub fn new(
+        config: &NetworkConfig,
+        network_pkey: pbc::PublicKey,
+        network_skey: pbc::SecretKey,
+    ) -> Result<Self, Error> {
+        let mut seed_nodes: Vec<Multiaddr> = Vec::with_capacity(config.seed_nodes.len());
+        for addr in config.seed_nodes.iter() {
+            let addr = parse_endpoint(addr, &config.transport)
+                .map_err(|e| format_err!("Invalid seed_nodes '{}': {}", addr, e))?;
+            seed_nodes.push(addr);
+        }
 
         let advertised_endpoint = if config.advertised_endpoint != "" {
-            let endpoint = SocketAddr::from_str(&config.advertised_endpoint)
-                .expect("Invalid advertised_endpoint");
-            let endpoint = socket_to_multi_addr(&endpoint);
+            let endpoint =
+                parse_endpoint(&config.advertised_endpoint, &config.transport).map_err(|e| {
+                    format_err!(
+                        "Invalid advertised_endpoint '{}': {}",
+                        config.advertised_endpoint,
+                        e
+                    )
+                })?;
             debug!(target: "stegos_network::ncp", "Public Network endpoint: {}", endpoint);
             seed_nodes.retain(|a| {
                 if a == &endpoint {
@@ -123,16 +156,47 @@ impl<TSubstream> Ncp<TSubstream> {
         } else {
             None
         };
-        Ncp {
+
+        // Re-bootstrap from peers saved by the previous run.
+        let address_book = AddressBook::load(&config.peers_file, KNOWN_PEERS_TABLE_SIZE);
+        let mut known_peers =
+            LruCache::<Vec<u8>, (pbc::PublicKey, SmallVec<[Multiaddr; 16]>)>::with_capacity(
+                KNOWN_PEERS_TABLE_SIZE,
+            );
+        let mut out_events = VecDeque::new();
+        for (peer_id, entry) in address_book.best_peers(config.max_connections) {
+            debug!(target: "stegos_network::ncp", "dialing saved peer: peer_id={}, last_seen={}", peer_id, entry.last_seen);
+            known_peers.insert(
+                peer_id.clone().into_bytes(),
+                (entry.node_id, entry.addresses.iter().cloned().collect()),
+            );
+            out_events.push_back(NcpOutEvent::DiscoveredPeer {
+                node_id: entry.node_id,
+                peer_id: peer_id.clone(),
+                addresses: entry.addresses,
+                relays: None,
+                hidden: false,
+            });
+            out_events.push_back(NcpOutEvent::DialPeer { peer_id });
+        }
+
+        Ok(Ncp {
             node_id: network_pkey,
+            network_skey,
             advertised_endpoint,
+            external_address: None,
+            hidden: config.hidden,
+            relay: config.nat.relay,
+            remote_addrs: HashMap::new(),
+            relay_peers: HashSet::new(),
+            hidden_peers: HashSet::new(),
+            known_relays: LruCache::with_capacity(KNOWN_PEERS_TABLE_SIZE),
+            peer_relays: LruCache::with_capacity(KNOWN_PEERS_TABLE_SIZE),
             events: VecDeque::new(),
-            out_events: VecDeque::new(),
+            out_events,
             connected_peers: ExpiringQueue::new(IDLE_TIMEOUT),
-            known_peers:
-                LruCache::<Vec<u8>, (pbc::PublicKey, SmallVec<[Multiaddr; 16]>)>::with_capacity(
-                    KNOWN_PEERS_TABLE_SIZE,
-                ),
+            known_peers,
+            address_book,
             max_connections: config.max_connections,
             min_connections: config.min_connections,
             monitor_delay: Delay::new(
@@ -143,11 +207,12 @@ impl<TSubstream> Ncp<TSubstream> {
             delay_between_monitor_events: Duration::from_secs(config.monitoring_interval),
             seed_nodes,
             marker: PhantomData,
-        }
+        })
     }
 
-    pub fn change_network_key(&mut self, new_pkey: pbc::PublicKey) {
+    pub fn change_network_key(&mut self, new_pkey: pbc::PublicKey, new_skey: pbc::SecretKey) {
         self.node_id = new_pkey;
+        self.network_skey = new_skey;
         // Update all connected peers with our new network key
         for p in self.connected_peers.keys() {
             self.events
@@ -155,6 +220,37 @@ impl<TSubstream> Ncp<TSubstream> {
         }
     }
 
+    /// Sets the address mapped on the NAT gateway (None if the node is not reachable).
+    pub fn set_external_address(&mut self, address: Option<Multiaddr>) {
+        self.external_address = address;
+        // Let peers know how to reach us now
+        for p in self.connected_peers.keys() {
+            self.events
+                .push_back(NcpEvent::SendPeers { peer_id: p.clone() });
+        }
+    }
+
+    /// Returns the address to advertise.
+    pub fn our_address(&self) -> Option<&Multiaddr> {
+        if self.hidden {
+            return None;
+        }
+        self.advertised_endpoint
+            .as_ref()
+            .or(self.external_address.as_ref())
+    }
+
+    /// Returns relays to advertise, if the node is not reachable from outside.
+    fn our_relays(&self) -> Vec<PeerId> {
+        if self.hidden || self.our_address().is_some() {
+            return Vec::new();
+        }
+        let mut relays: Vec<PeerId> = self.relay_peers.iter().cloned().collect();
+        relays.sort_by(|a, b| a.as_bytes().cmp(b.as_bytes()));
+        relays.truncate(MAX_RELAYS);
+        relays
+    }
+
     // Terminate connection to peer
     pub fn terminate(&mut self, peer_id: PeerId) {
         debug!(target: "stegos_network::ncp", "terminating connection with peer: peer_id={}", peer_id);
@@ -174,6 +270,159 @@ impl<TSubstream> Ncp<TSubstream> {
         }
         nodes
     }
+
+    /// Returns known addresses of the peer.
+    fn known_addresses(&mut self, peer: &PeerId) -> Vec<Multiaddr> {
+        let small = self
+            .known_peers
+            .get(peer.as_bytes())
+            .map(|(_, v)| v.clone())
+            .unwrap_or(SmallVec::new());
+        let addresses: Vec<Multiaddr> = small.iter().map(|v| v.clone()).collect();
+        if addresses.is_empty() {
+            if let Some(entry) = self.address_book.get(peer) {
+                return entry.addresses.clone();
+            }
+        }
+        addresses
+    }
+
+    /// Stores peers received from the neighbor.
+    fn store_peers(&mut self, from: PeerId, message: GetPeersResponse, local_peer_id: &PeerId) {
+        debug!(target: "stegos_network::ncp", "received peers: from_peer={}", from.to_base58());
+        if let Some(address) = message.observed_addr {
+            self.out_events.push_back(NcpOutEvent::ObservedAddress {
+                peer_id: from.clone(),
+                address,
+            });
+        }
+        for peer in message.peers.into_iter() {
+            if peer.peer_id != *local_peer_id {
+                let id = peer.peer_id.clone();
+                if peer.relay {
+                    self.known_relays.insert(id.clone().into_bytes(), ());
+                } else {
+                    self.known_relays.remove(id.as_bytes());
+                }
+                if id == from && peer.relay && self.connected_peers.contains_key(&id) {
+                    self.relay_peers.insert(id.clone());
+                } else if id == from {
+                    self.relay_peers.remove(&id);
+                }
+                // Only the node itself can ask not to be advertised.
+                if id == from && peer.hidden {
+                    self.hidden_peers.insert(id.clone());
+                } else if id == from {
+                    self.hidden_peers.remove(&id);
+                }
+                let hidden = self.hidden_peers.contains(&id);
+                // Only the node itself can tell where it is reachable.
+                let relays = peer.signed_relays().cloned();
+                match (&relays, &peer.relays_signature) {
+                    (Some(relays), Some(signature)) if !relays.is_empty() => {
+                        self.peer_relays
+                            .insert(id.clone().into_bytes(), (relays.clone(), signature.clone()));
+                    }
+                    (Some(_), _) => {
+                        self.peer_relays.remove(id.as_bytes());
+                    }
+                    (None, _) => {
+                        if !peer.relays.is_empty() {
+                            debug!(target: "stegos_network::ncp", "ignoring unsigned relays: peer_id={}, from_peer={}", id, from);
+                        }
+                    }
+                }
+                // Replace information for peer
+                self.known_peers.insert(
+                    id.clone().into_bytes(),
+                    (peer.node_id.clone(), SmallVec::new()),
+                );
+                for addr in peer.addresses.into_iter() {
+                    // Don't store 127.0.0.1 IPs
+                    if is_localhost(&addr) {
+                        continue;
+                    }
+                    // Safe to unwrap, since we initalized entry on previous step
+                    if self
+                        .known_peers
+                        .get_mut(id.as_bytes())
+                        .unwrap()
+                        .1
+                        .iter()
+                        .all(|a| *a != addr)
+                    {
+                        self.known_peers
+                            .get_mut(id.as_bytes())
+                            .unwrap()
+                            .1
+                            .push(addr)
+                    }
+                }
+                let addresses: Vec<Multiaddr> = self
+                    .known_peers
+                    .get(id.as_bytes())
+                    .unwrap()
+                    .1
+                    .iter()
+                    .map(|v| v.clone())
+                    .filter(|v| !is_localhost(v))
+                    .collect();
+                self.address_book.discovered(&id, &peer.node_id, &addresses);
+                if id == from {
+                    self.address_book.seen(&id);
+                }
+                self.out_events.push_back(NcpOutEvent::DiscoveredPeer {
+                    peer_id: peer.peer_id.clone(),
+                    node_id: peer.node_id.clone(),
+                    addresses,
+                    relays,
+                    hidden,
+                });
+            }
+        }
+    }
+
+    /// Builds information about connected peers and ourselves for the neighbor.
+    /// Peers which asked not to be advertised are skipped.
+    fn peers_response(&mut self, peer_id: &PeerId, local_peer_id: &PeerId) -> GetPeersResponse {
+        let mut response = GetPeersResponse {
+            peers: vec![],
+            observed_addr: self.remote_addrs.get(peer_id).cloned(),
+        };
+        let mut connected: Vec<PeerId> = self.connected_peers.keys().map(|v| v.clone()).collect();
+        for peer in connected.drain(..) {
+            if peer == *peer_id || self.hidden_peers.contains(&peer) {
+                continue;
+            }
+            if self.known_peers.get(&peer.clone().into_bytes()).is_none() {
+                continue;
+            }
+            let node_id = self.known_peers.get(&peer.clone().into_bytes()).unwrap().0;
+            let mut peer_info = PeerInfo::new(&peer, &node_id);
+            for addr in self.known_addresses(&peer) {
+                peer_info.addresses.push(addr);
+            }
+            peer_info.relay = self.known_relays.peek(peer.as_bytes()).is_some();
+            if let Some((relays, signature)) = self.peer_relays.peek(peer.as_bytes()) {
+                peer_info.relays = relays.clone();
+                peer_info.relays_signature = Some(signature.clone());
+            }
+            if peer_info.addresses.len() > 0 || peer_info.relays.len() > 0 {
+                response.peers.push(peer_info);
+            }
+        }
+        let mut peer_info = PeerInfo::new(local_peer_id, &self.node_id);
+        // Peers without addresses are neither advertised by NCP nor added to DHT
+        if let Some(address) = self.our_address() {
+            peer_info.addresses.push(address.clone());
+            peer_info.relay = self.relay;
+        }
+        peer_info.relays = self.our_relays();
+        peer_info.hidden = self.hidden;
+        peer_info.sign_relays(&self.network_skey);
+        response.peers.push(peer_info);
+        response
+    }
 }
 
 impl<TSubstream> NetworkBehaviour for Ncp<TSubstream>
@@ -188,16 +437,10 @@ where
     }
 
     fn addresses_of_peer(&mut self, peer: &PeerId) -> Vec<Multiaddr> {
-        let small = self
-            .known_peers
-            .get(peer.as_bytes())
-            .map(|(_, v)| v.clone())
-            .unwrap_or(SmallVec::new());
-        let addresses: Vec<Multiaddr> = small.iter().map(|v| v.clone()).collect();
-        addresses
+        self.known_addresses(peer)
     }
 
-    fn inject_connected(&mut self, id: PeerId, _: ConnectedPoint) {
+    fn inject_connected(&mut self, id: PeerId, endpoint: ConnectedPoint) {
         debug!(target: "stegos_network::ncp", "peer connected: peer_id={}", id.to_base58());
         self.events.push_back(NcpEvent::RequestPeers {
             peer_id: id.clone(),
@@ -205,13 +448,27 @@ where
         self.out_events.push_back(NcpOutEvent::Connected {
             peer_id: id.clone(),
         });
+        self.address_book.connected(&id);
+        let remote_addr = match endpoint {
+            ConnectedPoint::Dialer { address } => address,
+            ConnectedPoint::Listener { send_back_addr, .. } => send_back_addr,
+        };
+        self.remote_addrs.insert(id.clone(), remote_addr);
         self.connected_peers.insert(id, Instant::now());
     }
 
+    fn inject_dial_failure(&mut self, peer_id: &PeerId) {
+        debug!(target: "stegos_network::ncp", "failed to dial peer: peer_id={}", peer_id.to_base58());
+        self.address_book.dial_failed(peer_id);
+    }
+
     fn inject_disconnected(&mut self, id: &PeerId, _: ConnectedPoint) {
         debug!(target: "stegos_network::ncp", "peer disconnected: peer_id={}", id.to_base58());
         self.connected_peers.remove(id);
         self.known_peers.remove(id.as_bytes());
+        self.remote_addrs.remove(id);
+        self.relay_peers.remove(id);
+        self.hidden_peers.remove(id);
         self.out_events.push_back(NcpOutEvent::Disconnected {
             peer_id: id.clone(),
         });
@@ -276,17 +533,49 @@ where
                             + self.delay_between_monitor_events
                             + Duration::from_secs(thread_rng().gen_range(0, 30)),
                     );
+                    self.address_book.save();
                     // refresh peers in known peers, so they wouldn't be purged
                     for p in self.connected_peers.keys() {
                         self.events
                             .push_back(NcpEvent::RequestPeers { peer_id: p.clone() });
                         let _ = self.known_peers.get(p.as_bytes());
                     }
+                    // Keep connections to relays if not reachable from outside
+                    if self.our_address().is_none()
+                        && !self.hidden
+                        && self.relay_peers.len() < MAX_RELAYS
+                    {
+                        let relays: Vec<PeerId> = self
+                            .known_relays
+                            .peek_iter()
+                            .filter_map(|(p, _)| PeerId::from_bytes(p.clone()).ok())
+                            .filter(|p| !self.connected_peers.contains_key(p))
+                            .take(MAX_RELAYS - self.relay_peers.len())
+                            .collect();
+                        for peer_id in relays {
+                            debug!(target: "stegos_network::ncp", "dialing relay: peer_id={}", peer_id);
+                            self.out_events.push_back(NcpOutEvent::DialPeer { peer_id });
+                        }
+                    }
                     if self.connected_peers.len() >= self.max_connections {
                         // Already have max connected_peers
                         continue;
                     }
                     if self.connected_peers.len() < self.min_connections {
+                        // Fall back to saved peers if the table of known peers is exhausted
+                        if self.known_peers.len() == 0 {
+                            for (peer_id, entry) in
+                                self.address_book.best_peers(self.min_connections)
+                            {
+                                if self.connected_peers.contains_key(&peer_id) {
+                                    continue;
+                                }
+                                self.known_peers.insert(
+                                    peer_id.clone().into_bytes(),
+                                    (entry.node_id, entry.addresses.into_iter().collect()),
+                                );
+                            }
+                        }
                         let mut seen_peers: Vec<PeerId> = Vec::new();
                         let mut bad_peer_ids: Vec<Vec<u8>> = Vec::new();
                         for (peer_bytes, _addresses) in self.known_peers.peek_iter() {
@@ -343,79 +632,13 @@ where
         if let Some(event) = self.events.pop_front() {
             match event {
                 NcpEvent::StorePeers { from, message } => {
-                    debug!(target: "stegos_network::ncp", "received peers: from_peer={}", from.to_base58());
-                    for peer in message.peers.into_iter() {
-                        if peer.peer_id != *poll_parameters.local_peer_id() {
-                            let id = peer.peer_id.clone();
-                            // Replace information for peer
-                            self.known_peers.insert(
-                                id.clone().into_bytes(),
-                                (peer.node_id.clone(), SmallVec::new()),
-                            );
-                            for addr in peer.addresses.into_iter() {
-                                // Don't store 127.0.0.1 IPs
-                                if is_localhost(&addr) {
-                                    continue;
-                                }
-                                // Safe to unwrap, since we initalized entry on previous step
-                                if self
-                                    .known_peers
-                                    .get_mut(id.as_bytes())
-                                    .unwrap()
-                                    .1
-                                    .iter()
-                                    .all(|a| *a != addr)
-                                {
-                                    self.known_peers
-                                        .get_mut(id.as_bytes())
-                                        .unwrap()
-                                        .1
-                                        .push(addr)
-                                }
-                            }
-                            self.out_events.push_back(NcpOutEvent::DiscoveredPeer {
-                                peer_id: peer.peer_id.clone(),
-                                node_id: peer.node_id.clone(),
-                                addresses: self
-                                    .known_peers
-                                    .get(id.as_bytes())
-                                    .unwrap()
-                                    .1
-                                    .iter()
-                                    .map(|v| v.clone())
-                                    .filter(|v| !is_localhost(v))
-                                    .collect(),
-                            });
-                        }
-                    }
+                    let local_peer_id = poll_parameters.local_peer_id().clone();
+                    self.store_peers(from, message, &local_peer_id);
                 }
                 NcpEvent::SendPeers { peer_id } => {
                     debug!(target: "stegos_network::ncp", "sending peers info: to_peer={}", peer_id.to_base58());
-                    let mut response = GetPeersResponse { peers: vec![] };
-                    let mut connected: Vec<PeerId> =
-                        self.connected_peers.keys().map(|v| v.clone()).collect();
-                    for peer in connected.drain(..) {
-                        if peer == peer_id {
-                            continue;
-                        }
-                        if self.known_peers.get(&peer.clone().into_bytes()).is_none() {
-                            continue;
-                        }
-                        let node_id = self.known_peers.get(&peer.clone().into_bytes()).unwrap().0;
-                        let mut peer_info = PeerInfo::new(&peer, &node_id);
-                        for addr in self.addresses_of_peer(&peer) {
-                            peer_info.addresses.push(addr);
-                        }
-                        if peer_info.addresses.len() > 0 {
-                            response.peers.push(peer_info);
-                        }
-                    }
-                    let peer = poll_parameters.local_peer_id().clone();
-                    let mut peer_info = PeerInfo::new(&peer, &self.node_id);
-                    if let Some(advertised_endpoint) = &self.advertised_endpoint {
-                        peer_info.addresses.push(advertised_endpoint.clone());
-                    }
-                    response.peers.push(peer_info);
+                    let local_peer_id = poll_parameters.local_peer_id().clone();
+                    let response = self.peers_response(&peer_id, &local_peer_id);
                     return Async::Ready(NetworkBehaviourAction::SendEvent {
                         peer_id,
                         event: NcpSendEvent::Send(NcpMessage::GetPeersResponse { response }),
@@ -495,6 +718,15 @@ pub enum NcpOutEvent {
         node_id: pbc::PublicKey,
         peer_id: PeerId,
         addresses: Vec<Multiaddr>,
+        /// Relays of a peer behind NAT, if announced by the peer itself (empty if reachable).
+        relays: Option<Vec<PeerId>>,
+        /// The peer itself asked not to be advertised.
+        hidden: bool,
+    },
+    /// Our address as seen by the peer.
+    ObservedAddress {
+        peer_id: PeerId,
+        address: Multiaddr,
     },
     Connected {
         peer_id: PeerId,
@@ -515,3 +747,69 @@ pub enum NcpSendEvent {
 pub enum NcpRecvEvent {
     Recv(NcpMessage),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_ncp(hidden: bool, address: &str) -> (Ncp<()>, PeerId) {
+        let mut config = NetworkConfig::default();
+        config.hidden = hidden;
+        let (network_skey, network_pkey) = pbc::make_random_keys();
+        let mut ncp = Ncp::<()>::new(&config, network_pkey, network_skey).unwrap();
+        ncp.set_external_address(Some(address.parse().unwrap()));
+        (ncp, PeerId::random())
+    }
+
+    fn connect(ncp: &mut Ncp<()>, peer_id: &PeerId) {
+        ncp.connected_peers.insert(peer_id.clone(), Instant::now());
+    }
+
+    #[test]
+    fn hidden_peer() {
+        let (mut validator, validator_peer_id) = new_ncp(true, "/ip4/203.0.113.1/tcp/10203");
+        let (mut sentry, sentry_peer_id) = new_ncp(false, "/ip4/203.0.113.2/tcp/10203");
+        let (mut public, public_peer_id) = new_ncp(false, "/ip4/203.0.113.3/tcp/10203");
+        let observer_peer_id = PeerId::random();
+
+        // The validator asks not to be advertised and hides its address.
+        let response = validator.peers_response(&sentry_peer_id, &validator_peer_id);
+        assert_eq!(response.peers.len(), 1);
+        assert_eq!(response.peers[0].peer_id, validator_peer_id);
+        assert!(response.peers[0].hidden);
+        assert!(response.peers[0].addresses.is_empty());
+
+        // The sentry still knows how to reach the validator.
+        let address: Multiaddr = "/ip4/203.0.113.1/tcp/10203".parse().unwrap();
+        sentry
+            .address_book
+            .discovered(&validator_peer_id, &validator.node_id, &[address]);
+        connect(&mut sentry, &validator_peer_id);
+        sentry.out_events.clear();
+        sentry.store_peers(validator_peer_id.clone(), response, &sentry_peer_id);
+        match sentry.out_events.pop_front() {
+            Some(NcpOutEvent::DiscoveredPeer {
+                peer_id, hidden, ..
+            }) => {
+                assert_eq!(peer_id, validator_peer_id);
+                assert!(hidden);
+            }
+            _ => panic!("expected DiscoveredPeer"),
+        }
+        assert!(sentry.hidden_peers.contains(&validator_peer_id));
+
+        // A public peer is advertised as usual.
+        let response = public.peers_response(&sentry_peer_id, &public_peer_id);
+        assert!(!response.peers[0].hidden);
+        connect(&mut sentry, &public_peer_id);
+        sentry.store_peers(public_peer_id.clone(), response, &sentry_peer_id);
+        assert!(!sentry.hidden_peers.contains(&public_peer_id));
+
+        // The sentry doesn't advertise the validator to other peers.
+        let response = sentry.peers_response(&observer_peer_id, &sentry_peer_id);
+        let peers: Vec<PeerId> = response.peers.iter().map(|p| p.peer_id.clone()).collect();
+        assert!(!peers.contains(&validator_peer_id));
+        assert!(peers.contains(&public_peer_id));
+        assert!(peers.contains(&sentry_peer_id));
+    }
+}
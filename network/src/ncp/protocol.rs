@@ -31,6 +31,7 @@ use libp2p_core::{
 use protobuf::Message;
 use std::convert::TryFrom;
 use std::{io, iter};
+use stegos_crypto::hash::{Hash, Hashable, Hasher};
 use stegos_crypto::pbc;
 use tokio::codec::{Decoder, Encoder, Framed};
 use tokio::io::{AsyncRead, AsyncWrite};
@@ -129,8 +130,19 @@ impl Encoder for NcpCodec {
                     for addr in peer.addresses.into_iter() {
                         peer_info.mut_addrs().push(addr.to_vec());
                     }
+                    peer_info.set_relay(peer.relay);
+                    for relay in peer.relays.into_iter() {
+                        peer_info.mut_relays().push(relay.into_bytes());
+                    }
+                    if let Some(signature) = peer.relays_signature {
+                        peer_info.set_relays_signature(signature.to_bytes().to_vec());
+                    }
+                    peer_info.set_hidden(peer.hidden);
                     msg.mut_peers().push(peer_info);
                 }
+                if let Some(addr) = response.observed_addr {
+                    msg.set_observed_addr(addr.to_vec());
+                }
 
                 msg
             }
@@ -183,7 +195,10 @@ impl Decoder for NcpCodec {
             ncp_proto::Message_MessageType::GET_PEERS_REQ => Ok(Some(NcpMessage::GetPeersRequest)),
 
             ncp_proto::Message_MessageType::GET_PEERS_RES => {
-                let mut response = GetPeersResponse { peers: vec![] };
+                let mut response = GetPeersResponse {
+                    peers: vec![],
+                    observed_addr: Multiaddr::try_from(message.get_observed_addr().to_vec()).ok(),
+                };
                 for peer in message.get_peers().into_iter() {
                     let peer_id =
                         PeerId::from_bytes(peer.get_peer_id().to_vec()).map_err(|_| {
@@ -204,12 +219,35 @@ impl Decoder for NcpCodec {
                         peer_id,
                         node_id,
                         addresses: vec![],
+                        relay: peer.get_relay(),
+                        relays: vec![],
+                        relays_signature: None,
+                        hidden: peer.get_hidden(),
                     };
                     for addr in peer.get_addrs().into_iter() {
                         if let Ok(addr_) = Multiaddr::try_from(addr.to_vec()) {
                             peer_info.addresses.push(addr_);
                         }
                     }
+                    for relay in peer.get_relays().into_iter() {
+                        let relay = PeerId::from_bytes(relay.to_vec()).map_err(|_| {
+                            io::Error::new(
+                                io::ErrorKind::InvalidData,
+                                "bad protobuf encoding, failed to decode relay",
+                            )
+                        })?;
+                        peer_info.relays.push(relay);
+                    }
+                    if !peer.get_relays_signature().is_empty() {
+                        let signature = pbc::Signature::try_from_bytes(peer.get_relays_signature())
+                            .map_err(|_| {
+                                io::Error::new(
+                                    io::ErrorKind::InvalidData,
+                                    "bad protobuf encoding, failed to decode relays_signature",
+                                )
+                            })?;
+                        peer_info.relays_signature = Some(signature);
+                    }
                     response.peers.push(peer_info);
                 }
                 Ok(Some(NcpMessage::GetPeersResponse { response }))
@@ -234,11 +272,21 @@ pub struct PeerInfo {
     pub peer_id: PeerId,
     pub node_id: pbc::PublicKey,
     pub addresses: Vec<Multiaddr>,
+    /// Peer relays unicast messages to nodes behind NAT.
+    pub relay: bool,
+    /// Peer is behind NAT and reachable through these relays.
+    pub relays: Vec<PeerId>,
+    /// Signature of `node_id` over `peer_id` and `relays`.
+    pub relays_signature: Option<pbc::Signature>,
+    /// Peer asks not to be advertised to other nodes.
+    pub hidden: bool,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct GetPeersResponse {
     pub peers: Vec<PeerInfo>,
+    /// Address of the requester as seen by the responder.
+    pub observed_addr: Option<Multiaddr>,
 }
 
 impl PeerInfo {
@@ -247,6 +295,36 @@ impl PeerInfo {
             peer_id: peer_id.clone(),
             node_id: node_id.clone(),
             addresses: vec![],
+            relay: false,
+            relays: vec![],
+            relays_signature: None,
+            hidden: false,
+        }
+    }
+
+    fn relays_hash(&self) -> Hash {
+        let mut hasher = Hasher::new();
+        "relays".hash(&mut hasher);
+        self.peer_id.as_bytes().hash(&mut hasher);
+        for relay in self.relays.iter() {
+            relay.as_bytes().hash(&mut hasher);
+        }
+        hasher.result()
+    }
+
+    /// Signs relays of our own node, so that nobody else can announce them.
+    pub fn sign_relays(&mut self, network_skey: &pbc::SecretKey) {
+        let hash = self.relays_hash();
+        self.relays_signature = Some(pbc::sign_hash(&hash, network_skey));
+    }
+
+    /// Returns relays if they were announced by the node itself.
+    pub fn signed_relays(&self) -> Option<&Vec<PeerId>> {
+        let signature = self.relays_signature.as_ref()?;
+        let hash = self.relays_hash();
+        match pbc::check_hash(&hash, signature, &self.node_id) {
+            Ok(()) => Some(&self.relays),
+            Err(_) => None,
         }
     }
 }
@@ -268,25 +346,60 @@ mod tests {
 
         test_one(NcpMessage::Pong);
 
-        let (_, node_id) = pbc::make_random_keys();
+        let (node_skey, node_id) = pbc::make_random_keys();
+
+        let mut peer_info = PeerInfo {
+            peer_id: PeerId::random(),
+            node_id,
+            addresses: vec![
+                "/ip4/1.2.3.4/tcp/1111".parse().unwrap(),
+                "/ip4/1.2.3.4/tcp/1231".parse().unwrap(),
+                "/ip4/1.2.3.4/tcp/1221".parse().unwrap(),
+            ],
+            relay: true,
+            relays: vec![PeerId::random()],
+            relays_signature: None,
+            hidden: true,
+        };
+        peer_info.sign_relays(&node_skey);
 
         let msg = NcpMessage::GetPeersResponse {
             response: GetPeersResponse {
-                peers: vec![PeerInfo {
-                    peer_id: PeerId::random(),
-                    node_id,
-                    addresses: vec![
-                        "/ip4/1.2.3.4/tcp/1111".parse().unwrap(),
-                        "/ip4/1.2.3.4/tcp/1231".parse().unwrap(),
-                        "/ip4/1.2.3.4/tcp/1221".parse().unwrap(),
-                    ],
-                }],
+                peers: vec![peer_info],
+                observed_addr: Some("/ip4/5.6.7.8/tcp/40000".parse().unwrap()),
             },
         };
 
         test_one(msg);
     }
 
+    #[test]
+    fn signed_relays() {
+        let (node_skey, node_id) = pbc::make_random_keys();
+        let (other_skey, _other_id) = pbc::make_random_keys();
+        let mut peer_info = PeerInfo::new(&PeerId::random(), &node_id);
+        peer_info.relays = vec![PeerId::random(), PeerId::random()];
+        assert_eq!(peer_info.signed_relays(), None);
+
+        peer_info.sign_relays(&node_skey);
+        assert_eq!(peer_info.signed_relays(), Some(&peer_info.relays));
+
+        // Relays were replaced by a third party.
+        let mut forged = peer_info.clone();
+        forged.relays = vec![PeerId::random()];
+        assert_eq!(forged.signed_relays(), None);
+
+        // Relays were signed by somebody else.
+        let mut forged = peer_info.clone();
+        forged.sign_relays(&other_skey);
+        assert_eq!(forged.signed_relays(), None);
+
+        // Announced by a different peer.
+        let mut forged = peer_info.clone();
+        forged.peer_id = PeerId::random();
+        assert_eq!(forged.signed_relays(), None);
+    }
+
     fn test_one(msg: NcpMessage) {
         let msg_server = msg.clone();
         let msg_client = msg.clone();
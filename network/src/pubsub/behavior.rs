@@ -21,8 +21,10 @@
 use super::handler::FloodsubHandler;
 use super::metrics;
 use super::protocol::{
-    FloodsubMessage, FloodsubRpc, FloodsubSubscription, FloodsubSubscriptionAction,
+    FloodsubMessage, FloodsubRpc, FloodsubSubscription, FloodsubSubscriptionAction, MessageOrigin,
 };
+use super::queue::OutboundQueue;
+use crate::config::BandwidthConfig;
 
 use futures::prelude::*;
 use libp2p_core::{ConnectedPoint, Multiaddr, PeerId};
@@ -37,6 +39,7 @@ use std::{
     collections::{hash_map::HashMap, hash_set::HashSet, VecDeque},
     marker::PhantomData,
 };
+use stegos_crypto::pbc;
 use tokio::io::{AsyncRead, AsyncWrite};
 use tokio_timer::Delay;
 use update_rate::{RateCounter, RollingRateCounter};
@@ -45,6 +48,8 @@ use update_rate::{RateCounter, RollingRateCounter};
 const PUBSUB_SAMPLES: u64 = 100;
 const METRICS_UPDATE_INTERVAL: Duration = Duration::from_secs(5);
 const LRU_EXPIRE_TIME: Duration = Duration::from_secs(60); // 1 minute to allow transaction retransmit
+/// Maximal number of remembered messages with invalid envelopes.
+const REJECTED_CAPACITY: usize = 100_000;
 
 /// Network behaviour that automatically identifies nodes periodically, and returns information
 /// about them.
@@ -68,26 +73,50 @@ pub struct Floodsub<TSubstream> {
     /// erroneously.
     subscribed_topics: SmallVec<[String; 16]>,
 
-    /// We keep track of the messages we received (in the format `hash(source ID, seq_no)`) so that
+    /// We keep track of the messages we received (in the format `hash(topic, data)`) so that
     /// we don't dispatch the same message twice if we receive it twice on the network.
     received: LruCache<u64, ()>,
 
+    /// Envelope ids of the messages with invalid signatures, to verify each envelope once.
+    rejected: LruCache<u64, ()>,
+
+    /// Topics on which unsigned messages are dropped.
+    signed_topics: HashSet<String>,
+
     /// Tracking incoming message rate for peers
     incoming_rates: HashMap<PeerId, RollingRateCounter>,
 
+    /// Peers which sent something since the last metrics update
+    active_remotes: HashSet<PeerId>,
+
+    /// Maximal allowed incoming message rate
+    max_message_rate: f64,
+
+    /// Peers we ignore messages from
+    graylisted: HashSet<PeerId>,
+
     /// Metrics uodate delay (update metrics at this interval)
     metrics_update_delay: Delay,
 
     /// Do we relay (disabled on edge nodes)
     relaying: bool,
 
+    /// Outgoing messages waiting for bandwidth limits
+    outbound: OutboundQueue,
+
     /// Marker to pin the generics.
     marker: PhantomData<TSubstream>,
 }
 
 impl<TSubstream> Floodsub<TSubstream> {
     /// Creates a `Floodsub`.
-    pub fn new(local_peer_id: PeerId, relaying: bool) -> Self {
+    pub fn new(
+        local_peer_id: PeerId,
+        relaying: bool,
+        max_message_rate: f64,
+        bandwidth: &BandwidthConfig,
+        signed_topics: &[String],
+    ) -> Self {
         Floodsub {
             events: VecDeque::new(),
             local_peer_id,
@@ -96,9 +125,18 @@ impl<TSubstream> Floodsub<TSubstream> {
             allowed_remotes: HashSet::new(),
             subscribed_topics: SmallVec::new(),
             received: LruCache::with_expiry_duration_and_capacity(LRU_EXPIRE_TIME, 1_000_000),
+            rejected: LruCache::with_expiry_duration_and_capacity(
+                LRU_EXPIRE_TIME,
+                REJECTED_CAPACITY,
+            ),
+            signed_topics: signed_topics.iter().cloned().collect(),
             incoming_rates: HashMap::new(),
+            active_remotes: HashSet::new(),
+            max_message_rate,
+            graylisted: HashSet::new(),
             metrics_update_delay: Delay::new(Instant::now() + METRICS_UPDATE_INTERVAL),
             relaying,
+            outbound: OutboundQueue::new(bandwidth),
             marker: PhantomData,
         }
     }
@@ -122,6 +160,7 @@ impl<TSubstream> Floodsub<TSubstream> {
                         topic: topic.clone(),
                         action: FloodsubSubscriptionAction::Subscribe,
                     }],
+                    control: Vec::new(),
                 }),
             });
         }
@@ -133,8 +172,12 @@ impl<TSubstream> Floodsub<TSubstream> {
     /// Publishes a message to the network.
     ///
     /// > **Note**: Doesn't do anything if we're not subscribed to the topic.
-    pub fn publish(&mut self, topic: String, data: Vec<u8>) {
-        let message = FloodsubMessage { data, topic };
+    pub fn publish(&mut self, topic: String, data: Vec<u8>, origin: Option<MessageOrigin>) {
+        let message = FloodsubMessage {
+            data,
+            topic,
+            origin,
+        };
 
         // Don't publish the message if we're not subscribed ourselves to any of the topics.
         if !self.subscribed_topics.iter().any(|t| t == &message.topic) {
@@ -151,13 +194,7 @@ impl<TSubstream> Floodsub<TSubstream> {
             }
 
             trace!(target: "stegos_network::pubsub", "sending message to peer: peer_id={}", peer_id);
-            self.events.push_back(NetworkBehaviourAction::SendEvent {
-                peer_id: peer_id.clone(),
-                event: FloodsubSendEvent::Publish(FloodsubRpc {
-                    subscriptions: Vec::new(),
-                    messages: vec![message.clone()],
-                }),
-            });
+            self.outbound.push(peer_id.clone(), message.clone());
         }
     }
 
@@ -193,11 +230,22 @@ impl<TSubstream> Floodsub<TSubstream> {
                         topic: topic.clone(),
                         action: FloodsubSubscriptionAction::Subscribe,
                     }],
+                    control: Vec::new(),
                 }),
             });
         }
     }
 
+    /// Ignores all messages from the peer.
+    pub fn graylist(&mut self, peer_id: &PeerId) {
+        self.graylisted.insert(peer_id.clone());
+    }
+
+    /// Accepts messages from the peer again.
+    pub fn ungraylist(&mut self, peer_id: &PeerId) {
+        self.graylisted.remove(peer_id);
+    }
+
     pub fn enable_incoming(&mut self, peer_id: &PeerId) {
         debug!(target: "stegos_network::gatekeeper", "enabling pubsub listener: peer_id={}", peer_id);
         if !self.connected_peers.contains(peer_id) {
@@ -244,6 +292,7 @@ where
         debug_assert!(was_in);
         self.allowed_remotes.remove(id);
         self.unlocked_remotes.remove(id);
+        self.outbound.remove_peer(id);
         super::metrics::CONNECTED_PEERS.set(self.connected_peers.len() as i64);
         super::metrics::UNLOCKED_PEERS.set(self.unlocked_remotes.len() as i64);
     }
@@ -253,12 +302,18 @@ where
             .entry(propagation_source.clone())
             .or_insert(RollingRateCounter::new(PUBSUB_SAMPLES))
             .update();
+        self.active_remotes.insert(propagation_source.clone());
 
         if !self.allowed_remotes.contains(&propagation_source) {
             debug!(target: "stegos_network::pubsub", "event from unwanted peer, dropping: peer_id={}", propagation_source);
             return;
         }
 
+        if self.graylisted.contains(&propagation_source) {
+            trace!(target: "stegos_network::pubsub", "event from graylisted peer, dropping: peer_id={}", propagation_source);
+            return;
+        }
+
         match event {
             FloodsubRecvEvent::Message(event) => {
                 // Update connected peers topics
@@ -296,9 +351,6 @@ where
                     }
                 }
 
-                // List of messages we're going to propagate on the network.
-                let mut rpcs_to_dispatch: Vec<(PeerId, FloodsubRpc)> = Vec::new();
-
                 for message in event.messages {
                     // Use `self.received` to skip the messages that we have already received in the past.
                     // Note that this can false positive.
@@ -306,15 +358,54 @@ where
                         trace!(target: "stegos_network::pubsub", "LRU cache hit");
                         super::metrics::LRU_CACHE_SIZE.set(self.received.len() as i64);
                         continue;
+                    }
+                    // Don't dispatch or forward messages with invalid envelopes.
+                    // Replays of the same envelope are dropped without checking the signature again.
+                    // Relays verify envelopes before forwarding, so on signed topics the source is to blame.
+                    let envelope_id = message.envelope_digest();
+                    let from = if self.rejected.contains_key(&envelope_id) {
+                        trace!(target: "stegos_network::pubsub", "rejected cache hit");
+                        None
                     } else {
-                        self.received.notify_insert(message.digest(), ());
+                        match message.verify() {
+                            Ok(from) => Some(from),
+                            Err(e) => {
+                                debug!(target: "stegos_network::pubsub", "invalid broadcast signature, dropping: topic={}, peer_id={}, error={}", message.topic, propagation_source, e);
+                                self.rejected.insert(envelope_id, ());
+                                None
+                            }
+                        }
+                    };
+                    let from = match from {
+                        Some(from) => from,
+                        None => {
+                            if self.signed_topics.contains(&message.topic) {
+                                self.events.push_back(NetworkBehaviourAction::GenerateEvent(
+                                    FloodsubEvent::InvalidSignature {
+                                        peer_id: propagation_source.clone(),
+                                        topic: message.topic,
+                                    },
+                                ));
+                            }
+                            continue;
+                        }
+                    };
+                    // Unsigned copies are dropped before caching, so they don't shadow signed ones.
+                    if from.is_none() && self.signed_topics.contains(&message.topic) {
+                        debug!(target: "stegos_network::pubsub", "unsigned broadcast message, dropping: topic={}, peer_id={}", message.topic, propagation_source);
+                        continue;
                     }
+                    self.received.notify_insert(message.digest(), ());
                     super::metrics::LRU_CACHE_SIZE.set(self.received.len() as i64);
                     trace!(target: "stegos_network::pubsub", "processing message: peer_id={}", propagation_source);
 
                     // Add the message to be dispatched to the user.
                     if self.subscribed_topics.iter().any(|t| t == &message.topic) {
-                        let event = FloodsubEvent::Message(message.clone());
+                        let event = FloodsubEvent::Message {
+                            source: propagation_source.clone(),
+                            from,
+                            message: message.clone(),
+                        };
                         self.events
                             .push_back(NetworkBehaviourAction::GenerateEvent(event));
                     }
@@ -335,26 +426,9 @@ where
                             continue;
                         }
 
-                        if let Some(pos) = rpcs_to_dispatch.iter().position(|(p, _)| p == peer_id) {
-                            rpcs_to_dispatch[pos].1.messages.push(message.clone());
-                        } else {
-                            rpcs_to_dispatch.push((
-                                peer_id.clone(),
-                                FloodsubRpc {
-                                    subscriptions: Vec::new(),
-                                    messages: vec![message.clone()],
-                                },
-                            ));
-                        }
+                        self.outbound.push(peer_id.clone(), message.clone());
                     }
                 }
-
-                for (peer_id, rpc) in rpcs_to_dispatch {
-                    self.events.push_back(NetworkBehaviourAction::SendEvent {
-                        peer_id,
-                        event: FloodsubSendEvent::Publish(rpc),
-                    });
-                }
             }
         }
     }
@@ -375,7 +449,19 @@ where
                         metrics::INCOMING_RATES
                             .with_label_values(&[&peer_id.clone().to_base58()])
                             .set(counter.rate());
+                        if self.active_remotes.contains(peer_id)
+                            && counter.rate() > self.max_message_rate
+                        {
+                            debug!(target: "stegos_network::pubsub", "incoming rate exceeded: peer_id={}, rate={}", peer_id, counter.rate());
+                            self.events.push_back(NetworkBehaviourAction::GenerateEvent(
+                                FloodsubEvent::RateLimitExceeded {
+                                    peer_id: peer_id.clone(),
+                                    rate: counter.rate(),
+                                },
+                            ));
+                        }
                     }
+                    self.active_remotes.clear();
                     self.metrics_update_delay
                         .reset(Instant::now() + METRICS_UPDATE_INTERVAL);
                 }
@@ -391,6 +477,17 @@ where
             return Async::Ready(event);
         }
 
+        if let Async::Ready((peer_id, message)) = self.outbound.poll() {
+            return Async::Ready(NetworkBehaviourAction::SendEvent {
+                peer_id,
+                event: FloodsubSendEvent::Publish(FloodsubRpc {
+                    subscriptions: Vec::new(),
+                    messages: vec![message],
+                    control: Vec::new(),
+                }),
+            });
+        }
+
         Async::NotReady
     }
 }
@@ -399,7 +496,14 @@ where
 #[derive(Debug)]
 pub enum FloodsubEvent {
     /// A message has been received.
-    Message(FloodsubMessage),
+    Message {
+        /// Remote that has forwarded the message to us.
+        source: PeerId,
+        /// Network key of the publisher, if the message was signed.
+        from: Option<pbc::PublicKey>,
+        /// The message.
+        message: FloodsubMessage,
+    },
 
     /// A remote subscribed to a topic.
     Subscribed {
@@ -416,6 +520,22 @@ pub enum FloodsubEvent {
         /// The topic it has subscribed from.
         topic: String,
     },
+
+    /// A remote forwarded a message with a bad signature on a signed topic.
+    InvalidSignature {
+        /// Remote that has forwarded the message.
+        peer_id: PeerId,
+        /// The topic of the message.
+        topic: String,
+    },
+
+    /// A remote sends messages too often.
+    RateLimitExceeded {
+        /// Remote that has exceeded the limit.
+        peer_id: PeerId,
+        /// Messages per second.
+        rate: f64,
+    },
 }
 
 #[derive(Debug)]
@@ -0,0 +1,1232 @@
+//
+// MIT License
+//
+// Copyright (c) 2019 Stegos AG
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Gossipsub: forwards messages to a bounded mesh of peers per topic
+//! and lazily gossips message ids to some other peers.
+
+use super::behavior::{FloodsubEvent, FloodsubRecvEvent, FloodsubSendEvent};
+use super::handler::FloodsubHandler;
+use super::mcache::MessageCache;
+use super::metrics;
+use super::protocol::{
+    FloodsubConfig, FloodsubMessage, FloodsubRpc, FloodsubSubscription, FloodsubSubscriptionAction,
+    GossipControl, MessageOrigin,
+};
+use super::queue::OutboundQueue;
+use crate::config::{BandwidthConfig, GossipsubConfig};
+
+use failure::{format_err, Error};
+use futures::prelude::*;
+use libp2p_core::{ConnectedPoint, Multiaddr, PeerId};
+use libp2p_swarm::{
+    protocols_handler::ProtocolsHandler, NetworkBehaviour, NetworkBehaviourAction, PollParameters,
+};
+use log::{debug, trace};
+use lru_time_cache::LruCache;
+use rand::seq::SliceRandom;
+use smallvec::SmallVec;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::marker::PhantomData;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_timer::Delay;
+use update_rate::{RateCounter, RollingRateCounter};
+
+// How many samples to use for rate calculation
+const PUBSUB_SAMPLES: u64 = 100;
+const METRICS_UPDATE_INTERVAL: Duration = Duration::from_secs(5);
+const LRU_EXPIRE_TIME: Duration = Duration::from_secs(60); // 1 minute to allow transaction retransmit
+/// Maximal number of remembered messages with invalid envelopes.
+const REJECTED_CAPACITY: usize = 100_000;
+/// Maximal number of message ids requested by one IWANT.
+const MAX_IWANT_IDS: usize = 500;
+
+/// Network behaviour which implements the gossipsub protocol.
+/// Has the same API as `Floodsub`.
+pub struct Gossipsub<TSubstream> {
+    /// Mesh parameters.
+    config: GossipsubConfig,
+
+    /// Events that need to be yielded to the outside when polling.
+    events: VecDeque<NetworkBehaviourAction<FloodsubSendEvent, FloodsubEvent>>,
+
+    /// Peer id of the local node.
+    local_peer_id: PeerId,
+
+    /// List of peers the network is connected to.
+    connected_peers: HashSet<PeerId>,
+
+    /// List of peers we are allowed to send to, and the topics that they're subscribed to.
+    unlocked_remotes: HashMap<PeerId, SmallVec<[String; 8]>>,
+
+    /// List of peers we accept messages from
+    allowed_remotes: HashSet<PeerId>,
+
+    /// List of topics we're subscribed to.
+    subscribed_topics: SmallVec<[String; 16]>,
+
+    /// Peers we forward messages of subscribed topics to.
+    mesh: HashMap<String, HashSet<PeerId>>,
+
+    /// Peers we publish messages of topics we aren't subscribed to.
+    fanout: HashMap<String, HashSet<PeerId>>,
+
+    /// Time of the last publish to fanout topics.
+    fanout_last_pub: HashMap<String, Instant>,
+
+    /// Ids of the messages we received, to dispatch each message once.
+    received: LruCache<u64, ()>,
+
+    /// Envelope ids of the messages with invalid signatures, to verify each envelope once.
+    rejected: LruCache<u64, ()>,
+
+    /// Topics on which unsigned messages are dropped.
+    signed_topics: HashSet<String>,
+
+    /// Recent messages, to answer IWANT requests.
+    mcache: MessageCache,
+
+    /// Tracking incoming message rate for peers
+    incoming_rates: HashMap<PeerId, RollingRateCounter>,
+
+    /// Peers which sent something since the last metrics update
+    active_remotes: HashSet<PeerId>,
+
+    /// Maximal allowed incoming message rate
+    max_message_rate: f64,
+
+    /// Peers we ignore messages from
+    graylisted: HashSet<PeerId>,
+
+    /// Metrics uodate delay (update metrics at this interval)
+    metrics_update_delay: Delay,
+
+    /// Mesh maintenance timer.
+    heartbeat: Delay,
+
+    /// Do we relay (disabled on edge nodes)
+    relaying: bool,
+
+    /// Outgoing messages waiting for bandwidth limits
+    outbound: OutboundQueue,
+
+    /// Marker to pin the generics.
+    marker: PhantomData<TSubstream>,
+}
+
+impl<TSubstream> Gossipsub<TSubstream> {
+    /// Creates a `Gossipsub`.
+    pub fn new(
+        local_peer_id: PeerId,
+        relaying: bool,
+        max_message_rate: f64,
+        bandwidth: &BandwidthConfig,
+        signed_topics: &[String],
+        config: GossipsubConfig,
+    ) -> Result<Self, Error> {
+        if config.heartbeat_interval == 0 {
+            return Err(format_err!(
+                "Invalid gossipsub.heartbeat_interval: must be positive"
+            ));
+        }
+        if config.mesh_n == 0
+            || config.mesh_n_low > config.mesh_n
+            || config.mesh_n > config.mesh_n_high
+        {
+            return Err(format_err!(
+                "Invalid gossipsub mesh size: expected mesh_n_low <= mesh_n <= mesh_n_high and mesh_n > 0, got mesh_n_low={}, mesh_n={}, mesh_n_high={}",
+                config.mesh_n_low,
+                config.mesh_n,
+                config.mesh_n_high
+            ));
+        }
+        if config.history_length == 0 || config.history_gossip > config.history_length {
+            return Err(format_err!(
+                "Invalid gossipsub history: expected history_gossip <= history_length and history_length > 0, got history_gossip={}, history_length={}",
+                config.history_gossip,
+                config.history_length
+            ));
+        }
+        let mcache = MessageCache::new(config.history_gossip, config.history_length);
+        let heartbeat = Delay::new(Instant::now() + Duration::from_secs(config.heartbeat_interval));
+        Ok(Gossipsub {
+            config,
+            events: VecDeque::new(),
+            local_peer_id,
+            connected_peers: HashSet::new(),
+            unlocked_remotes: HashMap::new(),
+            allowed_remotes: HashSet::new(),
+            subscribed_topics: SmallVec::new(),
+            mesh: HashMap::new(),
+            fanout: HashMap::new(),
+            fanout_last_pub: HashMap::new(),
+            received: LruCache::with_expiry_duration_and_capacity(LRU_EXPIRE_TIME, 1_000_000),
+            rejected: LruCache::with_expiry_duration_and_capacity(
+                LRU_EXPIRE_TIME,
+                REJECTED_CAPACITY,
+            ),
+            signed_topics: signed_topics.iter().cloned().collect(),
+            mcache,
+            incoming_rates: HashMap::new(),
+            active_remotes: HashSet::new(),
+            max_message_rate,
+            graylisted: HashSet::new(),
+            metrics_update_delay: Delay::new(Instant::now() + METRICS_UPDATE_INTERVAL),
+            heartbeat,
+            relaying,
+            outbound: OutboundQueue::new(bandwidth),
+            marker: PhantomData,
+        })
+    }
+
+    /// Subscribes to a topic and joins its mesh.
+    ///
+    /// Returns true if the subscription worked. Returns false if we were already subscribed.
+    pub fn subscribe(&mut self, topic: String) -> bool {
+        if self.subscribed_topics.iter().any(|t| t == &topic) {
+            return false;
+        }
+
+        let peers: Vec<PeerId> = self.unlocked_remotes.keys().cloned().collect();
+        for peer_id in peers {
+            self.send_subscriptions(peer_id, vec![topic.clone()]);
+        }
+        self.subscribed_topics.push(topic.clone());
+
+        // Reuse fanout peers, if any.
+        let mut peers = self.fanout.remove(&topic).unwrap_or_default();
+        self.fanout_last_pub.remove(&topic);
+        metrics::GOSSIP_FANOUT_PEERS
+            .with_label_values(&[&topic])
+            .set(0);
+        if peers.len() < self.config.mesh_n {
+            let count = self.config.mesh_n - peers.len();
+            let extra = self.random_peers(&topic, count, |p| peers.contains(p));
+            peers.extend(extra);
+        }
+        debug!(target: "stegos_network::pubsub", "joined mesh: topic={}, peers={}", topic, peers.len());
+        for peer_id in peers.iter() {
+            self.send_control(
+                peer_id.clone(),
+                GossipControl::Graft {
+                    topic: topic.clone(),
+                },
+            );
+        }
+        metrics::GOSSIP_MESH_PEERS
+            .with_label_values(&[&topic])
+            .set(peers.len() as i64);
+        self.mesh.insert(topic, peers);
+        true
+    }
+
+    /// Publishes a message to the network.
+    ///
+    /// Unlike floodsub, works even if we're not subscribed to the topic.
+    pub fn publish(&mut self, topic: String, data: Vec<u8>, origin: Option<MessageOrigin>) {
+        let message = FloodsubMessage {
+            data,
+            topic,
+            origin,
+        };
+        self.received.notify_insert(message.digest(), ());
+        metrics::LRU_CACHE_SIZE.set(self.received.len() as i64);
+        self.mcache.put(message.clone());
+
+        let mesh_n = self.config.mesh_n;
+        let peers: Vec<PeerId> = match self.mesh.get(&message.topic) {
+            Some(mesh) if !mesh.is_empty() => mesh.iter().cloned().collect(),
+            // The mesh hasn't been built yet.
+            Some(_) => self.random_peers(&message.topic, mesh_n, |_| false),
+            None => {
+                self.fanout_last_pub
+                    .insert(message.topic.clone(), Instant::now());
+                let mut fanout = self.fanout.get(&message.topic).cloned().unwrap_or_default();
+                if fanout.is_empty() {
+                    fanout = self
+                        .random_peers(&message.topic, mesh_n, |_| false)
+                        .into_iter()
+                        .collect();
+                    metrics::GOSSIP_FANOUT_PEERS
+                        .with_label_values(&[&message.topic])
+                        .set(fanout.len() as i64);
+                    self.fanout.insert(message.topic.clone(), fanout.clone());
+                }
+                fanout.into_iter().collect()
+            }
+        };
+
+        for peer_id in peers {
+            trace!(target: "stegos_network::pubsub", "sending message to peer: peer_id={}", peer_id);
+            self.send_messages(peer_id, vec![message.clone()]);
+        }
+    }
+
+    pub fn enable_outgoing(&mut self, peer_id: &PeerId) {
+        debug!(target: "stegos_network::pubsub", "enabling gossipsub dialer: peer_id={}", peer_id);
+        if !self.connected_peers.contains(peer_id) {
+            debug!(target: "stegos_network::pubsub", "peer appears to be disconnected: peer_id={}", peer_id);
+            return;
+        }
+
+        if !self.unlocked_remotes.contains_key(peer_id) {
+            self.unlocked_remotes
+                .insert(peer_id.clone(), SmallVec::new());
+        }
+        metrics::UNLOCKED_PEERS.set(self.unlocked_remotes.len() as i64);
+
+        if !self.allowed_remotes.contains(peer_id) {
+            debug!(target: "stegos_network::pubsub", "autoenabling receive: peer_id={}", peer_id);
+            self.allowed_remotes.insert(peer_id.clone());
+        }
+
+        // We need to send our subscriptions to the newly-enabled node.
+        if !self.subscribed_topics.is_empty() {
+            let topics = self.subscribed_topics.iter().cloned().collect();
+            self.send_subscriptions(peer_id.clone(), topics);
+        }
+    }
+
+    pub fn enable_incoming(&mut self, peer_id: &PeerId) {
+        debug!(target: "stegos_network::pubsub", "enabling gossipsub listener: peer_id={}", peer_id);
+        if !self.connected_peers.contains(peer_id) {
+            debug!(target: "stegos_network::pubsub", "peer appears to be disconnected: peer_id={}", peer_id);
+            return;
+        }
+        self.allowed_remotes.insert(peer_id.clone());
+    }
+
+    /// Ignores all messages from the peer.
+    pub fn graylist(&mut self, peer_id: &PeerId) {
+        self.graylisted.insert(peer_id.clone());
+    }
+
+    /// Accepts messages from the peer again.
+    pub fn ungraylist(&mut self, peer_id: &PeerId) {
+        self.graylisted.remove(peer_id);
+    }
+
+    /// Returns true if the peer can receive messages of the topic.
+    fn is_subscribed_peer(&self, peer_id: &PeerId, topic: &str) -> bool {
+        self.unlocked_remotes
+            .get(peer_id)
+            .map(|topics| topics.iter().any(|t| t == topic))
+            .unwrap_or(false)
+    }
+
+    /// Chooses up to `count` random peers subscribed to the topic.
+    fn random_peers<F>(&self, topic: &str, count: usize, exclude: F) -> Vec<PeerId>
+    where
+        F: Fn(&PeerId) -> bool,
+    {
+        let mut peers: Vec<PeerId> = self
+            .unlocked_remotes
+            .iter()
+            .filter(|(peer_id, topics)| topics.iter().any(|t| t == topic) && !exclude(*peer_id))
+            .map(|(peer_id, _topics)| peer_id.clone())
+            .collect();
+        peers.shuffle(&mut rand::thread_rng());
+        peers.truncate(count);
+        peers
+    }
+
+    fn send_subscriptions(&mut self, peer_id: PeerId, topics: Vec<String>) {
+        let subscriptions = topics
+            .into_iter()
+            .map(|topic| FloodsubSubscription {
+                topic,
+                action: FloodsubSubscriptionAction::Subscribe,
+            })
+            .collect();
+        self.events.push_back(NetworkBehaviourAction::SendEvent {
+            peer_id,
+            event: FloodsubSendEvent::Publish(FloodsubRpc {
+                messages: Vec::new(),
+                subscriptions,
+                control: Vec::new(),
+            }),
+        });
+    }
+
+    fn send_messages(&mut self, peer_id: PeerId, messages: Vec<FloodsubMessage>) {
+        for message in messages {
+            self.outbound.push(peer_id.clone(), message);
+        }
+    }
+
+    fn send_control(&mut self, peer_id: PeerId, control: GossipControl) {
+        trace!(target: "stegos_network::pubsub", "sending control: peer_id={}, control={:?}", peer_id, control);
+        metrics::GOSSIP_OUTGOING_CONTROL
+            .with_label_values(&[control_type(&control)])
+            .inc();
+        self.events.push_back(NetworkBehaviourAction::SendEvent {
+            peer_id,
+            event: FloodsubSendEvent::Publish(FloodsubRpc {
+                messages: Vec::new(),
+                subscriptions: Vec::new(),
+                control: vec![control],
+            }),
+        });
+    }
+
+    /// Removes the peer from mesh and fanout of the topic.
+    fn forget_peer_topic(&mut self, peer_id: &PeerId, topic: &str) {
+        if let Some(peers) = self.mesh.get_mut(topic) {
+            peers.remove(peer_id);
+        }
+        if let Some(peers) = self.fanout.get_mut(topic) {
+            peers.remove(peer_id);
+        }
+    }
+
+    fn handle_message(&mut self, propagation_source: &PeerId, message: FloodsubMessage) {
+        // Note that this can false positive.
+        let id = message.digest();
+        if self.received.contains_key(&id) {
+            trace!(target: "stegos_network::pubsub", "LRU cache hit");
+            metrics::GOSSIP_DUPLICATES.inc();
+            return;
+        }
+        // Don't dispatch, gossip or forward messages with invalid envelopes.
+        // Replays of the same envelope are dropped without checking the signature again.
+        let envelope_id = message.envelope_digest();
+        if self.rejected.contains_key(&envelope_id) {
+            trace!(target: "stegos_network::pubsub", "rejected cache hit");
+            self.invalid_signature(propagation_source, message.topic);
+            return;
+        }
+        let from = match message.verify() {
+            Ok(from) => from,
+            Err(e) => {
+                debug!(target: "stegos_network::pubsub", "invalid broadcast signature, dropping: topic={}, peer_id={}, error={}", message.topic, propagation_source, e);
+                self.rejected.insert(envelope_id, ());
+                self.invalid_signature(propagation_source, message.topic);
+                return;
+            }
+        };
+        // Unsigned copies are dropped before caching, so they don't shadow signed ones.
+        if from.is_none() && self.signed_topics.contains(&message.topic) {
+            debug!(target: "stegos_network::pubsub", "unsigned broadcast message, dropping: topic={}, peer_id={}", message.topic, propagation_source);
+            return;
+        }
+        self.received.notify_insert(id, ());
+        metrics::LRU_CACHE_SIZE.set(self.received.len() as i64);
+        trace!(target: "stegos_network::pubsub", "processing message: peer_id={}", propagation_source);
+        self.mcache.put(message.clone());
+
+        // Add the message to be dispatched to the user.
+        if self.subscribed_topics.iter().any(|t| t == &message.topic) {
+            let event = FloodsubEvent::Message {
+                source: propagation_source.clone(),
+                from,
+                message: message.clone(),
+            };
+            self.events
+                .push_back(NetworkBehaviourAction::GenerateEvent(event));
+        }
+
+        if !self.relaying {
+            trace!(target: "stegos_network::pubsub", "skipping message forwarding...");
+            return;
+        }
+
+        // Forward the message to the mesh or to random peers if we aren't in the mesh.
+        let peers: Vec<PeerId> = match self.mesh.get(&message.topic) {
+            Some(mesh) => mesh
+                .iter()
+                .filter(|peer_id| *peer_id != propagation_source)
+                .cloned()
+                .collect(),
+            None => self.random_peers(&message.topic, self.config.mesh_n, |peer_id| {
+                peer_id == propagation_source
+            }),
+        };
+        for peer_id in peers {
+            self.send_messages(peer_id, vec![message.clone()]);
+        }
+    }
+
+    /// Reports the propagation source of an invalid envelope on a signed topic.
+    /// Relays verify envelopes before forwarding, so the source is to blame.
+    fn invalid_signature(&mut self, propagation_source: &PeerId, topic: String) {
+        if !self.signed_topics.contains(&topic) {
+            return;
+        }
+        let event = FloodsubEvent::InvalidSignature {
+            peer_id: propagation_source.clone(),
+            topic,
+        };
+        self.events
+            .push_back(NetworkBehaviourAction::GenerateEvent(event));
+    }
+
+    fn handle_control(&mut self, propagation_source: &PeerId, control: GossipControl) {
+        trace!(target: "stegos_network::pubsub", "received control: peer_id={}, control={:?}", propagation_source, control);
+        metrics::GOSSIP_INCOMING_CONTROL
+            .with_label_values(&[control_type(&control)])
+            .inc();
+        // We can't send anything to the peer until the handshake is finished.
+        if !self.unlocked_remotes.contains_key(propagation_source) {
+            return;
+        }
+
+        match control {
+            GossipControl::Graft { topic } => {
+                if let Some(peers) = self.mesh.get_mut(&topic) {
+                    peers.insert(propagation_source.clone());
+                } else {
+                    self.send_control(propagation_source.clone(), GossipControl::Prune { topic });
+                }
+            }
+            GossipControl::Prune { topic } => {
+                if let Some(peers) = self.mesh.get_mut(&topic) {
+                    peers.remove(propagation_source);
+                }
+            }
+            GossipControl::IHave { topic, message_ids } => {
+                if !self.subscribed_topics.iter().any(|t| t == &topic) {
+                    return;
+                }
+                let mut wanted = Vec::new();
+                for id in message_ids.into_iter().take(MAX_IWANT_IDS) {
+                    if !self.received.contains_key(&id) {
+                        wanted.push(id);
+                    }
+                }
+                if !wanted.is_empty() {
+                    let control = GossipControl::IWant {
+                        message_ids: wanted,
+                    };
+                    self.send_control(propagation_source.clone(), control);
+                }
+            }
+            GossipControl::IWant { message_ids } => {
+                let messages: Vec<FloodsubMessage> = message_ids
+                    .iter()
+                    .take(MAX_IWANT_IDS)
+                    .filter_map(|id| self.mcache.get(id))
+                    .cloned()
+                    .collect();
+                if !messages.is_empty() {
+                    self.send_messages(propagation_source.clone(), messages);
+                }
+            }
+        }
+    }
+
+    /// Maintains mesh and fanout, gossips recent messages.
+    fn heartbeat(&mut self) {
+        let mesh_n = self.config.mesh_n;
+
+        // Keep the mesh size between mesh_n_low and mesh_n_high.
+        let topics: Vec<String> = self.mesh.keys().cloned().collect();
+        for topic in topics {
+            let mut peers = self.mesh.remove(&topic).expect("topic exists");
+            peers.retain(|peer_id| self.is_subscribed_peer(peer_id, &topic));
+            if peers.len() < self.config.mesh_n_low {
+                let count = mesh_n - peers.len();
+                let extra = self.random_peers(&topic, count, |p| peers.contains(p));
+                for peer_id in extra {
+                    self.send_control(
+                        peer_id.clone(),
+                        GossipControl::Graft {
+                            topic: topic.clone(),
+                        },
+                    );
+                    peers.insert(peer_id);
+                }
+            } else if peers.len() > self.config.mesh_n_high {
+                let mut excess: Vec<PeerId> = peers.iter().cloned().collect();
+                excess.shuffle(&mut rand::thread_rng());
+                for peer_id in excess.into_iter().skip(mesh_n) {
+                    peers.remove(&peer_id);
+                    self.send_control(
+                        peer_id,
+                        GossipControl::Prune {
+                            topic: topic.clone(),
+                        },
+                    );
+                }
+            }
+            metrics::GOSSIP_MESH_PEERS
+                .with_label_values(&[&topic])
+                .set(peers.len() as i64);
+            self.mesh.insert(topic, peers);
+        }
+
+        // Forget fanout of topics we haven't published to for a while.
+        let now = Instant::now();
+        let fanout_ttl = Duration::from_secs(self.config.fanout_ttl);
+        let expired: Vec<String> = self
+            .fanout_last_pub
+            .iter()
+            .filter(|(_topic, last_pub)| now.duration_since(**last_pub) >= fanout_ttl)
+            .map(|(topic, _last_pub)| topic.clone())
+            .collect();
+        for topic in expired {
+            self.fanout_last_pub.remove(&topic);
+            self.fanout.remove(&topic);
+            metrics::GOSSIP_FANOUT_PEERS
+                .with_label_values(&[&topic])
+                .set(0);
+        }
+        let topics: Vec<String> = self.fanout.keys().cloned().collect();
+        for topic in topics {
+            let mut peers = self.fanout.remove(&topic).expect("topic exists");
+            peers.retain(|peer_id| self.is_subscribed_peer(peer_id, &topic));
+            if peers.len() < mesh_n {
+                let count = mesh_n - peers.len();
+                let extra = self.random_peers(&topic, count, |p| peers.contains(p));
+                peers.extend(extra);
+            }
+            metrics::GOSSIP_FANOUT_PEERS
+                .with_label_values(&[&topic])
+                .set(peers.len() as i64);
+            self.fanout.insert(topic, peers);
+        }
+
+        // Advertise recent messages to some peers outside of the mesh.
+        let topics: Vec<String> = self
+            .mesh
+            .keys()
+            .chain(self.fanout.keys())
+            .cloned()
+            .collect();
+        for topic in topics {
+            let message_ids = self.mcache.gossip_ids(&topic);
+            if message_ids.is_empty() {
+                continue;
+            }
+            let mesh = self.mesh.get(&topic);
+            let fanout = self.fanout.get(&topic);
+            let peers = self.random_peers(&topic, self.config.gossip_lazy, |peer_id| {
+                mesh.map(|m| m.contains(peer_id)).unwrap_or(false)
+                    || fanout.map(|f| f.contains(peer_id)).unwrap_or(false)
+            });
+            for peer_id in peers {
+                let control = GossipControl::IHave {
+                    topic: topic.clone(),
+                    message_ids: message_ids.clone(),
+                };
+                self.send_control(peer_id, control);
+            }
+        }
+
+        self.mcache.shift();
+        metrics::GOSSIP_MCACHE_SIZE.set(self.mcache.len() as i64);
+    }
+}
+
+impl<TSubstream> NetworkBehaviour for Gossipsub<TSubstream>
+where
+    TSubstream: AsyncRead + AsyncWrite,
+{
+    type ProtocolsHandler = FloodsubHandler<TSubstream>;
+    type OutEvent = FloodsubEvent;
+
+    fn new_handler(&mut self) -> Self::ProtocolsHandler {
+        FloodsubHandler::with_config(FloodsubConfig::gossipsub())
+    }
+
+    fn addresses_of_peer(&mut self, _: &PeerId) -> Vec<Multiaddr> {
+        Vec::new()
+    }
+
+    fn inject_connected(&mut self, id: PeerId, _: ConnectedPoint) {
+        debug!(target: "stegos_network::pubsub", "peer connected: peer_id={}", id);
+        self.connected_peers.insert(id);
+    }
+
+    fn inject_disconnected(&mut self, id: &PeerId, _: ConnectedPoint) {
+        debug!(target: "stegos_network::pubsub", "peer disconnected: peer_id={}", id);
+        self.connected_peers.remove(id);
+        self.allowed_remotes.remove(id);
+        self.outbound.remove_peer(id);
+        if let Some(topics) = self.unlocked_remotes.remove(id) {
+            for topic in topics {
+                self.forget_peer_topic(id, &topic);
+            }
+        }
+        metrics::UNLOCKED_PEERS.set(self.unlocked_remotes.len() as i64);
+    }
+
+    fn inject_node_event(&mut self, propagation_source: PeerId, event: FloodsubRecvEvent) {
+        self.incoming_rates
+            .entry(propagation_source.clone())
+            .or_insert(RollingRateCounter::new(PUBSUB_SAMPLES))
+            .update();
+        self.active_remotes.insert(propagation_source.clone());
+
+        if !self.allowed_remotes.contains(&propagation_source) {
+            debug!(target: "stegos_network::pubsub", "event from unwanted peer, dropping: peer_id={}", propagation_source);
+            return;
+        }
+
+        if self.graylisted.contains(&propagation_source) {
+            trace!(target: "stegos_network::pubsub", "event from graylisted peer, dropping: peer_id={}", propagation_source);
+            return;
+        }
+
+        match event {
+            FloodsubRecvEvent::Message(rpc) => {
+                for subscription in rpc.subscriptions {
+                    let remote_peer_topics = self
+                        .unlocked_remotes
+                        .entry(propagation_source.clone())
+                        .or_insert(SmallVec::new());
+                    match subscription.action {
+                        FloodsubSubscriptionAction::Subscribe => {
+                            if !remote_peer_topics.contains(&subscription.topic) {
+                                remote_peer_topics.push(subscription.topic.clone());
+                            }
+                            self.events.push_back(NetworkBehaviourAction::GenerateEvent(
+                                FloodsubEvent::Subscribed {
+                                    peer_id: propagation_source.clone(),
+                                    topic: subscription.topic,
+                                },
+                            ));
+                        }
+                        FloodsubSubscriptionAction::Unsubscribe => {
+                            if let Some(pos) = remote_peer_topics
+                                .iter()
+                                .position(|t| t == &subscription.topic)
+                            {
+                                remote_peer_topics.remove(pos);
+                            }
+                            self.forget_peer_topic(&propagation_source, &subscription.topic);
+                            self.events.push_back(NetworkBehaviourAction::GenerateEvent(
+                                FloodsubEvent::Unsubscribed {
+                                    peer_id: propagation_source.clone(),
+                                    topic: subscription.topic,
+                                },
+                            ));
+                        }
+                    }
+                }
+
+                for message in rpc.messages {
+                    self.handle_message(&propagation_source, message);
+                }
+
+                for control in rpc.control {
+                    self.handle_control(&propagation_source, control);
+                }
+            }
+        }
+    }
+
+    fn poll(
+        &mut self,
+        _: &mut impl PollParameters,
+    ) -> Async<
+        NetworkBehaviourAction<
+            <Self::ProtocolsHandler as ProtocolsHandler>::InEvent,
+            Self::OutEvent,
+        >,
+    > {
+        loop {
+            match self.metrics_update_delay.poll() {
+                Ok(Async::Ready(_)) => {
+                    for (peer_id, counter) in self.incoming_rates.iter() {
+                        metrics::INCOMING_RATES
+                            .with_label_values(&[&peer_id.clone().to_base58()])
+                            .set(counter.rate());
+                        if self.active_remotes.contains(peer_id)
+                            && counter.rate() > self.max_message_rate
+                        {
+                            debug!(target: "stegos_network::pubsub", "incoming rate exceeded: peer_id={}, rate={}", peer_id, counter.rate());
+                            self.events.push_back(NetworkBehaviourAction::GenerateEvent(
+                                FloodsubEvent::RateLimitExceeded {
+                                    peer_id: peer_id.clone(),
+                                    rate: counter.rate(),
+                                },
+                            ));
+                        }
+                    }
+                    self.active_remotes.clear();
+                    self.metrics_update_delay
+                        .reset(Instant::now() + METRICS_UPDATE_INTERVAL);
+                }
+                Ok(Async::NotReady) => break,
+                Err(e) => {
+                    debug!(target: "stegos_network::pubsub", "metrics delay timer error: error={}", e);
+                    break;
+                }
+            }
+        }
+
+        loop {
+            match self.heartbeat.poll() {
+                Ok(Async::Ready(_)) => {
+                    self.heartbeat();
+                    let interval = Duration::from_secs(self.config.heartbeat_interval);
+                    self.heartbeat.reset(Instant::now() + interval);
+                }
+                Ok(Async::NotReady) => break,
+                Err(e) => {
+                    debug!(target: "stegos_network::pubsub", "heartbeat timer error: error={}", e);
+                    break;
+                }
+            }
+        }
+
+        if let Some(event) = self.events.pop_front() {
+            return Async::Ready(event);
+        }
+
+        if let Async::Ready((peer_id, message)) = self.outbound.poll() {
+            return Async::Ready(NetworkBehaviourAction::SendEvent {
+                peer_id,
+                event: FloodsubSendEvent::Publish(FloodsubRpc {
+                    messages: vec![message],
+                    subscriptions: Vec::new(),
+                    control: Vec::new(),
+                }),
+            });
+        }
+
+        Async::NotReady
+    }
+}
+
+/// Label for metrics.
+fn control_type(control: &GossipControl) -> &'static str {
+    match control {
+        GossipControl::IHave { .. } => "ihave",
+        GossipControl::IWant { .. } => "iwant",
+        GossipControl::Graft { .. } => "graft",
+        GossipControl::Prune { .. } => "prune",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use stegos_crypto::pbc;
+
+    const TOPIC: &str = "tx";
+
+    fn new_gossipsub(config: GossipsubConfig) -> Result<Gossipsub<()>, Error> {
+        Gossipsub::new(
+            PeerId::random(),
+            true,
+            100.0,
+            &BandwidthConfig::default(),
+            &[],
+            config,
+        )
+    }
+
+    fn small_mesh() -> GossipsubConfig {
+        GossipsubConfig {
+            mesh_n: 3,
+            mesh_n_low: 2,
+            mesh_n_high: 4,
+            ..GossipsubConfig::default()
+        }
+    }
+
+    /// Adds a connected peer subscribed to the topic.
+    fn add_peer(gossipsub: &mut Gossipsub<()>, topic: &str) -> PeerId {
+        let peer_id = PeerId::random();
+        gossipsub.connected_peers.insert(peer_id.clone());
+        gossipsub.enable_outgoing(&peer_id);
+        gossipsub
+            .unlocked_remotes
+            .get_mut(&peer_id)
+            .unwrap()
+            .push(topic.to_string());
+        peer_id
+    }
+
+    fn message(data: Vec<u8>) -> FloodsubMessage {
+        FloodsubMessage {
+            topic: TOPIC.to_string(),
+            data,
+            origin: None,
+        }
+    }
+
+    /// Takes control messages sent to peers, drops other events.
+    fn sent_control(gossipsub: &mut Gossipsub<()>) -> Vec<(PeerId, GossipControl)> {
+        let mut sent = Vec::new();
+        for event in gossipsub.events.drain(..) {
+            if let NetworkBehaviourAction::SendEvent {
+                peer_id,
+                event: FloodsubSendEvent::Publish(rpc),
+            } = event
+            {
+                for control in rpc.control {
+                    sent.push((peer_id.clone(), control));
+                }
+            }
+        }
+        sent
+    }
+
+    fn sent_grafts(gossipsub: &mut Gossipsub<()>) -> Vec<PeerId> {
+        sent_control(gossipsub)
+            .into_iter()
+            .filter(|(_peer_id, control)| match control {
+                GossipControl::Graft { .. } => true,
+                _ => false,
+            })
+            .map(|(peer_id, _control)| peer_id)
+            .collect()
+    }
+
+    /// Takes messages sent to peers.
+    fn sent_messages(gossipsub: &mut Gossipsub<()>) -> Vec<(PeerId, FloodsubMessage)> {
+        let mut sent = Vec::new();
+        while let Async::Ready(sent_message) = gossipsub.outbound.poll() {
+            sent.push(sent_message);
+        }
+        sent
+    }
+
+    /// Takes messages dispatched to the user.
+    fn dispatched(gossipsub: &mut Gossipsub<()>) -> Vec<FloodsubMessage> {
+        let mut messages = Vec::new();
+        for event in gossipsub.events.drain(..) {
+            if let NetworkBehaviourAction::GenerateEvent(FloodsubEvent::Message {
+                message, ..
+            }) = event
+            {
+                messages.push(message);
+            }
+        }
+        messages
+    }
+
+    #[test]
+    fn mesh_maintenance() {
+        let mut gossipsub = new_gossipsub(small_mesh()).unwrap();
+        for _ in 0..10 {
+            add_peer(&mut gossipsub, TOPIC);
+        }
+
+        // Joining grafts mesh_n peers.
+        assert!(gossipsub.subscribe(TOPIC.to_string()));
+        let grafted = sent_grafts(&mut gossipsub);
+        assert_eq!(grafted.len(), 3);
+        assert_eq!(gossipsub.mesh[TOPIC].len(), 3);
+
+        // The mesh is kept while it is between mesh_n_low and mesh_n_high.
+        gossipsub
+            .unlocked_remotes
+            .get_mut(&grafted[0])
+            .unwrap()
+            .clear();
+        gossipsub.heartbeat();
+        assert_eq!(gossipsub.mesh[TOPIC].len(), 2);
+        assert!(!gossipsub.mesh[TOPIC].contains(&grafted[0]));
+        assert!(sent_grafts(&mut gossipsub).is_empty());
+
+        // Below mesh_n_low, the mesh is refilled up to mesh_n.
+        gossipsub
+            .unlocked_remotes
+            .get_mut(&grafted[1])
+            .unwrap()
+            .clear();
+        gossipsub.heartbeat();
+        assert_eq!(gossipsub.mesh[TOPIC].len(), 3);
+        assert!(!gossipsub.mesh[TOPIC].contains(&grafted[1]));
+        assert_eq!(sent_grafts(&mut gossipsub).len(), 2);
+
+        // Above mesh_n_high, the mesh is pruned down to mesh_n.
+        let outside: Vec<PeerId> = gossipsub
+            .unlocked_remotes
+            .iter()
+            .filter(|(peer_id, topics)| {
+                !topics.is_empty() && !gossipsub.mesh[TOPIC].contains(peer_id)
+            })
+            .map(|(peer_id, _topics)| peer_id.clone())
+            .collect();
+        for peer_id in outside.iter().take(2) {
+            let control = GossipControl::Graft {
+                topic: TOPIC.to_string(),
+            };
+            gossipsub.handle_control(peer_id, control);
+        }
+        assert_eq!(gossipsub.mesh[TOPIC].len(), 5);
+        gossipsub.heartbeat();
+        assert_eq!(gossipsub.mesh[TOPIC].len(), 3);
+        let pruned: Vec<(PeerId, GossipControl)> = sent_control(&mut gossipsub);
+        assert_eq!(pruned.len(), 2);
+        for (peer_id, control) in pruned {
+            assert!(!gossipsub.mesh[TOPIC].contains(&peer_id));
+            assert_eq!(
+                control,
+                GossipControl::Prune {
+                    topic: TOPIC.to_string()
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn graft_unsubscribed() {
+        let mut gossipsub = new_gossipsub(GossipsubConfig::default()).unwrap();
+        let peer_id = add_peer(&mut gossipsub, TOPIC);
+        let graft = GossipControl::Graft {
+            topic: TOPIC.to_string(),
+        };
+
+        // We aren't subscribed to the topic.
+        gossipsub.handle_control(&peer_id, graft.clone());
+        assert_eq!(
+            sent_control(&mut gossipsub),
+            vec![(
+                peer_id.clone(),
+                GossipControl::Prune {
+                    topic: TOPIC.to_string()
+                }
+            )]
+        );
+        assert!(gossipsub.mesh.get(TOPIC).is_none());
+
+        // The peer is accepted to the mesh after subscription.
+        gossipsub.subscribe(TOPIC.to_string());
+        gossipsub.mesh.get_mut(TOPIC).unwrap().clear();
+        sent_control(&mut gossipsub);
+        gossipsub.handle_control(&peer_id, graft);
+        assert!(sent_control(&mut gossipsub).is_empty());
+        assert!(gossipsub.mesh[TOPIC].contains(&peer_id));
+    }
+
+    #[test]
+    fn ihave_iwant() {
+        let config = GossipsubConfig {
+            mesh_n: 1,
+            mesh_n_low: 1,
+            mesh_n_high: 2,
+            ..GossipsubConfig::default()
+        };
+        let mut alice = new_gossipsub(config).unwrap();
+        let mut bob = new_gossipsub(GossipsubConfig::default()).unwrap();
+        let alice_id = add_peer(&mut bob, TOPIC);
+        bob.subscribe(TOPIC.to_string());
+        sent_control(&mut bob);
+
+        // Alice publishes to the fanout, then Bob connects.
+        let fanout_id = add_peer(&mut alice, TOPIC);
+        let message = message(vec![1, 2, 3]);
+        alice.publish(TOPIC.to_string(), message.data.clone(), None);
+        assert_eq!(
+            sent_messages(&mut alice),
+            vec![(fanout_id.clone(), message.clone())]
+        );
+        let bob_id = add_peer(&mut alice, TOPIC);
+
+        // Bob is outside of the fanout, so he gets IHAVE.
+        alice.heartbeat();
+        let ihave = GossipControl::IHave {
+            topic: TOPIC.to_string(),
+            message_ids: vec![message.digest()],
+        };
+        assert_eq!(
+            sent_control(&mut alice),
+            vec![(bob_id.clone(), ihave.clone())]
+        );
+
+        // Bob requests the message.
+        bob.handle_control(&alice_id, ihave.clone());
+        let iwant = GossipControl::IWant {
+            message_ids: vec![message.digest()],
+        };
+        assert_eq!(
+            sent_control(&mut bob),
+            vec![(alice_id.clone(), iwant.clone())]
+        );
+
+        // Alice answers from the message cache.
+        alice.handle_control(&bob_id, iwant);
+        let sent = sent_messages(&mut alice);
+        assert_eq!(sent, vec![(bob_id.clone(), message.clone())]);
+        bob.handle_message(&alice_id, sent[0].1.clone());
+        assert_eq!(dispatched(&mut bob), vec![message]);
+
+        // Known messages aren't requested again.
+        bob.handle_control(&alice_id, ihave);
+        assert!(sent_control(&mut bob).is_empty());
+    }
+
+    #[test]
+    fn fanout_ttl() {
+        let mut gossipsub = new_gossipsub(small_mesh()).unwrap();
+        for _ in 0..5 {
+            add_peer(&mut gossipsub, TOPIC);
+        }
+        gossipsub.publish(TOPIC.to_string(), vec![1], None);
+        assert_eq!(sent_messages(&mut gossipsub).len(), 3);
+        let fanout = gossipsub.fanout[TOPIC].clone();
+        assert_eq!(fanout.len(), 3);
+
+        // The fanout is kept while we publish.
+        gossipsub.heartbeat();
+        assert_eq!(gossipsub.fanout[TOPIC], fanout);
+
+        // And forgotten after fanout_ttl.
+        let ttl = Duration::from_secs(gossipsub.config.fanout_ttl);
+        let last_pub = Instant::now() - ttl - Duration::from_secs(1);
+        gossipsub
+            .fanout_last_pub
+            .insert(TOPIC.to_string(), last_pub);
+        gossipsub.heartbeat();
+        assert!(gossipsub.fanout.get(TOPIC).is_none());
+        assert!(gossipsub.fanout_last_pub.get(TOPIC).is_none());
+
+        // The next publish chooses the fanout again.
+        gossipsub.publish(TOPIC.to_string(), vec![2], None);
+        assert_eq!(sent_messages(&mut gossipsub).len(), 3);
+        assert_eq!(gossipsub.fanout[TOPIC].len(), 3);
+    }
+
+    #[test]
+    fn forwarding() {
+        let mut gossipsub = new_gossipsub(GossipsubConfig::default()).unwrap();
+        let peers: Vec<PeerId> = (0..3).map(|_| add_peer(&mut gossipsub, TOPIC)).collect();
+        gossipsub.subscribe(TOPIC.to_string());
+        sent_control(&mut gossipsub);
+        assert_eq!(gossipsub.mesh[TOPIC].len(), 3);
+
+        // The message is dispatched and forwarded to the mesh, except the propagation source.
+        let message = message(vec![1, 2, 3]);
+        gossipsub.handle_message(&peers[0], message.clone());
+        assert_eq!(dispatched(&mut gossipsub), vec![message.clone()]);
+        let mut forwarded: Vec<PeerId> = sent_messages(&mut gossipsub)
+            .into_iter()
+            .map(|(peer_id, forwarded)| {
+                assert_eq!(forwarded, message);
+                peer_id
+            })
+            .collect();
+        forwarded.sort_by_key(|peer_id| peer_id.to_base58());
+        let mut expected = peers[1..].to_vec();
+        expected.sort_by_key(|peer_id| peer_id.to_base58());
+        assert_eq!(forwarded, expected);
+
+        // Duplicates are neither dispatched nor forwarded.
+        gossipsub.handle_message(&peers[1], message);
+        assert!(dispatched(&mut gossipsub).is_empty());
+        assert!(sent_messages(&mut gossipsub).is_empty());
+
+        // Without a mesh, the message is forwarded to random subscribed peers.
+        let other = FloodsubMessage {
+            topic: "block".to_string(),
+            data: vec![4],
+            origin: None,
+        };
+        let block_peers: Vec<PeerId> = (0..2).map(|_| add_peer(&mut gossipsub, "block")).collect();
+        gossipsub.handle_message(&block_peers[0], other);
+        assert!(dispatched(&mut gossipsub).is_empty());
+        let forwarded: Vec<PeerId> = sent_messages(&mut gossipsub)
+            .into_iter()
+            .map(|(peer_id, _message)| peer_id)
+            .collect();
+        assert_eq!(forwarded, vec![block_peers[1].clone()]);
+    }
+
+    #[test]
+    fn invalid_signature() {
+        let mut gossipsub = Gossipsub::<()>::new(
+            PeerId::random(),
+            true,
+            100.0,
+            &BandwidthConfig::default(),
+            &[TOPIC.to_string()],
+            GossipsubConfig::default(),
+        )
+        .unwrap();
+        let peer_id = add_peer(&mut gossipsub, TOPIC);
+        gossipsub.subscribe(TOPIC.to_string());
+        sent_control(&mut gossipsub);
+
+        let (skey, pkey) = pbc::make_random_keys();
+        let data = vec![1, 2, 3];
+        let origin = MessageOrigin::sign(TOPIC, vec![1], &data, &pkey, &skey);
+        let valid = FloodsubMessage {
+            origin: Some(origin),
+            ..message(data.clone())
+        };
+        let mut invalid = valid.clone();
+        invalid.origin.as_mut().unwrap().seq_no = vec![2];
+
+        // Every copy is reported, the signature is checked once.
+        for _ in 0..2 {
+            gossipsub.handle_message(&peer_id, invalid.clone());
+            match gossipsub.events.pop_front() {
+                Some(NetworkBehaviourAction::GenerateEvent(FloodsubEvent::InvalidSignature {
+                    peer_id: source,
+                    topic,
+                })) => {
+                    assert_eq!(source, peer_id);
+                    assert_eq!(topic, TOPIC);
+                }
+                _ => panic!("Expected InvalidSignature"),
+            }
+            assert!(gossipsub.events.is_empty());
+            assert!(gossipsub.rejected.contains_key(&invalid.envelope_digest()));
+        }
+        assert!(sent_messages(&mut gossipsub).is_empty());
+
+        // Unsigned copies are dropped without caching.
+        gossipsub.handle_message(&peer_id, message(data));
+        assert!(gossipsub.events.is_empty());
+
+        // The valid copy is still accepted.
+        gossipsub.handle_message(&peer_id, valid.clone());
+        match gossipsub.events.pop_front() {
+            Some(NetworkBehaviourAction::GenerateEvent(FloodsubEvent::Message {
+                from,
+                message,
+                ..
+            })) => {
+                assert_eq!(from, Some(pkey));
+                assert_eq!(message, valid);
+            }
+            _ => panic!("Expected Message"),
+        }
+    }
+
+    #[test]
+    fn invalid_config() {
+        assert!(new_gossipsub(GossipsubConfig::default()).is_ok());
+
+        let mut config = GossipsubConfig::default();
+        config.heartbeat_interval = 0;
+        assert!(new_gossipsub(config).is_err());
+
+        let mut config = GossipsubConfig::default();
+        config.mesh_n_low = config.mesh_n + 1;
+        assert!(new_gossipsub(config).is_err());
+
+        let mut config = GossipsubConfig::default();
+        config.mesh_n_high = config.mesh_n - 1;
+        assert!(new_gossipsub(config).is_err());
+
+        let mut config = GossipsubConfig::default();
+        config.history_gossip = config.history_length + 1;
+        assert!(new_gossipsub(config).is_err());
+    }
+}
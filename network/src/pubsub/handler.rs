@@ -90,8 +90,13 @@ where
 {
     /// Builds a new `FloodsubHandler`.
     pub fn new() -> Self {
+        Self::with_config(FloodsubConfig::new())
+    }
+
+    /// Builds a new `FloodsubHandler` for the specified protocol.
+    pub fn with_config(config: FloodsubConfig) -> Self {
         FloodsubHandler {
-            config: FloodsubConfig::new(),
+            config,
             substreams: Vec::new(),
             keep_alive: KeepAlive::Yes,
             send_queue: SmallVec::new(),
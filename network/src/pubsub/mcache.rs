@@ -0,0 +1,133 @@
+//
+// MIT License
+//
+// Copyright (c) 2019 Stegos AG
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Sliding window cache of recently seen messages, used to answer IWANT requests.
+
+use super::protocol::FloodsubMessage;
+use std::collections::{HashMap, VecDeque};
+
+pub struct MessageCache {
+    /// Messages by id.
+    messages: HashMap<u64, FloodsubMessage>,
+    /// Ids and topics of messages, one entry per heartbeat, most recent first.
+    history: VecDeque<Vec<(u64, String)>>,
+    /// Number of windows to advertise in IHAVE gossip.
+    gossip: usize,
+}
+
+impl MessageCache {
+    pub fn new(gossip: usize, history_length: usize) -> Self {
+        assert!(history_length > 0);
+        assert!(gossip <= history_length);
+        let mut history = VecDeque::with_capacity(history_length);
+        for _ in 0..history_length {
+            history.push_back(Vec::new());
+        }
+        MessageCache {
+            messages: HashMap::new(),
+            history,
+            gossip,
+        }
+    }
+
+    /// Adds a message to the current window.
+    pub fn put(&mut self, message: FloodsubMessage) {
+        let id = message.digest();
+        if self.messages.contains_key(&id) {
+            return;
+        }
+        self.history[0].push((id, message.topic.clone()));
+        self.messages.insert(id, message);
+    }
+
+    /// Returns a message by id.
+    pub fn get(&self, id: &u64) -> Option<&FloodsubMessage> {
+        self.messages.get(id)
+    }
+
+    /// Returns ids of recent messages of the topic.
+    pub fn gossip_ids(&self, topic: &str) -> Vec<u64> {
+        self.history
+            .iter()
+            .take(self.gossip)
+            .flat_map(|window| window.iter())
+            .filter(|(_id, t)| t == topic)
+            .map(|(id, _t)| *id)
+            .collect()
+    }
+
+    /// Opens a new window and forgets messages from the oldest one.
+    pub fn shift(&mut self) {
+        if let Some(window) = self.history.pop_back() {
+            for (id, _topic) in window {
+                self.messages.remove(&id);
+            }
+        }
+        self.history.push_front(Vec::new());
+    }
+
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(topic: &str, n: u8) -> FloodsubMessage {
+        FloodsubMessage {
+            topic: topic.to_string(),
+            data: vec![n],
+            origin: None,
+        }
+    }
+
+    #[test]
+    fn shift() {
+        let mut mcache = MessageCache::new(2, 3);
+        let m1 = message("a", 1);
+        let m2 = message("b", 2);
+        let m3 = message("a", 3);
+        mcache.put(m1.clone());
+        mcache.put(m1.clone());
+        mcache.put(m2.clone());
+        assert_eq!(mcache.len(), 2);
+        assert_eq!(mcache.gossip_ids("a"), vec![m1.digest()]);
+
+        mcache.shift();
+        mcache.put(m3.clone());
+        assert_eq!(mcache.gossip_ids("a"), vec![m3.digest(), m1.digest()]);
+
+        // m1 is out of the gossip window, but still can be requested.
+        mcache.shift();
+        assert_eq!(mcache.gossip_ids("a"), vec![m3.digest()]);
+        assert_eq!(mcache.get(&m1.digest()), Some(&m1));
+
+        mcache.shift();
+        assert_eq!(mcache.get(&m1.digest()), None);
+        assert_eq!(mcache.get(&m2.digest()), None);
+        assert_eq!(mcache.get(&m3.digest()), Some(&m3));
+        assert_eq!(mcache.len(), 1);
+    }
+}
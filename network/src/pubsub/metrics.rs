@@ -53,4 +53,56 @@ lazy_static! {
     .unwrap();
     pub static ref UNLOCKED_PEERS: IntGauge =
         register_int_gauge!("stegos_pubsub_unlocked_peers", "Count of unlocked peers").unwrap();
+    pub static ref GOSSIP_MESH_PEERS: IntGaugeVec = register_int_gauge_vec!(
+        "stegos_gossipsub_mesh_peers",
+        "Count of mesh peers per topic",
+        &["topic"]
+    )
+    .unwrap();
+    pub static ref GOSSIP_FANOUT_PEERS: IntGaugeVec = register_int_gauge_vec!(
+        "stegos_gossipsub_fanout_peers",
+        "Count of fanout peers per topic",
+        &["topic"]
+    )
+    .unwrap();
+    pub static ref GOSSIP_MCACHE_SIZE: IntGauge = register_int_gauge!(
+        "stegos_gossipsub_mcache_size",
+        "Count of messages available for IWANT requests"
+    )
+    .unwrap();
+    pub static ref GOSSIP_DUPLICATES: IntCounter = register_int_counter!(
+        "stegos_gossipsub_duplicate_messages",
+        "Count of received duplicate messages"
+    )
+    .unwrap();
+    pub static ref GOSSIP_OUTGOING_CONTROL: IntCounterVec = register_int_counter_vec!(
+        "stegos_gossipsub_outgoing_control",
+        "Count of sent control messages",
+        &["type"]
+    )
+    .unwrap();
+    pub static ref OUTGOING_QUEUE_LEN: IntGauge = register_int_gauge!(
+        "stegos_pubsub_outgoing_queue_length",
+        "Count of queued outgoing messages"
+    )
+    .unwrap();
+    pub static ref OUTGOING_DROPPED: IntCounterVec = register_int_counter_vec!(
+        "stegos_pubsub_outgoing_dropped_per_topic",
+        "Count of dropped outgoing messages",
+        &["topic"]
+    )
+    .unwrap();
+    pub static ref OUTGOING_QUEUE_DELAY: HistogramVec = register_histogram_vec!(
+        "stegos_pubsub_outgoing_queue_delay",
+        "Time spent by outgoing messages in the queue (secs)",
+        &["priority"],
+        vec![0.001, 0.01, 0.1, 0.5, 1.0, 5.0, 10.0, 30.0]
+    )
+    .unwrap();
+    pub static ref GOSSIP_INCOMING_CONTROL: IntCounterVec = register_int_counter_vec!(
+        "stegos_gossipsub_incoming_control",
+        "Count of received control messages",
+        &["type"]
+    )
+    .unwrap();
 }
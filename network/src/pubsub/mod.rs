@@ -18,15 +18,20 @@
 // FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
 // DEALINGS IN THE SOFTWARE.
 
-//! Implements the floodsub protocol, see also the:
+//! Implements the floodsub and gossipsub protocols, see also the:
 //! [spec](https://github.com/libp2p/specs/tree/master/pubsub).
 
 pub mod handler;
 pub mod protocol;
 
 mod behavior;
+mod gossipsub;
+mod mcache;
 mod metrics;
+mod origin;
 mod proto;
+mod queue;
 
 pub use self::behavior::{Floodsub, FloodsubEvent};
-pub use self::protocol::{FloodsubMessage, FloodsubRpc};
+pub use self::gossipsub::Gossipsub;
+pub use self::protocol::{FloodsubMessage, FloodsubRpc, MessageOrigin};
@@ -0,0 +1,110 @@
+//
+// MIT License
+//
+// Copyright (c) 2019 Stegos AG
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Signed envelopes of broadcast messages.
+
+use super::protocol::{FloodsubMessage, MessageOrigin};
+use failure::{format_err, Error};
+use stegos_crypto::hash::{Hash, Hashable, Hasher};
+use stegos_crypto::pbc;
+
+fn broadcast_hash(topic: &str, seq_no: &[u8], data: &[u8]) -> Hash {
+    let mut hasher = Hasher::new();
+    topic.hash(&mut hasher);
+    seq_no.hash(&mut hasher);
+    data.hash(&mut hasher);
+    hasher.result()
+}
+
+impl MessageOrigin {
+    /// Signs a broadcast message by the publisher.
+    pub fn sign(
+        topic: &str,
+        seq_no: Vec<u8>,
+        data: &[u8],
+        pkey: &pbc::PublicKey,
+        skey: &pbc::SecretKey,
+    ) -> MessageOrigin {
+        let hash = broadcast_hash(topic, &seq_no, data);
+        let sig = pbc::sign_hash(&hash, skey);
+        MessageOrigin {
+            from: pkey.to_bytes().to_vec(),
+            seq_no,
+            signature: sig.to_bytes().to_vec(),
+        }
+    }
+
+    /// Checks the signature of the publisher, returns its network key.
+    pub fn verify(&self, topic: &str, data: &[u8]) -> Result<pbc::PublicKey, Error> {
+        let from = pbc::PublicKey::try_from_bytes(&self.from)?;
+        let signature = pbc::Signature::try_from_bytes(&self.signature)?;
+        let hash = broadcast_hash(topic, &self.seq_no, data);
+        if let Err(_e) = pbc::check_hash(&hash, &signature, &from) {
+            return Err(format_err!("Bad broadcast signature."));
+        }
+        Ok(from)
+    }
+}
+
+impl FloodsubMessage {
+    /// Checks the signed envelope, if any, returns the network key of the publisher.
+    pub fn verify(&self) -> Result<Option<pbc::PublicKey>, Error> {
+        match self.origin {
+            Some(ref origin) => origin.verify(&self.topic, &self.data).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sign_verify() {
+        let (skey, pkey) = pbc::make_random_keys();
+        let topic = "tx";
+        let data = random_vec(128);
+
+        let origin = MessageOrigin::sign(topic, vec![0, 1], &data, &pkey, &skey);
+        assert_eq!(origin.verify(topic, &data).unwrap(), pkey);
+
+        // Other topic.
+        assert!(origin.verify("block", &data).is_err());
+        // Other payload.
+        assert!(origin.verify(topic, &random_vec(128)).is_err());
+        // Other sequence number.
+        let mut replayed = origin.clone();
+        replayed.seq_no = vec![0, 2];
+        assert!(replayed.verify(topic, &data).is_err());
+        // Other publisher.
+        let (_skey2, pkey2) = pbc::make_random_keys();
+        let mut forged = origin.clone();
+        forged.from = pkey2.to_bytes().to_vec();
+        assert!(forged.verify(topic, &data).is_err());
+    }
+
+    fn random_vec(len: usize) -> Vec<u8> {
+        (0..len).map(|_| rand::random::<u8>()).collect()
+    }
+}
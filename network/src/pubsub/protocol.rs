@@ -38,13 +38,26 @@ const PROTOCOL_LABEL: &'static str = "pubsub";
 
 /// Implementation of `ConnectionUpgrade` for the floodsub protocol.
 #[derive(Debug, Clone)]
-pub struct FloodsubConfig {}
+pub struct FloodsubConfig {
+    /// Protocol name to negotiate.
+    protocol: &'static [u8],
+}
 
 impl FloodsubConfig {
     /// Builds a new `FloodsubConfig`.
     #[inline]
     pub fn new() -> FloodsubConfig {
-        FloodsubConfig {}
+        FloodsubConfig {
+            protocol: b"/floodsub/1.0.0",
+        }
+    }
+
+    /// Builds a `FloodsubConfig` for the gossipsub protocol, which uses the same wire format.
+    #[inline]
+    pub fn gossipsub() -> FloodsubConfig {
+        FloodsubConfig {
+            protocol: b"/stegos/meshsub/1.0.0",
+        }
     }
 }
 
@@ -54,7 +67,7 @@ impl UpgradeInfo for FloodsubConfig {
 
     #[inline]
     fn protocol_info(&self) -> Self::InfoIter {
-        iter::once(b"/floodsub/1.0.0")
+        iter::once(self.protocol)
     }
 }
 
@@ -116,6 +129,11 @@ impl Encoder for FloodsubCodec {
             let mut msg = rpc_proto::Message::new();
             msg.set_data(message.data);
             msg.set_topic(message.topic);
+            if let Some(origin) = message.origin {
+                msg.set_origin(origin.from);
+                msg.set_seq_no(origin.seq_no);
+                msg.set_signature(origin.signature);
+            }
             proto.mut_publish().push(msg);
         }
 
@@ -126,6 +144,36 @@ impl Encoder for FloodsubCodec {
             proto.mut_subscriptions().push(subscription);
         }
 
+        if !item.control.is_empty() {
+            let mut control = rpc_proto::ControlMessage::new();
+            for action in item.control.into_iter() {
+                match action {
+                    GossipControl::IHave { topic, message_ids } => {
+                        let mut ihave = rpc_proto::ControlIHave::new();
+                        ihave.set_topic(topic);
+                        ihave.set_message_ids(message_ids);
+                        control.mut_ihave().push(ihave);
+                    }
+                    GossipControl::IWant { message_ids } => {
+                        let mut iwant = rpc_proto::ControlIWant::new();
+                        iwant.set_message_ids(message_ids);
+                        control.mut_iwant().push(iwant);
+                    }
+                    GossipControl::Graft { topic } => {
+                        let mut graft = rpc_proto::ControlGraft::new();
+                        graft.set_topic(topic);
+                        control.mut_graft().push(graft);
+                    }
+                    GossipControl::Prune { topic } => {
+                        let mut prune = rpc_proto::ControlPrune::new();
+                        prune.set_topic(topic);
+                        control.mut_prune().push(prune);
+                    }
+                }
+            }
+            proto.set_control(control);
+        }
+
         let msg_size = proto.compute_size();
         // Reserve enough space for the data and the length. The length has a maximum of 32 bits,
         // which means that 5 bytes is enough for the variable-length integer.
@@ -167,10 +215,50 @@ impl Decoder for FloodsubCodec {
             pubsub_metrics::INCOMING_PUBSUB_TRAFFIC
                 .with_label_values(&[&topic])
                 .inc_by(data.len() as i64);
-            messages.push(FloodsubMessage { data, topic });
+            let origin = if publish.get_origin().is_empty() {
+                None
+            } else {
+                Some(MessageOrigin {
+                    from: publish.take_origin(),
+                    seq_no: publish.take_seq_no(),
+                    signature: publish.take_signature(),
+                })
+            };
+            messages.push(FloodsubMessage {
+                data,
+                topic,
+                origin,
+            });
+        }
+
+        let mut control = Vec::new();
+        if rpc.has_control() {
+            let mut proto = rpc.take_control();
+            for mut ihave in proto.take_ihave().into_iter() {
+                control.push(GossipControl::IHave {
+                    topic: ihave.take_topic(),
+                    message_ids: ihave.take_message_ids(),
+                });
+            }
+            for mut iwant in proto.take_iwant().into_iter() {
+                control.push(GossipControl::IWant {
+                    message_ids: iwant.take_message_ids(),
+                });
+            }
+            for mut graft in proto.take_graft().into_iter() {
+                control.push(GossipControl::Graft {
+                    topic: graft.take_topic(),
+                });
+            }
+            for mut prune in proto.take_prune().into_iter() {
+                control.push(GossipControl::Prune {
+                    topic: prune.take_topic(),
+                });
+            }
         }
 
         Ok(Some(FloodsubRpc {
+            control,
             messages,
             subscriptions: rpc
                 .take_subscriptions()
@@ -195,6 +283,8 @@ pub struct FloodsubRpc {
     pub messages: Vec<FloodsubMessage>,
     /// List of subscriptions.
     pub subscriptions: Vec<FloodsubSubscription>,
+    /// Gossip control messages, always empty for floodsub.
+    pub control: Vec<GossipControl>,
 }
 
 /// A message received by the floodsub system.
@@ -205,14 +295,61 @@ pub struct FloodsubMessage {
 
     /// Content of the message. Its meaning is out of scope of this library.
     pub data: Vec<u8>,
+
+    /// Signed envelope of the message, if the publisher has signed it.
+    pub origin: Option<MessageOrigin>,
 }
 
 impl FloodsubMessage {
+    /// Identifier of the message.
+    /// Relays can strip or replace the optional envelope, so it isn't included.
     pub fn digest(&self) -> u64 {
+        Self::content_digest(&self.topic, &self.data)
+    }
+
+    /// Identifier of the message together with its envelope.
+    pub fn envelope_digest(&self) -> u64 {
         let mut hasher = DefaultHasher::new();
         self.hash(&mut hasher);
         hasher.finish()
     }
+
+    /// Identifier of the payload, independent of its envelope.
+    pub fn content_digest(topic: &str, data: &[u8]) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        topic.hash(&mut hasher);
+        data.hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+/// Signed envelope of a broadcast message.
+///
+/// The signature is checked before the message is dispatched or forwarded.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MessageOrigin {
+    /// Network key of the publisher.
+    pub from: Vec<u8>,
+    /// Sequence number chosen by the publisher.
+    pub seq_no: Vec<u8>,
+    /// Signature over topic, seq_no and data.
+    pub signature: Vec<u8>,
+}
+
+/// A control message of the gossipsub protocol.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum GossipControl {
+    /// The remote has these messages of the topic.
+    IHave {
+        topic: String,
+        message_ids: Vec<u64>,
+    },
+    /// The remote requests these messages.
+    IWant { message_ids: Vec<u64> },
+    /// The remote added us to its mesh of the topic.
+    Graft { topic: String },
+    /// The remote removed us from its mesh of the topic.
+    Prune { topic: String },
 }
 
 /// A subscription received by the floodsub system.
@@ -232,3 +369,95 @@ pub enum FloodsubSubscriptionAction {
     /// The remote wants to unsubscribe from the given topic.
     Unsubscribe,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codec() {
+        let rpc = FloodsubRpc {
+            messages: vec![
+                FloodsubMessage {
+                    topic: "tx".to_string(),
+                    data: vec![1, 2, 3],
+                    origin: None,
+                },
+                FloodsubMessage {
+                    topic: "tx".to_string(),
+                    data: vec![4, 5],
+                    origin: Some(MessageOrigin {
+                        from: vec![6; 65],
+                        seq_no: vec![7; 8],
+                        signature: vec![8; 33],
+                    }),
+                },
+            ],
+            subscriptions: vec![FloodsubSubscription {
+                action: FloodsubSubscriptionAction::Subscribe,
+                topic: "tx".to_string(),
+            }],
+            control: vec![
+                GossipControl::IHave {
+                    topic: "tx".to_string(),
+                    message_ids: vec![1, 2],
+                },
+                GossipControl::IWant {
+                    message_ids: vec![3],
+                },
+                GossipControl::Graft {
+                    topic: "block".to_string(),
+                },
+                GossipControl::Prune {
+                    topic: "tx".to_string(),
+                },
+            ],
+        };
+        let mut codec = FloodsubCodec {
+            length_prefix: Default::default(),
+        };
+        let mut buf = BytesMut::new();
+        codec.encode(rpc.clone(), &mut buf).unwrap();
+        let decoded = codec.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(decoded, rpc);
+    }
+
+    #[test]
+    fn digest() {
+        let unsigned = FloodsubMessage {
+            topic: "tx".to_string(),
+            data: vec![1, 2, 3],
+            origin: None,
+        };
+        assert_eq!(
+            unsigned.digest(),
+            FloodsubMessage::content_digest("tx", &[1, 2, 3])
+        );
+        let origin = MessageOrigin {
+            from: vec![6; 65],
+            seq_no: vec![7; 8],
+            signature: vec![8; 33],
+        };
+        let signed = FloodsubMessage {
+            origin: Some(origin.clone()),
+            ..unsigned.clone()
+        };
+        // The envelope doesn't change the identity.
+        assert_eq!(signed.digest(), unsigned.digest());
+        let mut other = signed.clone();
+        other.origin.as_mut().unwrap().seq_no = vec![9; 8];
+        assert_eq!(other.digest(), signed.digest());
+        let mut other = signed.clone();
+        other.origin.as_mut().unwrap().from = vec![9; 65];
+        assert_eq!(other.digest(), signed.digest());
+        // The payload does.
+        let mut other = signed.clone();
+        other.data = vec![4];
+        assert_ne!(other.digest(), signed.digest());
+        // Envelopes with different signatures are distinct.
+        let mut other = signed.clone();
+        other.origin.as_mut().unwrap().signature = vec![9; 33];
+        assert_ne!(other.envelope_digest(), signed.envelope_digest());
+        assert_ne!(unsigned.envelope_digest(), signed.envelope_digest());
+    }
+}
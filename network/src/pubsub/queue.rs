@@ -0,0 +1,303 @@
+//
+// MIT License
+//
+// Copyright (c) 2018-2019 Stegos AG
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//!
+//! Outbound queue of broadcast messages with token-bucket limits and priorities.
+//!
+
+use super::metrics;
+use super::protocol::FloodsubMessage;
+use crate::config::BandwidthConfig;
+use crate::utils::TokenBucket;
+use futures::prelude::*;
+use libp2p_core::PeerId;
+use log::*;
+use std::cmp::{max, min};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::{Duration, Instant};
+use tokio_timer::Delay;
+
+// Minimal delay between attempts to send rate-limited messages.
+const MIN_RETRY_INTERVAL: Duration = Duration::from_millis(10);
+
+struct QueuedMessage {
+    peer_id: PeerId,
+    message: FloodsubMessage,
+    size: u64,
+    queued_at: Instant,
+}
+
+/// Outgoing broadcast messages waiting to be sent.
+///
+/// Messages of priority topics are sent first and bypass the limits.
+/// Other messages are sent when both the peer and the topic limits allow.
+pub struct OutboundQueue {
+    /// Topics with strict priority.
+    priority_topics: HashSet<String>,
+    /// Limits per peer.
+    peer_rate: u64,
+    peer_burst: u64,
+    peer_buckets: HashMap<PeerId, TokenBucket>,
+    /// Limits per topic.
+    topic_buckets: HashMap<String, TokenBucket>,
+    /// Queued messages of priority topics.
+    priority: VecDeque<QueuedMessage>,
+    /// Queued messages of other topics.
+    normal: VecDeque<QueuedMessage>,
+    /// Maximal length of the normal queue.
+    max_queue_len: usize,
+    /// Maximal time in the normal queue.
+    max_queue_delay: Duration,
+    /// Wait for limits to allow sending.
+    wakeup: Option<Delay>,
+}
+
+impl OutboundQueue {
+    pub fn new(config: &BandwidthConfig) -> Self {
+        let topic_buckets = config
+            .topics
+            .iter()
+            .map(|(topic, limit)| (topic.clone(), TokenBucket::new(limit.rate, limit.burst)))
+            .collect();
+        OutboundQueue {
+            priority_topics: config.priority_topics.iter().cloned().collect(),
+            peer_rate: config.peer_rate,
+            peer_burst: config.peer_burst,
+            peer_buckets: HashMap::new(),
+            topic_buckets,
+            priority: VecDeque::new(),
+            normal: VecDeque::new(),
+            max_queue_len: config.max_queue_len,
+            max_queue_delay: Duration::from_secs(config.max_queue_delay),
+            wakeup: None,
+        }
+    }
+
+    /// Queues the message for sending to the peer.
+    pub fn push(&mut self, peer_id: PeerId, message: FloodsubMessage) {
+        let size = (message.topic.len() + message.data.len()) as u64;
+        let queued = QueuedMessage {
+            peer_id,
+            message,
+            size,
+            queued_at: Instant::now(),
+        };
+        if self.priority_topics.contains(&queued.message.topic) {
+            self.priority.push_back(queued);
+        } else if self.normal.len() >= self.max_queue_len {
+            debug!(target: "stegos_network::pubsub", "outbound queue is full, dropping message: peer_id={}, topic={}", queued.peer_id, queued.message.topic);
+            metrics::OUTGOING_DROPPED
+                .with_label_values(&[&queued.message.topic])
+                .inc();
+            return;
+        } else {
+            self.normal.push_back(queued);
+        }
+        // Re-check limits on the next poll.
+        self.wakeup = None;
+        self.update_metrics();
+    }
+
+    /// Drops all messages queued for the peer.
+    pub fn remove_peer(&mut self, peer_id: &PeerId) {
+        self.priority.retain(|m| &m.peer_id != peer_id);
+        self.normal.retain(|m| &m.peer_id != peer_id);
+        self.peer_buckets.remove(peer_id);
+        self.update_metrics();
+    }
+
+    /// Returns the next message allowed to be sent.
+    pub fn poll(&mut self) -> Async<(PeerId, FloodsubMessage)> {
+        loop {
+            if let Some(wakeup) = &mut self.wakeup {
+                match wakeup.poll() {
+                    Ok(Async::NotReady) => return Async::NotReady,
+                    Ok(Async::Ready(())) => self.wakeup = None,
+                    Err(e) => {
+                        error!(target: "stegos_network::pubsub", "outbound queue timer error: {}", e);
+                        self.wakeup = None;
+                    }
+                }
+            }
+            match self.pop(Instant::now()) {
+                Ok(queued) => {
+                    self.update_metrics();
+                    return Async::Ready((queued.peer_id, queued.message));
+                }
+                Err(None) => return Async::NotReady,
+                Err(Some(wait)) => {
+                    let wait = max(wait, MIN_RETRY_INTERVAL);
+                    self.wakeup = Some(Delay::new(Instant::now() + wait));
+                }
+            }
+        }
+    }
+
+    /// Takes the next message allowed to be sent.
+    /// Returns time to wait for limits if all queued messages are limited.
+    fn pop(&mut self, now: Instant) -> Result<QueuedMessage, Option<Duration>> {
+        if let Some(queued) = self.priority.pop_front() {
+            // Priority messages are never delayed, but still count against the peer.
+            if let Some(bucket) = self.peer_buckets.get_mut(&queued.peer_id) {
+                bucket.consume(queued.size, now);
+            }
+            observe_delay("priority", &queued, now);
+            return Ok(queued);
+        }
+
+        while let Some(queued) = self.normal.front() {
+            if queued.queued_at + self.max_queue_delay >= now {
+                break;
+            }
+            let queued = self.normal.pop_front().unwrap();
+            debug!(target: "stegos_network::pubsub", "outbound message expired, dropping: peer_id={}, topic={}", queued.peer_id, queued.message.topic);
+            metrics::OUTGOING_DROPPED
+                .with_label_values(&[&queued.message.topic])
+                .inc();
+        }
+
+        let mut wait: Option<Duration> = None;
+        for i in 0..self.normal.len() {
+            let queued = &self.normal[i];
+            let mut queued_wait = Duration::from_secs(0);
+            if self.peer_rate > 0 {
+                let (rate, burst) = (self.peer_rate, self.peer_burst);
+                let bucket = self
+                    .peer_buckets
+                    .entry(queued.peer_id.clone())
+                    .or_insert_with(|| TokenBucket::new(rate, burst));
+                queued_wait = max(queued_wait, bucket.wait_time(queued.size, now));
+            }
+            if let Some(bucket) = self.topic_buckets.get_mut(&queued.message.topic) {
+                queued_wait = max(queued_wait, bucket.wait_time(queued.size, now));
+            }
+            if queued_wait == Duration::from_secs(0) {
+                let queued = self.normal.remove(i).unwrap();
+                if let Some(bucket) = self.peer_buckets.get_mut(&queued.peer_id) {
+                    bucket.consume(queued.size, now);
+                }
+                if let Some(bucket) = self.topic_buckets.get_mut(&queued.message.topic) {
+                    bucket.consume(queued.size, now);
+                }
+                observe_delay("normal", &queued, now);
+                return Ok(queued);
+            }
+            wait = Some(wait.map_or(queued_wait, |w| min(w, queued_wait)));
+        }
+        Err(wait)
+    }
+
+    fn update_metrics(&self) {
+        metrics::OUTGOING_QUEUE_LEN.set((self.priority.len() + self.normal.len()) as i64);
+    }
+}
+
+fn observe_delay(priority: &str, queued: &QueuedMessage, now: Instant) {
+    let delay = if now > queued.queued_at {
+        now.duration_since(queued.queued_at)
+    } else {
+        Duration::from_secs(0)
+    };
+    let delay = delay.as_secs() as f64 + delay.subsec_nanos() as f64 * 1e-9;
+    metrics::OUTGOING_QUEUE_DELAY
+        .with_label_values(&[priority])
+        .observe(delay);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::TopicLimit;
+
+    fn message(topic: &str, size: usize) -> FloodsubMessage {
+        FloodsubMessage {
+            topic: topic.to_string(),
+            data: vec![0u8; size - topic.len()],
+            origin: None,
+        }
+    }
+
+    #[test]
+    fn priority_and_limits() {
+        let mut config = BandwidthConfig::default();
+        config.topics.insert(
+            "tx".to_string(),
+            TopicLimit {
+                rate: 1000,
+                burst: 1000,
+            },
+        );
+        let mut queue = OutboundQueue::new(&config);
+        let peer1 = PeerId::random();
+        let peer2 = PeerId::random();
+        let now = Instant::now();
+
+        queue.push(peer1.clone(), message("tx", 1000));
+        queue.push(peer1.clone(), message("tx", 1000));
+        queue.push(peer2.clone(), message("block", 100));
+        queue.push(peer2.clone(), message("consensus", 100));
+
+        // Priority first.
+        let queued = queue.pop(now).ok().unwrap();
+        assert_eq!(queued.message.topic, "consensus");
+        // Then by order, while limits allow.
+        let queued = queue.pop(now).ok().unwrap();
+        assert_eq!(queued.message.topic, "tx");
+        // The second tx is limited, but the block is not.
+        let queued = queue.pop(now).ok().unwrap();
+        assert_eq!(queued.message.topic, "block");
+        assert_eq!(queue.pop(now).err().unwrap(), Some(Duration::from_secs(1)));
+        let queued = queue.pop(now + Duration::from_secs(1)).ok().unwrap();
+        assert_eq!(queued.message.topic, "tx");
+        assert_eq!(queue.pop(now).err().unwrap(), None);
+
+        // Per-peer limits.
+        let mut config = BandwidthConfig::default();
+        config.peer_rate = 100;
+        config.peer_burst = 100;
+        let mut queue = OutboundQueue::new(&config);
+        queue.push(peer1.clone(), message("tx", 100));
+        queue.push(peer1.clone(), message("tx", 100));
+        queue.push(peer2.clone(), message("tx", 100));
+        assert_eq!(queue.pop(now).ok().unwrap().peer_id, peer1);
+        assert_eq!(queue.pop(now).ok().unwrap().peer_id, peer2);
+        assert!(queue.pop(now).is_err());
+        queue.remove_peer(&peer1);
+        assert_eq!(queue.pop(now + Duration::from_secs(1)).err().unwrap(), None);
+    }
+
+    #[test]
+    fn overflow() {
+        let mut config = BandwidthConfig::default();
+        config.max_queue_len = 1;
+        let mut queue = OutboundQueue::new(&config);
+        let peer_id = PeerId::random();
+        let now = Instant::now();
+        queue.push(peer_id.clone(), message("tx", 10));
+        queue.push(peer_id.clone(), message("tx", 10));
+        queue.push(peer_id.clone(), message("consensus", 10));
+        assert_eq!(queue.pop(now).ok().unwrap().message.topic, "consensus");
+        assert_eq!(queue.pop(now).ok().unwrap().message.topic, "tx");
+        assert!(queue.pop(now).is_err());
+    }
+}
@@ -63,15 +63,19 @@ pub struct Replication<TSubstream> {
     /// Events that need to be yielded to the outside when polling.
     events: VecDeque<NetworkBehaviourAction<HandlerInEvent, ReplicationEvent>>,
 
+    /// Outgoing rate limit per peer (bytes per sec, 0 - unlimited).
+    upload_rate: u64,
+
     /// Marker to pin the generics.
     marker: PhantomData<TSubstream>,
 }
 
 impl<TSubstream> Replication<TSubstream> {
     /// Creates a `Replication`.
-    pub fn new() -> Self {
+    pub fn new(upload_rate: u64) -> Self {
         Replication {
             events: VecDeque::new(),
+            upload_rate,
             marker: PhantomData,
         }
     }
@@ -103,7 +107,7 @@ where
     type OutEvent = ReplicationEvent;
 
     fn new_handler(&mut self) -> Self::ProtocolsHandler {
-        ReplicationHandler::new()
+        ReplicationHandler::new(self.upload_rate)
     }
 
     fn addresses_of_peer(&mut self, _peer_id: &PeerId) -> Vec<Multiaddr> {
@@ -34,8 +34,12 @@ use libp2p_swarm::protocols_handler::{
 use log::*;
 use std::fmt;
 use std::io;
+use std::time::{Duration, Instant};
 use tokio::codec::Framed;
 use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_timer::Delay;
+
+use crate::utils::TokenBucket;
 
 const INPUT_BUFFER_SIZE: usize = 10;
 const OUTPUT_BUFFER_SIZE: usize = 10;
@@ -84,10 +88,12 @@ where
     /// Connected to a remote side.
     Connected {
         protocol: Framed<Negotiated<TSubstream>, ReplicationCodec>,
+        upload_rate: u64,
     },
     /// Accepted a remote side.
     Accepted {
         protocol: Framed<Negotiated<TSubstream>, ReplicationCodec>,
+        upload_rate: u64,
     },
     /// Forwarding network <-> mpsc::channel().
     /// Sic: Rust doesn't support Box::new() for <T> type.
@@ -112,12 +118,16 @@ where
         >,
         tx_forward: future::Map<
             stream::Forward<
-                stream::MapErr<futures::sync::mpsc::Receiver<Vec<u8>>, fn(()) -> io::Error>,
+                Throttled<
+                    stream::MapErr<futures::sync::mpsc::Receiver<Vec<u8>>, fn(()) -> io::Error>,
+                >,
                 stream::SplitSink<Framed<Negotiated<TSubstream>, ReplicationCodec>>,
             >,
             fn(
                 (
-                    stream::MapErr<futures::sync::mpsc::Receiver<Vec<u8>>, fn(()) -> io::Error>,
+                    Throttled<
+                        stream::MapErr<futures::sync::mpsc::Receiver<Vec<u8>>, fn(()) -> io::Error>,
+                    >,
                     stream::SplitSink<Framed<Negotiated<TSubstream>, ReplicationCodec>>,
                 ),
             ),
@@ -129,6 +139,69 @@ fn to_io_error<E>(_e: E) -> io::Error {
     io::Error::new(io::ErrorKind::ConnectionReset, "channel")
 }
 
+/// Stream which limits the rate of outgoing bytes.
+pub struct Throttled<S> {
+    inner: S,
+    bucket: TokenBucket,
+    /// Message waiting for tokens.
+    pending: Option<Vec<u8>>,
+    /// Delay until enough tokens.
+    delay: Option<Delay>,
+}
+
+impl<S> Throttled<S> {
+    /// Limits `inner` to `rate` bytes per second (0 - unlimited).
+    fn new(inner: S, rate: u64) -> Self {
+        Throttled {
+            inner,
+            bucket: TokenBucket::new(rate, rate),
+            pending: None,
+            delay: None,
+        }
+    }
+}
+
+impl<S> Stream for Throttled<S>
+where
+    S: Stream<Item = Vec<u8>, Error = io::Error>,
+{
+    type Item = Vec<u8>;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            if let Some(delay) = &mut self.delay {
+                match delay.poll() {
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Ok(Async::Ready(())) => self.delay = None,
+                    Err(e) => {
+                        error!("Throttle timer error: {}", e);
+                        self.delay = None;
+                    }
+                }
+            }
+            let item = match self.pending.take() {
+                Some(item) => item,
+                None => match self.inner.poll()? {
+                    Async::Ready(Some(item)) => item,
+                    Async::Ready(None) => return Ok(Async::Ready(None)),
+                    Async::NotReady => return Ok(Async::NotReady),
+                },
+            };
+            let now = Instant::now();
+            let wait = self.bucket.wait_time(item.len() as u64, now);
+            if wait > Duration::from_secs(0) {
+                trace!("Throttling: bytes={}, wait={:?}", item.len(), wait);
+                self.pending = Some(item);
+                self.delay = Some(Delay::new(now + wait));
+                continue;
+            }
+            self.bucket.consume(item.len() as u64, now);
+            return Ok(Async::Ready(Some(item)));
+        }
+    }
+}
+
 impl<TSubstream> Future for SubstreamState<TSubstream>
 where
     TSubstream: AsyncRead + AsyncWrite + Send,
@@ -142,17 +215,25 @@ where
             | SubstreamState::InjectConnecting
             | SubstreamState::Connecting => Ok(Async::NotReady),
             SubstreamState::Connected { .. } | SubstreamState::Accepted { .. } => {
-                let protocol = match std::mem::replace(self, SubstreamState::Registered) {
-                    SubstreamState::Connected { protocol }
-                    | SubstreamState::Accepted { protocol } => protocol,
-                    _ => unreachable!("Expected Connected|Accepted state"),
-                };
+                let (protocol, upload_rate) =
+                    match std::mem::replace(self, SubstreamState::Registered) {
+                        SubstreamState::Connected {
+                            protocol,
+                            upload_rate,
+                        }
+                        | SubstreamState::Accepted {
+                            protocol,
+                            upload_rate,
+                        } => (protocol, upload_rate),
+                        _ => unreachable!("Expected Connected|Accepted state"),
+                    };
 
                 let (net_tx, net_rx) = protocol.split();
                 let (node_tx, rx) = mpsc::channel::<Vec<u8>>(INPUT_BUFFER_SIZE);
                 let (tx, node_rx) = mpsc::channel::<Vec<u8>>(OUTPUT_BUFFER_SIZE);
                 let node_tx = node_tx.sink_map_err(to_io_error as fn(_) -> _);
-                let node_rx = node_rx.map_err(to_io_error as fn(_) -> _);
+                let node_rx =
+                    Throttled::new(node_rx.map_err(to_io_error as fn(_) -> _), upload_rate);
 
                 let rx_forward = net_rx.forward(node_tx).map(drop as fn(_));
                 let tx_forward = node_rx.forward(net_tx).map(drop as fn(_) -> _);
@@ -204,6 +285,8 @@ where
 {
     /// Configuration for the floodsub protocol.
     config: ReplicationConfig,
+    /// Outgoing rate limit (bytes per sec, 0 - unlimited).
+    upload_rate: u64,
 
     upstream: SubstreamState<TSubstream>,
     downstream: SubstreamState<TSubstream>,
@@ -214,9 +297,10 @@ where
     TSubstream: AsyncRead + AsyncWrite + Send,
 {
     /// Builds a new `ReplicationHandler`.
-    pub fn new() -> Self {
+    pub fn new(upload_rate: u64) -> Self {
         ReplicationHandler {
             config: ReplicationConfig::new(),
+            upload_rate,
             upstream: SubstreamState::Registered,
             downstream: SubstreamState::Registered,
         }
@@ -247,7 +331,10 @@ where
         match self.downstream {
             SubstreamState::Registered | SubstreamState::ConnectionFailed { .. } => {
                 debug!("Accepted");
-                self.downstream = SubstreamState::Accepted { protocol };
+                self.downstream = SubstreamState::Accepted {
+                    protocol,
+                    upload_rate: self.upload_rate,
+                };
             }
             _ => {
                 debug!("Rejected");
@@ -263,7 +350,10 @@ where
         match self.upstream {
             SubstreamState::Connecting => {
                 debug!("Connected");
-                self.upstream = SubstreamState::Connected { protocol };
+                self.upstream = SubstreamState::Connected {
+                    protocol,
+                    upload_rate: self.upload_rate,
+                };
             }
             _ => {
                 debug!("Disconnect");
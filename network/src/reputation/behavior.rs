@@ -0,0 +1,522 @@
+//
+// MIT License
+//
+// Copyright (c) 2018-2019 Stegos AG
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use futures::prelude::*;
+use libp2p_core::{ConnectedPoint, Multiaddr, PeerId};
+use libp2p_swarm::{
+    protocols_handler::{DummyProtocolsHandler, ProtocolsHandler},
+    NetworkBehaviour, NetworkBehaviourAction, PollParameters,
+};
+use log::*;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::marker::PhantomData;
+use std::str::FromStr;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_timer::Delay;
+use void::Void;
+
+use super::metrics;
+use crate::config::{NetworkConfig, PeerScoringConfig};
+
+// How often to decay scores and expire bans
+const DECAY_INTERVAL: Duration = Duration::from_secs(10);
+// Forget peers with score closer to zero than this
+const SCORE_EPSILON: f64 = 0.1;
+
+/// Misbehaviour of a remote peer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PeerOffense {
+    /// A message which can't be decoded.
+    MalformedMessage,
+    /// A block which failed validation.
+    InvalidBlock,
+    /// A transaction which failed validation.
+    InvalidTransaction,
+    /// A consensus message which failed validation.
+    InvalidConsensusMessage,
+    /// A broadcast message with a bad signature of the publisher on a signed topic.
+    InvalidSignature,
+    /// Broadcast messages above network.peer_scoring.max_message_rate.
+    RateLimitExceeded,
+    /// A replication batch which can't be parsed or applied.
+    BadReplicationBatch,
+}
+
+impl PeerOffense {
+    /// How much this offense decreases the peer score.
+    pub fn penalty(&self) -> f64 {
+        match self {
+            PeerOffense::MalformedMessage => 25.0,
+            PeerOffense::InvalidBlock => 50.0,
+            PeerOffense::InvalidTransaction => 10.0,
+            PeerOffense::InvalidConsensusMessage => 25.0,
+            PeerOffense::InvalidSignature => 25.0,
+            PeerOffense::RateLimitExceeded => 10.0,
+            PeerOffense::BadReplicationBatch => 50.0,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            PeerOffense::MalformedMessage => "malformed_message",
+            PeerOffense::InvalidBlock => "invalid_block",
+            PeerOffense::InvalidTransaction => "invalid_transaction",
+            PeerOffense::InvalidConsensusMessage => "invalid_consensus_message",
+            PeerOffense::InvalidSignature => "invalid_signature",
+            PeerOffense::RateLimitExceeded => "rate_limit_exceeded",
+            PeerOffense::BadReplicationBatch => "bad_replication_batch",
+        }
+    }
+}
+
+/// Peer score for passing to API
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PeerScoreInfo {
+    /// Libp2p PeerId of the node
+    pub peer_id: String,
+    /// Current score (zero for well-behaved peers, negative for offenders)
+    pub score: f64,
+    /// Broadcast messages from this peer are ignored
+    pub graylisted: bool,
+    /// Unix timestamp (secs) when the ban expires
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub banned_until: Option<u64>,
+}
+
+struct PeerScore {
+    score: f64,
+    updated: Instant,
+}
+
+/// Network behaviour which keeps scores of remote peers.
+/// Doesn't open any substreams, just emits events when a peer should be graylisted or banned.
+pub struct Reputation<TSubstream> {
+    /// Thresholds and decay parameters.
+    config: PeerScoringConfig,
+    /// Events that need to be yielded to the outside when polling.
+    events: VecDeque<NetworkBehaviourAction<Void, ReputationEvent>>,
+    /// Scores of peers which have misbehaved recently.
+    scores: HashMap<PeerId, PeerScore>,
+    /// Peers with score below graylist_threshold.
+    graylisted: HashSet<PeerId>,
+    /// Banned peers with ban expiration time.
+    bans: HashMap<PeerId, SystemTime>,
+    /// Decay timer.
+    decay_delay: Delay,
+    /// Marker to pin the generics.
+    marker: PhantomData<TSubstream>,
+}
+
+impl<TSubstream> Reputation<TSubstream> {
+    /// Creates a `Reputation`, loading bans from network.peer_scoring.bans_file.
+    pub fn new(config: &NetworkConfig) -> Self {
+        let config = config.peer_scoring.clone();
+        let bans = load_bans(&config.bans_file);
+        metrics::BANNED_PEERS.set(bans.len() as i64);
+        Reputation {
+            config,
+            events: VecDeque::new(),
+            scores: HashMap::new(),
+            graylisted: HashSet::new(),
+            bans,
+            decay_delay: Delay::new(Instant::now() + DECAY_INTERVAL),
+            marker: PhantomData,
+        }
+    }
+
+    /// Decreases the score of the peer.
+    pub fn report(&mut self, peer_id: &PeerId, offense: PeerOffense) {
+        self.penalize(peer_id, offense, false)
+    }
+
+    /// Decreases the score of the peer which has forwarded an invalid message.
+    /// The peer might be an honest relay, so it is never graylisted or banned for this.
+    pub fn report_relayed(&mut self, peer_id: &PeerId, offense: PeerOffense) {
+        self.penalize(peer_id, offense, true)
+    }
+
+    fn penalize(&mut self, peer_id: &PeerId, offense: PeerOffense, relayed: bool) {
+        metrics::OFFENSES.with_label_values(&[offense.name()]).inc();
+        if self.bans.contains_key(peer_id) {
+            return;
+        }
+        let half_life = Duration::from_secs(self.config.decay_half_life);
+        let entry = self.scores.entry(peer_id.clone()).or_insert(PeerScore {
+            score: 0.0,
+            updated: Instant::now(),
+        });
+        let decayed = decay(entry.score, entry.updated.elapsed(), half_life);
+        entry.score = decayed - offense.penalty();
+        if relayed {
+            let floor = self.config.graylist_threshold + SCORE_EPSILON;
+            entry.score = entry.score.max(decayed.min(floor));
+        }
+        entry.updated = Instant::now();
+        let score = entry.score;
+        debug!(target: "stegos_network::reputation", "peer misbehaved: peer_id={}, offense={:?}, relayed={}, score={}", peer_id, offense, relayed, score);
+
+        if score <= self.config.ban_threshold {
+            let until = SystemTime::now() + Duration::from_secs(self.config.ban_duration);
+            warn!(target: "stegos_network::reputation", "banning peer: peer_id={}, score={}, duration={}s", peer_id, score, self.config.ban_duration);
+            self.scores.remove(peer_id);
+            self.bans.insert(peer_id.clone(), until);
+            self.save_bans();
+            metrics::BANNED_PEERS.set(self.bans.len() as i64);
+            self.graylist(peer_id);
+            self.events.push_back(NetworkBehaviourAction::GenerateEvent(
+                ReputationEvent::Banned {
+                    peer_id: peer_id.clone(),
+                },
+            ));
+        } else if score <= self.config.graylist_threshold {
+            self.graylist(peer_id);
+        }
+    }
+
+    /// Returns true if connections with this peer are not allowed.
+    pub fn is_banned(&self, peer_id: &PeerId) -> bool {
+        self.bans.contains_key(peer_id)
+    }
+
+    /// Returns scores of misbehaving and banned peers.
+    pub fn scores(&self) -> Vec<PeerScoreInfo> {
+        let half_life = Duration::from_secs(self.config.decay_half_life);
+        let mut peers: Vec<PeerScoreInfo> = self
+            .scores
+            .iter()
+            .map(|(peer_id, s)| PeerScoreInfo {
+                peer_id: peer_id.to_base58(),
+                score: decay(s.score, s.updated.elapsed(), half_life),
+                graylisted: self.graylisted.contains(peer_id),
+                banned_until: None,
+            })
+            .collect();
+        for (peer_id, until) in self.bans.iter() {
+            peers.push(PeerScoreInfo {
+                peer_id: peer_id.to_base58(),
+                score: self.config.ban_threshold,
+                graylisted: true,
+                banned_until: Some(unix_secs(*until)),
+            });
+        }
+        peers.sort_by(|a, b| a.peer_id.cmp(&b.peer_id));
+        peers
+    }
+
+    fn graylist(&mut self, peer_id: &PeerId) {
+        if self.graylisted.insert(peer_id.clone()) {
+            debug!(target: "stegos_network::reputation", "graylisting peer: peer_id={}", peer_id);
+            metrics::GRAYLISTED_PEERS.set(self.graylisted.len() as i64);
+            self.events.push_back(NetworkBehaviourAction::GenerateEvent(
+                ReputationEvent::Graylisted {
+                    peer_id: peer_id.clone(),
+                },
+            ));
+        }
+    }
+
+    fn ungraylist(&mut self, peer_id: &PeerId) {
+        if self.graylisted.remove(peer_id) {
+            debug!(target: "stegos_network::reputation", "peer is no more graylisted: peer_id={}", peer_id);
+            metrics::GRAYLISTED_PEERS.set(self.graylisted.len() as i64);
+            self.events.push_back(NetworkBehaviourAction::GenerateEvent(
+                ReputationEvent::Ungraylisted {
+                    peer_id: peer_id.clone(),
+                },
+            ));
+        }
+    }
+
+    /// Decays scores and expires bans.
+    fn on_decay_timer(&mut self) {
+        let half_life = Duration::from_secs(self.config.decay_half_life);
+        let mut ungraylisted = Vec::new();
+        for (peer_id, s) in self.scores.iter_mut() {
+            s.score = decay(s.score, s.updated.elapsed(), half_life);
+            s.updated = Instant::now();
+            if s.score > self.config.graylist_threshold {
+                ungraylisted.push(peer_id.clone());
+            }
+        }
+        self.scores.retain(|_, s| s.score.abs() >= SCORE_EPSILON);
+
+        let now = SystemTime::now();
+        let expired: Vec<PeerId> = self
+            .bans
+            .iter()
+            .filter(|(_, until)| **until <= now)
+            .map(|(peer_id, _)| peer_id.clone())
+            .collect();
+        for peer_id in expired.iter() {
+            info!(target: "stegos_network::reputation", "ban expired: peer_id={}", peer_id);
+            self.bans.remove(peer_id);
+            ungraylisted.push(peer_id.clone());
+        }
+        if !expired.is_empty() {
+            self.save_bans();
+            metrics::BANNED_PEERS.set(self.bans.len() as i64);
+        }
+
+        for peer_id in ungraylisted {
+            if !self.bans.contains_key(&peer_id) {
+                self.ungraylist(&peer_id);
+            }
+        }
+    }
+
+    fn save_bans(&self) {
+        if self.config.bans_file == "" {
+            return;
+        }
+        let mut contents = String::new();
+        for (peer_id, until) in self.bans.iter() {
+            contents.push_str(&format!("{} {}\n", peer_id.to_base58(), unix_secs(*until)));
+        }
+        if let Err(e) = fs::write(&self.config.bans_file, contents) {
+            error!(target: "stegos_network::reputation", "failed to save bans: file={}, error={}", self.config.bans_file, e);
+        }
+    }
+}
+
+impl<TSubstream> NetworkBehaviour for Reputation<TSubstream>
+where
+    TSubstream: AsyncRead + AsyncWrite,
+{
+    type ProtocolsHandler = DummyProtocolsHandler<TSubstream>;
+    type OutEvent = ReputationEvent;
+
+    fn new_handler(&mut self) -> Self::ProtocolsHandler {
+        DummyProtocolsHandler::default()
+    }
+
+    fn addresses_of_peer(&mut self, _: &PeerId) -> Vec<Multiaddr> {
+        Vec::new()
+    }
+
+    fn inject_connected(&mut self, id: PeerId, _: ConnectedPoint) {
+        if self.bans.contains_key(&id) {
+            debug!(target: "stegos_network::reputation", "banned peer connected: peer_id={}", id);
+            self.events.push_back(NetworkBehaviourAction::GenerateEvent(
+                ReputationEvent::Banned { peer_id: id },
+            ));
+        }
+    }
+
+    fn inject_disconnected(&mut self, _: &PeerId, _: ConnectedPoint) {}
+
+    fn inject_node_event(&mut self, _: PeerId, event: Void) {
+        void::unreachable(event)
+    }
+
+    fn poll(
+        &mut self,
+        _: &mut impl PollParameters,
+    ) -> Async<
+        NetworkBehaviourAction<
+            <Self::ProtocolsHandler as ProtocolsHandler>::InEvent,
+            Self::OutEvent,
+        >,
+    > {
+        loop {
+            match self.decay_delay.poll() {
+                Ok(Async::Ready(_)) => {
+                    self.on_decay_timer();
+                    self.decay_delay.reset(Instant::now() + DECAY_INTERVAL);
+                }
+                Ok(Async::NotReady) => break,
+                Err(e) => {
+                    debug!(target: "stegos_network::reputation", "decay timer error: error={}", e);
+                    break;
+                }
+            }
+        }
+
+        if let Some(event) = self.events.pop_front() {
+            return Async::Ready(event);
+        }
+
+        Async::NotReady
+    }
+}
+
+/// Event that can happen on the reputation behaviour.
+#[derive(Debug)]
+pub enum ReputationEvent {
+    /// Ignore broadcast messages from this peer.
+    Graylisted { peer_id: PeerId },
+    /// Accept broadcast messages from this peer again.
+    Ungraylisted { peer_id: PeerId },
+    /// Disconnect from this peer.
+    Banned { peer_id: PeerId },
+}
+
+/// Decays score exponentially towards zero.
+fn decay(score: f64, elapsed: Duration, half_life: Duration) -> f64 {
+    if half_life == Duration::from_secs(0) {
+        return 0.0;
+    }
+    let periods = elapsed.as_secs_f64() / half_life.as_secs_f64();
+    score * 0.5f64.powf(periods)
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Loads not expired bans, one "PEER_ID UNIX_SECS" pair per line.
+fn load_bans(path: &str) -> HashMap<PeerId, SystemTime> {
+    let mut bans = HashMap::new();
+    if path == "" {
+        return bans;
+    }
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) => {
+            debug!(target: "stegos_network::reputation", "no saved bans: file={}, error={}", path, e);
+            return bans;
+        }
+    };
+    let now = SystemTime::now();
+    for line in contents.lines() {
+        let mut parts = line.split_whitespace();
+        let (peer_id, until) = match (parts.next(), parts.next()) {
+            (Some(peer_id), Some(until)) => (peer_id, until),
+            _ => continue,
+        };
+        let peer_id = match PeerId::from_str(peer_id) {
+            Ok(peer_id) => peer_id,
+            Err(_) => {
+                warn!(target: "stegos_network::reputation", "invalid peer id in bans file: {}", peer_id);
+                continue;
+            }
+        };
+        let until = match until.parse::<u64>() {
+            Ok(secs) => UNIX_EPOCH + Duration::from_secs(secs),
+            Err(_) => continue,
+        };
+        if until > now {
+            bans.insert(peer_id, until);
+        }
+    }
+    info!(target: "stegos_network::reputation", "loaded bans: file={}, count={}", path, bans.len());
+    bans
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decay_halves_score() {
+        let half_life = Duration::from_secs(60);
+        assert_eq!(decay(-100.0, Duration::from_secs(0), half_life), -100.0);
+        assert_eq!(decay(-100.0, half_life, half_life), -50.0);
+        assert_eq!(decay(-100.0, half_life * 2, half_life), -25.0);
+        assert_eq!(decay(-100.0, half_life, Duration::from_secs(0)), 0.0);
+    }
+
+    #[test]
+    fn thresholds() {
+        let config = NetworkConfig::default();
+        let mut reputation = Reputation::<()>::new(&config);
+        let peer_id = PeerId::random();
+
+        reputation.report(&peer_id, PeerOffense::InvalidTransaction);
+        assert!(reputation.events.is_empty());
+        assert!(!reputation.graylisted.contains(&peer_id));
+
+        reputation.report(&peer_id, PeerOffense::InvalidBlock);
+        assert!(reputation.graylisted.contains(&peer_id));
+        match reputation.events.pop_front() {
+            Some(NetworkBehaviourAction::GenerateEvent(ReputationEvent::Graylisted {
+                peer_id: p,
+            })) => assert_eq!(p, peer_id),
+            _ => panic!("unexpected event"),
+        }
+        assert!(!reputation.is_banned(&peer_id));
+
+        reputation.report(&peer_id, PeerOffense::InvalidBlock);
+        assert!(reputation.is_banned(&peer_id));
+        match reputation.events.pop_front() {
+            Some(NetworkBehaviourAction::GenerateEvent(ReputationEvent::Banned { peer_id: p })) => {
+                assert_eq!(p, peer_id)
+            }
+            _ => panic!("unexpected event"),
+        }
+        let scores = reputation.scores();
+        assert_eq!(scores.len(), 1);
+        assert!(scores[0].banned_until.is_some());
+
+        // Other peers are not affected.
+        let other = PeerId::random();
+        assert!(!reputation.is_banned(&other));
+    }
+
+    #[test]
+    fn relayed_offenses() {
+        let config = NetworkConfig::default();
+        let mut reputation = Reputation::<()>::new(&config);
+        let peer_id = PeerId::random();
+
+        for _ in 0..10 {
+            reputation.report_relayed(&peer_id, PeerOffense::InvalidBlock);
+        }
+        assert!(reputation.events.is_empty());
+        assert!(!reputation.graylisted.contains(&peer_id));
+        assert!(!reputation.is_banned(&peer_id));
+        let scores = reputation.scores();
+        assert_eq!(scores.len(), 1);
+        assert!(scores[0].score < 0.0);
+        assert!(scores[0].score > config.peer_scoring.graylist_threshold);
+
+        // Own offenses are still counted.
+        reputation.report(&peer_id, PeerOffense::InvalidBlock);
+        assert!(reputation.graylisted.contains(&peer_id));
+        reputation.report(&peer_id, PeerOffense::InvalidBlock);
+        assert!(reputation.is_banned(&peer_id));
+    }
+
+    #[test]
+    fn bans_file() {
+        let path = std::env::temp_dir().join(format!("stegos_bans_{}.txt", rand::random::<u64>()));
+        let path = path.to_string_lossy().to_string();
+        let mut config = NetworkConfig::default();
+        config.peer_scoring.bans_file = path.clone();
+        let peer_id = PeerId::random();
+        {
+            let mut reputation = Reputation::<()>::new(&config);
+            reputation.report(&peer_id, PeerOffense::BadReplicationBatch);
+            reputation.report(&peer_id, PeerOffense::BadReplicationBatch);
+            assert!(reputation.is_banned(&peer_id));
+        }
+        let reputation = Reputation::<()>::new(&config);
+        assert!(reputation.is_banned(&peer_id));
+        fs::remove_file(&path).unwrap();
+    }
+}
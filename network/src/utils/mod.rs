@@ -25,11 +25,15 @@ mod expiring_queue;
 mod lru_bimap;
 mod multihash;
 mod peer_id_key;
+mod token_bucket;
 
 pub use self::expiring_queue::ExpiringQueue;
 pub use self::lru_bimap::LruBimap;
 pub use self::multihash::IntoMultihash;
 pub use self::peer_id_key::PeerIdKey;
+pub use self::token_bucket::TokenBucket;
+use crate::config::{TransportConfig, TransportProfile};
+use failure::{format_err, Error};
 use libp2p_core::multiaddr::{Multiaddr, Protocol};
 use std::net::SocketAddr;
 
@@ -38,3 +42,29 @@ pub fn socket_to_multi_addr(addr: &SocketAddr) -> Multiaddr {
     maddr.push(Protocol::Tcp(addr.port()));
     maddr
 }
+
+/// Converts the socket address to the multiaddr of the configured transport.
+pub fn endpoint_to_multi_addr(addr: &SocketAddr, config: &TransportConfig) -> Multiaddr {
+    let mut maddr = socket_to_multi_addr(addr);
+    if config.profile == TransportProfile::Websocket {
+        if config.tls_cert_file != "" {
+            maddr.push(Protocol::Wss);
+        } else {
+            maddr.push(Protocol::Ws);
+        }
+    }
+    maddr
+}
+
+/// Parses an endpoint from the config.
+/// A full multiaddr (e.g. "/ip4/1.2.3.4/tcp/10203/ws") selects the transport itself,
+/// "host:port" uses the configured transport profile.
+pub fn parse_endpoint(endpoint: &str, config: &TransportConfig) -> Result<Multiaddr, Error> {
+    if endpoint.starts_with('/') {
+        return endpoint
+            .parse::<Multiaddr>()
+            .map_err(|e| format_err!("{}", e));
+    }
+    let addr = endpoint.parse::<SocketAddr>()?;
+    Ok(endpoint_to_multi_addr(&addr, config))
+}
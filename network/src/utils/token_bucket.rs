@@ -0,0 +1,117 @@
+//
+// MIT License
+//
+// Copyright (c) 2018-2019 Stegos AG
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use std::time::{Duration, Instant};
+
+/// Token bucket rate limiter.
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    /// Tokens added per second (0 - unlimited).
+    rate: u64,
+    /// Maximal number of tokens.
+    burst: u64,
+    /// Currently available tokens.
+    tokens: f64,
+    /// Time of the last refill.
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(rate: u64, burst: u64) -> Self {
+        TokenBucket {
+            rate,
+            burst,
+            tokens: burst as f64,
+            last_refill: Instant::now(),
+        }
+    }
+
+    pub fn is_unlimited(&self) -> bool {
+        self.rate == 0
+    }
+
+    fn refill(&mut self, now: Instant) {
+        if now <= self.last_refill {
+            return;
+        }
+        let elapsed = now.duration_since(self.last_refill);
+        let elapsed = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 * 1e-9;
+        self.tokens = (self.tokens + elapsed * self.rate as f64).min(self.burst as f64);
+        self.last_refill = now;
+    }
+
+    /// Returns true if `amount` tokens can be taken now.
+    /// Requests larger than the burst are allowed when the bucket is full.
+    pub fn check(&mut self, amount: u64, now: Instant) -> bool {
+        if self.is_unlimited() {
+            return true;
+        }
+        self.refill(now);
+        self.tokens >= (amount.min(self.burst)) as f64
+    }
+
+    /// Takes `amount` tokens, the balance can go negative.
+    pub fn consume(&mut self, amount: u64, now: Instant) {
+        if self.is_unlimited() {
+            return;
+        }
+        self.refill(now);
+        self.tokens -= amount as f64;
+    }
+
+    /// Returns time until `amount` tokens become available.
+    pub fn wait_time(&mut self, amount: u64, now: Instant) -> Duration {
+        if self.check(amount, now) {
+            return Duration::from_secs(0);
+        }
+        let missing = amount.min(self.burst) as f64 - self.tokens;
+        Duration::from_millis((missing * 1000.0 / self.rate as f64).ceil() as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_bucket() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(100, 200);
+        assert!(bucket.check(200, now));
+        bucket.consume(200, now);
+        assert!(!bucket.check(1, now));
+        assert_eq!(bucket.wait_time(50, now), Duration::from_millis(500));
+        let now = now + Duration::from_secs(1);
+        assert!(bucket.check(100, now));
+        assert!(!bucket.check(101, now));
+        let now = now + Duration::from_secs(10);
+        // Capped by burst
+        assert!(bucket.check(1000, now));
+        bucket.consume(1000, now);
+        assert!(!bucket.check(1, now));
+
+        let mut unlimited = TokenBucket::new(0, 0);
+        assert!(unlimited.check(std::u64::MAX, now));
+        assert_eq!(unlimited.wait_time(1000, now), Duration::from_secs(0));
+    }
+}
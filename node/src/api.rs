@@ -27,15 +27,16 @@ use std::collections::HashMap;
 use stegos_blockchain::api::StatusInfo;
 use stegos_blockchain::{
     ElectionInfo, EpochInfo, EscrowInfo, MacroBlock, MicroBlock, Output, Timestamp, Transaction,
-    TransactionStatus, ValidatorKeyInfo,
+    TransactionStatus, ValidatorKeyInfo, ValidatorStats,
 };
 use stegos_crypto::hash::Hash;
-use stegos_crypto::scc;
 use stegos_crypto::utils::{
     deserialize_protobuf_array_from_hex, deserialize_protobuf_from_hex,
     serialize_protobuf_array_to_hex, serialize_protobuf_to_hex,
 };
+use stegos_crypto::{pbc, scc};
 use stegos_replication::api::*;
+use stegos_txpool::TxPoolInfo;
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(tag = "output_type")]
@@ -109,6 +110,18 @@ pub enum NodeRequest {
         epoch: u64,
         offset: u32,
     },
+    TxPoolInfo {},
+    ConsensusInfo {},
+    /// Get performance of validator for epochs in range [from_epoch, to_epoch].
+    ValidatorStats {
+        pkey: pbc::PublicKey,
+        from_epoch: u64,
+        to_epoch: u64,
+    },
+    /// Skip the specified epoch for maintenance, zero cancels scheduled maintenance.
+    ScheduleMaintenance {
+        epoch: u64,
+    },
 }
 
 ///
@@ -149,6 +162,10 @@ pub enum NodeResponse {
     },
     RestakingEnabled,
     RestakingDisabled,
+    MaintenanceScheduled {
+        epoch: u64,
+        tx_hash: Hash,
+    },
     UpstreamChanged,
     StatusInfo(StatusInfo),
     ValidatorsInfo {
@@ -171,6 +188,12 @@ pub enum NodeResponse {
         #[serde(skip)]
         rx: Option<mpsc::Receiver<ChainNotification>>, // Option is needed for serde.
     },
+    TxPoolInfo(TxPoolInfo),
+    ConsensusInfo(ConsensusInfo),
+    ValidatorStats {
+        pkey: pbc::PublicKey,
+        stats: Vec<ValidatorStats>,
+    },
     Error {
         error: String,
     },
@@ -240,6 +263,80 @@ impl RevertedMicroBlock {
     }
 }
 
+/// Role of the node in the current consensus.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ValidationRole {
+    MicroBlockAuditor,
+    MicroBlockValidator,
+    MacroBlockAuditor,
+    MacroBlockValidator,
+}
+
+/// View change votes collected for the current micro block.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ViewChangeVotesInfo {
+    /// Validators which have voted for a view change.
+    pub validators: Vec<pbc::PublicKey>,
+    /// Slots owned by these validators.
+    pub collected_slots: i64,
+    /// Total number of slots in the epoch.
+    pub total_slots: i64,
+}
+
+/// A consensus message queued until the node reaches its epoch.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FutureConsensusMessageInfo {
+    pub epoch: u64,
+    pub round: u32,
+    pub name: String,
+    pub pkey: pbc::PublicKey,
+}
+
+/// State of pBFT consensus for the macro block.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MacroBlockConsensusInfo {
+    pub round: u32,
+    pub state: String,
+    /// Leader of the current round.
+    pub leader: pbc::PublicKey,
+    /// Validators which have sent a prevote in the current round.
+    pub prevotes: Vec<pbc::PublicKey>,
+    /// Validators which have sent a precommit in the current round.
+    pub precommits: Vec<pbc::PublicKey>,
+    /// True if some block is locked from the previous rounds.
+    pub locked: bool,
+}
+
+/// Time left until the consensus timers fire, in milliseconds.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ConsensusTimersInfo {
+    pub macro_block_propose: Option<u64>,
+    pub macro_block_view_change: Option<u64>,
+    pub micro_block_view_change: Option<u64>,
+    /// True if VDF for the next micro block is being solved.
+    pub micro_block_solving: bool,
+}
+
+/// Consensus state of the node.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ConsensusInfo {
+    pub epoch: u64,
+    pub offset: u32,
+    pub view_change: u32,
+    pub role: ValidationRole,
+    /// Leader of the current block.
+    pub leader: pbc::PublicKey,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub view_change_votes: Option<ViewChangeVotesInfo>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default)]
+    pub future_consensus_messages: Vec<FutureConsensusMessageInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub macro_block_consensus: Option<MacroBlockConsensusInfo>,
+    pub timers: ConsensusTimersInfo,
+}
+
 /// PA micro block with extra information.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ExtendedMicroBlock {
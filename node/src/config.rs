@@ -23,6 +23,7 @@
 
 use serde_derive::{Deserialize, Serialize};
 use std::time::Duration;
+use stegos_txpool::TxPoolConfig;
 
 /// Node configuration.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
@@ -50,6 +51,12 @@ pub struct NodeConfig {
     pub min_payment_fee: i64,
     /// Minimal fee for the stake transactions.
     pub min_stake_fee: i64,
+    /// Snowball pool formation policy, used when this node is a facilitator.
+    pub txpool: TxPoolConfig,
+    /// Directory with network.skey/network.pkey.
+    /// A rotated key pair is picked up from network.next.skey/network.next.pkey
+    /// at the start of the epoch. Empty value disables key rotation.
+    pub keys_dir: String,
 }
 
 impl Default for NodeConfig {
@@ -67,6 +74,8 @@ impl Default for NodeConfig {
             max_outputs_in_mempool: 10000,
             min_payment_fee: 1_000, // 0.001 STG
             min_stake_fee: 0,       // free
+            txpool: Default::default(),
+            keys_dir: String::new(),
         }
     }
 }
@@ -24,8 +24,10 @@
 // SOFTWARE.
 
 use failure::Fail;
-use stegos_blockchain::{BlockError, BlockchainError, StorageError};
+use stegos_blockchain::{BlockError, BlockchainError, StorageError, TransactionError};
+use stegos_consensus::ConsensusError;
 use stegos_crypto::hash::Hash;
+use stegos_network::PeerOffense;
 
 #[derive(Debug, Fail, PartialEq, Eq)]
 pub enum NodeTransactionError {
@@ -90,3 +92,36 @@ impl From<StorageError> for ForkError {
 }
 
 pub type ForkResult = Result<(), ForkError>;
+
+/// Returns the offense of a peer which sent us a message failed with this error.
+/// Errors which can be caused by honest peers (forks, races, lagging) are not offenses.
+pub(crate) fn peer_offense(error: &failure::Error) -> Option<PeerOffense> {
+    if let Some(e) = error.downcast_ref::<BlockError>() {
+        return match e {
+            BlockError::InvalidLeaderSignature(..)
+            | BlockError::InvalidBlockSignature(..)
+            | BlockError::InvalidVDFProof(..)
+            | BlockError::IncorrectRandom(..)
+            | BlockError::InvalidBlockBalance(..) => Some(PeerOffense::InvalidBlock),
+            _ => None,
+        };
+    }
+    if let Some(e) = error.downcast_ref::<TransactionError>() {
+        return match e {
+            TransactionError::InvalidSignature(..)
+            | TransactionError::InvalidMonetaryBalance(..)
+            | TransactionError::NegativeFee(..) => Some(PeerOffense::InvalidTransaction),
+            _ => None,
+        };
+    }
+    if let Some(e) = error.downcast_ref::<ConsensusError>() {
+        return match e {
+            ConsensusError::InvalidMessageSignature
+            | ConsensusError::InvalidRequestSignature(..) => {
+                Some(PeerOffense::InvalidConsensusMessage)
+            }
+            _ => None,
+        };
+    }
+    None
+}
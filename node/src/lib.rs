@@ -42,6 +42,8 @@ use futures::sync::oneshot;
 use rand::{self, Rng};
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
 use std::time::{Duration, Instant};
 use stegos_blockchain::Timestamp;
 use stegos_blockchain::*;
@@ -53,6 +55,10 @@ use stegos_crypto::hash::Hash;
 use stegos_crypto::scc::Fr;
 use stegos_crypto::vdf::VDF;
 use stegos_crypto::{pbc, scc};
+use stegos_keychain::keyfile::{
+    load_network_keypair, NEXT_NETWORK_PKEY_FILE, NEXT_NETWORK_SKEY_FILE,
+};
+use stegos_network::PeerOffense;
 use stegos_serialization::traits::ProtoConvert;
 pub use stegos_txpool::MAX_PARTICIPANTS;
 use Validation::*;
@@ -143,8 +149,17 @@ pub enum NodeOutgoingEvent {
         topic: String,
         data: Vec<u8>,
     },
+    ReportMessage {
+        topic: String,
+        data: Vec<u8>,
+        offense: PeerOffense,
+    },
     FacilitatorChanged {
-        facilitator: pbc::PublicKey,
+        facilitators: Vec<pbc::PublicKey>,
+    },
+    ChangeNetworkKeys {
+        network_skey: pbc::SecretKey,
+        network_pkey: pbc::PublicKey,
     },
     ReplicationBlock {
         block: Block,
@@ -279,6 +294,7 @@ impl NodeState {
 
     /// Invoked when network is ready.
     pub fn init(&mut self) -> Result<(), Error> {
+        self.switch_network_keys()?;
         self.update_validation_status();
         self.on_facilitator_changed();
         self.on_status_changed();
@@ -286,6 +302,42 @@ impl NodeState {
         Ok(())
     }
 
+    /// Switch to the next network key pair after the stakes have been re-bound to it.
+    fn switch_network_keys(&mut self) -> Result<(), Error> {
+        if self.cfg.keys_dir.is_empty() {
+            return Ok(());
+        }
+        let keys_dir = Path::new(&self.cfg.keys_dir);
+        let next_skey_file = keys_dir.join(NEXT_NETWORK_SKEY_FILE);
+        let next_pkey_file = keys_dir.join(NEXT_NETWORK_PKEY_FILE);
+        if !next_skey_file.exists() || !next_pkey_file.exists() {
+            return Ok(());
+        }
+        if self
+            .chain
+            .account_by_network_key(&self.network_pkey)
+            .is_some()
+        {
+            // The rotation hasn't been applied yet.
+            return Ok(());
+        }
+        let (network_skey, network_pkey) = load_network_keypair(&next_skey_file, &next_pkey_file)?;
+        if self.chain.account_by_network_key(&network_pkey).is_none() {
+            return Ok(());
+        }
+
+        sinfo!(self, "Switching network key: new_pkey={}", network_pkey);
+        fs::rename(&next_skey_file, keys_dir.join("network.skey"))?;
+        fs::rename(&next_pkey_file, keys_dir.join("network.pkey"))?;
+        self.network_skey = network_skey.clone();
+        self.network_pkey = network_pkey.clone();
+        self.outgoing.push(NodeOutgoingEvent::ChangeNetworkKeys {
+            network_skey,
+            network_pkey,
+        });
+        Ok(())
+    }
+
     /// Send transaction to node and to the network.
     fn send_transaction(&mut self, tx: Transaction) -> Result<(), Error> {
         let data = tx.into_buffer()?;
@@ -342,10 +394,11 @@ impl NodeState {
         let check_limits = match &tx {
             Transaction::PaymentTransaction(_tx) => true,
             Transaction::RestakeTransaction(_tx) => false,
+            Transaction::ValidatorKeyRotationTransaction(_tx) => false,
             _ => return Err(NodeTransactionError::InvalidType(tx_hash).into()),
         };
 
-        // Ignore all limits for RestakeTransaction.
+        // Ignore all limits for RestakeTransaction and ValidatorKeyRotationTransaction.
         if check_limits {
             // Limit the number of inputs and outputs.
             if tx.txins().len() > self.cfg.max_inputs_in_tx {
@@ -453,12 +506,21 @@ impl NodeState {
         }
         assert_eq!(self.cfg.min_stake_fee, 0);
         strace!(self, "Restaking expiring stakes");
+        let owner_pkey = match self.chain.account_by_network_key(&self.network_pkey) {
+            Some(owner_pkey) => owner_pkey,
+            None => return Ok(()), // Nothing to re-stake.
+        };
         let mut inputs: Vec<Output> = Vec::new();
         let mut output_info = None;
         let mut pending_txs = HashSet::new();
         for (input_hash, amount, account_pkey, active_until_epoch) in
             self.chain.iter_validator_stakes(&self.network_pkey)
         {
+            // Delegators re-stake on their own.
+            if account_pkey != &owner_pkey {
+                continue;
+            }
+
             // Re-stake in the last epoch.
             if self.chain.epoch() < active_until_epoch {
                 sdebug!(
@@ -512,12 +574,20 @@ impl NodeState {
         let (account_pkey, amount) = output_info.expect("some output info");
 
         strace!(self, "Creating StakeUTXO ...");
-        let output = Output::new_stake(
+        let commission = self.chain.validator_commission(&self.network_pkey);
+        let maintenance_epoch = self
+            .chain
+            .scheduled_maintenance(&self.network_pkey)
+            .unwrap_or(0);
+        let output: Output = StakeOutput::new_with_maintenance(
             &account_pkey,
             &self.network_skey,
             &self.network_pkey,
             amount,
-        )?;
+            commission,
+            maintenance_epoch,
+        )?
+        .into();
         let output_hash = Hash::digest(&output);
         sinfo!(self, "Restake: new_utxo={}, amount={}", output_hash, amount);
 
@@ -943,6 +1013,11 @@ impl NodeState {
         // Remove conflict transactions from the mempool.
         self.mempool.prune(inputs.iter(), outputs.keys());
 
+        // Stakes rotated during the previous epoch are bound to the new key now.
+        if let Err(e) = self.switch_network_keys() {
+            serror!(self, "Failed to switch network keys: error={}", e);
+        }
+
         let epoch_info = self
             .chain
             .epoch_info(epoch)?
@@ -1329,9 +1404,9 @@ impl NodeState {
 
     /// Called when facilitator is changed.
     fn on_facilitator_changed(&mut self) {
-        let facilitator = self.chain.facilitator().clone();
+        let facilitators = self.chain.facilitators();
         self.outgoing
-            .push(NodeOutgoingEvent::FacilitatorChanged { facilitator });
+            .push(NodeOutgoingEvent::FacilitatorChanged { facilitators });
     }
 
     ///
@@ -1545,7 +1620,7 @@ impl NodeState {
         // Propose a new block.
         let recipient_pkey = self
             .chain
-            .account_by_network_key(&self.network_pkey)
+            .validator_account(&self.network_pkey)
             .expect("Staked");
 
         let (block, transactions) = self.chain.create_macro_block(
@@ -1832,7 +1907,7 @@ impl NodeState {
         // Create a new micro block from the mempool.
         let recipient_pkey = self
             .chain
-            .account_by_network_key(&self.network_pkey)
+            .validator_account(&self.network_pkey)
             .expect("Staked");
         let timestamp = self.next_block_timestamp();
         let mut block = self.mempool.create_block(
@@ -1843,7 +1918,7 @@ impl NodeState {
             view_change_proof,
             self.chain.last_random(),
             solution,
-            self.chain.cfg().block_reward,
+            self.chain.coinbase_reward(&self.network_pkey),
             &recipient_pkey,
             &self.network_skey,
             &self.network_pkey,
@@ -1922,6 +1997,178 @@ impl NodeState {
         Ok(block)
     }
 
+    /// Re-stake own stakes to skip the specified epoch.
+    /// Zero epoch cancels scheduled maintenance.
+    /// The stakes keep their current lock.
+    fn handle_schedule_maintenance(&mut self, epoch: u64) -> Result<Hash, Error> {
+        let current_epoch = self.chain.epoch();
+        if epoch != 0 && epoch <= current_epoch {
+            return Err(format_err!(
+                "Maintenance can be scheduled only for future epochs: epoch={}, current_epoch={}",
+                epoch,
+                current_epoch
+            ));
+        }
+        if epoch > current_epoch + self.chain.cfg().stake_epochs {
+            return Err(format_err!(
+                "Maintenance is scheduled too far: epoch={}, current_epoch={}, stake_epochs={}",
+                epoch,
+                current_epoch,
+                self.chain.cfg().stake_epochs
+            ));
+        }
+        // Re-staking without changes would re-lock the stakes.
+        let scheduled_epoch = self
+            .chain
+            .scheduled_maintenance(&self.network_pkey)
+            .unwrap_or(0);
+        if epoch == scheduled_epoch {
+            return Err(format_err!("Maintenance is unchanged: epoch={}", epoch));
+        }
+        let owner_pkey = match self.chain.account_by_network_key(&self.network_pkey) {
+            Some(owner_pkey) => owner_pkey,
+            None => {
+                return Err(format_err!(
+                    "Node has no stakes: pkey={}",
+                    self.network_pkey
+                ))
+            }
+        };
+
+        let mut inputs: Vec<Output> = Vec::new();
+        let mut amount: i64 = 0;
+        for (input_hash, stake_amount, account_pkey, _active_until_epoch) in
+            self.chain.iter_validator_stakes(&self.network_pkey)
+        {
+            // Delegators don't take part in maintenance.
+            if account_pkey != &owner_pkey {
+                continue;
+            }
+            if let Some(tx_hash) = self.mempool.get_tx_by_input(input_hash) {
+                return Err(format_err!(
+                    "Stake is pending: utxo={}, tx={}",
+                    input_hash,
+                    tx_hash
+                ));
+            }
+            let input = self
+                .chain
+                .output_by_hash(input_hash)?
+                .expect("Stake exists");
+            inputs.push(input);
+            amount += stake_amount;
+        }
+
+        let commission = self.chain.validator_commission(&self.network_pkey);
+        let output: Output = StakeOutput::new_with_maintenance(
+            &owner_pkey,
+            &self.network_skey,
+            &self.network_pkey,
+            amount,
+            commission,
+            epoch,
+        )?
+        .into();
+        let tx =
+            RestakeTransaction::new(&self.network_skey, &self.network_pkey, &inputs, &[output])?;
+        let tx_hash = Hash::digest(&tx);
+        sinfo!(
+            self,
+            "Created a maintenance transaction: hash={}, epoch={}, inputs={}, amount={}",
+            tx_hash,
+            epoch,
+            tx.txins.len(),
+            amount
+        );
+        self.send_transaction(tx.into())?;
+        Ok(tx_hash)
+    }
+
+    fn handle_validator_stats(
+        &self,
+        pkey: &pbc::PublicKey,
+        from_epoch: u64,
+        to_epoch: u64,
+    ) -> Result<Vec<ValidatorStats>, Error> {
+        if from_epoch > to_epoch {
+            return Err(format_err!(
+                "Invalid epoch range: from_epoch={}, to_epoch={}",
+                from_epoch,
+                to_epoch
+            ));
+        }
+        let mut stats = self.chain.validator_stats(pkey, from_epoch, to_epoch)?;
+        // Include the current epoch, which is not finished yet.
+        let epoch = self.chain.epoch();
+        if from_epoch <= epoch && epoch <= to_epoch {
+            if let Some(current) = self.chain.epoch_stats().get(pkey) {
+                stats.push(current.clone());
+            }
+        }
+        Ok(stats)
+    }
+
+    /// Returns the current state of consensus.
+    /// Timers are owned by NodeService and must be provided by the caller.
+    pub(crate) fn consensus_info(&self, timers: ConsensusTimersInfo) -> ConsensusInfo {
+        let mut view_change_votes = None;
+        let mut future_consensus_messages = Vec::new();
+        let mut macro_block_consensus = None;
+        let role = match &self.validation {
+            MicroBlockAuditor => ValidationRole::MicroBlockAuditor,
+            MicroBlockValidator {
+                view_change_collector,
+                future_consensus_messages: messages,
+                ..
+            } => {
+                let validators = view_change_collector
+                    .voted_validators()
+                    .into_iter()
+                    .filter_map(|id| self.chain.validator_key_by_id(id as usize))
+                    .collect();
+                view_change_votes = Some(ViewChangeVotesInfo {
+                    validators,
+                    collected_slots: view_change_collector.collected_slots(),
+                    total_slots: self.chain.total_slots(),
+                });
+                future_consensus_messages = messages
+                    .iter()
+                    .map(|msg| FutureConsensusMessageInfo {
+                        epoch: msg.epoch,
+                        round: msg.round,
+                        name: msg.name().to_string(),
+                        pkey: msg.pkey,
+                    })
+                    .collect();
+                ValidationRole::MicroBlockValidator
+            }
+            MacroBlockAuditor => ValidationRole::MacroBlockAuditor,
+            MacroBlockValidator { consensus, .. } => {
+                let info = consensus.to_info();
+                macro_block_consensus = Some(MacroBlockConsensusInfo {
+                    round: info.round,
+                    state: info.state.name().to_string(),
+                    leader: info.leader,
+                    prevotes: info.prevotes,
+                    precommits: info.precommits,
+                    locked: info.locked,
+                });
+                ValidationRole::MacroBlockValidator
+            }
+        };
+        ConsensusInfo {
+            epoch: self.chain.epoch(),
+            offset: self.chain.offset(),
+            view_change: self.chain.view_change(),
+            role,
+            leader: self.chain.leader(),
+            view_change_votes,
+            future_consensus_messages,
+            macro_block_consensus,
+            timers,
+        }
+    }
+
     fn handle_create_raw_tx(
         &mut self,
         txins: Vec<Hash>,
@@ -1979,6 +2226,48 @@ impl NodeState {
     // Event Handling
     /////////////////////////////////////////////////////////////////////////////////////////////////
 
+    /// Decodes and processes a broadcast message.
+    /// Penalizes the peer which forwarded the message if it is malformed or invalid.
+    fn handle_broadcast<M, F>(
+        &mut self,
+        topic: &str,
+        data: Vec<u8>,
+        handler: F,
+    ) -> Result<(), Error>
+    where
+        M: ProtoConvert,
+        F: FnOnce(&mut Self, M) -> Result<(), Error>,
+    {
+        let msg = match M::from_buffer(&data) {
+            Ok(msg) => msg,
+            Err(e) => {
+                self.report_message(topic, data, PeerOffense::MalformedMessage);
+                return Err(e);
+            }
+        };
+        let result = handler(self, msg);
+        if let Err(e) = &result {
+            if let Some(offense) = peer_offense(e) {
+                self.report_message(topic, data, offense);
+            }
+        }
+        result
+    }
+
+    fn report_message(&mut self, topic: &str, data: Vec<u8>, offense: PeerOffense) {
+        sdebug!(
+            self,
+            "Reporting invalid message: topic={}, offense={:?}",
+            topic,
+            offense
+        );
+        self.outgoing.push(NodeOutgoingEvent::ReportMessage {
+            topic: topic.to_string(),
+            data,
+            offense,
+        });
+    }
+
     fn handle_event(&mut self, event: NodeIncomingEvent) {
         let result: Result<(), Error> = match event {
             NodeIncomingEvent::Request { request, tx } => {
@@ -2165,13 +2454,37 @@ impl NodeState {
                     NodeRequest::SubscribeChain { .. } => {
                         unreachable!("Must be handled by NodeService");
                     }
+                    NodeRequest::TxPoolInfo {} => {
+                        unreachable!("Must be handled by NodeService");
+                    }
+                    NodeRequest::ConsensusInfo {} => {
+                        unreachable!("Must be handled by NodeService");
+                    }
+                    NodeRequest::ValidatorStats {
+                        pkey,
+                        from_epoch,
+                        to_epoch,
+                    } => match self.handle_validator_stats(&pkey, from_epoch, to_epoch) {
+                        Ok(stats) => NodeResponse::ValidatorStats { pkey, stats },
+                        Err(e) => NodeResponse::Error {
+                            error: format!("{}", e),
+                        },
+                    },
+                    NodeRequest::ScheduleMaintenance { epoch } => {
+                        match self.handle_schedule_maintenance(epoch) {
+                            Ok(tx_hash) => NodeResponse::MaintenanceScheduled { epoch, tx_hash },
+                            Err(e) => NodeResponse::Error {
+                                error: format!("{}", e),
+                            },
+                        }
+                    }
                 };
                 strace!(self, "<= {:?}", response);
                 tx.send(response).ok(); // ignore errors.
                 Ok(())
             }
             NodeIncomingEvent::Transaction(msg) => {
-                match Transaction::from_buffer(&msg).and_then(|msg| self.handle_transaction(msg)) {
+                match self.handle_broadcast(TX_TOPIC, msg, Self::handle_transaction) {
                     Ok(()) => Ok(()),
                     Err(e) => match e.downcast_ref::<NodeTransactionError>() {
                         Some(NodeTransactionError::NotSynchronized(_)) => Ok(()),
@@ -2179,20 +2492,25 @@ impl NodeState {
                     },
                 }
             }
-            NodeIncomingEvent::Consensus(msg) => ConsensusMessage::from_buffer(&msg)
-                .and_then(|msg| self.handle_consensus_message(msg)),
-            NodeIncomingEvent::ViewChangeMessage(msg) => ViewChangeMessage::from_buffer(&msg)
-                .and_then(|msg| self.handle_view_change_message(msg)),
-            NodeIncomingEvent::ViewChangeProof(msg) => AddressedViewChangeProof::from_buffer(&msg)
-                .and_then(|proof| {
-                    self.handle_view_change_direct(proof.view_change_proof, proof.pkey)
-                }),
+            NodeIncomingEvent::Consensus(msg) => {
+                self.handle_broadcast(CONSENSUS_TOPIC, msg, Self::handle_consensus_message)
+            }
+            NodeIncomingEvent::ViewChangeMessage(msg) => {
+                self.handle_broadcast(VIEW_CHANGE_TOPIC, msg, Self::handle_view_change_message)
+            }
+            NodeIncomingEvent::ViewChangeProof(msg) => self.handle_broadcast(
+                VIEW_CHANGE_PROOFS_TOPIC,
+                msg,
+                |node, proof: AddressedViewChangeProof| {
+                    node.handle_view_change_direct(proof.view_change_proof, proof.pkey)
+                },
+            ),
             NodeIncomingEvent::ViewChangeProofMessage { from, data } => {
                 SealedViewChangeProof::from_buffer(&data)
                     .and_then(|proof| self.handle_view_change_direct(proof, from))
             }
             NodeIncomingEvent::Block(msg) => {
-                Block::from_buffer(&msg).and_then(|msg| self.handle_block(msg))
+                self.handle_broadcast(SEALED_BLOCK_TOPIC, msg, Self::handle_block)
             }
             NodeIncomingEvent::DecodedBlock(msg) => self.handle_block(msg),
             NodeIncomingEvent::CheckSyncTimer => {
@@ -2226,3 +2544,313 @@ impl NodeState {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use stegos_blockchain::test::{self as chain_test, KeyChain};
+    use tempdir::TempDir;
+
+    /// Creates a node of the first validator on a new chain.
+    fn new_node(chain_dir: &TempDir) -> (NodeState, Vec<KeyChain>, Timestamp) {
+        let mut timestamp = Timestamp::now();
+        let mut cfg = ChainConfig::default();
+        cfg.micro_blocks_in_epoch = 2;
+        let (keychains, genesis) = chain_test::fake_genesis(
+            cfg.min_stake_amount,
+            10 * cfg.min_stake_amount,
+            cfg.max_slot_count,
+            3,
+            timestamp,
+            None,
+        );
+        let chain = Blockchain::new(
+            cfg,
+            chain_dir.path(),
+            ConsistencyCheck::None,
+            genesis,
+            timestamp,
+        )
+        .expect("Failed to create blockchain");
+        timestamp += Duration::from_secs(1);
+        let node = NodeState::new(
+            NodeConfig::default(),
+            chain,
+            keychains[0].network_skey.clone(),
+            keychains[0].network_pkey,
+            "dev".to_string(),
+        )
+        .expect("Failed to create node");
+        (node, keychains, timestamp)
+    }
+
+    fn push_micro_block(node: &mut NodeState, keychains: &[KeyChain], timestamp: Timestamp) {
+        let (block, _, _) = chain_test::create_fake_micro_block(&node.chain, keychains, timestamp);
+        node.chain
+            .push_micro_block(block, timestamp)
+            .expect("no I/O errors");
+        node.update_validation_status();
+    }
+
+    /// Finishes the epoch, micro blocks are replaced by the macro block.
+    fn push_macro_block(node: &mut NodeState, keychains: &[KeyChain], timestamp: Timestamp) {
+        let (block, _) = chain_test::create_fake_macro_block(&node.chain, keychains, timestamp);
+        node.apply_macro_block(block).expect("block is valid");
+        node.update_validation_status();
+    }
+
+    /// Includes the transaction from mempool into a micro block.
+    fn commit_transaction(
+        node: &mut NodeState,
+        keychains: &[KeyChain],
+        timestamp: Timestamp,
+        tx_hash: &Hash,
+    ) {
+        let tx = node.mempool.get_tx(tx_hash).expect("tx in mempool").clone();
+        let block = chain_test::create_micro_block_with_transactions(
+            &node.chain,
+            keychains,
+            timestamp,
+            vec![tx],
+        );
+        node.chain
+            .push_micro_block(block, timestamp)
+            .expect("no I/O errors");
+        node.update_validation_status();
+    }
+
+    /// Returns the lock of own stakes of the validator.
+    fn stake_lock(node: &NodeState, network_pkey: &pbc::PublicKey) -> u64 {
+        node.chain
+            .iter_validator_stakes(network_pkey)
+            .map(|(_, _, _, active_until_epoch)| active_until_epoch)
+            .max()
+            .expect("validator has stakes")
+    }
+
+    #[test]
+    fn consensus_info() {
+        let chain_dir = TempDir::new("test").unwrap();
+        let (mut node, keychains, mut timestamp) = new_node(&chain_dir);
+        assert_eq!(
+            node.consensus_info(ConsensusTimersInfo::default()).role,
+            ValidationRole::MicroBlockAuditor
+        );
+
+        // Micro block validator.
+        node.update_validation_status();
+        let timers = ConsensusTimersInfo {
+            micro_block_view_change: Some(1000),
+            ..Default::default()
+        };
+        let info = node.consensus_info(timers);
+        assert_eq!((info.epoch, info.offset, info.view_change), (1, 0, 0));
+        assert_eq!(info.role, ValidationRole::MicroBlockValidator);
+        assert_eq!(info.leader, node.chain.leader());
+        let votes = info.view_change_votes.expect("validators collect votes");
+        assert!(votes.validators.is_empty());
+        assert_eq!(votes.collected_slots, 0);
+        assert_eq!(votes.total_slots, node.chain.total_slots());
+        assert!(info.future_consensus_messages.is_empty());
+        assert!(info.macro_block_consensus.is_none());
+        assert_eq!(info.timers.micro_block_view_change, Some(1000));
+        assert_eq!(info.timers.macro_block_propose, None);
+
+        push_micro_block(&mut node, &keychains, timestamp);
+        timestamp += Duration::from_secs(1);
+        let info = node.consensus_info(ConsensusTimersInfo::default());
+        assert_eq!((info.epoch, info.offset), (1, 1));
+        assert_eq!(info.role, ValidationRole::MicroBlockValidator);
+
+        // Macro block validator.
+        push_micro_block(&mut node, &keychains, timestamp);
+        let info = node.consensus_info(ConsensusTimersInfo::default());
+        assert_eq!((info.epoch, info.offset), (1, 2));
+        assert_eq!(info.role, ValidationRole::MacroBlockValidator);
+        assert!(info.view_change_votes.is_none());
+        let consensus = info.macro_block_consensus.expect("macro block consensus");
+        assert_eq!(consensus.round, 0);
+        assert_eq!(consensus.state, "Propose");
+        assert_eq!(consensus.leader, node.chain.select_leader(0));
+        assert!(!consensus.locked);
+    }
+
+    #[test]
+    fn validator_stats() {
+        let chain_dir = TempDir::new("test").unwrap();
+        let (mut node, keychains, mut timestamp) = new_node(&chain_dir);
+        node.update_validation_status();
+        assert_eq!(node.chain.epoch(), 1);
+
+        // Invalid range.
+        let pkey = keychains[0].network_pkey;
+        assert!(node.handle_validator_stats(&pkey, 2, 1).is_err());
+
+        // The current unfinished epoch.
+        let leader = node.chain.leader();
+        push_micro_block(&mut node, &keychains, timestamp);
+        timestamp += Duration::from_secs(1);
+        let stats = node.handle_validator_stats(&leader, 1, 1).unwrap();
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[0].epoch, 1);
+        assert_eq!(stats[0].blocks_proposed, 1);
+        assert_eq!(stats[0].view_changes, 0);
+        assert!(node
+            .handle_validator_stats(&leader, 0, 0)
+            .unwrap()
+            .is_empty());
+        assert!(node
+            .handle_validator_stats(&leader, 2, 5)
+            .unwrap()
+            .is_empty());
+
+        // Finish the epoch.
+        push_micro_block(&mut node, &keychains, timestamp);
+        timestamp += Duration::from_secs(1);
+        push_macro_block(&mut node, &keychains, timestamp);
+        assert_eq!(node.chain.epoch(), 2);
+
+        for keychain in &keychains {
+            let stats = node
+                .handle_validator_stats(&keychain.network_pkey, 1, 2)
+                .unwrap();
+            assert_eq!(stats.len(), 1);
+            assert_eq!(stats[0].epoch, 1);
+            assert_eq!(stats[0].missed_macro_signatures, 0);
+        }
+        let stats = node.handle_validator_stats(&leader, 1, 2).unwrap();
+        assert!(stats[0].blocks_proposed >= 1);
+    }
+
+    #[test]
+    fn key_rotation() {
+        let chain_dir = TempDir::new("test").unwrap();
+        let keys_dir = TempDir::new("keys").unwrap();
+        let (mut node, keychains, mut timestamp) = new_node(&chain_dir);
+        node.cfg.keys_dir = keys_dir.path().to_string_lossy().to_string();
+        node.update_validation_status();
+        let old_network_pkey = keychains[0].network_pkey;
+
+        // The wallet stores the new key pair in the keys directory of the node.
+        let (new_network_skey, new_network_pkey) =
+            stegos_keychain::keyfile::load_next_network_keys(keys_dir.path()).unwrap();
+        let stakes: Vec<Output> = node
+            .chain
+            .iter_validator_stakes(&old_network_pkey)
+            .map(|(hash, _, _, _)| node.chain.output_by_hash(hash).unwrap().unwrap())
+            .collect();
+        let tx = ValidatorKeyRotationTransaction::new(
+            &keychains[0].account_skey,
+            &keychains[0].network_skey,
+            &new_network_skey,
+            &new_network_pkey,
+            &stakes,
+        )
+        .unwrap();
+        let block = chain_test::create_micro_block_with_transactions(
+            &node.chain,
+            &keychains,
+            timestamp,
+            vec![tx.into()],
+        );
+        node.chain
+            .push_micro_block(block, timestamp)
+            .expect("no I/O errors");
+        timestamp += Duration::from_secs(1);
+
+        // The old key is used until the end of epoch.
+        node.switch_network_keys().unwrap();
+        assert_eq!(node.network_pkey, old_network_pkey);
+        assert!(keys_dir.path().join(NEXT_NETWORK_SKEY_FILE).exists());
+
+        while !node.chain.is_epoch_full() {
+            push_micro_block(&mut node, &keychains, timestamp);
+            timestamp += Duration::from_secs(1);
+        }
+        node.outgoing.clear();
+        push_macro_block(&mut node, &keychains, timestamp);
+
+        // The node has switched to the new key.
+        assert_eq!(node.network_pkey, new_network_pkey);
+        assert!(node.chain.is_validator(&new_network_pkey));
+        assert!(node.outgoing.iter().any(|event| match event {
+            NodeOutgoingEvent::ChangeNetworkKeys { network_pkey, .. } => {
+                *network_pkey == new_network_pkey
+            }
+            _ => false,
+        }));
+        assert!(!keys_dir.path().join(NEXT_NETWORK_SKEY_FILE).exists());
+        let (_, network_pkey) = load_network_keypair(
+            &keys_dir.path().join("network.skey"),
+            &keys_dir.path().join("network.pkey"),
+        )
+        .unwrap();
+        assert_eq!(network_pkey, new_network_pkey);
+    }
+
+    #[test]
+    fn maintenance() {
+        let chain_dir = TempDir::new("test").unwrap();
+        let (mut node, keychains, mut timestamp) = new_node(&chain_dir);
+        node.update_validation_status();
+        let network_pkey = keychains[0].network_pkey;
+        let lock = stake_lock(&node, &network_pkey);
+        assert!(node.chain.is_validator(&network_pkey));
+
+        // Only future epochs within the stake lock.
+        assert!(node.handle_schedule_maintenance(1).is_err());
+        let stake_epochs = node.chain.cfg().stake_epochs;
+        assert!(node.handle_schedule_maintenance(2 + stake_epochs).is_err());
+        // Nothing to cancel.
+        assert!(node.handle_schedule_maintenance(0).is_err());
+
+        // Schedule maintenance for the next epoch.
+        let tx_hash = node.handle_schedule_maintenance(2).unwrap();
+        commit_transaction(&mut node, &keychains, timestamp, &tx_hash);
+        timestamp += Duration::from_secs(1);
+        assert_eq!(node.chain.scheduled_maintenance(&network_pkey), Some(2));
+        assert_eq!(stake_lock(&node, &network_pkey), lock);
+        assert!(node.handle_schedule_maintenance(2).is_err());
+
+        // Cancel it.
+        let tx_hash = node.handle_schedule_maintenance(0).unwrap();
+        commit_transaction(&mut node, &keychains, timestamp, &tx_hash);
+        timestamp += Duration::from_secs(1);
+        assert_eq!(node.chain.scheduled_maintenance(&network_pkey), None);
+        assert_eq!(stake_lock(&node, &network_pkey), lock);
+
+        // The validator is elected for the next epoch.
+        assert!(node.chain.is_epoch_full());
+        push_macro_block(&mut node, &keychains, timestamp);
+        timestamp += Duration::from_secs(1);
+        assert_eq!(node.chain.epoch(), 2);
+        assert!(node.chain.is_validator(&network_pkey));
+
+        // Schedule maintenance again.
+        let tx_hash = node.handle_schedule_maintenance(3).unwrap();
+        commit_transaction(&mut node, &keychains, timestamp, &tx_hash);
+        timestamp += Duration::from_secs(1);
+        assert_eq!(stake_lock(&node, &network_pkey), lock);
+        while !node.chain.is_epoch_full() {
+            push_micro_block(&mut node, &keychains, timestamp);
+            timestamp += Duration::from_secs(1);
+        }
+
+        // The validator skips the scheduled epoch.
+        push_macro_block(&mut node, &keychains, timestamp);
+        timestamp += Duration::from_secs(1);
+        assert_eq!(node.chain.epoch(), 3);
+        assert!(!node.chain.is_validator(&network_pkey));
+        assert_eq!(node.chain.scheduled_maintenance(&network_pkey), None);
+
+        // And comes back after it.
+        while !node.chain.is_epoch_full() {
+            push_micro_block(&mut node, &keychains, timestamp);
+            timestamp += Duration::from_secs(1);
+        }
+        push_macro_block(&mut node, &keychains, timestamp);
+        assert_eq!(node.chain.epoch(), 4);
+        assert!(node.chain.is_validator(&network_pkey));
+        assert_eq!(stake_lock(&node, &network_pkey), lock);
+    }
+}
@@ -257,13 +257,14 @@ impl Mempool {
         let random = pbc::make_VRF(network_skey, &seed);
 
         // Sort transactions by fee.
-        // RestakeTransactions have high priority.
+        // RestakeTransactions and ValidatorKeyRotationTransactions have high priority.
         let mut pool: Vec<(&Hash, &Transaction, i64)> = self
             .pool
             .iter()
             .map(|(hash, tx)| {
                 let prio = match tx {
-                    Transaction::RestakeTransaction(_) => i64::max_value(),
+                    Transaction::RestakeTransaction(_)
+                    | Transaction::ValidatorKeyRotationTransaction(_) => i64::max_value(),
                     _ => tx.fee(),
                 };
                 (hash, tx, prio)
@@ -285,8 +286,11 @@ impl Mempool {
             match tx {
                 Transaction::PaymentTransaction(_tx) => {}
                 Transaction::RestakeTransaction(_tx) => {}
+                Transaction::ValidatorKeyRotationTransaction(_tx) => {}
                 Transaction::SlashingTransaction(_tx) => {}
-                Transaction::CoinbaseTransaction(_) | Transaction::ServiceAwardTransaction(_) => {
+                Transaction::CoinbaseTransaction(_)
+                | Transaction::ServiceAwardTransaction(_)
+                | Transaction::DelegationRewardTransaction(_) => {
                     panic!("Invalid transaction type in mempool: tx={:?}", tx_hash)
                 }
             };
@@ -24,6 +24,7 @@
 use super::api::*;
 use super::protos::{ChainLoaderMessage, RequestBlocks, ResponseBlocks};
 use super::{NodeIncomingEvent, NodeOutgoingEvent, NodeRequest, NodeResponse, NodeState};
+use crate::error::peer_offense;
 use crate::{
     NodeConfig, CHAIN_LOADER_TOPIC, CONSENSUS_TOPIC, SEALED_BLOCK_TOPIC, TX_TOPIC,
     VIEW_CHANGE_DIRECT, VIEW_CHANGE_PROOFS_TOPIC, VIEW_CHANGE_TOPIC,
@@ -34,6 +35,7 @@ use futures::{task, Async, AsyncSink, Future, Poll, Sink, Stream};
 use futures_stream_select_all_send::select_all;
 use log::*;
 use std::thread;
+use std::time::Instant;
 use stegos_blockchain::{Block, BlockReader, Blockchain, Transaction};
 use stegos_crypto::pbc;
 use stegos_network::PeerId;
@@ -291,6 +293,17 @@ impl NodeService {
         self.state.init()
     }
 
+    /// Returns time left until the consensus timers fire.
+    fn consensus_timers_info(&self) -> ConsensusTimersInfo {
+        let now = clock::now();
+        ConsensusTimersInfo {
+            macro_block_propose: time_left(&self.macro_block_propose_timer, now),
+            macro_block_view_change: time_left(&self.macro_block_view_change_timer, now),
+            micro_block_view_change: time_left(&self.micro_block_view_change_timer, now),
+            micro_block_solving: self.micro_block_propose_timer.is_some(),
+        }
+    }
+
     /// Handler subscription to status.
     fn handle_subscription_to_status(
         &mut self,
@@ -554,6 +567,24 @@ impl Future for NodeService {
                                     tx.send(response).ok(); // ignore errors.
                                     continue;
                                 }
+                                NodeRequest::TxPoolInfo {} => {
+                                    let response = match &self.txpool_service {
+                                        Some(txpool_service) => {
+                                            NodeResponse::TxPoolInfo(txpool_service.info())
+                                        }
+                                        None => NodeResponse::Error {
+                                            error: "Not a facilitator".to_string(),
+                                        },
+                                    };
+                                    tx.send(response).ok(); // ignore errors.
+                                    continue;
+                                }
+                                NodeRequest::ConsensusInfo {} => {
+                                    let timers = self.consensus_timers_info();
+                                    let info = self.state.consensus_info(timers);
+                                    tx.send(NodeResponse::ConsensusInfo(info)).ok(); // ignore errors.
+                                    continue;
+                                }
                                 NodeRequest::SubscribeChain { epoch, offset } => {
                                     let response =
                                         match self.handle_subscription_to_chain(epoch, offset) {
@@ -619,8 +650,12 @@ impl Future for NodeService {
                     panic!("Received the light block from the replication");
                 }
                 Async::Ready(Some(ReplicationRow::Block(block))) => {
-                    let event = NodeIncomingEvent::DecodedBlock(block);
-                    self.state.handle_event(event);
+                    if let Err(e) = self.state.handle_block(block) {
+                        error!("Invalid block from replication: {}", e);
+                        if peer_offense(&e).is_some() {
+                            self.replication.on_invalid_block();
+                        }
+                    }
                 }
                 Async::Ready(None) => return Ok(Async::Ready(())), // Shutdown.
                 Async::NotReady => break,
@@ -632,17 +667,27 @@ impl Future for NodeService {
         //
         for event in std::mem::replace(&mut self.state.outgoing, Vec::new()) {
             let result = match event {
-                NodeOutgoingEvent::FacilitatorChanged { facilitator } => {
-                    if facilitator == self.state.network_pkey {
+                NodeOutgoingEvent::FacilitatorChanged { facilitators } => {
+                    // Every candidate serves pools, wallets fall back to the next one
+                    // if the previous candidate doesn't respond.
+                    if facilitators.contains(&self.state.network_pkey) {
                         info!("I am facilitator");
-                        let txpool_service = TransactionPoolService::new(self.network.clone());
+                        let txpool_service = TransactionPoolService::new(
+                            self.state.cfg.txpool.clone(),
+                            self.network.clone(),
+                            self.state.network_skey.clone(),
+                        );
                         self.txpool_service = Some(txpool_service);
                     } else {
-                        info!("Facilitator is {}", facilitator);
+                        info!("Facilitators are {:?}", facilitators);
                         self.txpool_service = None;
                     }
                     Ok(())
                 }
+                NodeOutgoingEvent::ChangeNetworkKeys {
+                    network_skey,
+                    network_pkey,
+                } => self.network.change_network_keys(network_pkey, network_skey),
                 NodeOutgoingEvent::Publish { topic, data } => {
                     //
                     self.network.publish(&topic, data)
@@ -651,6 +696,11 @@ impl Future for NodeService {
                     //
                     self.network.send(dest, &topic, data)
                 }
+                NodeOutgoingEvent::ReportMessage {
+                    topic,
+                    data,
+                    offense,
+                } => self.network.report_message(&topic, data, offense),
                 NodeOutgoingEvent::MacroBlockProposeTimer(duration) => {
                     let deadline = clock::now() + duration;
                     self.macro_block_propose_timer = Some(Delay::new(deadline));
@@ -714,3 +764,31 @@ impl Future for NodeService {
         Ok(Async::NotReady)
     }
 }
+
+/// Returns the time left until the timer fires in milliseconds, None if the timer is not set.
+fn time_left(timer: &Option<Delay>, now: Instant) -> Option<u64> {
+    timer.as_ref().map(|timer| {
+        let deadline = timer.deadline();
+        if deadline > now {
+            (deadline - now).as_millis() as u64
+        } else {
+            0
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn timers_info() {
+        let now = Instant::now();
+        assert_eq!(time_left(&None, now), None);
+        let timer = Some(Delay::new(now + Duration::from_millis(1500)));
+        assert_eq!(time_left(&timer, now), Some(1500));
+        // Fired, but not handled yet.
+        assert_eq!(time_left(&timer, now + Duration::from_secs(2)), Some(0));
+    }
+}
@@ -95,6 +95,10 @@ pub(crate) fn validate_external_transaction(
     match tx {
         // Staking balance of cheater was already validated in tx.validate()
         Transaction::SlashingTransaction(_) => {}
+        // Locked stakes are moved to the new key as is.
+        Transaction::ValidatorKeyRotationTransaction(_) => {
+            chain.validate_key_rotation(inputs.iter(), tx.txouts().iter())?
+        }
         _ => chain.validate_stakes(inputs.iter(), tx.txouts().iter())?,
     }
 
@@ -102,9 +106,11 @@ pub(crate) fn validate_external_transaction(
     match tx {
         Transaction::RestakeTransaction(tx) => tx.validate(&inputs)?,
         Transaction::PaymentTransaction(tx) => tx.validate(&inputs)?,
+        Transaction::ValidatorKeyRotationTransaction(tx) => tx.validate(&inputs)?,
         Transaction::SlashingTransaction(..)
         | Transaction::CoinbaseTransaction(..)
-        | Transaction::ServiceAwardTransaction(..) => {
+        | Transaction::ServiceAwardTransaction(..)
+        | Transaction::DelegationRewardTransaction(..) => {
             return Err(TransactionError::ReceivedInvalidTransaction(tx.to_type_str()).into())
         }
     }
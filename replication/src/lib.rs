@@ -36,7 +36,7 @@ use rand::thread_rng;
 use std::collections::HashMap;
 use std::time::Duration;
 use stegos_blockchain::{Block, BlockReader, LightBlock};
-use stegos_network::{Network, PeerId, ReplicationEvent};
+use stegos_network::{Network, PeerId, PeerOffense, ReplicationEvent};
 use tokio_timer::{clock, Delay};
 
 pub enum ReplicationRow {
@@ -101,6 +101,22 @@ impl Replication {
         // A new upstream will be selected on the next poll().
     }
 
+    ///
+    /// Called when a block received from the upstream can't be applied.
+    /// Penalizes the upstream and switches to another one.
+    ///
+    pub fn on_invalid_block(&mut self) {
+        for (peer_id, peer) in self.peers.iter_mut() {
+            if peer.is_upstream() {
+                warn!("[{}] Invalid block from the upstream", peer_id);
+                self.network
+                    .report_peer(peer_id.clone(), PeerOffense::BadReplicationBatch)
+                    .expect("network is alive");
+                peer.disconnected();
+            }
+        }
+    }
+
     ///
     /// Processes a new block.
     ///
@@ -180,7 +196,8 @@ impl Replication {
         }
 
         let mut has_upstream = false;
-        for (_peer_id, peer) in self.peers.iter_mut() {
+        for (peer_id, peer) in self.peers.iter_mut() {
+            let was_misbehaving = peer.is_misbehaving();
             match peer.poll(
                 current_epoch,
                 current_offset,
@@ -192,6 +209,11 @@ impl Replication {
                 }
                 Async::NotReady => {}
             }
+            if !was_misbehaving && peer.is_misbehaving() {
+                self.network
+                    .report_peer(peer_id.clone(), PeerOffense::BadReplicationBatch)
+                    .expect("network is alive");
+            }
             if peer.is_upstream() {
                 has_upstream = true;
             }
@@ -431,6 +431,16 @@ impl Peer {
         }
     }
 
+    ///
+    /// Returns true if the remote side has sent unexpected or malformed data.
+    ///
+    pub(super) fn is_misbehaving(&self) -> bool {
+        match self {
+            Peer::Failed { error, .. } => error.kind() == std::io::ErrorKind::InvalidData,
+            _ => false,
+        }
+    }
+
     ///
     /// Returns true if this Peer is an upstream.
     ///
@@ -0,0 +1,211 @@
+//
+// Copyright (c) 2018 Stegos AG
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use clap::{crate_version, App, Arg};
+use failure::{format_err, Error};
+use futures::Future;
+use log::*;
+use simple_logger;
+use std::path::PathBuf;
+use std::process;
+use std::thread;
+use std::time::{Duration, SystemTime};
+use stegos_blockchain::{
+    chain_to_prefix, initialize_chain, Blockchain, ConsistencyCheck, Timestamp,
+};
+use stegos_keychain::keyfile::load_network_keys;
+use stegos_network::capture::{read_capture, CaptureRecord, Direction};
+use stegos_network::loopback::{Loopback, MessageFromNode};
+use stegos_node::{NodeConfig, NodeService};
+use tokio::runtime::Runtime;
+
+fn log_sent_by_node(loopback: &mut Loopback) {
+    while let Some(msg) = loopback.pop_message() {
+        match msg {
+            MessageFromNode::SendUnicast {
+                to,
+                protocol_id,
+                data,
+            } => info!(
+                "Node sent unicast: protocol={}, to={}, size={}",
+                protocol_id,
+                to,
+                data.len()
+            ),
+            MessageFromNode::Publish { topic, data } => {
+                info!("Node sent broadcast: topic={}, size={}", topic, data.len())
+            }
+        }
+    }
+}
+
+fn log_record(record: &CaptureRecord, replayed: bool) {
+    let action = match (record.direction, replayed) {
+        (Direction::Sent, _) => "Captured sent",
+        (Direction::Received, true) => "Replayed",
+        (Direction::Received, false) => "Skipped",
+    };
+    info!(
+        "{} {:?}: time={}, topic={}, peer={}, size={}",
+        action,
+        record.kind,
+        humantime::format_rfc3339_millis(record.timestamp),
+        record.topic,
+        record
+            .peer
+            .map(|p| p.to_string())
+            .unwrap_or_else(|| "-".to_string()),
+        record.data.len()
+    );
+}
+
+fn run() -> Result<(), Error> {
+    let args = App::new("Stegos Replay Utility")
+        .version(crate_version!())
+        .author("Stegos AG <info@stegos.com>")
+        .about(
+            "A tool to feed captured network messages to a node, see [network.capture] in stegosd.toml.",
+        )
+        .arg(
+            Arg::with_name("chain")
+                .short("n")
+                .long("chain")
+                .env("STEGOS_CHAIN")
+                .value_name("NAME")
+                .help("Specify chain to use: mainnet, testnet or dev")
+                .default_value("dev")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("data-dir")
+                .short("d")
+                .long("data-dir")
+                .value_name("DIR")
+                .help("A copy of the data directory of the captured node (chain and network keys)")
+                .required(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("realtime")
+                .short("r")
+                .long("realtime")
+                .help("Keep the original intervals between messages"),
+        )
+        .arg(
+            Arg::with_name("delay")
+                .long("delay")
+                .value_name("MS")
+                .default_value("10")
+                .validator(|s| s.parse::<u64>().map(|_| ()).map_err(|e| format!("{}", e)))
+                .help("Time to let the node process each message if not --realtime")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("capture")
+                .value_name("FILE")
+                .help("Capture files, oldest first (capture.bin.2 capture.bin.1 capture.bin)")
+                .required(true)
+                .multiple(true),
+        )
+        .get_matches();
+
+    let chain = args.value_of("chain").unwrap();
+    let data_dir = PathBuf::from(args.value_of("data-dir").unwrap());
+    let realtime = args.is_present("realtime");
+    let delay = Duration::from_millis(args.value_of("delay").unwrap().parse().unwrap());
+
+    let mut records = Vec::new();
+    for file in args.values_of("capture").unwrap() {
+        let file = PathBuf::from(file);
+        let mut captured = read_capture(&file)
+            .map_err(|e| format_err!("Failed to read capture {:?}: {}", file, e))?;
+        info!("Loaded {} messages from {:?}", captured.len(), file);
+        records.append(&mut captured);
+    }
+    // Stable, keeps the order of messages captured within the same millisecond.
+    records.sort_by_key(|r| r.timestamp);
+
+    stegos_crypto::set_network_prefix(chain_to_prefix(chain))
+        .expect("Network prefix not initialised.");
+    let (network_skey, network_pkey) = load_network_keys(
+        &data_dir.join("network.skey"),
+        &data_dir.join("network.pkey"),
+    )?;
+
+    let (genesis, chain_cfg) = initialize_chain(chain)?;
+    let chain_db = Blockchain::new(
+        chain_cfg,
+        &data_dir.join("chain"),
+        ConsistencyCheck::None,
+        genesis,
+        Timestamp::now(),
+    )?;
+
+    let (mut loopback, network, peer_id, replication_rx) = Loopback::new();
+    let (mut node_service, _node) = NodeService::new(
+        NodeConfig::default(),
+        chain_db,
+        network_skey,
+        network_pkey,
+        network,
+        chain.to_string(),
+        peer_id,
+        replication_rx,
+    )?;
+    node_service.init()?;
+    let mut rt = Runtime::new()?;
+    rt.spawn(node_service);
+
+    let mut last_timestamp: Option<SystemTime> = None;
+    for record in records.iter() {
+        if realtime {
+            if let Some(last_timestamp) = last_timestamp {
+                let interval = record
+                    .timestamp
+                    .duration_since(last_timestamp)
+                    .unwrap_or_default();
+                thread::sleep(interval);
+            }
+            last_timestamp = Some(record.timestamp);
+        }
+        let replayed = loopback.replay(record);
+        log_record(record, replayed);
+        if replayed && !realtime {
+            thread::sleep(delay);
+        }
+        log_sent_by_node(&mut loopback);
+    }
+    thread::sleep(delay);
+    log_sent_by_node(&mut loopback);
+
+    info!("Replayed {} messages", records.len());
+    rt.shutdown_now().wait().ok();
+    Ok(())
+}
+
+fn main() {
+    simple_logger::init_with_level(log::Level::Info).unwrap_or_default();
+
+    if let Err(e) = run() {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
@@ -53,13 +53,17 @@ use stegos_crypto::{pbc, scc};
 
 lazy_static! {
     /// Regex to parse "pay" command.
-    static ref PAY_COMMAND_RE: Regex = Regex::new(r"^\s*(?P<recipient>[0-9A-Za-z]+)\s+(?P<amount>[0-9_]{1,25})(?P<arguments>.+)?$").unwrap();
+    static ref PAY_COMMAND_RE: Regex = Regex::new(r"^\s*(?P<recipient>[0-9A-Za-z_\-]+)\s+(?P<amount>[0-9_]{1,25})(?P<arguments>.+)?$").unwrap();
     /// Regex to parse argument of "pay" command.
     static ref PAY_ARGUMENTS_RE: Regex = Regex::new(r"^(\s+(?P<public>(/public)))?(\s+(?P<snowball>(/snowball)))?(\s+(?P<comment>[^/]+?))?(\s+(?P<fee>(/fee\s[0-9_]{1,25})))?(\s+(?P<certificate>(/certificate)))?$").unwrap();
     /// Regex to parse "msg" command.
     static ref MSG_COMMAND_RE: Regex = Regex::new(r"^\s*(?P<recipient>[0-9a-f]+)\s+(?P<msg>.+)$").unwrap();
     /// Regex to parse "stake/unstake" command.
     static ref STAKE_COMMAND_RE: Regex = Regex::new(r"^\s*(?P<amount>[0-9_]{1,25})$").unwrap();
+    /// Regex to parse "delegate/undelegate" command.
+    static ref DELEGATE_COMMAND_RE: Regex = Regex::new(r"^\s*(?P<validator>[0-9a-f]+)\s+(?P<amount>[0-9_]{1,25})$").unwrap();
+    /// Regex to parse "set commission" command.
+    static ref COMMISSION_COMMAND_RE: Regex = Regex::new(r"^\s*(?P<commission>[0-9]{1,5})$").unwrap();
     /// Regex to parse "publish" command.
     static ref PUBLISH_COMMAND_RE: Regex = Regex::new(r"^\s*(?P<topic>[0-9A-Za-z]+)\s+(?P<msg>.*)$").unwrap();
     /// Regex to parse "send" command.
@@ -68,8 +72,26 @@ lazy_static! {
     static ref VALIDATE_CERTIFICATE_COMMAND_RE: Regex = Regex::new(r"^\s*(?P<utxo>[0-9a-f]+)\s+(?P<spender>[0-9A-Za-z]+)\s+(?P<recipient>[0-9A-Za-z]+)\s+(?P<rvalue>[0-9a-f]+)$").unwrap();
     /// Regex to parse "show block" command.
     static ref SHOW_BLOCK_COMMAND_RE: Regex = Regex::new(r"^\s*(?P<epoch>[0-9]+)(\s+(?P<offset>[0-9]+))?$").unwrap();
+    /// Regex to parse "show validator stats" command.
+    static ref SHOW_VALIDATOR_STATS_COMMAND_RE: Regex = Regex::new(r"^\s*(?P<pkey>[0-9a-f]+)(\s+(?P<from_epoch>[0-9]+)(\s+(?P<to_epoch>[0-9]+))?)?$").unwrap();
+    /// Regex to parse "schedule maintenance" command.
+    static ref SCHEDULE_MAINTENANCE_COMMAND_RE: Regex = Regex::new(r"^\s*(?P<epoch>[0-9]+)$").unwrap();
     /// Regex to parse "use" command.
     static ref USE_COMMAND_RE: Regex = Regex::new(r"^\s*(?P<account_id>[0-9A-Za-z]+)$").unwrap();
+    /// Regex to parse "add contact" command.
+    static ref ADD_CONTACT_COMMAND_RE: Regex = Regex::new(r"^\s*(?P<name>[0-9A-Za-z_\-]+)\s+(?P<address>[0-9A-Za-z]+)(\s+(?P<notes>.+))?$").unwrap();
+    /// Regex to parse "show contact" and "remove contact" commands.
+    static ref CONTACT_COMMAND_RE: Regex = Regex::new(r"^\s*(?P<name>[0-9A-Za-z_\-]+)$").unwrap();
+    /// Regex to parse "label" command.
+    static ref LABEL_COMMAND_RE: Regex = Regex::new(r"^\s*(?P<hash>[0-9a-f]+)(\s+(?P<label>.+))?$").unwrap();
+    // pay stegos:URI [/fee FEE] [/certificate]
+    static ref PAY_URI_COMMAND_RE: Regex = Regex::new(r"^\s*(?P<uri>stegos:\S+)(\s+/fee\s+(?P<fee>[0-9_]{1,25}))?(\s+(?P<certificate>/certificate))?$").unwrap();
+    // invoice AMOUNT [COMMENT] [/expires DURATION]
+    static ref INVOICE_COMMAND_RE: Regex = Regex::new(r"^\s*(?P<amount>[0-9_]{1,25})(\s+(?P<comment>[^/]+?))?(\s+/expires\s+(?P<expires>.+))?$").unwrap();
+    // export history FILE [csv|jsonl]
+    static ref EXPORT_HISTORY_COMMAND_RE: Regex = Regex::new(r"^\s*(?P<file>\S+)(\s+(?P<format>csv|jsonl))?$").unwrap();
+    /// Regex to parse "enable consolidation" command.
+    static ref ENABLE_CONSOLIDATION_COMMAND_RE: Regex = Regex::new(r"^(\s+/min\s+(?P<min>[0-9]{1,10}))?(\s+/dust\s+(?P<dust>[0-9_]{1,25}))?(\s+/fee\s+(?P<fee>[0-9_]{1,25}))?(\s+/max_fee\s+(?P<max_fee>[0-9_]{1,25}))?(\s+(?P<snowball>/snowball))?\s*$").unwrap();
 }
 
 const RECOVERY_PROMPT: &'static str = "Enter 24-word recovery phrase: ";
@@ -162,6 +184,9 @@ impl From<Option<String>> for ChainNameResolver {
     }
 }
 
+/// A payment which waits until the recipient's name is resolved using the address book.
+type PendingPayment = Box<dyn FnOnce(scc::PublicKey) -> AccountRequest + Send>;
+
 /// Console (stdin) service.
 pub struct ConsoleService {
     /// Chain name.
@@ -178,6 +203,10 @@ pub struct ConsoleService {
     formatter: Formatter,
     /// Parse stdin line as JSON request.
     raw: bool,
+    /// A payment to a contact from the address book.
+    pending_payment: Option<PendingPayment>,
+    /// A file to save exported history or backup.
+    pending_export: Option<PathBuf>,
 }
 
 impl ConsoleService {
@@ -225,6 +254,8 @@ impl ConsoleService {
             stdin_th,
             formatter,
             raw,
+            pending_payment: None,
+            pending_export: None,
         }
     }
 
@@ -320,26 +351,55 @@ impl ConsoleService {
         eprintln!(
             "pay ADDRESS AMOUNT [COMMENT] [/snowball] [/public] [/fee FEE] [/certificate] - send money"
         );
+        eprintln!("pay URI [/fee FEE] [/certificate] - pay a stegos: payment request");
+        eprintln!("invoice AMOUNT [COMMENT] [/expires DURATION] - create a payment request");
+        eprintln!("show invoices - print created invoices");
         eprintln!("validate certificate UTXO SENDER_ADDRESS RECIPIENT_ADDRESS RVALUE - check that payment certificate is valid");
+        eprintln!("show contacts - print the address book");
+        eprintln!("show contact NAME - print a contact from the address book");
+        eprintln!("add contact NAME ADDRESS [NOTES] - add a contact to the address book");
+        eprintln!("remove contact NAME - remove a contact from the address book");
+        eprintln!("label HASH [LABEL] - label a transaction or an output, or remove the label");
         eprintln!("msg ADDRESS MESSAGE - send a message via blockchain");
         eprintln!("stake remote - stake money to remote node, network key should be located near account key.");
         eprintln!("stake AMOUNT - stake money");
         eprintln!("stake all - stake all available money");
+        eprintln!("secure stake AMOUNT - shuffle money using Snowball and then stake it");
         eprintln!("unstake [AMOUNT] - unstake money");
+        eprintln!("delegate VALIDATOR AMOUNT - delegate money to a third-party validator");
+        eprintln!("undelegate VALIDATOR AMOUNT - withdraw money delegated to a validator");
+        eprintln!("set commission BASIS_POINTS - re-stake own stakes with a new commission");
         eprintln!("enable restaking - enable automatic re-staking (default)");
         eprintln!("disable restaking - disable automatic re-staking");
+        eprintln!("schedule maintenance EPOCH - skip the epoch, e.g. to upgrade the node");
+        eprintln!("cancel maintenance - cancel scheduled maintenance");
+        eprintln!(
+            "rotate validator key - re-bind all stakes to the network key located near account key"
+        );
         eprintln!("cloak - exchange all available public outputs");
+        eprintln!("enable consolidation [/min COUNT] [/dust AMOUNT] [/fee FEE] [/max_fee FEE] [/snowball] - merge small outputs in background");
+        eprintln!("disable consolidation - disable merging of small outputs");
+        eprintln!("show consolidation - print the consolidation policy");
         eprintln!("show version - print version information");
         eprintln!("show validators - print active epoch validators list.");
         eprintln!("show keys - print keys");
         eprintln!("show balance - print balance");
         eprintln!("show utxo - print unspent outputs");
         eprintln!("show history [STARTING DATE] - print history since date");
+        eprintln!("show snowball [STARTING DATE] - print Snowball sessions since date");
+        eprintln!("export history FILE [csv|jsonl] - save the entire history to a file");
         eprintln!("show election - show consensus state");
         eprintln!("show escrow - print escrow");
         eprintln!("show replication - show replication status");
+        eprintln!("show txpool - show Snowball pools formed by this facilitator");
+        eprintln!("show consensus - show validator role, votes and timers");
+        eprintln!(
+            "show validator stats NETWORK_KEY [FROM_EPOCH [TO_EPOCH]] - show validator performance"
+        );
         eprintln!("change upstream - change the current replication upstream");
         eprintln!("show recovery - print recovery information");
+        eprintln!("export backup FILE - save encrypted backup of the account to a file");
+        eprintln!("restore backup FILE - add a new account from a backup file");
         eprintln!("show block EPOCH [OFFSET] - show a block");
         eprintln!("pop block - revert the latest micro block");
         eprintln!("subscribe chain EPOCH [OFFSET] - subscribe for blockchain changes");
@@ -348,6 +408,7 @@ impl ConsoleService {
         eprintln!("net publish TOPIC MESSAGE - publish a network message via floodsub");
         eprintln!("net send NETWORK_ADDRESS TOPIC MESSAGE - send a network message via unicast");
         eprintln!("net peers - show connected peers");
+        eprintln!("net scores - show scores of misbehaving and banned peers");
         eprintln!();
     }
 
@@ -370,7 +431,7 @@ impl ConsoleService {
         eprintln!(
             "Usage: pay ADDRESS AMOUNT [COMMENT] [/snowball] [/public] [/fee FEE] [/certificate]"
         );
-        eprintln!(" - ADDRESS recipient's address");
+        eprintln!(" - ADDRESS recipient's address or name from the address book");
         eprintln!(" - AMOUNT amount in μSTG");
         eprintln!(" - COMMENT purpose of payment");
         eprintln!(" - /snowball use Snowball mixing protocol");
@@ -381,6 +442,22 @@ impl ConsoleService {
         eprintln!();
     }
 
+    fn help_pay_uri() {
+        eprintln!("Usage: pay URI [/fee FEE] [/certificate]");
+        eprintln!(" - URI payment request, starting with 'stegos:'");
+        eprintln!(" - /fee FEE set fee in μSTG per each created UTXO");
+        eprintln!(" - /certificate create payment certificate");
+        eprintln!();
+    }
+
+    fn help_invoice() {
+        eprintln!("Usage: invoice AMOUNT [COMMENT] [/expires DURATION]");
+        eprintln!(" - AMOUNT amount in μSTG");
+        eprintln!(" - COMMENT unique reference, a random one is used if not specified");
+        eprintln!(" - /expires DURATION validity period, e.g. '1h', '7days'");
+        eprintln!();
+    }
+
     fn help_stake_remote() {
         eprintln!("Usage: stake_remote AMOUNT");
         eprintln!(" - AMOUNT amount to stake into escrow, in μSTG");
@@ -392,6 +469,12 @@ impl ConsoleService {
         eprintln!();
     }
 
+    fn help_secure_stake() {
+        eprintln!("Usage: secure stake AMOUNT");
+        eprintln!(" - AMOUNT amount to stake into escrow, in μSTG");
+        eprintln!();
+    }
+
     fn help_validate_certificate() {
         eprintln!("Usage: validate certificate UTXO SENDER_ADDRESS RECIPIENT_ADDRESS RVALUE");
         eprintln!(" - UTXO - UTXO ID");
@@ -408,6 +491,26 @@ impl ConsoleService {
         eprintln!();
     }
 
+    fn help_delegate() {
+        eprintln!("Usage: delegate VALIDATOR AMOUNT");
+        eprintln!(" - VALIDATOR validator's network key");
+        eprintln!(" - AMOUNT amount to delegate, in μSTG");
+        eprintln!();
+    }
+
+    fn help_undelegate() {
+        eprintln!("Usage: undelegate VALIDATOR AMOUNT");
+        eprintln!(" - VALIDATOR validator's network key");
+        eprintln!(" - AMOUNT amount to withdraw, in μSTG");
+        eprintln!();
+    }
+
+    fn help_set_commission() {
+        eprintln!("Usage: set commission BASIS_POINTS");
+        eprintln!(" - BASIS_POINTS share of delegators' rewards, 100 = 1%, max 10000");
+        eprintln!();
+    }
+
     fn help_msg() {
         eprintln!("Usage: msg ADDRESS MESSAGE");
         eprintln!(" - ADDRESS recipient's address");
@@ -415,6 +518,48 @@ impl ConsoleService {
         eprintln!();
     }
 
+    fn help_add_contact() {
+        eprintln!("Usage: add contact NAME ADDRESS [NOTES]");
+        eprintln!(" - NAME unique name, letters, digits, '_' and '-' are allowed");
+        eprintln!(" - ADDRESS contact's address");
+        eprintln!(" - NOTES free-form notes");
+        eprintln!();
+    }
+
+    fn help_contact() {
+        eprintln!("Usage: show contact NAME");
+        eprintln!("       remove contact NAME");
+        eprintln!(" - NAME name from the address book");
+        eprintln!();
+    }
+
+    fn help_label() {
+        eprintln!("Usage: label HASH [LABEL]");
+        eprintln!(" - HASH transaction hash or UTXO ID");
+        eprintln!(" - LABEL free-form text, removes the existing label if not specified");
+        eprintln!();
+    }
+
+    fn help_export_history() {
+        eprintln!("Usage: export history FILE [csv|jsonl]");
+        eprintln!(" - FILE path to the output file");
+        eprintln!(" - csv comma-separated values (default)");
+        eprintln!(" - jsonl one JSON object per line");
+        eprintln!();
+    }
+
+    fn help_enable_consolidation() {
+        eprintln!(
+            "Usage: enable consolidation [/min COUNT] [/dust AMOUNT] [/fee FEE] [/max_fee FEE] [/snowball]"
+        );
+        eprintln!(" - /min COUNT merge when there are at least COUNT small outputs");
+        eprintln!(" - /dust AMOUNT outputs up to AMOUNT μSTG are considered small");
+        eprintln!(" - /fee FEE set fee in μSTG per each created UTXO");
+        eprintln!(" - /max_fee FEE never pay more than FEE μSTG per consolidation");
+        eprintln!(" - /snowball use Snowball mixing protocol");
+        eprintln!();
+    }
+
     fn help_use() {
         eprintln!("Usage: use ACCOUNT_ID");
         eprintln!();
@@ -427,6 +572,20 @@ impl ConsoleService {
         eprintln!();
     }
 
+    fn help_show_validator_stats() {
+        eprintln!("Usage: show validator stats NETWORK_KEY [FROM_EPOCH [TO_EPOCH]]");
+        eprintln!(" - NETWORK_KEY - validator's network key");
+        eprintln!(" - FROM_EPOCH - first epoch, 0 by default");
+        eprintln!(" - TO_EPOCH - last epoch, the current epoch by default");
+        eprintln!();
+    }
+
+    fn help_schedule_maintenance() {
+        eprintln!("Usage: schedule maintenance EPOCH");
+        eprintln!(" - EPOCH - epoch, in which the node doesn't take part in consensus");
+        eprintln!();
+    }
+
     fn help_subscribe_chain() {
         eprintln!("Usage: subscribe chain EPOCH [OFFSET]");
         eprintln!(" - EPOCH - epoch number");
@@ -576,24 +735,88 @@ impl ConsoleService {
             })?;
         } else if msg.starts_with("net peers") {
             self.send_network_request(NetworkRequest::ConnectedNodesRequest {})?
-        } else if msg.starts_with("pay ") {
-            let caps = match PAY_COMMAND_RE.captures(&msg[4..]) {
+        } else if msg.starts_with("net scores") {
+            self.send_network_request(NetworkRequest::PeerScores {})?
+        } else if msg.starts_with("pay stegos:") {
+            let caps = match PAY_URI_COMMAND_RE.captures(&msg[4..]) {
                 Some(c) => c,
                 None => {
-                    Self::help_pay();
+                    Self::help_pay_uri();
                     return Ok(true);
                 }
             };
-
-            let recipient = caps.name("recipient").unwrap().as_str();
-            let recipient = match scc::PublicKey::from_str(recipient) {
-                Ok(r) => r,
+            let uri = caps.name("uri").unwrap().as_str().to_string();
+            let payment_fee = match caps.name("fee") {
+                Some(fee) => match parse_money(fee.as_str()) {
+                    Ok(fee) => fee,
+                    Err(e) => {
+                        eprintln!("Invalid fee '{}': {}", fee.as_str(), e);
+                        Self::help_pay_uri();
+                        return Ok(true);
+                    }
+                },
+                None => PAYMENT_FEE,
+            };
+            let with_certificate = caps.name("certificate").is_some();
+            let request = AccountRequest::PayInvoice {
+                uri,
+                payment_fee,
+                with_certificate,
+            };
+            self.send_account_request(request)?
+        } else if msg.starts_with("invoice ") {
+            let caps = match INVOICE_COMMAND_RE.captures(&msg[8..]) {
+                Some(c) => c,
+                None => {
+                    Self::help_invoice();
+                    return Ok(true);
+                }
+            };
+            let amount = caps.name("amount").unwrap().as_str();
+            let amount = match parse_money(amount) {
+                Ok(amount) => amount,
                 Err(e) => {
-                    eprintln!("Invalid account public key '{}': {}", recipient, e);
+                    eprintln!("Invalid amount '{}': {}", amount, e);
+                    Self::help_invoice();
+                    return Ok(true);
+                }
+            };
+            let comment = caps
+                .name("comment")
+                .map(|s| String::from(s.as_str()))
+                .unwrap_or(String::new());
+            let expires = match caps.name("expires") {
+                Some(expires) => match humantime::parse_duration(expires.as_str()) {
+                    Ok(duration) => Some(Timestamp::now() + duration),
+                    Err(e) => {
+                        eprintln!("Invalid duration '{}': {}", expires.as_str(), e);
+                        Self::help_invoice();
+                        return Ok(true);
+                    }
+                },
+                None => None,
+            };
+            let request = AccountRequest::CreateInvoice {
+                amount,
+                comment,
+                expires,
+            };
+            self.send_account_request(request)?
+        } else if msg == "show invoices" {
+            let request = AccountRequest::InvoicesInfo {};
+            self.send_account_request(request)?
+        } else if msg.starts_with("pay ") {
+            let caps = match PAY_COMMAND_RE.captures(&msg[4..]) {
+                Some(c) => c,
+                None => {
                     Self::help_pay();
                     return Ok(true);
                 }
             };
+
+            // Try to resolve the name using the address book if it is not an address.
+            let name = caps.name("recipient").unwrap().as_str().to_string();
+            let recipient = scc::PublicKey::from_str(&name).ok();
             let amount = caps.name("amount").unwrap().as_str();
             let amount = match parse_money(amount) {
                 Ok(amount) => amount,
@@ -665,29 +888,39 @@ impl ConsoleService {
                 return Err(format_err!("Public payments doesn't support comments"));
             }
 
-            let request = if snowball {
-                AccountRequest::SecurePayment {
-                    recipient,
-                    amount,
-                    payment_fee,
-                    comment,
-                }
-            } else if public {
-                AccountRequest::PublicPayment {
-                    recipient,
-                    amount,
-                    payment_fee,
-                }
-            } else {
-                AccountRequest::Payment {
-                    recipient,
-                    amount,
-                    payment_fee,
-                    comment,
-                    with_certificate,
+            let make_request = move |recipient| {
+                if snowball {
+                    AccountRequest::SecurePayment {
+                        recipient,
+                        amount,
+                        payment_fee,
+                        comment,
+                        min_participants: 0,
+                        max_wait: 0,
+                    }
+                } else if public {
+                    AccountRequest::PublicPayment {
+                        recipient,
+                        amount,
+                        payment_fee,
+                    }
+                } else {
+                    AccountRequest::Payment {
+                        recipient,
+                        amount,
+                        payment_fee,
+                        comment,
+                        with_certificate,
+                    }
                 }
             };
-            self.send_account_request(request)?
+            match recipient {
+                Some(recipient) => self.send_account_request(make_request(recipient))?,
+                None => {
+                    self.pending_payment = Some(Box::new(make_request));
+                    self.send_account_request(AccountRequest::ContactInfo { name })?
+                }
+            }
         } else if msg.starts_with("validate certificate ") {
             let caps = match VALIDATE_CERTIFICATE_COMMAND_RE.captures(&msg[20..]) {
                 Some(c) => c,
@@ -773,6 +1006,30 @@ impl ConsoleService {
                 with_certificate: false,
             };
             self.send_account_request(request)?
+        } else if msg.starts_with("secure stake ") {
+            let caps = match STAKE_COMMAND_RE.captures(&msg[13..]) {
+                Some(c) => c,
+                None => {
+                    Self::help_secure_stake();
+                    return Ok(true);
+                }
+            };
+
+            let amount = caps.name("amount").unwrap().as_str();
+            let amount = match parse_money(amount) {
+                Ok(amount) => amount,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    Self::help_secure_stake();
+                    return Ok(true);
+                }
+            };
+            let payment_fee = PAYMENT_FEE;
+            let request = AccountRequest::SecureStake {
+                amount,
+                payment_fee,
+            };
+            self.send_account_request(request)?
         } else if msg.starts_with("stake all") {
             let payment_fee = PAYMENT_FEE;
             let request = AccountRequest::StakeAll { payment_fee };
@@ -853,6 +1110,147 @@ impl ConsoleService {
                 payment_fee,
             };
             self.send_account_request(request)?
+        } else if msg.starts_with("delegate ") || msg.starts_with("undelegate ") {
+            let (is_delegate, args) = if msg.starts_with("delegate ") {
+                (true, &msg[9..])
+            } else {
+                (false, &msg[11..])
+            };
+            let help = if is_delegate {
+                Self::help_delegate
+            } else {
+                Self::help_undelegate
+            };
+            let caps = match DELEGATE_COMMAND_RE.captures(args) {
+                Some(c) => c,
+                None => {
+                    help();
+                    return Ok(true);
+                }
+            };
+            let validator = caps.name("validator").unwrap().as_str();
+            let validator = match pbc::PublicKey::try_from_hex(validator) {
+                Ok(validator) => validator,
+                Err(e) => {
+                    eprintln!("Invalid validator key '{}': {}", validator, e);
+                    help();
+                    return Ok(true);
+                }
+            };
+            let amount = caps.name("amount").unwrap().as_str();
+            let amount = match parse_money(amount) {
+                Ok(amount) => amount,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    help();
+                    return Ok(true);
+                }
+            };
+            let payment_fee = PAYMENT_FEE;
+            let request = if is_delegate {
+                AccountRequest::Delegate {
+                    validator,
+                    amount,
+                    payment_fee,
+                }
+            } else {
+                AccountRequest::Undelegate {
+                    validator,
+                    amount,
+                    payment_fee,
+                }
+            };
+            self.send_account_request(request)?
+        } else if msg.starts_with("set commission ") {
+            let caps = match COMMISSION_COMMAND_RE.captures(&msg[15..]) {
+                Some(c) => c,
+                None => {
+                    Self::help_set_commission();
+                    return Ok(true);
+                }
+            };
+            let commission: u32 = caps.name("commission").unwrap().as_str().parse().unwrap();
+            let request = AccountRequest::SetCommission { commission };
+            self.send_account_request(request)?
+        } else if msg == "show contacts" {
+            let request = AccountRequest::ContactsInfo {};
+            self.send_account_request(request)?
+        } else if msg.starts_with("show contact ") {
+            let caps = match CONTACT_COMMAND_RE.captures(&msg[13..]) {
+                Some(c) => c,
+                None => {
+                    Self::help_contact();
+                    return Ok(true);
+                }
+            };
+            let name = caps.name("name").unwrap().as_str().to_string();
+            let request = AccountRequest::ContactInfo { name };
+            self.send_account_request(request)?
+        } else if msg.starts_with("add contact ") {
+            let caps = match ADD_CONTACT_COMMAND_RE.captures(&msg[12..]) {
+                Some(c) => c,
+                None => {
+                    Self::help_add_contact();
+                    return Ok(true);
+                }
+            };
+            let name = caps.name("name").unwrap().as_str().to_string();
+            let address = caps.name("address").unwrap().as_str();
+            let account_pkey = match scc::PublicKey::from_str(address) {
+                Ok(r) => r,
+                Err(e) => {
+                    eprintln!("Invalid account public key '{}': {}", address, e);
+                    Self::help_add_contact();
+                    return Ok(true);
+                }
+            };
+            let notes = caps
+                .name("notes")
+                .map(|s| String::from(s.as_str()))
+                .unwrap_or(String::new());
+            let request = AccountRequest::AddContact {
+                name,
+                account_pkey,
+                notes,
+            };
+            self.send_account_request(request)?
+        } else if msg.starts_with("remove contact ") {
+            let caps = match CONTACT_COMMAND_RE.captures(&msg[15..]) {
+                Some(c) => c,
+                None => {
+                    Self::help_contact();
+                    return Ok(true);
+                }
+            };
+            let name = caps.name("name").unwrap().as_str().to_string();
+            let request = AccountRequest::RemoveContact { name };
+            self.send_account_request(request)?
+        } else if msg.starts_with("label ") {
+            let caps = match LABEL_COMMAND_RE.captures(&msg[6..]) {
+                Some(c) => c,
+                None => {
+                    Self::help_label();
+                    return Ok(true);
+                }
+            };
+            let hash = caps.name("hash").unwrap().as_str();
+            let hash = match Hash::try_from_hex(hash) {
+                Ok(r) => r,
+                Err(e) => {
+                    eprintln!("Invalid hash '{}': {}", hash, e);
+                    Self::help_label();
+                    return Ok(true);
+                }
+            };
+            let label = caps
+                .name("label")
+                .map(|s| String::from(s.as_str()))
+                .unwrap_or(String::new());
+            let request = AccountRequest::SetLabel { hash, label };
+            self.send_account_request(request)?
+        } else if msg == "rotate validator key" {
+            let request = AccountRequest::RotateValidatorKey {};
+            self.send_account_request(request)?
         } else if msg == "cloak" {
             let payment_fee = PAYMENT_FEE;
             let request = AccountRequest::CloakAll { payment_fee };
@@ -878,6 +1276,12 @@ impl ConsoleService {
         } else if msg == "show replication" {
             let request = NodeRequest::ReplicationInfo {};
             self.send_node_request(request)?
+        } else if msg == "show txpool" {
+            let request = NodeRequest::TxPoolInfo {};
+            self.send_node_request(request)?
+        } else if msg == "show consensus" {
+            let request = NodeRequest::ConsensusInfo {};
+            self.send_node_request(request)?
         } else if msg == "change upstream" {
             let request = NodeRequest::ChangeUpstream {};
             self.send_node_request(request)?
@@ -899,6 +1303,49 @@ impl ConsoleService {
                 limit: CONSOLE_HISTORY_LIMIT,
             };
             self.send_account_request(request)?
+        } else if msg.starts_with("show snowball") {
+            let arg = &msg[13..];
+            let starting_from = if arg.is_empty() {
+                Timestamp::now() - Duration::from_secs(86400)
+            } else {
+                parse_past_datetime(arg)?
+            };
+            let request = AccountRequest::SnowballHistory {
+                starting_from,
+                limit: CONSOLE_HISTORY_LIMIT,
+            };
+            self.send_account_request(request)?
+        } else if msg.starts_with("export history") {
+            let caps = match EXPORT_HISTORY_COMMAND_RE.captures(&msg[14..]) {
+                Some(c) => c,
+                None => {
+                    Self::help_export_history();
+                    return Ok(true);
+                }
+            };
+            let file = PathBuf::from(caps.name("file").unwrap().as_str());
+            let format = match caps.name("format").map(|f| f.as_str()) {
+                Some("jsonl") => ExportFormat::Jsonl,
+                _ => ExportFormat::Csv,
+            };
+            let request = AccountRequest::ExportHistory { format };
+            self.send_account_request(request)?;
+            self.pending_export = Some(file);
+        } else if msg.starts_with("export backup ") {
+            let file = PathBuf::from(msg[14..].trim());
+            let password = read_password_with_confirmation()?;
+            let request = AccountRequest::ExportBackup { password };
+            self.send_account_request(request)?;
+            self.pending_export = Some(file);
+        } else if msg.starts_with("restore backup ") {
+            let file = PathBuf::from(msg[15..].trim());
+            let backup = std::fs::read_to_string(&file)?;
+            let password = read_password()?;
+            let request = WalletControlRequest::RestoreBackup {
+                backup: AccountBackup { backup },
+                password,
+            };
+            self.send_wallet_control_request(request)?;
         } else if msg == "show recovery" {
             let request = AccountRequest::GetRecovery {};
             self.send_account_request(request)?
@@ -922,6 +1369,37 @@ impl ConsoleService {
                 NodeRequest::MacroBlockInfo { epoch }
             };
             self.send_node_request(request)?
+        } else if msg.starts_with("show validator stats") {
+            let caps = match SHOW_VALIDATOR_STATS_COMMAND_RE.captures(&msg[20..]) {
+                Some(c) => c,
+                None => {
+                    Self::help_show_validator_stats();
+                    return Ok(true);
+                }
+            };
+            let pkey = caps.name("pkey").unwrap().as_str();
+            let pkey = match pbc::PublicKey::try_from_hex(pkey) {
+                Ok(pkey) => pkey,
+                Err(e) => {
+                    eprintln!("Invalid network key '{}': {}", pkey, e);
+                    Self::help_show_validator_stats();
+                    return Ok(true);
+                }
+            };
+            let from_epoch: u64 = match caps.name("from_epoch") {
+                Some(epoch) => epoch.as_str().parse()?,
+                None => 0,
+            };
+            let to_epoch: u64 = match caps.name("to_epoch") {
+                Some(epoch) => epoch.as_str().parse()?,
+                None => std::u64::MAX,
+            };
+            let request = NodeRequest::ValidatorStats {
+                pkey,
+                from_epoch,
+                to_epoch,
+            };
+            self.send_node_request(request)?
         } else if msg.starts_with("subscribe chain") {
             let caps = match SHOW_BLOCK_COMMAND_RE.captures(&msg[15..]) {
                 Some(c) => c,
@@ -1001,6 +1479,62 @@ impl ConsoleService {
         } else if msg == "pop block" {
             let request = NodeRequest::PopMicroBlock {};
             self.send_node_request(request)?
+        } else if msg.starts_with("enable consolidation") {
+            let caps = match ENABLE_CONSOLIDATION_COMMAND_RE.captures(&msg[20..]) {
+                Some(c) => c,
+                None => {
+                    Self::help_enable_consolidation();
+                    return Ok(true);
+                }
+            };
+            let mut policy = ConsolidationPolicy::default();
+            policy.enabled = true;
+            if let Some(min) = caps.name("min") {
+                policy.min_utxos = min.as_str().parse().unwrap();
+            }
+            for name in &["dust", "fee", "max_fee"] {
+                let amount = match caps.name(name) {
+                    Some(amount) => amount.as_str(),
+                    None => continue,
+                };
+                let amount = match parse_money(amount) {
+                    Ok(amount) => amount,
+                    Err(e) => {
+                        eprintln!("Invalid amount '{}': {}", amount, e);
+                        Self::help_enable_consolidation();
+                        return Ok(true);
+                    }
+                };
+                match *name {
+                    "dust" => policy.dust_amount = amount,
+                    "fee" => policy.payment_fee = amount,
+                    _ => policy.max_fee = amount,
+                }
+            }
+            policy.use_snowball = caps.name("snowball").is_some();
+            let request = AccountRequest::SetConsolidationPolicy { policy };
+            self.send_account_request(request)?
+        } else if msg == "disable consolidation" {
+            let policy = ConsolidationPolicy::default();
+            let request = AccountRequest::SetConsolidationPolicy { policy };
+            self.send_account_request(request)?
+        } else if msg == "show consolidation" {
+            let request = AccountRequest::ConsolidationPolicyInfo {};
+            self.send_account_request(request)?
+        } else if msg.starts_with("schedule maintenance") {
+            let caps = match SCHEDULE_MAINTENANCE_COMMAND_RE.captures(&msg[20..]) {
+                Some(c) => c,
+                None => {
+                    Self::help_schedule_maintenance();
+                    return Ok(true);
+                }
+            };
+            let epoch: u64 = caps.name("epoch").unwrap().as_str().parse()?;
+            let request = NodeRequest::ScheduleMaintenance { epoch };
+            self.send_node_request(request)?
+        } else if msg == "cancel maintenance" {
+            let request = NodeRequest::ScheduleMaintenance { epoch: 0 };
+            self.send_node_request(request)?
         } else if msg == "enable restaking" {
             let request = NodeRequest::EnableRestaking {};
             self.send_node_request(request)?
@@ -1034,6 +1568,42 @@ impl ConsoleService {
     }
 
     fn on_response(&mut self, response: Response) {
+        if let Some(make_request) = self.pending_payment.take() {
+            if let ResponseKind::WalletResponse(WalletResponse::AccountResponse {
+                response: AccountResponse::ContactInfo(contact),
+                ..
+            }) = &response.kind
+            {
+                self.print(&response);
+                let request = make_request(contact.account_pkey);
+                if let Err(e) = self.send_account_request(request) {
+                    eprintln!("{}", e);
+                    self.stdin_th.thread().unpark();
+                }
+                return; // keep stdin parked until the payment is processed.
+            }
+        }
+        if let Some(file) = self.pending_export.take() {
+            let exported = match &response.kind {
+                ResponseKind::WalletResponse(WalletResponse::AccountResponse {
+                    response: AccountResponse::Backup(AccountBackup { backup }),
+                    ..
+                }) => Some(("backup".to_string(), backup)),
+                ResponseKind::WalletResponse(WalletResponse::AccountResponse {
+                    response: AccountResponse::HistoryExported { records, data, .. },
+                    ..
+                }) => Some((format!("{} records", records), data)),
+                _ => None,
+            };
+            if let Some((what, data)) = exported {
+                match std::fs::write(&file, data) {
+                    Ok(()) => eprintln!("Exported {} to {}", what, file.display()),
+                    Err(e) => eprintln!("Failed to write {}: {}", file.display(), e),
+                }
+                self.stdin_th.thread().unpark();
+                return;
+            }
+        }
         match &response.kind {
             ResponseKind::NodeResponse(_)
             | ResponseKind::WalletResponse(_)
@@ -50,7 +50,7 @@ use stegos_blockchain::{
 };
 use stegos_crypto::hash::Hash;
 use stegos_keychain::keyfile::load_network_keys;
-use stegos_network::{Libp2pNetwork, NETWORK_STATUS_TOPIC};
+use stegos_network::{parse_endpoint, Libp2pNetwork, NETWORK_STATUS_TOPIC};
 use stegos_node::NodeService;
 use stegos_wallet::WalletService;
 use tokio::runtime::Runtime;
@@ -277,7 +277,7 @@ fn load_configuration(args: &ArgMatches<'_>) -> Result<config::Config, Error> {
     }
 
     if cfg.network.endpoint != "" {
-        SocketAddr::from_str(&cfg.network.endpoint).map_err(|e| {
+        parse_endpoint(&cfg.network.endpoint, &cfg.network.transport).map_err(|e| {
             format_err!("Invalid network.endpoint '{}': {}", cfg.network.endpoint, e)
         })?;
     }
@@ -287,7 +287,7 @@ fn load_configuration(args: &ArgMatches<'_>) -> Result<config::Config, Error> {
         cfg.network.advertised_endpoint = network_endpoint.to_string();
     }
     if cfg.network.advertised_endpoint != "" {
-        SocketAddr::from_str(&cfg.network.advertised_endpoint).map_err(|e| {
+        parse_endpoint(&cfg.network.advertised_endpoint, &cfg.network.transport).map_err(|e| {
             format_err!(
                 "Invalid network.advertised_endpoint '{}': {}",
                 cfg.network.advertised_endpoint,
@@ -529,17 +529,27 @@ fn run() -> Result<(), Error> {
         .expect("Network prefix not initialised.");
 
     // Initialize keychain
-    let network_skey_file = data_dir.join("network.skey");
-    let network_pkey_file = data_dir.join("network.pkey");
+    let keys_dir = if cfg.node.keys_dir == "" {
+        data_dir.clone()
+    } else {
+        PathBuf::from(&cfg.node.keys_dir)
+    };
+    let network_skey_file = keys_dir.join("network.skey");
+    let network_pkey_file = keys_dir.join("network.pkey");
     let (network_skey, network_pkey) = load_network_keys(&network_skey_file, &network_pkey_file)?;
 
     // Initialize network
+    let mut network_cfg = cfg.network.clone();
+    if network_cfg.peer_scoring.bans_file == "" {
+        network_cfg.peer_scoring.bans_file =
+            data_dir.join("peer_bans.txt").to_string_lossy().to_string();
+    }
+    if network_cfg.peers_file == "" {
+        network_cfg.peers_file = data_dir.join("peers.txt").to_string_lossy().to_string();
+    }
     let mut rt = Runtime::new()?;
-    let (network, network_service, peer_id, replication_rx) = Libp2pNetwork::new(
-        cfg.network.clone(),
-        network_skey.clone(),
-        network_pkey.clone(),
-    )?;
+    let (network, network_service, peer_id, replication_rx) =
+        Libp2pNetwork::new(network_cfg, network_skey.clone(), network_pkey.clone())?;
 
     // Start metrics exporter
     if cfg.general.prometheus_endpoint != "" {
@@ -575,8 +585,10 @@ fn run() -> Result<(), Error> {
         )?;
 
         // Initialize node
+        let mut node_cfg = cfg.node.clone();
+        node_cfg.keys_dir = keys_dir.to_string_lossy().to_string();
         let (mut node_service, node) = NodeService::new(
-            cfg.node.clone(),
+            node_cfg,
             chain,
             network_skey.clone(),
             network_pkey.clone(),
@@ -614,6 +626,7 @@ fn run() -> Result<(), Error> {
         info!("Starting the light node");
         let (wallet_service, wallet) = WalletService::new(
             &accounts_dir,
+            &keys_dir,
             network_skey,
             network_pkey,
             network.clone(),
@@ -191,6 +191,7 @@ fn init(
         let node_cfg = NodeConfig::default();
         let (wallet_service, wallet) = WalletService::new(
             &accounts_dir,
+            &data_dir,
             network_skey,
             network_pkey,
             network.clone(),
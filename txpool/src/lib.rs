@@ -24,96 +24,275 @@
 pub mod protos;
 pub use self::protos::*;
 
+use failure::{bail, format_err, Error};
 use futures::sync::mpsc;
 use futures::{Async, Future, Poll, Stream};
 use log::*;
-use std::collections::HashMap;
-use std::time::Duration;
+use serde_derive::{Deserialize, Serialize};
+use std::cmp;
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
 use stegos_blockchain::PaymentOutput;
 use stegos_crypto::dicemix;
-use stegos_crypto::hash::Hash;
+use stegos_crypto::hash::{Hash, Hashable, Hasher};
 use stegos_crypto::pbc;
 use stegos_crypto::scc;
 use stegos_network::{Network, UnicastMessage};
 use stegos_serialization::traits::*;
-use tokio_timer::Interval;
+use tokio_timer::{clock, Interval};
 
 pub const MESSAGE_TIMEOUT: Duration = Duration::from_secs(10);
 const MIN_PARTICIPANTS: usize = 3;
 pub const MAX_PARTICIPANTS: usize = 20;
+const MAX_WAIT: Duration = Duration::from_secs(2 * 60);
+/// Granularity of output slots in the DiceMix matrix.
+/// Sic: synchronize this value with snowball::MAX_UTXOS.
+const SLOTS_GRANULARITY: u32 = 5;
+/// The maximal number of output slots of one participant.
+/// Sic: synchronize this value with snowball::MAX_OUTPUTS.
+const MAX_SLOTS: u32 = 4 * SLOTS_GRANULARITY;
 
 type TXIN = Hash;
 type UTXO = PaymentOutput;
 type SchnorrSig = scc::SchnorrSig;
 type ParticipantID = dicemix::ParticipantID;
 
+/// Pool formation policy.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct TxPoolConfig {
+    /// The minimal number of participants in a pool.
+    pub min_participants: usize,
+    /// The maximal number of participants in a pool.
+    pub max_participants: usize,
+    /// How often to try to form pools.
+    pub formation_interval: Duration,
+    /// The maximal time to keep a join request.
+    pub max_wait: Duration,
+}
+
+impl Default for TxPoolConfig {
+    fn default() -> Self {
+        TxPoolConfig {
+            min_participants: MIN_PARTICIPANTS,
+            max_participants: MAX_PARTICIPANTS,
+            formation_interval: MESSAGE_TIMEOUT,
+            max_wait: MAX_WAIT,
+        }
+    }
+}
+
+/// Facilitator's statistics.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TxPoolInfo {
+    pub min_participants: usize,
+    pub max_participants: usize,
+    /// The number of participants waiting for a pool.
+    pub pending_participants: usize,
+    /// The number of waiting participants by the requested minimal pool size.
+    pub pending_by_min_participants: BTreeMap<usize, usize>,
+    /// The number of formed pools.
+    pub pools_formed: u64,
+    /// The total number of participants in formed pools.
+    pub participants_pooled: u64,
+    /// The number of join requests dropped after max_wait.
+    pub requests_expired: u64,
+}
+
+/// A join request waiting for a pool.
+struct PendingJoin {
+    txins: Vec<TXIN>,
+    utxos: Vec<UTXO>,
+    ownsig: SchnorrSig,
+    output_slots: u32,
+    /// The minimal number of participants, limited by the config.
+    min_participants: usize,
+    joined: Instant,
+    deadline: Instant,
+}
+
 pub struct TransactionPoolService {
+    cfg: TxPoolConfig,
     network: Network,
-    participants: HashMap<ParticipantID, (Vec<TXIN>, Vec<UTXO>, SchnorrSig)>,
+    /// Network key used to sign formed pools.
+    network_skey: pbc::SecretKey,
+    participants: HashMap<ParticipantID, PendingJoin>,
     timer: Interval,
     pool_join_rx: mpsc::UnboundedReceiver<UnicastMessage>,
+    pools_formed: u64,
+    participants_pooled: u64,
+    requests_expired: u64,
 }
 
 impl TransactionPoolService {
     /// Crates new TransactionPool.
-    pub fn new(network: Network) -> TransactionPoolService {
+    pub fn new(
+        cfg: TxPoolConfig,
+        network: Network,
+        network_skey: pbc::SecretKey,
+    ) -> TransactionPoolService {
         let participants = HashMap::new();
-        let mut timer = Interval::new_interval(MESSAGE_TIMEOUT);
+        let mut timer = Interval::new_interval(cfg.formation_interval);
         // register new timer to the current task.
         let _ = timer.poll();
         // Unicast messages from other nodes
         let pool_join_rx = network.subscribe_unicast(POOL_JOIN_TOPIC).unwrap();
         TransactionPoolService {
+            cfg,
             network,
+            network_skey,
             participants,
             timer,
             pool_join_rx,
+            pools_formed: 0,
+            participants_pooled: 0,
+            requests_expired: 0,
+        }
+    }
+
+    /// Returns the facilitator's statistics.
+    pub fn info(&self) -> TxPoolInfo {
+        let mut pending_by_min_participants = BTreeMap::new();
+        for join in self.participants.values() {
+            *pending_by_min_participants
+                .entry(join.min_participants)
+                .or_insert(0) += 1;
+        }
+        TxPoolInfo {
+            min_participants: self.cfg.min_participants,
+            max_participants: self.cfg.max_participants,
+            pending_participants: self.participants.len(),
+            pending_by_min_participants,
+            pools_formed: self.pools_formed,
+            participants_pooled: self.participants_pooled,
+            requests_expired: self.requests_expired,
         }
     }
 
     fn add_participant(&mut self, pkey: pbc::PublicKey, data: PoolJoin) -> bool {
-        match self.participants.insert(
-            ParticipantID::new(pkey, data.seed),
-            (data.txins, data.utxos, data.ownsig),
-        ) {
+        let participant = ParticipantID::new(pkey, data.seed);
+        let min_participants = cmp::min(
+            cmp::max(data.min_participants as usize, self.cfg.min_participants),
+            self.cfg.max_participants,
+        );
+        let max_wait = if data.max_wait == Duration::from_secs(0) {
+            self.cfg.max_wait
+        } else {
+            cmp::min(data.max_wait, self.cfg.max_wait)
+        };
+        // Keep the original join time on repeated requests.
+        let joined = match self.participants.get(&participant) {
+            Some(join) => join.joined,
+            None => clock::now(),
+        };
+        let join = PendingJoin {
+            txins: data.txins,
+            utxos: data.utxos,
+            ownsig: data.ownsig,
+            output_slots: data.output_slots,
+            min_participants,
+            joined,
+            deadline: joined + max_wait,
+        };
+        match self.participants.insert(participant, join) {
             None => true,
             _ => false,
         }
     }
 
+    /// Drop join requests which have waited for longer than requested.
+    fn expire_participants(&mut self) {
+        let now = clock::now();
+        let expired: Vec<ParticipantID> = self
+            .participants
+            .iter()
+            .filter(|(_, join)| join.deadline <= now)
+            .map(|(participant, _)| *participant)
+            .collect();
+        if expired.is_empty() {
+            return;
+        }
+        let data = PoolNotification::Canceled.into_buffer().unwrap();
+        for participant in expired {
+            let join = self.participants.remove(&participant).unwrap();
+            info!(
+                "Join request expired: pkey={}, min_participants={}",
+                participant.pkey, join.min_participants
+            );
+            self.requests_expired += 1;
+            if let Err(e) = self
+                .network
+                .send(participant.pkey, POOL_ANNOUNCE_TOPIC, data.clone())
+            {
+                error!(
+                    "Failed to send PoolCanceled message {}: {}",
+                    participant.pkey, e
+                );
+            }
+        }
+    }
+
+    /// Form pools from compatible join requests.
     fn try_to_form_pool(&mut self) -> bool {
-        if self.participants.len() < MIN_PARTICIPANTS {
+        if self.participants.len() < self.cfg.min_participants {
             debug!(
                 "Found no enough participants, skipping pool formation: pool_len={}, min_len={}",
                 self.participants.len(),
-                MIN_PARTICIPANTS
+                self.cfg.min_participants
             );
             return false;
         }
 
+        let pending: Vec<(ParticipantID, usize, Instant)> = self
+            .participants
+            .iter()
+            .map(|(participant, join)| (*participant, join.min_participants, join.joined))
+            .collect();
+        let pools = group_participants(pending, self.cfg.max_participants);
+        if pools.is_empty() {
+            debug!(
+                "Found no compatible participants, skipping pool formation: pool_len={}",
+                self.participants.len()
+            );
+            return false;
+        }
+        for pool in pools {
+            self.form_pool(pool);
+        }
+        true
+    }
+
+    fn form_pool(&mut self, pool: Vec<ParticipantID>) {
         // after timeout facilitator should broadcast message to each node.
-        let participants = std::mem::replace(&mut self.participants, HashMap::new());
-        let participants_pkeys: Vec<pbc::PublicKey> =
-            participants.keys().map(|k| k.pkey.clone()).collect();
-        let participants: Vec<ParticipantTXINMap> = participants
+        let participants_pkeys: Vec<pbc::PublicKey> = pool.iter().map(|k| k.pkey).collect();
+        let participants: Vec<ParticipantTXINMap> = pool
             .into_iter()
-            .map(|(participant, (txins, utxos, ownsig))| ParticipantTXINMap {
-                participant,
-                txins,
-                utxos,
-                ownsig,
+            .map(|participant| {
+                let join = self.participants.remove(&participant).unwrap();
+                ParticipantTXINMap {
+                    participant,
+                    txins: join.txins,
+                    utxos: join.utxos,
+                    ownsig: join.ownsig,
+                    output_slots: join.output_slots,
+                }
             })
             .collect();
+        self.pools_formed += 1;
+        self.participants_pooled += participants.len() as u64;
 
         let session_id = Hash::random();
         info!(
             "Formed a new pool: session_id={}, participants={:?}",
             session_id, &participants
         );
-        let info = PoolInfo {
+        let mut info = PoolInfo {
             participants,
             session_id,
+            max_participants: self.cfg.max_participants as u32,
+            signature: None,
         };
+        info.sign(&self.network_skey);
         let msg: PoolNotification = info.into();
         let msg = msg.into_buffer().unwrap();
         for dest in participants_pkeys {
@@ -121,25 +300,89 @@ impl TransactionPoolService {
                 error!("Failed to send PoolInfo to {}: {}", dest, e);
             }
         }
-        true
     }
 
     /// Receive message of other nodes from unicast channel.
     fn handle_join_message(&mut self, data: PoolJoin, from: pbc::PublicKey) {
+        if let Err(e) = validate_join(&data.txins, &data.utxos, &data.ownsig, data.output_slots) {
+            warn!("Invalid join request: pkey={}, error={}", from, e);
+            return;
+        }
         if self.add_participant(from, data) {
             info!("Added a new member: pkey={}", from);
-            if self.participants.len() >= MAX_PARTICIPANTS {
+            if self.participants.len() >= self.cfg.max_participants {
                 self.try_to_form_pool();
             }
         }
     }
 }
 
+/// Checks the proof of ownership of inputs and the number of output slots of a participant.
+pub fn validate_join(
+    txins: &[TXIN],
+    utxos: &[UTXO],
+    ownsig: &SchnorrSig,
+    output_slots: u32,
+) -> Result<(), Error> {
+    // Wallets without output slots encode a fixed matrix with other sheet numbers,
+    // they can't take part in the same session.
+    if output_slots == 0 {
+        bail!("Outdated Snowball protocol, output slots are not supported");
+    }
+    if output_slots > MAX_SLOTS || output_slots % SLOTS_GRANULARITY != 0 {
+        bail!("Invalid number of output slots: slots={}", output_slots);
+    }
+    if txins.is_empty() || txins.len() != utxos.len() {
+        bail!(
+            "Invalid number of inputs: txins={}, utxos={}",
+            txins.len(),
+            utxos.len()
+        );
+    }
+    let mut p_cmp = scc::Pt::inf();
+    let mut state = Hasher::new();
+    for (txin, utxo) in txins.iter().zip(utxos.iter()) {
+        if Hash::digest(utxo) != *txin {
+            bail!("Input doesn't match UTXO: txin={}", txin);
+        }
+        p_cmp += scc::Pt::from(utxo.recipient);
+        utxo.hash(&mut state);
+    }
+    scc::validate_sig(&state.result(), ownsig, &scc::PublicKey::from(p_cmp))
+        .map_err(|e| format_err!("Invalid ownership signature: error={}", e))?;
+    Ok(())
+}
+
+/// Split participants into pools, so that every pool satisfies
+/// the minimal size requested by each of its members.
+/// Participants are given as (id, min_participants, join time).
+fn group_participants(
+    mut pending: Vec<(ParticipantID, usize, Instant)>,
+    max_participants: usize,
+) -> Vec<Vec<ParticipantID>> {
+    // Prefer participants which wait longer among equal requirements.
+    pending.sort_by_key(|&(_, min_participants, joined)| (min_participants, joined));
+    let mut pools = Vec::new();
+    loop {
+        // The sizes are sorted, so a prefix of length len is compatible
+        // if its last member accepts a pool of len participants.
+        let max_len = cmp::min(pending.len(), max_participants);
+        let len = match (1..=max_len).rev().find(|&len| pending[len - 1].1 <= len) {
+            Some(len) => len,
+            None => break,
+        };
+        let pool = pending
+            .drain(..len)
+            .map(|(participant, _, _)| participant)
+            .collect();
+        pools.push(pool);
+    }
+    pools
+}
+
 impl Drop for TransactionPoolService {
     fn drop(&mut self) {
-        if self.try_to_form_pool() {
-            return;
-        }
+        self.try_to_form_pool();
         let info = PoolNotification::Canceled;
         let data = info.into_buffer().unwrap();
         for part in self.participants.keys() {
@@ -189,6 +432,7 @@ impl Future for TransactionPoolService {
         match self.timer.poll().expect("timer fails") {
             Async::Ready(Some(_)) => {
                 self.try_to_form_pool();
+                self.expire_participants();
             }
             Async::Ready(None) => return Ok(Async::Ready(())), // shutdown.
             Async::NotReady => {}
@@ -197,3 +441,115 @@ impl Future for TransactionPoolService {
         Ok(Async::NotReady)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn participants(min_participants: &[usize]) -> Vec<(ParticipantID, usize, Instant)> {
+        let now = Instant::now();
+        min_participants
+            .iter()
+            .enumerate()
+            .map(|(i, min_participants)| {
+                let (_skey, pkey) = pbc::make_random_keys();
+                let participant = ParticipantID::new(pkey, [i as u8; 32]);
+                let joined = now + Duration::from_millis(i as u64);
+                (participant, *min_participants, joined)
+            })
+            .collect()
+    }
+
+    fn sizes(pools: &Vec<Vec<ParticipantID>>) -> Vec<usize> {
+        pools.iter().map(|pool| pool.len()).collect()
+    }
+
+    #[test]
+    fn group_participants() {
+        // Not enough participants.
+        let pools = super::group_participants(participants(&[3, 3]), 20);
+        assert!(pools.is_empty());
+
+        // All compatible.
+        let pools = super::group_participants(participants(&[3, 3, 3, 3]), 20);
+        assert_eq!(sizes(&pools), vec![4]);
+
+        // A larger anonymity set is requested by the some participants.
+        let pools = super::group_participants(participants(&[5, 3, 5, 3, 3]), 20);
+        assert_eq!(sizes(&pools), vec![5]);
+        let pools = super::group_participants(participants(&[6, 3, 6, 3, 3]), 20);
+        assert_eq!(sizes(&pools), vec![3]);
+
+        // Split by max_participants, keeping the first requests.
+        let pending = participants(&[3, 3, 3, 3, 3, 3, 3]);
+        let first: Vec<ParticipantID> = pending.iter().take(4).map(|p| p.0).collect();
+        let pools = super::group_participants(pending, 4);
+        assert_eq!(sizes(&pools), vec![4, 3]);
+        assert_eq!(pools[0], first);
+    }
+
+    #[test]
+    fn join_validation() {
+        let (skey, pkey) = scc::make_random_keys();
+        let utxos: Vec<UTXO> = (0..2)
+            .map(|_| PaymentOutput::new(&pkey, 10).unwrap().0)
+            .collect();
+        let txins: Vec<TXIN> = utxos.iter().map(Hash::digest).collect();
+        let ownsig = {
+            let mut signing_f = scc::Fr::zero();
+            let mut state = Hasher::new();
+            for utxo in &utxos {
+                utxo.hash(&mut state);
+                let payload = utxo.decrypt_payload(&pkey, &skey).unwrap();
+                signing_f += scc::Fr::from(skey.clone()) + payload.gamma * payload.delta;
+            }
+            scc::sign_hash(&state.result(), &scc::SecretKey::from(signing_f))
+        };
+        validate_join(&txins, &utxos, &ownsig, 5).expect("valid");
+        validate_join(&txins, &utxos, &ownsig, 10).expect("valid");
+
+        // Outdated wallet.
+        assert!(validate_join(&txins, &utxos, &ownsig, 0).is_err());
+        // Invalid number of output slots.
+        assert!(validate_join(&txins, &utxos, &ownsig, 7).is_err());
+        assert!(validate_join(&txins, &utxos, &ownsig, 25).is_err());
+        // Inputs don't match UTXOs.
+        assert!(validate_join(&txins[..1], &utxos, &ownsig, 5).is_err());
+        assert!(validate_join(&[txins[1], txins[0]], &utxos, &ownsig, 5).is_err());
+        assert!(validate_join(&[], &[], &ownsig, 5).is_err());
+        // Not an owner.
+        let (_skey1, pkey1) = scc::make_random_keys();
+        let other = PaymentOutput::new(&pkey1, 10).unwrap().0;
+        let txins1 = vec![txins[0], Hash::digest(&other)];
+        let utxos1 = vec![utxos[0].clone(), other];
+        assert!(validate_join(&txins1, &utxos1, &ownsig, 5).is_err());
+    }
+
+    #[test]
+    fn pool_signature() {
+        let (skey, pkey) = pbc::make_random_keys();
+        let (_skey1, pkey1) = pbc::make_random_keys();
+        let mut pool = PoolInfo {
+            participants: Vec::new(),
+            session_id: Hash::digest("session"),
+            max_participants: MAX_PARTICIPANTS as u32,
+            signature: None,
+        };
+        assert!(pool.validate_signature(&pkey).is_err());
+        // Outdated facilitators don't sign pools.
+        pool.max_participants = 0;
+        pool.validate_signature(&pkey)
+            .expect("unsigned pool of outdated facilitator");
+        pool.max_participants = MAX_PARTICIPANTS as u32;
+        pool.sign(&skey);
+        pool.validate_signature(&pkey)
+            .expect("signed by facilitator");
+        assert!(pool.validate_signature(&pkey1).is_err());
+        // Tampering invalidates the signature.
+        pool.session_id = Hash::digest("other");
+        assert!(pool.validate_signature(&pkey).is_err());
+        pool.session_id = Hash::digest("session");
+        pool.max_participants = 1;
+        assert!(pool.validate_signature(&pkey).is_err());
+    }
+}
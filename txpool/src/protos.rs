@@ -20,8 +20,10 @@
 // SOFTWARE.
 
 use failure::{format_err, Error};
+use std::time::Duration;
 use stegos_blockchain::PaymentOutput;
 use stegos_crypto::hash::{Hash, Hashable, Hasher};
+use stegos_crypto::pbc;
 use stegos_crypto::scc;
 use stegos_crypto::{dicemix, CryptoError};
 
@@ -51,6 +53,12 @@ pub struct PoolJoin {
     pub utxos: Vec<UTXO>,
     pub seed: [u8; 32],
     pub ownsig: SchnorrSig,
+    /// The minimal number of participants in the pool, 0 - facilitator's default.
+    pub min_participants: u32,
+    /// How long to wait for a pool, zero - facilitator's default.
+    pub max_wait: Duration,
+    /// The number of output slots in the DiceMix matrix, 0 - outdated wallet.
+    pub output_slots: u32,
 }
 
 /// Sent when a new transaction pool is formed.
@@ -60,12 +68,35 @@ pub struct ParticipantTXINMap {
     pub txins: Vec<TXIN>,
     pub utxos: Vec<UTXO>,
     pub ownsig: SchnorrSig,
+    pub output_slots: u32,
 }
 
 #[derive(Debug, Clone)]
 pub struct PoolInfo {
     pub participants: Vec<ParticipantTXINMap>,
     pub session_id: Hash,
+    /// The facilitator's maximal pool size, 0 - outdated facilitator.
+    pub max_participants: u32,
+    /// Facilitator's signature, makes the facilitator accountable for the pool.
+    /// None - outdated facilitator.
+    pub signature: Option<pbc::Signature>,
+}
+
+impl PoolInfo {
+    /// Sign the pool by the facilitator's network key.
+    pub fn sign(&mut self, skey: &pbc::SecretKey) {
+        self.signature = Some(pbc::sign_hash(&Hash::digest(self), skey));
+    }
+
+    /// Check that the pool was formed by the facilitator.
+    /// Outdated facilitators don't sign pools, their pools are accepted for compatibility.
+    pub fn validate_signature(&self, facilitator: &pbc::PublicKey) -> Result<(), CryptoError> {
+        match self.signature {
+            Some(ref signature) => pbc::check_hash(&Hash::digest(self), signature, facilitator),
+            None if self.max_participants == 0 => Ok(()),
+            None => Err(CryptoError::BadKeyingSignature),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -86,6 +117,9 @@ impl Hashable for PoolJoin {
         }
         self.seed.hash(state);
         self.ownsig.hash(state);
+        self.min_participants.hash(state);
+        (self.max_wait.as_millis() as u64).hash(state);
+        self.output_slots.hash(state);
     }
 }
 
@@ -100,6 +134,7 @@ impl Hashable for ParticipantTXINMap {
             utxo.hash(state);
         }
         self.ownsig.hash(state);
+        self.output_slots.hash(state);
     }
 }
 impl Hashable for PoolInfo {
@@ -109,6 +144,7 @@ impl Hashable for PoolInfo {
             elt.hash(state);
         }
         self.session_id.hash(state);
+        self.max_participants.hash(state);
     }
 }
 
@@ -142,6 +178,9 @@ impl ProtoConvert for PoolJoin {
         }
         proto.set_ownsig(self.ownsig.into_proto());
         proto.set_seed(self.seed.to_vec());
+        proto.set_min_participants(self.min_participants);
+        proto.set_max_wait(self.max_wait.as_millis() as u64);
+        proto.set_output_slots(self.output_slots);
         proto
     }
     fn from_proto(proto: &Self::Proto) -> Result<Self, Error> {
@@ -160,11 +199,17 @@ impl ProtoConvert for PoolJoin {
         let mut seed: [u8; 32] = [0u8; 32];
         seed.copy_from_slice(seed_slice);
         let ownsig = SchnorrSig::from_proto(proto.get_ownsig())?;
+        let min_participants = proto.get_min_participants();
+        let max_wait = Duration::from_millis(proto.get_max_wait());
+        let output_slots = proto.get_output_slots();
         Ok(PoolJoin {
             txins,
             utxos,
             seed,
             ownsig,
+            min_participants,
+            max_wait,
+            output_slots,
         })
     }
 }
@@ -181,6 +226,7 @@ impl ProtoConvert for ParticipantTXINMap {
         for utxo in &self.utxos {
             proto.utxos.push((*utxo).into_proto());
         }
+        proto.set_output_slots(self.output_slots);
         proto
     }
     fn from_proto(proto: &Self::Proto) -> Result<Self, Error> {
@@ -194,11 +240,13 @@ impl ProtoConvert for ParticipantTXINMap {
             utxos.push(UTXO::from_proto(utxo)?);
         }
         let ownsig = SchnorrSig::from_proto(proto.get_ownsig())?;
+        let output_slots = proto.get_output_slots();
         Ok(ParticipantTXINMap {
             participant,
             txins,
             utxos,
             ownsig,
+            output_slots,
         })
     }
 }
@@ -211,6 +259,10 @@ impl ProtoConvert for PoolInfo {
             proto.participants.push((*elt).into_proto());
         }
         proto.set_session_id(self.session_id.into_proto());
+        proto.set_max_participants(self.max_participants);
+        if let Some(ref signature) = self.signature {
+            proto.set_signature(signature.into_proto());
+        }
         proto
     }
     fn from_proto(proto: &Self::Proto) -> Result<Self, Error> {
@@ -219,9 +271,17 @@ impl ProtoConvert for PoolInfo {
             participants.push(ParticipantTXINMap::from_proto(elt)?);
         }
         let session_id = Hash::from_proto(proto.get_session_id())?;
+        let max_participants = proto.get_max_participants();
+        let signature = if proto.has_signature() {
+            Some(pbc::Signature::from_proto(proto.get_signature())?)
+        } else {
+            None
+        };
         Ok(PoolInfo {
             participants,
             session_id,
+            max_participants,
+            signature,
         })
     }
 }
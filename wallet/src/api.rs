@@ -73,6 +73,12 @@ pub struct PaymentInfo {
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub rvalue: Option<scc::Fr>,
     pub is_change: bool,
+    /// Name of the recipient in the address book.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub contact: Option<String>,
+    /// User-defined label.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
@@ -82,6 +88,12 @@ pub struct PublicPaymentInfo {
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub pending_timestamp: Option<Timestamp>,
     pub recipient: scc::PublicKey,
+    /// Name of the recipient in the address book.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub contact: Option<String>,
+    /// User-defined label.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
 }
 
 ///
@@ -114,6 +126,146 @@ pub struct AccountBalance {
     pub is_final: bool,
 }
 
+///
+/// An entry of the address book.
+///
+#[derive(Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct ContactInfo {
+    /// Unique name of the contact.
+    pub name: String,
+    /// Account public key of the contact.
+    pub account_pkey: scc::PublicKey,
+    /// Free-form notes.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub notes: String,
+}
+
+///
+/// Format of exported history.
+///
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    /// Comma-separated values with a header line.
+    Csv,
+    /// One JSON object per line.
+    Jsonl,
+}
+
+impl Default for ExportFormat {
+    fn default() -> Self {
+        ExportFormat::Csv
+    }
+}
+
+///
+/// Direction of an exported record.
+///
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportDirection {
+    Incoming,
+    Outgoing,
+}
+
+///
+/// Kind of an exported record.
+///
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportKind {
+    Payment,
+    PublicPayment,
+    Cloak,
+    Stake,
+    Unstake,
+    BlockReward,
+    ServiceAward,
+}
+
+///
+/// Confirmation state of an exported record.
+///
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportConfirmation {
+    /// Not yet included into a block.
+    Pending,
+    /// Included into a micro block.
+    Prepared,
+    /// Finalized by a macro block.
+    Final,
+    Rejected,
+    Conflicted,
+}
+
+///
+/// A single record of exported history.
+/// All amounts are in μSTG, no conversion to other currencies is made.
+///
+#[derive(Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct ExportRecord {
+    pub timestamp: Timestamp,
+    pub direction: ExportDirection,
+    pub kind: ExportKind,
+    /// Contact name or address of the other party, if known.
+    pub counterparty: Option<String>,
+    pub amount: i64,
+    pub fee: i64,
+    pub tx_hash: Option<Hash>,
+    pub output_hash: Option<Hash>,
+    pub epoch: Option<u64>,
+    pub confirmation: ExportConfirmation,
+    /// User-defined label.
+    pub label: Option<String>,
+}
+
+///
+/// A request to pay, encoded as `stegos:` URI.
+///
+#[derive(Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct PaymentRequest {
+    pub recipient: scc::PublicKey,
+    pub amount: i64,
+    /// Reference, which must be included into the payment as is.
+    #[serde(flatten)]
+    pub data: PaymentPayloadData,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires: Option<Timestamp>,
+}
+
+#[derive(Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "status")]
+#[serde(rename_all = "snake_case")]
+pub enum InvoiceStatus {
+    Unpaid,
+    Paid { utxo: Hash, paid_at: Timestamp },
+    Expired,
+}
+
+///
+/// An invoice created by this account.
+///
+#[derive(Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct InvoiceInfo {
+    /// Hash of the reference.
+    pub invoice_id: Hash,
+    /// Payment request to share with the payer.
+    pub uri: String,
+    pub amount: i64,
+    #[serde(flatten)]
+    pub data: PaymentPayloadData,
+    pub created: Timestamp,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires: Option<Timestamp>,
+    #[serde(flatten)]
+    pub status: InvoiceStatus,
+}
+
 /// Recovery information.
 #[derive(Eq, PartialEq, Debug, Clone, Serialize, Deserialize, Default)]
 #[serde(rename_all = "snake_case")]
@@ -122,6 +274,163 @@ pub struct AccountRecovery {
     pub recovery: String,
 }
 
+/// Encrypted backup of an account.
+#[derive(Eq, PartialEq, Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub struct AccountBackup {
+    /// Hex-encoded encrypted archive.
+    pub backup: String,
+}
+
+/// Background consolidation of small payment UTXOs.
+#[derive(Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[serde(default)]
+pub struct ConsolidationPolicy {
+    pub enabled: bool,
+    /// Consolidate when the account has at least this number of dust UTXOs.
+    pub min_utxos: usize,
+    /// Payment UTXOs with amount less or equal to this value are dust.
+    pub dust_amount: i64,
+    /// Fee per output of consolidation transactions.
+    pub payment_fee: i64,
+    /// Maximal total fee of one consolidation transaction.
+    pub max_fee: i64,
+    /// Merge UTXOs using Snowball instead of a regular self-payment.
+    pub use_snowball: bool,
+}
+
+impl Default for ConsolidationPolicy {
+    fn default() -> Self {
+        ConsolidationPolicy {
+            enabled: false,
+            min_utxos: 50,
+            dust_amount: 1_000_000, // 1 STG
+            payment_fee: 1_000,     // 0.001 STG
+            max_fee: 10_000,        // 0.01 STG
+            use_snowball: false,
+        }
+    }
+}
+
+/// Why a participant was excluded from a Snowball session.
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SnowballExclusionReason {
+    /// Didn't respond in time.
+    Timeout,
+    /// Found cheating by blame discovery.
+    Blame,
+}
+
+/// A participant excluded from a Snowball session.
+#[derive(Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct SnowballExclusionInfo {
+    /// Network key of the participant.
+    pub pkey: pbc::PublicKey,
+    /// Phase in which the participant was excluded.
+    #[serde(flatten)]
+    pub state: SnowballStatus,
+    pub reason: SnowballExclusionReason,
+}
+
+/// Why a facilitator was abandoned during a Snowball session.
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SnowballFacilitatorFault {
+    /// Didn't announce a pool in time.
+    Timeout,
+    /// Announced a pool with an invalid signature.
+    InvalidSignature,
+    /// Announced a pool without us.
+    Excluded,
+    /// Announced a pool with an invalid participant.
+    InvalidParticipant,
+    /// Announced a pool with too few participants.
+    TooFewParticipants,
+}
+
+impl SnowballFacilitatorFault {
+    /// Enum to string.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            SnowballFacilitatorFault::Timeout => "timeout",
+            SnowballFacilitatorFault::InvalidSignature => "invalid_signature",
+            SnowballFacilitatorFault::Excluded => "excluded",
+            SnowballFacilitatorFault::InvalidParticipant => "invalid_participant",
+            SnowballFacilitatorFault::TooFewParticipants => "too_few_participants",
+        }
+    }
+
+    /// String to enum.
+    pub fn from_name(name: &str) -> Option<SnowballFacilitatorFault> {
+        let fault = match name {
+            "timeout" => SnowballFacilitatorFault::Timeout,
+            "invalid_signature" => SnowballFacilitatorFault::InvalidSignature,
+            "excluded" => SnowballFacilitatorFault::Excluded,
+            "invalid_participant" => SnowballFacilitatorFault::InvalidParticipant,
+            "too_few_participants" => SnowballFacilitatorFault::TooFewParticipants,
+            _ => return None,
+        };
+        Some(fault)
+    }
+}
+
+/// A facilitator abandoned during a Snowball session.
+#[derive(Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct SnowballFacilitatorInfo {
+    /// Network key of the facilitator.
+    pub pkey: pbc::PublicKey,
+    pub fault: SnowballFacilitatorFault,
+    /// The signed pool announced by the facilitator, protobuf-encoded.
+    /// Empty if the facilitator didn't sign what it announced.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[serde(deserialize_with = "stegos_crypto::utils::vec_deserialize_from_hex")]
+    #[serde(serialize_with = "stegos_crypto::utils::vec_serialize_to_hex")]
+    pub pool: Vec<u8>,
+}
+
+/// A finished phase of a Snowball session.
+#[derive(Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct SnowballPhaseInfo {
+    #[serde(flatten)]
+    pub state: SnowballStatus,
+    /// Duration in milliseconds.
+    pub duration: u64,
+    /// The phase was finished by timeout rather than by responses of all participants.
+    pub timed_out: bool,
+}
+
+/// Outcome of a Snowball session.
+#[derive(Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct SnowballSessionInfo {
+    /// Session id assigned by the facilitator.
+    pub session_id: Hash,
+    pub started: Timestamp,
+    pub finished: Timestamp,
+    /// 1 for the first attempt, incremented on every automatic retry with the same inputs.
+    pub attempt: u32,
+    /// The number of participants in the pool.
+    pub participants: usize,
+    /// The number of DiceMix rounds.
+    pub rounds: u32,
+    pub phases: Vec<SnowballPhaseInfo>,
+    pub excluded: Vec<SnowballExclusionInfo>,
+    /// Facilitators abandoned for misbehaviour, with evidence.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub facilitators: Vec<SnowballFacilitatorInfo>,
+    pub succeeded: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// The resulting transaction.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tx_hash: Option<Hash>,
+}
+
 ///
 /// Out-of-band notifications.
 ///
@@ -147,6 +456,7 @@ pub enum AccountNotification {
     SpentPublic(PublicPaymentInfo),
     Staked(StakeInfo),
     Unstaked(StakeInfo),
+    InvoicePaid(InvoiceInfo),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -189,6 +499,12 @@ pub enum AccountRequest {
         amount: i64,
         payment_fee: i64,
         comment: String,
+        /// The minimal number of participants in the pool, 0 - facilitator's default.
+        #[serde(default)]
+        min_participants: u32,
+        /// How long to wait for a pool in seconds, 0 - facilitator's default.
+        #[serde(default)]
+        max_wait: u64,
     },
     StakeAll {
         payment_fee: i64,
@@ -197,6 +513,11 @@ pub enum AccountRequest {
         amount: i64,
         payment_fee: i64,
     },
+    /// Shuffle coins using Snowball and stake the received output.
+    SecureStake {
+        amount: i64,
+        payment_fee: i64,
+    },
     Stake {
         amount: i64,
         payment_fee: i64,
@@ -208,6 +529,29 @@ pub enum AccountRequest {
     UnstakeAll {
         payment_fee: i64,
     },
+    /// Re-bind all stakes from the current network key to the key pair
+    /// stored as network.next.{skey,pkey} in the keys directory of the node, generating it if needed.
+    /// The new key becomes a validator starting from the next epoch,
+    /// the node switches to it at the epoch boundary.
+    RotateValidatorKey {},
+    /// Delegate money to a third-party validator.
+    /// Delegated money is confiscated together with the stake of validator if it gets slashed.
+    Delegate {
+        validator: pbc::PublicKey,
+        amount: i64,
+        payment_fee: i64,
+    },
+    /// Withdraw money delegated to a third-party validator.
+    Undelegate {
+        validator: pbc::PublicKey,
+        amount: i64,
+        payment_fee: i64,
+    },
+    /// Re-stake all own stakes with a new commission, in basis points.
+    /// An increase applies only after the current delegations are unlocked.
+    SetCommission {
+        commission: u32,
+    },
     CloakAll {
         payment_fee: i64,
     },
@@ -222,6 +566,63 @@ pub enum AccountRequest {
         new_password: String,
     },
     GetRecovery {},
+    /// Create a backup of the account keys, history and metadata,
+    /// encrypted by password.
+    ExportBackup {
+        password: String,
+    },
+    AddContact {
+        name: String,
+        account_pkey: scc::PublicKey,
+        #[serde(default)]
+        notes: String,
+    },
+    RemoveContact {
+        name: String,
+    },
+    ContactInfo {
+        name: String,
+    },
+    ContactsInfo {},
+    /// Attach a label to a transaction or an output.
+    /// An empty label removes the existing one.
+    SetLabel {
+        hash: Hash,
+        label: String,
+    },
+    /// Create an invoice and return its payment request.
+    /// A random reference is used if comment is empty.
+    CreateInvoice {
+        amount: i64,
+        #[serde(default)]
+        comment: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        expires: Option<Timestamp>,
+    },
+    InvoicesInfo {},
+    /// Pay a `stegos:` payment request.
+    PayInvoice {
+        uri: String,
+        payment_fee: i64,
+        #[serde(default)]
+        with_certificate: bool,
+    },
+    /// Export the entire history as an accounting statement.
+    /// The statement is returned in the response, the client saves it.
+    ExportHistory {
+        #[serde(default)]
+        format: ExportFormat,
+    },
+    /// Change the policy of background UTXO consolidation.
+    SetConsolidationPolicy {
+        #[serde(flatten)]
+        policy: ConsolidationPolicy,
+    },
+    ConsolidationPolicyInfo {},
+    SnowballHistory {
+        starting_from: Timestamp,
+        limit: u64,
+    },
 }
 
 #[derive(Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
@@ -242,6 +643,13 @@ pub enum WalletControlRequest {
         account_id: AccountId,
     },
     LightReplicationInfo {},
+    /// Create a new account from a backup.
+    /// The password is also used to encrypt the account key.
+    RestoreBackup {
+        #[serde(flatten)]
+        backup: AccountBackup,
+        password: String,
+    },
 }
 
 #[derive(Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
@@ -266,6 +674,9 @@ pub struct TransactionInfo {
     pub inputs: Vec<Hash>,
     #[serde(flatten)]
     pub status: TransactionStatus,
+    /// User-defined label.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
 }
 
 ///
@@ -292,6 +703,43 @@ pub enum AccountResponse {
     },
     PasswordChanged,
     Recovery(AccountRecovery),
+    Backup(AccountBackup),
+    ContactInfo(ContactInfo),
+    ContactRemoved(ContactInfo),
+    ContactsInfo {
+        contacts: Vec<ContactInfo>,
+    },
+    LabelChanged {
+        hash: Hash,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        label: Option<String>,
+    },
+    InvoiceCreated(InvoiceInfo),
+    InvoicesInfo {
+        invoices: Vec<InvoiceInfo>,
+    },
+    HistoryExported {
+        format: ExportFormat,
+        /// The number of exported records.
+        records: usize,
+        /// The statement in the requested format.
+        data: String,
+    },
+    ConsolidationPolicyInfo(ConsolidationPolicy),
+    SnowballHistory {
+        sessions: Vec<SnowballSessionInfo>,
+    },
+    ValidatorKeyRotated {
+        tx_hash: Hash,
+        old_network_pkey: pbc::PublicKey,
+        new_network_pkey: pbc::PublicKey,
+        amount: i64,
+    },
+    CommissionChanged {
+        tx_hash: Hash,
+        commission: u32,
+        amount: i64,
+    },
     Error {
         error: String,
     },
@@ -383,5 +831,19 @@ pub mod tests {
         let request2_check: WalletRequest = serde_json::from_str(&json2).unwrap();
         assert_eq!(&request2, &request2_check);
         println!("{:?} {}", &request2, json2);
+
+        let (_skey, account_pkey) = scc::make_random_keys();
+        let request3 = WalletRequest::AccountRequest {
+            account_id: "my_account_id".to_string(),
+            request: AccountRequest::AddContact {
+                name: "alice".to_string(),
+                account_pkey,
+                notes: String::new(),
+            },
+        };
+        let json3 = serde_json::to_string(&request3).unwrap();
+        let request3_check: WalletRequest = serde_json::from_str(&json3).unwrap();
+        assert_eq!(&request3, &request3_check);
+        println!("{:?} {}", &request3, json3);
     }
 }
@@ -0,0 +1,155 @@
+//! Wallet - Encrypted Backups.
+
+//
+// Copyright (c) 2019 Stegos AG
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use crate::error::WalletError;
+use failure::Error;
+use stegos_blockchain::Timestamp;
+use stegos_crypto::hash::{Hashable, Hasher};
+use stegos_crypto::scc;
+use stegos_crypto::utils::{hexstr_to_bev_u8, u8v_to_hexstr};
+use stegos_serialization::traits::ProtoConvert;
+
+/// The current version of backup format.
+pub const BACKUP_VERSION: u32 = 1;
+
+/// Raw entry of the wallet database.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackupEntry {
+    /// Column family.
+    pub cf: String,
+    pub key: Vec<u8>,
+    pub value: Vec<u8>,
+}
+
+///
+/// Account keys and the part of the wallet database which can't be
+/// recovered from the blockchain: history with comments and certificates,
+/// the address book, labels and invoices.
+///
+#[derive(Debug, Clone)]
+pub struct Backup {
+    pub version: u32,
+    pub account_skey: scc::SecretKey,
+    pub account_pkey: scc::PublicKey,
+    pub created: Timestamp,
+    pub entries: Vec<BackupEntry>,
+}
+
+impl Backup {
+    /// Encrypts the backup by password and encodes it as hex.
+    pub fn encrypt(&self, password: &str) -> String {
+        let data = self.into_buffer().expect("Failed to encode backup");
+        let encrypted = scc::encrypt_key(password, &data)
+            .into_buffer()
+            .expect("Failed to encode encrypted payload");
+        u8v_to_hexstr(&encrypted)
+    }
+
+    /// Decodes, decrypts and verifies the backup.
+    pub fn decrypt(data: &str, password: &str) -> Result<Backup, Error> {
+        let data = data.trim();
+        let mut bytes = vec![0u8; (data.len() + 1) / 2];
+        hexstr_to_bev_u8(data, &mut bytes)
+            .map_err(|e| WalletError::InvalidBackup(format!("{}", e)))?;
+        let encrypted = scc::EncryptedKey::from_buffer(&bytes)
+            .map_err(|e| WalletError::InvalidBackup(format!("{}", e)))?;
+        // The signature of encrypted payload is checked before decryption.
+        let bytes =
+            scc::decrypt_key(password, &encrypted).map_err(|_| WalletError::BackupDecryption)?;
+        let backup = Backup::from_buffer(&bytes)
+            .map_err(|e| WalletError::InvalidBackup(format!("{}", e)))?;
+        if backup.version != BACKUP_VERSION {
+            return Err(WalletError::InvalidBackup(format!(
+                "unsupported version {}",
+                backup.version
+            ))
+            .into());
+        }
+        let account_pkey: scc::PublicKey = backup.account_skey.clone().into();
+        if account_pkey != backup.account_pkey {
+            return Err(WalletError::InvalidBackup("keys mismatch".to_string()).into());
+        }
+        Ok(backup)
+    }
+}
+
+impl Hashable for BackupEntry {
+    fn hash(&self, hasher: &mut Hasher) {
+        self.cf.hash(hasher);
+        self.key.hash(hasher);
+        self.value.hash(hasher);
+    }
+}
+
+impl Hashable for Backup {
+    fn hash(&self, hasher: &mut Hasher) {
+        self.version.hash(hasher);
+        self.account_skey.hash(hasher);
+        self.account_pkey.hash(hasher);
+        self.created.hash(hasher);
+        for entry in &self.entries {
+            entry.hash(hasher);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use stegos_crypto::hash::Hash;
+
+    #[test]
+    fn encrypt_decrypt() {
+        let (account_skey, account_pkey) = scc::make_random_keys();
+        let backup = Backup {
+            version: BACKUP_VERSION,
+            account_skey,
+            account_pkey,
+            created: Timestamp::now(),
+            entries: vec![BackupEntry {
+                cf: "history".to_string(),
+                key: vec![1, 2, 3],
+                value: vec![4, 5, 6],
+            }],
+        };
+        let data = backup.encrypt("password");
+
+        let restored = Backup::decrypt(&data, "password").unwrap();
+        assert_eq!(Hash::digest(&restored), Hash::digest(&backup));
+        assert_eq!(restored.entries, backup.entries);
+
+        // Wrong password.
+        let e = Backup::decrypt(&data, "wrong").unwrap_err();
+        assert_eq!(
+            e.downcast::<WalletError>().unwrap(),
+            WalletError::BackupDecryption
+        );
+
+        // Corrupted data.
+        let mut corrupted = data.into_bytes();
+        let pos = corrupted.len() / 2;
+        corrupted[pos] = if corrupted[pos] == b'0' { b'1' } else { b'0' };
+        let corrupted = String::from_utf8(corrupted).unwrap();
+        assert!(Backup::decrypt(&corrupted, "password").is_err());
+    }
+}
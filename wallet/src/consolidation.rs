@@ -0,0 +1,145 @@
+//! Wallet - UTXO Consolidation.
+
+//
+// Copyright (c) 2019 Stegos AG
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use crate::api::ConsolidationPolicy;
+use crate::error::WalletError;
+
+/// Returns the total fee of a consolidation transaction.
+/// Transaction builders always reserve the fee for a change output.
+pub(crate) fn consolidation_fee(policy: &ConsolidationPolicy) -> i64 {
+    2 * policy.payment_fee
+}
+
+/// Check that the policy makes sense.
+pub(crate) fn validate_policy(policy: &ConsolidationPolicy) -> Result<(), WalletError> {
+    let error = |msg: &str| Err(WalletError::InvalidConsolidationPolicy(msg.to_string()));
+    if policy.min_utxos < 2 {
+        return error("min_utxos must be at least 2");
+    }
+    if policy.dust_amount <= 0 {
+        return error("dust_amount must be positive");
+    }
+    if policy.payment_fee < 0 || policy.max_fee < 0 {
+        return error("fees must not be negative");
+    }
+    if consolidation_fee(policy) > policy.max_fee {
+        return error("fee of a consolidation transaction exceeds max_fee");
+    }
+    Ok(())
+}
+
+/// Find dust UTXOs to merge.
+/// Returns inputs and the amount of the merged output or None if consolidation is not needed.
+pub(crate) fn find_dust<I, T>(
+    unspent_iter: I,
+    policy: &ConsolidationPolicy,
+    max_inputs_in_tx: usize,
+) -> Option<(Vec<T>, i64)>
+where
+    I: IntoIterator<Item = (T, i64)>,
+{
+    let mut dust: Vec<(i64, T)> = unspent_iter
+        .into_iter()
+        .filter(|(_output, amount)| *amount <= policy.dust_amount)
+        .map(|(output, amount)| (amount, output))
+        .collect();
+    if dust.len() < policy.min_utxos {
+        return None;
+    }
+
+    // Merge the smallest outputs first.
+    dust.sort_by_key(|(amount, _output)| *amount);
+    dust.truncate(max_inputs_in_tx);
+    if dust.len() < 2 {
+        return None;
+    }
+
+    // validate_policy() guarantees that the fee doesn't exceed max_fee.
+    let fee = consolidation_fee(policy);
+    let sum: i64 = dust.iter().map(|(amount, _output)| *amount).sum();
+    let amount = sum - fee;
+    // Don't spend more on fees than we get.
+    if amount <= fee {
+        return None;
+    }
+
+    let inputs = dust.into_iter().map(|(_amount, output)| output).collect();
+    Some((inputs, amount))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_find_dust() {
+        let policy = ConsolidationPolicy {
+            enabled: true,
+            min_utxos: 3,
+            dust_amount: 10,
+            payment_fee: 1,
+            max_fee: 2,
+            use_snowball: false,
+        };
+        validate_policy(&policy).unwrap();
+        let unspent: Vec<(usize, i64)> = vec![(0, 100), (1, 5), (2, 10), (3, 1), (4, 7)];
+
+        let (inputs, amount) = find_dust(unspent.clone(), &policy, 100).unwrap();
+        assert_eq!(inputs, vec![3, 1, 4, 2]);
+        assert_eq!(amount, 1 + 5 + 7 + 10 - 2);
+
+        // Limited by max_inputs_in_tx.
+        let (inputs, amount) = find_dust(unspent.clone(), &policy, 2).unwrap();
+        assert_eq!(inputs, vec![3, 1]);
+        assert_eq!(amount, 1 + 5 - 2);
+
+        // Not enough dust.
+        let policy2 = ConsolidationPolicy {
+            min_utxos: 5,
+            ..policy.clone()
+        };
+        assert!(find_dust(unspent.clone(), &policy2, 100).is_none());
+
+        // Fees eat everything.
+        let policy2 = ConsolidationPolicy {
+            payment_fee: 5,
+            max_fee: 10,
+            ..policy.clone()
+        };
+        assert!(find_dust(unspent.clone(), &policy2, 100).is_some());
+        assert!(find_dust(unspent.clone(), &policy2, 2).is_none());
+
+        // Too expensive.
+        let policy2 = ConsolidationPolicy {
+            max_fee: 1,
+            ..policy.clone()
+        };
+        assert!(validate_policy(&policy2).is_err());
+
+        let policy2 = ConsolidationPolicy {
+            min_utxos: 1,
+            ..policy.clone()
+        };
+        assert!(validate_policy(&policy2).is_err());
+    }
+}
@@ -22,7 +22,9 @@
 // SOFTWARE.
 
 use failure::Fail;
-use stegos_crypto::scc;
+use stegos_blockchain::Timestamp;
+use stegos_crypto::hash::Hash;
+use stegos_crypto::{pbc, scc};
 
 #[derive(Debug, Fail, PartialEq, Eq)]
 pub enum WalletError {
@@ -53,4 +55,40 @@ pub enum WalletError {
     IncorrectTXINType,
     #[fail(display = "Snowball is busy")]
     SnowballBusy,
+    #[fail(
+        display = "Invalid contact name '{}': only letters, digits, '_' and '-' are allowed",
+        _0
+    )]
+    InvalidContactName(String),
+    #[fail(display = "Contact '{}' not found", _0)]
+    ContactNotFound(String),
+    #[fail(display = "Transaction or output not found: hash={}", _0)]
+    UnknownHash(Hash),
+    #[fail(display = "Label is too long: len={}, max={}", _0, _1)]
+    LabelTooLong(usize, usize),
+    #[fail(display = "Invalid payment request: {}", _0)]
+    InvalidPaymentRequest(String),
+    #[fail(display = "Invoice amount must be positive: amount={}", _0)]
+    InvalidInvoiceAmount(i64),
+    #[fail(
+        display = "Invoice with the same reference already exists: invoice={}",
+        _0
+    )]
+    InvoiceExists(Hash),
+    #[fail(display = "Payment request has expired at {}", _0)]
+    PaymentRequestExpired(Timestamp),
+    #[fail(display = "Invalid backup: {}", _0)]
+    InvalidBackup(String),
+    #[fail(display = "Failed to decrypt backup: invalid password or corrupted data")]
+    BackupDecryption,
+    #[fail(display = "Invalid consolidation policy: {}", _0)]
+    InvalidConsolidationPolicy(String),
+    #[fail(display = "No stakes bound to the validator: validator={}", _0)]
+    NoValidatorStakes(pbc::PublicKey),
+    #[fail(display = "Stakes are used by a pending transaction: utxo={}", _0)]
+    StakeIsPending(Hash),
+    #[fail(display = "New network key is the same as the current one: key={}", _0)]
+    SameNetworkKey(pbc::PublicKey),
+    #[fail(display = "Invalid commission: max={}, got={}", _0, _1)]
+    InvalidCommission(u32, u32),
 }
@@ -0,0 +1,513 @@
+//! Wallet - History Export.
+
+//
+// Copyright (c) 2019 Stegos AG
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use crate::api::*;
+use crate::storage::{LightDatabase, LogEntry, OutputSource, OutputValue, TransactionValue};
+use failure::Error;
+use std::collections::HashSet;
+use std::io::Write;
+use stegos_blockchain::{Timestamp, TransactionStatus};
+use stegos_crypto::hash::Hash;
+use stegos_crypto::scc;
+
+const CSV_HEADER: &str = "timestamp,direction,kind,counterparty,amount,fee,\
+                          tx_hash,output_hash,epoch,confirmation,label";
+
+///
+/// Converts history entries into accounting records.
+///
+/// Outputs received from our own transactions (change, stakes, unstaked money)
+/// are not exported separately, because they are already accounted
+/// by the corresponding outgoing records.
+///
+pub(crate) struct Exporter<'a> {
+    database: &'a LightDatabase,
+    account_pkey: &'a scc::PublicKey,
+    /// Outputs created by our own transactions.
+    own_outputs: HashSet<Hash>,
+    /// Received stakes.
+    stakes: HashSet<Hash>,
+    /// Received public payments.
+    public_payments: HashSet<Hash>,
+}
+
+impl<'a> Exporter<'a> {
+    pub fn new(database: &'a LightDatabase, account_pkey: &'a scc::PublicKey) -> Self {
+        Exporter {
+            database,
+            account_pkey,
+            own_outputs: HashSet::new(),
+            stakes: HashSet::new(),
+            public_payments: HashSet::new(),
+        }
+    }
+
+    /// The first pass - collect information about outputs.
+    pub fn scan(&mut self, entry: &LogEntry) {
+        match entry {
+            LogEntry::Incoming { output, .. } => {
+                let output_hash = Hash::digest(&output.to_output());
+                match output {
+                    OutputValue::Payment(_) => {}
+                    OutputValue::PublicPayment(_) => {
+                        self.public_payments.insert(output_hash);
+                    }
+                    OutputValue::Stake(_) => {
+                        self.stakes.insert(output_hash);
+                    }
+                }
+            }
+            LogEntry::Outgoing { tx } => {
+                for output in &tx.outputs {
+                    self.own_outputs.insert(Hash::digest(&output.to_output()));
+                }
+            }
+        }
+    }
+
+    /// The second pass - convert the entry into records.
+    pub fn records(&self, timestamp: Timestamp, entry: &LogEntry) -> Vec<ExportRecord> {
+        match entry {
+            LogEntry::Incoming {
+                output,
+                epoch,
+                offset,
+                source,
+            } => self
+                .incoming(timestamp, output, *epoch, *offset, *source)
+                .into_iter()
+                .collect(),
+            LogEntry::Outgoing { tx } => self.outgoing(timestamp, tx),
+        }
+    }
+
+    fn incoming(
+        &self,
+        timestamp: Timestamp,
+        output: &OutputValue,
+        epoch: Option<u64>,
+        offset: Option<u32>,
+        source: OutputSource,
+    ) -> Option<ExportRecord> {
+        let output_hash = Hash::digest(&output.to_output());
+        if self.own_outputs.contains(&output_hash) {
+            return None;
+        }
+
+        let (kind, amount) = match (output, source) {
+            (OutputValue::Payment(p), OutputSource::BlockReward) => {
+                (ExportKind::BlockReward, p.amount)
+            }
+            (OutputValue::Payment(p), _) => (ExportKind::Payment, p.amount),
+            (OutputValue::PublicPayment(p), OutputSource::ServiceAward) => {
+                (ExportKind::ServiceAward, p.output.amount)
+            }
+            (OutputValue::PublicPayment(p), _) => (ExportKind::PublicPayment, p.output.amount),
+            (OutputValue::Stake(s), _) => (ExportKind::Stake, s.output.amount),
+        };
+
+        let confirmation = match epoch {
+            Some(epoch) if offset.is_none() || epoch < self.database.epoch() => {
+                ExportConfirmation::Final
+            }
+            Some(_) => ExportConfirmation::Prepared,
+            // Entries created by older versions don't have information about blocks.
+            None if timestamp <= self.database.status().last_macro_block_timestamp => {
+                ExportConfirmation::Final
+            }
+            None => ExportConfirmation::Prepared,
+        };
+
+        Some(ExportRecord {
+            timestamp,
+            direction: ExportDirection::Incoming,
+            kind,
+            counterparty: None,
+            amount,
+            fee: 0,
+            tx_hash: None,
+            output_hash: Some(output_hash),
+            epoch,
+            confirmation,
+            label: self.database.label(&output_hash).cloned(),
+        })
+    }
+
+    fn outgoing(&self, timestamp: Timestamp, tx: &TransactionValue) -> Vec<ExportRecord> {
+        let tx_hash = Hash::digest(&tx.tx);
+        let (epoch, confirmation) = self.tx_confirmation(&tx.status);
+        let tx_label = self.database.label(&tx_hash);
+        let is_unstake = tx.tx.txins.iter().any(|h| self.stakes.contains(h));
+        let is_cloak = tx.tx.txins.iter().any(|h| self.public_payments.contains(h));
+
+        let mut records = Vec::new();
+        for output in &tx.outputs {
+            let (kind, recipient, amount) = match output {
+                OutputValue::Payment(p) if p.is_change => continue,
+                OutputValue::Payment(p) if &p.recipient == self.account_pkey => {
+                    let kind = if is_unstake {
+                        ExportKind::Unstake
+                    } else if is_cloak {
+                        ExportKind::Cloak
+                    } else {
+                        ExportKind::Payment
+                    };
+                    (kind, None, p.amount)
+                }
+                OutputValue::Payment(p) => (ExportKind::Payment, Some(&p.recipient), p.amount),
+                OutputValue::PublicPayment(p) => (
+                    ExportKind::PublicPayment,
+                    Some(&p.output.recipient),
+                    p.output.amount,
+                ),
+                // The rest of stake after unstaking.
+                OutputValue::Stake(_) if is_unstake => continue,
+                OutputValue::Stake(s) => (ExportKind::Stake, None, s.output.amount),
+            };
+            let output_hash = Hash::digest(&output.to_output());
+            let label = self.database.label(&output_hash).or(tx_label).cloned();
+            records.push(ExportRecord {
+                timestamp,
+                direction: ExportDirection::Outgoing,
+                kind,
+                counterparty: recipient.map(|r| self.counterparty(r)),
+                amount,
+                fee: 0,
+                tx_hash: Some(tx_hash),
+                output_hash: Some(output_hash),
+                epoch,
+                confirmation,
+                label,
+            });
+        }
+
+        // Transaction without payments, e.g. when all outputs are change.
+        if records.is_empty() {
+            records.push(ExportRecord {
+                timestamp,
+                direction: ExportDirection::Outgoing,
+                kind: ExportKind::Payment,
+                counterparty: None,
+                amount: 0,
+                fee: 0,
+                tx_hash: Some(tx_hash),
+                output_hash: None,
+                epoch,
+                confirmation,
+                label: tx_label.cloned(),
+            });
+        }
+        // The fee is paid once per transaction.
+        records[0].fee = tx.tx.fee;
+        records
+    }
+
+    fn tx_confirmation(&self, status: &TransactionStatus) -> (Option<u64>, ExportConfirmation) {
+        match status {
+            TransactionStatus::Created {}
+            | TransactionStatus::Accepted {}
+            | TransactionStatus::Rollback { .. } => (None, ExportConfirmation::Pending),
+            TransactionStatus::Rejected { .. } => (None, ExportConfirmation::Rejected),
+            TransactionStatus::Prepared { epoch, .. } if *epoch < self.database.epoch() => {
+                (Some(*epoch), ExportConfirmation::Final)
+            }
+            TransactionStatus::Prepared { epoch, .. } => {
+                (Some(*epoch), ExportConfirmation::Prepared)
+            }
+            TransactionStatus::Committed { epoch } => (Some(*epoch), ExportConfirmation::Final),
+            TransactionStatus::Conflicted { epoch, .. } => {
+                (Some(*epoch), ExportConfirmation::Conflicted)
+            }
+        }
+    }
+
+    fn counterparty(&self, pkey: &scc::PublicKey) -> String {
+        match self.database.contact_name(pkey) {
+            Some(name) => name.clone(),
+            None => String::from(pkey),
+        }
+    }
+}
+
+/// Export the entire history of the account into `out`.
+/// Records are written one by one as they are produced.
+/// Returns the number of records.
+pub(crate) fn export_history<W: Write>(
+    database: &LightDatabase,
+    account_pkey: &scc::PublicKey,
+    format: ExportFormat,
+    out: &mut W,
+) -> Result<usize, Error> {
+    let mut exporter = Exporter::new(database, account_pkey);
+    for (_timestamp, entry) in database.iter_range(Timestamp::UNIX_EPOCH, u64::max_value()) {
+        exporter.scan(&entry);
+    }
+
+    if format == ExportFormat::Csv {
+        writeln!(out, "{}", CSV_HEADER)?;
+    }
+    let mut count = 0;
+    for (timestamp, entry) in database.iter_range(Timestamp::UNIX_EPOCH, u64::max_value()) {
+        for record in exporter.records(timestamp, &entry) {
+            write_record(out, &record, format)?;
+            count += 1;
+        }
+    }
+    out.flush()?;
+    Ok(count)
+}
+
+/// Write a single formatted record, including the line separator.
+pub(crate) fn write_record<W: Write>(
+    out: &mut W,
+    record: &ExportRecord,
+    format: ExportFormat,
+) -> Result<(), Error> {
+    match format {
+        ExportFormat::Csv => {
+            let fields = [
+                record.timestamp.to_string(),
+                enum_str(&record.direction),
+                enum_str(&record.kind),
+                csv_escape(&record.counterparty.clone().unwrap_or_default()),
+                record.amount.to_string(),
+                record.fee.to_string(),
+                record.tx_hash.map(|h| h.to_hex()).unwrap_or_default(),
+                record.output_hash.map(|h| h.to_hex()).unwrap_or_default(),
+                record.epoch.map(|e| e.to_string()).unwrap_or_default(),
+                enum_str(&record.confirmation),
+                csv_escape(&record.label.clone().unwrap_or_default()),
+            ];
+            writeln!(out, "{}", fields.join(","))?;
+        }
+        ExportFormat::Jsonl => {
+            serde_json::to_writer(&mut *out, record)?;
+            writeln!(out)?;
+        }
+    }
+    Ok(())
+}
+
+/// Returns serde name of an unit enum variant.
+fn enum_str<T: serde::Serialize>(value: &T) -> String {
+    match serde_json::to_value(value).expect("serializable") {
+        serde_json::Value::String(s) => s,
+        _ => unreachable!("unit variant"),
+    }
+}
+
+/// Quote a CSV field if needed (RFC 4180).
+fn csv_escape(field: &str) -> String {
+    if field.contains(|c| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{PaymentValue, PublicPaymentValue, StakeValue};
+    use stegos_blockchain::{
+        ChainConfig, PaymentOutput, PaymentPayloadData, PaymentTransaction, PublicPaymentOutput,
+        StakeOutput,
+    };
+    use stegos_crypto::pbc;
+    use tempdir::TempDir;
+
+    fn payment(recipient: &scc::PublicKey, amount: i64, comment: &str) -> PaymentValue {
+        let data = PaymentPayloadData::Comment(comment.to_string());
+        let (output, _gamma, rvalue) =
+            PaymentOutput::with_payload(None, recipient, amount, data.clone()).unwrap();
+        PaymentValue {
+            output,
+            amount,
+            recipient: *recipient,
+            data,
+            rvalue: Some(rvalue),
+            is_change: false,
+        }
+    }
+
+    #[test]
+    fn records() {
+        let temp_dir = TempDir::new("export").unwrap();
+        let database = LightDatabase::open(
+            temp_dir.path(),
+            Hash::digest("genesis"),
+            ChainConfig::default(),
+        );
+        let (_skey, account_pkey) = scc::make_random_keys();
+        let (_skey, other_pkey) = scc::make_random_keys();
+        let (network_skey, network_pkey) = pbc::make_random_keys();
+        let now = Timestamp::now();
+
+        // Incoming payment, block reward and service award.
+        let received = payment(&account_pkey, 100, "Thanks");
+        let reward = payment(&account_pkey, 50, "reward+fee");
+        // Rewards are recognized by the source, not by the comment.
+        let forged = payment(&account_pkey, 7, "Block reward");
+        let award = PublicPaymentOutput::new(&account_pkey, 10);
+        let award = OutputValue::PublicPayment(PublicPaymentValue { output: award });
+
+        // Outgoing payment with change.
+        let sent = payment(&other_pkey, 30, "Coffee, with \"milk\"");
+        let mut change = payment(&account_pkey, 65, "Change");
+        change.is_change = true;
+        let mut tx = PaymentTransaction::dum();
+        tx.fee = 5;
+        let payment_tx = TransactionValue {
+            tx,
+            outputs: vec![sent.clone().into(), change.clone().into()],
+            status: TransactionStatus::Committed { epoch: 0 },
+        };
+
+        // Stake and unstake.
+        let stake = StakeOutput::new(&account_pkey, &network_skey, &network_pkey, 40).unwrap();
+        let stake = OutputValue::Stake(StakeValue {
+            output: stake,
+            active_until_epoch: None,
+        });
+        let mut tx = PaymentTransaction::dum();
+        tx.fee = 1;
+        let stake_tx = TransactionValue {
+            tx,
+            outputs: vec![stake.clone()],
+            status: TransactionStatus::Created {},
+        };
+        let unstaked = payment(&account_pkey, 39, "Unstake amount.");
+        let mut tx = PaymentTransaction::dum();
+        tx.txins = vec![Hash::digest(&stake.to_output())];
+        tx.fee = 1;
+        let unstake_tx = TransactionValue {
+            tx,
+            outputs: vec![unstaked.clone().into()],
+            status: TransactionStatus::Created {},
+        };
+
+        let entries = vec![
+            LogEntry::Incoming {
+                output: received.into(),
+                epoch: Some(0),
+                offset: Some(1),
+                source: OutputSource::Transaction,
+            },
+            LogEntry::Incoming {
+                output: reward.into(),
+                epoch: Some(0),
+                offset: Some(2),
+                source: OutputSource::BlockReward,
+            },
+            LogEntry::Incoming {
+                output: forged.into(),
+                epoch: Some(0),
+                offset: Some(2),
+                source: OutputSource::Transaction,
+            },
+            LogEntry::Incoming {
+                output: award,
+                epoch: Some(0),
+                offset: None,
+                source: OutputSource::ServiceAward,
+            },
+            LogEntry::Outgoing { tx: payment_tx },
+            LogEntry::Incoming {
+                output: change.into(),
+                epoch: Some(0),
+                offset: Some(3),
+                source: OutputSource::Transaction,
+            },
+            LogEntry::Outgoing { tx: stake_tx },
+            LogEntry::Incoming {
+                output: stake,
+                epoch: Some(1),
+                offset: Some(0),
+                source: OutputSource::Transaction,
+            },
+            LogEntry::Outgoing { tx: unstake_tx },
+            LogEntry::Incoming {
+                output: unstaked.into(),
+                epoch: Some(1),
+                offset: Some(1),
+                source: OutputSource::Transaction,
+            },
+        ];
+
+        let mut exporter = Exporter::new(&database, &account_pkey);
+        for entry in &entries {
+            exporter.scan(entry);
+        }
+        let records: Vec<ExportRecord> = entries
+            .iter()
+            .flat_map(|entry| exporter.records(now, entry))
+            .collect();
+
+        let summary: Vec<(ExportDirection, ExportKind, i64, i64)> = records
+            .iter()
+            .map(|r| (r.direction, r.kind, r.amount, r.fee))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (ExportDirection::Incoming, ExportKind::Payment, 100, 0),
+                (ExportDirection::Incoming, ExportKind::BlockReward, 50, 0),
+                (ExportDirection::Incoming, ExportKind::Payment, 7, 0),
+                (ExportDirection::Incoming, ExportKind::ServiceAward, 10, 0),
+                (ExportDirection::Outgoing, ExportKind::Payment, 30, 5),
+                (ExportDirection::Outgoing, ExportKind::Stake, 40, 1),
+                (ExportDirection::Outgoing, ExportKind::Unstake, 39, 1),
+            ]
+        );
+        assert_eq!(records[4].counterparty, Some(String::from(&other_pkey)));
+        assert_eq!(records[4].confirmation, ExportConfirmation::Final);
+        assert_eq!(records[5].confirmation, ExportConfirmation::Pending);
+
+        let mut csv = Vec::new();
+        write_record(&mut csv, &records[4], ExportFormat::Csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert_eq!(csv.lines().count(), 1);
+        assert_eq!(csv.split(',').count(), CSV_HEADER.split(',').count());
+
+        let mut jsonl = Vec::new();
+        write_record(&mut jsonl, &records[4], ExportFormat::Jsonl).unwrap();
+        let jsonl = String::from_utf8(jsonl).unwrap();
+        let record: ExportRecord = serde_json::from_str(jsonl.trim_end()).unwrap();
+        assert_eq!(record, records[4]);
+
+        // An empty history has only the header.
+        let mut data = Vec::new();
+        let count = export_history(&database, &account_pkey, ExportFormat::Csv, &mut data).unwrap();
+        assert_eq!(count, 0);
+        let data = String::from_utf8(data).unwrap();
+        assert_eq!(data.lines().collect::<Vec<_>>(), vec![CSV_HEADER]);
+    }
+
+    #[test]
+    fn csv() {
+        assert_eq!(csv_escape("coffee"), "coffee");
+        assert_eq!(csv_escape("a,b"), "\"a,b\"");
+        assert_eq!(csv_escape("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(enum_str(&ExportKind::ServiceAward), "service_award");
+    }
+}
@@ -0,0 +1,45 @@
+//! Wallet - Entry points for fuzz targets, see `wallet/fuzz`.
+
+//
+// Copyright (c) 2018 Stegos AG
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use crate::snowball::message::SnowballMessage;
+use stegos_crypto::hash::Hash;
+use stegos_serialization::traits::ProtoConvert;
+use stegos_txpool::PoolNotification;
+
+/// Decodes an untrusted Snowball message and checks that it survives re-encoding.
+pub fn snowball_message(data: &[u8]) {
+    if let Ok(msg) = SnowballMessage::from_buffer(data) {
+        let data = msg.into_buffer().expect("serialized");
+        let msg2 = SnowballMessage::from_buffer(&data).expect("re-encoded message is valid");
+        assert_eq!(Hash::digest(&msg), Hash::digest(&msg2));
+    }
+}
+
+/// Decodes an untrusted pool notification and checks that it survives re-encoding.
+pub fn pool_notification(data: &[u8]) {
+    if let Ok(msg) = PoolNotification::from_buffer(data) {
+        let data = msg.into_buffer().expect("serialized");
+        let msg2 = PoolNotification::from_buffer(&data).expect("re-encoded message is valid");
+        assert_eq!(Hash::digest(&msg), Hash::digest(&msg2));
+    }
+}
@@ -0,0 +1,218 @@
+//! Wallet - Payment Requests and Invoices.
+
+//
+// Copyright (c) 2019 Stegos AG
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use crate::api::PaymentRequest;
+use crate::error::WalletError;
+use failure::Error;
+use std::fmt;
+use std::str::FromStr;
+use stegos_blockchain::{PaymentPayloadData, Timestamp};
+use stegos_crypto::hash::Hash;
+use stegos_crypto::scc;
+
+/// URI scheme of payment requests.
+pub const URI_SCHEME: &'static str = "stegos";
+
+///
+/// Format: `stegos:ADDRESS?amount=AMOUNT[&comment=TEXT|&hash=HEX][&expires=RFC3339]`.
+///
+impl fmt::Display for PaymentRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}?amount={}",
+            URI_SCHEME,
+            String::from(&self.recipient),
+            self.amount
+        )?;
+        match &self.data {
+            PaymentPayloadData::Comment(comment) if comment.is_empty() => {}
+            PaymentPayloadData::Comment(comment) => {
+                write!(f, "&comment={}", percent_encode(comment))?
+            }
+            PaymentPayloadData::ContentHash(hash) => write!(f, "&hash={}", hash.to_hex())?,
+        }
+        if let Some(expires) = &self.expires {
+            write!(f, "&expires={}", percent_encode(&expires.format_rfc3339()))?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for PaymentRequest {
+    type Err = Error;
+
+    fn from_str(uri: &str) -> Result<Self, Self::Err> {
+        let invalid = |msg: String| -> Error { WalletError::InvalidPaymentRequest(msg).into() };
+
+        let rest = match uri.find(':') {
+            Some(pos) if uri[..pos].eq_ignore_ascii_case(URI_SCHEME) => &uri[pos + 1..],
+            _ => return Err(invalid(format!("expected '{}:' scheme", URI_SCHEME))),
+        };
+        let (address, query) = match rest.find('?') {
+            Some(pos) => (&rest[..pos], &rest[pos + 1..]),
+            None => (rest, ""),
+        };
+        let recipient = scc::PublicKey::from_str(address)
+            .map_err(|e| invalid(format!("invalid address '{}': {}", address, e)))?;
+
+        let mut amount: Option<i64> = None;
+        let mut data: Option<PaymentPayloadData> = None;
+        let mut expires: Option<Timestamp> = None;
+        for pair in query.split('&').filter(|p| !p.is_empty()) {
+            let (key, value) = match pair.find('=') {
+                Some(pos) => (&pair[..pos], &pair[pos + 1..]),
+                None => (pair, ""),
+            };
+            let value = percent_decode(value)
+                .ok_or_else(|| invalid(format!("invalid encoding of '{}'", key)))?;
+            match key {
+                "amount" => {
+                    let value = value
+                        .parse()
+                        .map_err(|e| invalid(format!("invalid amount '{}': {}", value, e)))?;
+                    amount = Some(value);
+                }
+                "comment" | "hash" if data.is_some() => {
+                    return Err(invalid("both comment and hash are specified".to_string()));
+                }
+                "comment" => data = Some(PaymentPayloadData::Comment(value)),
+                "hash" => {
+                    let hash = Hash::try_from_hex(&value)
+                        .map_err(|e| invalid(format!("invalid hash '{}': {}", value, e)))?;
+                    data = Some(PaymentPayloadData::ContentHash(hash));
+                }
+                "expires" => {
+                    let timestamp = Timestamp::parse_rfc3339(&value)
+                        .map_err(|e| invalid(format!("invalid expiry '{}': {}", value, e)))?;
+                    expires = Some(timestamp);
+                }
+                // Ignore unknown parameters for forward compatibility.
+                _ => {}
+            }
+        }
+
+        let amount = amount.ok_or_else(|| invalid("missing amount".to_string()))?;
+        if amount <= 0 {
+            return Err(invalid(format!("amount must be positive: {}", amount)));
+        }
+        let data = data.unwrap_or_else(|| PaymentPayloadData::Comment(String::new()));
+        data.validate()
+            .map_err(|e| invalid(format!("invalid reference: {}", e)))?;
+
+        Ok(PaymentRequest {
+            recipient,
+            amount,
+            data,
+            expires,
+        })
+    }
+}
+
+/// Percent-encode everything except unreserved characters (RFC 3986).
+fn percent_encode(s: &str) -> String {
+    let mut encoded = String::with_capacity(s.len());
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(b as char)
+            }
+            b => encoded.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    encoded
+}
+
+/// Decode percent-encoded string, '+' is treated as a space.
+fn percent_decode(s: &str) -> Option<String> {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => {
+                let hex = s.get(i + 1..i + 3)?;
+                decoded.push(u8::from_str_radix(hex, 16).ok()?);
+                i += 3;
+            }
+            b'+' => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            b => {
+                decoded.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn uri() {
+        let (_skey, recipient) = scc::make_random_keys();
+
+        let request = PaymentRequest {
+            recipient,
+            amount: 1_000_000,
+            data: PaymentPayloadData::Comment("Order #42, thanks & bye".to_string()),
+            expires: Some(Timestamp::UNIX_EPOCH + Duration::from_secs(1_560_850_195)),
+        };
+        let uri = request.to_string();
+        assert!(uri.starts_with("stegos:"));
+        assert!(!uri.contains(' '));
+        assert_eq!(PaymentRequest::from_str(&uri).unwrap(), request);
+
+        let request = PaymentRequest {
+            recipient,
+            amount: 1,
+            data: PaymentPayloadData::ContentHash(Hash::digest("invoice")),
+            expires: None,
+        };
+        let uri = request.to_string();
+        assert_eq!(PaymentRequest::from_str(&uri).unwrap(), request);
+
+        let address = String::from(&recipient);
+        let uri = format!("stegos:{}?amount=10&comment=a+b&unknown=1", address);
+        let request = PaymentRequest::from_str(&uri).unwrap();
+        assert_eq!(request.amount, 10);
+        assert_eq!(request.data, PaymentPayloadData::Comment("a b".to_string()));
+
+        let invalid = vec![
+            format!("bitcoin:{}?amount=10", address),
+            format!("stegos:{}", address),
+            format!("stegos:{}?amount=-1", address),
+            format!("stegos:{}?amount=10&comment=a&hash=00", address),
+            format!("stegos:{}?amount=10&comment=%zz", address),
+            "stegos:nonsense?amount=10".to_string(),
+        ];
+        for uri in invalid {
+            assert!(PaymentRequest::from_str(&uri).is_err(), "{}", uri);
+        }
+    }
+}
@@ -24,8 +24,14 @@
 #![deny(warnings)]
 
 pub mod api;
+mod backup;
 mod change;
+mod consolidation;
 mod error;
+mod export;
+#[cfg(feature = "fuzzing")]
+pub mod fuzzing;
+mod invoice;
 mod metrics;
 mod protos;
 mod recovery;
@@ -35,6 +41,7 @@ mod storage;
 //mod test;
 mod transaction;
 
+use self::backup::{Backup, BACKUP_VERSION};
 use self::error::WalletError;
 use self::recovery::recovery_to_account_skey;
 use self::snowball::{Snowball, SnowballOutput, State as SnowballState};
@@ -51,6 +58,7 @@ use std::collections::HashMap;
 use std::fs;
 use std::mem;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use std::time::Duration;
 use stegos_blockchain::api::StatusInfo;
 use stegos_blockchain::TransactionStatus;
@@ -59,7 +67,8 @@ use stegos_crypto::hash::Hash;
 use stegos_crypto::{pbc, scc};
 use stegos_keychain as keychain;
 use stegos_keychain::keyfile::{
-    load_account_pkey, load_network_keypair, write_account_pkey, write_account_skey,
+    load_account_pkey, load_network_keypair, load_next_network_keys, write_account_pkey,
+    write_account_skey,
 };
 use stegos_keychain::KeyError;
 use stegos_network::{Network, PeerId, ReplicationEvent};
@@ -73,6 +82,15 @@ const STAKE_FEE: i64 = 0;
 const RESEND_TX_INTERVAL: Duration = Duration::from_secs(2 * 60);
 const PENDING_UTXO_TIME: Duration = Duration::from_secs(5 * 60);
 const CHECK_LOCKED_INPUTS: Duration = Duration::from_secs(10);
+const CONSOLIDATION_INTERVAL: Duration = Duration::from_secs(5 * 60);
+/// Comment of consolidated UTXOs.
+const CONSOLIDATION_COMMENT: &'static str = "Consolidation";
+/// Maximal length of user-defined labels.
+const MAX_LABEL_LEN: usize = 256;
+/// Maximal number of Snowball sessions with the same inputs.
+const SNOWBALL_MAX_ATTEMPTS: u32 = 3;
+/// Comment of Snowball outputs used for staking.
+const SECURE_STAKE_COMMENT: &'static str = "Stake";
 
 /// Topic used for sending transactions.
 pub const TX_TOPIC: &'static str = "tx";
@@ -102,6 +120,8 @@ struct UnsealedAccountService {
     database_dir: PathBuf,
     /// Path to account key folder.
     account_dir: PathBuf,
+    /// Path to network key folder of the node.
+    keys_dir: PathBuf,
     /// Account Secret Key.
     account_skey: scc::SecretKey,
     /// Account Public Key.
@@ -126,6 +146,8 @@ struct UnsealedAccountService {
 
     /// Check for pending utxos.
     expire_locked_inputs: Interval,
+    /// Check for dust utxos.
+    consolidate_utxos: Interval,
     //
     // Snowball state (owned)
     //
@@ -156,6 +178,7 @@ impl UnsealedAccountService {
     fn new(
         database_dir: PathBuf,
         account_dir: PathBuf,
+        keys_dir: PathBuf,
         account_skey: scc::SecretKey,
         account_pkey: scc::PublicKey,
         network_skey: pbc::SecretKey,
@@ -180,12 +203,14 @@ impl UnsealedAccountService {
         debug!("Opened database: epoch={}", epoch);
         let resend_tx = Interval::new(clock::now(), RESEND_TX_INTERVAL);
         let expire_locked_inputs = Interval::new(clock::now(), CHECK_LOCKED_INPUTS);
+        let consolidate_utxos = Interval::new(clock::now(), CONSOLIDATION_INTERVAL);
         let transaction_rx = network.subscribe(&TX_TOPIC).unwrap();
 
         info!("Loaded account {}", account_pkey);
         let mut service = UnsealedAccountService {
             database_dir,
             account_dir,
+            keys_dir,
             account_skey,
             account_pkey,
             network_skey,
@@ -193,6 +218,7 @@ impl UnsealedAccountService {
             database,
             resend_tx,
             expire_locked_inputs,
+            consolidate_utxos,
             snowball,
             max_inputs_in_tx,
             network,
@@ -212,7 +238,7 @@ impl UnsealedAccountService {
         recipient: &scc::PublicKey,
         amount: i64,
         payment_fee: i64,
-        comment: String,
+        data: PaymentPayloadData,
         with_certificate: bool,
     ) -> Result<TransactionInfo, Error> {
         let payment_balance = self.database.balance().payment;
@@ -224,7 +250,6 @@ impl UnsealedAccountService {
             .into());
         }
 
-        let data = PaymentPayloadData::Comment(comment);
         let unspent_iter = self.database.available_payment_outputs();
         let sender = if with_certificate {
             Some(&self.account_skey)
@@ -298,26 +323,314 @@ impl UnsealedAccountService {
             .map(|(timestamp, e)| match e {
                 LogEntry::Incoming {
                     output: ref output_value,
+                    ..
                 } => {
                     let mut output_info = output_value.to_info(self.database.epoch());
                     // Update information about change.
                     if let OutputInfo::Payment(ref mut p) = output_info {
                         p.is_change = self.database.is_known_changes(p.output_hash);
                     }
+                    self.annotate_output(&mut output_info, false);
 
                     LogEntryInfo::Incoming {
                         timestamp,
                         output: output_info,
                     }
                 }
-                LogEntry::Outgoing { ref tx } => LogEntryInfo::Outgoing {
-                    timestamp,
-                    tx: tx.to_info(self.database.epoch()),
-                },
+                LogEntry::Outgoing { ref tx } => {
+                    let mut tx_info = tx.to_info(self.database.epoch());
+                    tx_info.label = self.database.label(&tx_info.tx_hash).cloned();
+                    for output_info in tx_info.outputs.iter_mut() {
+                        self.annotate_output(output_info, true);
+                    }
+                    LogEntryInfo::Outgoing {
+                        timestamp,
+                        tx: tx_info,
+                    }
+                }
             })
             .collect()
     }
 
+    /// Fill contact names and labels from the address book.
+    /// The recipient of incoming outputs is this account, so only outgoing outputs get contacts.
+    fn annotate_output(&self, output_info: &mut OutputInfo, is_outgoing: bool) {
+        match output_info {
+            OutputInfo::Payment(p) => {
+                if is_outgoing {
+                    p.contact = self.database.contact_name(&p.recipient).cloned();
+                }
+                p.label = self.database.label(&p.output_hash).cloned();
+            }
+            OutputInfo::PublicPayment(p) => {
+                if is_outgoing {
+                    p.contact = self.database.contact_name(&p.recipient).cloned();
+                }
+                p.label = self.database.label(&p.output_hash).cloned();
+            }
+            OutputInfo::Staked(_) => {}
+        }
+    }
+
+    /// Add a new contact to the address book or update the existing one.
+    fn add_contact(
+        &mut self,
+        name: String,
+        account_pkey: scc::PublicKey,
+        notes: String,
+    ) -> Result<ContactInfo, Error> {
+        let is_valid = name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        // Names must never be confused with addresses.
+        if name.is_empty() || !is_valid || scc::PublicKey::from_str(&name).is_ok() {
+            return Err(WalletError::InvalidContactName(name).into());
+        }
+        let contact = ContactValue {
+            account_pkey,
+            notes,
+        };
+        self.database.add_contact(name.clone(), contact.clone())?;
+        info!("Added contact: name={}, account={}", name, account_pkey);
+        Ok(contact.to_info(name))
+    }
+
+    /// Remove a contact from the address book.
+    fn remove_contact(&mut self, name: String) -> Result<ContactInfo, Error> {
+        match self.database.remove_contact(&name)? {
+            Some(contact) => {
+                info!("Removed contact: name={}", name);
+                Ok(contact.to_info(name))
+            }
+            None => Err(WalletError::ContactNotFound(name).into()),
+        }
+    }
+
+    /// Find a contact in the address book.
+    fn contact_info(&self, name: String) -> Result<ContactInfo, Error> {
+        match self.database.contact(&name) {
+            Some(contact) => Ok(contact.to_info(name)),
+            None => Err(WalletError::ContactNotFound(name).into()),
+        }
+    }
+
+    /// Return the whole address book.
+    fn contacts_info(&self) -> Vec<ContactInfo> {
+        self.database
+            .iter_contacts()
+            .map(|(name, contact)| contact.to_info(name.clone()))
+            .collect()
+    }
+
+    /// Attach a label to a transaction or an output.
+    fn set_label(&mut self, hash: Hash, label: String) -> Result<Option<String>, Error> {
+        if label.len() > MAX_LABEL_LEN {
+            return Err(WalletError::LabelTooLong(label.len(), MAX_LABEL_LEN).into());
+        }
+        if self.database.tx_entry(hash).is_none() && self.database.output_entry(hash).is_none() {
+            return Err(WalletError::UnknownHash(hash).into());
+        }
+        self.database.set_label(hash, label)?;
+        Ok(self.database.label(&hash).cloned())
+    }
+
+    /// Change the policy of background UTXO consolidation.
+    fn set_consolidation_policy(&mut self, policy: ConsolidationPolicy) -> Result<(), Error> {
+        consolidation::validate_policy(&policy)?;
+        info!("Changed consolidation policy: policy={:?}", policy);
+        self.database.set_consolidation_policy(policy)?;
+        Ok(())
+    }
+
+    /// Merge dust UTXOs according to the consolidation policy.
+    /// Fees are fixed per output, so there is no cheaper time to merge;
+    /// consolidation runs when the account is idle.
+    fn consolidate_utxos(&mut self) {
+        let policy = self.database.consolidation_policy().clone();
+        if !policy.enabled
+            || !self.database.is_synchronized()
+            || self.snowball.is_some()
+            || self.database.mixed_stake().is_some()
+        {
+            return;
+        }
+        // Wait until all our transactions are settled.
+        if self.database.pending_txs().next().is_some() {
+            return;
+        }
+
+        let max_inputs_in_tx = if policy.use_snowball {
+            snowball::MAX_UTXOS
+        } else {
+            self.max_inputs_in_tx
+        };
+        let unspent_iter = self
+            .database
+            .available_payment_outputs()
+            .map(|(output, amount)| ((output, amount), amount));
+        let (inputs, amount) =
+            match consolidation::find_dust(unspent_iter, &policy, max_inputs_in_tx) {
+                Some(dust) => dust,
+                None => return,
+            };
+
+        info!(
+            "Consolidating UTXOs: inputs={}, amount={}, snowball={}",
+            inputs.len(),
+            amount,
+            policy.use_snowball
+        );
+        let result = if policy.use_snowball {
+            self.consolidate_via_snowball(inputs, amount, policy.payment_fee)
+        } else {
+            self.consolidate_via_payment(inputs, amount, policy.payment_fee)
+        };
+        if let Err(e) = result {
+            error!("Failed to consolidate UTXOs: error={}", e);
+        }
+    }
+
+    fn consolidate_via_payment(
+        &mut self,
+        inputs: Vec<(PaymentOutput, i64)>,
+        amount: i64,
+        payment_fee: i64,
+    ) -> Result<(), Error> {
+        let max_inputs_in_tx = inputs.len();
+        let data = PaymentPayloadData::Comment(CONSOLIDATION_COMMENT.to_string());
+        let (inputs, outputs, gamma, extended_outputs, fee) = create_payment_transaction(
+            None,
+            &self.account_pkey,
+            &self.account_pkey,
+            inputs.into_iter(),
+            amount,
+            payment_fee,
+            TransactionType::Regular(data),
+            max_inputs_in_tx,
+        )?;
+        let tx = PaymentTransaction::new(&self.account_skey, &inputs, &outputs, &gamma, fee)?;
+        let tx_value = TransactionValue::new_payment(tx, extended_outputs);
+        self.send_and_log_transaction(tx_value)?;
+        Ok(())
+    }
+
+    fn consolidate_via_snowball(
+        &mut self,
+        inputs: Vec<(PaymentOutput, i64)>,
+        amount: i64,
+        payment_fee: i64,
+    ) -> Result<(), Error> {
+        let data = PaymentPayloadData::Comment(CONSOLIDATION_COMMENT.to_string());
+        let (inputs, outputs, fee) = create_snowball_transaction(
+            &self.account_pkey,
+            &self.account_pkey,
+            inputs.into_iter(),
+            amount,
+            payment_fee,
+            data,
+            snowball::MAX_UTXOS,
+        )?;
+        for (input, _) in &inputs {
+            self.database.lock_input(&input);
+        }
+        let snowball = Snowball::new(
+            self.account_skey.clone(),
+            self.account_pkey.clone(),
+            self.network_pkey.clone(),
+            self.network.clone(),
+            self.database.facilitators(),
+            inputs,
+            outputs,
+            fee,
+            0,
+            Duration::from_secs(0),
+        );
+        // Nobody waits for the result, status is reported via notifications.
+        let (tx, _rx) = oneshot::channel();
+        self.notify(AccountNotification::SnowballStatus(snowball.state()));
+        self.snowball = (snowball, tx).into();
+        Ok(())
+    }
+
+    /// Create a new invoice.
+    fn create_invoice(
+        &mut self,
+        amount: i64,
+        comment: String,
+        expires: Option<Timestamp>,
+    ) -> Result<InvoiceInfo, Error> {
+        if amount <= 0 {
+            return Err(WalletError::InvalidInvoiceAmount(amount).into());
+        }
+        let data = if comment.is_empty() {
+            PaymentPayloadData::ContentHash(Hash::random())
+        } else {
+            PaymentPayloadData::Comment(comment)
+        };
+        data.validate()?;
+        let invoice_id = Hash::digest(&data);
+        if self.database.invoice(&invoice_id).is_some() {
+            return Err(WalletError::InvoiceExists(invoice_id).into());
+        }
+        let now = Timestamp::now();
+        let invoice = InvoiceValue {
+            amount,
+            data,
+            created: now,
+            expires,
+            paid: None,
+        };
+        let invoice_info = invoice.to_info(invoice_id, &self.account_pkey, now);
+        self.database.put_invoice(invoice_id, invoice)?;
+        info!(
+            "Created invoice: invoice={}, amount={}",
+            invoice_id, invoice_info.amount
+        );
+        Ok(invoice_info)
+    }
+
+    fn invoices_info(&self) -> Vec<InvoiceInfo> {
+        let now = Timestamp::now();
+        let mut invoices: Vec<InvoiceInfo> = self
+            .database
+            .iter_invoices()
+            .map(|(invoice_id, invoice)| invoice.to_info(*invoice_id, &self.account_pkey, now))
+            .collect();
+        invoices.sort_by_key(|invoice| invoice.created);
+        invoices
+    }
+
+    /// Pay a payment request.
+    fn pay_invoice(
+        &mut self,
+        uri: String,
+        payment_fee: i64,
+        with_certificate: bool,
+    ) -> Result<TransactionInfo, Error> {
+        let request = PaymentRequest::from_str(&uri)?;
+        if let Some(expires) = request.expires {
+            if Timestamp::now() > expires {
+                return Err(WalletError::PaymentRequestExpired(expires).into());
+            }
+        }
+        self.payment(
+            &request.recipient,
+            request.amount,
+            payment_fee,
+            request.data,
+            with_certificate,
+        )
+    }
+
+    fn notify_paid_invoices(&mut self) {
+        let now = Timestamp::now();
+        for invoice_id in self.database.take_paid_invoices() {
+            let invoice = self.database.invoice(&invoice_id).expect("invoice exists");
+            let invoice_info = invoice.to_info(invoice_id, &self.account_pkey, now);
+            self.notify(AccountNotification::InvoicePaid(invoice_info));
+        }
+    }
+
     /// Send money using value shuffle.
     fn secure_payment(
         &mut self,
@@ -325,8 +638,11 @@ impl UnsealedAccountService {
         amount: i64,
         payment_fee: i64,
         comment: String,
+        min_participants: u32,
+        max_wait: Duration,
     ) -> Result<Snowball, Error> {
-        if self.snowball.is_some() {
+        // The output of a secure stake must be staked first.
+        if self.snowball.is_some() || self.database.mixed_stake().is_some() {
             return Err(WalletError::SnowballBusy.into());
         }
         let payment_balance = self.database.balance().payment;
@@ -360,10 +676,12 @@ impl UnsealedAccountService {
             self.account_pkey.clone(),
             self.network_pkey.clone(),
             self.network.clone(),
-            self.database.facilitator_pkey().clone(),
+            self.database.facilitators(),
             inputs,
             outputs,
             fee,
+            min_participants,
+            max_wait,
         );
 
         metrics::WALLET_CREATEAD_SECURE_PAYMENTS
@@ -413,12 +731,14 @@ impl UnsealedAccountService {
             .into());
         }
 
+        let commission = self.commission(&network_pkey);
         let unspent_iter = self.database.available_payment_outputs();
         let (tx, outputs) = create_staking_transaction(
             &self.account_skey,
             &self.account_pkey,
             &network_pkey,
-            &network_skey,
+            Some(&network_skey),
+            commission,
             unspent_iter,
             amount,
             payment_fee,
@@ -431,6 +751,71 @@ impl UnsealedAccountService {
         Ok(tx_info)
     }
 
+    /// Shuffle coins using Snowball before staking them.
+    /// The stake transaction is sent when the Snowball transaction is committed.
+    fn secure_stake(&mut self, amount: i64, payment_fee: i64) -> Result<Snowball, Error> {
+        if amount <= payment_fee {
+            return Err(WalletError::AmountTooSmall(payment_fee, amount).into());
+        }
+        let account_pkey = self.account_pkey.clone();
+        // The Snowball output also pays the fee of the stake transaction.
+        let snowball = self.secure_payment(
+            &account_pkey,
+            amount + payment_fee + STAKE_FEE,
+            payment_fee,
+            SECURE_STAKE_COMMENT.to_string(),
+            0,
+            Duration::from_secs(0),
+        )?;
+        let mixed_stake = MixedStakeValue {
+            amount,
+            payment_fee,
+            output: None,
+        };
+        if let Err(e) = self.database.set_mixed_stake(Some(mixed_stake)) {
+            for input_hash in snowball.inputs() {
+                self.database.unlock_input(&input_hash);
+            }
+            return Err(e);
+        }
+        Ok(snowball)
+    }
+
+    /// Stake the output received from Snowball, once it is committed.
+    fn stake_mixed_output(&mut self) -> Result<(), Error> {
+        let mixed_stake = match self.database.mixed_stake() {
+            Some(mixed_stake) if mixed_stake.output.is_some() => mixed_stake.clone(),
+            _ => return Ok(()),
+        };
+        let unspent = match self.database.mixed_stake_output() {
+            Some(unspent) => unspent,
+            None => return Ok(()), // Not committed yet.
+        };
+        let output_hash = Hash::digest(&unspent.0);
+        let commission = self.commission(&self.network_pkey);
+        // Spend only the shuffled output, so the stake isn't linked to other coins.
+        let (tx, outputs) = create_staking_transaction(
+            &self.account_skey,
+            &self.account_pkey,
+            &self.network_pkey,
+            Some(&self.network_skey),
+            commission,
+            std::iter::once(unspent),
+            mixed_stake.amount,
+            mixed_stake.payment_fee,
+            STAKE_FEE,
+            self.max_inputs_in_tx,
+        )?;
+        let tx_value = TransactionValue::new_stake(tx, outputs);
+        let tx_info = self.send_and_log_transaction(tx_value)?;
+        self.database.set_mixed_stake(None)?;
+        info!(
+            "Staked Snowball output: output={}, tx={}",
+            output_hash, tx_info.tx_hash
+        );
+        Ok(())
+    }
+
     /// Stake money into the escrow, for remote node.
     fn stake_remote(&mut self, amount: i64, payment_fee: i64) -> Result<TransactionInfo, Error> {
         let network_pkey_file = self.account_dir.join("network.pkey");
@@ -462,12 +847,15 @@ impl UnsealedAccountService {
             .into());
         }
 
-        let unspent_iter = self.database.available_stake_outputs();
+        let unspent_iter = self
+            .database
+            .available_stake_outputs()
+            .filter(|o| !o.delegated);
         let (tx, outputs) = create_unstaking_transaction(
             &self.account_skey,
             &self.account_pkey,
             &self.network_pkey,
-            &self.network_skey,
+            Some(&self.network_skey),
             unspent_iter,
             amount,
             payment_fee,
@@ -482,7 +870,11 @@ impl UnsealedAccountService {
     /// Unstake all of the money from the escrow.
     fn unstake_all(&mut self, payment_fee: i64) -> Result<TransactionInfo, Error> {
         let mut amount: i64 = 0;
-        let mut outputs: Vec<_> = self.database.available_stake_outputs().collect();
+        let mut outputs: Vec<_> = self
+            .database
+            .available_stake_outputs()
+            .filter(|o| !o.delegated)
+            .collect();
         outputs.sort_by_key(|o| o.amount);
         if outputs.len() > self.max_inputs_in_tx {
             warn!(
@@ -501,6 +893,195 @@ impl UnsealedAccountService {
         self.unstake(amount, payment_fee)
     }
 
+    /// Re-bind all stakes to a new network key.
+    fn rotate_validator_key(&mut self) -> Result<AccountResponse, Error> {
+        // The node picks up the new key pair from its keys directory.
+        let (new_network_skey, new_network_pkey) = load_next_network_keys(&self.keys_dir)?;
+        if new_network_pkey == self.network_pkey {
+            return Err(WalletError::SameNetworkKey(new_network_pkey).into());
+        }
+
+        let mut inputs: Vec<Output> = Vec::new();
+        let mut amount: i64 = 0;
+        let stakes: Vec<(Hash, StakeOutput)> = self
+            .database
+            .validator_stake_outputs(&self.network_pkey)
+            .collect();
+        for (hash, output) in stakes {
+            if self.database.is_input_locked(&hash).is_some() {
+                return Err(WalletError::StakeIsPending(hash).into());
+            }
+            amount += output.amount;
+            inputs.push(output.into());
+        }
+        if inputs.is_empty() {
+            return Err(WalletError::NoValidatorStakes(self.network_pkey).into());
+        }
+
+        let tx = ValidatorKeyRotationTransaction::new(
+            &self.account_skey,
+            &self.network_skey,
+            &new_network_skey,
+            &new_network_pkey,
+            &inputs,
+        )?;
+        let tx_hash = Hash::digest(&tx);
+        info!(
+            "Created validator key rotation transaction: hash={}, old_validator={}, new_validator={}, amount={}",
+            tx_hash, self.network_pkey, new_network_pkey, amount
+        );
+        for input in &tx.txins {
+            self.database.lock_input(input);
+        }
+        self.send_transaction(tx.into())?;
+        Ok(AccountResponse::ValidatorKeyRotated {
+            tx_hash,
+            old_network_pkey: self.network_pkey,
+            new_network_pkey,
+            amount,
+        })
+    }
+
+    /// Commission declared on the most recent own stake of validator, in basis points.
+    fn commission(&self, network_pkey: &pbc::PublicKey) -> u32 {
+        self.database
+            .validator_commission(network_pkey)
+            .unwrap_or(0)
+    }
+
+    /// Delegate money to a third-party validator.
+    fn delegate(
+        &mut self,
+        validator_pkey: &pbc::PublicKey,
+        amount: i64,
+        payment_fee: i64,
+    ) -> Result<TransactionInfo, Error> {
+        let payment_balance = self.database.balance().payment;
+        if amount > payment_balance.available {
+            return Err(WalletError::NoEnoughToPay(
+                payment_balance.current,
+                payment_balance.available,
+            )
+            .into());
+        }
+
+        let unspent_iter = self.database.available_payment_outputs();
+        let (tx, outputs) = create_staking_transaction(
+            &self.account_skey,
+            &self.account_pkey,
+            validator_pkey,
+            None,
+            0,
+            unspent_iter,
+            amount,
+            payment_fee,
+            STAKE_FEE,
+            self.max_inputs_in_tx,
+        )?;
+
+        let tx_value = TransactionValue::new_stake(tx.clone(), outputs);
+        let tx_info = self.send_and_log_transaction(tx_value)?;
+        Ok(tx_info)
+    }
+
+    /// Withdraw money delegated to a third-party validator.
+    /// NOTE: amount must include PAYMENT_FEE.
+    fn undelegate(
+        &mut self,
+        validator_pkey: &pbc::PublicKey,
+        amount: i64,
+        payment_fee: i64,
+    ) -> Result<TransactionInfo, Error> {
+        let delegated: Vec<StakeOutput> = self
+            .database
+            .validator_stake_outputs(validator_pkey)
+            .filter(|(_hash, output)| output.delegated)
+            .map(|(_hash, output)| output)
+            .collect();
+        let current: i64 = delegated.iter().map(|o| o.amount).sum();
+        let unspent: Vec<StakeOutput> = self
+            .database
+            .available_stake_outputs()
+            .filter(|o| o.delegated && o.validator == *validator_pkey)
+            .collect();
+        let available: i64 = unspent.iter().map(|o| o.amount).sum();
+        if amount > available {
+            return Err(WalletError::NoEnoughToStake(current, available).into());
+        }
+
+        let (tx, outputs) = create_unstaking_transaction(
+            &self.account_skey,
+            &self.account_pkey,
+            validator_pkey,
+            None,
+            unspent.into_iter(),
+            amount,
+            payment_fee,
+            STAKE_FEE,
+            self.max_inputs_in_tx,
+        )?;
+        let tx_value = TransactionValue::new_stake(tx.clone(), outputs);
+        let tx_info = self.send_and_log_transaction(tx_value)?;
+        Ok(tx_info)
+    }
+
+    /// Re-stake all own stakes with a new commission.
+    fn set_commission(&mut self, commission: u32) -> Result<AccountResponse, Error> {
+        if commission > MAX_COMMISSION {
+            return Err(WalletError::InvalidCommission(MAX_COMMISSION, commission).into());
+        }
+
+        let mut inputs: Vec<Output> = Vec::new();
+        let mut amount: i64 = 0;
+        let mut maintenance_epoch: u64 = 0;
+        let stakes: Vec<(Hash, StakeOutput)> = self
+            .database
+            .validator_stake_outputs(&self.network_pkey)
+            .filter(|(_hash, output)| !output.delegated)
+            .collect();
+        for (hash, output) in stakes {
+            if self.database.is_input_locked(&hash).is_some() {
+                return Err(WalletError::StakeIsPending(hash).into());
+            }
+            amount += output.amount;
+            // Keep scheduled maintenance.
+            maintenance_epoch = std::cmp::max(maintenance_epoch, output.maintenance_epoch);
+            inputs.push(output.into());
+        }
+        if inputs.is_empty() {
+            return Err(WalletError::NoValidatorStakes(self.network_pkey).into());
+        }
+
+        let output = StakeOutput::new_with_maintenance(
+            &self.account_pkey,
+            &self.network_skey,
+            &self.network_pkey,
+            amount,
+            commission,
+            maintenance_epoch,
+        )?;
+        let tx = RestakeTransaction::new(
+            &self.network_skey,
+            &self.network_pkey,
+            &inputs,
+            &[output.into()],
+        )?;
+        let tx_hash = Hash::digest(&tx);
+        info!(
+            "Created commission change transaction: hash={}, validator={}, commission={}, amount={}",
+            tx_hash, self.network_pkey, commission, amount
+        );
+        for input in &tx.txins {
+            self.database.lock_input(input);
+        }
+        self.send_transaction(tx.into())?;
+        Ok(AccountResponse::CommissionChanged {
+            tx_hash,
+            commission,
+            amount,
+        })
+    }
+
     /// Cloak all available public outputs.
     fn cloak_all(&mut self, fee: i64) -> Result<TransactionInfo, Error> {
         // Secret key to sign the transaction.
@@ -622,6 +1203,20 @@ impl UnsealedAccountService {
         Ok(AccountRecovery { recovery })
     }
 
+    /// Return encrypted backup.
+    fn export_backup(&mut self, password: String) -> Result<AccountBackup, Error> {
+        let backup = Backup {
+            version: BACKUP_VERSION,
+            account_skey: self.account_skey.clone(),
+            account_pkey: self.account_pkey,
+            created: Timestamp::now(),
+            entries: self.database.backup_entries(),
+        };
+        info!("Created backup: entries={}", backup.entries.len());
+        let backup = backup.encrypt(&password);
+        Ok(AccountBackup { backup })
+    }
+
     fn apply_light_micro_block(
         &mut self,
         header: MicroBlockHeader,
@@ -677,6 +1272,7 @@ impl UnsealedAccountService {
             outputs.iter(),
             &self.account_pkey,
             &self.account_skey,
+            &self.network_pkey,
         );
 
         self.notify_status();
@@ -684,6 +1280,10 @@ impl UnsealedAccountService {
         if transaction_statuses.len() > 0 {
             self.notify_balance_changed(self.database.balance());
         }
+        self.notify_paid_invoices();
+        if let Err(e) = self.stake_mixed_output() {
+            error!("Failed to stake Snowball output: {}", e);
+        }
         Ok(())
     }
 
@@ -743,16 +1343,21 @@ impl UnsealedAccountService {
             validators,
             &self.account_pkey,
             &self.account_skey,
+            &self.network_pkey,
         );
 
         if let Some((ref mut snowball, _)) = &mut self.snowball {
-            snowball.change_facilitator(self.database.facilitator_pkey().clone());
+            snowball.change_facilitator(self.database.facilitators());
         }
         self.notify_status();
         self.on_tx_statuses_changed(&transaction_statuses);
         if transaction_statuses.len() > 0 {
             self.notify_balance_changed(self.database.balance());
         }
+        self.notify_paid_invoices();
+        if let Err(e) = self.stake_mixed_output() {
+            error!("Failed to stake Snowball output: {}", e);
+        }
         Ok(())
     }
 
@@ -876,31 +1481,29 @@ impl UnsealedAccountService {
         trace!("Handle check pending utxo transactions");
         let pending = self.database.expire_locked_inputs(PENDING_UTXO_TIME);
         let mut balance_unlocked = false;
+        let mut snowball_expired = false;
         for hash in pending {
             trace!("Found outdated pending utxo = {}", hash);
             balance_unlocked = true;
             if let Some((snowball, _)) = &self.snowball {
-                if !snowball.is_my_input(hash) {
-                    continue;
+                if snowball.is_my_input(hash) {
+                    info!(
+                        "Some outputs of snowball are now outdated: snowball_session = {}",
+                        hash
+                    );
+                    snowball_expired = true;
                 }
-                // Terminate Snowball session.
-                error!("Snowball timed out");
-                let (_snowball, tx) = self.snowball.take().unwrap();
-                self.notify(AccountNotification::SnowballStatus(SnowballState::Failed));
-                let response = AccountResponse::Error {
-                    error: "Snowball timed out".to_string(),
-                };
-                let _ = tx.send(response);
-
-                info!(
-                    "Some outputs of snowball are now outdated: snowball_session = {}",
-                    hash
-                );
-                warn!("Resetting Snowball on timeout.");
-                self.snowball = None;
             }
         }
 
+        if snowball_expired {
+            // Terminate Snowball session.
+            error!("Snowball timed out");
+            warn!("Resetting Snowball on timeout.");
+            let (snowball, tx) = self.snowball.take().unwrap();
+            self.on_snowball_failed(snowball, tx, "Snowball timed out".to_string());
+        }
+
         if !balance_unlocked {
             return;
         }
@@ -910,6 +1513,58 @@ impl UnsealedAccountService {
         self.notify_balance_changed(balance);
     }
 
+    /// Save the log of a failed Snowball session and retry it if possible.
+    /// Sessions are retried only while all inputs are unspent,
+    /// otherwise the transaction can't be valid anymore.
+    fn on_snowball_failed(
+        &mut self,
+        snowball: Snowball,
+        response_sender: oneshot::Sender<AccountResponse>,
+        error: String,
+    ) {
+        let log = snowball.session_log(Some(error.clone()));
+        if let Err(e) = self.database.push_snowball_session(&log) {
+            error!("Failed to save Snowball session: error={}", e);
+        }
+        self.notify(AccountNotification::SnowballStatus(SnowballState::Failed));
+
+        let spent: Vec<Hash> = snowball
+            .inputs()
+            .into_iter()
+            .filter(|input_hash| !self.database.is_unspent(input_hash))
+            .collect();
+        if !spent.is_empty() {
+            warn!(
+                "Not retrying Snowball, inputs were spent: inputs={:?}",
+                spent
+            );
+        } else if snowball.attempt() < SNOWBALL_MAX_ATTEMPTS {
+            warn!(
+                "Retrying Snowball: attempt={}, max_attempts={}",
+                snowball.attempt() + 1,
+                SNOWBALL_MAX_ATTEMPTS
+            );
+            for input_hash in snowball.inputs() {
+                self.database.lock_input(&input_hash);
+            }
+            let snowball = snowball.retry(self.database.facilitators());
+            self.notify(AccountNotification::SnowballStatus(snowball.state()));
+            self.snowball = (snowball, response_sender).into();
+            // Poll the new session.
+            task::current().notify();
+            return;
+        }
+
+        for input_hash in snowball.inputs() {
+            self.database.unlock_input(&input_hash);
+        }
+        if let Err(e) = self.database.set_mixed_stake(None) {
+            error!("Failed to cancel secure stake: error={}", e);
+        }
+        let response = AccountResponse::Error { error };
+        let _ = response_sender.send(response);
+    }
+
     fn notify_balance_changed(&mut self, balance: AccountBalance) {
         debug!("Balance changed");
         let account = String::from(&self.account_pkey);
@@ -1019,6 +1674,14 @@ impl Future for UnsealedAccountService {
             }
         }
 
+        loop {
+            match self.consolidate_utxos.poll().expect("no errors in timers") {
+                Async::Ready(Some(_t)) => self.consolidate_utxos(),
+                Async::NotReady => break,
+                e => panic!("Error in handling consolidation timer = {:?}", e),
+            }
+        }
+
         if let Some((mut snowball, response_sender)) = mem::replace(&mut self.snowball, None) {
             let state = snowball.state();
             match snowball.poll() {
@@ -1027,6 +1690,21 @@ impl Future for UnsealedAccountService {
                     is_leader,
                     outputs,
                 }))) => {
+                    let log = snowball.session_log(None);
+                    if let Err(e) = self.database.push_snowball_session(&log) {
+                        error!("Failed to save Snowball session: error={}", e);
+                    }
+                    if let Some(mixed_stake) = self.database.mixed_stake() {
+                        let mut mixed_stake = mixed_stake.clone();
+                        mixed_stake.output = mixed_stake.find_output(&outputs, STAKE_FEE);
+                        if mixed_stake.output.is_none() {
+                            error!("Snowball output for staking not found");
+                        }
+                        let mixed_stake = mixed_stake.output.map(|_| mixed_stake);
+                        if let Err(e) = self.database.set_mixed_stake(mixed_stake) {
+                            error!("Failed to save secure stake: error={}", e);
+                        }
+                    }
                     self.notify(AccountNotification::SnowballStatus(
                         SnowballState::Succeeded,
                     ));
@@ -1044,16 +1722,9 @@ impl Future for UnsealedAccountService {
                 Ok(Async::Ready(None)) => {
                     return Ok(Async::Ready(UnsealedAccountResult::Terminated))
                 } // Shutdown.
-                Err((error, inputs)) => {
+                Err((error, _inputs)) => {
                     error!("Snowball failed: error={}", error);
-                    self.notify(AccountNotification::SnowballStatus(SnowballState::Failed));
-                    for (input_hash, _input) in inputs {
-                        self.database.unlock_input(&input_hash);
-                    }
-                    let response = AccountResponse::Error {
-                        error: error.to_string(),
-                    };
-                    let _ = response_sender.send(response);
+                    self.on_snowball_failed(snowball, response_sender, error.to_string());
                 }
                 Ok(Async::NotReady) => {
                     if state != snowball.state() {
@@ -1088,9 +1759,17 @@ impl Future for UnsealedAccountService {
                                 payment_fee,
                                 comment,
                                 with_certificate,
-                            } => self
-                                .payment(&recipient, amount, payment_fee, comment, with_certificate)
-                                .into(),
+                            } => {
+                                let data = PaymentPayloadData::Comment(comment);
+                                self.payment(
+                                    &recipient,
+                                    amount,
+                                    payment_fee,
+                                    data,
+                                    with_certificate,
+                                )
+                                .into()
+                            }
                             AccountRequest::PublicPayment {
                                 recipient,
                                 amount,
@@ -1107,6 +1786,20 @@ impl Future for UnsealedAccountService {
                                 amount,
                                 payment_fee,
                             } => self.stake_remote(amount, payment_fee).into(),
+                            AccountRequest::SecureStake {
+                                amount,
+                                payment_fee,
+                            } => match self.secure_stake(amount, payment_fee) {
+                                Ok(snowball) => {
+                                    let state = snowball.state();
+                                    self.notify(AccountNotification::SnowballStatus(state));
+                                    self.snowball = (snowball, tx).into();
+                                    continue;
+                                }
+                                Err(e) => AccountResponse::Error {
+                                    error: format!("{}", e),
+                                },
+                            },
                             AccountRequest::Unstake {
                                 amount,
                                 payment_fee,
@@ -1114,6 +1807,32 @@ impl Future for UnsealedAccountService {
                             AccountRequest::UnstakeAll { payment_fee } => {
                                 self.unstake_all(payment_fee).into()
                             }
+                            AccountRequest::RotateValidatorKey {} => {
+                                match self.rotate_validator_key() {
+                                    Ok(response) => response,
+                                    Err(e) => AccountResponse::Error {
+                                        error: format!("{}", e),
+                                    },
+                                }
+                            }
+                            AccountRequest::Delegate {
+                                validator,
+                                amount,
+                                payment_fee,
+                            } => self.delegate(&validator, amount, payment_fee).into(),
+                            AccountRequest::Undelegate {
+                                validator,
+                                amount,
+                                payment_fee,
+                            } => self.undelegate(&validator, amount, payment_fee).into(),
+                            AccountRequest::SetCommission { commission } => {
+                                match self.set_commission(commission) {
+                                    Ok(response) => response,
+                                    Err(e) => AccountResponse::Error {
+                                        error: format!("{}", e),
+                                    },
+                                }
+                            }
                             AccountRequest::CloakAll { payment_fee } => {
                                 self.cloak_all(payment_fee).into()
                             }
@@ -1141,12 +1860,20 @@ impl Future for UnsealedAccountService {
                                         OutputValue::Stake(s) => {
                                             stakes.push(s.to_info(self.database.epoch()))
                                         }
-                                        OutputValue::Payment(p) => payments.push(
-                                            p.to_info(self.database.is_input_locked(&output_hash)),
-                                        ),
-                                        OutputValue::PublicPayment(p) => public_payments.push(
-                                            p.to_info(self.database.is_input_locked(&output_hash)),
-                                        ),
+                                        OutputValue::Payment(p) => {
+                                            let mut info = p.to_info(
+                                                self.database.is_input_locked(&output_hash),
+                                            );
+                                            info.label = self.database.label(&output_hash).cloned();
+                                            payments.push(info)
+                                        }
+                                        OutputValue::PublicPayment(p) => {
+                                            let mut info = p.to_info(
+                                                self.database.is_input_locked(&output_hash),
+                                            );
+                                            info.label = self.database.label(&output_hash).cloned();
+                                            public_payments.push(info)
+                                        }
                                     }
                                 }
                                 AccountResponse::UnspentInfo {
@@ -1167,20 +1894,132 @@ impl Future for UnsealedAccountService {
                                     },
                                 }
                             }
+                            AccountRequest::ExportBackup { password } => {
+                                match self.export_backup(password) {
+                                    Ok(backup) => AccountResponse::Backup(backup),
+                                    Err(e) => AccountResponse::Error {
+                                        error: format!("{}", e),
+                                    },
+                                }
+                            }
                             AccountRequest::GetRecovery {} => match self.get_recovery() {
                                 Ok(recovery) => AccountResponse::Recovery(recovery),
                                 Err(e) => AccountResponse::Error {
                                     error: format!("{}", e),
                                 },
                             },
+                            AccountRequest::AddContact {
+                                name,
+                                account_pkey,
+                                notes,
+                            } => match self.add_contact(name, account_pkey, notes) {
+                                Ok(contact) => AccountResponse::ContactInfo(contact),
+                                Err(e) => AccountResponse::Error {
+                                    error: format!("{}", e),
+                                },
+                            },
+                            AccountRequest::RemoveContact { name } => {
+                                match self.remove_contact(name) {
+                                    Ok(contact) => AccountResponse::ContactRemoved(contact),
+                                    Err(e) => AccountResponse::Error {
+                                        error: format!("{}", e),
+                                    },
+                                }
+                            }
+                            AccountRequest::ContactInfo { name } => match self.contact_info(name) {
+                                Ok(contact) => AccountResponse::ContactInfo(contact),
+                                Err(e) => AccountResponse::Error {
+                                    error: format!("{}", e),
+                                },
+                            },
+                            AccountRequest::ContactsInfo {} => AccountResponse::ContactsInfo {
+                                contacts: self.contacts_info(),
+                            },
+                            AccountRequest::SetLabel { hash, label } => {
+                                match self.set_label(hash, label) {
+                                    Ok(label) => AccountResponse::LabelChanged { hash, label },
+                                    Err(e) => AccountResponse::Error {
+                                        error: format!("{}", e),
+                                    },
+                                }
+                            }
+                            AccountRequest::CreateInvoice {
+                                amount,
+                                comment,
+                                expires,
+                            } => match self.create_invoice(amount, comment, expires) {
+                                Ok(invoice_info) => AccountResponse::InvoiceCreated(invoice_info),
+                                Err(e) => AccountResponse::Error {
+                                    error: format!("{}", e),
+                                },
+                            },
+                            AccountRequest::InvoicesInfo {} => AccountResponse::InvoicesInfo {
+                                invoices: self.invoices_info(),
+                            },
+                            AccountRequest::SnowballHistory {
+                                starting_from,
+                                limit,
+                            } => AccountResponse::SnowballHistory {
+                                sessions: self
+                                    .database
+                                    .iter_snowball_sessions(starting_from, limit)
+                                    .collect(),
+                            },
+                            AccountRequest::SetConsolidationPolicy { policy } => {
+                                match self.set_consolidation_policy(policy) {
+                                    Ok(()) => AccountResponse::ConsolidationPolicyInfo(
+                                        self.database.consolidation_policy().clone(),
+                                    ),
+                                    Err(e) => AccountResponse::Error {
+                                        error: format!("{}", e),
+                                    },
+                                }
+                            }
+                            AccountRequest::ConsolidationPolicyInfo {} => {
+                                AccountResponse::ConsolidationPolicyInfo(
+                                    self.database.consolidation_policy().clone(),
+                                )
+                            }
+                            AccountRequest::PayInvoice {
+                                uri,
+                                payment_fee,
+                                with_certificate,
+                            } => self.pay_invoice(uri, payment_fee, with_certificate).into(),
+                            AccountRequest::ExportHistory { format } => {
+                                let mut data = Vec::new();
+                                match export::export_history(
+                                    &self.database,
+                                    &self.account_pkey,
+                                    format,
+                                    &mut data,
+                                ) {
+                                    Ok(records) => AccountResponse::HistoryExported {
+                                        format,
+                                        records,
+                                        data: String::from_utf8(data)
+                                            .expect("records are valid UTF-8"),
+                                    },
+                                    Err(e) => AccountResponse::Error {
+                                        error: format!("{}", e),
+                                    },
+                                }
+                            }
                             AccountRequest::SecurePayment {
                                 recipient,
                                 amount,
                                 payment_fee,
                                 comment,
+                                min_participants,
+                                max_wait,
                             } => {
-                                match self.secure_payment(&recipient, amount, payment_fee, comment)
-                                {
+                                match self.secure_payment(
+                                    &recipient,
+                                    amount,
+                                    payment_fee,
+                                    comment,
+                                    min_participants,
+                                    Duration::from_secs(max_wait),
+                                ) {
                                     Ok(snowball) => {
                                         let state = snowball.state();
                                         self.notify(AccountNotification::SnowballStatus(state));
@@ -1260,6 +2099,8 @@ struct SealedAccountService {
     database_dir: PathBuf,
     /// Path to account directory.
     account_dir: PathBuf,
+    /// Path to network key folder of the node.
+    keys_dir: PathBuf,
     /// Account Public Key.
     account_pkey: scc::PublicKey,
     /// Network Secret Key.
@@ -1290,6 +2131,7 @@ impl SealedAccountService {
     fn new(
         database_dir: PathBuf,
         account_dir: PathBuf,
+        keys_dir: PathBuf,
         account_pkey: scc::PublicKey,
         network_skey: pbc::SecretKey,
         network_pkey: pbc::PublicKey,
@@ -1304,6 +2146,7 @@ impl SealedAccountService {
         let mut service = SealedAccountService {
             database_dir,
             account_dir,
+            keys_dir,
             account_pkey,
             network_skey,
             network_pkey,
@@ -1419,6 +2262,7 @@ impl Future for AccountService {
                     let unsealed = UnsealedAccountService::new(
                         sealed.database_dir,
                         sealed.account_dir,
+                        sealed.keys_dir,
                         account_skey,
                         sealed.account_pkey,
                         sealed.network_skey,
@@ -1460,6 +2304,7 @@ impl Future for AccountService {
                     let sealed = SealedAccountService::new(
                         unsealed.database_dir,
                         unsealed.account_dir,
+                        unsealed.keys_dir,
                         unsealed.account_pkey,
                         unsealed.network_skey,
                         unsealed.network_pkey,
@@ -1486,6 +2331,7 @@ impl AccountService {
     fn new(
         database_dir: &Path,
         account_dir: &Path,
+        keys_dir: &Path,
         network_skey: pbc::SecretKey,
         network_pkey: pbc::PublicKey,
         network: Network,
@@ -1501,6 +2347,7 @@ impl AccountService {
         let service = SealedAccountService::new(
             database_dir.to_path_buf(),
             account_dir.to_path_buf(),
+            keys_dir.to_path_buf(),
             account_pkey,
             network_skey,
             network_pkey,
@@ -1569,6 +2416,7 @@ struct AccountHandle {
 
 pub struct WalletService {
     accounts_dir: PathBuf,
+    keys_dir: PathBuf,
     network_skey: pbc::SecretKey,
     network_pkey: pbc::PublicKey,
     network: Network,
@@ -1585,6 +2433,7 @@ pub struct WalletService {
 impl WalletService {
     pub fn new(
         accounts_dir: &Path,
+        keys_dir: &Path,
         network_skey: pbc::SecretKey,
         network_pkey: pbc::PublicKey,
         network: Network,
@@ -1601,6 +2450,7 @@ impl WalletService {
         let replication = Replication::new(peer_id, network.clone(), light, replication_rx);
         let mut service = WalletService {
             accounts_dir: accounts_dir.to_path_buf(),
+            keys_dir: keys_dir.to_path_buf(),
             network_skey,
             network_pkey,
             network,
@@ -1677,6 +2527,7 @@ impl WalletService {
         let (account_service, account) = AccountService::new(
             &account_database_dir,
             &account_dir,
+            &self.keys_dir,
             self.network_skey.clone(),
             self.network_pkey.clone(),
             self.network.clone(),
@@ -1800,6 +2651,32 @@ impl WalletService {
                 self.open_account(&account_id, false)?;
                 Ok(WalletControlResponse::AccountCreated { account_id })
             }
+            WalletControlRequest::RestoreBackup {
+                backup: AccountBackup { backup },
+                password,
+            } => {
+                let backup = Backup::decrypt(&backup, &password)?;
+                let account_pkey = backup.account_pkey;
+                // Check for duplicates.
+                for handle in self.accounts.values() {
+                    if handle.account_pkey == account_pkey {
+                        return Err(WalletError::DuplicateAccount(account_pkey).into());
+                    }
+                }
+                let account_id =
+                    self.create_account(backup.account_skey, account_pkey, &password)?;
+                let account_dir = self.accounts_dir.join(&account_id);
+                let account_database_dir = account_dir.join("lightdb");
+                if let Err(e) =
+                    LightDatabase::restore_backup(&account_database_dir, &backup.entries)
+                {
+                    fs::remove_dir_all(&account_dir)?;
+                    return Err(e);
+                }
+                info!("Restored account from backup {}", account_pkey);
+                self.open_account(&account_id, false)?;
+                Ok(WalletControlResponse::AccountCreated { account_id })
+            }
             WalletControlRequest::DeleteAccount { .. } => {
                 unreachable!("Delete account should be already processed in different routine")
             }
@@ -23,20 +23,29 @@
 
 #![allow(bare_trait_objects)]
 
-use failure::Error;
+use failure::{format_err, Error};
 use stegos_serialization::traits::*;
 
 // link protobuf dependencies
 use stegos_blockchain::protos::*;
 use stegos_crypto::protos::*;
 include!(concat!(env!("OUT_DIR"), "/protos/mod.rs"));
-use super::storage::{LogEntry, OutputValue, PaymentValue, TransactionValue};
-use crate::storage::{PublicPaymentValue, StakeValue};
-use stegos_blockchain::TransactionStatus;
+use super::storage::{LogEntry, OutputSource, OutputValue, PaymentValue, TransactionValue};
+use crate::api::{
+    ConsolidationPolicy, SnowballExclusionInfo, SnowballExclusionReason, SnowballFacilitatorFault,
+    SnowballFacilitatorInfo, SnowballPhaseInfo, SnowballSessionInfo, SnowballStatus,
+};
+use crate::backup::{Backup, BackupEntry};
+use crate::storage::{
+    ContactValue, InvoiceValue, MixedStakeValue, PublicPaymentValue, StakeValue, MACRO_BLOCK_OFFSET,
+};
 use stegos_blockchain::{
     PaymentOutput, PaymentPayloadData, PaymentTransaction, PublicPaymentOutput, StakeOutput,
 };
-use stegos_crypto::scc::{Fr, PublicKey};
+use stegos_blockchain::{Timestamp, TransactionStatus};
+use stegos_crypto::hash::Hash;
+use stegos_crypto::pbc;
+use stegos_crypto::scc::{Fr, PublicKey, SecretKey};
 
 // -----------------------------------------------------------
 
@@ -50,9 +59,27 @@ impl ProtoConvert for LogEntry {
                 enum_value.set_value(tx.into_proto());
                 msg.set_outgoing(enum_value);
             }
-            LogEntry::Incoming { output } => {
+            LogEntry::Incoming {
+                output,
+                epoch,
+                offset,
+                source,
+            } => {
                 let mut enum_value = account_log::Incoming::new();
                 enum_value.set_output(output.into_proto());
+                let source = match source {
+                    OutputSource::Unknown => 0,
+                    OutputSource::Transaction => 1,
+                    OutputSource::BlockReward => 2,
+                    OutputSource::ServiceAward => 3,
+                };
+                enum_value.set_source(source);
+                if let Some(epoch) = epoch {
+                    let mut block = account_log::EpochWithOffset::new();
+                    block.set_epoch(*epoch);
+                    block.set_offset(offset.unwrap_or(MACRO_BLOCK_OFFSET));
+                    enum_value.set_block(block);
+                }
                 msg.set_incoming(enum_value);
             }
         }
@@ -63,7 +90,29 @@ impl ProtoConvert for LogEntry {
         let payload = match proto.enum_value {
             Some(account_log::LogEntry_oneof_enum_value::incoming(ref msg)) => {
                 let output = OutputValue::from_proto(msg.get_output())?;
-                LogEntry::Incoming { output }
+                let (epoch, offset) = if msg.has_block() {
+                    let block = msg.get_block();
+                    let offset = match block.get_offset() {
+                        MACRO_BLOCK_OFFSET => None,
+                        offset => Some(offset),
+                    };
+                    (Some(block.get_epoch()), offset)
+                } else {
+                    (None, None)
+                };
+                let source = match msg.get_source() {
+                    0 => OutputSource::Unknown,
+                    1 => OutputSource::Transaction,
+                    2 => OutputSource::BlockReward,
+                    3 => OutputSource::ServiceAward,
+                    source => return Err(format_err!("Invalid output source: {}", source)),
+                };
+                LogEntry::Incoming {
+                    output,
+                    epoch,
+                    offset,
+                    source,
+                }
             }
             Some(account_log::LogEntry_oneof_enum_value::outgoing(ref msg)) => {
                 let tx = TransactionValue::from_proto(msg.get_value())?;
@@ -299,11 +348,292 @@ impl ProtoConvert for TransactionValue {
     }
 }
 
+impl ProtoConvert for ContactValue {
+    type Proto = account_log::ContactValue;
+    fn into_proto(&self) -> Self::Proto {
+        let mut msg = account_log::ContactValue::new();
+        msg.set_account_pkey(self.account_pkey.into_proto());
+        msg.set_notes(self.notes.clone());
+        msg
+    }
+
+    fn from_proto(proto: &Self::Proto) -> Result<Self, Error> {
+        let account_pkey = PublicKey::from_proto(proto.get_account_pkey())?;
+        let notes = proto.get_notes().to_string();
+        let value = ContactValue {
+            account_pkey,
+            notes,
+        };
+        Ok(value)
+    }
+}
+
+impl ProtoConvert for InvoiceValue {
+    type Proto = account_log::InvoiceValue;
+    fn into_proto(&self) -> Self::Proto {
+        let mut msg = account_log::InvoiceValue::new();
+        msg.set_amount(self.amount);
+        msg.set_data(self.data.into_proto());
+        msg.set_created(self.created.into());
+        if let Some(expires) = self.expires {
+            msg.set_expires(expires.into());
+        }
+        if let Some((utxo, paid_at)) = &self.paid {
+            msg.set_paid_utxo(utxo.into_proto());
+            msg.set_paid_at((*paid_at).into());
+        }
+        msg
+    }
+
+    fn from_proto(proto: &Self::Proto) -> Result<Self, Error> {
+        let amount = proto.get_amount();
+        let data = PaymentPayloadData::from_proto(proto.get_data())?;
+        let created: Timestamp = proto.get_created().into();
+        let expires = match proto.get_expires() {
+            0 => None,
+            expires => Some(expires.into()),
+        };
+        let paid = if proto.has_paid_utxo() {
+            let utxo = Hash::from_proto(proto.get_paid_utxo())?;
+            let paid_at: Timestamp = proto.get_paid_at().into();
+            Some((utxo, paid_at))
+        } else {
+            None
+        };
+        let value = InvoiceValue {
+            amount,
+            data,
+            created,
+            expires,
+            paid,
+        };
+        Ok(value)
+    }
+}
+
+impl ProtoConvert for ConsolidationPolicy {
+    type Proto = account_log::ConsolidationPolicy;
+    fn into_proto(&self) -> Self::Proto {
+        let mut msg = account_log::ConsolidationPolicy::new();
+        msg.set_enabled(self.enabled);
+        msg.set_min_utxos(self.min_utxos as u64);
+        msg.set_dust_amount(self.dust_amount);
+        msg.set_payment_fee(self.payment_fee);
+        msg.set_max_fee(self.max_fee);
+        msg.set_use_snowball(self.use_snowball);
+        msg
+    }
+
+    fn from_proto(proto: &Self::Proto) -> Result<Self, Error> {
+        let policy = ConsolidationPolicy {
+            enabled: proto.get_enabled(),
+            min_utxos: proto.get_min_utxos() as usize,
+            dust_amount: proto.get_dust_amount(),
+            payment_fee: proto.get_payment_fee(),
+            max_fee: proto.get_max_fee(),
+            use_snowball: proto.get_use_snowball(),
+        };
+        Ok(policy)
+    }
+}
+
+impl ProtoConvert for MixedStakeValue {
+    type Proto = account_log::MixedStakeValue;
+    fn into_proto(&self) -> Self::Proto {
+        let mut msg = account_log::MixedStakeValue::new();
+        msg.set_amount(self.amount);
+        msg.set_payment_fee(self.payment_fee);
+        if let Some(output) = &self.output {
+            msg.set_output(output.into_proto());
+        }
+        msg
+    }
+
+    fn from_proto(proto: &Self::Proto) -> Result<Self, Error> {
+        let output = if proto.has_output() {
+            Some(Hash::from_proto(proto.get_output())?)
+        } else {
+            None
+        };
+        Ok(MixedStakeValue {
+            amount: proto.get_amount(),
+            payment_fee: proto.get_payment_fee(),
+            output,
+        })
+    }
+}
+
+impl ProtoConvert for BackupEntry {
+    type Proto = backup::BackupEntry;
+    fn into_proto(&self) -> Self::Proto {
+        let mut msg = backup::BackupEntry::new();
+        msg.set_cf(self.cf.clone());
+        msg.set_key(self.key.clone());
+        msg.set_value(self.value.clone());
+        msg
+    }
+
+    fn from_proto(proto: &Self::Proto) -> Result<Self, Error> {
+        let cf = proto.get_cf().to_string();
+        let key = proto.get_key().to_vec();
+        let value = proto.get_value().to_vec();
+        Ok(BackupEntry { cf, key, value })
+    }
+}
+
+impl ProtoConvert for Backup {
+    type Proto = backup::Backup;
+    fn into_proto(&self) -> Self::Proto {
+        let mut msg = backup::Backup::new();
+        msg.set_version(self.version);
+        msg.set_account_skey(self.account_skey.into_proto());
+        msg.set_account_pkey(self.account_pkey.into_proto());
+        msg.set_created(self.created.into());
+        for entry in &self.entries {
+            msg.entries.push(entry.into_proto());
+        }
+        msg.set_checksum(Hash::digest(self).into_proto());
+        msg
+    }
+
+    fn from_proto(proto: &Self::Proto) -> Result<Self, Error> {
+        let version = proto.get_version();
+        let account_skey = SecretKey::from_proto(proto.get_account_skey())?;
+        let account_pkey = PublicKey::from_proto(proto.get_account_pkey())?;
+        let created: Timestamp = proto.get_created().into();
+        let mut entries = Vec::<BackupEntry>::with_capacity(proto.entries.len());
+        for entry in proto.entries.iter() {
+            entries.push(BackupEntry::from_proto(entry)?);
+        }
+        let checksum = Hash::from_proto(proto.get_checksum())?;
+        let value = Backup {
+            version,
+            account_skey,
+            account_pkey,
+            created,
+            entries,
+        };
+        if Hash::digest(&value) != checksum {
+            return Err(format_err!("Invalid backup checksum"));
+        }
+        Ok(value)
+    }
+}
+
+impl ProtoConvert for SnowballSessionInfo {
+    type Proto = account_log::SnowballSession;
+    fn into_proto(&self) -> Self::Proto {
+        let mut msg = account_log::SnowballSession::new();
+        msg.set_session_id(self.session_id.into_proto());
+        msg.set_started(self.started.into());
+        msg.set_finished(self.finished.into());
+        msg.set_attempt(self.attempt);
+        msg.set_participants(self.participants as u64);
+        msg.set_rounds(self.rounds);
+        for phase in &self.phases {
+            let mut phase_msg = account_log::SnowballPhase::new();
+            phase_msg.set_state(phase.state.name().to_string());
+            phase_msg.set_duration(phase.duration);
+            phase_msg.set_timed_out(phase.timed_out);
+            msg.mut_phases().push(phase_msg);
+        }
+        for excluded in &self.excluded {
+            let mut excluded_msg = account_log::SnowballExclusion::new();
+            excluded_msg.set_pkey(excluded.pkey.into_proto());
+            excluded_msg.set_state(excluded.state.name().to_string());
+            excluded_msg.set_blame(excluded.reason == SnowballExclusionReason::Blame);
+            msg.mut_excluded().push(excluded_msg);
+        }
+        for facilitator in &self.facilitators {
+            let mut facilitator_msg = account_log::SnowballFacilitator::new();
+            facilitator_msg.set_pkey(facilitator.pkey.into_proto());
+            facilitator_msg.set_fault(facilitator.fault.name().to_string());
+            facilitator_msg.set_pool(facilitator.pool.clone());
+            msg.mut_facilitators().push(facilitator_msg);
+        }
+        msg.set_succeeded(self.succeeded);
+        if let Some(error) = &self.error {
+            msg.set_error(error.clone());
+        }
+        if let Some(tx_hash) = &self.tx_hash {
+            msg.set_tx_hash(tx_hash.into_proto());
+        }
+        msg
+    }
+
+    fn from_proto(proto: &Self::Proto) -> Result<Self, Error> {
+        fn state_from_proto(name: &str) -> Result<SnowballStatus, Error> {
+            SnowballStatus::from_name(name)
+                .ok_or_else(|| format_err!("Invalid Snowball state: {}", name))
+        }
+        let session_id = Hash::from_proto(proto.get_session_id())?;
+        let started: Timestamp = proto.get_started().into();
+        let finished: Timestamp = proto.get_finished().into();
+        let mut phases = Vec::with_capacity(proto.get_phases().len());
+        for phase in proto.get_phases() {
+            phases.push(SnowballPhaseInfo {
+                state: state_from_proto(phase.get_state())?,
+                duration: phase.get_duration(),
+                timed_out: phase.get_timed_out(),
+            });
+        }
+        let mut excluded = Vec::with_capacity(proto.get_excluded().len());
+        for exclusion in proto.get_excluded() {
+            let reason = if exclusion.get_blame() {
+                SnowballExclusionReason::Blame
+            } else {
+                SnowballExclusionReason::Timeout
+            };
+            excluded.push(SnowballExclusionInfo {
+                pkey: pbc::PublicKey::from_proto(exclusion.get_pkey())?,
+                state: state_from_proto(exclusion.get_state())?,
+                reason,
+            });
+        }
+        let mut facilitators = Vec::with_capacity(proto.get_facilitators().len());
+        for facilitator in proto.get_facilitators() {
+            let name = facilitator.get_fault();
+            let fault = SnowballFacilitatorFault::from_name(name)
+                .ok_or_else(|| format_err!("Invalid facilitator fault: {}", name))?;
+            facilitators.push(SnowballFacilitatorInfo {
+                pkey: pbc::PublicKey::from_proto(facilitator.get_pkey())?,
+                fault,
+                pool: facilitator.get_pool().to_vec(),
+            });
+        }
+        let error = match proto.get_error() {
+            "" => None,
+            error => Some(error.to_string()),
+        };
+        let tx_hash = if proto.has_tx_hash() {
+            Some(Hash::from_proto(proto.get_tx_hash())?)
+        } else {
+            None
+        };
+        let value = SnowballSessionInfo {
+            session_id,
+            started,
+            finished,
+            attempt: proto.get_attempt(),
+            participants: proto.get_participants() as usize,
+            rounds: proto.get_rounds(),
+            phases,
+            excluded,
+            facilitators,
+            succeeded: proto.get_succeeded(),
+            error,
+            tx_hash,
+        };
+        Ok(value)
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
     use super::*;
     use stegos_crypto::hash::{Hash, Hashable};
+    use stegos_crypto::scc;
 
     fn roundtrip<T>(x: &T) -> T
     where
@@ -373,4 +703,116 @@ mod tests {
         };
         roundtrip(&request);
     }
+
+    #[test]
+    fn contact() {
+        let (_skey, account_pkey) = scc::make_random_keys();
+        let contact = ContactValue {
+            account_pkey,
+            notes: "Alice from the coffee shop".to_string(),
+        };
+        roundtrip(&contact);
+    }
+
+    #[test]
+    fn invoice() {
+        let created = Timestamp::now();
+        let mut invoice = InvoiceValue {
+            amount: 100,
+            data: PaymentPayloadData::Comment("Order #42".to_string()),
+            created,
+            expires: None,
+            paid: None,
+        };
+        roundtrip(&invoice);
+        invoice.data = PaymentPayloadData::ContentHash(Hash::digest("order"));
+        invoice.expires = Some(created + std::time::Duration::from_secs(3600));
+        invoice.paid = Some((Hash::digest("utxo"), created));
+        roundtrip(&invoice);
+    }
+
+    #[test]
+    fn snowball_session() {
+        let (_skey, pkey) = pbc::make_random_keys();
+        let started = Timestamp::now();
+        let mut session = SnowballSessionInfo {
+            session_id: Hash::digest("session"),
+            started,
+            finished: started + std::time::Duration::from_secs(60),
+            attempt: 1,
+            participants: 3,
+            rounds: 1,
+            phases: vec![
+                SnowballPhaseInfo {
+                    state: SnowballStatus::PoolWait,
+                    duration: 1500,
+                    timed_out: false,
+                },
+                SnowballPhaseInfo {
+                    state: SnowballStatus::SharedKeying,
+                    duration: 60000,
+                    timed_out: true,
+                },
+            ],
+            excluded: vec![SnowballExclusionInfo {
+                pkey,
+                state: SnowballStatus::SharedKeying,
+                reason: SnowballExclusionReason::Timeout,
+            }],
+            facilitators: vec![SnowballFacilitatorInfo {
+                pkey,
+                fault: SnowballFacilitatorFault::Timeout,
+                pool: Vec::new(),
+            }],
+            succeeded: false,
+            error: Some("Not enough participants: 2".to_string()),
+            tx_hash: None,
+        };
+        let r = SnowballSessionInfo::from_buffer(&session.into_buffer().unwrap()).unwrap();
+        assert_eq!(r, session);
+
+        session.excluded[0].reason = SnowballExclusionReason::Blame;
+        session.facilitators[0].fault = SnowballFacilitatorFault::Excluded;
+        session.facilitators[0].pool = vec![1, 2, 3];
+        session.succeeded = true;
+        session.error = None;
+        session.tx_hash = Some(Hash::digest("tx"));
+        let r = SnowballSessionInfo::from_buffer(&session.into_buffer().unwrap()).unwrap();
+        assert_eq!(r, session);
+    }
+
+    #[test]
+    fn incoming() {
+        let (_skey, pkey) = scc::make_random_keys();
+        let output = PublicPaymentOutput::new(&pkey, 10);
+        let output = OutputValue::PublicPayment(PublicPaymentValue { output });
+        let cases = [
+            (None, None, OutputSource::Unknown),
+            (Some(5), None, OutputSource::ServiceAward),
+            (Some(5), Some(3), OutputSource::Transaction),
+            (Some(5), Some(0), OutputSource::BlockReward),
+        ];
+        for &(epoch, offset, source) in &cases {
+            let entry = LogEntry::Incoming {
+                output: output.clone(),
+                epoch,
+                offset,
+                source,
+            };
+            match LogEntry::from_proto(&entry.into_proto()).unwrap() {
+                LogEntry::Incoming {
+                    output: r_output,
+                    epoch: r_epoch,
+                    offset: r_offset,
+                    source: r_source,
+                } => {
+                    assert_eq!(Hash::digest(&output), Hash::digest(&r_output));
+                    assert_eq!(epoch, r_epoch);
+                    assert_eq!(offset, r_offset);
+                    assert_eq!(source, r_source);
+                }
+                LogEntry::Outgoing { .. } => panic!("Expected incoming entry"),
+            }
+        }
+    }
 }
@@ -61,9 +61,12 @@
 //
 // The arguments to start() are checked for validity:
 //
-//  1. No more than MAX_UTXOS can be indicated by the proposed spending list
-//     (Currently MAX_UTXOS = 5). If fewer UTXOs will be produced, then the
-//     DiceMix sharing matrix will be zero-filled and cloaked up to this maximum.
+//  1. No more than MAX_OUTPUTS can be indicated by the proposed spending list.
+//     Each participant announces the number of its output slots, which is
+//     rounded up to a multiple of MAX_UTXOS (Currently MAX_UTXOS = 5), and
+//     the DiceMix sharing matrix of the session has as many sheets as the
+//     largest announcement. If fewer UTXOs will be produced, then the
+//     DiceMix sharing matrix will be zero-filled and cloaked up to this size.
 //
 //  2. Each TXIN must refer to a blockchain UTXO that can be proven to be
 //     owned by the wallet. We do that by checking that the hash of the UTXO
@@ -81,6 +84,10 @@ use message::*;
 
 mod protos;
 
+use crate::api::{
+    SnowballExclusionInfo, SnowballExclusionReason, SnowballFacilitatorFault,
+    SnowballFacilitatorInfo, SnowballPhaseInfo, SnowballSessionInfo,
+};
 use crate::snowball::message::SnowballMessage;
 use crate::storage::{OutputValue, PaymentValue};
 use byteorder::{ByteOrder, LittleEndian};
@@ -94,13 +101,15 @@ use log::{log, trace, Level};
 use rand::thread_rng;
 use rand::Rng;
 use serde_derive::{Deserialize, Serialize};
+use std::cmp;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::collections::VecDeque;
 use std::mem;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use stegos_blockchain::Output;
 use stegos_blockchain::PaymentTransaction;
+use stegos_blockchain::Timestamp;
 use stegos_blockchain::{PaymentOutput, PaymentPayloadData};
 use stegos_crypto::bulletproofs::{simple_commit, validate_range_proof};
 use stegos_crypto::dicemix::*;
@@ -112,6 +121,8 @@ use stegos_crypto::scc::{
 use stegos_crypto::{dicemix, CryptoError};
 use stegos_network::Network;
 use stegos_serialization::traits::ProtoConvert;
+use stegos_txpool::validate_join;
+use stegos_txpool::PoolInfo;
 use stegos_txpool::PoolJoin;
 use stegos_txpool::PoolNotification;
 use stegos_txpool::POOL_ANNOUNCE_TOPIC;
@@ -123,7 +134,9 @@ pub const SNOWBALL_TOPIC: &'static str = "snowball";
 
 pub const SNOWBALL_TIMER: Duration = Duration::from_secs(60); // recurring 1sec events
 
-pub const MAX_UTXOS: usize = 5; // max nbr of txout UTXO permitted
+pub const MAX_UTXOS: usize = 5; // granularity of txout UTXO slots
+
+pub const MAX_OUTPUTS: usize = 4 * MAX_UTXOS; // max nbr of txout UTXO permitted
 
 pub const MSG_FLOOD_LIMIT: usize = 5; // max nbr of pending messages from one participant
 
@@ -201,7 +214,7 @@ pub enum State {
 
 impl State {
     /// Enum to string.
-    fn name(&self) -> &'static str {
+    pub fn name(&self) -> &'static str {
         match *self {
             State::Started => "Started",
             State::PoolWait => "PoolWait",
@@ -214,6 +227,23 @@ impl State {
             State::Succeeded => "Succeeded",
         }
     }
+
+    /// String to enum.
+    pub fn from_name(name: &str) -> Option<State> {
+        let state = match name {
+            "Started" => State::Started,
+            "PoolWait" => State::PoolWait,
+            "SharedKeying" => State::SharedKeying,
+            "Commitment" => State::Commitment,
+            "CloakedVals" => State::CloakedVals,
+            "Signature" => State::Signature,
+            "SecretKeying" => State::SecretKeying,
+            "Failed" => State::Failed,
+            "Succeeded" => State::Succeeded,
+            _ => return None,
+        };
+        Some(state)
+    }
 }
 
 #[derive(Debug)]
@@ -240,6 +270,9 @@ pub struct Snowball {
     /// Faciliator's PBC public key
     facilitator: pbc::PublicKey,
 
+    /// Facilitator candidates, in the order of preference.
+    facilitators: Vec<pbc::PublicKey>,
+
     /// Public keys of txpool's members,
     participants: Vec<ParticipantID>,
 
@@ -263,6 +296,10 @@ pub struct Snowball {
     my_txouts: Vec<ProposedUTXO>,
     my_fee: i64,
 
+    // The minimal pool size and the maximal wait time requested from the facilitator.
+    min_participants: u32,
+    max_wait: Duration,
+
     // sum(gamma_i) for i over TXINs
     txin_gamma_sum: Fr,
 
@@ -300,6 +337,9 @@ pub struct Snowball {
     // (TXIN, UTXO) lists from each participant
     all_txins: HashMap<ParticipantID, Vec<(TXIN, UTXO)>>,
 
+    // The number of output slots announced by each participant
+    all_output_slots: HashMap<ParticipantID, usize>,
+
     // --------------------------------------------
     // Items compupted in commit()
 
@@ -366,6 +406,16 @@ pub struct Snowball {
     // they get moved back to participants list. Remaining pending_participants
     // is the list of participants that dropped out during this exchange
     pending_participants: HashSet<ParticipantID>,
+
+    // --------------------------------------------
+    // Session log, saved to the account history when finished.
+    log: SnowballSessionInfo,
+
+    // Start of the current phase.
+    phase_started: Instant,
+
+    // The current phase was finished by timeout.
+    phase_timed_out: bool,
 }
 
 impl Snowball {
@@ -379,13 +429,17 @@ impl Snowball {
         account_pkey: PublicKey,
         network_pkey: pbc::PublicKey,
         network: Network,
-        facilitator: pbc::PublicKey,
+        facilitators: Vec<pbc::PublicKey>,
         my_txins: Vec<(TXIN, UTXO)>,
         my_txouts: Vec<ProposedUTXO>,
         my_fee: i64,
+        min_participants: u32,
+        max_wait: Duration,
     ) -> Snowball {
         // check the maximal number of UTXOs.
-        assert!(my_txouts.len() <= MAX_UTXOS);
+        assert!(my_txouts.len() <= MAX_OUTPUTS);
+        assert!(!facilitators.is_empty());
+        let facilitator = facilitators[0];
 
         // validate each TXIN and get my initial signature keying info
         let utxos = my_txins.iter().map(|(_txin, u)| u.clone()).collect();
@@ -419,6 +473,21 @@ impl Snowball {
         let participants: Vec<ParticipantID> = Vec::new();
         let session_id: Hash = Hash::random();
         let state = State::Started;
+        let started = Timestamp::now();
+        let log = SnowballSessionInfo {
+            session_id,
+            started,
+            finished: started,
+            attempt: 1,
+            participants: 0,
+            rounds: 0,
+            phases: Vec::new(),
+            excluded: Vec::new(),
+            facilitators: Vec::new(),
+            succeeded: false,
+            error: None,
+            tx_hash: None,
+        };
         let mut rng = thread_rng();
         let seed = rng.gen::<[u8; 32]>();
         let my_participant_id = dicemix::ParticipantID::new(network_pkey, seed);
@@ -453,6 +522,7 @@ impl Snowball {
             sess_pkey: PublicKey::zero(), // just a dummy placeholder for now
             my_participant_id,
             facilitator,
+            facilitators,
             state,
             participants,
             session_id,
@@ -463,12 +533,15 @@ impl Snowball {
             my_txouts,
             my_utxos: Vec::new(),
             my_fee,
+            min_participants,
+            max_wait,
             sess_skey: SecretKey::zero(), // dummy placeholder for now
             my_signing_skey,
             txin_gamma_sum,
             // these are all empty participant lists
             session_round: 0,
             all_txins: HashMap::new(),
+            all_output_slots: HashMap::new(),
             my_round_k: Fr::zero(),
             sigK_vals: HashMap::new(),
             sess_skeys: HashMap::new(),
@@ -486,6 +559,9 @@ impl Snowball {
             trans: PaymentTransaction::dum(),
             msg_queue: VecDeque::new(),
             commit_phase_participants: Vec::new(),
+            log,
+            phase_started: clock::now(),
+            phase_timed_out: false,
         };
         sb.send_pool_join();
         sb
@@ -496,9 +572,88 @@ impl Snowball {
         self.state
     }
 
+    /// Return the number of this attempt with the same inputs.
+    pub fn attempt(&self) -> u32 {
+        self.log.attempt
+    }
+
+    /// Return the hashes of my inputs.
+    pub fn inputs(&self) -> Vec<Hash> {
+        self.my_txins.iter().map(|(txin, _)| txin.clone()).collect()
+    }
+
+    /// Start a new session with the same inputs and outputs.
+    pub fn retry(&self, facilitators: Vec<pbc::PublicKey>) -> Snowball {
+        let mut snowball = Snowball::new(
+            self.account_skey.clone(),
+            self.account_pkey.clone(),
+            self.my_participant_id.pkey,
+            self.network.clone(),
+            facilitators,
+            self.my_txins.clone(),
+            self.my_txouts.clone(),
+            self.my_fee,
+            self.min_participants,
+            self.max_wait,
+        );
+        snowball.log.attempt = self.log.attempt + 1;
+        snowball
+    }
+
+    /// Return the log of this session, `error` is None if it has succeeded.
+    pub fn session_log(&self, error: Option<String>) -> SnowballSessionInfo {
+        let mut log = self.log.clone();
+        if self.state != State::Started
+            && self.state != State::Succeeded
+            && self.state != State::Failed
+        {
+            log.phases.push(self.current_phase());
+        }
+        log.finished = Timestamp::now();
+        log.succeeded = error.is_none();
+        log.error = error;
+        log
+    }
+
+    fn current_phase(&self) -> SnowballPhaseInfo {
+        let duration = clock::now().duration_since(self.phase_started);
+        SnowballPhaseInfo {
+            state: self.state,
+            duration: duration.as_secs() * 1000 + duration.subsec_millis() as u64,
+            timed_out: self.phase_timed_out,
+        }
+    }
+
+    fn exclude(&mut self, participant: &ParticipantID, reason: SnowballExclusionReason) {
+        self.log.excluded.push(SnowballExclusionInfo {
+            pkey: participant.pkey,
+            state: self.state,
+            reason,
+        });
+    }
+
+    /// Records a misbehaving facilitator with the signed pool as evidence, if any.
+    fn blame_facilitator(&mut self, fault: SnowballFacilitatorFault, pool_info: Option<&PoolInfo>) {
+        let pool = match pool_info {
+            Some(pool_info) if pool_info.signature.is_some() => pool_info.into_buffer().unwrap(),
+            _ => Vec::new(),
+        };
+        self.log.facilitators.push(SnowballFacilitatorInfo {
+            pkey: self.facilitator,
+            fault,
+            pool,
+        });
+    }
+
     /// Change state.
     fn change_state(&mut self, state: State) {
         swarn!(self, "=> ({})", state.name());
+        if self.state != State::Started {
+            let phase = self.current_phase();
+            self.log.phases.push(phase);
+        }
+        self.phase_started = clock::now();
+        self.phase_timed_out = false;
         self.state = state;
         if self.state == State::Succeeded || self.state == State::Failed {
             return;
@@ -530,15 +685,51 @@ impl Snowball {
     // start a Snowball session. The Facilitator should send that list of
     // node ID's along with an initial unique session ID (sid).
 
-    /// Called when facilitator has been changed.
-    pub fn change_facilitator(&mut self, facilitator: pbc::PublicKey) {
-        self.facilitator = facilitator;
-        sinfo!(self, "Change facilitator to {}", &facilitator);
+    /// Called when facilitator candidates have been changed.
+    pub fn change_facilitator(&mut self, facilitators: Vec<pbc::PublicKey>) {
+        assert!(!facilitators.is_empty());
+        self.facilitator = facilitators[0];
+        self.facilitators = facilitators;
+        sinfo!(self, "Change facilitator to {}", &self.facilitator);
         if self.state == State::PoolWait {
             self.send_pool_join();
         }
     }
 
+    /// Fall back to the next facilitator candidate.
+    fn next_facilitator(&mut self) {
+        let index = self
+            .facilitators
+            .iter()
+            .position(|f| *f == self.facilitator)
+            .map(|i| (i + 1) % self.facilitators.len())
+            .unwrap_or(0);
+        let facilitator = self.facilitators[index];
+        if facilitator != self.facilitator {
+            swarn!(
+                self,
+                "Fall back to the next facilitator: old={}, new={}",
+                self.facilitator,
+                facilitator
+            );
+            self.facilitator = facilitator;
+        }
+    }
+
+    /// Falls back to the next candidate if the facilitator has formed a pool without us.
+    fn on_unexpected_pool(&mut self, pool_info: &PoolInfo, fault: SnowballFacilitatorFault) {
+        swarn!(
+            self,
+            "Unexpected pool: facilitator={}, fault={}, pool={}",
+            self.facilitator,
+            fault.name(),
+            Hash::digest(pool_info)
+        );
+        self.blame_facilitator(fault, Some(pool_info));
+        self.next_facilitator();
+        self.send_pool_join();
+    }
+
     /// Sends a request to join tx pool.
     fn send_pool_join(&mut self) {
         sdebug!(
@@ -560,6 +751,9 @@ impl Snowball {
             txins: msg_txins,
             utxos,
             ownsig,
+            min_participants: self.min_participants,
+            max_wait: self.max_wait,
+            output_slots: output_slots(self.my_txouts.len()) as u32,
         };
         let msg = msg.into_buffer().unwrap();
         self.network
@@ -609,39 +803,82 @@ impl Snowball {
             PoolNotification::Started(info) => info,
         };
 
+        if let Err(e) = pool_info.validate_signature(&self.facilitator) {
+            swarn!(self, "Invalid pool signature: error={}", e);
+            self.blame_facilitator(SnowballFacilitatorFault::InvalidSignature, None);
+            self.next_facilitator();
+            self.send_pool_join();
+            return Ok(Async::NotReady);
+        }
+
         if pool_info
             .participants
             .iter()
             .find(|k| k.participant == self.my_participant_id)
             .is_none()
         {
-            swarn!(self, "We aren't a participant");
-            // Wait until self.change_facilitator() is called by timer.
-            assert!(self.timer.is_some(), "timer is active");
+            self.on_unexpected_pool(&pool_info, SnowballFacilitatorFault::Excluded);
             return Ok(Async::NotReady);
         }
 
         self.session_id = pool_info.session_id;
-        let part_info = pool_info.participants;
+        self.log.session_id = pool_info.session_id;
         self.participants = Vec::<ParticipantID>::new();
-        for elt in &part_info {
-            let mut pairs = Vec::<(TXIN, UTXO)>::new();
-            for (txin, utxo) in elt.txins.iter().zip(elt.utxos.iter()) {
-                pairs.push((txin.clone(), utxo.clone()));
-            }
-            match validate_ownership(&pairs, &elt.ownsig) {
-                Ok(_) => {
-                    self.participants.push(elt.participant);
-                    self.all_txins.insert(elt.participant, pairs);
-                }
-                _ => {
-                    swarn!(self, "Invalid ownership signature");
-                }
+        let mut invalid_participants = false;
+        for elt in &pool_info.participants {
+            // Facilitators validate join requests, but skip invalid participants anyway.
+            if let Err(e) = validate_join(&elt.txins, &elt.utxos, &elt.ownsig, elt.output_slots) {
+                swarn!(
+                    self,
+                    "Skip invalid participant: participant={:?}, error={}",
+                    elt.participant,
+                    e
+                );
+                invalid_participants = true;
+                continue;
             }
+            let slots = elt.output_slots as usize;
+            let pairs: Vec<(TXIN, UTXO)> = elt
+                .txins
+                .iter()
+                .cloned()
+                .zip(elt.utxos.iter().cloned())
+                .collect();
+            self.participants.push(elt.participant);
+            self.all_txins.insert(elt.participant, pairs);
+            self.all_output_slots.insert(elt.participant, slots);
         }
 
         self.participants.sort();
         self.participants.dedup();
+        self.log.participants = self.participants.len();
+        if invalid_participants {
+            self.blame_facilitator(
+                SnowballFacilitatorFault::InvalidParticipant,
+                Some(&pool_info),
+            );
+        }
+        // Facilitators limit the requested pool size by their maximum.
+        let min_participants = if pool_info.max_participants > 0 {
+            cmp::min(self.min_participants, pool_info.max_participants)
+        } else {
+            self.min_participants
+        };
+        if self.participants.len() < min_participants as usize {
+            swarn!(
+                self,
+                "Ignore pool with too few participants: expected={}, got={}",
+                min_participants,
+                self.participants.len()
+            );
+            self.blame_facilitator(
+                SnowballFacilitatorFault::TooFewParticipants,
+                Some(&pool_info),
+            );
+            // Wait until self.change_facilitator() is called by timer.
+            assert!(self.timer.is_some(), "timer is active");
+            return Ok(Async::NotReady);
+        }
 
         sinfo!(self, "Formed a pool");
         for pkey in &self.participants {
@@ -834,7 +1071,11 @@ impl Snowball {
         // whichever participants did not respond are in self.pending_participants.
         self.timer = None;
         swarn!(self, "Timed out");
+        self.phase_timed_out = true;
         if self.state == State::PoolWait {
+            // The facilitator is unresponsive.
+            self.blame_facilitator(SnowballFacilitatorFault::Timeout, None);
+            self.next_facilitator();
             self.send_pool_join();
             return Ok(Async::NotReady);
         }
@@ -845,6 +1086,12 @@ impl Snowball {
                 "Missing participants: {:?}",
                 self.pending_participants
             );
+            let mut missing: Vec<ParticipantID> =
+                self.pending_participants.iter().cloned().collect();
+            missing.sort();
+            for participant in missing.iter() {
+                self.exclude(participant, SnowballExclusionReason::Timeout);
+            }
         }
         self.perform_next_phase()
     }
@@ -1098,10 +1345,26 @@ impl Snowball {
         // than we do... (but also checking for phony nbr of rows and sheets)
         let ncols_expected = self.commit_phase_participants.len();
         let nrows_expected = ROWS_FIXED_SIZE;
-        matrix.len() == MAX_UTXOS
-            && matrix.iter().all(|sheet| {
-                sheet.len() == nrows_expected && sheet.iter().all(|row| row.len() == ncols_expected)
+        dc_matrix_has_dims(
+            matrix,
+            self.session_sheets(),
+            nrows_expected,
+            ncols_expected,
+        )
+    }
+
+    /// The number of sheets in DiceMix matrices of the current round.
+    fn session_sheets(&self) -> usize {
+        self.commit_phase_participants
+            .iter()
+            .map(|p| {
+                *self
+                    .all_output_slots
+                    .get(p)
+                    .expect("Can't access output slots")
             })
+            .max()
+            .unwrap_or(MAX_UTXOS)
     }
 
     fn handle_cloaked_vals(
@@ -1222,6 +1485,7 @@ impl Snowball {
 
         self.participants.sort(); // put into consistent order
         self.session_round += 1;
+        self.log.rounds = self.session_round as u32;
         self.session_id = {
             let mut state = Hasher::new();
             "sid".hash(&mut state);
@@ -1337,6 +1601,7 @@ impl Snowball {
             &my_utxos,
             &self.my_participant_id,
             &self.k_cloaks,
+            self.session_sheets(),
             ROWS_FIXED_SIZE,
         );
         self.matrices = HashMap::new();
@@ -1491,7 +1756,7 @@ impl Snowball {
             &self.participants,
             &self.matrices,
             &self.my_participant_id,
-            MAX_UTXOS,
+            self.session_sheets(),
             ROWS_FIXED_SIZE,
             &self.excl_participants, // the excluded participants
             &self.all_excl_cloaks,
@@ -1675,6 +1940,7 @@ impl Snowball {
                 .collect();
 
             self.msg_queue.clear();
+            self.log.tx_hash = Some(Hash::digest(&tx));
             self.change_state(State::Succeeded);
             return Ok(Async::Ready(SnowballOutput {
                 tx,
@@ -1736,6 +2002,9 @@ impl Snowball {
                 Self::validate_uncloaked_contrib,
                 &data,
             );
+            for participant in new_p_excl.iter() {
+                self.exclude(participant, SnowballExclusionReason::Blame);
+            }
             self.exclude_participants(&new_p_excl);
         }
         // and begin another round
@@ -1820,6 +2089,9 @@ impl Snowball {
         }
         let mut txout_sum = Pt::inf();
         for msg in msgs {
+            if msg.iter().all(|b| *b == 0) {
+                continue; // unused output slot
+            }
             let utxo = match deserialize_utxo(msg) {
                 Ok(u) => u,
                 _ => {
@@ -1860,34 +2132,14 @@ impl Snowball {
         my_utxos: &Vec<UTXO>,
         my_id: &ParticipantID,
         k_cloaks: &HashMap<ParticipantID, Hash>,
+        n_sheets: usize,
         n_chunks: usize,
     ) -> DcMatrix {
-        // Encode UTXOs to matrix for cloaked sharing
-        let mut matrix = Vec::<DcSheet>::new();
-        let mut sheet_id = 0;
-        for utxo in my_utxos.clone() {
-            sheet_id += 1;
-            let msg = serialize_utxo(&utxo);
-            let sheet = dc_encode_sheet(sheet_id, n_chunks, &msg, participants, my_id, &k_cloaks);
-            matrix.push(sheet);
-        }
-        // fill out matrix with dummy UTXO messages
+        // Encode UTXOs to matrix for cloaked sharing,
+        // the rest of sheets are filled with dummy UTXO messages
         // (sheets containing zero fill plus cloaking factors)
-        let n_utxos = my_utxos.len();
-        let null_msg = Vec::<u8>::new();
-        for _ in n_utxos..MAX_UTXOS {
-            sheet_id += 1;
-            let sheet = dc_encode_sheet(
-                sheet_id,
-                n_chunks,
-                &null_msg,
-                participants,
-                my_id,
-                &k_cloaks,
-            );
-            matrix.push(sheet);
-        }
-        matrix
+        let msgs: Vec<Vec<u8>> = my_utxos.iter().map(serialize_utxo).collect();
+        dc_encode_matrix(n_sheets, n_chunks, &msgs, participants, my_id, k_cloaks)
     }
 
     fn generate_fresh_utxos(
@@ -2152,6 +2404,12 @@ fn validate_ownership(
     validate_sig(&hash, owner_sig, &PublicKey::from(p_cmp))
 }
 
+/// The number of output slots to announce for the given number of outputs.
+fn output_slots(n_outputs: usize) -> usize {
+    let n_outputs = std::cmp::max(n_outputs, 1);
+    (n_outputs + MAX_UTXOS - 1) / MAX_UTXOS * MAX_UTXOS
+}
+
 fn serialize_utxo(utxo: &UTXO) -> Vec<u8> {
     let buffer = utxo.into_buffer().expect("Can't serialize UTXO");
     assert!(8 + buffer.len() <= UTXO_FIXED_SIZE);
@@ -2218,8 +2476,11 @@ fn times_G(val: &Fr) -> Pt {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use futures::future;
     use std::dbg;
     use stegos_crypto::scc::make_random_keys;
+    use stegos_network::loopback::{Loopback, MessageFromNode};
+    use stegos_txpool::ParticipantTXINMap;
 
     #[test]
     fn test_serialize_deserialize_utxo() {
@@ -2258,4 +2519,472 @@ mod tests {
             println!("k {} v {}", k, v);
         }
     }
+
+    // ----------------------------------------------------------------------
+    // In-process driver for multi-participant sessions.
+    //
+    // Messages are routed between participants by hand and timeouts are
+    // fired when nothing is in flight. The first participant can be faulty.
+
+    const NUM_PARTICIPANTS: usize = 4;
+    const AMOUNT: i64 = 100;
+    const FEE: i64 = 1;
+    const MAX_TIMEOUTS: usize = 20;
+
+    /// Protocol phases, in the order of execution.
+    const PHASES: [State; 5] = [
+        State::SharedKeying,
+        State::Commitment,
+        State::CloakedVals,
+        State::Signature,
+        State::SecretKeying,
+    ];
+
+    #[derive(Debug, Clone, Copy)]
+    enum Fault {
+        /// Stops sending messages from the given phase.
+        DropOut(State),
+        /// Sends bad messages in the given phase.
+        Malicious(State),
+    }
+
+    fn phase(state: State) -> usize {
+        PHASES
+            .iter()
+            .position(|s| *s == state)
+            .expect("protocol phase")
+    }
+
+    fn payload_phase(payload: &SnowballPayload) -> usize {
+        let state = match payload {
+            SnowballPayload::SharedKeying { .. } => State::SharedKeying,
+            SnowballPayload::Commitment { .. } => State::Commitment,
+            SnowballPayload::CloakedVals { .. } => State::CloakedVals,
+            SnowballPayload::Signature { .. } => State::Signature,
+            SnowballPayload::SecretKeying { .. } => State::SecretKeying,
+        };
+        phase(state)
+    }
+
+    struct Peer {
+        loopback: Loopback,
+        network_pkey: pbc::PublicKey,
+        snowball: Snowball,
+        result: Option<Result<Hash, SnowballError>>,
+    }
+
+    impl Peer {
+        fn new(facilitator: pbc::PublicKey) -> Peer {
+            let (account_skey, account_pkey) = make_random_keys();
+            let (_network_skey, network_pkey) = pbc::make_random_keys();
+            let (loopback, network, _peer_id, _replication_rx) = Loopback::new();
+            let (utxo, _gamma) = PaymentOutput::new(&account_pkey, AMOUNT).unwrap();
+            let txin = Hash::digest(&utxo);
+            let txout = ProposedUTXO {
+                recip: account_pkey,
+                amount: AMOUNT - FEE,
+                data: PaymentPayloadData::Comment("Snowball".to_string()),
+                is_change: false,
+            };
+            let snowball = Snowball::new(
+                account_skey,
+                account_pkey,
+                network_pkey,
+                network,
+                vec![facilitator],
+                vec![(txin, utxo)],
+                vec![txout],
+                FEE,
+                NUM_PARTICIPANTS as u32,
+                Duration::from_secs(0),
+            );
+            Peer {
+                loopback,
+                network_pkey,
+                snowball,
+                result: None,
+            }
+        }
+
+        fn finish(&mut self, result: HandlerResult) {
+            match result {
+                Ok(Async::Ready(output)) => self.result = Some(Ok(Hash::digest(&output.tx))),
+                Ok(Async::NotReady) => {}
+                Err(e) => self.result = Some(Err(e)),
+            }
+        }
+
+        fn is_excluded(&self, peer: &Peer, reason: SnowballExclusionReason) -> bool {
+            self.snowball
+                .log
+                .excluded
+                .iter()
+                .any(|e| e.pkey == peer.network_pkey && e.reason == reason)
+        }
+    }
+
+    /// Collects join requests sent to the facilitator.
+    fn collect_joins(
+        peers: &mut Vec<Peer>,
+        facilitator_pkey: &pbc::PublicKey,
+    ) -> Vec<ParticipantTXINMap> {
+        let mut participants = Vec::new();
+        for peer in peers.iter_mut() {
+            let join = match peer.loopback.pop_message().expect("PoolJoin") {
+                MessageFromNode::SendUnicast {
+                    to,
+                    protocol_id,
+                    data,
+                } => {
+                    assert_eq!(to, *facilitator_pkey);
+                    assert_eq!(protocol_id, POOL_JOIN_TOPIC);
+                    PoolJoin::from_buffer(&data).unwrap()
+                }
+                msg => panic!("Unexpected message: {:?}", msg),
+            };
+            participants.push(ParticipantTXINMap {
+                participant: ParticipantID::new(peer.network_pkey, join.seed),
+                txins: join.txins,
+                utxos: join.utxos,
+                ownsig: join.ownsig,
+                output_slots: join.output_slots,
+            });
+        }
+        participants
+    }
+
+    /// Announces the pool to all peers and checks that they have started the session.
+    fn announce_pool(peers: &mut Vec<Peer>, facilitator_pkey: &pbc::PublicKey, pool: &PoolInfo) {
+        for peer in peers.iter_mut() {
+            let notification = PoolNotification::Started(pool.clone());
+            let result = peer
+                .snowball
+                .on_pool_notification(*facilitator_pkey, notification);
+            assert!(result.expect("started").is_not_ready());
+            assert_eq!(peer.snowball.state(), State::SharedKeying);
+        }
+    }
+
+    /// Collects join requests and announces a signed pool.
+    fn form_pool(
+        peers: &mut Vec<Peer>,
+        facilitator_skey: &pbc::SecretKey,
+        facilitator_pkey: &pbc::PublicKey,
+    ) {
+        let participants = collect_joins(peers, facilitator_pkey);
+        let mut pool = PoolInfo {
+            participants,
+            session_id: Hash::random(),
+            max_participants: peers.len() as u32,
+            signature: None,
+        };
+        pool.sign(facilitator_skey);
+        announce_pool(peers, facilitator_pkey, &pool);
+    }
+
+    /// Applies the fault to an outgoing message, returns None if it is dropped.
+    fn apply_fault(
+        fault: Fault,
+        sender: &mut Snowball,
+        mut msg: SnowballMessage,
+    ) -> Option<SnowballMessage> {
+        match fault {
+            Fault::DropOut(state) => {
+                if payload_phase(&msg.payload) >= phase(state) {
+                    return None;
+                }
+            }
+            Fault::Malicious(state) => match (state, &mut msg.payload) {
+                (State::SharedKeying, SnowballPayload::SharedKeying { .. }) => {
+                    // A replay from some other session.
+                    msg.sid = Hash::digest("other session");
+                }
+                (State::Commitment, SnowballPayload::Commitment { parts, .. }) => {
+                    // A different view of participants.
+                    parts.pop();
+                }
+                (State::CloakedVals, SnowballPayload::Commitment { cmt, .. }) => {
+                    // Commits to an inflated fee, which passes commitment checks,
+                    // but breaks the balance of the supertransaction.
+                    let me = sender.my_participant_id;
+                    if *cmt == sender.commits[&me] {
+                        let fee = sender.cloaked_fees[&me] + Fr::one();
+                        sender.cloaked_fees.insert(me, fee);
+                        let commit =
+                            hash_data(&sender.matrices[&me], &sender.cloaked_gamma_adjs[&me], &fee);
+                        sender.commits.insert(me, commit);
+                    }
+                    *cmt = sender.commits[&me];
+                }
+                (State::Signature, SnowballPayload::Signature { sig }) => {
+                    *sig = SchnorrSig::new();
+                }
+                _ => {}
+            },
+        }
+        Some(msg)
+    }
+
+    /// Runs a session until all honest participants finish.
+    fn run_session(fault: Option<Fault>) -> Vec<Peer> {
+        future::lazy(move || {
+            let (facilitator_skey, facilitator_pkey) = pbc::make_random_keys();
+            let mut peers: Vec<Peer> = (0..NUM_PARTICIPANTS)
+                .map(|_| Peer::new(facilitator_pkey))
+                .collect();
+            form_pool(&mut peers, &facilitator_skey, &facilitator_pkey);
+            let honest = if fault.is_some() { 1 } else { 0 };
+            let mut timeouts = 0;
+            loop {
+                let mut delivered = false;
+                for from in 0..peers.len() {
+                    while let Some(msg) = peers[from].loopback.pop_message() {
+                        let (to, data) = match msg {
+                            MessageFromNode::SendUnicast {
+                                to,
+                                protocol_id,
+                                data,
+                            } => {
+                                assert_eq!(protocol_id, SNOWBALL_TOPIC);
+                                (to, data)
+                            }
+                            msg => panic!("Unexpected message: {:?}", msg),
+                        };
+                        let msg = SnowballMessage::from_buffer(&data).expect("valid message");
+                        assert_eq!(msg.source.pkey, peers[from].network_pkey);
+                        let msg = match fault {
+                            Some(fault) if from == 0 => {
+                                match apply_fault(fault, &mut peers[0].snowball, msg) {
+                                    Some(msg) => msg,
+                                    None => continue,
+                                }
+                            }
+                            _ => msg,
+                        };
+                        let to = peers
+                            .iter()
+                            .position(|p| p.network_pkey == to)
+                            .expect("known participant");
+                        if peers[to].result.is_some() {
+                            continue;
+                        }
+                        let result = peers[to].snowball.on_message_received(
+                            &msg.source,
+                            &msg.sid,
+                            &msg.payload,
+                        );
+                        peers[to].finish(result);
+                        delivered = true;
+                    }
+                }
+                if delivered {
+                    continue;
+                }
+                if peers[honest..].iter().all(|p| p.result.is_some()) {
+                    break;
+                }
+                timeouts += 1;
+                assert!(timeouts <= MAX_TIMEOUTS, "Session stalled");
+                for peer in peers.iter_mut() {
+                    if peer.result.is_none() && peer.snowball.timer.is_some() {
+                        let result = peer.snowball.handle_timer();
+                        peer.finish(result);
+                    }
+                }
+            }
+            Ok::<_, ()>(peers)
+        })
+        .wait()
+        .unwrap()
+    }
+
+    /// Checks that honest participants agreed on a transaction without the faulty one.
+    fn check_session(fault: Fault, reason: SnowballExclusionReason) {
+        println!("Fault: {:?}", fault);
+        let peers = run_session(Some(fault));
+        let (faulty, honest) = peers.split_first().unwrap();
+        let tx_hash = match honest[0].result {
+            Some(Ok(tx_hash)) => tx_hash,
+            ref r => panic!("Session failed: {:?}", r),
+        };
+        for peer in honest {
+            match peer.result {
+                Some(Ok(h)) => assert_eq!(h, tx_hash),
+                ref r => panic!("Session failed: {:?}", r),
+            }
+            assert!(
+                peer.is_excluded(faulty, reason),
+                "Expected exclusion: reason={:?}, excluded={:?}",
+                reason,
+                peer.snowball.log.excluded
+            );
+        }
+    }
+
+    #[test]
+    fn session_honest() {
+        let peers = run_session(None);
+        let tx_hash = match peers[0].result {
+            Some(Ok(tx_hash)) => tx_hash,
+            ref r => panic!("Session failed: {:?}", r),
+        };
+        for peer in &peers {
+            match peer.result {
+                Some(Ok(h)) => assert_eq!(h, tx_hash),
+                ref r => panic!("Session failed: {:?}", r),
+            }
+            assert!(peer.snowball.log.excluded.is_empty());
+            assert_eq!(peer.snowball.log.rounds, 1);
+        }
+        assert_eq!(peers[0].snowball.trans.txins.len(), NUM_PARTICIPANTS);
+    }
+
+    #[test]
+    fn session_dropouts() {
+        // Drop-outs are excluded by timeout at any phase.
+        for state in &PHASES[..4] {
+            check_session(Fault::DropOut(*state), SnowballExclusionReason::Timeout);
+        }
+    }
+
+    #[test]
+    fn session_malicious() {
+        // Messages which can't be accepted look like a drop-out.
+        check_session(
+            Fault::Malicious(State::SharedKeying),
+            SnowballExclusionReason::Timeout,
+        );
+        check_session(
+            Fault::Malicious(State::Commitment),
+            SnowballExclusionReason::Timeout,
+        );
+        // Bad contributions are found by blame discovery.
+        check_session(
+            Fault::Malicious(State::CloakedVals),
+            SnowballExclusionReason::Blame,
+        );
+        check_session(
+            Fault::Malicious(State::Signature),
+            SnowballExclusionReason::Blame,
+        );
+    }
+
+    #[test]
+    fn pool_size_limited_by_facilitator() {
+        future::lazy(|| {
+            let (facilitator_skey, facilitator_pkey) = pbc::make_random_keys();
+            let mut peers: Vec<Peer> = (0..NUM_PARTICIPANTS)
+                .map(|_| Peer::new(facilitator_pkey))
+                .collect();
+            // Ask for more participants than the facilitator can pool.
+            for peer in peers.iter_mut() {
+                peer.snowball.min_participants = NUM_PARTICIPANTS as u32 + 1;
+            }
+            form_pool(&mut peers, &facilitator_skey, &facilitator_pkey);
+            Ok::<_, ()>(())
+        })
+        .wait()
+        .unwrap();
+    }
+
+    #[test]
+    fn facilitator_evidence() {
+        future::lazy(|| {
+            let (facilitator_skey, facilitator_pkey) = pbc::make_random_keys();
+            let (_next_skey, next_pkey) = pbc::make_random_keys();
+            let mut peers: Vec<Peer> = (0..NUM_PARTICIPANTS)
+                .map(|_| Peer::new(facilitator_pkey))
+                .collect();
+            let mut participants = collect_joins(&mut peers, &facilitator_pkey);
+            // A signed pool without the first peer.
+            participants.remove(0);
+            let mut pool = PoolInfo {
+                participants,
+                session_id: Hash::random(),
+                max_participants: 0,
+                signature: None,
+            };
+            pool.sign(&facilitator_skey);
+
+            let peer = &mut peers[0];
+            peer.snowball.facilitators = vec![facilitator_pkey, next_pkey];
+            let notification = PoolNotification::Started(pool.clone());
+            let result = peer
+                .snowball
+                .on_pool_notification(facilitator_pkey, notification);
+            assert!(result.unwrap().is_not_ready());
+            assert_eq!(peer.snowball.state(), State::PoolWait);
+
+            // The signed pool is kept as evidence.
+            let log = peer.snowball.session_log(None);
+            assert_eq!(log.facilitators.len(), 1);
+            let blamed = &log.facilitators[0];
+            assert_eq!(blamed.pkey, facilitator_pkey);
+            assert_eq!(blamed.fault, SnowballFacilitatorFault::Excluded);
+            let evidence = PoolInfo::from_buffer(&blamed.pool).unwrap();
+            evidence.validate_signature(&facilitator_pkey).unwrap();
+            assert_eq!(Hash::digest(&evidence), Hash::digest(&pool));
+
+            // Fall back to the next facilitator.
+            match peer.loopback.pop_message().expect("PoolJoin") {
+                MessageFromNode::SendUnicast {
+                    to, protocol_id, ..
+                } => {
+                    assert_eq!(to, next_pkey);
+                    assert_eq!(protocol_id, POOL_JOIN_TOPIC);
+                }
+                msg => panic!("Unexpected message: {:?}", msg),
+            }
+            Ok::<_, ()>(())
+        })
+        .wait()
+        .unwrap();
+    }
+
+    #[test]
+    fn outdated_facilitator() {
+        future::lazy(|| {
+            let (_facilitator_skey, facilitator_pkey) = pbc::make_random_keys();
+
+            // Outdated facilitators neither limit nor sign pools.
+            let mut peers: Vec<Peer> = (0..NUM_PARTICIPANTS)
+                .map(|_| Peer::new(facilitator_pkey))
+                .collect();
+            let pool = PoolInfo {
+                participants: collect_joins(&mut peers, &facilitator_pkey),
+                session_id: Hash::random(),
+                max_participants: 0,
+                signature: None,
+            };
+            announce_pool(&mut peers, &facilitator_pkey, &pool);
+
+            // Up-to-date facilitators must sign pools.
+            let mut peers: Vec<Peer> = (0..NUM_PARTICIPANTS)
+                .map(|_| Peer::new(facilitator_pkey))
+                .collect();
+            let pool = PoolInfo {
+                participants: collect_joins(&mut peers, &facilitator_pkey),
+                session_id: Hash::random(),
+                max_participants: NUM_PARTICIPANTS as u32,
+                signature: None,
+            };
+            let peer = &mut peers[0];
+            let notification = PoolNotification::Started(pool);
+            let result = peer
+                .snowball
+                .on_pool_notification(facilitator_pkey, notification);
+            assert!(result.unwrap().is_not_ready());
+            assert_eq!(peer.snowball.state(), State::PoolWait);
+            let log = peer.snowball.session_log(None);
+            assert_eq!(log.facilitators.len(), 1);
+            assert_eq!(
+                log.facilitators[0].fault,
+                SnowballFacilitatorFault::InvalidSignature
+            );
+            assert!(log.facilitators[0].pool.is_empty());
+            Ok::<_, ()>(())
+        })
+        .wait()
+        .unwrap();
+    }
 }
@@ -22,13 +22,14 @@
 // SOFTWARE.
 
 use crate::api::*;
+use crate::backup::BackupEntry;
 use bit_vec::BitVec;
 use byteorder::{BigEndian, ByteOrder};
 use failure::{bail, Error};
 use log::*;
 use rocksdb::{Direction, IteratorMode, Options, WriteBatch, DB};
 use serde_derive::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::mem;
 use std::path::Path;
 use std::time::{Duration, Instant};
@@ -45,23 +46,56 @@ use tokio_timer::clock;
 const HISTORY: &'static str = "history";
 const UNSPENT: &'static str = "unspent";
 const META: &'static str = "meta";
-const COLON_FAMILIES: &[&'static str] = &[HISTORY, UNSPENT, META];
+const CONTACTS: &'static str = "contacts";
+const LABELS: &'static str = "labels";
+const INVOICES: &'static str = "invoices";
+const SNOWBALL: &'static str = "snowball";
+const COLON_FAMILIES: &[&'static str] =
+    &[HISTORY, UNSPENT, META, CONTACTS, LABELS, INVOICES, SNOWBALL];
+/// Column families saved to backups, all other are recovered from the blockchain.
+const BACKUP_COLON_FAMILIES: &[&'static str] = &[HISTORY, CONTACTS, LABELS, INVOICES];
 
 // Keys in meta cf
 const EPOCH_KEY: &[u8; 5] = b"epoch";
+const CONSOLIDATION_KEY: &[u8; 13] = b"consolidation";
+const MIXED_STAKE_KEY: &[u8; 11] = b"mixed_stake";
 
 /// A special offset used to tore Macro Blocks on the disk.
-const MACRO_BLOCK_OFFSET: u32 = u32::max_value();
+pub(crate) const MACRO_BLOCK_OFFSET: u32 = u32::max_value();
 
 #[derive(Debug, Default, Clone, Copy, PartialOrd, Ord, PartialEq, Eq, Serialize, Deserialize)]
 struct LSN(u64, u32); // use `struct` to disable explicit casts.
 
 type OutputByHashMap = MultiVersionedMap<Hash, OutputValue, LSN>;
 
+/// The kind of transaction which created an incoming output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputSource {
+    /// Entries created by older versions and outputs received from
+    /// macro blocks of epochs which wallet didn't follow.
+    Unknown,
+    /// A regular transaction.
+    Transaction,
+    /// Coinbase of a block proposed by our validator.
+    BlockReward,
+    /// Service award paid by a macro block.
+    ServiceAward,
+}
+
 #[derive(Debug, Clone)]
 pub enum LogEntry {
-    Incoming { output: OutputValue },
-    Outgoing { tx: TransactionValue },
+    Incoming {
+        output: OutputValue,
+        /// Epoch of the block, None for entries created by older versions.
+        epoch: Option<u64>,
+        /// Offset of the micro block, None for macro blocks.
+        offset: Option<u32>,
+        /// Transaction which created the output.
+        source: OutputSource,
+    },
+    Outgoing {
+        tx: TransactionValue,
+    },
 }
 
 ///
@@ -86,6 +120,8 @@ pub struct LightDatabase {
     validators: StakersGroup,
     /// Facilitator.
     facilitator_pkey: pbc::PublicKey,
+    /// Facilitator candidates, in the order of preference.
+    facilitators: Vec<pbc::PublicKey>,
     /// Micro blocks for the current epoch.
     micro_blocks: Vec<MicroBlockHeader>,
 
@@ -110,6 +146,18 @@ pub struct LightDatabase {
     outputs: HashMap<Hash, Hash>,
     /// Transactions that was created in current epoch.
     epoch_transactions: HashSet<Hash>,
+    /// Address book, indexed by name.
+    contacts: BTreeMap<String, ContactValue>,
+    /// User-defined labels of transactions and outputs.
+    labels: HashMap<Hash, String>,
+    /// Invoices, indexed by the hash of reference.
+    invoices: HashMap<Hash, InvoiceValue>,
+    /// Invoices paid since the last call of take_paid_invoices().
+    paid_invoices: Vec<Hash>,
+    /// Policy of background UTXO consolidation.
+    consolidation_policy: ConsolidationPolicy,
+    /// Stake of the Snowball output.
+    mixed_stake: Option<MixedStakeValue>,
 }
 
 impl LightDatabase {
@@ -132,6 +180,7 @@ impl LightDatabase {
             last_macro_block_timestamp: Timestamp::now(),
             validators: vec![],
             facilitator_pkey: pbc::PublicKey::dum(),
+            facilitators: Vec::new(),
             micro_blocks: Vec::new(),
             created_txs: HashMap::new(),
             locked_inputs: HashMap::new(),
@@ -143,6 +192,12 @@ impl LightDatabase {
             known_changes: HashSet::new(),
             utxos: MultiVersionedMap::new(),
             current_epoch_balance_changed: false,
+            contacts: BTreeMap::new(),
+            labels: HashMap::new(),
+            invoices: HashMap::new(),
+            paid_invoices: Vec::new(),
+            consolidation_policy: ConsolidationPolicy::default(),
+            mixed_stake: None,
         };
         log.recover_state();
         log
@@ -200,6 +255,14 @@ impl LightDatabase {
         &self.facilitator_pkey
     }
 
+    /// Returns facilitator candidates, in the order of preference.
+    pub fn facilitators(&self) -> Vec<pbc::PublicKey> {
+        if self.facilitators.is_empty() {
+            return vec![self.facilitator_pkey];
+        }
+        self.facilitators.clone()
+    }
+
     /// Returns chain configuration.
     #[inline(always)]
     pub fn cfg(&self) -> &ChainConfig {
@@ -293,6 +356,7 @@ impl LightDatabase {
         self.iter_unspent()
             .filter_map(|(k, v)| v.payment().map(|v| (k, v)))
             .filter(move |(h, _)| self.locked_inputs.get(h).is_none())
+            .filter(move |(h, _)| !self.is_mixed_stake_output(h))
             .inspect(|(h, _)| trace!("Using PaymentOutput: hash={}", h))
             .map(|(_, v)| (v.output, v.amount))
     }
@@ -318,9 +382,53 @@ impl LightDatabase {
             .map(|(o, _e)| o)
     }
 
+    /// Returns an iterator over all stake outputs bound to the validator,
+    /// including outputs which are still locked in the escrow.
+    pub fn validator_stake_outputs<'a>(
+        &'a self,
+        validator_pkey: &'a pbc::PublicKey,
+    ) -> impl Iterator<Item = (Hash, StakeOutput)> + 'a {
+        self.iter_unspent()
+            .filter_map(|(k, v)| v.stake().map(|v| (k, v.output)))
+            .filter(move |(_h, o)| o.validator == *validator_pkey)
+    }
+
+    /// Returns commission declared on the most recent own stake of the validator.
+    pub fn validator_commission(&self, validator_pkey: &pbc::PublicKey) -> Option<u32> {
+        self.iter_unspent()
+            .filter_map(|(_k, v)| v.stake())
+            .filter(|v| v.output.validator == *validator_pkey && !v.output.delegated)
+            .max_by_key(|v| v.active_until_epoch)
+            .map(|v| v.output.commission)
+    }
+
     /// Returns id of first unknown epoch
     fn recover_state(&mut self) {
+        self.recover_address_book();
+        self.recover_invoices();
+        // HISTORY can be restored from a backup without META.
+        self.recover_history();
         let meta_cf = self.database.cf_handle(META).expect("META cf created");
+        if let Some(policy) = self
+            .database
+            .get_cf(meta_cf, CONSOLIDATION_KEY)
+            .expect("cannot read consolidation_key")
+        {
+            self.consolidation_policy =
+                ConsolidationPolicy::from_buffer(&policy).expect("ConsolidationPolicy is valid");
+        }
+        if let Some(mixed_stake) = self
+            .database
+            .get_cf(meta_cf, MIXED_STAKE_KEY)
+            .expect("cannot read mixed_stake_key")
+        {
+            let mixed_stake =
+                MixedStakeValue::from_buffer(&mixed_stake).expect("MixedStakeValue is valid");
+            // Snowball sessions don't survive restarts.
+            if mixed_stake.output.is_some() {
+                self.mixed_stake = Some(mixed_stake);
+            }
+        }
         let epoch_info = match self
             .database
             .get_cf(meta_cf, EPOCH_KEY)
@@ -339,6 +447,11 @@ impl LightDatabase {
         self.last_macro_block_random = epoch_info.header.random.rand;
         self.last_macro_block_timestamp = epoch_info.header.timestamp;
         self.facilitator_pkey = epoch_info.facilitator;
+        self.facilitators = election::select_facilitators(
+            &epoch_info.header.random.rand,
+            &epoch_info.validators,
+            election::FACILITATOR_CANDIDATES,
+        );
         self.validators = epoch_info.validators;
         let lsn = LSN(epoch_info.header.epoch, MACRO_BLOCK_OFFSET);
         let cf_unspent = self
@@ -358,6 +471,14 @@ impl LightDatabase {
             self.utxos.insert(lsn, unspent_hash, unspent);
         }
 
+        info!(
+            "Recovered database: epoch={}, last_macro_block={}",
+            self.epoch, self.last_macro_block_hash
+        );
+    }
+
+    /// Rebuilds in-memory indexes of outputs and transactions from HISTORY.
+    fn recover_history(&mut self) {
         let starting_time = Timestamp::UNIX_EPOCH;
         // Motivation: We need to update in memory indexes while iterating over DB.
         // 1) We update only in memory indexes, without modifying database.
@@ -368,7 +489,7 @@ impl LightDatabase {
 
         for (timestamp, entry) in static_iter {
             match entry {
-                LogEntry::Incoming { output } => {
+                LogEntry::Incoming { output, .. } => {
                     let output_hash = Hash::digest(&output.to_output());
                     trace!("Recovered output: output={}", output_hash,);
                     assert!(self.utxos_list.insert(output_hash, timestamp).is_none());
@@ -389,11 +510,298 @@ impl LightDatabase {
             }
         }
         drop(static_db);
+        debug!(
+            "Recovered history: outputs={}, transactions={}",
+            self.utxos_list.len(),
+            self.created_txs.len()
+        );
+    }
+
+    /// Loads the address book and labels.
+    fn recover_address_book(&mut self) {
+        let contacts_cf = self
+            .database
+            .cf_handle(CONTACTS)
+            .expect("CONTACTS cf created");
+        for (name, contact) in self
+            .database
+            .iterator_cf(contacts_cf, IteratorMode::Start)
+            .expect("Cannot read CONTACTS cf.")
+        {
+            let name =
+                String::from_utf8(name.to_vec()).expect("couldn't deserialize CONTACTS key.");
+            let contact =
+                ContactValue::from_buffer(&*contact).expect("couldn't deserialize CONTACTS entry.");
+            self.contacts.insert(name, contact);
+        }
+
+        let labels_cf = self.database.cf_handle(LABELS).expect("LABELS cf created");
+        for (hash, label) in self
+            .database
+            .iterator_cf(labels_cf, IteratorMode::Start)
+            .expect("Cannot read LABELS cf.")
+        {
+            let hash = Hash::from_buffer(&*hash).expect("couldn't deserialize LABELS key.");
+            let label =
+                String::from_utf8(label.to_vec()).expect("couldn't deserialize LABELS entry.");
+            self.labels.insert(hash, label);
+        }
+        debug!(
+            "Recovered address book: contacts={}, labels={}",
+            self.contacts.len(),
+            self.labels.len()
+        );
+    }
 
+    /// Creates a new database from backup entries.
+    pub fn restore_backup(path: &Path, entries: &[BackupEntry]) -> Result<(), Error> {
+        if path.exists() {
+            bail!("Database already exists: path={:?}", path);
+        }
+        let mut opts = Options::default();
+        opts.create_if_missing(true);
+        opts.create_missing_column_families(true);
+        let database = DB::open_cf(&opts, path, COLON_FAMILIES)?;
+        let mut batch = WriteBatch::default();
+        for entry in entries {
+            if !BACKUP_COLON_FAMILIES.contains(&entry.cf.as_str()) {
+                bail!("Unexpected column family in backup: cf={}", entry.cf);
+            }
+            let cf = database.cf_handle(&entry.cf).expect("cf created");
+            batch.put_cf(cf, &entry.key, &entry.value)?;
+        }
+        database.write(batch)?;
         info!(
-            "Recovered database: epoch={}, last_macro_block={}",
-            self.epoch, self.last_macro_block_hash
+            "Restored database from backup: path={:?}, entries={}",
+            path,
+            entries.len()
         );
+        Ok(())
+    }
+
+    /// Returns raw entries which should be saved to backups.
+    pub fn backup_entries(&self) -> Vec<BackupEntry> {
+        let mut entries = Vec::new();
+        for cf_name in BACKUP_COLON_FAMILIES {
+            let cf = self.database.cf_handle(cf_name).expect("cf created");
+            for (key, value) in self
+                .database
+                .iterator_cf(cf, IteratorMode::Start)
+                .expect("cannot open cf")
+            {
+                entries.push(BackupEntry {
+                    cf: cf_name.to_string(),
+                    key: key.to_vec(),
+                    value: value.to_vec(),
+                });
+            }
+        }
+        entries
+    }
+
+    /// Loads invoices.
+    fn recover_invoices(&mut self) {
+        let invoices_cf = self
+            .database
+            .cf_handle(INVOICES)
+            .expect("INVOICES cf created");
+        for (invoice_id, invoice) in self
+            .database
+            .iterator_cf(invoices_cf, IteratorMode::Start)
+            .expect("Cannot read INVOICES cf.")
+        {
+            let invoice_id =
+                Hash::from_buffer(&*invoice_id).expect("couldn't deserialize INVOICES key.");
+            let invoice =
+                InvoiceValue::from_buffer(&*invoice).expect("couldn't deserialize INVOICES entry.");
+            self.invoices.insert(invoice_id, invoice);
+        }
+        debug!("Recovered invoices: invoices={}", self.invoices.len());
+    }
+
+    /// Adds a new contact or replaces the existing one.
+    pub fn add_contact(&mut self, name: String, contact: ContactValue) -> Result<(), Error> {
+        let cf = self.database.cf_handle(CONTACTS).expect("cf created");
+        let data = contact.into_buffer()?;
+        let mut batch = WriteBatch::default();
+        batch.put_cf(cf, name.as_bytes(), &data)?;
+        self.database.write(batch)?;
+        self.contacts.insert(name, contact);
+        Ok(())
+    }
+
+    /// Removes a contact from the address book.
+    pub fn remove_contact(&mut self, name: &str) -> Result<Option<ContactValue>, Error> {
+        let contact = match self.contacts.remove(name) {
+            Some(contact) => contact,
+            None => return Ok(None),
+        };
+        let cf = self.database.cf_handle(CONTACTS).expect("cf created");
+        let mut batch = WriteBatch::default();
+        batch.delete_cf(cf, name.as_bytes())?;
+        self.database.write(batch)?;
+        Ok(Some(contact))
+    }
+
+    /// Returns a contact by name.
+    pub fn contact(&self, name: &str) -> Option<&ContactValue> {
+        self.contacts.get(name)
+    }
+
+    /// Returns the name of a contact by its account key.
+    pub fn contact_name(&self, account_pkey: &scc::PublicKey) -> Option<&String> {
+        self.contacts
+            .iter()
+            .find(|(_name, contact)| &contact.account_pkey == account_pkey)
+            .map(|(name, _contact)| name)
+    }
+
+    /// Returns an iterator over the address book, ordered by name.
+    pub fn iter_contacts<'a>(
+        &'a self,
+    ) -> impl Iterator<Item = (&'a String, &'a ContactValue)> + 'a {
+        self.contacts.iter()
+    }
+
+    /// Attaches a label to a transaction or an output.
+    /// An empty label removes the existing one.
+    pub fn set_label(&mut self, hash: Hash, label: String) -> Result<(), Error> {
+        let cf = self.database.cf_handle(LABELS).expect("cf created");
+        let key = hash.into_buffer()?;
+        let mut batch = WriteBatch::default();
+        if label.is_empty() {
+            batch.delete_cf(cf, &key)?;
+            self.database.write(batch)?;
+            self.labels.remove(&hash);
+        } else {
+            batch.put_cf(cf, &key, label.as_bytes())?;
+            self.database.write(batch)?;
+            self.labels.insert(hash, label);
+        }
+        Ok(())
+    }
+
+    /// Returns a label of a transaction or an output.
+    pub fn label(&self, hash: &Hash) -> Option<&String> {
+        self.labels.get(hash)
+    }
+
+    /// Adds a new invoice or updates the existing one.
+    pub fn put_invoice(&mut self, invoice_id: Hash, invoice: InvoiceValue) -> Result<(), Error> {
+        let cf = self.database.cf_handle(INVOICES).expect("cf created");
+        let key = invoice_id.into_buffer()?;
+        let data = invoice.into_buffer()?;
+        let mut batch = WriteBatch::default();
+        batch.put_cf(cf, &key, &data)?;
+        self.database.write(batch)?;
+        self.invoices.insert(invoice_id, invoice);
+        Ok(())
+    }
+
+    /// Returns an invoice by the hash of its reference.
+    pub fn invoice(&self, invoice_id: &Hash) -> Option<&InvoiceValue> {
+        self.invoices.get(invoice_id)
+    }
+
+    /// Returns an iterator over all invoices.
+    pub fn iter_invoices<'a>(&'a self) -> impl Iterator<Item = (&'a Hash, &'a InvoiceValue)> + 'a {
+        self.invoices.iter()
+    }
+
+    /// Returns invoices paid since the last call.
+    pub fn take_paid_invoices(&mut self) -> Vec<Hash> {
+        mem::replace(&mut self.paid_invoices, Vec::new())
+    }
+
+    /// Returns the policy of background UTXO consolidation.
+    pub fn consolidation_policy(&self) -> &ConsolidationPolicy {
+        &self.consolidation_policy
+    }
+
+    /// Changes the policy of background UTXO consolidation.
+    pub fn set_consolidation_policy(&mut self, policy: ConsolidationPolicy) -> Result<(), Error> {
+        let cf = self.database.cf_handle(META).expect("cf created");
+        let data = policy.into_buffer()?;
+        let mut batch = WriteBatch::default();
+        batch.put_cf(cf, CONSOLIDATION_KEY, &data)?;
+        self.database.write(batch)?;
+        self.consolidation_policy = policy;
+        Ok(())
+    }
+
+    /// Returns the stake waiting for Snowball or for its output.
+    pub fn mixed_stake(&self) -> Option<&MixedStakeValue> {
+        self.mixed_stake.as_ref()
+    }
+
+    /// Changes the stake waiting for Snowball, None when it is done or failed.
+    pub fn set_mixed_stake(&mut self, mixed_stake: Option<MixedStakeValue>) -> Result<(), Error> {
+        let cf = self.database.cf_handle(META).expect("cf created");
+        let mut batch = WriteBatch::default();
+        match &mixed_stake {
+            Some(mixed_stake) => {
+                let data = mixed_stake.into_buffer()?;
+                batch.put_cf(cf, MIXED_STAKE_KEY, &data)?;
+            }
+            None => batch.delete_cf(cf, MIXED_STAKE_KEY)?,
+        }
+        self.database.write(batch)?;
+        self.mixed_stake = mixed_stake;
+        Ok(())
+    }
+
+    /// Returns the Snowball output to stake, if it is unspent.
+    pub fn mixed_stake_output(&self) -> Option<(PaymentOutput, i64)> {
+        let output_hash = self.mixed_stake.as_ref()?.output?;
+        match self.utxos.get(&output_hash) {
+            Some(OutputValue::Payment(p)) => Some((p.output.clone(), p.amount)),
+            _ => None,
+        }
+    }
+
+    /// The Snowball output is reserved for staking.
+    fn is_mixed_stake_output(&self, output_hash: &Hash) -> bool {
+        match &self.mixed_stake {
+            Some(MixedStakeValue {
+                output: Some(output),
+                ..
+            }) => output == output_hash,
+            _ => false,
+        }
+    }
+
+    /// Marks an invoice as paid if the payment matches its reference.
+    fn match_invoice(
+        &mut self,
+        payment: &PaymentValue,
+        output_hash: Hash,
+        timestamp: Timestamp,
+    ) -> Result<(), Error> {
+        let invoice_id = Hash::digest(&payment.data);
+        let mut invoice = match self.invoices.get(&invoice_id) {
+            Some(invoice) => invoice.clone(),
+            None => return Ok(()),
+        };
+        if invoice.paid.is_some() || invoice.is_expired(timestamp) {
+            return Ok(());
+        }
+        if payment.amount < invoice.amount {
+            warn!(
+                "Invoice is underpaid: invoice={}, utxo={}, amount={}, expected={}",
+                invoice_id, output_hash, payment.amount, invoice.amount
+            );
+            return Ok(());
+        }
+        info!("Invoice paid: invoice={}, utxo={}", invoice_id, output_hash);
+        invoice.paid = Some((output_hash, timestamp));
+        self.put_invoice(invoice_id, invoice)?;
+        self.paid_invoices.push(invoice_id);
+        Ok(())
+    }
+
+    /// Returns true if the output is known and not spent yet.
+    pub fn is_unspent(&self, output_hash: &Hash) -> bool {
+        self.utxos.get(output_hash).is_some()
     }
 
     pub fn iter_unspent<'a>(&'a self) -> impl Iterator<Item = (Hash, OutputValue)> + 'a {
@@ -486,6 +894,7 @@ impl LightDatabase {
         timestamp: Timestamp,
         input_hashes: Vec<Hash>,
         outputs: Vec<OutputValue>,
+        sources: HashMap<Hash, OutputSource>,
     ) -> HashMap<Hash, TransactionStatus> {
         //
         // Process inputs.
@@ -546,7 +955,19 @@ impl LightDatabase {
             assert_eq!(self.utxos.current_lsn(), lsn);
 
             // Update history.
-            self.push_incoming(timestamp, output_value.clone().into())
+            let epoch = self.epoch;
+            let source = sources
+                .get(&output_hash)
+                .cloned()
+                .unwrap_or(OutputSource::Transaction);
+            let output_timestamp = self
+                .push_incoming(
+                    timestamp,
+                    output_value.clone().into(),
+                    epoch,
+                    offset,
+                    source,
+                )
                 .expect("I/O error");
 
             match output_value {
@@ -555,6 +976,12 @@ impl LightDatabase {
                         "Received: utxo={}, amount={}, data={:?}",
                         output_hash, p.amount, p.data
                     );
+                    // Micro blocks can be reverted, invoices are paid only by macro blocks.
+                    // The payment time is the time when the output was seen at first.
+                    if !p.is_change && offset.is_none() {
+                        self.match_invoice(p, output_hash, output_timestamp)
+                            .expect("I/O error");
+                    }
                 }
                 OutputValue::PublicPayment(p) => {
                     let PublicPaymentOutput { ref amount, .. } = &p.output;
@@ -910,6 +1337,7 @@ impl LightDatabase {
         validators: StakersGroup,
         account_pkey: &scc::PublicKey,
         account_skey: &scc::SecretKey,
+        network_pkey: &pbc::PublicKey,
     ) -> HashMap<Hash, TransactionStatus>
     where
         InputsIter: Iterator<Item = &'a Hash>,
@@ -921,6 +1349,28 @@ impl LightDatabase {
         let (my_inputs, my_outputs) =
             self.filter_inputs_and_outputs(inputs_iter, outputs_iter, account_pkey, account_skey);
 
+        //
+        // Outputs which were not seen in micro blocks were created by the macro block itself.
+        // This is known only if all micro blocks of the epoch were processed.
+        //
+        let is_epoch_followed = !self.micro_blocks.is_empty();
+        let mut sources: HashMap<Hash, OutputSource> = HashMap::new();
+        for output_value in &my_outputs {
+            let output_hash = Hash::digest(&output_value.to_output());
+            if self.utxos_list.contains_key(&output_hash) {
+                continue;
+            }
+            let source = match output_value {
+                _ if !is_epoch_followed => OutputSource::Unknown,
+                OutputValue::Payment(_) if &header.pkey == network_pkey => {
+                    OutputSource::BlockReward
+                }
+                OutputValue::PublicPayment(_) => OutputSource::ServiceAward,
+                _ => OutputSource::Transaction,
+            };
+            sources.insert(output_hash, source);
+        }
+
         //
         // Revert micro blocks.
         //
@@ -941,6 +1391,7 @@ impl LightDatabase {
             header.timestamp,
             my_inputs,
             my_outputs,
+            sources,
         );
         for (tx_hash, tx_status) in transaction_statuses2 {
             transaction_statuses.insert(tx_hash, tx_status);
@@ -948,6 +1399,11 @@ impl LightDatabase {
 
         let facilitator = election::select_facilitator(&header.random.rand, &validators);
         self.facilitator_pkey = facilitator;
+        self.facilitators = election::select_facilitators(
+            &header.random.rand,
+            &validators,
+            election::FACILITATOR_CANDIDATES,
+        );
         self.epoch += 1;
         self.micro_blocks.clear();
         self.last_macro_block_hash = block_hash;
@@ -1016,6 +1472,7 @@ impl LightDatabase {
         outputs_iter: OutputsIter,
         account_pkey: &scc::PublicKey,
         account_skey: &scc::SecretKey,
+        network_pkey: &pbc::PublicKey,
     ) -> HashMap<Hash, TransactionStatus>
     where
         InputsIter: Iterator<Item = &'a Hash>,
@@ -1028,6 +1485,15 @@ impl LightDatabase {
         let epoch = self.epoch;
         let offset = self.offset();
 
+        // Coinbase is always the first transaction of a micro block.
+        let mut outputs_iter = outputs_iter.peekable();
+        let mut sources: HashMap<Hash, OutputSource> = HashMap::new();
+        if &header.pkey == network_pkey {
+            if let Some(Output::PaymentOutput(o)) = outputs_iter.peek() {
+                sources.insert(Hash::digest(o), OutputSource::BlockReward);
+            }
+        }
+
         let (my_inputs, my_outputs) =
             self.filter_inputs_and_outputs(inputs_iter, outputs_iter, account_pkey, account_skey);
         let is_balance_changed = my_outputs.len() > 0;
@@ -1044,6 +1510,7 @@ impl LightDatabase {
             header.timestamp,
             my_inputs,
             my_outputs,
+            sources,
         );
         self.micro_blocks.push(header);
 
@@ -1286,11 +1753,19 @@ impl LightDatabase {
         self.created_txs.get(&tx_hash).cloned()
     }
 
+    /// Returns exact timestamp of received output, if output found.
+    pub fn output_entry(&self, output_hash: Hash) -> Option<Timestamp> {
+        self.utxos_list.get(&output_hash).cloned()
+    }
+
     /// Insert log entry as last entry in log.
     fn push_incoming(
         &mut self,
         timestamp: Timestamp,
         incoming: OutputValue,
+        epoch: u64,
+        offset: Option<u32>,
+        source: OutputSource,
     ) -> Result<Timestamp, Error> {
         let output_hash = Hash::digest(&incoming.to_output());
         trace!("Push incoming utxo = {:?}", output_hash);
@@ -1299,7 +1774,12 @@ impl LightDatabase {
             return Ok(*time);
         }
 
-        let entry = LogEntry::Incoming { output: incoming };
+        let entry = LogEntry::Incoming {
+            output: incoming,
+            epoch: Some(epoch),
+            offset,
+            source,
+        };
         let timestamp = self.push_entry(timestamp, entry)?;
         assert!(self.utxos_list.insert(output_hash, timestamp).is_none());
         Ok(timestamp)
@@ -1397,6 +1877,19 @@ impl LightDatabase {
 
         // If status updated, update indexes.
         if let Some(tx) = updated_tx {
+            // A secure stake is dropped together with its Snowball transaction.
+            if let TransactionStatus::Rejected { .. } | TransactionStatus::Conflicted { .. } =
+                tx.status
+            {
+                let stakes_output = tx.outputs.iter().any(|output| match output {
+                    OutputValue::Payment(p) => self.is_mixed_stake_output(&Hash::digest(&p.output)),
+                    _ => false,
+                });
+                if stakes_output {
+                    warn!("Snowball transaction failed, cancelling secure stake");
+                    self.set_mixed_stake(None)?;
+                }
+            }
             self.update_tx_indexes(tx);
         }
         Ok(())
@@ -1431,6 +1924,44 @@ impl LightDatabase {
         Self::iter_range_inner(&self.database, starting_from, limit)
     }
 
+    /// Save the outcome of a Snowball session.
+    pub fn push_snowball_session(&mut self, session: &SnowballSessionInfo) -> Result<(), Error> {
+        let snowball_cf = self.database.cf_handle(SNOWBALL).expect("cf created");
+        let data = session.into_buffer().expect("couldn't serialize session.");
+
+        // avoid key collisions by increasing time.
+        let mut timestamp = session.started;
+        while let Some(_) = self
+            .database
+            .get_cf(snowball_cf, &Self::bytes_from_timestamp(timestamp))?
+        {
+            timestamp += Duration::from_millis(1);
+        }
+
+        let mut batch = WriteBatch::default();
+        batch.put_cf(snowball_cf, &Self::bytes_from_timestamp(timestamp), &data)?;
+        self.database.write(batch)?;
+        Ok(())
+    }
+
+    /// List Snowball sessions starting from `starting_from`, limited by `limit`.
+    pub fn iter_snowball_sessions<'a>(
+        &'a self,
+        starting_from: Timestamp,
+        limit: u64,
+    ) -> impl Iterator<Item = SnowballSessionInfo> + 'a {
+        let snowball_cf = self.database.cf_handle(SNOWBALL).expect("cf created");
+        let key = Self::bytes_from_timestamp(starting_from);
+        let mode = IteratorMode::From(&key, Direction::Forward);
+        self.database
+            .iterator_cf(snowball_cf, mode)
+            .expect("cannot open cf")
+            .map(|(_k, v)| {
+                SnowballSessionInfo::from_buffer(&*v).expect("couldn't deserialize session.")
+            })
+            .take(limit as usize)
+    }
+
     //
     // Internal api.
     //
@@ -1493,6 +2024,47 @@ pub struct TransactionValue {
     pub outputs: Vec<OutputValue>,
 }
 
+/// An invoice created by this account.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InvoiceValue {
+    pub amount: i64,
+    /// Reference, which must be included into the payment.
+    pub data: PaymentPayloadData,
+    pub created: Timestamp,
+    pub expires: Option<Timestamp>,
+    /// UTXO and time of the payment.
+    pub paid: Option<(Hash, Timestamp)>,
+}
+
+/// A stake waiting for its coins to be shuffled by Snowball.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MixedStakeValue {
+    pub amount: i64,
+    pub payment_fee: i64,
+    /// Snowball output to stake, known when Snowball has succeeded.
+    pub output: Option<Hash>,
+}
+
+impl MixedStakeValue {
+    /// Finds the Snowball output which pays for the stake and its fees.
+    pub fn find_output(&self, outputs: &[OutputValue], stake_fee: i64) -> Option<Hash> {
+        let amount = self.amount + self.payment_fee + stake_fee;
+        outputs.iter().find_map(|output| match output {
+            OutputValue::Payment(p) if !p.is_change && p.amount == amount => {
+                Some(Hash::digest(&p.output))
+            }
+            _ => None,
+        })
+    }
+}
+
+/// An entry of the address book.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ContactValue {
+    pub account_pkey: scc::PublicKey,
+    pub notes: String,
+}
+
 /// Represents Outputs created by account.
 /// With extended info about its creation.
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -1620,6 +2192,7 @@ impl TransactionValue {
             fee: self.tx.fee,
             inputs: self.tx.txins.clone(),
             status: self.status.clone(),
+            label: None,
         }
     }
 }
@@ -1647,6 +2220,8 @@ impl PaymentValue {
             recipient: self.recipient,
             rvalue: self.rvalue.clone(),
             is_change: self.is_change,
+            contact: None,
+            label: None,
         }
     }
 }
@@ -1659,6 +2234,18 @@ impl PublicPaymentValue {
             amount: self.output.amount,
             pending_timestamp,
             recipient: self.output.recipient,
+            contact: None,
+            label: None,
+        }
+    }
+}
+
+impl ContactValue {
+    pub fn to_info(&self, name: String) -> ContactInfo {
+        ContactInfo {
+            name,
+            account_pkey: self.account_pkey,
+            notes: self.notes.clone(),
         }
     }
 }
@@ -1674,6 +2261,13 @@ impl StakeValue {
             amount: self.output.amount,
             active_until_epoch: self.active_until_epoch,
             is_active,
+            commission: self.output.commission,
+            delegated: self.output.delegated,
+            maintenance_epoch: if self.output.maintenance_epoch != 0 {
+                Some(self.output.maintenance_epoch)
+            } else {
+                None
+            },
         }
     }
 }
@@ -1759,6 +2353,58 @@ impl Hashable for PublicPaymentValue {
     }
 }
 
+impl InvoiceValue {
+    pub fn is_expired(&self, timestamp: Timestamp) -> bool {
+        match self.expires {
+            Some(expires) => timestamp > expires,
+            None => false,
+        }
+    }
+
+    pub fn to_info(
+        &self,
+        invoice_id: Hash,
+        recipient: &scc::PublicKey,
+        now: Timestamp,
+    ) -> InvoiceInfo {
+        let request = PaymentRequest {
+            recipient: *recipient,
+            amount: self.amount,
+            data: self.data.clone(),
+            expires: self.expires,
+        };
+        let status = match self.paid {
+            Some((utxo, paid_at)) => InvoiceStatus::Paid { utxo, paid_at },
+            None if self.is_expired(now) => InvoiceStatus::Expired,
+            None => InvoiceStatus::Unpaid,
+        };
+        InvoiceInfo {
+            invoice_id,
+            uri: request.to_string(),
+            amount: self.amount,
+            data: self.data.clone(),
+            created: self.created,
+            expires: self.expires,
+            status,
+        }
+    }
+}
+
+impl Hashable for InvoiceValue {
+    fn hash(&self, hasher: &mut Hasher) {
+        self.amount.hash(hasher);
+        self.data.hash(hasher);
+        self.created.hash(hasher);
+        if let Some(expires) = &self.expires {
+            expires.hash(hasher);
+        }
+        if let Some((utxo, paid_at)) = &self.paid {
+            utxo.hash(hasher);
+            paid_at.hash(hasher);
+        }
+    }
+}
+
 impl Hashable for StakeValue {
     fn hash(&self, hasher: &mut Hasher) {
         self.output.hash(hasher);
@@ -1790,6 +2436,9 @@ mod test {
 
             LogEntry::Incoming {
                 output: OutputValue::PublicPayment(public),
+                epoch: Some(0),
+                offset: Some(id as u32),
+                source: OutputSource::Transaction,
             }
         }
 
@@ -1905,4 +2554,401 @@ mod test {
         // TODO: check
         drop(db);
     }
+
+    #[test]
+    fn address_book() {
+        let _ = simple_logger::init();
+
+        let temp_dir = TempDir::new("account").expect("couldn't create temp dir");
+        let mut db = LightDatabase::testing(temp_dir.path());
+        let (_skey, alice_pkey) = scc::make_random_keys();
+        let (_skey, bob_pkey) = scc::make_random_keys();
+        let alice = ContactValue {
+            account_pkey: alice_pkey,
+            notes: "Alice".to_string(),
+        };
+        let bob = ContactValue {
+            account_pkey: bob_pkey,
+            notes: String::new(),
+        };
+        db.add_contact("alice".to_string(), alice.clone()).unwrap();
+        db.add_contact("bob".to_string(), bob.clone()).unwrap();
+        let tx_hash = Hash::digest("tx");
+        db.set_label(tx_hash, "rent".to_string()).unwrap();
+        drop(db);
+
+        // Reopen the database.
+        let mut db = LightDatabase::testing(temp_dir.path());
+        assert_eq!(db.contact("alice"), Some(&alice));
+        assert_eq!(db.contact_name(&bob_pkey), Some(&"bob".to_string()));
+        let names: Vec<&String> = db.iter_contacts().map(|(name, _)| name).collect();
+        assert_eq!(names, vec!["alice", "bob"]);
+        assert_eq!(db.label(&tx_hash), Some(&"rent".to_string()));
+
+        assert_eq!(db.remove_contact("alice").unwrap(), Some(alice));
+        assert_eq!(db.remove_contact("alice").unwrap(), None);
+        db.set_label(tx_hash, String::new()).unwrap();
+        drop(db);
+
+        let db = LightDatabase::testing(temp_dir.path());
+        assert_eq!(db.contact("alice"), None);
+        assert_eq!(db.contact("bob"), Some(&bob));
+        assert_eq!(db.label(&tx_hash), None);
+    }
+
+    #[test]
+    fn invoices() {
+        let _ = simple_logger::init();
+
+        let temp_dir = TempDir::new("account").expect("couldn't create temp dir");
+        let mut db = LightDatabase::testing(temp_dir.path());
+        let (_skey, account_pkey) = scc::make_random_keys();
+        let now = Timestamp::now();
+        let data = PaymentPayloadData::Comment("Order #42".to_string());
+        let invoice_id = Hash::digest(&data);
+        let invoice = InvoiceValue {
+            amount: 100,
+            data: data.clone(),
+            created: now,
+            expires: Some(now + Duration::from_secs(60)),
+            paid: None,
+        };
+        db.put_invoice(invoice_id, invoice.clone()).unwrap();
+
+        let payment = |amount: i64| {
+            let (output, _gamma, rvalue) =
+                PaymentOutput::with_payload(None, &account_pkey, amount, data.clone()).unwrap();
+            let output_hash = Hash::digest(&output);
+            let value = PaymentValue {
+                output,
+                amount,
+                data: data.clone(),
+                recipient: account_pkey,
+                rvalue: Some(rvalue),
+                is_change: false,
+            };
+            (value, output_hash)
+        };
+
+        // Underpaid.
+        let (value, output_hash) = payment(99);
+        db.match_invoice(&value, output_hash, now).unwrap();
+        assert!(db.take_paid_invoices().is_empty());
+
+        // Expired.
+        let (value, output_hash) = payment(100);
+        let late = now + Duration::from_secs(61);
+        db.match_invoice(&value, output_hash, late).unwrap();
+        assert!(db.take_paid_invoices().is_empty());
+        let info = invoice.to_info(invoice_id, &account_pkey, late);
+        assert_eq!(info.status, InvoiceStatus::Expired);
+
+        // Paid.
+        db.match_invoice(&value, output_hash, now).unwrap();
+        assert_eq!(db.take_paid_invoices(), vec![invoice_id]);
+        assert!(db.take_paid_invoices().is_empty());
+        drop(db);
+
+        // Reopen the database.
+        let db = LightDatabase::testing(temp_dir.path());
+        let invoice = db.invoice(&invoice_id).expect("invoice exists");
+        assert_eq!(invoice.paid, Some((output_hash, now)));
+        let info = invoice.to_info(invoice_id, &account_pkey, now);
+        assert_eq!(
+            info.status,
+            InvoiceStatus::Paid {
+                utxo: output_hash,
+                paid_at: now
+            }
+        );
+        assert!(info.uri.starts_with("stegos:"));
+    }
+
+    #[test]
+    fn invoices_rollback() {
+        let _ = simple_logger::init();
+
+        let temp_dir = TempDir::new("account").expect("couldn't create temp dir");
+        let mut db = LightDatabase::testing(temp_dir.path());
+        let (_skey, account_pkey) = scc::make_random_keys();
+        let now = Timestamp::now();
+        let data = PaymentPayloadData::Comment("Order #42".to_string());
+        let invoice_id = Hash::digest(&data);
+        let invoice = InvoiceValue {
+            amount: 100,
+            data: data.clone(),
+            created: now,
+            expires: Some(now + Duration::from_secs(60)),
+            paid: None,
+        };
+        db.put_invoice(invoice_id, invoice).unwrap();
+
+        let payment = || {
+            let (output, _gamma, rvalue) =
+                PaymentOutput::with_payload(None, &account_pkey, 100, data.clone()).unwrap();
+            let output_hash = Hash::digest(&output);
+            let value = PaymentValue {
+                output,
+                amount: 100,
+                data: data.clone(),
+                recipient: account_pkey,
+                rvalue: Some(rvalue),
+                is_change: false,
+            };
+            (OutputValue::Payment(value), output_hash)
+        };
+        let micro_block_hash = Hash::digest("micro");
+        let macro_block_hash = Hash::digest("macro");
+
+        // The payment is in a micro block.
+        let (value, _output_hash) = payment();
+        db.register_inputs_and_outputs(
+            LSN(1, 0),
+            micro_block_hash,
+            Some(0),
+            now,
+            vec![],
+            vec![value],
+            HashMap::new(),
+        );
+        assert!(db.take_paid_invoices().is_empty());
+        assert_eq!(db.invoice(&invoice_id).unwrap().paid, None);
+
+        // The micro block is reverted, the payment didn't get into the macro block.
+        db.utxos.rollback_to_lsn(LSN(0, MACRO_BLOCK_OFFSET));
+        db.register_inputs_and_outputs(
+            LSN(1, MACRO_BLOCK_OFFSET),
+            macro_block_hash,
+            None,
+            now + Duration::from_secs(10),
+            vec![],
+            vec![],
+            HashMap::new(),
+        );
+        assert!(db.take_paid_invoices().is_empty());
+        assert_eq!(db.invoice(&invoice_id).unwrap().paid, None);
+
+        // Another payment gets into the macro block,
+        // which is finalized after the invoice is expired.
+        let (value, output_hash) = payment();
+        let paid_at = now + Duration::from_secs(20);
+        db.register_inputs_and_outputs(
+            LSN(2, 0),
+            micro_block_hash,
+            Some(0),
+            paid_at,
+            vec![],
+            vec![value.clone()],
+            HashMap::new(),
+        );
+        assert!(db.take_paid_invoices().is_empty());
+        db.utxos.rollback_to_lsn(LSN(1, MACRO_BLOCK_OFFSET));
+        db.register_inputs_and_outputs(
+            LSN(2, MACRO_BLOCK_OFFSET),
+            macro_block_hash,
+            None,
+            now + Duration::from_secs(90),
+            vec![],
+            vec![value],
+            HashMap::new(),
+        );
+        assert_eq!(db.take_paid_invoices(), vec![invoice_id]);
+        let invoice = db.invoice(&invoice_id).unwrap();
+        assert_eq!(invoice.paid, Some((output_hash, paid_at)));
+    }
+
+    #[test]
+    fn backup() {
+        let _ = simple_logger::init();
+
+        let temp_dir = TempDir::new("account").expect("couldn't create temp dir");
+        let mut db = LightDatabase::testing(&temp_dir.path().join("old"));
+        for id in 0..5 {
+            let (time, entry) = create_entry(id);
+            db.push_entry(time, entry).unwrap();
+        }
+        let (_skey, alice_pkey) = scc::make_random_keys();
+        let alice = ContactValue {
+            account_pkey: alice_pkey,
+            notes: String::new(),
+        };
+        db.add_contact("alice".to_string(), alice.clone()).unwrap();
+
+        // A received payment and a sent transaction with change.
+        let (_skey, account_pkey) = scc::make_random_keys();
+        let payment = |amount: i64, is_change: bool| {
+            let data = PaymentPayloadData::Comment(String::new());
+            let (output, _gamma, rvalue) =
+                PaymentOutput::with_payload(None, &account_pkey, amount, data.clone()).unwrap();
+            OutputValue::Payment(PaymentValue {
+                output,
+                amount,
+                data,
+                recipient: account_pkey,
+                rvalue: Some(rvalue),
+                is_change,
+            })
+        };
+        let received = payment(100, false);
+        let received_hash = Hash::digest(&received.to_output());
+        let change = payment(10, true);
+        let change_hash = Hash::digest(&change.to_output());
+        let tx = TransactionValue {
+            tx: PaymentTransaction::dum(),
+            outputs: vec![change.clone()],
+            status: TransactionStatus::Committed { epoch: 0 },
+        };
+        let tx_hash = Hash::digest(&tx.tx);
+        let now = Timestamp::now();
+        db.register_inputs_and_outputs(
+            LSN(0, MACRO_BLOCK_OFFSET),
+            Hash::digest("macro"),
+            None,
+            now,
+            vec![],
+            vec![received.clone()],
+            HashMap::new(),
+        );
+        let tx_time = db.push_outgoing(now, tx).unwrap();
+        let received_time = db.output_entry(received_hash).unwrap();
+        let entries = db.backup_entries();
+        assert_eq!(entries.len(), 8);
+
+        let path = temp_dir.path().join("new");
+        LightDatabase::restore_backup(&path, &entries).unwrap();
+        assert!(LightDatabase::restore_backup(&path, &entries).is_err());
+        let mut db = LightDatabase::testing(&path);
+        assert_eq!(db.contact("alice"), Some(&alice));
+        assert_eq!(db.epoch(), 0);
+        assert_eq!(db.output_entry(received_hash), Some(received_time));
+        assert_eq!(db.tx_entry(tx_hash), Some(tx_time));
+        assert!(db.is_known_changes(change_hash));
+
+        // Rescan the chain, known outputs are not duplicated in the history.
+        db.register_inputs_and_outputs(
+            LSN(0, MACRO_BLOCK_OFFSET),
+            Hash::digest("macro"),
+            None,
+            now + Duration::from_secs(10),
+            vec![],
+            vec![received, change],
+            HashMap::new(),
+        );
+        assert_eq!(db.output_entry(received_hash), Some(received_time));
+        assert_eq!(db.tx_entry(tx_hash), Some(tx_time));
+        assert!(db.is_known_changes(change_hash));
+        assert!(db.output_entry(change_hash).is_some());
+        let history: Vec<(Timestamp, LogEntry)> = db
+            .iter_range(Timestamp::UNIX_EPOCH, u64::max_value())
+            .collect();
+        assert_eq!(history.len(), 8);
+        for (id, (_time, entry)) in history.iter().take(5).enumerate() {
+            assert!(entry.is_testing_stub(id));
+        }
+    }
+
+    #[test]
+    fn consolidation_policy() {
+        let _ = simple_logger::init();
+
+        let temp_dir = TempDir::new("account").expect("couldn't create temp dir");
+        let mut db = LightDatabase::testing(temp_dir.path());
+        assert_eq!(db.consolidation_policy(), &ConsolidationPolicy::default());
+        let policy = ConsolidationPolicy {
+            enabled: true,
+            min_utxos: 10,
+            dust_amount: 100,
+            payment_fee: 1,
+            max_fee: 2,
+            use_snowball: true,
+        };
+        db.set_consolidation_policy(policy.clone()).unwrap();
+        assert_eq!(db.consolidation_policy(), &policy);
+        drop(db);
+
+        // Reopen the database.
+        let db = LightDatabase::testing(temp_dir.path());
+        assert_eq!(db.consolidation_policy(), &policy);
+    }
+
+    #[test]
+    fn mixed_stake() {
+        let _ = simple_logger::init();
+
+        let temp_dir = TempDir::new("account").expect("couldn't create temp dir");
+        let mut db = LightDatabase::testing(temp_dir.path());
+        let (_skey, account_pkey) = scc::make_random_keys();
+        let now = Timestamp::now();
+        let payment = |amount: i64, is_change: bool| {
+            let data = PaymentPayloadData::Comment("Stake".to_string());
+            let (output, _gamma, rvalue) =
+                PaymentOutput::with_payload(None, &account_pkey, amount, data.clone()).unwrap();
+            let output_hash = Hash::digest(&output);
+            let value = PaymentValue {
+                output,
+                amount,
+                data,
+                recipient: account_pkey,
+                rvalue: Some(rvalue),
+                is_change,
+            };
+            (OutputValue::Payment(value), output_hash)
+        };
+
+        // Snowball is running, its session doesn't survive restarts.
+        let mut mixed_stake = MixedStakeValue {
+            amount: 100,
+            payment_fee: 1,
+            output: None,
+        };
+        db.set_mixed_stake(Some(mixed_stake.clone())).unwrap();
+        assert_eq!(db.mixed_stake(), Some(&mixed_stake));
+        drop(db);
+        let mut db = LightDatabase::testing(temp_dir.path());
+        assert_eq!(db.mixed_stake(), None);
+
+        // Snowball has succeeded, find the output which pays for the stake and fees.
+        let stake_fee = 1;
+        let (change, _change_hash) = payment(102, true);
+        let (other, _other_hash) = payment(50, false);
+        let (stake, stake_hash) = payment(102, false);
+        let snowball_outputs = vec![change, other.clone(), stake.clone()];
+        assert_eq!(mixed_stake.find_output(&snowball_outputs, 0), None);
+        mixed_stake.output = mixed_stake.find_output(&snowball_outputs, stake_fee);
+        assert_eq!(mixed_stake.output, Some(stake_hash));
+        db.set_mixed_stake(Some(mixed_stake.clone())).unwrap();
+        // Not committed yet.
+        assert_eq!(db.mixed_stake_output(), None);
+
+        // The output is committed and reserved for staking.
+        db.register_inputs_and_outputs(
+            LSN(1, 0),
+            Hash::digest("micro"),
+            Some(0),
+            now,
+            vec![],
+            vec![other, stake],
+            HashMap::new(),
+        );
+        let (output, amount) = db.mixed_stake_output().expect("committed");
+        assert_eq!(Hash::digest(&output), stake_hash);
+        assert_eq!(amount, 102);
+        let available: Vec<i64> = db
+            .available_payment_outputs()
+            .map(|(_output, amount)| amount)
+            .collect();
+        assert_eq!(available, vec![50]);
+        drop(db);
+
+        // Reopen the database.
+        let mut db = LightDatabase::testing(temp_dir.path());
+        assert_eq!(db.mixed_stake(), Some(&mixed_stake));
+
+        // The output is staked.
+        db.set_mixed_stake(None).unwrap();
+        assert_eq!(db.mixed_stake(), None);
+        drop(db);
+        let db = LightDatabase::testing(temp_dir.path());
+        assert_eq!(db.mixed_stake(), None);
+    }
 }
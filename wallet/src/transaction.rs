@@ -305,12 +305,35 @@ where
     Ok((inputs, outputs, gamma, extended_outputs, fee))
 }
 
+/// Create a new stake UTXO.
+/// The stake is delegated to a third-party validator if `validator_skey` is not provided.
+fn create_stake_output(
+    recipient_pkey: &PublicKey,
+    validator_pkey: &pbc::PublicKey,
+    validator_skey: Option<&pbc::SecretKey>,
+    amount: i64,
+    commission: u32,
+) -> Result<StakeOutput, Error> {
+    match validator_skey {
+        Some(validator_skey) => StakeOutput::new_with_commission(
+            recipient_pkey,
+            validator_skey,
+            validator_pkey,
+            amount,
+            commission,
+        ),
+        None => StakeOutput::new_delegated(recipient_pkey, validator_pkey, amount),
+    }
+}
+
 /// Create a new staking transaction.
+/// Money is delegated to a third-party validator if `validator_skey` is not provided.
 pub(crate) fn create_staking_transaction<'a, UnspentIter>(
     sender_skey: &SecretKey,
     sender_pkey: &PublicKey,
     validator_pkey: &pbc::PublicKey,
-    validator_skey: &pbc::SecretKey,
+    validator_skey: Option<&pbc::SecretKey>,
+    commission: u32,
     unspent_iter: UnspentIter,
     amount: i64,
     payment_fee: i64,
@@ -367,7 +390,13 @@ where
 
     // Create an output for staking.
     trace!("Creating stake UTXO...");
-    let output1 = StakeOutput::new(sender_pkey, validator_skey, validator_pkey, amount)?;
+    let output1 = create_stake_output(
+        sender_pkey,
+        validator_pkey,
+        validator_skey,
+        amount,
+        commission,
+    )?;
     info!(
         "Created stake UTXO: hash={}, recipient={}, validator={}, amount={}",
         Hash::digest(&output1),
@@ -426,12 +455,13 @@ where
 }
 
 /// Create a new unstaking transaction.
+/// Delegated money is withdrawn if `validator_skey` is not provided.
 /// NOTE: amount must include PAYMENT_FEE.
 pub(crate) fn create_unstaking_transaction<'a, UnspentIter>(
     sender_skey: &SecretKey,
     sender_pkey: &PublicKey,
     validator_pkey: &pbc::PublicKey,
-    validator_skey: &pbc::SecretKey,
+    validator_skey: Option<&pbc::SecretKey>,
     unspent_iter: UnspentIter,
     amount: i64,
     payment_fee: i64,
@@ -469,6 +499,15 @@ where
         .into_iter()
         .map(|o| Output::StakeOutput(o.clone()))
         .collect();
+    // Keep commission declared by validator.
+    let commission = inputs
+        .iter()
+        .filter_map(|o| match o {
+            Output::StakeOutput(o) => Some(o.commission),
+            _ => None,
+        })
+        .max()
+        .unwrap_or(0);
     if change > 0 && change <= payment_fee {
         // Stake must be > PAYMENT_FEE.
         return Err(WalletError::AmountTooSmall(payment_fee, change).into());
@@ -518,7 +557,13 @@ where
         // Create an output for staking.
         assert_eq!(fee, payment_fee + stake_fee);
         trace!("Creating stake UTXO...");
-        let output2 = StakeOutput::new(sender_pkey, validator_skey, validator_pkey, change)?;
+        let output2 = create_stake_output(
+            sender_pkey,
+            validator_pkey,
+            validator_skey,
+            change,
+            commission,
+        )?;
         info!(
             "Created stake UTXO: hash={}, validator={}, amount={}",
             Hash::digest(&output2),
@@ -576,7 +621,7 @@ pub mod tests {
             &skey,
             &pkey,
             &validator_pkey,
-            &validator_skey,
+            Some(&validator_skey),
             unspent.clone().into_iter(),
             stake - payment_fee,
             payment_fee,
@@ -602,7 +647,7 @@ pub mod tests {
             &skey,
             &pkey,
             &validator_pkey,
-            &validator_skey,
+            Some(&validator_skey),
             unspent.clone().into_iter(),
             unstake,
             payment_fee,
@@ -633,7 +678,7 @@ pub mod tests {
             &skey,
             &pkey,
             &validator_pkey,
-            &validator_skey,
+            Some(&validator_skey),
             unspent.clone().into_iter(),
             payment_fee - 1,
             payment_fee,
@@ -651,7 +696,7 @@ pub mod tests {
             &skey,
             &pkey,
             &validator_pkey,
-            &validator_skey,
+            Some(&validator_skey),
             unspent.clone().into_iter(),
             payment_fee,
             payment_fee,
@@ -670,7 +715,7 @@ pub mod tests {
             &skey,
             &pkey,
             &validator_pkey,
-            &validator_skey,
+            Some(&validator_skey),
             unspent.clone().into_iter(),
             unstake,
             payment_fee,
@@ -686,4 +731,47 @@ pub mod tests {
             _ => panic!(),
         }
     }
+
+    /// Check withdrawal of delegated money.
+    #[test]
+    fn undelegating_transactions() {
+        let payment_fee: i64 = 1;
+        let stake_fee: i64 = 1;
+        let max_inputs_in_tx: usize = 3;
+        simple_logger::init_with_level(log::Level::Debug).unwrap_or_default();
+
+        let (skey, pkey) = make_random_keys();
+        let (_validator_skey, validator_pkey) = pbc::make_random_keys();
+
+        let stake: i64 = 100;
+        let output =
+            StakeOutput::new_delegated(&pkey, &validator_pkey, stake).expect("keys are valid");
+        let inputs = [Output::StakeOutput(output.clone())];
+        let unspent: Vec<StakeOutput> = vec![output];
+
+        // Withdraw part of the money, the rest stays delegated.
+        let unstake = stake / 2;
+        let (tx, _) = create_unstaking_transaction(
+            &skey,
+            &pkey,
+            &validator_pkey,
+            None,
+            unspent.into_iter(),
+            unstake,
+            payment_fee,
+            stake_fee,
+            max_inputs_in_tx,
+        )
+        .expect("tx is created");
+        tx.validate(&inputs).expect("tx is valid");
+        assert_eq!(tx.txouts.len(), 2);
+        match &tx.txouts[1] {
+            Output::StakeOutput(o) => {
+                assert!(o.delegated);
+                assert_eq!(o.validator, validator_pkey);
+                assert_eq!(o.amount, stake - unstake - stake_fee);
+            }
+            _ => panic!("invalid tx"),
+        }
+    }
 }